@@ -0,0 +1,71 @@
+//! Cost of the clones the engine makes on every step.
+//!
+//! Run with `cargo bench --bench memory_clone`. Each case uses a history of
+//! 50 tool calls with 64 KiB observations (think file contents).
+//!
+//! `history_clone_owned` is the baseline: the same history with each
+//! observation in its own `String`, as `HistoryEntry` stored it before
+//! observations became `Arc<str>`. Cloning it copies all 3.2 MiB of tool
+//! output; `history_clone` copies none of it, only the tool calls.
+
+use agent_b::types::{HistoryEntry, Observation, ToolCall};
+use agent_b::AgentMemory;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::collections::HashMap;
+
+const ENTRIES: usize = 50;
+const OBSERVATION_BYTES: usize = 64 * 1024;
+
+fn large_memory() -> AgentMemory {
+    let mut memory = AgentMemory::new("Summarize the repository");
+    let observation = "x".repeat(OBSERVATION_BYTES);
+    for step in 1..=ENTRIES {
+        memory.history.push(HistoryEntry {
+            step,
+            tool: ToolCall {
+                name: "read_file".to_string(),
+                args: HashMap::from([("path".to_string(), format!("src/file_{}.rs", step).into())]),
+                id: Some(format!("call_{}", step)),
+            },
+            observation: Observation::success(observation.as_str()),
+            assistant_text: None,
+            latency_ms: None,
+            model_used: None,
+            usage: None,
+        });
+        memory.log("Observing", "HISTORY_COMMIT", &format!("step={}", step));
+    }
+    memory
+}
+
+/// The history's tool calls and observations as they were held before the
+/// refactor, each observation in its own `String`
+fn owned_history(memory: &AgentMemory) -> Vec<(ToolCall, String)> {
+    memory
+        .history
+        .iter()
+        .map(|entry| (entry.tool.clone(), entry.observation.content.to_string()))
+        .collect()
+}
+
+fn bench_memory_clone(c: &mut Criterion) {
+    let memory = large_memory();
+    let owned = owned_history(&memory);
+
+    // What every checkpoint save pays
+    c.bench_function("memory_clone", |b| b.iter(|| black_box(memory.clone())));
+
+    c.bench_function("history_clone", |b| b.iter(|| black_box(memory.history.clone())));
+
+    c.bench_function("history_clone_owned", |b| b.iter(|| black_box(owned.clone())));
+
+    // Serialization still has to copy the text once
+    c.bench_function("memory_serialize", |b| {
+        b.iter(|| black_box(serde_json::to_vec(&memory).unwrap()))
+    });
+
+    c.bench_function("build_messages", |b| b.iter(|| black_box(memory.build_messages())));
+}
+
+criterion_group!(benches, bench_memory_clone);
+criterion_main!(benches);
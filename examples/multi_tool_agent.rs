@@ -147,7 +147,7 @@ fn evaluate_expression(expr: &str) -> String {
     // Try to parse: <number> <op> <number>
     for op in &['*', '/', '+', '-'] {
         // Find operator (avoid splitting negative numbers)
-        if let Some(pos) = expr.rfind(|c: char| c == *op) {
+        if let Some(pos) = expr.rfind(*op) {
             if pos == 0 { continue; }
             let lhs = expr[..pos].trim().parse::<f64>();
             let rhs = expr[pos + 1..].trim().parse::<f64>();
@@ -53,9 +53,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             AgentOutput::ToolCallFinished { name, result, success } => {
                 println!("[TOOL RESULT] {} (Success: {}): {}", name, success, result);
             }
+            AgentOutput::Reasoning(text) => {
+                print!("{}", text);
+            }
+            AgentOutput::Commentary(text) => {
+                println!("\n💬 {}", text);
+            }
             AgentOutput::Action(msg) => {
                 println!("\n[ACTION] {}", msg);
             }
+            AgentOutput::Usage { cumulative, cost, .. } => {
+                let cost = cost.map(|c| format!(" (${:.4})", c)).unwrap_or_default();
+                println!("\n[USAGE] {} tokens so far{}", cumulative.total_tokens, cost);
+            }
+            AgentOutput::AnswerTruncated(_) => {
+                println!("\n[TRUNCATED] The answer hit the output token limit");
+            }
             AgentOutput::FinalAnswer(answer) => {
                 println!("\n\n✅ [FINAL ANSWER]\n{}", answer);
             }
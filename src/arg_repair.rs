@@ -0,0 +1,385 @@
+//! Tool-Argument Repair — let a cheap model fix malformed tool calls.
+//!
+//! Open models regularly emit tool arguments that are not valid JSON or do
+//! not match the tool's schema. When an `arg_repair_model` is configured,
+//! the broken payload and the schema are sent to that model, and its answer
+//! replaces the original arguments before the tool runs. Only if repair
+//! fails is the call reported as a `ToolFailure`.
+//!
+//! A cheaper first pass, `coerce_tool_args`, fixes the most common mismatch
+//! without a model: scalars sent with the wrong JSON type, such as `"3"` for
+//! an integer or `"true"` for a boolean, are converted to the type the
+//! schema declares.
+
+use crate::budget::TokenUsage;
+use crate::llm::AsyncLlmCaller;
+use crate::memory::AgentMemory;
+use crate::tools::{ToolRegistry, ToolSchema, RAW_ARGS_KEY};
+use crate::types::{AgentOutput, LlmResponse, OutputSchema, ToolCall};
+use serde_json::Value;
+use std::collections::HashMap;
+
+const REPAIR_SYSTEM_PROMPT: &str = "You repair malformed tool-call arguments. \
+Respond ONLY with a JSON object that conforms to the given schema, preserving the caller's intent.";
+
+/// Arguments produced by a successful repair.
+#[derive(Debug, Clone)]
+pub struct RepairedArgs {
+    pub args: HashMap<String, Value>,
+    pub usage: Option<TokenUsage>,
+}
+
+/// Ask `model` to repair the arguments of a call to the tool described by `schema`.
+pub async fn repair_tool_args(
+    llm: &dyn AsyncLlmCaller,
+    model: &str,
+    schema: &ToolSchema,
+    args: &HashMap<String, Value>,
+    error: &str,
+) -> Result<RepairedArgs, String> {
+    let broken = args
+        .get(RAW_ARGS_KEY)
+        .and_then(|v| v.as_str())
+        .map(String::from)
+        .unwrap_or_else(|| serde_json::to_string(args).unwrap_or_default());
+
+    let mut memory = AgentMemory::new(format!(
+        "Tool: {}\nDescription: {}\nProblem: {}\n\nBroken arguments:\n{}\n\nReturn the corrected arguments.",
+        schema.name, schema.description, error, broken
+    ))
+    .with_system_prompt(REPAIR_SYSTEM_PROMPT);
+    memory.config.output_schema = Some(OutputSchema {
+        name: format!("{}_arguments", schema.name),
+        description: Some(format!("Arguments for the '{}' tool", schema.name)),
+        schema: schema.input_schema.clone(),
+        strict: false,
+    });
+
+    let response = llm
+        .call_async(&memory, &ToolRegistry::new(), model, None)
+        .await?;
+
+    let (value, usage) = match response {
+        LlmResponse::Structured { data, usage, .. } => (data, usage),
+        LlmResponse::FinalAnswer { content, usage, .. } => {
+            let trimmed = content
+                .trim()
+                .trim_start_matches("```json")
+                .trim_start_matches("```")
+                .trim_end_matches("```")
+                .trim();
+            let data = serde_json::from_str(trimmed)
+                .map_err(|e| format!("repair model returned invalid JSON: {}", e))?;
+            (data, usage)
+        }
+        LlmResponse::ToolCall { tool, usage, .. } => {
+            (Value::Object(tool.args.into_iter().collect()), usage)
+        }
+        LlmResponse::ParallelToolCalls { .. } => {
+            return Err("repair model returned multiple tool calls".to_string())
+        }
+        LlmResponse::Truncated { .. } => {
+            return Err("repair model hit its output token limit".to_string())
+        }
+    };
+
+    match value {
+        Value::Object(map) => Ok(RepairedArgs {
+            args: map.into_iter().collect(),
+            usage,
+        }),
+        other => Err(format!("repair model returned a non-object: {}", other)),
+    }
+}
+
+/// Convert top-level arguments to the primitive `type` their property
+/// declares in `schema`, where the conversion loses nothing: numeric and
+/// boolean strings to numbers and booleans, numbers and booleans to strings,
+/// whole floats to integers, and JSON text to arrays and objects.
+///
+/// Returns one note per converted argument, e.g. `count: "3" -> 3`.
+pub fn coerce_args(schema: &Value, args: &mut HashMap<String, Value>) -> Vec<String> {
+    let Some(properties) = schema["properties"].as_object() else {
+        return Vec::new();
+    };
+    let mut keys: Vec<String> = args.keys().cloned().collect();
+    keys.sort_unstable();
+
+    let mut notes = Vec::new();
+    for key in keys {
+        let Some(expected) = properties.get(&key).and_then(|p| p["type"].as_str()) else {
+            continue;
+        };
+        let value = &args[&key];
+        if let Some(coerced) = coerce_value(expected, value) {
+            notes.push(format!("{}: {} -> {}", key, value, coerced));
+            args.insert(key, coerced);
+        }
+    }
+    notes
+}
+
+/// `value` as `expected`, or None if it already is one or cannot be converted.
+fn coerce_value(expected: &str, value: &Value) -> Option<Value> {
+    match (expected, value) {
+        ("integer", Value::String(s)) => s.trim().parse::<i64>().ok().map(Value::from),
+        ("integer", Value::Number(n)) if !n.is_i64() && !n.is_u64() => n
+            .as_f64()
+            .filter(|f| f.fract() == 0.0 && f.abs() < i64::MAX as f64)
+            .map(|f| Value::from(f as i64)),
+        ("number", Value::String(s)) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number),
+        ("boolean", Value::String(s)) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        ("string", Value::Number(n)) => Some(Value::String(n.to_string())),
+        ("string", Value::Bool(b)) => Some(Value::String(b.to_string())),
+        ("array", Value::String(s)) => serde_json::from_str(s).ok().filter(Value::is_array),
+        ("object", Value::String(s)) => serde_json::from_str(s).ok().filter(Value::is_object),
+        _ => None,
+    }
+}
+
+/// Validate a pending call and repair it in place if needed.
+///
+/// Returns `Err` with a message suitable as a tool-failure observation when
+/// the arguments are unusable. Schema mismatches are only enforced when a
+/// repair model is configured; unparseable JSON is always rejected.
+pub(crate) async fn repair_if_invalid(
+    memory: &mut AgentMemory,
+    tools: &ToolRegistry,
+    llm: &dyn AsyncLlmCaller,
+    call: &mut ToolCall,
+    state: &str,
+    output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+) -> Result<(), String> {
+    // Unknown tools are reported by the registry at execution time
+    let schema = match tools.get_schema(&call.name) {
+        Some(s) => s.clone(),
+        None => return Ok(()),
+    };
+    if memory.config.coerce_tool_args {
+        let notes = coerce_args(&schema.input_schema, &mut call.args);
+        if !notes.is_empty() {
+            memory.log(
+                state,
+                "ARGS_COERCED",
+                &format!("tool='{}' {}", call.name, notes.join(", ")),
+            );
+        }
+    }
+    let error = match tools.validate_args(&call.name, &call.args) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+
+    let model = match memory.config.arg_repair_model.clone() {
+        Some(m) => m,
+        None if call.args.contains_key(RAW_ARGS_KEY) => {
+            return Err(format!("Invalid arguments for tool '{}': {}", call.name, error));
+        }
+        None => return Ok(()),
+    };
+
+    memory.log(
+        state,
+        "ARGS_INVALID",
+        &format!("tool='{}' error={}", call.name, error),
+    );
+
+    let repaired = match repair_tool_args(llm, &model, &schema, &call.args, &error).await {
+        Ok(r) => r,
+        Err(e) => {
+            memory.log(state, "ARGS_REPAIR_FAILED", &e);
+            return Err(format!(
+                "Invalid arguments for tool '{}': {} (repair failed: {})",
+                call.name, error, e
+            ));
+        }
+    };
+
+    if let Some(u) = repaired.usage {
+        crate::states::report_usage(memory, &model, u, output_tx);
+    }
+
+    if let Err(e) = tools.validate_args(&call.name, &repaired.args) {
+        memory.log(state, "ARGS_REPAIR_FAILED", &e);
+        return Err(format!(
+            "Invalid arguments for tool '{}': {} (repaired arguments still invalid: {})",
+            call.name, error, e
+        ));
+    }
+
+    memory.log(
+        state,
+        "ARGS_REPAIRED",
+        &format!("tool='{}' model={}", call.name, model),
+    );
+    call.args = repaired.args;
+    Ok(())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::MockLlmCaller;
+    use crate::tools::{parse_tool_args, Tool};
+
+    fn make_registry() -> ToolRegistry {
+        let mut registry = ToolRegistry::new();
+        registry.register_tool(
+            Tool::new("add", "Add two numbers")
+                .param("a", "number", "First")
+                .param("b", "number", "Second")
+                .call(|_| Ok("ok".to_string())),
+        );
+        registry
+    }
+
+    fn make_call(args: HashMap<String, Value>) -> ToolCall {
+        ToolCall {
+            name: "add".to_string(),
+            args,
+            id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_valid_args_untouched() {
+        let tools = make_registry();
+        let llm = MockLlmCaller::new(vec![]);
+        let mut memory = AgentMemory::new("t");
+        memory.config.arg_repair_model = Some("cheap".into());
+        let mut call = make_call(parse_tool_args(r#"{"a": 1, "b": 2}"#));
+
+        assert!(repair_if_invalid(&mut memory, &tools, &llm, &mut call, "Acting", None).await.is_ok());
+        assert_eq!(llm.call_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_broken_json_without_repair_fails() {
+        let tools = make_registry();
+        let llm = MockLlmCaller::new(vec![]);
+        let mut memory = AgentMemory::new("t");
+        let mut call = make_call(parse_tool_args(r#"{"a": 1, "b": "#));
+
+        let err = repair_if_invalid(&mut memory, &tools, &llm, &mut call, "Acting", None)
+            .await
+            .unwrap_err();
+        assert!(err.contains("not valid JSON"));
+    }
+
+    #[tokio::test]
+    async fn test_schema_mismatch_without_repair_passes_through() {
+        let tools = make_registry();
+        let llm = MockLlmCaller::new(vec![]);
+        let mut memory = AgentMemory::new("t");
+        let mut call = make_call(parse_tool_args(r#"{"a": 1}"#));
+
+        assert!(repair_if_invalid(&mut memory, &tools, &llm, &mut call, "Acting", None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_repair_replaces_args() {
+        let tools = make_registry();
+        let llm = MockLlmCaller::new(vec![LlmResponse::Structured {
+            data: serde_json::json!({"a": 1, "b": 2}),
+            usage: Some(TokenUsage::new(10, 5)),
+            model: None,
+        }]);
+        let mut memory = AgentMemory::new("t");
+        memory.config.arg_repair_model = Some("cheap".into());
+        let mut call = make_call(parse_tool_args(r#"{"a": 1, "b": 2"#));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        repair_if_invalid(&mut memory, &tools, &llm, &mut call, "Acting", Some(&tx))
+            .await
+            .unwrap();
+        assert_eq!(call.args["b"], 2);
+        assert_eq!(llm.model_for_call(0).as_deref(), Some("cheap"));
+        assert_eq!(memory.total_usage.total_tokens, 15);
+        assert!(matches!(rx.try_recv(), Ok(AgentOutput::Usage { usage, .. }) if usage.total_tokens == 15));
+        assert_eq!(memory.trace.entries().last().unwrap().event, "ARGS_REPAIRED");
+    }
+
+    #[test]
+    fn test_coerce_args_to_schema_types() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "count":  { "type": "integer" },
+                "ratio":  { "type": "number" },
+                "exact":  { "type": "boolean" },
+                "label":  { "type": "string" },
+                "tags":   { "type": "array" },
+                "note":   { "type": "boolean" },
+            },
+        });
+        let mut args = parse_tool_args(
+            r#"{"count": "3", "ratio": "0.5", "exact": "True", "label": 7,
+                "tags": "[\"a\"]", "note": "maybe", "extra": "1"}"#,
+        );
+
+        let notes = coerce_args(&schema, &mut args);
+        assert_eq!(
+            notes,
+            vec![
+                r#"count: "3" -> 3"#,
+                r#"exact: "True" -> true"#,
+                "label: 7 -> \"7\"",
+                r#"ratio: "0.5" -> 0.5"#,
+                r#"tags: "[\"a\"]" -> ["a"]"#,
+            ]
+        );
+        assert_eq!(args["count"], 3);
+        assert_eq!(args["tags"], serde_json::json!(["a"]));
+        // Not convertible, or not in the schema: left alone
+        assert_eq!(args["note"], "maybe");
+        assert_eq!(args["extra"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_coercion_runs_before_validation() {
+        let tools = make_registry();
+        let llm = MockLlmCaller::new(vec![]);
+        let mut memory = AgentMemory::new("t");
+        memory.config.arg_repair_model = Some("cheap".into());
+        memory.config.coerce_tool_args = true;
+        let mut call = make_call(parse_tool_args(r#"{"a": "1", "b": 2}"#));
+
+        repair_if_invalid(&mut memory, &tools, &llm, &mut call, "Acting", None).await.unwrap();
+        assert_eq!(call.args["a"], 1.0);
+        assert_eq!(llm.call_count(), 0);
+        assert_eq!(memory.trace.entries().last().unwrap().event, "ARGS_COERCED");
+    }
+
+    #[tokio::test]
+    async fn test_repair_still_invalid_fails() {
+        let tools = make_registry();
+        let llm = MockLlmCaller::new(vec![LlmResponse::FinalAnswer {
+            content: r#"{"a": "one"}"#.into(),
+            confidence: 1.0,
+            usage: None,
+            model: None,
+        }]);
+        let mut memory = AgentMemory::new("t");
+        memory.config.arg_repair_model = Some("cheap".into());
+        let mut call = make_call(parse_tool_args(r#"{"a": 1}"#));
+
+        let err = repair_if_invalid(&mut memory, &tools, &llm, &mut call, "Acting", None)
+            .await
+            .unwrap_err();
+        assert!(err.contains("still invalid"));
+    }
+}
@@ -0,0 +1,250 @@
+//! Tool Execution Audit Log — what an autonomous agent actually did.
+//!
+//! The trace explains the agent's reasoning. The audit log is a separate,
+//! append-only record for security review. Every real tool execution
+//! produces one `AuditRecord` with the following fields:
+//! - who ran it (the session)
+//! - where it ran (a host fingerprint)
+//! - what ran (the tool name and a hash of its arguments)
+//! - how it went (success, a hash of the result, and the duration)
+//! - who approved it, when a human decision let it run
+//!
+//! Calls simulated in plan mode and calls rejected before execution are not
+//! recorded.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Records
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The machine and process a tool ran on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostFingerprint {
+    pub hostname: String,
+    pub user: String,
+    pub os: String,
+    pub arch: String,
+    pub pid: u32,
+    pub cwd: String,
+}
+
+impl HostFingerprint {
+    /// Fingerprint of the current process, computed once.
+    pub fn current() -> &'static HostFingerprint {
+        static FINGERPRINT: OnceLock<HostFingerprint> = OnceLock::new();
+        FINGERPRINT.get_or_init(|| HostFingerprint {
+            hostname: std::env::var("HOSTNAME")
+                .or_else(|_| std::env::var("COMPUTERNAME"))
+                .ok()
+                .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+                .map(|h| h.trim().to_string())
+                .filter(|h| !h.is_empty())
+                .unwrap_or_else(|| "unknown".to_string()),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "unknown".to_string()),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            pid: std::process::id(),
+            cwd: std::env::current_dir()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// One tool execution.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub session_id: String,
+    pub step: usize,
+    pub tool: String,
+    /// SHA-256 of the arguments as canonical (key-sorted) JSON
+    pub args_sha256: String,
+    /// SHA-256 of the tool's output, or of its error message
+    pub result_sha256: String,
+    pub success: bool,
+    pub duration_ms: u64,
+    pub host: HostFingerprint,
+    /// The principal whose approval let the call run (`HumanDecision::by`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approved_by: Option<String>,
+}
+
+/// SHA-256 of tool arguments, independent of map ordering.
+pub fn hash_args(args: &HashMap<String, Value>) -> String {
+    let sorted: BTreeMap<&String, &Value> = args.iter().collect();
+    sha256_hex(&serde_json::to_string(&sorted).unwrap_or_default())
+}
+
+fn sha256_hex(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Sinks
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Destination for audit records. Called synchronously after each tool runs.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord);
+}
+
+/// Appends one JSON object per line to a file.
+pub struct JsonlAuditSink {
+    path: PathBuf,
+    file: Mutex<std::fs::File>,
+}
+
+impl JsonlAuditSink {
+    pub fn new(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self { path, file: Mutex::new(file) })
+    }
+}
+
+impl AuditSink for JsonlAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        let line = match serde_json::to_string(record) {
+            Ok(l) => l,
+            Err(e) => {
+                tracing::error!(error = %e, "Audit record serialization failed");
+                return;
+            }
+        };
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+            tracing::error!(path = %self.path.display(), error = %e, "Audit log write failed");
+        }
+    }
+}
+
+/// Keeps records in memory. Useful for tests.
+#[derive(Default)]
+pub struct MemoryAuditSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl MemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        self.records.lock().unwrap().push(record.clone());
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// AuditLog
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A sink bound to one agent session. Set on `AgentMemory` by the builder.
+#[derive(Clone)]
+pub struct AuditLog {
+    sink: Arc<dyn AuditSink>,
+    session_id: String,
+}
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AuditLog({})", self.session_id)
+    }
+}
+
+impl AuditLog {
+    pub fn new(sink: Arc<dyn AuditSink>, session_id: impl Into<String>) -> Self {
+        Self { sink, session_id: session_id.into() }
+    }
+
+    /// Record one tool execution.
+    pub fn tool_executed(
+        &self,
+        step: usize,
+        tool: &str,
+        args: &HashMap<String, Value>,
+        result: &Result<String, String>,
+        duration: Duration,
+        approved_by: Option<&str>,
+    ) {
+        let (output, success) = match result {
+            Ok(out) => (out, true),
+            Err(err) => (err, false),
+        };
+        self.sink.record(&AuditRecord {
+            timestamp: Utc::now(),
+            session_id: self.session_id.clone(),
+            step,
+            tool: tool.to_string(),
+            args_sha256: hash_args(args),
+            result_sha256: sha256_hex(output),
+            success,
+            duration_ms: duration.as_millis() as u64,
+            host: HostFingerprint::current().clone(),
+            approved_by: approved_by.map(str::to_string),
+        });
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_args_hash_ignores_order() {
+        let mut a = HashMap::new();
+        a.insert("x".to_string(), json!(1));
+        a.insert("y".to_string(), json!("two"));
+        let mut b = HashMap::new();
+        b.insert("y".to_string(), json!("two"));
+        b.insert("x".to_string(), json!(1));
+        assert_eq!(hash_args(&a), hash_args(&b));
+
+        b.insert("x".to_string(), json!(2));
+        assert_ne!(hash_args(&a), hash_args(&b));
+    }
+
+    #[test]
+    fn test_jsonl_sink_appends() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = AuditLog::new(Arc::new(JsonlAuditSink::new(&path).unwrap()), "s1");
+
+        log.tool_executed(1, "ls", &HashMap::new(), &Ok("a b".into()), Duration::from_millis(3), None);
+        log.tool_executed(2, "rm", &HashMap::new(), &Err("denied".into()), Duration::from_millis(1), Some("alice"));
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<AuditRecord> =
+            text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].tool, "ls");
+        assert!(records[0].success);
+        assert!(!records[1].success);
+        assert_eq!(records[0].approved_by, None);
+        assert_eq!(records[1].approved_by.as_deref(), Some("alice"));
+        assert_eq!(records[1].session_id, "s1");
+        assert_eq!(records[1].host.pid, std::process::id());
+    }
+}
@@ -0,0 +1,973 @@
+//! Batch execution — trade latency for cost on large offline workloads.
+//!
+//! `BatchRunner` drives many agent sessions in lock-step. Whenever sessions
+//! reach Planning, their LLM calls are grouped into one provider batch
+//! (OpenAI Batch API or Anthropic Message Batches), which is typically
+//! billed at half price. While the batch runs the sessions are checkpointed,
+//! so a restarted process can rebuild them and continue waiting on the same
+//! batch via `BatchRunner::with_pending_batch`.
+//!
+//! ```rust,ignore
+//! let sessions = tickets.iter()
+//!     .map(|t| AgentBuilder::new(format!("Classify: {}", t)).model("gpt-4o-mini").build())
+//!     .collect::<Result<Vec<_>, _>>()?;
+//!
+//! let results = BatchRunner::new(Arc::new(OpenAiBatchProvider::from_env()?), sessions)
+//!     .poll_interval(Duration::from_secs(60))
+//!     .run()
+//!     .await;
+//! ```
+
+use crate::budget::TokenUsage;
+use crate::engine::AgentEngine;
+use crate::error::AgentError;
+use crate::llm::{AnthropicSampling, AsyncLlmCaller, LlmError, ModelCapabilities, OpenAiCaller};
+use crate::memory::AgentMemory;
+use crate::states::PlanningState;
+use crate::tools::{parse_tool_args, ToolRegistry, ToolSchema};
+use crate::types::{AgentOutput, LlmResponse, LlmStreamChunk, ModelParams, OutputSchema, State, ToolCall};
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Provider interface
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// One LLM call inside a batch.
+#[derive(Debug, Clone)]
+pub struct BatchRequest {
+    /// Unique within the batch; used to route the result back to its session
+    pub custom_id: String,
+    pub model: String,
+    /// OpenAI-style messages from `AgentMemory::build_messages()`
+    pub messages: Vec<Value>,
+    pub tools: Vec<ToolSchema>,
+    pub parallel_tools: bool,
+    /// What the model accepts, from `AgentMemory::model_capabilities`
+    pub capabilities: ModelCapabilities,
+    /// Stop sequences for the step, from `AgentMemory::stop`
+    pub stop: Vec<String>,
+    /// Sampling for the model, from `AgentMemory::model_params`
+    pub params: ModelParams,
+    /// From `SamplingParams::seed`
+    pub seed: Option<u64>,
+    /// Requested answer format, from `AgentConfig::output_schema`
+    pub output_schema: Option<OutputSchema>,
+}
+
+/// Progress of a submitted batch.
+#[derive(Debug, Clone)]
+pub enum BatchStatus {
+    InProgress,
+    /// Results keyed by `custom_id`
+    Completed(HashMap<String, Result<LlmResponse, String>>),
+    Failed(String),
+}
+
+/// A provider batch API.
+#[async_trait]
+pub trait BatchProvider: Send + Sync {
+    /// Submit requests and return the provider's batch id.
+    async fn submit(&self, requests: Vec<BatchRequest>) -> Result<String, String>;
+
+    /// Check on a batch previously returned by `submit`.
+    async fn poll(&self, batch_id: &str) -> Result<BatchStatus, String>;
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// OpenAI Batch API
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// OpenAI Batch API: requests are uploaded as a JSONL file, results are
+/// downloaded from the batch's output file.
+pub struct OpenAiBatchProvider {
+    client: reqwest::Client,
+    api_key: String,
+    api_base: String,
+}
+
+impl OpenAiBatchProvider {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key: api_key.into(),
+            api_base: "https://api.openai.com/v1".to_string(),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        let key =
+            std::env::var("OPENAI_API_KEY").map_err(|_| "OPENAI_API_KEY not set".to_string())?;
+        Ok(Self::new(key))
+    }
+
+    /// Point at an OpenAI-compatible endpoint that implements `/files` and `/batches`.
+    pub fn with_base_url(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = api_base.into();
+        self
+    }
+
+    fn request_line(req: &BatchRequest) -> Value {
+        let mut messages = req.messages.clone();
+        if let Some(schema) = &req.output_schema {
+            OpenAiCaller::add_schema_instruction(&mut messages, schema);
+        }
+        let mut body = json!({
+            "model": req.model,
+            "messages": messages,
+        });
+        // As for live calls, structured output is sent without tools
+        if let Some(schema) = &req.output_schema {
+            if req.capabilities.json_mode {
+                body["response_format"] = if schema.strict {
+                    OpenAiCaller::json_schema_format(schema)
+                } else {
+                    json!({ "type": "json_object" })
+                };
+            }
+        } else if !req.tools.is_empty() {
+            body["tools"] = Value::Array(
+                req.tools
+                    .iter()
+                    .map(|s| {
+                        json!({
+                            "type": "function",
+                            "function": {
+                                "name": s.name,
+                                "description": s.description,
+                                "parameters": s.input_schema,
+                            }
+                        })
+                    })
+                    .collect(),
+            );
+            if !req.parallel_tools {
+                body["parallel_tool_calls"] = Value::Bool(false);
+            }
+        }
+        if let Some(stop) = OpenAiCaller::stop_for(&req.capabilities, &req.stop) {
+            body["stop"] = json!(stop);
+        }
+        if let Value::Object(fields) = &mut body {
+            fields.extend(OpenAiCaller::sampling_fields(&req.capabilities, req.seed, &req.params));
+        }
+        json!({
+            "custom_id": req.custom_id,
+            "method": "POST",
+            "url": "/v1/chat/completions",
+            "body": body,
+        })
+    }
+
+    async fn get_json(&self, path: &str) -> Result<Value, String> {
+        let resp = self
+            .client
+            .get(format!("{}{}", self.api_base, path))
+            .bearer_auth(&self.api_key)
+            .send()
+            .await
+            .map_err(|e| format!("Network error: {}", e))?;
+        check_status(resp)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse OpenAI response: {}", e))
+    }
+}
+
+#[async_trait]
+impl BatchProvider for OpenAiBatchProvider {
+    async fn submit(&self, requests: Vec<BatchRequest>) -> Result<String, String> {
+        let jsonl = requests
+            .iter()
+            .map(|r| Self::request_line(r).to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let part = reqwest::multipart::Part::bytes(jsonl.into_bytes()).file_name("batch.jsonl");
+        let form = reqwest::multipart::Form::new()
+            .text("purpose", "batch")
+            .part("file", part);
+        let file: Value = check_status(
+            self.client
+                .post(format!("{}/files", self.api_base))
+                .bearer_auth(&self.api_key)
+                .multipart(form)
+                .send()
+                .await
+                .map_err(|e| format!("Network error: {}", e))?,
+        )
+        .await?
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse OpenAI file upload: {}", e))?;
+        let file_id = file["id"].as_str().ok_or("OpenAI file upload returned no id")?;
+
+        let batch: Value = check_status(
+            self.client
+                .post(format!("{}/batches", self.api_base))
+                .bearer_auth(&self.api_key)
+                .json(&json!({
+                    "input_file_id": file_id,
+                    "endpoint": "/v1/chat/completions",
+                    "completion_window": "24h",
+                }))
+                .send()
+                .await
+                .map_err(|e| format!("Network error: {}", e))?,
+        )
+        .await?
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse OpenAI batch: {}", e))?;
+        batch["id"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| "OpenAI batch creation returned no id".to_string())
+    }
+
+    async fn poll(&self, batch_id: &str) -> Result<BatchStatus, String> {
+        let batch = self.get_json(&format!("/batches/{}", batch_id)).await?;
+        match batch["status"].as_str().unwrap_or_default() {
+            "completed" => {}
+            "failed" | "expired" | "cancelled" => {
+                return Ok(BatchStatus::Failed(format!(
+                    "OpenAI batch {} {}",
+                    batch_id, batch["status"]
+                )))
+            }
+            _ => return Ok(BatchStatus::InProgress),
+        }
+
+        let mut results = HashMap::new();
+        for file_key in ["output_file_id", "error_file_id"] {
+            let Some(file_id) = batch[file_key].as_str() else { continue };
+            let resp = self
+                .client
+                .get(format!("{}/files/{}/content", self.api_base, file_id))
+                .bearer_auth(&self.api_key)
+                .send()
+                .await
+                .map_err(|e| format!("Network error: {}", e))?;
+            let text = check_status(resp)
+                .await?
+                .text()
+                .await
+                .map_err(|e| format!("Failed to read batch results: {}", e))?;
+            for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                let entry: Value = serde_json::from_str(line)
+                    .map_err(|e| format!("Invalid batch result line: {}", e))?;
+                let Some(id) = entry["custom_id"].as_str() else { continue };
+                let result = if entry["error"].is_null()
+                    && entry["response"]["status_code"].as_u64() == Some(200)
+                {
+                    parse_openai_completion(&entry["response"]["body"])
+                } else {
+                    Err(format!("OpenAI batch request failed: {}", entry["error"]))
+                };
+                results.insert(id.to_string(), result);
+            }
+        }
+        Ok(BatchStatus::Completed(results))
+    }
+}
+
+/// Convert a chat-completions response body into an `LlmResponse`.
+fn parse_openai_completion(body: &Value) -> Result<LlmResponse, String> {
+    let usage = body["usage"]["prompt_tokens"].as_u64().map(|p| {
+        TokenUsage::new(
+            p as u32,
+            body["usage"]["completion_tokens"].as_u64().unwrap_or(0) as u32,
+        )
+    });
+    let message = &body["choices"][0]["message"];
+    let content = message["content"].as_str().map(String::from);
+
+    let mut calls: Vec<ToolCall> = message["tool_calls"]
+        .as_array()
+        .map(|tcs| {
+            tcs.iter()
+                .map(|tc| ToolCall {
+                    name: tc["function"]["name"].as_str().unwrap_or_default().to_string(),
+                    args: parse_tool_args(tc["function"]["arguments"].as_str().unwrap_or_default()),
+                    id: tc["id"].as_str().map(String::from),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    match calls.len() {
+        0 => content
+            .map(|content| LlmResponse::FinalAnswer { content, confidence: 1.0, usage, model: None })
+            .ok_or_else(|| "No content in OpenAI batch response".to_string()),
+        1 => Ok(LlmResponse::ToolCall {
+            tool: calls.remove(0),
+            confidence: 1.0,
+            assistant_text: content.filter(|c| !c.trim().is_empty()),
+            usage,
+            model: None,
+        }),
+        _ => Ok(LlmResponse::ParallelToolCalls {
+            tools: calls,
+            confidence: 1.0,
+            usage,
+            model: None,
+        }),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Anthropic Message Batches
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Anthropic Message Batches API.
+pub struct AnthropicBatchProvider {
+    client: reqwest::Client,
+    api_key: String,
+    api_base: String,
+    max_tokens: u32,
+}
+
+impl AnthropicBatchProvider {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key: api_key.into(),
+            api_base: "https://api.anthropic.com".to_string(),
+            max_tokens: 4096,
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        let key = std::env::var("ANTHROPIC_API_KEY")
+            .map_err(|_| "ANTHROPIC_API_KEY not set".to_string())?;
+        Ok(Self::new(key))
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// The request's Messages API body. Thinking is not turned on: batch
+    /// results keep no signed thinking blocks to send back with tool results.
+    fn request_params(&self, req: &BatchRequest) -> Value {
+        let mut system = req
+            .messages
+            .iter()
+            .filter(|m| m["role"] == "system")
+            .filter_map(|m| m["content"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if req.output_schema.is_some() {
+            system.push_str(&crate::llm::structured_output_instruction());
+        }
+        // Tool calls and results in history become tool_use/tool_result blocks
+        let messages = crate::llm::anthropic_messages(req.messages.clone(), |_| Vec::new());
+        let mut params = json!(AnthropicSampling::new(&req.params, self.max_tokens));
+        params["model"] = json!(req.model);
+        params["messages"] = json!(messages);
+        if !system.is_empty() {
+            params["system"] = json!(system);
+        }
+        if !req.stop.is_empty() {
+            params["stop_sequences"] = json!(req.stop);
+        }
+        let mut tools: Vec<Value> = req
+            .tools
+            .iter()
+            .map(|s| {
+                json!({
+                    "name": s.name,
+                    "description": s.description,
+                    "input_schema": s.input_schema,
+                })
+            })
+            .collect();
+        if let Some(schema) = &req.output_schema {
+            tools.push(json!(crate::llm::structured_output_tool(schema)));
+        }
+        if !tools.is_empty() {
+            params["tools"] = Value::Array(tools);
+            if !req.parallel_tools {
+                params["tool_choice"] = json!({ "type": "auto", "disable_parallel_tool_use": true });
+            }
+        }
+        params
+    }
+
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        builder
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+    }
+}
+
+#[async_trait]
+impl BatchProvider for AnthropicBatchProvider {
+    async fn submit(&self, requests: Vec<BatchRequest>) -> Result<String, String> {
+        let body = json!({
+            "requests": requests
+                .iter()
+                .map(|r| json!({ "custom_id": r.custom_id, "params": self.request_params(r) }))
+                .collect::<Vec<_>>(),
+        });
+        let resp = self
+            .request(self.client.post(format!("{}/v1/messages/batches", self.api_base)))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("Network error: {}", e))?;
+        let batch: Value = check_status(resp)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse Anthropic batch: {}", e))?;
+        batch["id"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| "Anthropic batch creation returned no id".to_string())
+    }
+
+    async fn poll(&self, batch_id: &str) -> Result<BatchStatus, String> {
+        let resp = self
+            .request(
+                self.client
+                    .get(format!("{}/v1/messages/batches/{}", self.api_base, batch_id)),
+            )
+            .send()
+            .await
+            .map_err(|e| format!("Network error: {}", e))?;
+        let batch: Value = check_status(resp)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse Anthropic batch: {}", e))?;
+        if batch["processing_status"] != "ended" {
+            return Ok(BatchStatus::InProgress);
+        }
+        let Some(results_url) = batch["results_url"].as_str() else {
+            return Ok(BatchStatus::Failed(format!(
+                "Anthropic batch {} ended without results",
+                batch_id
+            )));
+        };
+
+        let resp = self
+            .request(self.client.get(results_url))
+            .send()
+            .await
+            .map_err(|e| format!("Network error: {}", e))?;
+        let text = check_status(resp)
+            .await?
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read batch results: {}", e))?;
+
+        let mut results = HashMap::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let entry: Value = serde_json::from_str(line)
+                .map_err(|e| format!("Invalid batch result line: {}", e))?;
+            let Some(id) = entry["custom_id"].as_str() else { continue };
+            let result = if entry["result"]["type"] == "succeeded" {
+                parse_anthropic_message(&entry["result"]["message"])
+            } else {
+                Err(format!("Anthropic batch request failed: {}", entry["result"]))
+            };
+            results.insert(id.to_string(), result);
+        }
+        Ok(BatchStatus::Completed(results))
+    }
+}
+
+/// Convert an Anthropic message into an `LlmResponse`.
+fn parse_anthropic_message(message: &Value) -> Result<LlmResponse, String> {
+    let usage = message["usage"]["input_tokens"].as_u64().map(|i| {
+        TokenUsage::new(
+            i as u32,
+            message["usage"]["output_tokens"].as_u64().unwrap_or(0) as u32,
+        )
+    });
+    let mut calls = Vec::new();
+    let mut texts = Vec::new();
+    for block in message["content"].as_array().into_iter().flatten() {
+        match block["type"].as_str() {
+            Some("tool_use") if block["name"] == crate::llm::STRUCTURED_OUTPUT_TOOL => {
+                return Ok(LlmResponse::Structured { data: block["input"].clone(), usage, model: None });
+            }
+            Some("tool_use") => calls.push(ToolCall {
+                name: block["name"].as_str().unwrap_or_default().to_string(),
+                args: match &block["input"] {
+                    Value::Object(map) => map.clone().into_iter().collect(),
+                    other => parse_tool_args(&other.to_string()),
+                },
+                id: block["id"].as_str().map(String::from),
+            }),
+            Some("text") => texts.push(block["text"].as_str().unwrap_or_default().to_string()),
+            _ => {}
+        }
+    }
+    let text = texts.join("\n");
+
+    match calls.len() {
+        0 if text.is_empty() => Err("Anthropic returned empty content".to_string()),
+        0 => Ok(LlmResponse::FinalAnswer { content: text, confidence: 1.0, usage, model: None }),
+        1 => Ok(LlmResponse::ToolCall {
+            tool: calls.remove(0),
+            confidence: 1.0,
+            assistant_text: Some(text).filter(|t| !t.trim().is_empty()),
+            usage,
+            model: None,
+        }),
+        _ => Ok(LlmResponse::ParallelToolCalls {
+            tools: calls,
+            confidence: 1.0,
+            usage,
+            model: None,
+        }),
+    }
+}
+
+async fn check_status(resp: reqwest::Response) -> Result<reqwest::Response, String> {
+    if resp.status().is_success() {
+        Ok(resp)
+    } else {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        Err(format!("Batch API error {}: {}", status, body))
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Precomputed caller
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Hands a batch result to PlanningState as if it came from a live call.
+///
+/// The result answers one call. A step that asks again (a continuation,
+/// an unknown-tool retry, a dedup nudge) gets a retryable error instead,
+/// so the session plans again and that call goes out with the next batch.
+struct BatchResultCaller {
+    result: Mutex<Option<Result<LlmResponse, String>>>,
+}
+
+impl BatchResultCaller {
+    fn new(result: Result<LlmResponse, String>) -> Self {
+        Self { result: Mutex::new(Some(result)) }
+    }
+
+    fn take(&self) -> Result<LlmResponse, LlmError> {
+        match self.result.lock().unwrap().take() {
+            Some(result) => result.map_err(LlmError::from),
+            None => Err(LlmError::Other(
+                "Batch result already used; the next call waits for another batch".to_string(),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncLlmCaller for BatchResultCaller {
+    async fn call_async(
+        &self,
+        _memory: &AgentMemory,
+        _tools: &ToolRegistry,
+        _model: &str,
+        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        self.take()
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        _memory: &'a AgentMemory,
+        _tools: &'a ToolRegistry,
+        _model: &'a str,
+        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        use futures::{stream, StreamExt};
+        let chunk = self.take().map(LlmStreamChunk::Done);
+        stream::once(async move { chunk }).boxed()
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// BatchRunner
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Runs many agent sessions, batching their LLM calls.
+pub struct BatchRunner {
+    provider: Arc<dyn BatchProvider>,
+    sessions: Vec<AgentEngine>,
+    poll_interval: Duration,
+    pending_batch: Option<String>,
+}
+
+impl BatchRunner {
+    pub fn new(provider: Arc<dyn BatchProvider>, sessions: Vec<AgentEngine>) -> Self {
+        Self {
+            provider,
+            sessions,
+            poll_interval: Duration::from_secs(30),
+            pending_batch: None,
+        }
+    }
+
+    /// How long to wait between batch status checks.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Wait on an already-submitted batch before submitting new ones.
+    ///
+    /// Use after a restart: resume each session from its checkpoint, then pass
+    /// the id recorded in the `BATCH_SUBMITTED` trace entry.
+    pub fn with_pending_batch(mut self, batch_id: impl Into<String>) -> Self {
+        self.pending_batch = Some(batch_id.into());
+        self
+    }
+
+    pub fn sessions(&self) -> &[AgentEngine] {
+        &self.sessions
+    }
+
+    /// Drive every session to a terminal state. Results are in session order.
+    pub async fn run(mut self) -> Vec<Result<String, AgentError>> {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut errors: Vec<Option<AgentError>> = self.sessions.iter().map(|_| None).collect();
+        let mut iterations: Vec<usize> = vec![0; self.sessions.len()];
+
+        loop {
+            // Advance every session to its next LLM call
+            for (i, engine) in self.sessions.iter_mut().enumerate() {
+                engine.memory.hooks = engine.hooks.clone();
+                let safety_cap = engine.memory.config.max_steps * 3;
+                while errors[i].is_none()
+                    && !engine.is_finished()
+                    && !(engine.state == State::planning() && calls_llm(engine))
+                {
+                    iterations[i] += 1;
+                    if iterations[i] > safety_cap {
+                        errors[i] = Some(AgentError::SafetyCapExceeded(iterations[i]));
+                    } else if let Err(e) = engine.step(&tx).await {
+                        errors[i] = Some(e);
+                    }
+                }
+            }
+
+            let waiting: Vec<usize> = (0..self.sessions.len())
+                .filter(|&i| errors[i].is_none() && !self.sessions[i].is_finished())
+                .collect();
+            if waiting.is_empty() {
+                break;
+            }
+
+            let batch_id = match self.pending_batch.take() {
+                Some(id) => id,
+                None => match self.submit(&waiting).await {
+                    Ok(id) => id,
+                    Err(e) => {
+                        for &i in &waiting {
+                            errors[i] = Some(AgentError::LlmError(e.clone().into()));
+                        }
+                        break;
+                    }
+                },
+            };
+
+            let mut results = match self.wait(&batch_id).await {
+                Ok(results) => results,
+                Err(e) => {
+                    for &i in &waiting {
+                        errors[i] = Some(AgentError::LlmError(e.clone().into()));
+                    }
+                    break;
+                }
+            };
+
+            // Feed each result through PlanningState
+            for &i in &waiting {
+                let engine = &mut self.sessions[i];
+                let result = results
+                    .remove(&engine.session_id)
+                    .unwrap_or_else(|| Err(format!("No batch result for session {}", engine.session_id)));
+                iterations[i] += 1;
+                let live = std::mem::replace(&mut engine.llm, Arc::new(BatchResultCaller::new(result)));
+                if let Err(e) = engine.step(&tx).await {
+                    errors[i] = Some(e);
+                }
+                engine.llm = live;
+            }
+        }
+
+        self.sessions
+            .iter()
+            .zip(errors)
+            .map(|(engine, err)| match err {
+                Some(e) => Err(e),
+                None => session_result(engine),
+            })
+            .collect()
+    }
+
+    async fn submit(&mut self, waiting: &[usize]) -> Result<String, String> {
+        let requests = waiting
+            .iter()
+            .map(|&i| {
+                let engine = &mut self.sessions[i];
+                let tools = engine.tools.snapshot();
+                let model = PlanningState.resolve_model(&engine.memory);
+                // Stop sequences can be set per model
+                engine.memory.current_model = Some(model.clone());
+                engine.memory.prepare_prompt(&tools);
+                let native = engine.memory.planning_prompter.native_tools();
+                BatchRequest {
+                    custom_id: engine.session_id.clone(),
+                    messages: engine.memory.build_messages(),
+                    tools: if native { tools.schemas() } else { Vec::new() },
+                    parallel_tools: engine.memory.config.parallel_tools,
+                    capabilities: engine.memory.model_capabilities(&model),
+                    stop: engine.memory.stop.clone(),
+                    params: engine.memory.model_params(&model),
+                    seed: engine.memory.config.sampling.seed,
+                    output_schema: engine.memory.config.output_schema.clone(),
+                    model,
+                }
+            })
+            .collect();
+
+        let batch_id = self.provider.submit(requests).await?;
+        tracing::info!(batch_id = %batch_id, sessions = waiting.len(), "Batch submitted");
+
+        // Persist sessions while the batch is pending
+        for &i in waiting {
+            let engine = &mut self.sessions[i];
+            engine.memory.log(
+                "Batch",
+                "BATCH_SUBMITTED",
+                &format!("batch_id={}", batch_id),
+            );
+            engine.save_checkpoint();
+            if let Err(e) = engine.flush_checkpoints().await {
+                tracing::warn!(session = %engine.session_id, error = %e, "Batch checkpoint failed");
+            }
+        }
+        Ok(batch_id)
+    }
+
+    async fn wait(
+        &self,
+        batch_id: &str,
+    ) -> Result<HashMap<String, Result<LlmResponse, String>>, String> {
+        loop {
+            match self.provider.poll(batch_id).await? {
+                BatchStatus::Completed(results) => return Ok(results),
+                BatchStatus::Failed(e) => return Err(e),
+                BatchStatus::InProgress => tokio::time::sleep(self.poll_interval).await,
+            }
+        }
+    }
+}
+
+/// Whether the session's next Planning step asks the LLM. The step limit,
+/// an exhausted budget, deferred tool calls and a cache hit are handled
+/// without a call, so those sessions stay out of the batch.
+fn calls_llm(engine: &mut AgentEngine) -> bool {
+    let memory = &mut engine.memory;
+    if memory.step >= memory.config.max_steps
+        || memory.budget.is_some_and(|b| b.is_exceeded(memory.total_usage))
+        || !memory.deferred_tool_calls.is_empty()
+    {
+        return false;
+    }
+    memory.prepare_prompt(&engine.tools.snapshot());
+    let key = crate::cache::cache_key(&memory.build_messages(), &PlanningState.resolve_model(memory));
+    // A cut-off answer is never served from the cache
+    memory.cache.get(&key).is_none_or(|r| matches!(r, LlmResponse::Truncated { .. }))
+}
+
+fn session_result(engine: &AgentEngine) -> Result<String, AgentError> {
+    if engine.state == State::error() {
+        Err(AgentError::AgentFailed(
+            engine
+                .memory
+                .error
+                .clone()
+                .unwrap_or_else(|| "Unknown error".to_string()),
+        ))
+    } else {
+        Ok(engine
+            .memory
+            .final_answer
+            .clone()
+            .unwrap_or_else(|| "[No answer produced]".to_string()))
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_openai_completion() {
+        let body = json!({
+            "choices": [{ "message": { "content": "billing" } }],
+            "usage": { "prompt_tokens": 12, "completion_tokens": 3 }
+        });
+        match parse_openai_completion(&body).unwrap() {
+            LlmResponse::FinalAnswer { content, usage, .. } => {
+                assert_eq!(content, "billing");
+                assert_eq!(usage.unwrap().total_tokens, 15);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_anthropic_tool_use() {
+        let message = json!({
+            "content": [
+                { "type": "text", "text": "Looking it up." },
+                { "type": "tool_use", "id": "t1", "name": "search", "input": { "q": "rust" } }
+            ],
+            "usage": { "input_tokens": 5, "output_tokens": 5 }
+        });
+        match parse_anthropic_message(&message).unwrap() {
+            LlmResponse::ToolCall { tool, assistant_text, .. } => {
+                assert_eq!(tool.name, "search");
+                assert_eq!(tool.args["q"], "rust");
+                assert_eq!(assistant_text.as_deref(), Some("Looking it up."));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_openai_request_line() {
+        let req = BatchRequest {
+            custom_id: "s1".into(),
+            model: "gpt-4o-mini".into(),
+            messages: vec![json!({ "role": "user", "content": "hi" })],
+            tools: vec![],
+            parallel_tools: true,
+            capabilities: ModelCapabilities::chat(),
+            stop: ["A", "B", "C", "D", "E"].map(String::from).to_vec(),
+            params: ModelParams::new().temperature(0.2).max_tokens(256),
+            seed: Some(7),
+            output_schema: None,
+        };
+        let line = OpenAiBatchProvider::request_line(&req);
+        assert_eq!(line["custom_id"], "s1");
+        assert_eq!(line["url"], "/v1/chat/completions");
+        assert!(line["body"].get("tools").is_none());
+        // The API takes at most four
+        assert_eq!(line["body"]["stop"], json!(["A", "B", "C", "D"]));
+        assert_eq!(line["body"]["temperature"], json!(0.2f32));
+        assert_eq!(line["body"]["max_tokens"], 256);
+        assert_eq!(line["body"]["seed"], 7);
+    }
+
+    #[test]
+    fn test_anthropic_params_with_history() {
+        let req = BatchRequest {
+            custom_id: "s1".into(),
+            model: "claude-3-5-haiku-latest".into(),
+            messages: vec![
+                json!({ "role": "system", "content": "Be brief." }),
+                json!({ "role": "user", "content": "Weather in Oslo?" }),
+                json!({
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "weather", "arguments": "{\"city\":\"Oslo\"}" }
+                    }]
+                }),
+                json!({ "role": "tool", "tool_call_id": "call_1", "name": "weather", "content": "4C" }),
+            ],
+            tools: vec![],
+            parallel_tools: true,
+            capabilities: ModelCapabilities::chat(),
+            stop: vec!["Observation:".to_string()],
+            params: ModelParams::new().top_p(0.9),
+            seed: None,
+            output_schema: None,
+        };
+        let params = AnthropicBatchProvider::new("key").request_params(&req);
+        assert_eq!(params["system"], "Be brief.");
+        assert_eq!(params["stop_sequences"], json!(["Observation:"]));
+        assert_eq!(params["top_p"], json!(0.9f32));
+        // The provider's limit applies when the params set none
+        assert_eq!(params["max_tokens"], 4096);
+        let messages = params["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(messages[1]["content"][0]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][0]["id"], "call_1");
+        assert_eq!(messages[1]["content"][0]["input"]["city"], "Oslo");
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["type"], "tool_result");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "call_1");
+    }
+
+    #[test]
+    fn test_structured_output_requests() {
+        let schema = OutputSchema {
+            name: "ticket".into(),
+            description: None,
+            schema: json!({ "type": "object", "properties": { "queue": { "type": "string" } } }),
+            strict: true,
+        };
+        let req = BatchRequest {
+            custom_id: "s1".into(),
+            model: "m".into(),
+            messages: vec![
+                json!({ "role": "system", "content": "Be brief." }),
+                json!({ "role": "system", "content": "Summary of earlier steps." }),
+                json!({ "role": "user", "content": "Route this ticket" }),
+            ],
+            tools: vec![ToolSchema {
+                name: "lookup".into(),
+                description: "Look something up".into(),
+                input_schema: json!({ "type": "object" }),
+            }],
+            parallel_tools: true,
+            capabilities: ModelCapabilities::chat(),
+            stop: vec![],
+            params: ModelParams::new(),
+            seed: None,
+            output_schema: Some(schema.clone()),
+        };
+
+        let body = &OpenAiBatchProvider::request_line(&req)["body"];
+        assert_eq!(body["response_format"]["type"], "json_schema");
+        assert_eq!(body["response_format"]["json_schema"]["schema"], schema.schema);
+        assert!(body.get("tools").is_none());
+
+        // Every system message is kept, and the answer comes through a tool
+        let params = AnthropicBatchProvider::new("key").request_params(&req);
+        let system = params["system"].as_str().unwrap();
+        assert!(system.starts_with("Be brief.\n\nSummary of earlier steps."));
+        assert!(system.contains(crate::llm::STRUCTURED_OUTPUT_TOOL));
+        assert_eq!(params["tools"][1]["name"], crate::llm::STRUCTURED_OUTPUT_TOOL);
+        assert_eq!(params["tools"][1]["input_schema"], schema.schema);
+
+        let message = json!({
+            "content": [{ "type": "tool_use", "id": "t1", "name": crate::llm::STRUCTURED_OUTPUT_TOOL, "input": { "queue": "billing" } }]
+        });
+        match parse_anthropic_message(&message).unwrap() {
+            LlmResponse::Structured { data, .. } => assert_eq!(data["queue"], "billing"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! `agentsm` — command-line companion for Agent-B.
+//!
+//! ```text
+//! agentsm monitor [--budget N] [FILE|URL]
+//! agentsm models openai|anthropic|gemini|ollama [BASE_URL]
+//! ```
+//!
+//! `monitor` reads `AgentOutput` events as NDJSON (one JSON object per line)
+//! from FILE, or from stdin when FILE is omitted or `-`, and renders them
+//! live. Pipe a running agent into it:
+//!
+//! ```text
+//! my_agent | agentsm monitor --budget 50000
+//! ```
+//!
+//! Given an `http://` URL instead, it subscribes to a server-sent event
+//! stream of `AgentOutput`, such as the `/outputs` route of the `server`
+//! feature's visualizer:
+//!
+//! ```text
+//! agentsm monitor http://127.0.0.1:7878/outputs
+//! ```
+//!
+//! `models` checks that a provider is reachable and lists the models it
+//! serves. Keys come from `OPENAI_API_KEY` / `ANTHROPIC_API_KEY` /
+//! `GEMINI_API_KEY`; Ollama
+//! defaults to `http://localhost:11434/v1`.
+
+use agent_b::llm::{AnthropicCaller, AsyncLlmCaller, GeminiCaller, OpenAiCaller};
+use agent_b::monitor::run_monitor;
+use agent_b::AgentOutput;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+
+const USAGE: &str = "usage: agentsm monitor [--budget N] [FILE|URL]\n       agentsm models openai|anthropic|gemini|ollama [BASE_URL]";
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(args).await {
+        eprintln!("agentsm: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: Vec<String>) -> Result<(), String> {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        Some("monitor") => {}
+        Some("models") => return models(args.collect()).await,
+        _ => return Err(USAGE.to_string()),
+    }
+
+    let mut budget = None;
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--budget" => {
+                let n = args.next().ok_or(USAGE)?;
+                budget = Some(n.parse::<u32>().map_err(|e| format!("invalid --budget: {}", e))?);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let outputs = match path.as_deref() {
+        Some(url) if url.starts_with("http://") || url.starts_with("https://") => subscribe(url).await?,
+        None | Some("-") => read_lines(Box::new(BufReader::new(tokio::io::stdin()))),
+        Some(p) => read_lines(Box::new(BufReader::new(
+            tokio::fs::File::open(p).await.map_err(|e| format!("{}: {}", p, e))?,
+        ))),
+    };
+
+    run_monitor(outputs, budget)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// NDJSON outputs, one per line.
+fn read_lines(reader: Box<dyn AsyncBufRead + Unpin + Send>) -> BoxStream<'static, AgentOutput> {
+    futures::stream::unfold(reader.lines(), |mut lines| async move {
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => {
+                    // Non-JSON lines (regular program output) are ignored
+                    if let Ok(output) = serde_json::from_str::<AgentOutput>(&line) {
+                        return Some((output, lines));
+                    }
+                }
+                _ => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Outputs from the `data:` lines of a server-sent event stream.
+async fn subscribe(url: &str) -> Result<BoxStream<'static, AgentOutput>, String> {
+    let response = reqwest::get(url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("{}: {}", url, e))?;
+
+    let outputs = futures::stream::unfold((response, Vec::new()), |(mut response, mut buffer)| async move {
+        loop {
+            if let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                if let Some(data) = line.trim_end().strip_prefix("data:") {
+                    if let Ok(output) = serde_json::from_str::<AgentOutput>(data.trim_start()) {
+                        return Some((output, (response, buffer)));
+                    }
+                }
+                continue;
+            }
+            match response.chunk().await {
+                Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+                _ => return None,
+            }
+        }
+    });
+    Ok(outputs.boxed())
+}
+
+async fn models(args: Vec<String>) -> Result<(), String> {
+    let base_url = args.get(1).cloned();
+    let caller: Box<dyn AsyncLlmCaller> = match (args.first().map(String::as_str), base_url) {
+        (Some("openai"), None) => Box::new(OpenAiCaller::new()),
+        (Some("openai"), Some(url)) => {
+            let key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
+            Box::new(OpenAiCaller::with_base_url(url, key))
+        }
+        (Some("anthropic"), None) => Box::new(AnthropicCaller::from_env()?),
+        (Some("gemini"), None) => Box::new(GeminiCaller::from_env()?),
+        (Some("ollama"), url) => Box::new(OpenAiCaller::with_base_url(
+            url.unwrap_or_else(|| "http://localhost:11434/v1".to_string()),
+            "ollama",
+        )),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let check = tokio::time::timeout(agent_b::preflight::HEALTH_CHECK_TIMEOUT, caller.health_check());
+    match check.await {
+        Ok(result) => result.map_err(|e| format!("health check failed ({}): {}", e.kind(), e))?,
+        Err(_) => return Err("health check timed out".to_string()),
+    }
+    let mut models = caller.list_models().await.map_err(|e| e.to_string())?;
+    models.sort();
+    for model in models {
+        println!("{}", model);
+    }
+    Ok(())
+}
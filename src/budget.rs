@@ -25,6 +25,26 @@ impl TokenUsage {
     }
 }
 
+/// Price of a model in USD per million tokens. Set with `AgentBuilder::model_pricing`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct ModelPricing {
+    pub input_per_mtok:  f64,
+    pub output_per_mtok: f64,
+}
+
+impl ModelPricing {
+    pub fn new(input_per_mtok: f64, output_per_mtok: f64) -> Self {
+        Self { input_per_mtok, output_per_mtok }
+    }
+
+    /// Cost of `usage` in USD.
+    pub fn cost(&self, usage: TokenUsage) -> f64 {
+        (usage.input_tokens as f64 * self.input_per_mtok
+            + usage.output_tokens as f64 * self.output_per_mtok)
+            / 1_000_000.0
+    }
+}
+
 /// Defines limits on token usage for an agent session.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TokenBudget {
@@ -56,4 +76,13 @@ impl TokenBudget {
         }
         false
     }
+
+    /// What is left of this budget after `used`, for handing on to a later run.
+    pub fn remaining(&self, used: TokenUsage) -> TokenBudget {
+        TokenBudget {
+            max_total_tokens:  self.max_total_tokens.map(|l| l.saturating_sub(used.total_tokens)),
+            max_input_tokens:  self.max_input_tokens.map(|l| l.saturating_sub(used.input_tokens)),
+            max_output_tokens: self.max_output_tokens.map(|l| l.saturating_sub(used.output_tokens)),
+        }
+    }
 }
@@ -7,15 +7,18 @@ use crate::events::Event;
 use crate::healing::HealingPolicy;
 use crate::hooks::{AgentHooks, CompositeHooks, NoopHooks};
 use crate::introspection::{IntrospectionConfig, IntrospectionEngine};
-use crate::llm::{AnthropicCaller, AsyncLlmCaller, OpenAiCaller, RetryingLlmCaller};
+use crate::llm::{
+    AnthropicCaller, AsyncLlmCaller, FallbackLlmCaller, GeminiCaller, OpenAiCaller, RateLimitedLlmCaller,
+    RateLimiter, ReasoningCaller, RetryingLlmCaller,
+};
 use crate::mcp::{bridge_mcp_tool, McpClient};
 use crate::memory::AgentMemory;
 use crate::states::{
     ActingState, AgentState, DoneState, ErrorState, IdleState, ObservingState, ParallelActingState,
     PlanningState, ReflectingState, WaitingForHumanState,
 };
-use crate::tools::{Tool, ToolFn, ToolRegistry};
-use crate::transitions::build_transition_table;
+use crate::tools::{Tool, ToolFn, ToolFnV2, ToolRegistry};
+use crate::transitions::{build_chat_transition_table, build_transition_table};
 use crate::types::{AgentConfig, State};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
@@ -27,6 +30,7 @@ pub struct AgentBuilder {
     llm: Option<Arc<dyn AsyncLlmCaller>>,
     config: Option<AgentConfig>,
     retry_count: Option<u32>,
+    rate_limiter: Option<Arc<RateLimiter>>,
     custom_handlers: HashMap<String, Arc<dyn AgentState>>,
     custom_transitions: Vec<(State, Event, State)>,
     terminal_states: HashSet<String>,
@@ -38,6 +42,15 @@ pub struct AgentBuilder {
     introspection: Option<IntrospectionEngine>,
     healing_policy: Option<HealingPolicy>,
     fork_config: Option<crate::fork::ForkConfig>,
+    config_watcher: Option<crate::hot_reload::ConfigWatcher>,
+    checkpoint_graph: Option<crate::checkpoint::GraphShape>,
+    /// Session and length of the trace log the resumed memory came from
+    checkpoint_trace: Option<(String, usize)>,
+    /// Workspace files from an imported session bundle, written at build
+    imported_artifacts: Vec<crate::bundle::Artifact>,
+    audit_sink: Option<Arc<dyn crate::audit::AuditSink>>,
+    workspace: Option<crate::workspace::WorkspaceSpec>,
+    chat_mode: bool,
 }
 
 impl AgentBuilder {
@@ -53,6 +66,7 @@ impl AgentBuilder {
             llm: None,
             config: None,
             retry_count: None,
+            rate_limiter: None,
             custom_handlers: HashMap::new(),
             custom_transitions: Vec::new(),
             terminal_states: terminal,
@@ -64,6 +78,13 @@ impl AgentBuilder {
             introspection: None,
             healing_policy: None,
             fork_config: None,
+            config_watcher: None,
+            checkpoint_graph: None,
+            checkpoint_trace: None,
+            imported_artifacts: Vec::new(),
+            audit_sink: None,
+            workspace: None,
+            chat_mode: false,
         }
     }
 
@@ -77,6 +98,14 @@ impl AgentBuilder {
         self
     }
 
+    /// Pin a constraint to the task. Pinned constraints are sent with the
+    /// task in every request, even after reflection has compressed history
+    /// or the memory strategy has trimmed older messages.
+    pub fn pin(mut self, constraint: impl Into<String>) -> Self {
+        self.memory.pinned.push(constraint.into());
+        self
+    }
+
     pub fn system_prompt(mut self, p: impl Into<String>) -> Self {
         self.memory.system_prompt = p.into();
         self
@@ -89,6 +118,31 @@ impl AgentBuilder {
         self
     }
 
+    /// Set the system prompt template for `locale` (e.g. `"de"`, `"pt-BR"`).
+    /// The one matching `AgentConfig::locale` (exactly, or by language) is
+    /// used instead of `prompt_template` and `system_prompt`.
+    pub fn localized_prompt(
+        mut self,
+        locale: impl Into<String>,
+        template: crate::prompt::PromptTemplate,
+    ) -> Self {
+        self.memory.localized_prompts.insert(locale.into(), template);
+        self
+    }
+
+    /// Locale used to choose localized prompts and observation markers.
+    /// Default: `"en"`.
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        self.memory.config.locale = locale.into();
+        self
+    }
+
+    /// Markers put in front of tool observations, overriding the locale's.
+    pub fn observation_markers(mut self, markers: crate::locale::ObservationMarkers) -> Self {
+        self.memory.config.observation_markers = Some(markers);
+        self
+    }
+
     /// Enable LLM response caching. Duplicate messages with the same content
     /// and model will return cached results instead of calling the LLM.
     pub fn cache(mut self, cache: std::sync::Arc<dyn crate::cache::LlmCache>) -> Self {
@@ -106,6 +160,54 @@ impl AgentBuilder {
         self
     }
 
+    /// Set how planning requests are framed (native function calling,
+    /// ReAct text, XML blocks). Defaults to `NativePrompter`.
+    pub fn planning_prompter(mut self, prompter: Arc<dyn crate::prompter::PlanningPrompter>) -> Self {
+        self.memory.planning_prompter = prompter;
+        self
+    }
+
+    /// Keep native tool calling, but also accept tool calls a model writes
+    /// into its answer in `format`. The format is described in the system prompt.
+    pub fn text_tool_calls(mut self, format: crate::prompter::TextToolFormat) -> Self {
+        self.memory.config.text_tool_calls = Some(format);
+        self
+    }
+
+    /// Set provider-side sampling options for planning requests.
+    pub fn sampling(mut self, params: crate::types::SamplingParams) -> Self {
+        self.memory.config.sampling = params;
+        self
+    }
+
+    /// Constrain planning replies with a grammar or JSON schema (llama.cpp, Ollama).
+    /// `GrammarConstraint::ToolCalls` pairs with `JsonPrompter`.
+    pub fn grammar(mut self, constraint: crate::grammar::GrammarConstraint) -> Self {
+        self.memory.config.sampling.grammar = Some(constraint);
+        self
+    }
+
+    /// End generations before `sequence`. Adds to the planning prompter's
+    /// own stop sequences (`ReActPrompter` stops at `"\nObservation:"`).
+    pub fn stop_sequence(mut self, sequence: impl Into<String>) -> Self {
+        self.memory.config.sampling.stop.push(sequence.into());
+        self
+    }
+
+    /// Cap the output tokens of each LLM call. Answers cut off at the cap
+    /// are continued up to `max_continuations` times.
+    pub fn max_output_tokens(mut self, max: u32) -> Self {
+        self.memory.config.sampling.max_tokens = Some(max);
+        self
+    }
+
+    /// How many follow-up calls continue an answer cut off at the output
+    /// token limit before it is accepted as truncated (0 = never continue).
+    pub fn max_continuations(mut self, n: usize) -> Self {
+        self.memory.config.max_continuations = n;
+        self
+    }
+
     // ── Execution Contracts ───────────────────────────────────────────────────
 
     /// Add a pre-condition guard on a state transition.
@@ -153,6 +255,14 @@ impl AgentBuilder {
         self
     }
 
+    /// Run mutating tools (`Tool::mutating(true)`) or only describe them.
+    /// With `ExecutionMode::Plan`, review `engine.change_plan()` after `run()`
+    /// and call `engine.apply()` once it is approved.
+    pub fn execution_mode(mut self, mode: crate::dry_run::ExecutionMode) -> Self {
+        self.memory.execution_mode = mode;
+        self
+    }
+
     /// Enable speculative forking with multiple parallel branches.
     pub fn fork_strategy(mut self, config: crate::fork::ForkConfig) -> Self {
         self.fork_config = Some(config);
@@ -184,13 +294,20 @@ impl AgentBuilder {
         self
     }
 
+    /// Use several providers in order, moving on to the next when one is
+    /// rate limited, failing with a 5xx or unreachable. See `FallbackLlmCaller`.
+    pub fn llm_fallback(mut self, callers: Vec<Arc<dyn AsyncLlmCaller>>) -> Self {
+        self.llm = Some(Arc::new(FallbackLlmCaller::new(callers)));
+        self
+    }
+
     /// Use the standard OpenAI API.
     pub fn openai(mut self, api_key: impl Into<String>) -> Self {
         let key = api_key.into();
         let caller = if key.is_empty() {
             OpenAiCaller::new()
         } else {
-            OpenAiCaller::with_base_url("https://api.openai.com/v1", key)
+            OpenAiCaller::with_base_url("https://api.openai.com/v1", key).with_logprobs(true)
         };
         self.llm = Some(Arc::new(caller));
         self
@@ -203,6 +320,50 @@ impl AgentBuilder {
         self
     }
 
+    /// Use xAI's Grok API. Streamed `reasoning_content` becomes `AgentOutput::Reasoning`.
+    pub fn xai(mut self, api_key: impl Into<String>) -> Self {
+        self.llm = Some(Arc::new(ReasoningCaller::xai(api_key)));
+        self
+    }
+
+    /// Use the DeepSeek API. Streamed `reasoning_content` becomes `AgentOutput::Reasoning`.
+    pub fn deepseek(mut self, api_key: impl Into<String>) -> Self {
+        self.llm = Some(Arc::new(ReasoningCaller::deepseek(api_key)));
+        self
+    }
+
+    /// Use llama.cpp's `llama-server`. Requests carry any grammar constraint.
+    pub fn llama_server(mut self, base_url: impl Into<String>) -> Self {
+        self.llm = Some(Arc::new(ReasoningCaller::llama_server(base_url)));
+        self
+    }
+
+    /// Run a GGUF model in-process with llama.cpp. The model is loaded on the
+    /// first call. Selects `JsonPrompter` unless a text prompter is already set.
+    #[cfg(feature = "llama-cpp")]
+    pub fn llama_cpp(
+        mut self,
+        model_path: impl Into<std::path::PathBuf>,
+        params: crate::llm::LlamaCppParams,
+    ) -> Self {
+        self.llm = Some(Arc::new(crate::llm::LlamaCppCaller::new(model_path, params)));
+        if self.memory.planning_prompter.native_tools() {
+            self.memory.planning_prompter = Arc::new(crate::prompter::JsonPrompter);
+        }
+        self
+    }
+
+    /// Run a local model directory with candle. The model is loaded on the first
+    /// call. Selects the family's prompter unless a text prompter is already set.
+    #[cfg(feature = "candle")]
+    pub fn candle(mut self, model_dir: impl Into<std::path::PathBuf>, params: crate::llm::CandleParams) -> Self {
+        if self.memory.planning_prompter.native_tools() {
+            self.memory.planning_prompter = params.arch.default_prompter();
+        }
+        self.llm = Some(Arc::new(crate::llm::CandleCaller::new(model_dir, params)));
+        self
+    }
+
     /// Use a local Ollama instance (OpenAI-compatible API).
     pub fn ollama(mut self, base_url: impl Into<String>) -> Self {
         let url = {
@@ -218,6 +379,13 @@ impl AgentBuilder {
         self
     }
 
+    /// Use a server that only has a `/completions` endpoint, rendering
+    /// messages with `template`. Tool calls are read from `<tool_call>` blocks.
+    pub fn completion_endpoint(mut self, base_url: impl Into<String>, template: crate::llm::ChatTemplate) -> Self {
+        self.llm = Some(Arc::new(crate::llm::CompletionCaller::new(base_url, template)));
+        self
+    }
+
     /// Set the maximum number of total tokens allowed for this agent session.
     pub fn max_tokens(mut self, max: u32) -> Self {
         self.memory.budget = Some(TokenBudget::new(max));
@@ -230,6 +398,23 @@ impl AgentBuilder {
         self
     }
 
+    /// Price `model` in USD per million input and output tokens, so
+    /// `AgentOutput::Usage` reports the session cost.
+    pub fn model_pricing(mut self, model: impl Into<String>, input_per_mtok: f64, output_per_mtok: f64) -> Self {
+        self.memory.config.pricing.insert(
+            model.into(),
+            crate::budget::ModelPricing::new(input_per_mtok, output_per_mtok),
+        );
+        self
+    }
+
+    /// Describe what `model`'s API accepts, overriding detection by name
+    /// (see `ModelCapabilities::detect`).
+    pub fn model_capabilities(mut self, model: impl Into<String>, capabilities: crate::llm::ModelCapabilities) -> Self {
+        self.memory.config.model_capabilities.insert(model.into(), capabilities);
+        self
+    }
+
     /// Use the Anthropic API (Claude models).
     pub fn anthropic(mut self, api_key: impl Into<String>) -> Self {
         let key = api_key.into();
@@ -244,6 +429,21 @@ impl AgentBuilder {
         self
     }
 
+    /// Use the Google Gemini API. An empty key reads `GEMINI_API_KEY`,
+    /// then `GOOGLE_API_KEY`.
+    pub fn gemini(mut self, api_key: impl Into<String>) -> Self {
+        let key = api_key.into();
+        let result = if key.is_empty() {
+            GeminiCaller::from_env()
+        } else {
+            Ok(GeminiCaller::new(key))
+        };
+        if let Ok(caller) = result {
+            self.llm = Some(Arc::new(caller));
+        }
+        self
+    }
+
     // ── Retry policy ────────────────────────────────────────────────────────
 
     pub fn retry_on_error(mut self, n: u32) -> Self {
@@ -251,6 +451,20 @@ impl AgentBuilder {
         self
     }
 
+    /// Keep LLM calls under `requests_per_minute` and `tokens_per_minute`
+    /// (0 = unlimited), waiting instead of hitting the provider's 429s.
+    /// Agents built from clones of this builder, such as sub-agents and
+    /// pools, share the limits.
+    pub fn rate_limit(self, requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        self.rate_limiter(Arc::new(RateLimiter::new(requests_per_minute, tokens_per_minute)))
+    }
+
+    /// Like `rate_limit`, drawing on a limiter shared with other builders.
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
     // ── Configuration ────────────────────────────────────────────────────────
 
     pub fn config(mut self, config: AgentConfig) -> Self {
@@ -258,12 +472,27 @@ impl AgentBuilder {
         self
     }
 
+    /// Reload the system prompt, model map and tool blacklist from a JSON
+    /// file whenever it changes. Checked between steps.
+    pub fn watch_config(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.config_watcher = Some(crate::hot_reload::ConfigWatcher::new(path));
+        self
+    }
+
     /// Set the checkpoint store for persistence.
     pub fn checkpoint_store(mut self, store: Arc<dyn CheckpointStore>) -> Self {
         self.checkpoint_store = Some(store);
         self
     }
 
+    /// Record every memory change in the trace, so memory can be rebuilt
+    /// from it (`AgentMemory::from_trace`). Checkpoints in stores that keep
+    /// a trace log then hold no memory of their own.
+    pub fn event_sourced_memory(mut self, enabled: bool) -> Self {
+        self.memory.config.event_sourced_memory = enabled;
+        self
+    }
+
     /// Set a custom session ID.
     pub fn session_id(mut self, id: impl Into<String>) -> Self {
         self.session_id = id.into();
@@ -286,11 +515,85 @@ impl AgentBuilder {
                 AgentError::BuildError(format!("No checkpoint found for session: {}", session_id))
             })?;
 
-        self.memory = checkpoint.memory;
-        self.session_id = checkpoint.session_id;
-        self.initial_state = Some(checkpoint.state);
+        self.session_id = checkpoint.session_id.clone();
+        Ok(self.from_checkpoint(checkpoint))
+    }
 
-        Ok(self)
+    /// Continue a session exported with `AgentEngine::export_session`.
+    ///
+    /// The bundle's checkpoints are saved to this builder's checkpoint store
+    /// (if any) and the session ID is taken from the bundle. Workspace files
+    /// are written to this builder's workspace at build, using a temp
+    /// workspace if none is set. As with `.resume()`, the graph must still
+    /// contain the bundled states and transitions.
+    pub async fn import_session(self, path: impl AsRef<std::path::Path>) -> Result<Self, AgentError> {
+        let bundle = crate::bundle::SessionBundle::read(path, &crate::checkpoint::PlainCodec)
+            .map_err(|e| AgentError::BuildError(format!("Failed to read session bundle: {}", e)))?;
+        self.import_bundle(bundle).await
+    }
+
+    /// [`AgentBuilder::import_session`] for a bundle already in memory.
+    pub async fn import_bundle(mut self, bundle: crate::bundle::SessionBundle) -> Result<Self, AgentError> {
+        if let Some(store) = &self.checkpoint_store {
+            for checkpoint in bundle.checkpoints {
+                store
+                    .save(checkpoint)
+                    .await
+                    .map_err(|e| AgentError::BuildError(format!("Failed to import checkpoint: {}", e)))?;
+            }
+        }
+        if !bundle.artifacts.is_empty() && self.workspace.is_none() {
+            self = self.temp_workspace();
+        }
+        self.imported_artifacts = bundle.artifacts;
+        self.session_id = bundle.session_id.clone();
+        Ok(self.from_checkpoint(crate::checkpoint::AgentCheckpoint {
+            checkpoint_id: uuid::Uuid::new_v4().to_string(),
+            session_id: bundle.session_id,
+            state: bundle.state,
+            memory: bundle.memory,
+            timestamp: bundle.exported_at,
+            graph: Some(bundle.config.graph),
+            trace_cursor: None,
+            memory_from_trace: false,
+            sequence: 0,
+            schema_version: crate::checkpoint::CHECKPOINT_SCHEMA_VERSION,
+        }))
+    }
+
+    /// Start from the memory and state captured in a checkpoint.
+    ///
+    /// Unlike `.resume()`, the session ID is left untouched, so the run is
+    /// recorded as a new session. Runtime-only settings configured on this
+    /// builder (hooks, cache, approval callback, ...) are kept.
+    pub fn from_checkpoint(mut self, checkpoint: crate::checkpoint::AgentCheckpoint) -> Self {
+        let mut memory = checkpoint.memory;
+        memory.approval_callback = self.memory.approval_callback.take();
+        memory.prompt_template = self.memory.prompt_template.take();
+        memory.localized_prompts = std::mem::take(&mut self.memory.localized_prompts);
+        memory.cache = Arc::clone(&self.memory.cache);
+        memory.memory_strategy = Arc::clone(&self.memory.memory_strategy);
+        memory.planning_prompter = Arc::clone(&self.memory.planning_prompter);
+        memory.hooks = Arc::clone(&self.memory.hooks);
+        memory.routing_policy = self.memory.routing_policy.take();
+        memory.semantic_dedup = self.memory.semantic_dedup.take();
+        memory.moderation = self.memory.moderation.take();
+        memory.policy_engine = self.memory.policy_engine.take();
+        memory.answer_transforms = std::mem::take(&mut self.memory.answer_transforms);
+        memory.run_hooks = std::mem::take(&mut self.memory.run_hooks);
+        memory.notifications = self.memory.notifications.take();
+        memory.raw_log = self.memory.raw_log.take();
+        memory.planning_mode = self.memory.planning_mode.clone();
+        memory.replay_recorder = self.memory.replay_recorder.clone();
+        memory.composite_tools = self.memory.composite_tools.clone();
+        memory.tool_extensions = self.memory.tool_extensions.clone();
+        memory.observability = self.memory.observability.clone();
+
+        self.memory = memory;
+        self.initial_state = Some(checkpoint.state);
+        self.checkpoint_graph = checkpoint.graph;
+        self.checkpoint_trace = checkpoint.trace_cursor.map(|c| (checkpoint.session_id, c));
+        self
     }
 
     pub fn max_steps(mut self, n: usize) -> Self {
@@ -298,12 +601,193 @@ impl AgentBuilder {
         self
     }
 
+    /// Also compress history whenever `trigger` fires after a step.
+    pub fn reflect_when(mut self, trigger: crate::types::ReflectionTrigger) -> Self {
+        self.memory.config.reflection_triggers.push(trigger);
+        self
+    }
+
+    /// Repair malformed or schema-violating tool arguments with a cheap model
+    /// before giving up on the call.
+    pub fn arg_repair(mut self, model: impl Into<String>) -> Self {
+        self.memory.config.arg_repair_model = Some(model.into());
+        self
+    }
+
+    /// Convert tool arguments to the types their schema declares before the
+    /// tool runs: `"3"` to `3`, `"true"` to `true`, `7` to `"7"`. Each
+    /// conversion is logged as `ARGS_COERCED`.
+    pub fn coerce_tool_args(mut self, enabled: bool) -> Self {
+        self.memory.config.coerce_tool_args = enabled;
+        self
+    }
+
+    /// Scan tool output for prompt-injection attempts before it is added to
+    /// history. Suspicious observations are flagged or stripped.
+    pub fn observation_sanitizer(mut self, sanitizer: crate::sanitizer::ObservationSanitizer) -> Self {
+        self.memory.config.observation_sanitizer = Some(sanitizer);
+        self
+    }
+
+    /// Collapse an observation that repeats a recent one into a single
+    /// history entry marked `[repeated N times]`.
+    pub fn observation_dedup(mut self, dedup: crate::dedup::ObservationDedup) -> Self {
+        self.memory.config.observation_dedup = Some(dedup);
+        self
+    }
+
+    /// While a slow tool call runs, make the next planning call against a
+    /// guess at its result: the earlier result of a repeated call, or a note
+    /// that it is pending. The response is kept if it holds for the real
+    /// observation.
+    pub fn speculative_planning(mut self, speculation: crate::speculation::SpeculativePlanning) -> Self {
+        self.memory.config.speculative_planning = Some(speculation);
+        self
+    }
+
+    /// Before running a tool call, compare it by embedding with earlier calls
+    /// to the same tool. A repeat is not run; the model is reminded of the
+    /// earlier result and asked again.
+    pub fn semantic_dedup(mut self, dedup: crate::dedup::SemanticDedup) -> Self {
+        self.memory.semantic_dedup = Some(dedup);
+        self
+    }
+
+    /// Limit how many times a too-short final answer is sent back for revision.
+    /// When `accept` is true the last answer is accepted once the limit is hit;
+    /// otherwise the agent fails with `AnswerRevisionsExhausted`.
+    pub fn answer_revisions(mut self, max: usize, accept: bool) -> Self {
+        self.memory.config.max_answer_revisions = max;
+        self.memory.config.accept_short_answer = accept;
+        self
+    }
+
+    /// Send back final answers that do not cite (`[n]`) the sources tools
+    /// attached with `citations::with_citations`. Shares the `answer_revisions` limit.
+    pub fn require_citations(mut self, required: bool) -> Self {
+        self.memory.config.require_citations = required;
+        self
+    }
+
+    /// Build a tool-less chat agent: the graph is just Idle → Planning → Done,
+    /// and no tool schemas are sent. Cheaper for plain Q&A. Building fails if
+    /// any tools are registered.
+    pub fn chat_mode(mut self, enabled: bool) -> Self {
+        self.chat_mode = enabled;
+        self
+    }
+
+    /// Announce the run with an `AgentOutput::Action` naming the task, the
+    /// model and the available tools before planning starts.
+    pub fn kickoff(mut self, enabled: bool) -> Self {
+        self.memory.config.kickoff = enabled;
+        self
+    }
+
+    /// Before the first LLM call, check every tool schema and that the
+    /// provider is reachable and knows the model. A problem fails the run
+    /// with `PreflightFailed` before any tokens are spent.
+    pub fn preflight(mut self, enabled: bool) -> Self {
+        self.memory.config.preflight = enabled;
+        self
+    }
+
+    /// End each planning request with a status line such as
+    /// "Status: step 7/15, 12,400/50,000 tokens used, 3 tools available",
+    /// so the model can pace itself and wrap up before hitting a limit.
+    pub fn status_line(mut self, enabled: bool) -> Self {
+        self.memory.config.status_line = enabled;
+        self
+    }
+
+    /// Which trace entries the run records (default: all). Errors are
+    /// always recorded. Change it while running through
+    /// `AgentEngine::observability()` or `AgentHandle::observability()`.
+    pub fn trace_level(self, level: crate::observability::TraceLevel) -> Self {
+        self.memory.observability.set_trace_level(level);
+        self
+    }
+
+    /// How token-level stream outputs are sent (default: one per chunk).
+    pub fn token_events(self, events: crate::observability::TokenEvents) -> Self {
+        self.memory.observability.set_token_events(events);
+        self
+    }
+
+    /// Make runs of the same agent and task as repeatable as the provider
+    /// allows: `seed` is sent to providers that take one, temperature is
+    /// pinned to 0 and tool calls run one at a time in the order the model
+    /// gave them. Applied at `build()`, so it overrides `.sampling()` and
+    /// `.parallel_tools()`. Each run logs `DETERMINISTIC` with the seed and
+    /// the config hash, to tell comparable runs apart.
+    pub fn deterministic(mut self, seed: u64) -> Self {
+        self.memory.config.deterministic_seed = Some(seed);
+        self
+    }
+
     /// Enable or disable parallel tool execution.
     pub fn parallel_tools(mut self, enabled: bool) -> Self {
         self.memory.config.parallel_tools = enabled;
         self
     }
 
+    /// How many failed LLM calls a run survives. Below the limit, Planning
+    /// waits `delay` (doubling per failure, up to 30s) and plans again.
+    pub fn max_llm_failures(mut self, per_run: usize, delay: std::time::Duration) -> Self {
+        self.memory.config.max_llm_failures_per_run = per_run;
+        self.memory.config.llm_retry_delay_ms = delay.as_millis() as u64;
+        self
+    }
+
+    /// Run a failed call in a parallel batch up to `n` more times before the
+    /// batch's results go back to the LLM. The count is kept in
+    /// `ToolResult::retries`. Default: 0.
+    pub fn parallel_tool_retries(mut self, n: u32) -> Self {
+        self.memory.config.parallel_tool_retries = n;
+        self
+    }
+
+    /// After a cancel, how long parallel tools get to return before they
+    /// are recorded as failed and abandoned on their threads. Default: 2s.
+    pub fn cancel_grace(mut self, grace: std::time::Duration) -> Self {
+        self.memory.config.cancel_grace_ms = grace.as_millis() as u64;
+        self
+    }
+
+    /// How many times Planning re-asks the LLM, in the same step, when it
+    /// names a tool that is not registered (0 = let Acting fail the call).
+    pub fn unknown_tool_retries(mut self, n: usize) -> Self {
+        self.memory.config.unknown_tool_retries = n;
+        self
+    }
+
+    /// What to do when the LLM repeats the previous step's tool call with
+    /// the same arguments. Default: run it again.
+    pub fn on_repeated_call(mut self, action: crate::types::RepeatedCallAction) -> Self {
+        self.memory.config.repeated_call_action = action;
+        self
+    }
+
+    /// Cap how many tool calls from a single LLM response run in one step.
+    /// Excess calls are deferred and executed on the following steps.
+    pub fn max_tool_calls_per_step(mut self, n: usize) -> Self {
+        self.memory.config.max_tool_calls_per_step = Some(n);
+        self
+    }
+
+    /// Collapse failed attempts into one note when a human rejects a tool call.
+    pub fn prune_on_rejection(mut self, enabled: bool) -> Self {
+        self.memory.config.prune_on_rejection = enabled;
+        self
+    }
+
+    /// Collapse failed attempts into one note after `n` consecutive tool
+    /// failures (0 = never).
+    pub fn prune_after_failures(mut self, n: usize) -> Self {
+        self.memory.config.prune_after_failures = n;
+        self
+    }
+
     /// Require human approval for certain tools.
     pub fn approval_policy(mut self, policy: crate::human::ApprovalPolicy) -> Self {
         self.memory.approval_policy = policy;
@@ -342,6 +826,15 @@ impl AgentBuilder {
         self
     }
 
+    /// Sampling options for a model name or task type, applied over
+    /// `sampling` when that model is called or that task type runs. A
+    /// model's entry wins over its task type's. Used by the OpenAI and
+    /// Anthropic callers.
+    pub fn model_params(mut self, model_or_task_type: impl Into<String>, params: crate::types::ModelParams) -> Self {
+        self.memory.config.model_params.insert(model_or_task_type.into(), params);
+        self
+    }
+
     /// Supply the full model map all at once.
     pub fn models(mut self, models: std::collections::HashMap<String, String>) -> Self {
         self.memory.config.models = models;
@@ -352,7 +845,9 @@ impl AgentBuilder {
     ///
     /// The LLM will be instructed to return JSON matching the schema.
     /// OpenAI uses `response_format: json_object` mode; Anthropic uses
-    /// a synthetic tool to extract structured data.
+    /// a synthetic tool to extract structured data. Planning checks the
+    /// answer against the schema and sends a mismatch back to the model
+    /// (`InvalidStructuredOutput`).
     ///
     /// # Example
     /// ```no_run
@@ -374,6 +869,22 @@ impl AgentBuilder {
             name: name.into(),
             description: None,
             schema,
+            strict: false,
+        });
+        self
+    }
+
+    /// Like `output_schema`, but OpenAI decodes against the schema itself
+    /// (`response_format: json_schema`, strict) instead of JSON mode. The
+    /// schema must list every property as required and set
+    /// `additionalProperties: false`. Other providers treat it like
+    /// `output_schema`.
+    pub fn output_schema_strict(mut self, name: impl Into<String>, schema: serde_json::Value) -> Self {
+        self.memory.config.output_schema = Some(crate::types::OutputSchema {
+            name: name.into(),
+            description: None,
+            schema,
+            strict: true,
         });
         self
     }
@@ -389,6 +900,7 @@ impl AgentBuilder {
             name: name.into(),
             description: Some(description.into()),
             schema,
+            strict: false,
         });
         self
     }
@@ -407,12 +919,59 @@ impl AgentBuilder {
         self
     }
 
+    /// Register a raw tool whose implementation receives the `ToolContext`.
+    pub fn tool_with_context(
+        mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        schema: serde_json::Value,
+        func: ToolFnV2,
+    ) -> Self {
+        self.tools.register_with_context(name, description, schema, func);
+        self
+    }
+
+    /// Give each built agent a fresh directory under the system temp dir,
+    /// shared by its tools through `ToolContext::workspace`. Removed at the
+    /// end of every run unless `workspace_cleanup` says otherwise.
+    pub fn temp_workspace(mut self) -> Self {
+        self.workspace = Some(crate::workspace::WorkspaceSpec { path: None, cleanup: None });
+        self
+    }
+
+    /// Use `path` (created if missing) as the tools' workspace. Kept after
+    /// runs unless `workspace_cleanup` says otherwise.
+    pub fn workspace(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.workspace = Some(crate::workspace::WorkspaceSpec { path: Some(path.into()), cleanup: None });
+        self
+    }
+
+    /// When the workspace is deleted. Call after `workspace` or `temp_workspace`.
+    pub fn workspace_cleanup(mut self, cleanup: crate::workspace::WorkspaceCleanup) -> Self {
+        if let Some(spec) = &mut self.workspace {
+            spec.cleanup = Some(cleanup);
+        }
+        self
+    }
+
+    /// Make `value` available to every tool through `ToolContext::extensions`.
+    pub fn tool_extension<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.memory.tool_extensions.insert(value);
+        self
+    }
+
     /// Register a tool built with the `Tool` builder.
     pub fn add_tool(mut self, tool: Tool) -> Self {
         self.tools.register_tool(tool);
         self
     }
 
+    /// Register the built-in `wait_until` tool, which suspends the run until
+    /// a time or an external event. See [`crate::suspend`].
+    pub fn wait_tool(self) -> Self {
+        self.add_tool(crate::suspend::wait_tool())
+    }
+
     /// Register an MCP server and all its tools.
     pub fn mcp_server(mut self, command: impl Into<String>, args: &[String]) -> Self {
         let cmd = command.into();
@@ -472,8 +1031,79 @@ impl AgentBuilder {
         self
     }
 
+    /// Debate every final answer before accepting it.
+    /// Routes `Planning --LlmFinalAnswer--> Debating --DebateConcluded--> Done`.
+    pub fn debate(self, config: crate::debate::DebateConfig) -> Self {
+        self.state("Debating", Arc::new(crate::debate::DebateState::new(config)))
+            .transition("Planning", "LlmFinalAnswer", "Debating")
+            .transition("Debating", "DebateConcluded", "Done")
+    }
+
     // ── Hooks ────────────────────────────────────────────────────────────────────
 
+    /// Write an audit record for every tool execution to `sink`,
+    /// separate from the trace.
+    pub fn audit_sink(mut self, sink: Arc<dyn crate::audit::AuditSink>) -> Self {
+        self.audit_sink = Some(sink);
+        self
+    }
+
+    /// Record the raw HTTP request and response of every provider call to
+    /// `sink`, with credentials redacted. For diagnosing provider errors;
+    /// the bodies hold full prompts and answers.
+    pub fn raw_llm_log(mut self, sink: Arc<dyn crate::llm::RawLogSink>) -> Self {
+        self.memory.raw_log = Some(sink);
+        self
+    }
+
+    /// Check every final answer with a moderation hook before it is accepted.
+    /// With `OnAnswerBlocked::Revise`, routes `Planning --AnswerBlocked--> Planning`.
+    pub fn moderation(mut self, moderation: crate::moderation::Moderation) -> Self {
+        let revise = moderation.on_blocked == crate::moderation::OnAnswerBlocked::Revise;
+        self.memory.moderation = Some(moderation);
+        if revise {
+            self.transition("Planning", "AnswerBlocked", "Planning")
+        } else {
+            self
+        }
+    }
+
+    /// Ask a policy engine (OPA, Cedar, ...) before every tool execution and
+    /// before a final answer is accepted. Denied calls are not run; a denied
+    /// answer fails the run.
+    pub fn policy_engine(mut self, engine: Arc<dyn crate::policy::PolicyEngine>) -> Self {
+        self.memory.policy_engine = Some(engine);
+        self
+    }
+
+    /// Post-process every final answer with `transform`, after the ones
+    /// already added (markdown cleanup, footnotes, templates, ...).
+    pub fn answer_transform(mut self, transform: Arc<dyn crate::postprocess::FinalAnswerTransform>) -> Self {
+        self.memory.answer_transforms.push(transform);
+        self
+    }
+
+    /// Send approval requests, run completions and failures to a notifier
+    /// (Slack, HTTP, ...). Can be called several times.
+    pub fn notifier(mut self, notifier: Arc<dyn crate::notify::Notifier>) -> Self {
+        self.memory
+            .notifications
+            .get_or_insert_with(Default::default)
+            .notifiers
+            .push(notifier);
+        self
+    }
+
+    /// Deep link to a session, included in notifications. `{session_id}` is
+    /// replaced with the session ID.
+    pub fn session_link(mut self, template: impl Into<String>) -> Self {
+        self.memory
+            .notifications
+            .get_or_insert_with(Default::default)
+            .session_link = Some(template.into());
+        self
+    }
+
     /// Register a callback hook for real-time agent observability.
     /// Multiple hooks can be added; they are called in registration order.
     pub fn on_hook(mut self, hook: Arc<dyn AgentHooks>) -> Self {
@@ -481,6 +1111,38 @@ impl AgentBuilder {
         self
     }
 
+    /// Run `hook` with the `RunResult` of every successful run, before
+    /// `run()` returns. Can be called several times; hooks run in order.
+    ///
+    /// ```rust,ignore
+    /// let agent = AgentBuilder::new("Build the quarterly report")
+    ///     .openai("")
+    ///     .on_complete(move |result| {
+    ///         let bucket = bucket.clone();
+    ///         async move { bucket.put("report.md", result.answer).await; }
+    ///     })
+    ///     .build()?;
+    /// ```
+    pub fn on_complete<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(crate::types::RunResult) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.memory.run_hooks.on_complete.push(Arc::new(move |result| Box::pin(hook(result))));
+        self
+    }
+
+    /// Run `hook` with the error of every failed run, before `run()` returns.
+    /// Can be called several times; hooks run in order.
+    pub fn on_error<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(AgentError) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.memory.run_hooks.on_error.push(Arc::new(move |error| Box::pin(hook(error))));
+        self
+    }
+
     // ── Sub-Agents as Tools ──────────────────────────────────────────────
 
     /// Converts this builder into a tool that can be used by another agent.
@@ -551,6 +1213,87 @@ impl AgentBuilder {
         self.add_tool(tool)
     }
 
+    /// Register a sub-agent tool that takes a list of tasks and runs one
+    /// instance of `subagent` per task, at most `max_parallel` at a time.
+    ///
+    /// The answers are merged into a single observation, one section per
+    /// task, in the order the tasks were given. The call only fails when
+    /// every sub-agent fails.
+    pub fn add_subagent_pool(
+        self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        subagent: AgentBuilder,
+        max_parallel: usize,
+    ) -> Self {
+        let max_parallel = max_parallel.max(1);
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "tasks": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Independent tasks to delegate, one sub-agent each"
+                }
+            },
+            "required": ["tasks"]
+        });
+
+        let func: ToolFn = Arc::new(move |args| {
+            let tasks: Vec<String> = args
+                .get("tasks")
+                .and_then(|v| v.as_array())
+                .ok_or_else(|| "Missing required 'tasks' array for sub-agent pool".to_string())?
+                .iter()
+                .map(|t| t.as_str().map(String::from).unwrap_or_else(|| t.to_string()))
+                .collect();
+            if tasks.is_empty() {
+                return Err("'tasks' must contain at least one task".to_string());
+            }
+
+            let results: Vec<Result<String, String>> = tokio::task::block_in_place(|| {
+                tokio::runtime::Handle::current().block_on(async {
+                    use futures::StreamExt;
+
+                    futures::stream::iter(tasks.iter().cloned())
+                        .map(|task| {
+                            let mut sub = subagent.clone();
+                            sub.memory.task = task;
+                            // Instances must not share checkpoints
+                            sub.session_id = uuid::Uuid::new_v4().to_string();
+                            async move {
+                                let mut engine = sub
+                                    .build()
+                                    .map_err(|e| format!("Failed to build sub-agent: {}", e))?;
+                                engine.run().await.map_err(|e| e.to_string())
+                            }
+                        })
+                        .buffered(max_parallel)
+                        .collect()
+                        .await
+                })
+            });
+
+            if results.iter().all(|r| r.is_err()) {
+                let errors: Vec<String> = results.into_iter().filter_map(Result::err).collect();
+                return Err(format!("All {} sub-agents failed: {}", errors.len(), errors.join("; ")));
+            }
+
+            let sections: Vec<String> = tasks
+                .iter()
+                .zip(results)
+                .enumerate()
+                .map(|(i, (task, result))| match result {
+                    Ok(answer) => format!("## Task {}: {}\n{}", i + 1, task, answer),
+                    Err(e) => format!("## Task {}: {}\nERROR: {}", i + 1, task, e),
+                })
+                .collect();
+            Ok(sections.join("\n\n"))
+        });
+
+        self.tool(name, description, schema, func)
+    }
+
     // ── Build ────────────────────────────────────────────────────────────────
 
     pub fn build(mut self) -> Result<AgentEngine, AgentError> {
@@ -558,6 +1301,9 @@ impl AgentBuilder {
             .llm
             .ok_or_else(|| AgentError::BuildError("LLM caller is required.".to_string()))?;
 
+        if let Some(limiter) = &self.rate_limiter {
+            llm = Arc::new(RateLimitedLlmCaller::with_limiter(llm, Arc::clone(limiter)));
+        }
         if let Some(n) = self.retry_count {
             llm = Arc::new(RetryingLlmCaller::new(llm, n));
         }
@@ -565,26 +1311,41 @@ impl AgentBuilder {
         if let Some(config) = self.config {
             self.memory.config = config;
         }
+        self.memory.config.apply_determinism();
+        self.memory.config.validate()?;
+
+        if self.chat_mode && !self.tools.is_empty() {
+            return Err(AgentError::BuildError(format!(
+                "chat mode agents cannot have tools ({} registered)",
+                self.tools.len()
+            )));
+        }
 
         let mut handlers: HashMap<String, Arc<dyn AgentState>> = HashMap::new();
         handlers.insert("Idle".to_string(), Arc::new(IdleState));
         handlers.insert("Planning".to_string(), Arc::new(PlanningState));
-        handlers.insert("Acting".to_string(), Arc::new(ActingState));
-        handlers.insert("ParallelActing".to_string(), Arc::new(ParallelActingState));
-        handlers.insert("Observing".to_string(), Arc::new(ObservingState));
-        handlers.insert("Reflecting".to_string(), Arc::new(ReflectingState));
         handlers.insert("Done".to_string(), Arc::new(DoneState));
         handlers.insert("Error".to_string(), Arc::new(ErrorState));
-        handlers.insert(
-            "WaitingForHuman".to_string(),
-            Arc::new(WaitingForHumanState),
-        );
+        if !self.chat_mode {
+            handlers.insert("Acting".to_string(), Arc::new(ActingState));
+            handlers.insert("ParallelActing".to_string(), Arc::new(ParallelActingState));
+            handlers.insert("Observing".to_string(), Arc::new(ObservingState));
+            handlers.insert("Reflecting".to_string(), Arc::new(ReflectingState));
+            handlers.insert(
+                "WaitingForHuman".to_string(),
+                Arc::new(WaitingForHumanState),
+            );
+        }
 
         for (name, handler) in self.custom_handlers {
             handlers.insert(name, handler);
         }
 
-        let mut transitions = build_transition_table();
+        let mut transitions = if self.chat_mode {
+            build_chat_transition_table()
+        } else {
+            build_transition_table()
+        };
         for (from, event, to) in self.custom_transitions {
             transitions.insert((from, event), to);
         }
@@ -602,6 +1363,19 @@ impl AgentBuilder {
             Arc::new(composite)
         };
 
+        self.memory.session_id = self.session_id.clone();
+        if let Some(spec) = &self.workspace {
+            let workspace = spec
+                .open()
+                .map_err(|e| AgentError::BuildError(format!("Failed to create workspace: {}", e)))?;
+            crate::bundle::restore_artifacts(&self.imported_artifacts, &workspace)
+                .map_err(|e| AgentError::BuildError(format!("Failed to restore workspace files: {}", e)))?;
+            self.memory.workspace = Some(Arc::new(workspace));
+        }
+        if let Some(sink) = self.audit_sink {
+            self.memory.audit = Some(crate::audit::AuditLog::new(sink, self.session_id.clone()));
+        }
+
         let mut engine = AgentEngine::new(
             self.memory,
             Arc::new(self.tools),
@@ -620,7 +1394,16 @@ impl AgentBuilder {
 
         if let Some(state) = self.initial_state {
             engine.state = state;
+            validate_resumed_graph(&engine, self.checkpoint_graph.as_ref())?;
         }
+        // Continuing the same session: the store already has this much of the trace
+        if let Some((session, cursor)) = self.checkpoint_trace {
+            if session == engine.session_id {
+                engine.trace_persisted = cursor;
+            }
+        }
+        engine.config_watcher = self.config_watcher;
+        engine.workspace = engine.memory.workspace.clone();
 
         Ok(engine)
     }
@@ -633,6 +1416,9 @@ impl AgentBuilder {
             .llm
             .ok_or_else(|| AgentError::BuildError("LLM caller is required".to_string()))?;
 
+        if let Some(limiter) = &self.rate_limiter {
+            llm = Arc::new(RateLimitedLlmCaller::with_limiter(llm, Arc::clone(limiter)));
+        }
         if let Some(n) = self.retry_count {
             llm = Arc::new(RetryingLlmCaller::new(llm, n));
         }
@@ -640,20 +1426,31 @@ impl AgentBuilder {
         if let Some(config) = self.config {
             self.memory.config = config;
         }
+        self.memory.config.apply_determinism();
+        self.memory.config.validate()?;
+
+        if self.chat_mode && !self.tools.is_empty() {
+            return Err(AgentError::BuildError(format!(
+                "chat mode agents cannot have tools ({} registered)",
+                self.tools.len()
+            )));
+        }
 
         let mut handlers: HashMap<String, Arc<dyn AgentState>> = HashMap::new();
         handlers.insert("Idle".to_string(), Arc::new(IdleState));
         handlers.insert("Planning".to_string(), Arc::new(PlanningState));
-        handlers.insert("Acting".to_string(), Arc::new(ActingState));
-        handlers.insert("ParallelActing".to_string(), Arc::new(ParallelActingState));
-        handlers.insert("Observing".to_string(), Arc::new(ObservingState));
-        handlers.insert("Reflecting".to_string(), Arc::new(ReflectingState));
         handlers.insert("Done".to_string(), Arc::new(DoneState));
         handlers.insert("Error".to_string(), Arc::new(ErrorState));
-        handlers.insert(
-            "WaitingForHuman".to_string(),
-            Arc::new(WaitingForHumanState),
-        );
+        if !self.chat_mode {
+            handlers.insert("Acting".to_string(), Arc::new(ActingState));
+            handlers.insert("ParallelActing".to_string(), Arc::new(ParallelActingState));
+            handlers.insert("Observing".to_string(), Arc::new(ObservingState));
+            handlers.insert("Reflecting".to_string(), Arc::new(ReflectingState));
+            handlers.insert(
+                "WaitingForHuman".to_string(),
+                Arc::new(WaitingForHumanState),
+            );
+        }
 
         for (name, handler) in self.custom_handlers {
             handlers.insert(name, handler);
@@ -663,7 +1460,11 @@ impl AgentBuilder {
             handlers.insert(key, handler);
         }
 
-        let mut transitions = build_transition_table();
+        let mut transitions = if self.chat_mode {
+            build_chat_transition_table()
+        } else {
+            build_transition_table()
+        };
         for (from, event, to) in self.custom_transitions {
             transitions.insert((from, event), to);
         }
@@ -681,6 +1482,19 @@ impl AgentBuilder {
             Arc::new(composite)
         };
 
+        self.memory.session_id = self.session_id.clone();
+        if let Some(spec) = &self.workspace {
+            let workspace = spec
+                .open()
+                .map_err(|e| AgentError::BuildError(format!("Failed to create workspace: {}", e)))?;
+            crate::bundle::restore_artifacts(&self.imported_artifacts, &workspace)
+                .map_err(|e| AgentError::BuildError(format!("Failed to restore workspace files: {}", e)))?;
+            self.memory.workspace = Some(Arc::new(workspace));
+        }
+        if let Some(sink) = self.audit_sink {
+            self.memory.audit = Some(crate::audit::AuditLog::new(sink, self.session_id.clone()));
+        }
+
         let mut engine = AgentEngine::new(
             self.memory,
             Arc::new(self.tools),
@@ -699,8 +1513,45 @@ impl AgentBuilder {
 
         if let Some(state) = self.initial_state {
             engine.state = state;
+            validate_resumed_graph(&engine, self.checkpoint_graph.as_ref())?;
         }
+        // Continuing the same session: the store already has this much of the trace
+        if let Some((session, cursor)) = self.checkpoint_trace {
+            if session == engine.session_id {
+                engine.trace_persisted = cursor;
+            }
+        }
+        engine.config_watcher = self.config_watcher;
+        engine.workspace = engine.memory.workspace.clone();
 
         Ok(engine)
     }
 }
+
+/// Check that a resumed engine can run the graph its checkpoint came from.
+fn validate_resumed_graph(
+    engine: &AgentEngine,
+    expected: Option<&crate::checkpoint::GraphShape>,
+) -> Result<(), AgentError> {
+    let current = engine.graph_shape();
+    let mut problems = expected
+        .map(|g| g.missing_from(&current))
+        .unwrap_or_default();
+
+    // Checkpoints written before graph shapes were stored still get this check
+    let state = engine.current_state().to_string();
+    let reported = expected.is_some_and(|g| g.states.contains(&state));
+    if !engine.is_finished() && !current.states.contains(&state) && !reported {
+        problems.insert(0, format!("resumed state '{}' has no handler", state));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(AgentError::GraphMismatch(format!(
+            "the builder does not match the checkpointed agent — {}. \
+             Register the same custom states and transitions before resuming.",
+            problems.join("; ")
+        )))
+    }
+}
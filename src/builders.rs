@@ -0,0 +1,76 @@
+//! Builders — pre-configured agents for common jobs.
+//!
+//! Each function returns an `AgentBuilder` with a prompt, model and limits
+//! suited to one job. Only the provider is left to set, and any setting can
+//! still be changed on the returned builder.
+//!
+//! ```rust,ignore
+//! let agent = AgentBuilder::new("Audit the nginx logs")
+//!     .openai("")
+//!     .add_subagent("summarize", SUMMARIZER_DESCRIPTION, builders::summarizer().openai(""))
+//!     .tool(/* ... */)
+//!     .build()?;
+//! ```
+
+use crate::builder::AgentBuilder;
+
+/// Model the summarizer uses unless `.model()` replaces it.
+pub const SUMMARIZER_MODEL: &str = "gpt-4o-mini";
+
+/// Output tokens the summarizer may spend on a summary.
+pub const SUMMARY_MAX_TOKENS: u32 = 512;
+
+/// Tool description for registering the summarizer as a sub-agent.
+pub const SUMMARIZER_DESCRIPTION: &str = "Compress a long document or tool output into a short summary. \
+    Pass the full text as `task`, optionally preceded by what the summary should focus on.";
+
+const SUMMARIZER_PROMPT: &str = "You summarize text. The task is the text to summarize, \
+    possibly preceded by what to focus on. Keep facts, figures, names, identifiers, errors \
+    and warnings; drop repetition, boilerplate and formatting. Do not add information or \
+    opinions. Reply with the summary only, in at most 300 words.";
+
+/// A summarizer agent for compressing large observations and documents.
+///
+/// It uses [`SUMMARIZER_MODEL`], has no tools, answers in one step and is
+/// capped at [`SUMMARY_MAX_TOKENS`] output tokens; an answer cut off at the
+/// cap is kept as is rather than continued. Set a provider before building,
+/// and a matching cheap model for providers other than OpenAI:
+///
+/// ```rust,ignore
+/// let summarizer = builders::summarizer()
+///     .anthropic("")
+///     .model("claude-3-5-haiku-latest");
+/// ```
+pub fn summarizer() -> AgentBuilder {
+    AgentBuilder::new("")
+        .system_prompt(SUMMARIZER_PROMPT)
+        .model(SUMMARIZER_MODEL)
+        .max_output_tokens(SUMMARY_MAX_TOKENS)
+        .max_continuations(0)
+        // One step to answer, plus room for a rejected (too short) answer
+        .max_steps(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::MockLlmCaller;
+    use crate::types::LlmResponse;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_summarizer_answers_under_its_limits() {
+        let mock = Arc::new(MockLlmCaller::new(vec![LlmResponse::FinalAnswer {
+            content: "Disk full on db-2 at 03:12; backups failed.".to_string(),
+            confidence: 1.0,
+            usage: None,
+            model: None,
+        }]));
+        let mut engine = summarizer().task("...long log...").llm(mock.clone()).build().unwrap();
+
+        assert!(engine.tools.snapshot().is_empty());
+        assert_eq!(engine.memory.config.sampling.max_tokens, Some(SUMMARY_MAX_TOKENS));
+        assert_eq!(engine.run().await.unwrap(), "Disk full on db-2 at 03:12; backups failed.");
+        assert_eq!(mock.model_for_call(0).as_deref(), Some(SUMMARIZER_MODEL));
+    }
+}
@@ -0,0 +1,248 @@
+//! Session bundles — a whole session in one file.
+//!
+//! A [`SessionBundle`] holds everything needed to look at a session or to
+//! carry on running it somewhere else. That is useful for bug reports,
+//! support escalations, and moving sessions between environments. It holds:
+//! - the live memory, with its trace
+//! - every checkpoint in the store, each with its full trace
+//! - the files in the session's workspace
+//! - a fingerprint of the configuration that produced it
+//!
+//! ```rust,ignore
+//! // On the machine where the session ran
+//! engine.export_session("session-123.bundle.json").await?;
+//!
+//! // Anywhere else: same tools and states, any checkpoint store
+//! let mut engine = AgentBuilder::new("")
+//!     .openai("")
+//!     .checkpoint_store(store)
+//!     .import_session("session-123.bundle.json").await?
+//!     .build()?;
+//! ```
+//!
+//! The bundle is JSON. To encrypt or compress it, build it with
+//! `AgentEngine::session_bundle` and write it with [`SessionBundle::write`]
+//! and a [`MemoryCodec`].
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use crate::checkpoint::{decode_with, encode_with, AgentCheckpoint, GraphShape, MemoryCodec};
+use crate::memory::AgentMemory;
+use crate::types::{AgentConfig, State};
+use crate::workspace::Workspace;
+
+/// Version of the bundle layout written by this crate.
+pub const BUNDLE_FORMAT: u32 = 1;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Bundle contents
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionBundle {
+    /// [`BUNDLE_FORMAT`] of the writer
+    pub format:      u32,
+    pub session_id:  String,
+    pub exported_at: DateTime<Utc>,
+    /// State the engine was in at export time
+    pub state:       State,
+    /// Memory at export time, trace included
+    pub memory:      AgentMemory,
+    /// Every checkpoint the store held for the session, oldest first
+    pub checkpoints: Vec<AgentCheckpoint>,
+    /// Files in the session's workspace
+    pub artifacts:   Vec<Artifact>,
+    pub config:      ConfigFingerprint,
+}
+
+/// One file from the workspace.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Artifact {
+    /// Path relative to the workspace, `/`-separated
+    pub path:    String,
+    pub content: ArtifactContent,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "encoding", content = "data", rename_all = "snake_case")]
+pub enum ArtifactContent {
+    Text(String),
+    /// Files that are not valid UTF-8
+    Bytes(Vec<u8>),
+}
+
+/// What the exporting engine was configured with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigFingerprint {
+    /// SHA-256 of the agent config, graph shape and tool names
+    pub hash:          String,
+    pub crate_version: String,
+    pub graph:         GraphShape,
+    /// Registered tool names, sorted
+    pub tools:         Vec<String>,
+}
+
+impl ConfigFingerprint {
+    pub fn new(config: &AgentConfig, graph: GraphShape, mut tools: Vec<String>) -> Self {
+        tools.sort();
+        // serde_json objects are sorted maps, so equal configs hash equally
+        let canonical = serde_json::json!({
+            "config": serde_json::to_value(config).unwrap_or_default(),
+            "graph": graph,
+            "tools": tools,
+        });
+        Self {
+            hash: format!("{:x}", Sha256::digest(canonical.to_string().as_bytes())),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            graph,
+            tools,
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Reading and writing
+// ─────────────────────────────────────────────────────────────────────────────
+
+impl SessionBundle {
+    /// Write the bundle to `path` through `codec`.
+    pub fn write(&self, path: impl AsRef<Path>, codec: &dyn MemoryCodec) -> Result<(), String> {
+        let data = encode_with(codec, self)?;
+        std::fs::write(path, data).map_err(|e| e.to_string())
+    }
+
+    /// Read a bundle written by [`SessionBundle::write`] with the same codec.
+    pub fn read(path: impl AsRef<Path>, codec: &dyn MemoryCodec) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| e.to_string())?;
+        let bundle: Self = decode_with(codec, data)?;
+        if bundle.format > BUNDLE_FORMAT {
+            return Err(format!(
+                "Bundle format {} is newer than this version supports ({})",
+                bundle.format, BUNDLE_FORMAT
+            ));
+        }
+        Ok(bundle)
+    }
+}
+
+/// Every file under the workspace, in path order.
+pub(crate) fn collect_artifacts(workspace: &Workspace) -> Result<Vec<Artifact>, String> {
+    let mut artifacts = Vec::new();
+    if workspace.path().exists() {
+        collect_dir(workspace.path(), workspace.path(), &mut artifacts)?;
+    }
+    artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(artifacts)
+}
+
+fn collect_dir(root: &Path, dir: &Path, out: &mut Vec<Artifact>) -> Result<(), String> {
+    for entry in std::fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            collect_dir(root, &path, out)?;
+        } else if path.is_file() {
+            let relative = path.strip_prefix(root).map_err(|e| e.to_string())?;
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let data = std::fs::read(&path).map_err(|e| e.to_string())?;
+            let content = match String::from_utf8(data) {
+                Ok(text) => ArtifactContent::Text(text),
+                Err(e) => ArtifactContent::Bytes(e.into_bytes()),
+            };
+            out.push(Artifact { path: relative, content });
+        }
+    }
+    Ok(())
+}
+
+/// Write `artifacts` into `workspace`. Paths that would leave it are rejected.
+pub(crate) fn restore_artifacts(artifacts: &[Artifact], workspace: &Workspace) -> Result<(), String> {
+    for artifact in artifacts {
+        let path = workspace.resolve(&artifact.path)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let data = match &artifact.content {
+            ArtifactContent::Text(text) => text.as_bytes(),
+            ArtifactContent::Bytes(bytes) => bytes.as_slice(),
+        };
+        std::fs::write(&path, data).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint::PlainCodec;
+
+    #[test]
+    fn test_artifacts_round_trip() {
+        let source = tempfile::TempDir::new().unwrap();
+        let from = Workspace::at(source.path()).unwrap();
+        std::fs::create_dir_all(source.path().join("out")).unwrap();
+        std::fs::write(source.path().join("notes.txt"), "hello").unwrap();
+        std::fs::write(source.path().join("out/data.bin"), [0xff, 0x00, 0xfe]).unwrap();
+
+        let artifacts = collect_artifacts(&from).unwrap();
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(artifacts[0].path, "notes.txt");
+        assert_eq!(artifacts[1].path, "out/data.bin");
+        assert_eq!(artifacts[1].content, ArtifactContent::Bytes(vec![0xff, 0x00, 0xfe]));
+
+        let target = tempfile::TempDir::new().unwrap();
+        restore_artifacts(&artifacts, &Workspace::at(target.path()).unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(target.path().join("notes.txt")).unwrap(), "hello");
+        assert_eq!(std::fs::read(target.path().join("out/data.bin")).unwrap(), vec![0xff, 0x00, 0xfe]);
+    }
+
+    #[test]
+    fn test_restore_rejects_escaping_paths() {
+        let target = tempfile::TempDir::new().unwrap();
+        let evil = Artifact {
+            path: "../escape.txt".to_string(),
+            content: ArtifactContent::Text("x".to_string()),
+        };
+        assert!(restore_artifacts(&[evil], &Workspace::at(target.path()).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_fingerprint_tracks_config_and_tools() {
+        let config = AgentConfig::default();
+        let a = ConfigFingerprint::new(&config, GraphShape::default(), vec!["b".into(), "a".into()]);
+        let b = ConfigFingerprint::new(&config, GraphShape::default(), vec!["a".into(), "b".into()]);
+        assert_eq!(a.hash, b.hash);
+        assert_eq!(a.tools, vec!["a", "b"]);
+
+        let c = ConfigFingerprint::new(&config, GraphShape::default(), vec!["a".into()]);
+        assert_ne!(a.hash, c.hash);
+    }
+
+    #[test]
+    fn test_newer_format_is_rejected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("s.bundle.json");
+        let bundle = SessionBundle {
+            format: BUNDLE_FORMAT + 1,
+            session_id: "s".to_string(),
+            exported_at: Utc::now(),
+            state: State::idle(),
+            memory: AgentMemory::new("t"),
+            checkpoints: Vec::new(),
+            artifacts: Vec::new(),
+            config: ConfigFingerprint::new(&AgentConfig::default(), GraphShape::default(), Vec::new()),
+        };
+        bundle.write(&path, &PlainCodec).unwrap();
+        assert!(SessionBundle::read(&path, &PlainCodec).unwrap_err().contains("newer"));
+    }
+}
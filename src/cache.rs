@@ -1,10 +1,18 @@
 //! LLM response caching — avoid duplicate API calls by caching
 //! responses keyed by SHA-256 hash of the messages payload.
-
+//!
+//! `AgentBuilder::cache` consults a cache from Planning. To cache below the
+//! agent, for every call a provider serves, wrap the provider in
+//! `CachingLlmCaller`. Both take any [`LlmCache`]: [`InMemoryCache`] for a
+//! process, [`DiskCache`] to keep responses across runs.
+
+use crate::memory::AgentMemory;
+use crate::tools::ToolRegistry;
 use crate::types::LlmResponse;
 use serde_json::Value;
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
@@ -22,6 +30,23 @@ pub fn cache_key(messages: &[Value], model: &str) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Computes a SHA-256 hex digest from everything that influences a
+/// provider's answer: model, messages, tool schemas, output schema and
+/// `parallel_tools`.
+pub fn request_key(memory: &AgentMemory, tools: &ToolRegistry, model: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(model.as_bytes());
+    for msg in memory.build_messages() {
+        hasher.update(msg.to_string().as_bytes());
+    }
+    let mut schemas = tools.schemas();
+    schemas.sort_by(|a, b| a.name.cmp(&b.name));
+    hasher.update(serde_json::to_string(&schemas).unwrap_or_default().as_bytes());
+    hasher.update(serde_json::to_string(&memory.config.output_schema).unwrap_or_default().as_bytes());
+    hasher.update([memory.config.parallel_tools as u8]);
+    format!("{:x}", hasher.finalize())
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Stats
 // ─────────────────────────────────────────────────────────────────────────────
@@ -186,6 +211,84 @@ impl LlmCache for InMemoryCache {
     }
 }
 
+// ─────────────────────────────────────────────────────────────────────────────
+// DiskCache
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Stores each response as a JSON file in a directory, so responses survive
+/// restarts — for development and CI runs that repeat the same tasks.
+///
+/// Entries never expire; delete the directory to clear the cache. Files that
+/// cannot be read or written are logged and treated as misses.
+pub struct DiskCache {
+    dir: PathBuf,
+    stats: Mutex<CacheStats>,
+}
+
+impl DiskCache {
+    /// A cache in `dir`, created on the first `put`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            stats: Mutex::new(CacheStats::default()),
+        }
+    }
+
+    /// File of a key. Keys that are not plain file names are hashed.
+    fn path(&self, key: &str) -> PathBuf {
+        let plain = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        let name = if plain {
+            key.to_string()
+        } else {
+            format!("{:x}", Sha256::digest(key.as_bytes()))
+        };
+        self.dir.join(format!("{}.json", name))
+    }
+
+    fn record(&self, hit: bool) {
+        let mut stats = self.stats.lock().unwrap();
+        if hit {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+    }
+}
+
+impl LlmCache for DiskCache {
+    fn get(&self, key: &str) -> Option<LlmResponse> {
+        let path = self.path(key);
+        let response = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| tracing::warn!(path = %path.display(), error = %e, "Unreadable cache entry"))
+                .ok(),
+            Err(_) => None,
+        };
+        self.record(response.is_some());
+        response
+    }
+
+    fn put(&self, key: String, response: LlmResponse) {
+        let path = self.path(&key);
+        // Write to a temporary file first so readers never see half an entry
+        let tmp = path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+        let written = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| {
+                let bytes = serde_json::to_vec(&response).map_err(std::io::Error::other)?;
+                std::fs::write(&tmp, bytes)
+            })
+            .and_then(|_| std::fs::rename(&tmp, &path));
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&tmp);
+            tracing::warn!(path = %path.display(), error = %e, "Cache write failed");
+        }
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Tests
 // ─────────────────────────────────────────────────────────────────────────────
@@ -199,7 +302,9 @@ mod tests {
     fn make_response(text: &str) -> LlmResponse {
         LlmResponse::FinalAnswer {
             content: text.to_string(),
+            confidence: 1.0,
             usage: None,
+            model: None,
         }
     }
 
@@ -211,7 +316,9 @@ mod tests {
                 id: None,
             },
             confidence: 0.9,
+            assistant_text: None,
             usage: None,
+            model: None,
         }
     }
 
@@ -287,6 +394,21 @@ mod tests {
         assert!((stats.hit_rate() - 0.75).abs() < 0.001);
     }
 
+    #[test]
+    fn test_disk_cache_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().join("llm"));
+        assert!(cache.get("k").is_none());
+        cache.put("k".to_string(), make_tool_response("search"));
+        cache.put("not a file name/..".to_string(), make_response("odd key"));
+
+        let reopened = DiskCache::new(dir.path().join("llm"));
+        assert!(matches!(reopened.get("k"), Some(LlmResponse::ToolCall { tool, .. }) if tool.name == "search"));
+        assert!(matches!(reopened.get("not a file name/.."), Some(LlmResponse::FinalAnswer { content, .. }) if content == "odd key"));
+        assert_eq!(reopened.stats().hits, 2);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
     #[test]
     fn test_cache_preserves_tool_calls() {
         let cache = InMemoryCache::new(100, Duration::from_secs(60));
@@ -0,0 +1,216 @@
+//! Cancellation — tell running tools that their run is over.
+//!
+//! Aborting a run's task or dropping its future (for example when
+//! `tokio::time::timeout` fires) stops the engine, but tools already running
+//! on `spawn_blocking` threads keep going: a thread cannot be killed. Every
+//! engine therefore owns a [`CancellationToken`] that tools see as
+//! `ToolContext::cancel`. It is cancelled when
+//!
+//! - `AgentEngine::cancel` or `AgentHandle::cancel` is called, or
+//! - the run's future is dropped before it finished (abort, timeout, panic).
+//!
+//! Abort is best effort. Long-running tools should check
+//! `ctx.cancel.is_cancelled()` between units of work and return early. After
+//! a cancel, ParallelActing waits `AgentConfig::cancel_grace_ms` for running
+//! tools to return, then records the rest as failed and stops waiting; their
+//! threads finish in the background and their results are discarded.
+//!
+//! A cancelled engine stays cancelled: further runs return
+//! `AgentError::Cancelled`. Resume the session from a checkpoint to continue.
+//!
+//! ## Graceful shutdown
+//!
+//! Cancelling stops tools midway. For rolling deploys,
+//! `AgentEngine::shutdown_gracefully` (or `AgentHandle::shutdown_gracefully`)
+//! instead raises a [`ShutdownSignal`]: the state being handled finishes,
+//! the engine checkpoints and the run returns `AgentError::ShutDown` before
+//! the next transition. If the run has not stopped when the timeout
+//! expires, it is cancelled.
+//!
+//! ```rust,ignore
+//! let handle = engine.handle();
+//! let run = tokio::spawn(async move { engine.run().await });
+//! sigterm.recv().await;
+//! handle.shutdown_gracefully(Duration::from_secs(25));
+//! run.await?; // Err(AgentError::ShutDown { .. }); resume on the next pod
+//! ```
+//!
+//! ```rust,ignore
+//! Tool::new("crawl", "Crawl a site")
+//!     .call_with_context(|args, ctx| {
+//!         let mut pages = Vec::new();
+//!         for url in seed_urls(args) {
+//!             if ctx.cancel.is_cancelled() {
+//!                 return Err("cancelled".to_string());
+//!             }
+//!             pages.push(fetch(&url)?);
+//!         }
+//!         Ok(pages.join("\n"))
+//!     })
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// Shared flag that a run was cancelled. Clones observe the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every clone of this token. Calling it again has no effect.
+    pub fn cancel(&self) {
+        if !self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            self.inner.notify.notify_waiters();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// A guard that cancels the token when dropped, unless disarmed first.
+    pub fn drop_guard(self) -> CancelOnDrop {
+        CancelOnDrop { token: Some(self) }
+    }
+}
+
+/// Cancels its token on drop. See [`CancellationToken::drop_guard`].
+#[derive(Debug)]
+pub struct CancelOnDrop {
+    token: Option<CancellationToken>,
+}
+
+impl CancelOnDrop {
+    /// Drop the guard without cancelling.
+    pub fn disarm(mut self) {
+        self.token = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            token.cancel();
+        }
+    }
+}
+
+/// Request to stop a run between steps. Clones share the request.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownSignal {
+    requested: CancellationToken,
+    stopped: CancellationToken,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.is_cancelled()
+    }
+
+    /// Ask the run to stop before its next transition. If it has not stopped
+    /// within `timeout`, cancel `cancel`. Calling it again has no effect.
+    pub(crate) fn request(&self, cancel: &CancellationToken, timeout: std::time::Duration) {
+        if self.requested.is_cancelled() {
+            return;
+        }
+        self.requested.cancel();
+        // Without a runtime there is nothing to time out
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let (stopped, cancel) = (self.stopped.clone(), cancel.clone());
+        runtime.spawn(async move {
+            tokio::select! {
+                _ = stopped.cancelled() => {}
+                _ = tokio::time::sleep(timeout) => cancel.cancel(),
+            }
+        });
+    }
+
+    /// The run has stopped after a request.
+    pub(crate) fn mark_stopped(&self) {
+        self.stopped.cancel();
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_wakes_waiters() {
+        let token = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!token.is_cancelled());
+        token.cancel();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter should wake")
+            .unwrap();
+        // Already cancelled: returns at once
+        token.cancelled().await;
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_after_timeout() {
+        let (signal, cancel) = (ShutdownSignal::new(), CancellationToken::new());
+        signal.request(&cancel, Duration::from_millis(10));
+        assert!(signal.is_requested());
+        tokio::time::timeout(Duration::from_secs(1), cancel.cancelled())
+            .await
+            .expect("run should be cancelled after the timeout");
+
+        // Stopped in time: no cancel
+        let (signal, cancel) = (ShutdownSignal::new(), CancellationToken::new());
+        signal.request(&cancel, Duration::from_millis(10));
+        signal.mark_stopped();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!cancel.is_cancelled());
+    }
+
+    #[test]
+    fn test_drop_guard() {
+        let token = CancellationToken::new();
+        token.clone().drop_guard().disarm();
+        assert!(!token.is_cancelled());
+        drop(token.clone().drop_guard());
+        assert!(token.is_cancelled());
+    }
+}
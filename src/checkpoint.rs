@@ -1,8 +1,17 @@
 use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
 use crate::memory::AgentMemory;
+use crate::trace::{Trace, TraceEntry};
+use crate::transitions::TransitionTable;
 use crate::types::State;
 use async_trait::async_trait;
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Layout version of the checkpoints this build writes. Bumped whenever
+/// stored `AgentMemory` JSON needs a [`CheckpointMigrations`] step to load.
+pub const CHECKPOINT_SCHEMA_VERSION: u32 = 1;
 
 /// A point-in-time snapshot of the agent's state.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,6 +21,335 @@ pub struct AgentCheckpoint {
     pub state:          State,
     pub memory:         AgentMemory, // Memory includes trace, history, and config
     pub timestamp:      chrono::DateTime<chrono::Utc>,
+    /// Shape of the state graph at save time, checked on resume
+    #[serde(default)]
+    pub graph:          Option<GraphShape>,
+    /// Length of the session's trace log at save time.
+    ///
+    /// When set, the trace is not stored with the memory. Stores fill it
+    /// back in from their trace log (see `CheckpointStore::append_trace`)
+    /// when the checkpoint is loaded.
+    #[serde(default)]
+    pub trace_cursor:   Option<usize>,
+    /// `memory` is a placeholder; rebuild it from the trace log on load.
+    /// Set for agents with `event_sourced_memory` whose store keeps a trace log.
+    #[serde(default)]
+    pub memory_from_trace: bool,
+    /// Position in the session, counting up from 1 with every save.
+    ///
+    /// Stores order checkpoints by this rather than `timestamp`, which can
+    /// skew or repeat. Checkpoints saved by older versions have 0 and keep
+    /// their save order.
+    #[serde(default)]
+    pub sequence:       u64,
+    /// [`CHECKPOINT_SCHEMA_VERSION`] at save time. Checkpoints saved before
+    /// versioning have 0.
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+impl AgentCheckpoint {
+    /// Restore the trace from the first `trace_cursor` entries of `log`,
+    /// and the memory too for `memory_from_trace` checkpoints.
+    fn hydrate(mut self, log: &[TraceEntry]) -> Self {
+        if let Some(cursor) = self.trace_cursor {
+            let entries = &log[..cursor.min(log.len())];
+            if self.memory_from_trace {
+                match AgentMemory::from_trace(entries) {
+                    Ok(memory) => {
+                        self.memory = memory;
+                        self.memory_from_trace = false;
+                    }
+                    Err(e) => tracing::warn!(checkpoint = %self.checkpoint_id, error = %e, "Memory rebuild failed"),
+                }
+            } else if self.memory.trace.is_empty() {
+                self.memory.trace = Trace::from_entries(entries.to_vec());
+            }
+        }
+        self
+    }
+}
+
+/// The states, transitions and terminal states an agent was built with.
+///
+/// Stored in checkpoints so that resuming with a differently configured
+/// builder fails with a clear report instead of `NoHandlerForState` mid-run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GraphShape {
+    /// State a fresh run starts in
+    #[serde(default)]
+    pub initial_state:   String,
+    pub states:          Vec<String>,
+    /// `(from, event, to)`
+    pub transitions:     Vec<(String, String, String)>,
+    pub terminal_states: Vec<String>,
+}
+
+impl GraphShape {
+    pub fn new<'a>(
+        initial_state:   &str,
+        states:          impl IntoIterator<Item = &'a String>,
+        transitions:     &TransitionTable,
+        terminal_states: impl IntoIterator<Item = &'a String>,
+    ) -> Self {
+        let mut states: Vec<String> = states.into_iter().cloned().collect();
+        let mut transitions: Vec<(String, String, String)> = transitions
+            .iter()
+            .map(|((from, event), to)| (from.to_string(), event.to_string(), to.to_string()))
+            .collect();
+        let mut terminal_states: Vec<String> = terminal_states.into_iter().cloned().collect();
+        states.sort();
+        transitions.sort();
+        terminal_states.sort();
+        Self { initial_state: initial_state.to_string(), states, transitions, terminal_states }
+    }
+
+    /// Everything this (checkpointed) graph has that `current` lacks.
+    ///
+    /// Additions in `current` are fine — a resumed run only needs the
+    /// states and transitions it could already reach.
+    pub fn missing_from(&self, current: &GraphShape) -> Vec<String> {
+        let mut problems = Vec::new();
+        for s in &self.states {
+            if !current.states.contains(s) {
+                problems.push(format!("state '{}' has no handler", s));
+            }
+        }
+        for t in &self.transitions {
+            if !current.transitions.contains(t) {
+                problems.push(format!("transition {} --{}--> {} is not registered", t.0, t.1, t.2));
+            }
+        }
+        for s in &self.terminal_states {
+            if !current.terminal_states.contains(s) {
+                problems.push(format!("state '{}' is no longer terminal", s));
+            }
+        }
+        problems
+    }
+
+    /// The graph as a Mermaid `stateDiagram-v2`, with `current` (if any)
+    /// highlighted. Paste it into any Mermaid renderer.
+    ///
+    /// States whose names are not plain identifiers are declared as
+    /// `state "name" as sN` and referred to by that id.
+    pub fn to_mermaid(&self, current: Option<&str>) -> String {
+        let mut out = String::from("stateDiagram-v2\n");
+        let names = std::iter::once(self.initial_state.as_str())
+            .filter(|s| !s.is_empty())
+            .chain(self.states.iter().map(String::as_str))
+            .chain(self.transitions.iter().flat_map(|(from, _, to)| [from.as_str(), to.as_str()]))
+            .chain(self.terminal_states.iter().map(String::as_str))
+            .chain(current)
+            .collect::<Vec<_>>();
+        let plain = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        let mut ids: HashMap<&str, String> = HashMap::new();
+        let mut next = 0;
+        for &name in &names {
+            if ids.contains_key(name) {
+                continue;
+            }
+            let id = if plain(name) {
+                name.to_string()
+            } else {
+                // A generated id must not collide with a state's own name
+                let id = loop {
+                    next += 1;
+                    let id = format!("s{}", next);
+                    if !names.contains(&id.as_str()) {
+                        break id;
+                    }
+                };
+                out.push_str(&format!("    state \"{}\" as {}\n", mermaid_escape(name), id));
+                id
+            };
+            ids.insert(name, id);
+        }
+
+        if !self.initial_state.is_empty() {
+            out.push_str(&format!("    [*] --> {}\n", ids[self.initial_state.as_str()]));
+        }
+        for (from, event, to) in &self.transitions {
+            out.push_str(&format!(
+                "    {} --> {}: {}\n",
+                ids[from.as_str()],
+                ids[to.as_str()],
+                mermaid_escape(event)
+            ));
+        }
+        for s in &self.terminal_states {
+            out.push_str(&format!("    {} --> [*]\n", ids[s.as_str()]));
+        }
+        if let Some(state) = current {
+            out.push_str("    classDef current fill:#fde68a,stroke:#b45309,stroke-width:2px\n");
+            out.push_str(&format!("    class {} current\n", ids[state]));
+        }
+        out
+    }
+
+    /// The graph in Graphviz DOT, with `current` (if any) filled and
+    /// terminal states drawn as double circles.
+    pub fn to_dot(&self, current: Option<&str>) -> String {
+        let mut out = String::from("digraph agent {\n    rankdir=LR;\n");
+        let terminal_only = self.terminal_states.iter().filter(|s| !self.states.contains(s));
+        for s in self.states.iter().chain(terminal_only) {
+            let mut attrs = Vec::new();
+            if self.terminal_states.contains(s) {
+                attrs.push("shape=doublecircle");
+            }
+            if current == Some(s.as_str()) {
+                attrs.push("style=filled, fillcolor=\"#fde68a\"");
+            }
+            out.push_str(&format!("    \"{}\" [{}];\n", dot_escape(s), attrs.join(", ")));
+        }
+        for (from, event, to) in &self.transitions {
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                dot_escape(from),
+                dot_escape(to),
+                dot_escape(event)
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Text safe inside a Mermaid quoted name or transition label.
+fn mermaid_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("#quot;"),
+            ';' => out.push_str("#59;"),
+            '#' => out.push_str("#35;"),
+            '\n' | '\r' => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Text safe inside a DOT double-quoted string.
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// A step that rewrites stored `AgentMemory` JSON from one schema version
+/// to the next.
+pub type MigrationFn = Arc<dyn Fn(&mut Value) -> Result<(), String> + Send + Sync>;
+
+/// Steps that bring checkpoints saved by older versions up to
+/// [`CHECKPOINT_SCHEMA_VERSION`], so in-flight sessions survive upgrades.
+///
+/// New `AgentMemory` fields with `#[serde(default)]` need no step. A field
+/// that is renamed, retyped or required gets one, registered under the
+/// version it upgrades from. Stores run the steps on the memory JSON before
+/// parsing it; a checkpoint from a newer version fails to load with a clear
+/// error instead of a parse error.
+///
+/// ```rust,ignore
+/// // Upgrading a store that holds checkpoints renamed by a fork
+/// let migrations = CheckpointMigrations::new().register(1, |memory| {
+///     if let Some(notes) = memory.as_object_mut().and_then(|m| m.remove("notes")) {
+///         memory["scratchpad"] = notes;
+///     }
+///     Ok(())
+/// });
+/// let store = SqliteCheckpointStore::new("agent.db")?.with_migrations(migrations);
+/// ```
+#[derive(Clone)]
+pub struct CheckpointMigrations {
+    steps: BTreeMap<u32, MigrationFn>,
+}
+
+impl Default for CheckpointMigrations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CheckpointMigrations {
+    /// The built-in steps for every released layout.
+    pub fn new() -> Self {
+        let mut steps: BTreeMap<u32, MigrationFn> = BTreeMap::new();
+        // 0 → 1: versioning added; every field since is defaulted
+        steps.insert(0, Arc::new(|_| Ok(())));
+        Self { steps }
+    }
+
+    /// Add (or replace) the step upgrading `from_version` to `from_version + 1`.
+    pub fn register<F>(mut self, from_version: u32, step: F) -> Self
+    where
+        F: Fn(&mut Value) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.steps.insert(from_version, Arc::new(step));
+        self
+    }
+
+    /// Run the steps from `version` up to [`CHECKPOINT_SCHEMA_VERSION`] on
+    /// stored memory JSON.
+    pub fn upgrade_memory(&self, memory: &mut Value, version: u32) -> Result<(), String> {
+        if version > CHECKPOINT_SCHEMA_VERSION {
+            return Err(format!(
+                "checkpoint schema version {} is newer than this build reads ({}); upgrade agent-b",
+                version, CHECKPOINT_SCHEMA_VERSION
+            ));
+        }
+        for v in version..CHECKPOINT_SCHEMA_VERSION {
+            if let Some(step) = self.steps.get(&v) {
+                step(memory).map_err(|e| format!("migrating checkpoint from schema version {}: {}", v, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Upgrade a stored checkpoint's JSON and parse it.
+    pub fn load(&self, mut checkpoint: Value) -> Result<AgentCheckpoint, String> {
+        let version = checkpoint["schema_version"].as_u64().unwrap_or(0) as u32;
+        if let Some(memory) = checkpoint.get_mut("memory") {
+            self.upgrade_memory(memory, version)?;
+        }
+        let mut checkpoint: AgentCheckpoint = serde_json::from_value(checkpoint).map_err(|e| e.to_string())?;
+        checkpoint.schema_version = CHECKPOINT_SCHEMA_VERSION;
+        Ok(checkpoint)
+    }
+}
+
+/// Transforms serialized memory on its way to and from storage.
+///
+/// Every store passes the JSON it writes through `encode` and what it reads
+/// back through `decode`, so encryption or compression can be plugged in
+/// without touching the stores themselves. `decode` must invert `encode`.
+pub trait MemoryCodec: Send + Sync {
+    fn encode(&self, plain: Vec<u8>) -> Result<Vec<u8>, String>;
+    fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, String>;
+}
+
+/// Stores JSON as-is. The default for every store.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlainCodec;
+
+impl MemoryCodec for PlainCodec {
+    fn encode(&self, plain: Vec<u8>) -> Result<Vec<u8>, String> {
+        Ok(plain)
+    }
+
+    fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, String> {
+        Ok(stored)
+    }
+}
+
+/// Serialize `value` to JSON and run it through `codec`.
+pub fn encode_with<T: Serialize + ?Sized>(codec: &dyn MemoryCodec, value: &T) -> Result<Vec<u8>, String> {
+    let json = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+    codec.encode(json)
+}
+
+/// Inverse of [`encode_with`].
+pub fn decode_with<T: DeserializeOwned>(codec: &dyn MemoryCodec, stored: Vec<u8>) -> Result<T, String> {
+    let json = codec.decode(stored)?;
+    serde_json::from_slice(&json).map_err(|e| e.to_string())
 }
 
 #[async_trait]
@@ -27,11 +365,52 @@ pub trait CheckpointStore: Send + Sync {
 
     /// List all checkpoints for a session.
     async fn list_sessions(&self) -> Result<Vec<String>, String>;
+
+    /// Load every checkpoint of a session, oldest first.
+    ///
+    /// The default implementation only knows about the latest checkpoint;
+    /// stores that keep the full history should override it.
+    async fn list_checkpoints(&self, session_id: &str) -> Result<Vec<AgentCheckpoint>, String> {
+        Ok(self.load_latest(session_id).await?.into_iter().collect())
+    }
+
+    /// Up to `limit` checkpoints of a session, oldest first, skipping the
+    /// first `offset`.
+    async fn list_checkpoints_page(
+        &self,
+        session_id: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<AgentCheckpoint>, String> {
+        let checkpoints = self.list_checkpoints(session_id).await?;
+        Ok(checkpoints.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// The highest `sequence` saved for a session, or 0 if it has none.
+    /// The engine numbers its next checkpoint from this.
+    async fn last_sequence(&self, session_id: &str) -> Result<u64, String> {
+        Ok(self.load_latest(session_id).await?.map_or(0, |c| c.sequence))
+    }
+
+    /// Write `entries` to the session's trace log at index `from`, replacing
+    /// anything already stored from that index on.
+    ///
+    /// Returns `false` if the store keeps no trace log (the default). The
+    /// engine then stores the full trace with every checkpoint instead of a
+    /// `trace_cursor`. Stores that return `true` must restore the trace of
+    /// checkpoints they load.
+    async fn append_trace(&self, _session_id: &str, _from: usize, _entries: &[TraceEntry]) -> Result<bool, String> {
+        Ok(false)
+    }
 }
 
 /// A simple in-memory store for testing and short-lived sessions.
+///
+/// Checkpoints are kept as values and never serialized, so no
+/// [`MemoryCodec`] applies.
 pub struct MemoryCheckpointStore {
     checkpoints: std::sync::Mutex<HashMap<String, Vec<AgentCheckpoint>>>, // session_id -> checkpoints
+    traces:      std::sync::Mutex<HashMap<String, Vec<TraceEntry>>>,      // session_id -> trace log
 }
 
 impl Default for MemoryCheckpointStore {
@@ -44,8 +423,15 @@ impl MemoryCheckpointStore {
     pub fn new() -> Self {
         Self {
             checkpoints: std::sync::Mutex::new(HashMap::new()),
+            traces:      std::sync::Mutex::new(HashMap::new()),
         }
     }
+
+    fn hydrate(&self, checkpoint: AgentCheckpoint) -> AgentCheckpoint {
+        let traces = self.traces.lock().unwrap();
+        let log = traces.get(&checkpoint.session_id).map(Vec::as_slice).unwrap_or_default();
+        checkpoint.hydrate(log)
+    }
 }
 
 #[async_trait]
@@ -59,101 +445,361 @@ impl CheckpointStore for MemoryCheckpointStore {
     }
 
     async fn load_latest(&self, session_id: &str) -> Result<Option<AgentCheckpoint>, String> {
-        let store = self.checkpoints.lock().unwrap();
-        Ok(store.get(session_id).and_then(|v| v.last().cloned()))
+        let latest = {
+            let store = self.checkpoints.lock().unwrap();
+            // max_by_key keeps the last of equal sequences, i.e. the newest save
+            store.get(session_id).and_then(|v| v.iter().max_by_key(|c| c.sequence).cloned())
+        };
+        Ok(latest.map(|cp| self.hydrate(cp)))
     }
 
     async fn load_by_id(&self, checkpoint_id: &str) -> Result<Option<AgentCheckpoint>, String> {
-        let store = self.checkpoints.lock().unwrap();
-        for session_checkpoints in store.values() {
-            if let Some(cp) = session_checkpoints.iter().find(|c| c.checkpoint_id == checkpoint_id) {
-                return Ok(Some(cp.clone()));
-            }
-        }
-        Ok(None)
+        let found = {
+            let store = self.checkpoints.lock().unwrap();
+            store
+                .values()
+                .flat_map(|v| v.iter())
+                .find(|c| c.checkpoint_id == checkpoint_id)
+                .cloned()
+        };
+        Ok(found.map(|cp| self.hydrate(cp)))
     }
 
     async fn list_sessions(&self) -> Result<Vec<String>, String> {
         let store = self.checkpoints.lock().unwrap();
         Ok(store.keys().cloned().collect())
     }
+
+    async fn list_checkpoints(&self, session_id: &str) -> Result<Vec<AgentCheckpoint>, String> {
+        self.list_checkpoints_page(session_id, 0, usize::MAX).await
+    }
+
+    async fn list_checkpoints_page(
+        &self,
+        session_id: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<AgentCheckpoint>, String> {
+        let page: Vec<AgentCheckpoint> = {
+            let store = self.checkpoints.lock().unwrap();
+            let mut checkpoints: Vec<&AgentCheckpoint> = store.get(session_id).map(|v| v.iter().collect()).unwrap_or_default();
+            checkpoints.sort_by_key(|c| c.sequence);
+            checkpoints.into_iter().skip(offset).take(limit).cloned().collect()
+        };
+        Ok(page.into_iter().map(|cp| self.hydrate(cp)).collect())
+    }
+
+    async fn last_sequence(&self, session_id: &str) -> Result<u64, String> {
+        let store = self.checkpoints.lock().unwrap();
+        Ok(store.get(session_id).and_then(|v| v.iter().map(|c| c.sequence).max()).unwrap_or(0))
+    }
+
+    async fn append_trace(&self, session_id: &str, from: usize, entries: &[TraceEntry]) -> Result<bool, String> {
+        let mut traces = self.traces.lock().unwrap();
+        let log = traces.entry(session_id.to_string()).or_default();
+        log.truncate(from);
+        log.extend_from_slice(entries);
+        Ok(true)
+    }
 }
 
-/// A checkpoint store that saves each session to a separate JSON file in a directory.
+/// A checkpoint store that saves each session to separate files in a directory.
+///
+/// Checkpoints are appended to `<session>.checkpoints` and the trace log to
+/// `<session>.trace`. Both are sequences of frames: a little-endian `u32`
+/// length, then the codec's output for one checkpoint (or one batch of
+/// trace entries). A save never rewrites earlier checkpoints and is synced
+/// to disk before it returns.
+///
+/// A crash mid-append leaves a torn or corrupt frame at the end of a file.
+/// Reads skip it, and the first append after opening truncates it, so the
+/// session resumes from the last complete checkpoint. Corruption anywhere
+/// else is reported as an error.
+///
+/// Sessions saved by older versions as a JSON array in `<session>.json`
+/// are still read; new checkpoints for them go to `<session>.checkpoints`.
+///
+/// File reads, writes and syncs run on tokio's blocking pool, never on the
+/// executor. Clones share the store's state.
+#[derive(Clone)]
 pub struct FileCheckpointStore {
     base_path: std::path::PathBuf,
+    codec:     Arc<dyn MemoryCodec>,
+    migrations: Arc<CheckpointMigrations>,
+    /// Files whose tail has been checked since the store was opened
+    repaired:  Arc<std::sync::Mutex<std::collections::HashSet<std::path::PathBuf>>>,
 }
 
 impl FileCheckpointStore {
     pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
         let path = path.into();
         let _ = std::fs::create_dir_all(&path);
-        Self { base_path: path }
+        Self {
+            base_path:  path,
+            codec:      Arc::new(PlainCodec),
+            migrations: Arc::new(CheckpointMigrations::new()),
+            repaired:   Default::default(),
+        }
+    }
+
+    /// Encrypt/compress session files with `codec`.
+    pub fn with_codec(mut self, codec: Arc<dyn MemoryCodec>) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Upgrade old checkpoints with `migrations` instead of the built-in steps.
+    pub fn with_migrations(mut self, migrations: CheckpointMigrations) -> Self {
+        self.migrations = Arc::new(migrations);
+        self
     }
 
-    fn session_path(&self, session_id: &str) -> std::path::PathBuf {
+    /// Run `f` against this store on the blocking thread pool.
+    async fn blocking<T, F>(&self, f: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&FileCheckpointStore) -> Result<T, String> + Send + 'static,
+    {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || f(&store))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    fn log_path(&self, session_id: &str) -> std::path::PathBuf {
+        self.base_path.join(format!("{}.checkpoints", session_id))
+    }
+
+    /// The single-array file written by older versions.
+    fn legacy_path(&self, session_id: &str) -> std::path::PathBuf {
         self.base_path.join(format!("{}.json", session_id))
     }
+
+    fn trace_path(&self, session_id: &str) -> std::path::PathBuf {
+        self.base_path.join(format!("{}.trace", session_id))
+    }
+
+    /// Session IDs with checkpoint files, skipping trace logs and anything else.
+    fn session_ids(&self) -> Result<Vec<String>, String> {
+        let mut ids = std::collections::BTreeSet::new();
+        for entry in std::fs::read_dir(&self.base_path).map_err(|e| e.to_string())? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            let is_checkpoints = path
+                .extension()
+                .is_some_and(|e| e == "checkpoints" || e == "json");
+            if path.is_file() && is_checkpoints {
+                if let Some(stem) = path.file_stem() {
+                    ids.insert(stem.to_string_lossy().to_string());
+                }
+            }
+        }
+        Ok(ids.into_iter().collect())
+    }
+
+    fn read_session(&self, session_id: &str) -> Result<Vec<AgentCheckpoint>, String> {
+        let legacy = self.legacy_path(session_id);
+        let mut stored: Vec<Value> = if legacy.exists() {
+            let data = std::fs::read(&legacy).map_err(|e| e.to_string())?;
+            decode_with(self.codec.as_ref(), data)?
+        } else {
+            Vec::new()
+        };
+        stored.extend(self.read_frames::<Value>(&self.log_path(session_id))?.0);
+        let mut checkpoints = stored
+            .into_iter()
+            .map(|c| self.migrations.load(c))
+            .collect::<Result<Vec<AgentCheckpoint>, String>>()?;
+        // Stable, so equal sequences (older saves) stay in file order
+        checkpoints.sort_by_key(|c| c.sequence);
+        if checkpoints.iter().all(|c| c.trace_cursor.is_none()) {
+            return Ok(checkpoints);
+        }
+        let log = self.read_trace(session_id)?;
+        Ok(checkpoints.into_iter().map(|c| c.hydrate(&log)).collect())
+    }
+
+    fn read_trace(&self, session_id: &str) -> Result<Vec<TraceEntry>, String> {
+        let mut log: Vec<TraceEntry> = Vec::new();
+        let batches = self.read_frames::<(usize, Vec<TraceEntry>)>(&self.trace_path(session_id))?.0;
+        for (from, entries) in batches {
+            log.truncate(from);
+            log.extend(entries);
+        }
+        Ok(log)
+    }
+
+    /// Decode every frame of `path`, skipping a torn or corrupt final frame.
+    /// Also returns the length of the valid prefix.
+    fn read_frames<T: DeserializeOwned>(&self, path: &std::path::Path) -> Result<(Vec<T>, u64), String> {
+        if !path.exists() { return Ok((Vec::new(), 0)); }
+        let data = std::fs::read(path).map_err(|e| e.to_string())?;
+        let mut frames = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let rest = &data[pos..];
+            if rest.iter().all(|b| *b == 0) {
+                break; // Zeros left behind by a crash
+            }
+            let len = match rest.get(..4) {
+                Some(b) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize,
+                None => break,
+            };
+            let Some(payload) = rest.get(4..4 + len) else {
+                break; // Cut short by a crash mid-append
+            };
+            match decode_with::<T>(self.codec.as_ref(), payload.to_vec()) {
+                Ok(frame) => frames.push(frame),
+                // Only the last frame can be half-written. A lone frame that
+                // does not decode is more likely a wrong codec.
+                Err(_) if pos + 4 + len == data.len() && !frames.is_empty() => break,
+                Err(e) => return Err(format!("{}: corrupt frame at byte {}: {}", path.display(), pos, e)),
+            }
+            pos += 4 + len;
+        }
+        if pos < data.len() {
+            tracing::warn!(path = %path.display(), valid = pos, len = data.len(), "Ignoring torn frame at end of file");
+        }
+        Ok((frames, pos as u64))
+    }
+
+    /// Append one frame to `path` and sync it. The first append to a file
+    /// truncates a torn tail left by a crash, so new frames stay readable.
+    fn append_frame<T: DeserializeOwned>(&self, path: &std::path::Path, payload: Vec<u8>) -> Result<(), String> {
+        use std::io::Write;
+        let len = u32::try_from(payload.len()).map_err(|_| "Frame too large".to_string())?;
+        let mut frame = len.to_le_bytes().to_vec();
+        frame.extend(payload);
+
+        let mut repaired = self.repaired.lock().unwrap();
+        if !repaired.contains(path) {
+            let (_, valid) = self.read_frames::<T>(path)?;
+            let on_disk = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            if valid < on_disk {
+                let file = std::fs::OpenOptions::new().write(true).open(path).map_err(|e| e.to_string())?;
+                file.set_len(valid).map_err(|e| e.to_string())?;
+                tracing::warn!(path = %path.display(), removed = on_disk - valid, "Truncated torn frame");
+            }
+            repaired.insert(path.to_path_buf());
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| e.to_string())?;
+        file.write_all(&frame).map_err(|e| e.to_string())?;
+        file.sync_data().map_err(|e| e.to_string())
+    }
 }
 
 #[async_trait]
 impl CheckpointStore for FileCheckpointStore {
     async fn save(&self, checkpoint: AgentCheckpoint) -> Result<(), String> {
-        let path = self.session_path(&checkpoint.session_id);
-        let mut checkpoints: Vec<AgentCheckpoint> = if path.exists() {
-            let data = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
-            serde_json::from_str(&data).map_err(|e| e.to_string())?
-        } else {
-            Vec::new()
-        };
-        checkpoints.push(checkpoint);
-        let data = serde_json::to_string_pretty(&checkpoints).map_err(|e| e.to_string())?;
-        std::fs::write(&path, data).map_err(|e| e.to_string())?;
-        Ok(())
+        let payload = encode_with(self.codec.as_ref(), &checkpoint)?;
+        let path = self.log_path(&checkpoint.session_id);
+        self.blocking(move |store| store.append_frame::<AgentCheckpoint>(&path, payload)).await
     }
 
     async fn load_latest(&self, session_id: &str) -> Result<Option<AgentCheckpoint>, String> {
-        let path = self.session_path(session_id);
-        if !path.exists() { return Ok(None); }
-        let data = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
-        let checkpoints: Vec<AgentCheckpoint> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-        Ok(checkpoints.last().cloned())
+        let session_id = session_id.to_string();
+        self.blocking(move |store| Ok(store.read_session(&session_id)?.pop())).await
     }
 
     async fn load_by_id(&self, checkpoint_id: &str) -> Result<Option<AgentCheckpoint>, String> {
-        // This is inefficient for FileStore but satisfies the trait
-        for entry in std::fs::read_dir(&self.base_path).map_err(|e| e.to_string())? {
-            let entry = entry.map_err(|e| e.to_string())?;
-            let data = std::fs::read_to_string(entry.path()).map_err(|e| e.to_string())?;
-            let checkpoints: Vec<AgentCheckpoint> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-            if let Some(cp) = checkpoints.iter().find(|c| c.checkpoint_id == checkpoint_id) {
-                return Ok(Some(cp.clone()));
+        let checkpoint_id = checkpoint_id.to_string();
+        self.blocking(move |store| {
+            // This is inefficient for FileStore but satisfies the trait
+            for session_id in store.session_ids()? {
+                let checkpoints = store.read_session(&session_id)?;
+                if let Some(cp) = checkpoints.into_iter().find(|c| c.checkpoint_id == checkpoint_id) {
+                    return Ok(Some(cp));
+                }
             }
-        }
-        Ok(None)
+            Ok(None)
+        })
+        .await
     }
 
     async fn list_sessions(&self) -> Result<Vec<String>, String> {
-        let mut sessions = Vec::new();
-        for entry in std::fs::read_dir(&self.base_path).map_err(|e| e.to_string())? {
-            let entry = entry.map_err(|e| e.to_string())?;
-            if let Some(stem) = entry.path().file_stem() {
-                sessions.push(stem.to_string_lossy().to_string());
-            }
-        }
-        Ok(sessions)
+        self.blocking(|store| store.session_ids()).await
+    }
+
+    async fn list_checkpoints(&self, session_id: &str) -> Result<Vec<AgentCheckpoint>, String> {
+        let session_id = session_id.to_string();
+        self.blocking(move |store| store.read_session(&session_id)).await
+    }
+
+    async fn append_trace(&self, session_id: &str, from: usize, entries: &[TraceEntry]) -> Result<bool, String> {
+        let payload = encode_with(self.codec.as_ref(), &(from, entries))?;
+        let path = self.trace_path(session_id);
+        self.blocking(move |store| store.append_frame::<(usize, Vec<TraceEntry>)>(&path, payload)).await?;
+        Ok(true)
     }
 }
 
 /// A checkpoint store that uses a SQLite database.
+///
+/// The codec applies to the `memory` column and to trace entries. Codec
+/// output that is not valid UTF-8 is stored as a BLOB.
+///
+/// Database calls run on tokio's blocking pool, never on the executor.
+/// Connections are opened once in WAL mode and reused (see `pool_size`),
+/// so a per-step checkpoint costs one insert instead of an open, schema
+/// check and journal setup.
 pub struct SqliteCheckpointStore {
-    path: std::path::PathBuf,
+    pool:  Arc<ConnectionPool>,
+    codec: Arc<dyn MemoryCodec>,
+    migrations: Arc<CheckpointMigrations>,
+}
+
+/// Idle connections kept open for reuse.
+struct ConnectionPool {
+    path:     std::path::PathBuf,
+    idle:     std::sync::Mutex<Vec<rusqlite::Connection>>,
+    max_idle: usize,
+}
+
+impl ConnectionPool {
+    fn new(path: std::path::PathBuf, max_idle: usize) -> Self {
+        Self { path, idle: std::sync::Mutex::new(Vec::new()), max_idle }
+    }
+
+    /// An idle connection, or a newly opened one.
+    fn get(&self) -> Result<rusqlite::Connection, String> {
+        if let Some(conn) = self.idle.lock().unwrap().pop() {
+            return Ok(conn);
+        }
+        let conn = rusqlite::Connection::open(&self.path).map_err(|e| e.to_string())?;
+        // WAL lets readers run alongside the writer; NORMAL sync is durable in WAL mode
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+            .map_err(|e| e.to_string())?;
+        conn.pragma_update(None, "synchronous", "NORMAL").map_err(|e| e.to_string())?;
+        conn.busy_timeout(std::time::Duration::from_secs(5)).map_err(|e| e.to_string())?;
+        Ok(conn)
+    }
+
+    /// Return a connection for reuse, or close it if enough are idle.
+    fn put(&self, conn: rusqlite::Connection) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_idle {
+            idle.push(conn);
+        }
+    }
 }
 
+/// Idle connections kept by default.
+const DEFAULT_POOL_SIZE: usize = 4;
+
+const CHECKPOINT_COLUMNS: &str =
+    "checkpoint_id, session_id, state, memory, timestamp, graph, trace_cursor, memory_from_trace, sequence, schema_version";
+
+/// Newest save last; `rowid` keeps the save order of equal sequences.
+const CHECKPOINT_ORDER: &str = "sequence ASC, rowid ASC";
+
 impl SqliteCheckpointStore {
     pub fn new(path: impl Into<std::path::PathBuf>) -> Result<Self, String> {
-        let path = path.into();
-        let conn = rusqlite::Connection::open(&path).map_err(|e| e.to_string())?;
+        let pool = ConnectionPool::new(path.into(), DEFAULT_POOL_SIZE);
+        let conn = pool.get()?;
         conn.execute(
             "CREATE TABLE IF NOT EXISTS checkpoints (
                 checkpoint_id TEXT PRIMARY KEY,
@@ -164,95 +810,337 @@ impl SqliteCheckpointStore {
             )",
             [],
         ).map_err(|e| e.to_string())?;
-        Ok(Self { path })
+        // Databases created before graph shapes were stored lack the column
+        let has_graph = conn
+            .prepare("SELECT graph FROM checkpoints LIMIT 0")
+            .is_ok();
+        if !has_graph {
+            conn.execute("ALTER TABLE checkpoints ADD COLUMN graph TEXT", [])
+                .map_err(|e| e.to_string())?;
+        }
+        // ... and the trace cursor
+        let has_trace_cursor = conn
+            .prepare("SELECT trace_cursor FROM checkpoints LIMIT 0")
+            .is_ok();
+        if !has_trace_cursor {
+            conn.execute("ALTER TABLE checkpoints ADD COLUMN trace_cursor INTEGER", [])
+                .map_err(|e| e.to_string())?;
+        }
+        // ... and the event-sourced memory flag
+        let has_memory_from_trace = conn
+            .prepare("SELECT memory_from_trace FROM checkpoints LIMIT 0")
+            .is_ok();
+        if !has_memory_from_trace {
+            conn.execute("ALTER TABLE checkpoints ADD COLUMN memory_from_trace INTEGER NOT NULL DEFAULT 0", [])
+                .map_err(|e| e.to_string())?;
+        }
+        // ... and the sequence number
+        let has_sequence = conn
+            .prepare("SELECT sequence FROM checkpoints LIMIT 0")
+            .is_ok();
+        if !has_sequence {
+            conn.execute("ALTER TABLE checkpoints ADD COLUMN sequence INTEGER NOT NULL DEFAULT 0", [])
+                .map_err(|e| e.to_string())?;
+        }
+        // ... and the schema version
+        let has_schema_version = conn
+            .prepare("SELECT schema_version FROM checkpoints LIMIT 0")
+            .is_ok();
+        if !has_schema_version {
+            conn.execute("ALTER TABLE checkpoints ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 0", [])
+                .map_err(|e| e.to_string())?;
+        }
+        // Latest/list queries filter by session and sort by sequence
+        conn.execute_batch(
+            "DROP INDEX IF EXISTS idx_checkpoints_session_timestamp;
+             CREATE INDEX IF NOT EXISTS idx_checkpoints_session_sequence
+             ON checkpoints (session_id, sequence)",
+        ).map_err(|e| e.to_string())?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS trace_entries (
+                session_id TEXT    NOT NULL,
+                idx        INTEGER NOT NULL,
+                entry      TEXT    NOT NULL,
+                PRIMARY KEY (session_id, idx)
+            )",
+            [],
+        ).map_err(|e| e.to_string())?;
+        pool.put(conn);
+        Ok(Self {
+            pool:       Arc::new(pool),
+            codec:      Arc::new(PlainCodec),
+            migrations: Arc::new(CheckpointMigrations::new()),
+        })
+    }
+
+    /// Encrypt/compress the stored memory with `codec`.
+    pub fn with_codec(mut self, codec: Arc<dyn MemoryCodec>) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Upgrade old checkpoints with `migrations` instead of the built-in steps.
+    pub fn with_migrations(mut self, migrations: CheckpointMigrations) -> Self {
+        self.migrations = Arc::new(migrations);
+        self
+    }
+
+    /// Keep up to `n` idle connections open for reuse (default 4). Extra
+    /// connections opened under concurrent load are closed after use.
+    pub fn pool_size(mut self, n: usize) -> Self {
+        self.pool = Arc::new(ConnectionPool::new(self.pool.path.clone(), n));
+        self
+    }
+
+    /// Run `f` with a pooled connection on the blocking thread pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut rusqlite::Connection, &dyn MemoryCodec) -> Result<T, String> + Send + 'static,
+    {
+        let pool = Arc::clone(&self.pool);
+        let codec = Arc::clone(&self.codec);
+        tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get()?;
+            let result = f(&mut conn, codec.as_ref());
+            pool.put(conn);
+            result
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    /// Codec output as TEXT when it is valid UTF-8, BLOB otherwise.
+    fn to_sql(data: Vec<u8>) -> rusqlite::types::Value {
+        match String::from_utf8(data) {
+            Ok(text) => rusqlite::types::Value::Text(text),
+            Err(e) => rusqlite::types::Value::Blob(e.into_bytes()),
+        }
+    }
+
+    fn from_sql(value: rusqlite::types::ValueRef<'_>) -> Result<Vec<u8>, String> {
+        match value {
+            rusqlite::types::ValueRef::Text(t) => Ok(t.to_vec()),
+            rusqlite::types::ValueRef::Blob(b) => Ok(b.to_vec()),
+            other => Err(format!("Unexpected column type: {:?}", other.data_type())),
+        }
+    }
+
+    /// The first `upto` entries of a session's trace log.
+    fn load_trace(
+        conn: &rusqlite::Connection,
+        codec: &dyn MemoryCodec,
+        session_id: &str,
+        upto: usize,
+    ) -> Result<Vec<TraceEntry>, String> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT entry FROM trace_entries WHERE session_id = ?1 AND idx < ?2 ORDER BY idx ASC"
+        ).map_err(|e| e.to_string())?;
+        let mut rows = stmt.query(rusqlite::params![session_id, upto as i64]).map_err(|e| e.to_string())?;
+        let mut log = Vec::new();
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let data = Self::from_sql(row.get_ref(0).map_err(|e| e.to_string())?)?;
+            log.push(decode_with(codec, data)?);
+        }
+        Ok(log)
+    }
+
+    /// Read all checkpoints matching `filter` (with `?1`, `?2`, ... bound to
+    /// `params`) and restore their traces.
+    async fn query_checkpoints(
+        &self,
+        filter: String,
+        params: Vec<rusqlite::types::Value>,
+    ) -> Result<Vec<AgentCheckpoint>, String> {
+        let migrations = Arc::clone(&self.migrations);
+        self.with_conn(move |conn, codec| {
+            let sql = format!("SELECT {} FROM checkpoints {}", CHECKPOINT_COLUMNS, filter);
+            let mut stmt = conn.prepare_cached(&sql).map_err(|e| e.to_string())?;
+            let mut rows = stmt.query(rusqlite::params_from_iter(params)).map_err(|e| e.to_string())?;
+            let mut checkpoints = Vec::new();
+            while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+                checkpoints.push(Self::row_to_checkpoint(codec, &migrations, row)?);
+            }
+            drop(rows);
+            drop(stmt);
+
+            // One read of the log covers every checkpoint of the session
+            let upto = checkpoints.iter().filter_map(|c| c.trace_cursor).max();
+            if let (Some(upto), Some(first)) = (upto, checkpoints.first()) {
+                let log = Self::load_trace(conn, codec, &first.session_id, upto)?;
+                checkpoints = checkpoints.into_iter().map(|c| c.hydrate(&log)).collect();
+            }
+            Ok(checkpoints)
+        })
+        .await
     }
 
-    fn get_conn(&self) -> Result<rusqlite::Connection, String> {
-        rusqlite::Connection::open(&self.path).map_err(|e| e.to_string())
+    /// Columns: see [`CHECKPOINT_COLUMNS`]
+    fn row_to_checkpoint(
+        codec:      &dyn MemoryCodec,
+        migrations: &CheckpointMigrations,
+        row:        &rusqlite::Row<'_>,
+    ) -> Result<AgentCheckpoint, String> {
+        let memory_data = Self::from_sql(row.get_ref(3).map_err(|e| e.to_string())?)?;
+        let state_json: String = row.get(2).map_err(|e| e.to_string())?;
+        let timestamp_str: String = row.get(4).map_err(|e| e.to_string())?;
+        let graph_json: Option<String> = row.get(5).map_err(|e| e.to_string())?;
+        let trace_cursor: Option<i64> = row.get(6).map_err(|e| e.to_string())?;
+        let memory_from_trace: bool = row.get(7).map_err(|e| e.to_string())?;
+        let sequence: i64 = row.get(8).map_err(|e| e.to_string())?;
+        let schema_version: i64 = row.get(9).map_err(|e| e.to_string())?;
+
+        let mut memory: Value = decode_with(codec, memory_data)?;
+        migrations.upgrade_memory(&mut memory, schema_version as u32)?;
+
+        Ok(AgentCheckpoint {
+            checkpoint_id: row.get(0).map_err(|e| e.to_string())?,
+            session_id:    row.get(1).map_err(|e| e.to_string())?,
+            state:          serde_json::from_str(&state_json).map_err(|e| e.to_string())?,
+            memory:         serde_json::from_value(memory).map_err(|e| e.to_string())?,
+            timestamp:      chrono::DateTime::parse_from_rfc3339(&timestamp_str)
+                                .map_err(|e| e.to_string())?.with_timezone(&chrono::Utc),
+            graph:          graph_json
+                                .map(|g| serde_json::from_str(&g))
+                                .transpose()
+                                .map_err(|e| e.to_string())?,
+            trace_cursor:   trace_cursor.map(|c| c as usize),
+            memory_from_trace,
+            sequence:       sequence as u64,
+            schema_version: CHECKPOINT_SCHEMA_VERSION,
+        })
     }
 }
 
 #[async_trait]
 impl CheckpointStore for SqliteCheckpointStore {
     async fn save(&self, checkpoint: AgentCheckpoint) -> Result<(), String> {
-        let conn = self.get_conn()?;
-        let memory_json = serde_json::to_string(&checkpoint.memory).map_err(|e| e.to_string())?;
+        let memory_data = Self::to_sql(encode_with(self.codec.as_ref(), &checkpoint.memory)?);
         let state_json = serde_json::to_string(&checkpoint.state).map_err(|e| e.to_string())?;
-        
-        conn.execute(
-            "INSERT INTO checkpoints (checkpoint_id, session_id, state, memory, timestamp)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            rusqlite::params![
+        let graph_json = checkpoint.graph
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| e.to_string())?;
+
+        self.with_conn(move |conn, _| {
+            let mut stmt = conn.prepare_cached(
+                "INSERT INTO checkpoints (checkpoint_id, session_id, state, memory, timestamp, graph, trace_cursor, memory_from_trace, sequence, schema_version)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            ).map_err(|e| e.to_string())?;
+            stmt.execute(rusqlite::params![
                 checkpoint.checkpoint_id,
                 checkpoint.session_id,
                 state_json,
-                memory_json,
-                checkpoint.timestamp.to_rfc3339()
-            ],
-        ).map_err(|e| e.to_string())?;
-        Ok(())
+                memory_data,
+                checkpoint.timestamp.to_rfc3339(),
+                graph_json,
+                checkpoint.trace_cursor.map(|c| c as i64),
+                checkpoint.memory_from_trace,
+                checkpoint.sequence as i64,
+                checkpoint.schema_version
+            ]).map_err(|e| e.to_string())?;
+            Ok(())
+        })
+        .await
     }
 
     async fn load_latest(&self, session_id: &str) -> Result<Option<AgentCheckpoint>, String> {
-        let conn = self.get_conn()?;
-        let mut stmt = conn.prepare(
-            "SELECT checkpoint_id, session_id, state, memory, timestamp 
-             FROM checkpoints WHERE session_id = ?1 ORDER BY timestamp DESC LIMIT 1"
-        ).map_err(|e| e.to_string())?;
-        
-        let mut rows = stmt.query(rusqlite::params![session_id]).map_err(|e| e.to_string())?;
-        if let Some(row) = rows.next().map_err(|e| e.to_string())? {
-            let memory_json: String = row.get(3).map_err(|e| e.to_string())?;
-            let state_json: String = row.get(2).map_err(|e| e.to_string())?;
-            let timestamp_str: String = row.get(4).map_err(|e| e.to_string())?;
-            
-            Ok(Some(AgentCheckpoint {
-                checkpoint_id: row.get(0).map_err(|e| e.to_string())?,
-                session_id:    row.get(1).map_err(|e| e.to_string())?,
-                state:          serde_json::from_str(&state_json).map_err(|e| e.to_string())?,
-                memory:         serde_json::from_str(&memory_json).map_err(|e| e.to_string())?,
-                timestamp:      chrono::DateTime::parse_from_rfc3339(&timestamp_str)
-                                    .map_err(|e| e.to_string())?.with_timezone(&chrono::Utc),
-            }))
-        } else {
-            Ok(None)
-        }
+        let checkpoints = self
+            .query_checkpoints(
+                "WHERE session_id = ?1 ORDER BY sequence DESC, rowid DESC LIMIT 1".to_string(),
+                vec![session_id.to_string().into()],
+            )
+            .await?;
+        Ok(checkpoints.into_iter().next())
     }
 
     async fn load_by_id(&self, checkpoint_id: &str) -> Result<Option<AgentCheckpoint>, String> {
-        let conn = self.get_conn()?;
-        let mut stmt = conn.prepare(
-            "SELECT checkpoint_id, session_id, state, memory, timestamp 
-             FROM checkpoints WHERE checkpoint_id = ?1"
-        ).map_err(|e| e.to_string())?;
-        
-        let mut rows = stmt.query(rusqlite::params![checkpoint_id]).map_err(|e| e.to_string())?;
-        if let Some(row) = rows.next().map_err(|e| e.to_string())? {
-            let memory_json: String = row.get(3).map_err(|e| e.to_string())?;
-            let state_json: String = row.get(2).map_err(|e| e.to_string())?;
-            let timestamp_str: String = row.get(4).map_err(|e| e.to_string())?;
-            
-            Ok(Some(AgentCheckpoint {
-                checkpoint_id: row.get(0).map_err(|e| e.to_string())?,
-                session_id:    row.get(1).map_err(|e| e.to_string())?,
-                state:          serde_json::from_str(&state_json).map_err(|e| e.to_string())?,
-                memory:         serde_json::from_str(&memory_json).map_err(|e| e.to_string())?,
-                timestamp:      chrono::DateTime::parse_from_rfc3339(&timestamp_str)
-                                    .map_err(|e| e.to_string())?.with_timezone(&chrono::Utc),
-            }))
-        } else {
-            Ok(None)
-        }
+        let checkpoints = self
+            .query_checkpoints("WHERE checkpoint_id = ?1".to_string(), vec![checkpoint_id.to_string().into()])
+            .await?;
+        Ok(checkpoints.into_iter().next())
     }
 
     async fn list_sessions(&self) -> Result<Vec<String>, String> {
-        let conn = self.get_conn()?;
-        let mut stmt = conn.prepare("SELECT DISTINCT session_id FROM checkpoints").map_err(|e| e.to_string())?;
-        let rows = stmt.query_map([], |row| row.get(0)).map_err(|e| e.to_string())?;
-        let mut sessions = Vec::new();
-        for session in rows {
-            sessions.push(session.map_err(|e| e.to_string())?);
-        }
-        Ok(sessions)
+        self.with_conn(|conn, _| {
+            let mut stmt = conn.prepare_cached("SELECT DISTINCT session_id FROM checkpoints").map_err(|e| e.to_string())?;
+            let rows = stmt.query_map([], |row| row.get(0)).map_err(|e| e.to_string())?;
+            let mut sessions = Vec::new();
+            for session in rows {
+                sessions.push(session.map_err(|e| e.to_string())?);
+            }
+            Ok(sessions)
+        })
+        .await
+    }
+
+    async fn list_checkpoints(&self, session_id: &str) -> Result<Vec<AgentCheckpoint>, String> {
+        self.query_checkpoints(
+            format!("WHERE session_id = ?1 ORDER BY {}", CHECKPOINT_ORDER),
+            vec![session_id.to_string().into()],
+        )
+        .await
+    }
+
+    async fn list_checkpoints_page(
+        &self,
+        session_id: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<AgentCheckpoint>, String> {
+        self.query_checkpoints(
+            format!("WHERE session_id = ?1 ORDER BY {} LIMIT ?2 OFFSET ?3", CHECKPOINT_ORDER),
+            vec![
+                session_id.to_string().into(),
+                // SQLite reads a negative limit as "no limit"
+                i64::try_from(limit).unwrap_or(-1).into(),
+                i64::try_from(offset).unwrap_or(i64::MAX).into(),
+            ],
+        )
+        .await
+    }
+
+    async fn last_sequence(&self, session_id: &str) -> Result<u64, String> {
+        let session_id = session_id.to_string();
+        self.with_conn(move |conn, _| {
+            let max: Option<i64> = conn
+                .query_row(
+                    "SELECT MAX(sequence) FROM checkpoints WHERE session_id = ?1",
+                    rusqlite::params![session_id],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            Ok(max.unwrap_or(0) as u64)
+        })
+        .await
+    }
+
+    async fn append_trace(&self, session_id: &str, from: usize, entries: &[TraceEntry]) -> Result<bool, String> {
+        let rows = entries
+            .iter()
+            .map(|entry| Ok(Self::to_sql(encode_with(self.codec.as_ref(), entry)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let session_id = session_id.to_string();
+        self.with_conn(move |conn, _| {
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            tx.execute(
+                "DELETE FROM trace_entries WHERE session_id = ?1 AND idx >= ?2",
+                rusqlite::params![session_id, from as i64],
+            ).map_err(|e| e.to_string())?;
+            {
+                let mut insert = tx
+                    .prepare_cached("INSERT INTO trace_entries (session_id, idx, entry) VALUES (?1, ?2, ?3)")
+                    .map_err(|e| e.to_string())?;
+                for (i, data) in rows.into_iter().enumerate() {
+                    insert
+                        .execute(rusqlite::params![session_id, (from + i) as i64, data])
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            tx.commit().map_err(|e| e.to_string())?;
+            Ok(true)
+        })
+        .await
     }
 }
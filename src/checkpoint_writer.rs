@@ -0,0 +1,332 @@
+//! Checkpoint Writer — saves checkpoints on a background task.
+//!
+//! Saving a checkpoint takes two round-trips to the store: new trace
+//! entries go to its trace log, then the checkpoint itself is written.
+//! Done inline, every step waited for both. Instead, the engine snapshots
+//! its state and memory after each transition and queues the snapshot on a
+//! [`CheckpointWriter`]. Its task writes one snapshot at a time, so a store
+//! never sees checkpoint N+1 before N.
+//!
+//! The queue holds at most one snapshot. A snapshot queued while an earlier
+//! one still waits replaces its state and memory and appends its trace
+//! entries, so slow storage costs skipped intermediate checkpoints, not
+//! memory: the latest checkpoint is always written, and the trace log still
+//! receives every entry, in order.
+//!
+//! [`CheckpointWriter::flush`] is the barrier: it returns once everything
+//! queued before it is written. The engine flushes when a run ends —
+//! however it ends — and after every call to the public
+//! `AgentEngine::step`, so a finished run, or a session stepped by hand, is
+//! in the store when control comes back. Snapshots queued by an engine that
+//! is dropped are still written.
+//!
+//! A failed write is logged when it happens, and the first failure since
+//! the last flush is returned by the flush.
+//!
+//! Once the store has taken trace entries into its log, an event-sourced
+//! session's memory is rebuilt from that log, so the engine stops copying
+//! its memory into snapshots ([`CheckpointWriter::logs_trace`]).
+
+use crate::checkpoint::{AgentCheckpoint, CheckpointStore, GraphShape};
+use crate::memory::AgentMemory;
+use crate::trace::{Trace, TraceEntry};
+use crate::types::State;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+/// Engine state at the end of one transition, as queued for saving.
+pub(crate) struct Snapshot {
+    pub state: State,
+    /// Memory without its trace
+    pub memory: AgentMemory,
+    /// Index of the first trace entry not sent before
+    pub trace_from: usize,
+    /// Trace entries from `trace_from` on
+    pub trace: Vec<TraceEntry>,
+    /// Memory can be rebuilt from the trace log (`event_sourced_memory`)
+    pub from_trace: bool,
+    pub graph: GraphShape,
+}
+
+impl Snapshot {
+    /// Fold a later snapshot into this one: its state and memory win, its
+    /// trace entries follow the ones this snapshot already carries.
+    fn merge(&mut self, later: Snapshot) {
+        if later.trace_from <= self.trace_from {
+            self.trace_from = later.trace_from;
+            self.trace = later.trace;
+        } else {
+            self.trace.truncate(later.trace_from - self.trace_from);
+            self.trace.extend(later.trace);
+        }
+        self.state = later.state;
+        self.memory = later.memory;
+        self.from_trace = later.from_trace;
+        self.graph = later.graph;
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    snapshot: Option<Snapshot>,
+    /// Flushes waiting for `snapshot` (or the write in progress) to finish
+    flushes: Vec<oneshot::Sender<Result<(), String>>>,
+}
+
+/// Writes one session's checkpoints in order on a background task.
+pub struct CheckpointWriter {
+    queue: Arc<Mutex<Queue>>,
+    /// Wakes the task; holds at most one signal
+    wake: mpsc::Sender<()>,
+    logs_trace: Arc<AtomicBool>,
+}
+
+impl CheckpointWriter {
+    /// Start writing `session_id`'s checkpoints to `store`, whose trace log
+    /// already holds the first `trace_persisted` entries of the session.
+    pub(crate) fn spawn(store: Arc<dyn CheckpointStore>, session_id: String, trace_persisted: usize) -> Self {
+        let queue = Arc::new(Mutex::new(Queue::default()));
+        let (wake, rx) = mpsc::channel(1);
+        let logs_trace = Arc::new(AtomicBool::new(false));
+        let writer = Writer {
+            store,
+            session_id,
+            unpersisted: Vec::new(),
+            trace_persisted,
+            sequence: None,
+            failure: None,
+            logs_trace: Arc::clone(&logs_trace),
+        };
+        tokio::spawn(writer.run(Arc::clone(&queue), rx));
+        Self { queue, wake, logs_trace }
+    }
+
+    /// True once the store has accepted trace entries into its log. A
+    /// store that keeps a trace log always does, so from then on a
+    /// snapshot with `from_trace` set needs no copy of the memory.
+    pub(crate) fn logs_trace(&self) -> bool {
+        self.logs_trace.load(Ordering::Relaxed)
+    }
+
+    /// False once the task is gone, e.g. with the runtime it ran on.
+    pub(crate) fn is_running(&self) -> bool {
+        !self.wake.is_closed()
+    }
+
+    pub(crate) fn save(&self, snapshot: Snapshot) {
+        {
+            let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+            match queue.snapshot.as_mut() {
+                Some(queued) => queued.merge(snapshot),
+                None => queue.snapshot = Some(snapshot),
+            }
+        }
+        // Full means a wake-up is already pending
+        let _ = self.wake.try_send(());
+    }
+
+    /// Wait until every snapshot queued so far is written. Returns the
+    /// first failure since the previous flush.
+    pub async fn flush(&self) -> Result<(), String> {
+        if !self.is_running() {
+            return Err("Checkpoint writer stopped".to_string());
+        }
+        let (done_tx, done_rx) = oneshot::channel();
+        self.queue
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .flushes
+            .push(done_tx);
+        let _ = self.wake.try_send(());
+        done_rx
+            .await
+            .map_err(|_| "Checkpoint writer stopped".to_string())?
+    }
+}
+
+struct Writer {
+    store: Arc<dyn CheckpointStore>,
+    session_id: String,
+    /// Trace entries from `trace_persisted` on, not yet in the store's trace log
+    unpersisted: Vec<TraceEntry>,
+    /// Trace entries already in the store's trace log
+    trace_persisted: usize,
+    /// `sequence` of the last checkpoint saved for this session, once known
+    sequence: Option<u64>,
+    failure: Option<String>,
+    logs_trace: Arc<AtomicBool>,
+}
+
+impl Writer {
+    /// Write whatever is queued on each wake-up, then answer the flushes
+    /// that were waiting for it. Ends once the `CheckpointWriter` is dropped
+    /// and the queue is drained.
+    async fn run(mut self, queue: Arc<Mutex<Queue>>, mut wake: mpsc::Receiver<()>) {
+        while wake.recv().await.is_some() {
+            self.drain(&queue).await;
+        }
+        self.drain(&queue).await;
+    }
+
+    async fn drain(&mut self, queue: &Mutex<Queue>) {
+        loop {
+            let (snapshot, flushes) = {
+                let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+                (queue.snapshot.take(), std::mem::take(&mut queue.flushes))
+            };
+            if snapshot.is_none() && flushes.is_empty() {
+                return;
+            }
+            if let Some(snapshot) = snapshot {
+                if let Err(e) = self.write(snapshot).await {
+                    tracing::warn!(session = %self.session_id, error = %e, "Checkpoint failed");
+                    self.failure.get_or_insert(e);
+                }
+            }
+            if !flushes.is_empty() {
+                let result = self.failure.take().map_or(Ok(()), Err);
+                for done in flushes {
+                    let _ = done.send(result.clone());
+                }
+            }
+        }
+    }
+
+    /// New trace entries go to the store's trace log when it keeps one, and
+    /// the checkpoint carries only a cursor into it. Otherwise the full
+    /// trace is stored with the memory, so nothing is ever persisted
+    /// separately and `unpersisted` holds the whole trace.
+    async fn write(&mut self, snapshot: Snapshot) -> Result<(), String> {
+        // Entries before `trace_persisted` are already logged (a new writer
+        // is sent the whole trace again)
+        let mut entries = snapshot.trace;
+        let keep = snapshot.trace_from.saturating_sub(self.trace_persisted).min(self.unpersisted.len());
+        let skip = self.trace_persisted.saturating_sub(snapshot.trace_from).min(entries.len());
+        self.unpersisted.truncate(keep);
+        self.unpersisted.extend(entries.drain(skip..));
+
+        // Continue the session's numbering, including saves by earlier runs
+        let sequence = match self.sequence {
+            Some(last) => last + 1,
+            None => self.store.last_sequence(&self.session_id).await? + 1,
+        };
+
+        let len = self.trace_persisted + self.unpersisted.len();
+        let logged = self
+            .store
+            .append_trace(&self.session_id, self.trace_persisted, &self.unpersisted)
+            .await?;
+
+        let from_trace = logged && snapshot.from_trace;
+        let memory = if logged {
+            self.logs_trace.store(true, Ordering::Relaxed);
+            self.trace_persisted = len;
+            self.unpersisted.clear();
+            if from_trace {
+                // The trace log holds every change; store a placeholder
+                AgentMemory::new("")
+            } else {
+                snapshot.memory
+            }
+        } else {
+            let mut memory = snapshot.memory;
+            memory.trace = Trace::from_entries(self.unpersisted.clone());
+            memory
+        };
+        self.store
+            .save(AgentCheckpoint {
+                checkpoint_id: uuid::Uuid::new_v4().to_string(),
+                session_id: self.session_id.clone(),
+                state: snapshot.state,
+                memory,
+                timestamp: chrono::Utc::now(),
+                graph: Some(snapshot.graph),
+                trace_cursor: logged.then_some(len),
+                memory_from_trace: from_trace,
+                sequence,
+                schema_version: crate::checkpoint::CHECKPOINT_SCHEMA_VERSION,
+            })
+            .await?;
+        self.sequence = Some(sequence);
+        Ok(())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint::MemoryCheckpointStore;
+
+    fn entry(n: usize) -> TraceEntry {
+        TraceEntry {
+            step: n,
+            state: "Planning".to_string(),
+            event: format!("E{}", n),
+            data: String::new(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    fn snapshot(step: usize, trace_from: usize, trace: Vec<TraceEntry>) -> Snapshot {
+        let mut memory = AgentMemory::new("task");
+        memory.step = step;
+        Snapshot {
+            state: State::planning(),
+            memory,
+            trace_from,
+            trace,
+            from_trace: false,
+            graph: GraphShape::default(),
+        }
+    }
+
+    fn events(trace: &[TraceEntry]) -> Vec<&str> {
+        trace.iter().map(|e| e.event.as_str()).collect()
+    }
+
+    #[test]
+    fn test_merge_keeps_latest_memory_and_every_entry() {
+        let mut queued = snapshot(1, 0, vec![entry(0), entry(1)]);
+        queued.merge(snapshot(2, 2, vec![entry(2)]));
+        queued.merge(snapshot(3, 3, vec![entry(3)]));
+        assert_eq!(queued.memory.step, 3);
+        assert_eq!(queued.trace_from, 0);
+        assert_eq!(events(&queued.trace), ["E0", "E1", "E2", "E3"]);
+
+        // A trace that shrank replaces what it no longer has
+        queued.merge(snapshot(4, 1, vec![entry(5)]));
+        assert_eq!(events(&queued.trace), ["E0", "E5"]);
+    }
+
+    #[tokio::test]
+    async fn test_writer_keeps_only_unlogged_entries() {
+        let store = Arc::new(MemoryCheckpointStore::new());
+        let mut writer = Writer {
+            store: store.clone(),
+            session_id: "s".to_string(),
+            unpersisted: Vec::new(),
+            trace_persisted: 0,
+            sequence: None,
+            failure: None,
+            logs_trace: Arc::new(AtomicBool::new(false)),
+        };
+        writer.write(snapshot(1, 0, vec![entry(0), entry(1)])).await.unwrap();
+        assert!(writer.logs_trace.load(Ordering::Relaxed));
+        assert!(writer.unpersisted.is_empty());
+        assert_eq!(writer.trace_persisted, 2);
+
+        // Sent the whole trace again, as a new writer is: logged entries are skipped
+        writer.write(snapshot(2, 0, vec![entry(0), entry(1), entry(2)])).await.unwrap();
+        assert!(writer.unpersisted.is_empty());
+        assert_eq!(writer.trace_persisted, 3);
+
+        let latest = store.load_latest("s").await.unwrap().unwrap();
+        assert_eq!(latest.sequence, 2);
+        assert_eq!(latest.trace_cursor, Some(3));
+    }
+}
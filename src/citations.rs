@@ -0,0 +1,200 @@
+//! Citation Tracking — source metadata for research agents.
+//!
+//! Tools attach sources to their output with [`with_citations`]. When the
+//! observation is committed, the sources are moved into
+//! `AgentMemory::citations` and numbered. In the observation, the tagged
+//! block is replaced with a `Sources:` list, so the model can cite `[1]`,
+//! `[2]`, … in its answer. With `AgentConfig::require_citations`, a final
+//! answer that cites nothing, or cites a number that does not exist, is
+//! sent back for revision.
+
+use serde::{Deserialize, Serialize};
+
+const OPEN_TAG: &str = "<citations>";
+const CLOSE_TAG: &str = "</citations>";
+
+/// A source backing part of an observation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    /// Number the model uses to cite this source (`[id]`). Assigned by memory.
+    #[serde(default)]
+    pub id: usize,
+    pub url: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub snippet: String,
+    /// Tool that produced the source. Filled in by memory.
+    #[serde(default)]
+    pub tool: String,
+    /// Step at which the source was first seen. Filled in by memory.
+    #[serde(default)]
+    pub step: usize,
+}
+
+impl Citation {
+    pub fn new(url: impl Into<String>, title: impl Into<String>) -> Self {
+        Self {
+            id: 0,
+            url: url.into(),
+            title: title.into(),
+            snippet: String::new(),
+            tool: String::new(),
+            step: 0,
+        }
+    }
+
+    pub fn snippet(mut self, snippet: impl Into<String>) -> Self {
+        self.snippet = snippet.into();
+        self
+    }
+}
+
+/// Attach sources to a tool's output. Use as the tool's return value.
+///
+/// ```
+/// use agent_b::citations::{with_citations, Citation};
+/// let out = with_citations(
+///     "Rust 1.0 was released in May 2015.",
+///     &[Citation::new("https://blog.rust-lang.org/2015/05/15/Rust-1.0.html", "Announcing Rust 1.0")],
+/// );
+/// assert!(out.starts_with("Rust 1.0"));
+/// ```
+pub fn with_citations(output: impl Into<String>, sources: &[Citation]) -> String {
+    let output = output.into();
+    if sources.is_empty() {
+        return output;
+    }
+    format!(
+        "{}\n{}{}{}",
+        output,
+        OPEN_TAG,
+        serde_json::to_string(sources).unwrap_or_else(|_| "[]".to_string()),
+        CLOSE_TAG
+    )
+}
+
+/// Split a tool output into its text and attached sources.
+///
+/// Output without a (well-formed) citation block is returned unchanged.
+pub fn extract_citations(output: &str) -> (String, Vec<Citation>) {
+    let start = match output.rfind(OPEN_TAG) {
+        Some(i) => i,
+        None => return (output.to_string(), Vec::new()),
+    };
+    let end = match output[start..].find(CLOSE_TAG) {
+        Some(i) => start + i,
+        None => return (output.to_string(), Vec::new()),
+    };
+    match serde_json::from_str::<Vec<Citation>>(&output[start + OPEN_TAG.len()..end]) {
+        Ok(sources) => {
+            let text = format!("{}{}", &output[..start], &output[end + CLOSE_TAG.len()..]);
+            (text.trim_end().to_string(), sources)
+        }
+        Err(_) => (output.to_string(), Vec::new()),
+    }
+}
+
+/// Numbers cited in `text` as `[n]` (also `[1, 2]` and `[1][2]`), in order of first use.
+pub fn cited_ids(text: &str) -> Vec<usize> {
+    let mut ids = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let close = match rest.find(']') {
+            Some(c) => c,
+            None => break,
+        };
+        let inner = &rest[..close];
+        let parsed: Option<Vec<usize>> = inner
+            .split(',')
+            .map(|part| part.trim().parse::<usize>().ok())
+            .collect();
+        if let Some(nums) = parsed {
+            for n in nums {
+                if !ids.contains(&n) {
+                    ids.push(n);
+                }
+            }
+            rest = &rest[close + 1..];
+        }
+    }
+    ids
+}
+
+/// Check that `answer` cites at least one source and only sources that exist.
+pub fn validate_answer(answer: &str, citations: &[Citation]) -> Result<(), String> {
+    let ids = cited_ids(answer);
+    if ids.is_empty() {
+        return Err("the answer does not cite any source".to_string());
+    }
+    let unknown: Vec<String> = ids
+        .iter()
+        .filter(|id| !citations.iter().any(|c| c.id == **id))
+        .map(|id| format!("[{}]", id))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!("the answer cites unknown sources {}", unknown.join(", ")));
+    }
+    Ok(())
+}
+
+/// `Sources:` block listing `citations`, as shown to the model.
+pub fn format_sources(citations: &[Citation]) -> String {
+    let mut out = String::from("Sources:");
+    for c in citations {
+        if c.title.is_empty() {
+            out.push_str(&format!("\n[{}] {}", c.id, c.url));
+        } else {
+            out.push_str(&format!("\n[{}] {} — {}", c.id, c.title, c.url));
+        }
+    }
+    out
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let out = with_citations(
+            "text",
+            &[Citation::new("https://a.example", "A").snippet("snip")],
+        );
+        let (text, sources) = extract_citations(&out);
+        assert_eq!(text, "text");
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0].url, "https://a.example");
+        assert_eq!(sources[0].snippet, "snip");
+    }
+
+    #[test]
+    fn test_malformed_block_left_alone() {
+        let out = "text\n<citations>not json</citations>";
+        let (text, sources) = extract_citations(out);
+        assert_eq!(text, out);
+        assert!(sources.is_empty());
+    }
+
+    #[test]
+    fn test_cited_ids() {
+        assert_eq!(cited_ids("A [1], B [2][1] and C [3, 4]."), vec![1, 2, 3, 4]);
+        assert_eq!(cited_ids("see [note] and [x, 1]"), Vec::<usize>::new());
+        assert_eq!(cited_ids("[[2]]"), vec![2]);
+    }
+
+    #[test]
+    fn test_validate_answer() {
+        let mut c = Citation::new("https://a.example", "A");
+        c.id = 1;
+        let citations = vec![c];
+        assert!(validate_answer("Fact [1].", &citations).is_ok());
+        assert!(validate_answer("Fact.", &citations).unwrap_err().contains("does not cite"));
+        assert!(validate_answer("Fact [2].", &citations).unwrap_err().contains("[2]"));
+    }
+}
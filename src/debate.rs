@@ -0,0 +1,372 @@
+//! Multi-Agent Debate — argue over a proposed answer before accepting it.
+//!
+//! When enabled with `AgentBuilder::debate()`, a final answer from Planning
+//! goes to the `Debating` state instead of straight to `Done`. Two or more
+//! debaters (each a model, optionally on its own provider) critique and
+//! defend the proposal for a fixed number of rounds. A judge model then
+//! picks the best position or synthesizes a new answer. Every argument and
+//! the verdict are recorded in the trace.
+
+use crate::events::Event;
+use crate::llm::AsyncLlmCaller;
+use crate::memory::AgentMemory;
+use crate::states::{AgentState, PlanningState};
+use crate::tools::ToolRegistry;
+use crate::types::{AgentOutput, LlmResponse, State};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+const DEBATER_SYSTEM_PROMPT: &str = "You are taking part in a debate about the answer to a task. \
+Examine the proposed answer and the arguments so far. Point out errors, defend what is correct, \
+and say what the best answer is. Be concise.";
+
+const JUDGE_SYSTEM_PROMPT: &str = "You are the judge of a debate about the answer to a task. \
+Weigh the arguments, then either pick the best answer or write a better one that combines them. \
+Respond ONLY with the final answer to the task.";
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Configuration
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// One participant in a debate (or the judge).
+#[derive(Clone)]
+pub struct Debater {
+    /// Label used in the transcript
+    pub name: String,
+    /// Model to call. Empty = the model Planning would use.
+    pub model: String,
+    /// Provider to call. `None` = the agent's own LLM caller.
+    pub llm: Option<Arc<dyn AsyncLlmCaller>>,
+    /// Optional position or persona, appended to the system prompt
+    pub stance: Option<String>,
+}
+
+impl Debater {
+    pub fn new(name: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            model: model.into(),
+            llm: None,
+            stance: None,
+        }
+    }
+
+    /// Call a different provider for this participant.
+    pub fn with_llm(mut self, llm: Arc<dyn AsyncLlmCaller>) -> Self {
+        self.llm = Some(llm);
+        self
+    }
+
+    /// Give this participant a position to argue, e.g. "Argue for the proposal".
+    pub fn stance(mut self, stance: impl Into<String>) -> Self {
+        self.stance = Some(stance.into());
+        self
+    }
+
+    fn system_prompt(&self, base: &str) -> String {
+        match &self.stance {
+            Some(s) => format!("{}\n\n{}", base, s),
+            None => base.to_string(),
+        }
+    }
+}
+
+/// Debaters, judge, and number of rounds.
+#[derive(Clone)]
+pub struct DebateConfig {
+    pub debaters: Vec<Debater>,
+    pub judge: Debater,
+    pub rounds: usize,
+}
+
+impl DebateConfig {
+    /// A debate decided by `judge`. Add participants with `.debater()`.
+    pub fn new(judge: Debater) -> Self {
+        Self {
+            debaters: Vec::new(),
+            judge,
+            rounds: 2,
+        }
+    }
+
+    pub fn debater(mut self, debater: Debater) -> Self {
+        self.debaters.push(debater);
+        self
+    }
+
+    /// Number of rounds in which every debater speaks once (default 2).
+    pub fn rounds(mut self, rounds: usize) -> Self {
+        self.rounds = rounds;
+        self
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// DebateState
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// One argument made during a debate.
+#[derive(Debug, Clone)]
+pub struct DebateArgument {
+    pub round: usize,
+    pub debater: String,
+    pub content: String,
+}
+
+/// Runs the debate over `memory.final_answer` and replaces it with the verdict.
+///
+/// Failures are not fatal: a debater whose call fails sits out that round,
+/// and if the judge fails, the proposed answer is kept.
+pub struct DebateState {
+    config: DebateConfig,
+}
+
+impl DebateState {
+    pub fn new(config: DebateConfig) -> Self {
+        Self { config }
+    }
+
+    fn transcript(proposal: &str, arguments: &[DebateArgument]) -> String {
+        let mut out = format!("Proposed answer:\n{}\n", proposal);
+        for arg in arguments {
+            out.push_str(&format!(
+                "\n[Round {}] {}:\n{}\n",
+                arg.round, arg.debater, arg.content
+            ));
+        }
+        out
+    }
+
+    async fn speak(
+        participant: &Debater,
+        memory: &AgentMemory,
+        fallback: &dyn AsyncLlmCaller,
+        system_prompt: &str,
+        prompt: String,
+        output_schema: Option<crate::types::OutputSchema>,
+    ) -> Result<(String, Option<crate::budget::TokenUsage>, String), String> {
+        let model = if participant.model.is_empty() {
+            PlanningState.resolve_model(memory)
+        } else {
+            participant.model.clone()
+        };
+        let mut request = AgentMemory::new(prompt)
+            .with_system_prompt(participant.system_prompt(system_prompt));
+        request.config.output_schema = output_schema;
+
+        let llm = participant.llm.as_deref().unwrap_or(fallback);
+        let response = llm.call_async(&request, &ToolRegistry::new(), &model, None).await?;
+        let model = response.answered_by().map(String::from).unwrap_or(model);
+        match response {
+            LlmResponse::FinalAnswer { content, usage, .. }
+            | LlmResponse::Truncated { content, usage, .. } => Ok((content, usage, model)),
+            LlmResponse::Structured { data, usage, .. } => Ok((data.to_string(), usage, model)),
+            LlmResponse::ToolCall { .. } | LlmResponse::ParallelToolCalls { .. } => {
+                Err(format!("{} requested a tool call instead of answering", participant.name))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AgentState for DebateState {
+    fn name(&self) -> &'static str { "Debating" }
+
+    async fn handle(
+        &self,
+        memory:    &mut AgentMemory,
+        _tools:    &Arc<ToolRegistry>,
+        llm:       &dyn AsyncLlmCaller,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) -> Event {
+        if let Some(tx) = output_tx {
+            let _ = tx.send(AgentOutput::StateStarted(State::new("Debating")));
+        }
+
+        let proposal = match memory.final_answer.clone() {
+            Some(a) => a,
+            None => {
+                memory.log("Debating", "DEBATE_SKIPPED", "no proposed answer");
+                return Event::debate_concluded();
+            }
+        };
+        memory.log("Debating", "DEBATE_START", &format!(
+            "debaters={} rounds={}", self.config.debaters.len(), self.config.rounds
+        ));
+
+        let mut arguments: Vec<DebateArgument> = Vec::new();
+        for round in 1..=self.config.rounds {
+            if let Some(tx) = output_tx {
+                let _ = tx.send(AgentOutput::Action(format!(
+                    "Debate round {}/{}", round, self.config.rounds
+                )));
+            }
+
+            // Debaters in a round speak concurrently and see only earlier rounds
+            let prompt = format!(
+                "Task:\n{}\n\n{}\nThis is round {} of {}. Give your argument.",
+                memory.task,
+                Self::transcript(&proposal, &arguments),
+                round,
+                self.config.rounds
+            );
+            let mem: &AgentMemory = memory;
+            let results = futures::future::join_all(self.config.debaters.iter().map(|d| {
+                Self::speak(d, mem, llm, DEBATER_SYSTEM_PROMPT, prompt.clone(), None)
+            }))
+            .await;
+
+            for (debater, result) in self.config.debaters.iter().zip(results) {
+                match result {
+                    Ok((content, usage, model)) => {
+                        if let Some(u) = usage {
+                            crate::states::report_usage(memory, &model, u, output_tx);
+                        }
+                        memory.log("Debating", "DEBATE_ARGUMENT", &format!(
+                            "round={} debater={}: {}", round, debater.name, content
+                        ));
+                        arguments.push(DebateArgument {
+                            round,
+                            debater: debater.name.clone(),
+                            content,
+                        });
+                    }
+                    Err(e) => {
+                        memory.log("Debating", "DEBATE_ARGUMENT_FAILED", &format!(
+                            "round={} debater={}: {}", round, debater.name, e
+                        ));
+                    }
+                }
+            }
+        }
+
+        let prompt = format!(
+            "Task:\n{}\n\n{}\nGive the final answer to the task.",
+            memory.task,
+            Self::transcript(&proposal, &arguments)
+        );
+        let output_schema = memory.config.output_schema.clone();
+        match Self::speak(&self.config.judge, memory, llm, JUDGE_SYSTEM_PROMPT, prompt, output_schema).await {
+            Ok((verdict, usage, model)) => {
+                if let Some(u) = usage {
+                    crate::states::report_usage(memory, &model, u, output_tx);
+                }
+                memory.log("Debating", "DEBATE_VERDICT", &format!(
+                    "judge={}: {}", self.config.judge.name, verdict
+                ));
+                memory.final_answer = Some(verdict);
+            }
+            Err(e) => {
+                memory.log("Debating", "DEBATE_JUDGE_FAILED", &format!(
+                    "judge={}: {} — keeping proposed answer", self.config.judge.name, e
+                ));
+            }
+        }
+
+        Event::debate_concluded()
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::MockLlmCaller;
+
+    fn answer(content: &str) -> LlmResponse {
+        LlmResponse::FinalAnswer { content: content.to_string(), confidence: 1.0, usage: None, model: None }
+    }
+
+    fn debater(name: &str, responses: Vec<LlmResponse>) -> (Arc<MockLlmCaller>, Debater) {
+        let mock = Arc::new(MockLlmCaller::new(responses));
+        let d = Debater::new(name, "debate-model").with_llm(mock.clone());
+        (mock, d)
+    }
+
+    #[tokio::test]
+    async fn test_judge_verdict_replaces_answer() {
+        let (pro_llm, pro) = debater("pro", vec![answer("it is 4"), answer("still 4")]);
+        let (con_llm, con) = debater("con", vec![answer("could be 5"), answer("fine, 4")]);
+        let judge_llm = MockLlmCaller::new(vec![answer("4")]);
+        let state = DebateState::new(
+            DebateConfig::new(Debater::new("judge", "judge-model"))
+                .debater(pro)
+                .debater(con)
+                .rounds(2),
+        );
+
+        let mut memory = AgentMemory::new("What is 2+2?");
+        memory.final_answer = Some("2+2 is 4".to_string());
+        let event = state
+            .handle(&mut memory, &Arc::new(ToolRegistry::new()), &judge_llm, None)
+            .await;
+
+        assert_eq!(event, Event::debate_concluded());
+        assert_eq!(memory.final_answer.as_deref(), Some("4"));
+        assert_eq!(pro_llm.call_count(), 2);
+        assert_eq!(con_llm.call_count(), 2);
+        assert_eq!(judge_llm.model_for_call(0).as_deref(), Some("judge-model"));
+
+        let arguments = memory
+            .trace
+            .entries()
+            .iter()
+            .filter(|e| e.event == "DEBATE_ARGUMENT")
+            .count();
+        assert_eq!(arguments, 4);
+        assert!(memory.trace.entries().iter().any(|e| e.event == "DEBATE_VERDICT"));
+    }
+
+    #[tokio::test]
+    async fn test_judge_failure_keeps_proposal() {
+        let (_, pro) = debater("pro", vec![answer("agree")]);
+        let judge_llm = MockLlmCaller::new(vec![]);
+        let state = DebateState::new(
+            DebateConfig::new(Debater::new("judge", "m")).debater(pro).rounds(1),
+        );
+
+        let mut memory = AgentMemory::new("task");
+        memory.final_answer = Some("proposal".to_string());
+        let event = state
+            .handle(&mut memory, &Arc::new(ToolRegistry::new()), &judge_llm, None)
+            .await;
+
+        assert_eq!(event, Event::debate_concluded());
+        assert_eq!(memory.final_answer.as_deref(), Some("proposal"));
+        assert!(memory.trace.entries().iter().any(|e| e.event == "DEBATE_JUDGE_FAILED"));
+    }
+
+    #[tokio::test]
+    async fn test_debate_usage_is_priced_and_reported() {
+        use crate::budget::{ModelPricing, TokenUsage};
+
+        let (_, pro) = debater("pro", vec![answer("agree").with_usage(TokenUsage::new(1000, 100))]);
+        let judge_llm = MockLlmCaller::new(vec![answer("verdict").with_usage(TokenUsage::new(2000, 200))]);
+        let state = DebateState::new(
+            DebateConfig::new(Debater::new("judge", "judge-model")).debater(pro).rounds(1),
+        );
+
+        let mut memory = AgentMemory::new("task");
+        memory.final_answer = Some("proposal".to_string());
+        memory.config.pricing.insert("debate-model".into(), ModelPricing::new(1.0, 1.0));
+        memory.config.pricing.insert("judge-model".into(), ModelPricing::new(10.0, 10.0));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        state
+            .handle(&mut memory, &Arc::new(ToolRegistry::new()), &judge_llm, Some(&tx))
+            .await;
+
+        let mut reported = Vec::new();
+        while let Ok(output) = rx.try_recv() {
+            if let AgentOutput::Usage { usage, .. } = output {
+                reported.push(usage.total_tokens);
+            }
+        }
+        assert_eq!(reported, [1100, 2200]);
+        assert_eq!(memory.total_usage.total_tokens, 3300);
+        // 1100 tokens at $1/M + 2200 at $10/M
+        assert!((memory.total_cost - 0.0231).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,425 @@
+//! Time-Travel Debugger — navigate a session's checkpoints as a timeline.
+//!
+//! Every checkpoint the engine saves is a point on the timeline. The
+//! debugger lets you step forward and back, inspect memory at any point,
+//! diff consecutive points, and re-run the agent from any point with a
+//! modified memory. It has no UI of its own; TUIs and GUIs are expected to
+//! drive it through this API.
+
+use crate::builder::AgentBuilder;
+use crate::budget::TokenUsage;
+use crate::checkpoint::{AgentCheckpoint, CheckpointStore};
+use crate::memory::AgentMemory;
+use crate::trace::TraceEntry;
+use crate::types::{HistoryEntry, State};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// ─────────────────────────────────────────────────────────────────────────────
+// TimelinePoint
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A lightweight summary of a single point on the timeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelinePoint {
+    pub index: usize,
+    pub checkpoint_id: String,
+    pub state: State,
+    pub step: usize,
+    pub history_len: usize,
+    pub total_tokens: u32,
+    pub timestamp: DateTime<Utc>,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// StepDiff
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Everything that changed between two points on the timeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepDiff {
+    pub from_index: usize,
+    pub to_index: usize,
+    pub from_state: State,
+    pub to_state: State,
+    pub from_step: usize,
+    pub to_step: usize,
+    /// History entries present at `to` but not at `from`.
+    pub history_added: Vec<HistoryEntry>,
+    /// Trace entries recorded between the two points.
+    pub trace_added: Vec<TraceEntry>,
+    /// Tokens consumed between the two points.
+    pub usage_delta: TokenUsage,
+    /// Set when the final answer differs between the two points.
+    pub final_answer: Option<String>,
+    /// Set when the error differs between the two points.
+    pub error: Option<String>,
+    /// True when a reflection compressed the history between the two points.
+    pub history_rewritten: bool,
+}
+
+impl StepDiff {
+    /// Returns true if nothing observable changed.
+    pub fn is_empty(&self) -> bool {
+        self.from_state == self.to_state
+            && self.from_step == self.to_step
+            && self.history_added.is_empty()
+            && self.trace_added.is_empty()
+            && self.final_answer.is_none()
+            && self.error.is_none()
+            && !self.history_rewritten
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Debugger
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A cursor over the checkpoints of one session, ordered oldest first.
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    session_id: String,
+    checkpoints: Vec<AgentCheckpoint>,
+    cursor: usize,
+}
+
+impl Debugger {
+    /// Build a debugger from an arbitrary set of checkpoints.
+    /// Checkpoints are sorted by sequence, then timestamp; the cursor starts
+    /// at the first one.
+    pub fn from_checkpoints(mut checkpoints: Vec<AgentCheckpoint>) -> Self {
+        checkpoints.sort_by_key(|c| (c.sequence, c.timestamp));
+        let session_id = checkpoints
+            .first()
+            .map(|c| c.session_id.clone())
+            .unwrap_or_default();
+        Self {
+            session_id,
+            checkpoints,
+            cursor: 0,
+        }
+    }
+
+    /// Load the full timeline of a session from a checkpoint store.
+    pub async fn load(store: &dyn CheckpointStore, session_id: &str) -> Result<Self, String> {
+        let checkpoints = store.list_checkpoints(session_id).await?;
+        let mut debugger = Self::from_checkpoints(checkpoints);
+        debugger.session_id = session_id.to_string();
+        Ok(debugger)
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub fn len(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checkpoints.is_empty()
+    }
+
+    /// Index of the point the cursor is on.
+    pub fn position(&self) -> usize {
+        self.cursor
+    }
+
+    /// Summaries of every point, suitable for rendering a timeline.
+    pub fn timeline(&self) -> Vec<TimelinePoint> {
+        self.checkpoints
+            .iter()
+            .enumerate()
+            .map(|(index, cp)| TimelinePoint {
+                index,
+                checkpoint_id: cp.checkpoint_id.clone(),
+                state: cp.state.clone(),
+                step: cp.memory.step,
+                history_len: cp.memory.history.len(),
+                total_tokens: cp.memory.total_usage.total_tokens,
+                timestamp: cp.timestamp,
+            })
+            .collect()
+    }
+
+    // ── Navigation ───────────────────────────────────────────────────────────
+
+    /// The checkpoint under the cursor.
+    pub fn current(&self) -> Option<&AgentCheckpoint> {
+        self.checkpoints.get(self.cursor)
+    }
+
+    /// Move one point forward. Returns `None` (and stays put) at the end.
+    pub fn step_forward(&mut self) -> Option<&AgentCheckpoint> {
+        if self.cursor + 1 >= self.checkpoints.len() {
+            return None;
+        }
+        self.cursor += 1;
+        self.current()
+    }
+
+    /// Move one point back. Returns `None` (and stays put) at the start.
+    pub fn step_back(&mut self) -> Option<&AgentCheckpoint> {
+        if self.cursor == 0 || self.checkpoints.is_empty() {
+            return None;
+        }
+        self.cursor -= 1;
+        self.current()
+    }
+
+    /// Jump to a specific index.
+    pub fn seek(&mut self, index: usize) -> Option<&AgentCheckpoint> {
+        if index >= self.checkpoints.len() {
+            return None;
+        }
+        self.cursor = index;
+        self.current()
+    }
+
+    /// Jump to the first point whose memory reached the given agent step.
+    pub fn seek_step(&mut self, step: usize) -> Option<&AgentCheckpoint> {
+        let index = self
+            .checkpoints
+            .iter()
+            .position(|c| c.memory.step >= step)?;
+        self.seek(index)
+    }
+
+    /// Jump to a checkpoint by ID.
+    pub fn seek_checkpoint(&mut self, checkpoint_id: &str) -> Option<&AgentCheckpoint> {
+        let index = self
+            .checkpoints
+            .iter()
+            .position(|c| c.checkpoint_id == checkpoint_id)?;
+        self.seek(index)
+    }
+
+    // ── Inspection ───────────────────────────────────────────────────────────
+
+    pub fn checkpoint_at(&self, index: usize) -> Option<&AgentCheckpoint> {
+        self.checkpoints.get(index)
+    }
+
+    pub fn memory_at(&self, index: usize) -> Option<&AgentMemory> {
+        self.checkpoints.get(index).map(|c| &c.memory)
+    }
+
+    pub fn state_at(&self, index: usize) -> Option<&State> {
+        self.checkpoints.get(index).map(|c| &c.state)
+    }
+
+    /// Diff between the point under the cursor and the one before it.
+    pub fn diff_current(&self) -> Option<StepDiff> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.diff(self.cursor - 1, self.cursor)
+    }
+
+    /// Diff between any two points.
+    pub fn diff(&self, from: usize, to: usize) -> Option<StepDiff> {
+        let a = self.checkpoints.get(from)?;
+        let b = self.checkpoints.get(to)?;
+        Some(diff_checkpoints(from, a, to, b))
+    }
+
+    // ── Re-run ───────────────────────────────────────────────────────────────
+
+    /// Prepare a re-run from the given point.
+    ///
+    /// `builder` supplies everything a checkpoint does not carry (LLM, tools,
+    /// hooks, custom states). `edit` may modify the restored memory before
+    /// the engine is built. The re-run gets the builder's own session ID, so
+    /// the original timeline is left intact.
+    pub fn rerun_from<F>(&self, index: usize, builder: AgentBuilder, edit: F) -> Option<AgentBuilder>
+    where
+        F: FnOnce(&mut AgentMemory),
+    {
+        let mut checkpoint = self.checkpoints.get(index)?.clone();
+        edit(&mut checkpoint.memory);
+        Some(builder.from_checkpoint(checkpoint))
+    }
+}
+
+/// Compute the difference between two checkpoints.
+pub fn diff_checkpoints(
+    from_index: usize,
+    from: &AgentCheckpoint,
+    to_index: usize,
+    to: &AgentCheckpoint,
+) -> StepDiff {
+    let (a, b) = (&from.memory, &to.memory);
+
+    // History is append-only except for reflection, which replaces it.
+    let history_rewritten = b.history.len() < a.history.len()
+        || a
+            .history
+            .iter()
+            .zip(b.history.iter())
+            .any(|(x, y)| x.step != y.step || x.tool.name != y.tool.name);
+    let history_added = if history_rewritten {
+        b.history.clone()
+    } else {
+        b.history[a.history.len()..].to_vec()
+    };
+
+    let trace_added = b
+        .trace
+        .entries()
+        .get(a.trace.len()..)
+        .map(|e| e.to_vec())
+        .unwrap_or_default();
+
+    let usage_delta = TokenUsage {
+        input_tokens: b.total_usage.input_tokens.saturating_sub(a.total_usage.input_tokens),
+        output_tokens: b.total_usage.output_tokens.saturating_sub(a.total_usage.output_tokens),
+        total_tokens: b.total_usage.total_tokens.saturating_sub(a.total_usage.total_tokens),
+    };
+
+    StepDiff {
+        from_index,
+        to_index,
+        from_state: from.state.clone(),
+        to_state: to.state.clone(),
+        from_step: a.step,
+        to_step: b.step,
+        history_added,
+        trace_added,
+        usage_delta,
+        final_answer: if a.final_answer != b.final_answer { b.final_answer.clone() } else { None },
+        error: if a.error != b.error { b.error.clone() } else { None },
+        history_rewritten,
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint::MemoryCheckpointStore;
+    use crate::types::{Observation, ToolCall};
+    use std::collections::HashMap;
+
+    fn make_checkpoint(step: usize, state: State, history: usize, offset_ms: i64) -> AgentCheckpoint {
+        let mut memory = AgentMemory::new("debug me");
+        memory.step = step;
+        for i in 0..history {
+            memory.history.push(HistoryEntry {
+                step: i + 1,
+                tool: ToolCall {
+                    name: format!("tool_{}", i),
+                    args: HashMap::new(),
+                    id: None,
+                },
+                observation: Observation::success("ok"),
+                assistant_text: None,
+                latency_ms: None,
+                model_used: None,
+                usage: None,
+            });
+            memory.log("Observing", "TOOL_SUCCESS", "ok");
+        }
+        memory.total_usage = TokenUsage::new(10 * step as u32, 5 * step as u32);
+        AgentCheckpoint {
+            checkpoint_id: format!("cp-{}", offset_ms),
+            session_id: "s1".to_string(),
+            state,
+            memory,
+            timestamp: chrono::DateTime::from_timestamp_millis(1_700_000_000_000 + offset_ms)
+                .unwrap(),
+            graph: None,
+            trace_cursor: None,
+            memory_from_trace: false,
+            sequence: 0,
+            schema_version: crate::checkpoint::CHECKPOINT_SCHEMA_VERSION,
+        }
+    }
+
+    fn make_debugger() -> Debugger {
+        Debugger::from_checkpoints(vec![
+            make_checkpoint(1, State::acting(), 0, 2),
+            make_checkpoint(0, State::planning(), 0, 1),
+            make_checkpoint(1, State::observing(), 1, 3),
+        ])
+    }
+
+    #[test]
+    fn test_sorted_by_timestamp() {
+        let dbg = make_debugger();
+        assert_eq!(dbg.len(), 3);
+        assert_eq!(dbg.session_id(), "s1");
+        assert_eq!(dbg.state_at(0), Some(&State::planning()));
+        assert_eq!(dbg.state_at(2), Some(&State::observing()));
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut dbg = make_debugger();
+        assert_eq!(dbg.position(), 0);
+        assert!(dbg.step_back().is_none());
+        assert_eq!(dbg.step_forward().unwrap().state, State::acting());
+        assert_eq!(dbg.step_forward().unwrap().state, State::observing());
+        assert!(dbg.step_forward().is_none());
+        assert_eq!(dbg.position(), 2);
+        assert_eq!(dbg.step_back().unwrap().state, State::acting());
+        assert!(dbg.seek(10).is_none());
+        assert_eq!(dbg.seek_checkpoint("cp-1").unwrap().state, State::planning());
+        assert_eq!(dbg.seek_step(1).unwrap().state, State::acting());
+    }
+
+    #[test]
+    fn test_diff_consecutive() {
+        let mut dbg = make_debugger();
+        dbg.seek(2);
+        let diff = dbg.diff_current().unwrap();
+        assert_eq!(diff.from_state, State::acting());
+        assert_eq!(diff.to_state, State::observing());
+        assert_eq!(diff.history_added.len(), 1);
+        assert_eq!(diff.trace_added.len(), 1);
+        assert!(!diff.history_rewritten);
+        assert_eq!(diff.usage_delta.total_tokens, 0);
+
+        let diff = dbg.diff(0, 1).unwrap();
+        assert_eq!(diff.usage_delta.total_tokens, 15);
+    }
+
+    #[test]
+    fn test_diff_detects_rewrite() {
+        let dbg = make_debugger();
+        let diff = dbg.diff(2, 0).unwrap();
+        assert!(diff.history_rewritten);
+        assert!(diff.history_added.is_empty());
+    }
+
+    #[test]
+    fn test_timeline() {
+        let dbg = make_debugger();
+        let timeline = dbg.timeline();
+        assert_eq!(timeline.len(), 3);
+        assert_eq!(timeline[2].history_len, 1);
+        assert_eq!(timeline[1].total_tokens, 15);
+    }
+
+    #[test]
+    fn test_rerun_from_applies_edit() {
+        let dbg = make_debugger();
+        let builder = AgentBuilder::new("ignored");
+        let rerun = dbg.rerun_from(1, builder, |m| m.task = "edited".to_string());
+        assert!(rerun.is_some());
+        assert!(dbg.rerun_from(99, AgentBuilder::new("x"), |_| {}).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_load_from_store() {
+        let store = MemoryCheckpointStore::new();
+        for cp in make_debugger().checkpoints {
+            store.save(cp).await.unwrap();
+        }
+        let dbg = Debugger::load(&store, "s1").await.unwrap();
+        assert_eq!(dbg.len(), 3);
+        assert!(Debugger::load(&store, "missing").await.unwrap().is_empty());
+    }
+}
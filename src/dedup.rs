@@ -0,0 +1,349 @@
+//! Observation Dedup — collapse repeated tool results in history.
+//!
+//! A model that keeps making the same failing call adds the same
+//! observation to history again and again. That wastes tokens and makes the
+//! repetition look normal. With an `ObservationDedup` configured, an
+//! observation that matches one of the last `window` entries (same tool,
+//! same text) replaces that entry instead of adding a new one. The text is
+//! marked `[repeated N times]`, and the entry moves to the end of history so
+//! the model still sees the latest result last.
+//!
+//! With `normalize` on, observations are compared after lowercasing,
+//! collapsing whitespace and masking digits, so results that differ only in
+//! a timestamp or a request id count as repeats.
+//!
+//! [`SemanticDedup`] works one stage earlier, on tool calls. It embeds each
+//! requested call and compares it with earlier calls to the same tool. A
+//! call that means the same thing as an earlier one ("rust lang" vs "rust
+//! language") is not run; the model is reminded of the earlier result and
+//! asked again within the same step.
+
+use crate::locale::ObservationMarkers;
+use crate::embedding::{cosine_similarity, EmbeddingProvider};
+use crate::memory::AgentMemory;
+use crate::types::{HistoryEntry, ToolCall};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// Configuration of observation dedup. Set with `AgentBuilder::observation_dedup`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObservationDedup {
+    /// How many recent history entries to compare against
+    pub window: usize,
+    /// Compare a normalized form instead of the exact text
+    #[serde(default)]
+    pub normalize: bool,
+}
+
+impl Default for ObservationDedup {
+    fn default() -> Self {
+        Self {
+            window: 5,
+            normalize: false,
+        }
+    }
+}
+
+impl ObservationDedup {
+    /// Exact matching against the last 5 entries.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn window(mut self, entries: usize) -> Self {
+        self.window = entries;
+        self
+    }
+
+    /// Treat observations that differ only in case, whitespace or digits as repeats.
+    pub fn normalize(mut self, enabled: bool) -> Self {
+        self.normalize = enabled;
+        self
+    }
+
+    /// Hash used to compare observations, ignoring any repeat marker.
+    pub fn fingerprint(&self, observation: &str) -> u64 {
+        let (text, _) = split_marker(observation);
+        let mut hasher = DefaultHasher::new();
+        if self.normalize {
+            normalized(text).hash(&mut hasher);
+        } else {
+            text.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Index of the most recent entry within the window that `entry` repeats.
+    pub fn find_repeat(&self, history: &[HistoryEntry], entry: &HistoryEntry) -> Option<usize> {
+        let key = self.fingerprint(&entry.observation.content);
+        let start = history.len().saturating_sub(self.window);
+        (start..history.len()).rev().find(|&i| {
+            history[i].tool.name == entry.tool.name
+                && history[i].observation.status == entry.observation.status
+                && self.fingerprint(&history[i].observation.content) == key
+        })
+    }
+}
+
+const MARKER_PREFIX: &str = "\n[repeated ";
+const MARKER_SUFFIX: &str = " times]";
+
+/// Split `text` into the observation and its repeat count (1 if unmarked).
+pub fn split_marker(text: &str) -> (&str, usize) {
+    if let Some(pos) = text.rfind(MARKER_PREFIX) {
+        let tail = &text[pos + MARKER_PREFIX.len()..];
+        if let Some(n) = tail.strip_suffix(MARKER_SUFFIX).and_then(|n| n.parse().ok()) {
+            return (&text[..pos], n);
+        }
+    }
+    (text, 1)
+}
+
+fn normalized(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = ' ';
+    for c in text.trim().chars() {
+        let c = if c.is_ascii_digit() {
+            '#'
+        } else if c.is_whitespace() {
+            ' '
+        } else {
+            c.to_ascii_lowercase()
+        };
+        if c != last || !matches!(c, ' ' | '#') {
+            out.push(c);
+        }
+        last = c;
+    }
+    out
+}
+
+/// Append `entry` to history, collapsing it into a recent repeat when dedup is on.
+pub(crate) fn push_history(memory: &mut AgentMemory, mut entry: HistoryEntry) {
+    if let Some(dedup) = &memory.config.observation_dedup {
+        if let Some(idx) = dedup.find_repeat(&memory.history, &entry) {
+            let previous = memory.history.remove(idx);
+            let (_, count) = split_marker(&previous.observation.content);
+            let (text, _) = split_marker(&entry.observation.content);
+            entry.observation.content =
+                format!("{}{}{}{}", text, MARKER_PREFIX, count + 1, MARKER_SUFFIX).into();
+            memory.log(
+                "Observing",
+                "OBSERVATION_DEDUPED",
+                &format!("tool='{}' step={} repeats={}", entry.tool.name, previous.step, count + 1),
+            );
+        }
+    }
+    memory.history.push(entry);
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Semantic repeats of tool calls
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Catches tool calls that repeat an earlier call with different arguments
+/// but the same intent. Set with `AgentBuilder::semantic_dedup`.
+#[derive(Clone)]
+pub struct SemanticDedup {
+    provider: Arc<dyn EmbeddingProvider>,
+    /// Similarity at or above which two calls count as the same (default 0.92)
+    pub threshold: f32,
+    /// Nudges per step; after that the call runs anyway (default 1)
+    pub max_nudges: usize,
+    /// Embeddings of call texts seen so far, so each is embedded once
+    cache: Arc<Mutex<HashMap<String, Vec<f32>>>>,
+}
+
+impl std::fmt::Debug for SemanticDedup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SemanticDedup")
+            .field("threshold", &self.threshold)
+            .field("max_nudges", &self.max_nudges)
+            .finish()
+    }
+}
+
+impl SemanticDedup {
+    pub fn new(provider: Arc<dyn EmbeddingProvider>) -> Self {
+        Self {
+            provider,
+            threshold: 0.92,
+            max_nudges: 1,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn threshold(mut self, similarity: f32) -> Self {
+        self.threshold = similarity;
+        self
+    }
+
+    pub fn max_nudges(mut self, n: usize) -> Self {
+        self.max_nudges = n;
+        self
+    }
+
+    /// The earlier entry that `call` repeats, and how similar the two are.
+    /// Only calls to the same tool are compared.
+    pub async fn find_repeat<'a>(
+        &self,
+        history: &'a [HistoryEntry],
+        call: &ToolCall,
+    ) -> Result<Option<(&'a HistoryEntry, f32)>, String> {
+        let earlier: Vec<&HistoryEntry> =
+            history.iter().filter(|e| e.tool.name == call.name).collect();
+        if earlier.is_empty() {
+            return Ok(None);
+        }
+        let mut texts: Vec<String> = earlier.iter().map(|e| call_text(&e.tool)).collect();
+        texts.push(call_text(call));
+        let vectors = self.embed_cached(&texts).await?;
+        let Some((target, past)) = vectors.split_last() else {
+            return Ok(None);
+        };
+        let best = past
+            .iter()
+            .zip(earlier)
+            .map(|(v, e)| (e, cosine_similarity(v, target)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        Ok(best.filter(|(_, similarity)| *similarity >= self.threshold))
+    }
+
+    async fn embed_cached(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let mut missing: Vec<String> = {
+            let cache = self.cache.lock().unwrap();
+            texts.iter().filter(|t| !cache.contains_key(*t)).cloned().collect()
+        };
+        missing.sort();
+        missing.dedup();
+        if !missing.is_empty() {
+            let vectors = self.provider.embed(&missing).await?;
+            let mut cache = self.cache.lock().unwrap();
+            cache.extend(missing.into_iter().zip(vectors));
+        }
+        let cache = self.cache.lock().unwrap();
+        Ok(texts.iter().map(|t| cache.get(t).cloned().unwrap_or_default()).collect())
+    }
+}
+
+/// Text embedded for a call: the tool name and its arguments with sorted keys.
+pub fn call_text(call: &ToolCall) -> String {
+    let args: BTreeMap<&String, &serde_json::Value> = call.args.iter().collect();
+    format!("{} {}", call.name, serde_json::to_string(&args).unwrap_or_default())
+}
+
+/// Reminder sent to the model instead of running a repeated call.
+pub(crate) fn repeat_note(entry: &HistoryEntry, markers: &ObservationMarkers) -> String {
+    let rendered = entry.observation.render(markers);
+    let (observation, _) = split_marker(&rendered);
+    let observation: String = observation.chars().take(500).collect();
+    format!(
+        "You already called '{}' with {} at step {}. The result was:\n{}\n\
+         Use that result, try a different approach, or give your final answer.",
+        entry.tool.name,
+        serde_json::to_string(&entry.tool.args).unwrap_or_default(),
+        entry.step,
+        observation
+    )
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Observation, ToolCall};
+    use std::collections::HashMap;
+
+    fn entry(step: usize, tool: &str, obs: &str) -> HistoryEntry {
+        HistoryEntry {
+            step,
+            tool: ToolCall { name: tool.into(), args: HashMap::new(), id: None },
+            observation: Observation::parse_legacy(obs),
+            assistant_text: None,
+            latency_ms: None,
+            model_used: None,
+            usage: None,
+        }
+    }
+
+    #[test]
+    fn test_repeats_collapse_with_count() {
+        let mut memory = AgentMemory::new("t");
+        memory.config.observation_dedup = Some(ObservationDedup::new());
+
+        push_history(&mut memory, entry(1, "fetch", "ERROR: 503"));
+        push_history(&mut memory, entry(2, "search", "SUCCESS: hits"));
+        push_history(&mut memory, entry(3, "fetch", "ERROR: 503"));
+        push_history(&mut memory, entry(4, "fetch", "ERROR: 503"));
+
+        assert_eq!(memory.history.len(), 2);
+        assert_eq!(memory.history[0].tool.name, "search");
+        assert_eq!(memory.history[1].step, 4);
+        assert_eq!(&*memory.history[1].observation.content, "503\n[repeated 3 times]");
+    }
+
+    #[test]
+    fn test_normalize_ignores_digits_and_whitespace() {
+        let dedup = ObservationDedup::new().normalize(true);
+        assert_eq!(
+            dedup.fingerprint("ERROR: timeout at 12:01:07  (req 4411)"),
+            dedup.fingerprint("error: timeout at 9:15:00 (req 12)")
+        );
+        assert_ne!(
+            ObservationDedup::new().fingerprint("ERROR: 1"),
+            ObservationDedup::new().fingerprint("ERROR: 2")
+        );
+    }
+
+    #[test]
+    fn test_window_and_tool_name_limit_matches() {
+        let dedup = ObservationDedup::new().window(1);
+        let history = vec![entry(1, "fetch", "ERROR: x"), entry(2, "search", "ERROR: x")];
+        assert_eq!(dedup.find_repeat(&history, &entry(3, "fetch", "ERROR: x")), None);
+        assert_eq!(dedup.find_repeat(&history, &entry(3, "search", "ERROR: x")), Some(1));
+    }
+
+    /// One dimension per known word; unknown words are ignored.
+    struct Words;
+
+    #[async_trait::async_trait]
+    impl EmbeddingProvider for Words {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+            let vocab = ["rust", "lang", "language", "python", "search"];
+            Ok(texts
+                .iter()
+                .map(|t| {
+                    let t = t.replace("language", "lang");
+                    vocab.iter().map(|w| t.matches(w).count() as f32).collect()
+                })
+                .collect())
+        }
+    }
+
+    fn call_entry(step: usize, query: &str) -> HistoryEntry {
+        let mut e = entry(step, "search", "SUCCESS: hits");
+        e.tool.args.insert("q".into(), serde_json::json!(query));
+        e
+    }
+
+    #[tokio::test]
+    async fn test_semantic_repeat_matches_same_intent() {
+        let dedup = SemanticDedup::new(Arc::new(Words));
+        let history = vec![call_entry(1, "rust lang"), entry(2, "fetch", "SUCCESS: page")];
+
+        let repeat = call_entry(3, "rust language").tool;
+        let (found, similarity) = dedup.find_repeat(&history, &repeat).await.unwrap().unwrap();
+        assert_eq!(found.step, 1);
+        assert!(similarity > 0.99);
+
+        let different = call_entry(3, "python").tool;
+        assert!(dedup.find_repeat(&history, &different).await.unwrap().is_none());
+        assert!(repeat_note(found, &ObservationMarkers::english()).contains("at step 1"));
+    }
+}
@@ -0,0 +1,117 @@
+//! Plan Mode — dry-run side-effecting tools, then apply after approval.
+//!
+//! Tools flagged with `Tool::mutating(true)` (or
+//! `ToolRegistry::set_mutating`) change the outside world. In
+//! `ExecutionMode::Plan` they are not run: the agent receives a description
+//! of what the call would do, and the call is recorded in
+//! `AgentMemory::planned_actions`. Read-only tools still run, so the agent
+//! can look around before it proposes changes.
+//!
+//! Once a human approves `AgentEngine::change_plan()`, call
+//! `AgentEngine::apply()`. It re-runs the task in `ExecutionMode::Apply`,
+//! with the approved actions in the prompt.
+
+use crate::memory::AgentMemory;
+use crate::tools::ToolRegistry;
+use crate::types::ToolCall;
+use serde::{Deserialize, Serialize};
+
+/// Whether side-effecting tools are run or only described.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecutionMode {
+    /// Run every tool (the default)
+    #[default]
+    Apply,
+    /// Simulate mutating tools and record them as planned actions
+    Plan,
+}
+
+/// What a plan-mode run would change, for review before `apply()`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangePlan {
+    /// Mutating tool calls the agent made, in order
+    pub actions: Vec<ToolCall>,
+    /// The agent's final answer from the plan run
+    pub summary: Option<String>,
+}
+
+impl ChangePlan {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// The approved plan as given to the apply run.
+    pub fn to_prompt(&self) -> String {
+        let mut out = String::from(
+            "A human reviewed and approved the following changes. Carry them out now:",
+        );
+        for (i, action) in self.actions.iter().enumerate() {
+            out.push_str(&format!(
+                "\n{}. {}({})",
+                i + 1,
+                action.name,
+                serde_json::to_string(&action.args).unwrap_or_default()
+            ));
+        }
+        if let Some(summary) = &self.summary {
+            out.push_str(&format!("\n\nPlan summary: {}", summary));
+        }
+        out
+    }
+}
+
+/// Whether this call must be simulated rather than run.
+pub(crate) fn should_simulate(memory: &AgentMemory, tools: &ToolRegistry, name: &str) -> bool {
+    memory.execution_mode == ExecutionMode::Plan && tools.is_mutating(name)
+}
+
+/// The observation returned in place of running a mutating tool.
+pub(crate) fn simulate(call: &ToolCall) -> String {
+    format!(
+        "[PLAN MODE] Not executed. Would call '{}' with arguments {}. \
+         Continue as if it succeeded and finish with a summary of the planned changes.",
+        call.name,
+        serde_json::to_string(&call.args).unwrap_or_default()
+    )
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::Tool;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_only_mutating_tools_simulated_in_plan_mode() {
+        let mut tools = ToolRegistry::new();
+        tools.register_tool(Tool::new("read", "Read").call(|_| Ok("r".into())));
+        tools.register_tool(Tool::new("write", "Write").mutating(true).call(|_| Ok("w".into())));
+
+        let mut memory = AgentMemory::new("task");
+        assert!(!should_simulate(&memory, &tools, "write"));
+
+        memory.execution_mode = ExecutionMode::Plan;
+        assert!(should_simulate(&memory, &tools, "write"));
+        assert!(!should_simulate(&memory, &tools, "read"));
+        assert!(!should_simulate(&memory, &tools, "unknown"));
+    }
+
+    #[test]
+    fn test_plan_prompt_lists_actions() {
+        let plan = ChangePlan {
+            actions: vec![ToolCall {
+                name: "write_file".into(),
+                args: HashMap::from([("path".to_string(), serde_json::json!("a.txt"))]),
+                id: None,
+            }],
+            summary: Some("Create a.txt".into()),
+        };
+        let prompt = plan.to_prompt();
+        assert!(prompt.contains("1. write_file({\"path\":\"a.txt\"})"));
+        assert!(prompt.contains("Plan summary: Create a.txt"));
+    }
+}
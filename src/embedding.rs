@@ -0,0 +1,127 @@
+//! Embedding providers — text to vectors for similarity checks.
+//!
+//! An [`EmbeddingProvider`] turns strings into vectors. Vectors of texts with
+//! the same meaning point in similar directions, which
+//! [`cosine_similarity`] measures. `SemanticDedup` uses this to spot tool
+//! calls that repeat an earlier one with different wording.
+//!
+//! `OpenAiEmbeddings` calls any OpenAI-compatible `/embeddings` endpoint,
+//! including Ollama and llama.cpp's server. Implement the trait to use a
+//! local model or another API.
+
+use async_trait::async_trait;
+
+/// Turns text into embedding vectors.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// One vector per input, in the same order.
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String>;
+}
+
+/// Cosine similarity of two vectors, in `[-1, 1]`. 0 for empty or mismatched vectors.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// OpenAI-compatible provider
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Embeddings from an OpenAI-compatible `/embeddings` endpoint.
+pub struct OpenAiEmbeddings {
+    client:   reqwest::Client,
+    api_base: String,
+    api_key:  String,
+    model:    String,
+}
+
+impl OpenAiEmbeddings {
+    pub fn new(api_base: impl Into<String>, api_key: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            client:   reqwest::Client::new(),
+            api_base: api_base.into(),
+            api_key:  api_key.into(),
+            model:    model.into(),
+        }
+    }
+
+    /// OpenAI `text-embedding-3-small`. An empty key falls back to `OPENAI_API_KEY`.
+    pub fn openai(api_key: impl Into<String>) -> Self {
+        let key = api_key.into();
+        let key = if key.is_empty() {
+            std::env::var("OPENAI_API_KEY").unwrap_or_default()
+        } else {
+            key
+        };
+        Self::new("https://api.openai.com/v1", key, "text-embedding-3-small")
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiEmbeddings {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let resp = self
+            .client
+            .post(format!("{}/embeddings", self.api_base))
+            .bearer_auth(&self.api_key)
+            .json(&serde_json::json!({ "model": self.model, "input": texts }))
+            .send()
+            .await
+            .map_err(|e| format!("Network error: {}", e))?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(format!("API error {}: {}", status, body));
+        }
+        let body: serde_json::Value = resp
+            .json()
+            .await
+            .map_err(|e| format!("Invalid embeddings response: {}", e))?;
+        let data = body["data"]
+            .as_array()
+            .ok_or_else(|| "Embeddings response has no data".to_string())?;
+        if data.len() != texts.len() {
+            return Err(format!("Expected {} embeddings, got {}", texts.len(), data.len()));
+        }
+        let mut out = vec![Vec::new(); texts.len()];
+        for (i, item) in data.iter().enumerate() {
+            let index = item["index"].as_u64().map(|n| n as usize).unwrap_or(i);
+            let vector = item["embedding"]
+                .as_array()
+                .ok_or_else(|| "Embedding is not an array".to_string())?
+                .iter()
+                .map(|v| v.as_f64().unwrap_or(0.0) as f32)
+                .collect();
+            if let Some(slot) = out.get_mut(index) {
+                *slot = vector;
+            }
+        }
+        Ok(out)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+}
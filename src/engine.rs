@@ -1,23 +1,30 @@
-use crate::checkpoint::{AgentCheckpoint, CheckpointStore};
+use crate::checkpoint::CheckpointStore;
 use crate::contracts::{ContractSet, ContractViolationAction};
 use crate::error::AgentError;
 use crate::events::Event;
+use crate::handle::{AgentHandle, AgentStatus};
 use crate::hooks::{safe_hook, AgentHooks};
 use crate::llm::AsyncLlmCaller;
 use crate::memory::AgentMemory;
+use crate::notify::NotificationKind;
 use crate::states::AgentState;
-use crate::tools::ToolRegistry;
+use crate::tools::SharedToolRegistry;
 use crate::trace::Trace;
 use crate::transitions::TransitionTable;
-use crate::types::{AgentOutput, State};
+use crate::types::{AgentOutput, RunResult, State};
 use futures::stream::BoxStream;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
+/// Receives the outputs of a run started with `AgentEngine::spawn`.
+pub type OutputReceiver = mpsc::UnboundedReceiver<AgentOutput>;
+
 pub struct AgentEngine {
     pub memory: AgentMemory,
-    pub tools: Arc<ToolRegistry>,
+    /// Tools, changeable while the agent runs; each state handler sees a
+    /// snapshot taken as it starts (see [`SharedToolRegistry`])
+    pub tools: SharedToolRegistry,
     pub llm: Arc<dyn AsyncLlmCaller>,
     pub state: State,
     transitions: TransitionTable,
@@ -30,13 +37,34 @@ pub struct AgentEngine {
     pub introspection: Option<crate::introspection::IntrospectionEngine>,
     pub healing_policy: Option<crate::healing::HealingPolicy>,
     pub fork_config: Option<crate::fork::ForkConfig>,
+    pub config_watcher: Option<crate::hot_reload::ConfigWatcher>,
+    /// Directory shared by this session's tools, cleaned up after each run
+    pub workspace: Option<Arc<crate::workspace::Workspace>>,
+    /// Trace entries already in the checkpoint store's trace log when the
+    /// engine was built
+    pub(crate) trace_persisted: usize,
+    /// Saves checkpoints off the step loop; started by the first save
+    checkpoint_writer: Option<crate::checkpoint_writer::CheckpointWriter>,
+    /// Trace entries already handed to `checkpoint_writer`
+    trace_sent: usize,
+    /// Memory as of the last `MEMORY_INIT` / `MEMORY_DELTA` trace entry
+    memory_snapshot: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Trace entries included in each `AgentStatus` snapshot
+    pub status_trace_len: usize,
+    status_tx: tokio::sync::watch::Sender<AgentStatus>,
+    /// Hints sent through `AgentHandle::hint`, moved into memory between steps
+    hint_tx: mpsc::UnboundedSender<String>,
+    hint_rx: mpsc::UnboundedReceiver<String>,
+    /// Set by `shutdown_gracefully`; checked before every transition
+    shutdown: crate::cancel::ShutdownSignal,
 }
 
 impl AgentEngine {
     /// Creates a new engine. Prefer using AgentBuilder for ergonomic construction.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         memory: AgentMemory,
-        tools: Arc<ToolRegistry>,
+        tools: impl Into<SharedToolRegistry>,
         llm: Arc<dyn AsyncLlmCaller>,
         transitions: TransitionTable,
         handlers: HashMap<String, Arc<dyn AgentState>>,
@@ -49,9 +77,10 @@ impl AgentEngine {
         healing_policy: Option<crate::healing::HealingPolicy>,
         fork_config: Option<crate::fork::ForkConfig>,
     ) -> Self {
+        let (hint_tx, hint_rx) = mpsc::unbounded_channel();
         Self {
             memory,
-            tools,
+            tools: tools.into(),
             llm,
             state: State::idle(),
             transitions,
@@ -64,37 +93,200 @@ impl AgentEngine {
             introspection,
             healing_policy,
             fork_config,
+            config_watcher: None,
+            workspace: None,
+            trace_persisted: 0,
+            checkpoint_writer: None,
+            trace_sent: 0,
+            memory_snapshot: None,
+            status_trace_len: 20,
+            status_tx: tokio::sync::watch::channel(AgentStatus::default()).0,
+            hint_tx,
+            hint_rx,
+            shutdown: crate::cancel::ShutdownSignal::new(),
         }
     }
 
+    /// Run the agent to completion and return the answer together with
+    /// the sources tools attached along the way.
+    pub async fn run_detailed(&mut self) -> Result<RunResult, AgentError> {
+        let answer = self.run().await?;
+        Ok(RunResult {
+            answer,
+            citations: self.memory.citations.clone(),
+        })
+    }
+
+    /// Run the agent to completion and parse the answer into `T`.
+    ///
+    /// The JSON schema of `T` is the output schema for this run: providers
+    /// with structured output decode against it, and the system prompt
+    /// spells it out for the others. An answer that does not deserialize
+    /// into `T` goes back to Planning with the error, up to
+    /// `max_answer_revisions` times. The output schema set on the builder,
+    /// if any, is restored afterwards.
+    ///
+    /// ```rust,ignore
+    /// #[derive(Deserialize, JsonSchema)]
+    /// struct Release { version: String, date: String }
+    ///
+    /// let release: Release = engine.run_typed().await?;
+    /// ```
+    pub async fn run_typed<T>(&mut self) -> Result<T, AgentError>
+    where
+        T: serde::de::DeserializeOwned + schemars::JsonSchema + 'static,
+    {
+        let root = schemars::schema_for!(T);
+        let schema = crate::types::OutputSchema {
+            name: T::schema_name(),
+            description: root.schema.metadata.as_ref().and_then(|m| m.description.clone()),
+            schema: serde_json::to_value(&root).map_err(|e| AgentError::BuildError(e.to_string()))?,
+            strict: false,
+        };
+        let name = schema.name.clone();
+        let check: crate::memory::AnswerCheck = Arc::new(|value: &serde_json::Value| {
+            serde_json::from_value::<T>(value.clone()).map(drop).map_err(|e| e.to_string())
+        });
+
+        let previous_schema = self.memory.config.output_schema.replace(schema);
+        let previous_check = self.memory.answer_check.replace(check);
+        let result = self.run().await;
+        self.memory.config.output_schema = previous_schema;
+        self.memory.answer_check = previous_check;
+
+        let answer = result?;
+        serde_json::from_str(&answer).map_err(|e| {
+            AgentError::AgentFailed(format!("Final answer is not a valid '{}': {}", name, e))
+        })
+    }
+
     /// Run the agent to completion asynchronously.
     /// Returns Ok(final_answer) or Err(AgentError).
     pub async fn run(&mut self) -> Result<String, AgentError> {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        self.run_with_output(&tx).await
+    }
+
+    /// Move the engine onto a tokio task and run it there.
+    ///
+    /// Returns a handle for live status, the receiver of every
+    /// `AgentOutput`, and the task's `JoinHandle`. Abort the `JoinHandle`
+    /// to cancel the run.
+    pub fn spawn(
+        mut self,
+    ) -> (
+        AgentHandle,
+        OutputReceiver,
+        tokio::task::JoinHandle<Result<RunResult, AgentError>>,
+    ) {
+        let handle = self.handle();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let join = tokio::spawn(async move {
+            let answer = self.run_with_output(&tx).await?;
+            Ok(RunResult {
+                answer,
+                citations: self.memory.citations.clone(),
+            })
+        });
+        (handle, rx, join)
+    }
+
+    async fn run_with_output(
+        &mut self,
+        tx: &mpsc::UnboundedSender<AgentOutput>,
+    ) -> Result<String, AgentError> {
+        // A run dropped before it returns (aborted task, timeout) cancels
+        // the token, so tools still running on blocking threads can stop
+        let guard = self.memory.cancel.clone().drop_guard();
+        let result = self.run_to_end(tx).await;
+        self.finish_run(&result).await;
+        guard.disarm();
+        result
+    }
+
+    /// What every run does before its first step, streamed or not.
+    fn start_run(&mut self) {
         // Inject hooks into memory so state handlers can access them
         self.memory.hooks = self.hooks.clone();
-
-        let (tx, _rx) = mpsc::unbounded_channel();
-        let safety_cap = self.memory.config.max_steps * 3;
-        let mut iterations = 0;
-        let mut postcondition_retries = 0;
-        let max_postcondition_retries = 3;
+        if let Some(workspace) = &self.workspace {
+            if let Err(e) = workspace.prepare() {
+                tracing::warn!(path = %workspace.path().display(), error = %e, "Workspace unavailable");
+            }
+            self.memory.workspace = Some(Arc::clone(workspace));
+        }
 
         // Hook: agent start
         let hooks = self.hooks.clone();
         let task = self.memory.task.clone();
         safe_hook(|| hooks.on_agent_start(&task, &self.memory));
 
+        self.resume_if_suspended();
+
+        if let Some(seed) = self.memory.config.deterministic_seed {
+            let data = format!("seed={} config_hash={}", seed, self.config_fingerprint().hash);
+            self.memory.log("Engine", "DETERMINISTIC", &data);
+        }
+    }
+
+    /// What every run does once it has its result, streamed or not.
+    async fn finish_run(&mut self, result: &Result<String, AgentError>) {
+        // A suspended or shut-down run continues later and keeps its files
+        if !matches!(result, Err(AgentError::Suspended(_) | AgentError::ShutDown { .. })) {
+            if let Some(workspace) = &self.workspace {
+                if workspace.finish(result.is_ok()) {
+                    let data = format!("path={}", workspace.path().display());
+                    self.memory.log("Engine", "WORKSPACE_REMOVED", &data);
+                }
+            }
+        }
+
+        // Hook: agent end
+        let hooks = self.hooks.clone();
+        match result {
+            Ok(answer) => safe_hook(|| hooks.on_agent_end(Ok(answer.as_str()), &self.memory)),
+            Err(e) => safe_hook(|| hooks.on_agent_end(Err(e), &self.memory)),
+        }
+
+        // However the run ended, its checkpoints are in the store before it returns;
+        // failed writes were already logged
+        let _ = self.flush_checkpoints().await;
+        let kind = match result {
+            Ok(answer) => Some(NotificationKind::RunCompleted { answer: answer.clone() }),
+            // A suspended run has not ended; it continues when resumed
+            Err(AgentError::Suspended(_)) => None,
+            Err(e) => Some(NotificationKind::RunFailed { error: e.to_string() }),
+        };
+        if let Some(kind) = kind {
+            crate::notify::send(&mut self.memory, "Engine", kind).await;
+        }
+        crate::hooks::run_completion_hooks(&mut self.memory, result).await;
+        // Tell `shutdown_gracefully` callers the run has stopped
+        if self.shutdown.is_requested() {
+            self.shutdown.mark_stopped();
+        }
+    }
+
+    async fn run_to_end(
+        &mut self,
+        tx: &mpsc::UnboundedSender<AgentOutput>,
+    ) -> Result<String, AgentError> {
+        self.start_run();
+
+        let safety_cap = self.memory.config.max_steps * 3;
+        let mut iterations = 0;
+        let mut postcondition_retries = 0;
+        let max_postcondition_retries = 3;
+
         'outer: loop {
             while !self.terminal_states.contains(self.state.as_str()) {
                 iterations += 1;
                 if iterations > safety_cap {
-                    let err = AgentError::SafetyCapExceeded(iterations);
-                    let hooks = self.hooks.clone();
-                    safe_hook(|| hooks.on_agent_end(Err(&err), &self.memory));
-                    return Err(err);
+                    return Err(AgentError::SafetyCapExceeded(iterations));
                 }
 
-                self.step(&tx).await?;
+                self.stop_if_shutting_down()?;
+                self.suspend_if_waiting()?;
+                self.advance(tx).await?;
 
                 // Contract: check invariants after every step
                 if let Some(failure) = self.contracts.check_invariants(&self.memory) {
@@ -151,7 +343,12 @@ impl AgentEngine {
             break; // All postconditions pass (or no postconditions) — exit loop
         }
 
-        let result = if self.state == State::done() {
+        self.terminal_result()
+    }
+
+    /// The answer, or the error, of a run that reached a terminal state.
+    fn terminal_result(&self) -> Result<String, AgentError> {
+        if self.state == State::done() {
             Ok(self
                 .memory
                 .final_answer
@@ -170,26 +367,105 @@ impl AgentEngine {
                 .final_answer
                 .clone()
                 .unwrap_or_else(|| format!("[Terminated in state: {}]", self.state)))
-        };
+        }
+    }
 
-        // Hook: agent end
-        let hooks = self.hooks.clone();
-        match &result {
-            Ok(answer) => safe_hook(|| hooks.on_agent_end(Ok(answer.as_str()), &self.memory)),
-            Err(e) => safe_hook(|| hooks.on_agent_end(Err(e), &self.memory)),
+    /// Graceful shutdown: the last state has finished, stop before the next
+    /// with `AgentError::ShutDown`.
+    fn stop_if_shutting_down(&mut self) -> Result<(), AgentError> {
+        if !self.shutdown.is_requested() {
+            return Ok(());
         }
+        self.memory.log("Engine", "SHUTDOWN", &format!("state={}", self.state));
+        self.record_memory();
+        self.save_checkpoint();
+        self.publish_status();
+        Err(AgentError::ShutDown { state: self.state.clone() })
+    }
 
-        result
+    /// A suspended run continues from where it waited.
+    fn resume_if_suspended(&mut self) {
+        if let Some(suspension) = self.memory.suspension.take() {
+            let now = chrono::Utc::now();
+            self.memory.log("Engine", "RESUMED", &format!("waited {}", suspension.describe()));
+            self.memory.add_hint(format!(
+                "The run was suspended at {} to wait {} and resumed at {}.",
+                suspension.suspended_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                suspension.describe(),
+                now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+            ));
+        }
+    }
+
+    /// `wait_until`: once its observation is committed, stop before the
+    /// next state with `AgentError::Suspended`.
+    fn suspend_if_waiting(&mut self) -> Result<(), AgentError> {
+        let Some(suspension) = self.memory.suspension.clone() else {
+            return Ok(());
+        };
+        let data = format!("state={} {}", self.state, suspension.describe());
+        self.memory.log("Engine", "SUSPENDED", &data);
+        self.record_memory();
+        self.save_checkpoint();
+        self.publish_status();
+        Err(AgentError::Suspended(suspension))
     }
 
     /// Executes a single state transition.
     /// Returns Ok(()) if successful, or Err(AgentError).
+    ///
+    /// The checkpoint of the transition is in the store when this returns:
+    /// each call waits for the checkpoint writer, so a session stepped by
+    /// hand saves as slowly as an inline save would. `run` and
+    /// `run_streaming` only wait when the run ends.
     pub async fn step(
         &mut self,
         tx: &mpsc::UnboundedSender<AgentOutput>,
     ) -> Result<(), AgentError> {
+        let result = self.advance(tx).await;
+        // Failed writes were already logged
+        let _ = self.flush_checkpoints().await;
+        result
+    }
+
+    /// One transition, with its checkpoint queued but not necessarily written.
+    async fn advance(
+        &mut self,
+        tx: &mpsc::UnboundedSender<AgentOutput>,
+    ) -> Result<(), AgentError> {
+        let result = self.transition(tx).await;
+        // Recorded once per step, whether or not the step moved the state
+        self.record_memory();
+        if matches!(result, Ok(true)) {
+            self.save_checkpoint();
+        }
+        self.publish_status();
+        result.map(drop)
+    }
+
+    /// Handle the current state and apply the transition for its event.
+    /// Returns whether the transition was taken; `advance` checkpoints it.
+    async fn transition(
+        &mut self,
+        tx: &mpsc::UnboundedSender<AgentOutput>,
+    ) -> Result<bool, AgentError> {
         tracing::info!(state = %self.state, "agent step");
 
+        if self.memory.cancel.is_cancelled() {
+            self.memory.log("Engine", "CANCELLED", &format!("state={}", self.state));
+            return Err(AgentError::Cancelled);
+        }
+
+        // Hints sent through handles apply to the next Planning call
+        while let Ok(hint) = self.hint_rx.try_recv() {
+            self.memory.add_hint(hint);
+        }
+
+        // Hot reload: pick up config file changes between steps
+        if let Some(watcher) = &mut self.config_watcher {
+            watcher.reload_into(&mut self.memory);
+        }
+
         // Get handler for current state
         let state_name = self.state.as_str();
         let handler = self
@@ -202,9 +478,10 @@ impl AgentEngine {
         let sn = state_name.to_string();
         safe_hook(|| hooks.on_state_enter(&sn, &self.memory));
 
-        // Execute state — get event
+        // Execute state — get event. Tool changes made meanwhile apply from the next state.
+        let tools = self.tools.snapshot();
         let event: Event = handler
-            .handle(&mut self.memory, &self.tools, self.llm.as_ref(), Some(tx))
+            .handle(&mut self.memory, &tools, self.llm.as_ref(), Some(tx))
             .await;
 
         // Hook: state exit
@@ -243,11 +520,11 @@ impl AgentEngine {
                     crate::healing::HealingOutcome::ForceFinish => {
                         // Jump to Done state
                         self.state = State::done();
-                        return Ok(());
+                        return Ok(false);
                     }
                     crate::healing::HealingOutcome::Retry => {
                         // Stay in current state — don't apply transition
-                        return Ok(());
+                        return Ok(false);
                     }
                     crate::healing::HealingOutcome::Continue => {
                         // Fall through to normal transition
@@ -280,7 +557,7 @@ impl AgentEngine {
                         self.state, next_state, self.state
                     );
                     // Don't apply transition; state stays the same
-                    return Ok(());
+                    return Ok(false);
                 }
                 ContractViolationAction::EmitEvent(ref evt) => {
                     // Redirect to a different transition via the custom event
@@ -288,11 +565,11 @@ impl AgentEngine {
                     if let Some(alt_next) = self.transitions.get(&alt_key).cloned() {
                         tracing::info!(guard = %failure.contract_name, event = %evt, to = %alt_next, "Guard redirected transition");
                         self.state = alt_next;
-                        return Ok(());
+                        return Ok(false);
                     } else {
                         // No transition for the emitted event — treat as block
                         tracing::warn!(guard = %failure.contract_name, event = %evt, "Guard emitted event but no transition found — blocking");
-                        return Ok(());
+                        return Ok(false);
                     }
                 }
                 ContractViolationAction::FatalError => {
@@ -306,7 +583,8 @@ impl AgentEngine {
         }
 
         tracing::info!(from = %self.state, event = %event, to = %next_state, "transition");
-        println!("  ══ {} --{}-->{} ══", self.state, event, next_state);
+        let data = format!("{} --{}--> {}", self.state, event, next_state);
+        self.memory.log("Engine", crate::observability::TRANSITION, &data);
 
         // Replay: record state transition
         self.memory.replay_recorder.record_transition(
@@ -325,11 +603,10 @@ impl AgentEngine {
                 if !plan.is_complete() {
                     // Check if last tool call succeeded or failed
                     if let Some(last) = self.memory.history.last() {
-                        if last.success {
-                            let obs = last.observation.chars().take(200).collect::<String>();
+                        let obs = last.observation.content.chars().take(200).collect::<String>();
+                        if last.is_success() {
                             plan.complete_current(obs);
                         } else {
-                            let obs = last.observation.chars().take(200).collect::<String>();
                             plan.fail_current(obs);
                         }
                     }
@@ -337,19 +614,68 @@ impl AgentEngine {
             }
         }
 
-        // Save checkpoint
-        if let Some(store) = &self.checkpoint_store {
-            let checkpoint: AgentCheckpoint = AgentCheckpoint {
-                checkpoint_id: uuid::Uuid::new_v4().to_string(),
-                session_id: self.session_id.clone(),
-                state: self.state.clone(),
-                memory: self.memory.clone(),
-                timestamp: chrono::Utc::now(),
-            };
-            let _ = store.save(checkpoint).await;
+        Ok(true)
+    }
+
+    /// Log memory changes to the trace when `event_sourced_memory` is on.
+    fn record_memory(&mut self) {
+        if self.memory.config.event_sourced_memory {
+            let last = self.memory_snapshot.take();
+            self.memory_snapshot = Some(crate::event_sourcing::record(&mut self.memory, last.as_ref()));
         }
+    }
 
-        Ok(())
+    /// Queue the current state and memory for saving to the checkpoint
+    /// store, if any. See [`crate::checkpoint_writer`].
+    pub(crate) fn save_checkpoint(&mut self) {
+        let Some(store) = self.checkpoint_store.clone() else {
+            return;
+        };
+        // The writer stops with the runtime it was started on; a new one starts over
+        if !self.checkpoint_writer.as_ref().is_some_and(|w| w.is_running()) {
+            self.checkpoint_writer = None;
+            self.trace_sent = 0;
+        }
+
+        let len = self.memory.trace.len();
+        let from = self.trace_sent.min(len);
+        let from_trace = self.memory.config.event_sourced_memory && self.memory_snapshot.is_some();
+        // Memory rebuilt from the store's trace log is not copied at all
+        let memory = if from_trace && self.checkpoint_writer.as_ref().is_some_and(|w| w.logs_trace()) {
+            AgentMemory::new("")
+        } else {
+            let trace = std::mem::take(&mut self.memory.trace);
+            let memory = self.memory.clone();
+            self.memory.trace = trace;
+            memory
+        };
+        let snapshot = crate::checkpoint_writer::Snapshot {
+            state: self.state.clone(),
+            memory,
+            trace_from: from,
+            trace: self.memory.trace.entries()[from..].to_vec(),
+            from_trace,
+            graph: self.graph_shape(),
+        };
+        self.trace_sent = len;
+
+        self.checkpoint_writer
+            .get_or_insert_with(|| {
+                crate::checkpoint_writer::CheckpointWriter::spawn(store, self.session_id.clone(), self.trace_persisted)
+            })
+            .save(snapshot);
+    }
+
+    /// Wait until every checkpoint queued so far is in the store. Returns
+    /// the first failed write since the previous flush.
+    ///
+    /// Runs flush when they end and `step()` after each transition; call
+    /// this before reading the store while a run is in progress.
+    pub async fn flush_checkpoints(&self) -> Result<(), String> {
+        match &self.checkpoint_writer {
+            Some(writer) => writer.flush().await,
+            None => Ok(()),
+        }
     }
 
     /// Run the agent and return a stream of AgentOutput events.
@@ -360,52 +686,232 @@ impl AgentEngine {
         let (tx, rx) = mpsc::unbounded_channel();
 
         stream::unfold(
-            (self, rx, tx, false),
-            |(engine, mut rx, tx, done)| async move {
+            (self, rx, tx, None, false),
+            |(engine, mut rx, tx, mut guard, done)| async move {
                 if done {
                     return None;
                 }
+                // Started on the first poll; dropping the stream before it
+                // ends cancels the token, like dropping `run()`
+                if guard.is_none() {
+                    engine.start_run();
+                    guard = Some(engine.memory.cancel.clone().drop_guard());
+                }
 
                 // 1. If we have pending messages in the channel (e.g. from the last step or tokens), yield them first.
                 if let Ok(msg) = rx.try_recv() {
-                    return Some((msg, (engine, rx, tx, false)));
+                    return Some((msg, (engine, rx, tx, guard, false)));
                 }
 
                 // 2. Check if we've reached a terminal state AND channel is empty.
                 if engine.terminal_states.contains(engine.state.as_str()) {
                     if let Ok(msg) = rx.try_recv() {
-                        return Some((msg, (engine, rx, tx, false)));
+                        return Some((msg, (engine, rx, tx, guard, false)));
+                    }
+                    let result = engine.terminal_result();
+                    engine.finish_run(&result).await;
+                    if let Some(guard) = guard {
+                        guard.disarm();
                     }
                     return None;
                 }
 
-                // 3. Execute one step of the engine.
+                // 3. Execute one step of the engine, unless it is shutting down or waits.
                 // This will likely send many events (StateStarted, tokens, ToolCallStarted, etc.) to tx.
-                if let Err(e) = engine.step(&tx).await {
-                    return Some((AgentOutput::Error(e.to_string()), (engine, rx, tx, true)));
+                let step = match engine.stop_if_shutting_down().and_then(|_| engine.suspend_if_waiting()) {
+                    Ok(()) => engine.advance(&tx).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = step {
+                    let message = e.to_string();
+                    engine.finish_run(&Err(e)).await;
+                    if let Some(guard) = guard {
+                        guard.disarm();
+                    }
+                    return Some((AgentOutput::Error(message), (engine, rx, tx, None, true)));
                 }
 
                 // 4. After a step, we should have at least one message (StateStarted).
                 if let Ok(msg) = rx.try_recv() {
-                    return Some((msg, (engine, rx, tx, false)));
+                    return Some((msg, (engine, rx, tx, guard, false)));
                 }
 
                 // If we get here, the step produced no output and wasn't terminal (rare but possible).
                 // We just return an empty action to keep the stream alive or recurse?
                 // Recursing is better.
+                if let Some(guard) = guard {
+                    guard.disarm();
+                }
                 None // For now, end stream if no output.
             },
         )
         .boxed()
     }
 
+    /// Give a suspended agent the external event it waits for (see
+    /// [`crate::suspend`]). The payload becomes the observation of its
+    /// `wait_until` call; run the engine to continue.
+    pub fn deliver_event(&mut self, payload: serde_json::Value) -> Result<(), AgentError> {
+        crate::suspend::deliver_event(&mut self.memory, &payload)
+            .map_err(|e| AgentError::BuildError(format!("Session {}: {}", self.session_id, e)))
+    }
+
+    /// Mutating tool calls simulated by a plan-mode run, for review.
+    pub fn change_plan(&self) -> crate::dry_run::ChangePlan {
+        crate::dry_run::ChangePlan {
+            actions: self.memory.planned_actions.clone(),
+            summary: self.memory.final_answer.clone(),
+        }
+    }
+
+    /// Carry out an approved plan: re-run the task from the start in apply
+    /// mode, with the planned actions added to the task.
+    ///
+    /// Call after a plan-mode `run()` has reached `Done` and its
+    /// `change_plan()` was approved.
+    pub async fn apply(&mut self) -> Result<String, AgentError> {
+        use crate::dry_run::ExecutionMode;
+
+        if self.memory.execution_mode != ExecutionMode::Plan || self.state != State::done() {
+            return Err(AgentError::AgentFailed(
+                "apply() requires a plan-mode run that reached Done".to_string(),
+            ));
+        }
+        let plan = self.change_plan();
+        self.memory.log("Engine", "PLAN_APPLY", &format!("actions={}", plan.actions.len()));
+
+        self.memory.reset_progress();
+        self.memory.execution_mode = ExecutionMode::Apply;
+        self.memory.task = format!("{}\n\n{}", self.memory.task, plan.to_prompt());
+        self.state = State::idle();
+        self.run().await
+    }
+
     /// Returns a reference to the full execution trace.
     pub fn trace(&self) -> &Trace {
         &self.memory.trace
     }
 
+    /// The states, transitions and terminal states this engine runs.
+    pub fn graph_shape(&self) -> crate::checkpoint::GraphShape {
+        crate::checkpoint::GraphShape::new(
+            State::idle().as_str(),
+            self.handlers.keys(),
+            &self.transitions,
+            &self.terminal_states,
+        )
+    }
+
+    /// Hash and summary of this engine's config, graph and tools.
+    pub fn config_fingerprint(&self) -> crate::bundle::ConfigFingerprint {
+        let tools = self.tools.snapshot().iter().map(|(name, _)| name.to_string()).collect();
+        crate::bundle::ConfigFingerprint::new(&self.memory.config, self.graph_shape(), tools)
+    }
+
+    /// Everything about this session: memory, the checkpoints in the store,
+    /// workspace files and a config fingerprint. See [`crate::bundle`].
+    pub async fn session_bundle(&self) -> Result<crate::bundle::SessionBundle, String> {
+        self.flush_checkpoints().await?;
+        let mut checkpoints = match &self.checkpoint_store {
+            Some(store) => store.list_checkpoints(&self.session_id).await?,
+            None => Vec::new(),
+        };
+        for checkpoint in &mut checkpoints {
+            // Loading restored the trace; the importing store has no log for it
+            if !checkpoint.memory_from_trace {
+                checkpoint.trace_cursor = None;
+            }
+        }
+        let artifacts = match &self.workspace {
+            Some(workspace) => crate::bundle::collect_artifacts(workspace)?,
+            None => Vec::new(),
+        };
+        Ok(crate::bundle::SessionBundle {
+            format: crate::bundle::BUNDLE_FORMAT,
+            session_id: self.session_id.clone(),
+            exported_at: chrono::Utc::now(),
+            state: self.state.clone(),
+            memory: self.memory.clone(),
+            checkpoints,
+            artifacts,
+            config: self.config_fingerprint(),
+        })
+    }
+
+    /// Write [`AgentEngine::session_bundle`] to `path` as JSON. Load it
+    /// elsewhere with `AgentBuilder::import_session`.
+    pub async fn export_session(&self, path: impl AsRef<std::path::Path>) -> Result<(), String> {
+        self.session_bundle()
+            .await?
+            .write(path, &crate::checkpoint::PlainCodec)
+    }
+
+    /// Whether the current state is terminal for this engine's graph.
+    pub fn is_finished(&self) -> bool {
+        self.terminal_states.contains(self.state.as_str())
+    }
+
     /// Returns the current state (useful for inspection after run).
     pub fn current_state(&self) -> &State {
         &self.state
     }
+
+    /// Steer the next Planning call with one-shot guidance that is not
+    /// kept in history. From another task, use `AgentHandle::hint`.
+    pub fn hint(&mut self, text: impl Into<String>) {
+        self.memory.add_hint(text);
+    }
+
+    /// Cancel the run: the next step returns `AgentError::Cancelled` and
+    /// tools see `ToolContext::cancel` set. From another task, use
+    /// `AgentHandle::cancel`. The engine stays cancelled.
+    pub fn cancel(&self) {
+        self.memory.cancel.cancel();
+    }
+
+    /// Stop the run once the state being handled has finished: the engine
+    /// checkpoints and `run` returns `AgentError::ShutDown` before the next
+    /// transition. If the run has not stopped within `timeout`, it is
+    /// cancelled. From another task, use `AgentHandle::shutdown_gracefully`.
+    /// The engine does not run again; resume the session from its checkpoint.
+    pub fn shutdown_gracefully(&self, timeout: std::time::Duration) {
+        self.shutdown.request(&self.memory.cancel, timeout);
+    }
+
+    /// The token tools see as `ToolContext::cancel`.
+    pub fn cancellation_token(&self) -> crate::cancel::CancellationToken {
+        self.memory.cancel.clone()
+    }
+
+    /// A read-only handle that sees a status snapshot after every step,
+    /// usable while `run` or `run_streaming` borrows the engine.
+    pub fn handle(&self) -> AgentHandle {
+        self.publish_status();
+        AgentHandle::new(
+            self.status_tx.subscribe(),
+            self.hint_tx.clone(),
+            self.memory.cancel.clone(),
+            self.shutdown.clone(),
+            self.memory.observability.clone(),
+        )
+    }
+
+    /// Trace and token event sampling for this session, adjustable while
+    /// the agent runs (see [`crate::observability`]).
+    pub fn observability(&self) -> &crate::observability::ObservabilityControl {
+        &self.memory.observability
+    }
+
+    fn publish_status(&self) {
+        let trace = self.memory.trace.entries();
+        let from = trace.len().saturating_sub(self.status_trace_len);
+        self.status_tx.send_replace(AgentStatus {
+            state: self.state.clone(),
+            step: self.memory.step,
+            usage: self.memory.total_usage,
+            cost: self.memory.total_cost,
+            recent_trace: trace[from..].to_vec(),
+            finished: self.is_finished(),
+        });
+    }
 }
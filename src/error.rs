@@ -17,7 +17,7 @@ pub enum AgentError {
     SafetyCapExceeded(usize),
 
     #[error("LLM caller error: {0}")]
-    LlmError(String),
+    LlmError(#[from] crate::llm::LlmError),
 
     #[error("Tool execution error: {0}")]
     ToolError(String),
@@ -28,6 +28,21 @@ pub enum AgentError {
     #[error("Build error: {0}")]
     BuildError(String),
 
+    #[error("Invalid config: {}", .0.join("; "))]
+    Config(Vec<String>),
+
     #[error("Contract violation '{name}': {message}")]
     ContractViolation { name: String, message: String },
+
+    #[error("Checkpoint graph mismatch: {0}")]
+    GraphMismatch(String),
+
+    #[error("Agent run was cancelled")]
+    Cancelled,
+
+    #[error("Agent shut down gracefully before {state}; resume from the last checkpoint")]
+    ShutDown { state: State },
+
+    #[error("Agent suspended {}; run it again to continue", .0.describe())]
+    Suspended(crate::suspend::Suspension),
 }
@@ -0,0 +1,197 @@
+//! Event-sourced memory — rebuild `AgentMemory` from the trace alone.
+//!
+//! States mutate memory and log to the trace separately, so the two can
+//! drift: the trace may say a tool ran while history says otherwise. With
+//! `AgentConfig::event_sourced_memory` on, the engine records every change
+//! to memory in the trace itself. The first step logs `MEMORY_INIT` with a
+//! full snapshot; every later step logs `MEMORY_DELTA` with the fields that
+//! changed. Arrays that only grew, like `history`, are stored as appends.
+//!
+//! [`reconstruct`] replays those entries, so the trace is the single source
+//! of truth and checkpoints can hold a cursor into the trace log instead of
+//! the memory (see `AgentCheckpoint::memory_from_trace`).
+//!
+//! Runtime-only fields (hooks, cache, prompter, …) are not serialized and
+//! come back as their defaults, as with any checkpoint.
+
+use crate::memory::AgentMemory;
+use crate::trace::{Trace, TraceEntry};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Trace event carrying a full memory snapshot.
+pub const MEMORY_INIT: &str = "MEMORY_INIT";
+/// Trace event carrying the fields that changed since the last record.
+pub const MEMORY_DELTA: &str = "MEMORY_DELTA";
+
+/// Changes to the serialized memory between two records.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryDelta {
+    /// Fields replaced with a new value
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub set: Map<String, Value>,
+    /// Array fields that gained items at the end
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub append: Map<String, Value>,
+    /// Fields no longer present
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+}
+
+impl MemoryDelta {
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.append.is_empty() && self.remove.is_empty()
+    }
+
+    /// The changes that turn `before` into `after`, field by field.
+    pub fn between(before: &Map<String, Value>, after: &Map<String, Value>) -> Self {
+        let mut delta = Self::default();
+        for (key, new) in after {
+            match (before.get(key), new) {
+                (Some(old), _) if old == new => {}
+                (Some(Value::Array(old)), Value::Array(items))
+                    if items.len() > old.len() && items[..old.len()] == old[..] =>
+                {
+                    delta.append.insert(key.clone(), Value::Array(items[old.len()..].to_vec()));
+                }
+                _ => {
+                    delta.set.insert(key.clone(), new.clone());
+                }
+            }
+        }
+        delta.remove = before.keys().filter(|k| !after.contains_key(*k)).cloned().collect();
+        delta
+    }
+
+    /// Apply the changes to a snapshot.
+    pub fn apply(&self, snapshot: &mut Map<String, Value>) -> Result<(), String> {
+        for (key, value) in &self.set {
+            snapshot.insert(key.clone(), value.clone());
+        }
+        for (key, items) in &self.append {
+            match (snapshot.get_mut(key), items) {
+                (Some(Value::Array(existing)), Value::Array(items)) => existing.extend(items.iter().cloned()),
+                _ => return Err(format!("Cannot append to memory field '{}'", key)),
+            }
+        }
+        for key in &self.remove {
+            snapshot.remove(key);
+        }
+        Ok(())
+    }
+}
+
+/// Serialized memory without its trace, which records it.
+pub fn snapshot(memory: &AgentMemory) -> Map<String, Value> {
+    let mut map = match serde_json::to_value(memory) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    map.remove("trace");
+    map
+}
+
+/// Log the change since `last` to the trace and return the new snapshot.
+/// Logs `MEMORY_INIT` when there is no previous snapshot, and nothing when
+/// memory is unchanged.
+pub(crate) fn record(memory: &mut AgentMemory, last: Option<&Map<String, Value>>) -> Map<String, Value> {
+    let current = snapshot(memory);
+    match last {
+        None => {
+            let data = Value::Object(current.clone()).to_string();
+            memory.log("Memory", MEMORY_INIT, &data);
+        }
+        Some(last) => {
+            let delta = MemoryDelta::between(last, &current);
+            if !delta.is_empty() {
+                let data = serde_json::to_string(&delta).unwrap_or_default();
+                memory.log("Memory", MEMORY_DELTA, &data);
+            }
+        }
+    }
+    current
+}
+
+/// Rebuild memory from a trace, starting at its last `MEMORY_INIT`.
+/// The returned memory carries `entries` as its trace.
+pub fn reconstruct(entries: &[TraceEntry]) -> Result<AgentMemory, String> {
+    let start = entries
+        .iter()
+        .rposition(|e| e.event == MEMORY_INIT)
+        .ok_or_else(|| "Trace has no MEMORY_INIT entry".to_string())?;
+
+    let mut state: Map<String, Value> = serde_json::from_str(&entries[start].data)
+        .map_err(|e| format!("Invalid MEMORY_INIT at entry {}: {}", start, e))?;
+    for (i, entry) in entries.iter().enumerate().skip(start + 1) {
+        if entry.event != MEMORY_DELTA {
+            continue;
+        }
+        let delta: MemoryDelta = serde_json::from_str(&entry.data)
+            .map_err(|e| format!("Invalid MEMORY_DELTA at entry {}: {}", i, e))?;
+        delta.apply(&mut state)?;
+    }
+
+    state.insert(
+        "trace".to_string(),
+        serde_json::to_value(Trace::new()).map_err(|e| e.to_string())?,
+    );
+    let mut memory: AgentMemory =
+        serde_json::from_value(Value::Object(state)).map_err(|e| format!("Invalid memory: {}", e))?;
+    memory.trace = Trace::from_entries(entries.to_vec());
+    Ok(memory)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn obj(v: Value) -> Map<String, Value> {
+        v.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_delta_appends_grown_arrays() {
+        let before = obj(json!({ "step": 1, "history": [1, 2], "error": null, "gone": true }));
+        let after = obj(json!({ "step": 2, "history": [1, 2, 3], "error": null }));
+
+        let delta = MemoryDelta::between(&before, &after);
+        assert_eq!(delta.set, obj(json!({ "step": 2 })));
+        assert_eq!(delta.append, obj(json!({ "history": [3] })));
+        assert_eq!(delta.remove, vec!["gone".to_string()]);
+
+        let mut replayed = before.clone();
+        delta.apply(&mut replayed).unwrap();
+        assert_eq!(replayed, after);
+
+        // A rewritten array is replaced whole
+        let compressed = obj(json!({ "step": 2, "history": [9], "error": null }));
+        let delta = MemoryDelta::between(&after, &compressed);
+        assert_eq!(delta.set, obj(json!({ "history": [9] })));
+    }
+
+    #[test]
+    fn test_reconstruct_from_trace() {
+        let mut memory = AgentMemory::new("task");
+        let first = record(&mut memory, None);
+        memory.step = 3;
+        memory.final_answer = Some("done".to_string());
+        let second = record(&mut memory, Some(&first));
+        // Unchanged memory logs nothing
+        let len = memory.trace.len();
+        record(&mut memory, Some(&second));
+        assert_eq!(memory.trace.len(), len);
+
+        let rebuilt = reconstruct(memory.trace.entries()).unwrap();
+        assert_eq!(rebuilt.step, 3);
+        assert_eq!(rebuilt.final_answer.as_deref(), Some("done"));
+        assert_eq!(snapshot(&rebuilt), snapshot(&memory));
+        assert_eq!(rebuilt.trace.len(), memory.trace.len());
+
+        assert!(reconstruct(&[]).is_err());
+    }
+}
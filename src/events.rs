@@ -30,6 +30,7 @@ impl Event {
 
     // Lifecycle
     pub fn start()           -> Self { Self::new("Start") }
+    pub fn preflight_failed()-> Self { Self::new("PreflightFailed") }
 
     // Planning outcomes
     pub fn llm_tool_call()   -> Self { Self::new("LlmToolCall") }
@@ -38,7 +39,16 @@ impl Event {
     pub fn max_steps()       -> Self { Self::new("MaxSteps") }
     pub fn low_confidence()  -> Self { Self::new("LowConfidence") }
     pub fn answer_too_short()-> Self { Self::new("AnswerTooShort") }
+    pub fn answer_revisions_exhausted() -> Self { Self::new("AnswerRevisionsExhausted") }
+    pub fn answer_uncited()  -> Self { Self::new("AnswerUncited") }
+    pub fn invalid_structured_output() -> Self { Self::new("InvalidStructuredOutput") }
+    pub fn answer_blocked()  -> Self { Self::new("AnswerBlocked") }
+    pub fn policy_denied()   -> Self { Self::new("PolicyDenied") }
     pub fn tool_blacklisted()-> Self { Self::new("ToolBlacklisted") }
+    pub fn tool_call_reused()-> Self { Self::new("ToolCallReused") }
+    pub fn repeated_tool_call() -> Self { Self::new("RepeatedToolCall") }
+    pub fn context_overflow()-> Self { Self::new("ContextOverflow") }
+    pub fn llm_retry()       -> Self { Self::new("LlmRetry") }
     pub fn fatal_error()     -> Self { Self::new("FatalError") }
 
     // Human involvement
@@ -46,6 +56,7 @@ impl Event {
     pub fn human_approved()          -> Self { Self::new("HumanApproved") }
     pub fn human_rejected()          -> Self { Self::new("HumanRejected") }
     pub fn human_modified()          -> Self { Self::new("HumanModified") }
+    pub fn human_approved_batch()    -> Self { Self::new("HumanApprovedBatch") }
 
     // Acting outcomes
     pub fn tool_success()    -> Self { Self::new("ToolSuccess") }
@@ -57,6 +68,9 @@ impl Event {
 
     // Reflecting outcomes
     pub fn reflect_done()    -> Self { Self::new("ReflectDone") }
+
+    // Debate outcomes
+    pub fn debate_concluded()-> Self { Self::new("DebateConcluded") }
 }
 
 impl std::fmt::Display for Event {
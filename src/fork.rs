@@ -34,7 +34,7 @@ impl ForkScorer for ToolSuccessRateScorer {
         if memory.history.is_empty() {
             return 0.5;
         }
-        let successes = memory.history.iter().filter(|h| h.success).count();
+        let successes = memory.history.iter().filter(|h| h.is_success()).count();
         successes as f64 / memory.history.len() as f64
     }
 }
@@ -49,7 +49,7 @@ impl ForkScorer for StepEfficiencyScorer {
         let success_rate = if memory.history.is_empty() {
             0.5
         } else {
-            let s = memory.history.iter().filter(|h| h.success).count();
+            let s = memory.history.iter().filter(|h| h.is_success()).count();
             s as f64 / memory.history.len() as f64
         };
         let quality = memory.confidence_score * success_rate;
@@ -149,7 +149,7 @@ pub struct ForkResult {
 }
 
 /// Select the best result from multiple fork results.
-pub fn select_best(results: &mut Vec<ForkResult>, strategy: &MergeStrategy) -> Option<ForkResult> {
+pub fn select_best(results: &mut [ForkResult], strategy: &MergeStrategy) -> Option<ForkResult> {
     if results.is_empty() {
         return None;
     }
@@ -243,7 +243,7 @@ pub fn fork_memory(
 mod tests {
     use super::*;
     use crate::memory::AgentMemory;
-    use crate::types::{HistoryEntry, ToolCall};
+    use crate::types::{HistoryEntry, Observation, ToolCall};
 
     fn make_memory() -> AgentMemory {
         AgentMemory::new("test task")
@@ -257,8 +257,11 @@ mod tests {
                 name: name.to_string(),
                 args: Default::default(),
             },
-            observation: "result".to_string(),
-            success,
+            observation: Observation::from_outcome(success, "result"),
+            assistant_text: None,
+            latency_ms: None,
+            model_used: None,
+            usage: None,
         });
     }
 
@@ -0,0 +1,199 @@
+//! Grammar-constrained decoding for local backends.
+//!
+//! llama.cpp's server and Ollama can restrict sampling so the model can only
+//! produce text that matches a grammar. Set `SamplingParams::grammar` and
+//! every planning request carries the constraint:
+//!
+//! - `Gbnf` — a GBNF grammar, sent as llama.cpp's `grammar` field
+//! - `JsonSchema` — a JSON schema, sent as `response_format` (llama.cpp and Ollama)
+//! - `ToolCalls` / `ToolCallsGbnf` — generated each step from the registered
+//!   tools, in the reply shape read by [`JsonPrompter`](crate::prompter::JsonPrompter)
+//!
+//! With `ToolCalls`, a reply is either `{"tool": <registered name>,
+//! "arguments": {..}}` with arguments matching that tool's schema, or
+//! `{"answer": ".."}`. Pair it with `JsonPrompter` so tools are described in
+//! text and the reply is parsed back into tool calls:
+//!
+//! ```rust,ignore
+//! let agent = AgentBuilder::new("task")
+//!     .llama_server("http://localhost:8080/v1")
+//!     .planning_prompter(Arc::new(JsonPrompter))
+//!     .grammar(GrammarConstraint::ToolCalls)
+//!     .build()?;
+//! ```
+//!
+//! The constraint is applied by callers that build raw request bodies
+//! (`ReasoningCaller`, used by `.llama_server()`). Hosted APIs ignore it.
+
+use crate::tools::ToolSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Output constraint sent with planning requests.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GrammarConstraint {
+    /// A GBNF grammar (llama.cpp only)
+    Gbnf(String),
+    /// A JSON schema the reply must match
+    JsonSchema(Value),
+    /// JSON schema generated from the registered tools
+    ToolCalls,
+    /// GBNF grammar generated from the registered tools. Checks tool names
+    /// but only requires the arguments to be a JSON object.
+    ToolCallsGbnf,
+}
+
+impl GrammarConstraint {
+    /// The concrete constraint for the current tools. `ToolCalls` and
+    /// `ToolCallsGbnf` become `JsonSchema` and `Gbnf`; the others are unchanged.
+    pub fn resolve(&self, tools: &[ToolSchema]) -> GrammarConstraint {
+        match self {
+            GrammarConstraint::ToolCalls => GrammarConstraint::JsonSchema(tool_call_schema(tools)),
+            GrammarConstraint::ToolCallsGbnf => GrammarConstraint::Gbnf(tool_call_gbnf(tools)),
+            other => other.clone(),
+        }
+    }
+
+    /// Add the constraint to an OpenAI-compatible request body.
+    /// Unresolved tool constraints add nothing; call `resolve` first.
+    pub fn apply(&self, body: &mut Value) {
+        match self {
+            GrammarConstraint::Gbnf(grammar) => {
+                body["grammar"] = Value::String(grammar.clone());
+            }
+            GrammarConstraint::JsonSchema(schema) => {
+                body["response_format"] = json!({
+                    "type": "json_schema",
+                    "json_schema": { "name": "reply", "strict": true, "schema": schema }
+                });
+            }
+            GrammarConstraint::ToolCalls | GrammarConstraint::ToolCallsGbnf => {}
+        }
+    }
+}
+
+fn sorted(tools: &[ToolSchema]) -> Vec<&ToolSchema> {
+    let mut tools: Vec<&ToolSchema> = tools.iter().collect();
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    tools
+}
+
+/// JSON schema accepting one tool call per registered tool, or an answer.
+pub fn tool_call_schema(tools: &[ToolSchema]) -> Value {
+    let mut options: Vec<Value> = sorted(tools)
+        .into_iter()
+        .map(|t| {
+            json!({
+                "type": "object",
+                "properties": {
+                    "tool": { "const": t.name },
+                    "arguments": t.input_schema,
+                },
+                "required": ["tool", "arguments"],
+                "additionalProperties": false,
+            })
+        })
+        .collect();
+    options.push(json!({
+        "type": "object",
+        "properties": { "answer": { "type": "string" } },
+        "required": ["answer"],
+        "additionalProperties": false,
+    }));
+    json!({ "anyOf": options })
+}
+
+/// Generic JSON rules shared by generated grammars.
+const JSON_RULES: &str = r#"value  ::= object | array | string | number | ("true" | "false" | "null")
+object ::= "{" ws ( string ws ":" ws value ( ws "," ws string ws ":" ws value )* )? ws "}"
+array  ::= "[" ws ( value ( ws "," ws value )* )? ws "]"
+string ::= "\"" ( [^"\\\x7F\x00-\x1F] | "\\" (["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F]) )* "\""
+number ::= "-"? ([0-9] | [1-9] [0-9]*) ("." [0-9]+)? ([eE] [-+]? [0-9]+)?
+ws     ::= [ \t\n]*"#;
+
+/// GBNF grammar accepting a call to a registered tool, or an answer.
+pub fn tool_call_gbnf(tools: &[ToolSchema]) -> String {
+    let answer = r#"answer ::= "{" ws "\"answer\"" ws ":" ws string ws "}""#;
+    if tools.is_empty() {
+        return format!("root   ::= answer\n{}\n{}\n", answer, JSON_RULES);
+    }
+    let names = sorted(tools)
+        .into_iter()
+        .map(|t| gbnf_literal(&Value::String(t.name.clone()).to_string()))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    format!(
+        "root   ::= call | answer\n\
+         call   ::= \"{{\" ws \"\\\"tool\\\"\" ws \":\" ws tool ws \",\" ws \"\\\"arguments\\\"\" ws \":\" ws object ws \"}}\"\n\
+         tool   ::= {}\n{}\n{}\n",
+        names, answer, JSON_RULES
+    )
+}
+
+/// `text` as a quoted GBNF literal.
+fn gbnf_literal(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(name: &str) -> ToolSchema {
+        ToolSchema {
+            name: name.into(),
+            description: String::new(),
+            input_schema: json!({ "type": "object", "properties": { "q": { "type": "string" } } }),
+        }
+    }
+
+    #[test]
+    fn test_tool_call_schema_lists_tools_and_answer() {
+        let value = tool_call_schema(&[schema("search"), schema("calc")]);
+        let options = value["anyOf"].as_array().unwrap();
+        assert_eq!(options.len(), 3);
+        assert_eq!(options[0]["properties"]["tool"]["const"], "calc");
+        assert_eq!(options[1]["properties"]["arguments"]["properties"]["q"]["type"], "string");
+        assert_eq!(options[2]["required"], json!(["answer"]));
+    }
+
+    #[test]
+    fn test_tool_call_gbnf_quotes_names() {
+        let grammar = tool_call_gbnf(&[schema("search"), schema("calc")]);
+        assert!(grammar.starts_with("root   ::= call | answer\n"));
+        assert!(grammar.contains(r#"tool   ::= "\"calc\"" | "\"search\"""#));
+        assert!(grammar.contains("\nws     ::="));
+        assert!(tool_call_gbnf(&[]).starts_with("root   ::= answer\n"));
+    }
+
+    #[test]
+    fn test_apply_sets_request_fields() {
+        let tools = [schema("search")];
+        let mut body = json!({ "model": "m" });
+        GrammarConstraint::ToolCalls.resolve(&tools).apply(&mut body);
+        assert_eq!(body["response_format"]["type"], "json_schema");
+        assert_eq!(body["response_format"]["json_schema"]["schema"], tool_call_schema(&tools));
+
+        let mut body = json!({ "model": "m" });
+        GrammarConstraint::ToolCallsGbnf.resolve(&tools).apply(&mut body);
+        assert!(body["grammar"].as_str().unwrap().contains("search"));
+
+        let mut body = json!({ "model": "m" });
+        GrammarConstraint::ToolCalls.apply(&mut body);
+        assert_eq!(body, json!({ "model": "m" }));
+    }
+}
@@ -0,0 +1,134 @@
+//! Agent Handle — watch a running agent without borrowing the engine.
+//!
+//! `run` and `run_streaming` borrow the engine mutably until they finish, so
+//! its memory and trace cannot be read while it works. An [`AgentHandle`],
+//! taken with `AgentEngine::handle()` before the run starts, receives an
+//! [`AgentStatus`] snapshot after every step through a `tokio::sync::watch`
+//! channel. Handles are cheap to clone and can be moved to other tasks,
+//! such as a web handler that reports progress. A handle can also send
+//! one-shot hints that steer the agent's next Planning call, change how much
+//! it records, cancel it, and shut it down gracefully.
+//!
+//! ```rust,ignore
+//! let handle = engine.handle();
+//! tokio::spawn(async move {
+//!     let mut handle = handle;
+//!     while handle.changed().await {
+//!         let s = handle.status();
+//!         println!("{} step {} ({} tokens)", s.state, s.step, s.usage.total_tokens);
+//!     }
+//! });
+//! engine.run().await?;
+//! ```
+
+use crate::budget::TokenUsage;
+use crate::cancel::{CancellationToken, ShutdownSignal};
+use crate::observability::ObservabilityControl;
+use crate::trace::TraceEntry;
+use crate::types::State;
+use tokio::sync::{mpsc, watch};
+
+/// Snapshot of an agent, published after every step.
+#[derive(Debug, Clone)]
+pub struct AgentStatus {
+    pub state: State,
+    pub step: usize,
+    pub usage: TokenUsage,
+    /// Session cost in USD, for models with pricing configured
+    pub cost: f64,
+    /// The most recent trace entries, oldest first
+    pub recent_trace: Vec<TraceEntry>,
+    /// The agent reached a terminal state
+    pub finished: bool,
+}
+
+impl Default for AgentStatus {
+    fn default() -> Self {
+        Self {
+            state: State::idle(),
+            step: 0,
+            usage: TokenUsage::default(),
+            cost: 0.0,
+            recent_trace: Vec::new(),
+            finished: false,
+        }
+    }
+}
+
+/// View of a running agent. Get one with `AgentEngine::handle()`.
+#[derive(Debug, Clone)]
+pub struct AgentHandle {
+    rx: watch::Receiver<AgentStatus>,
+    hints: mpsc::UnboundedSender<String>,
+    cancel: CancellationToken,
+    shutdown: ShutdownSignal,
+    observability: ObservabilityControl,
+}
+
+impl AgentHandle {
+    pub(crate) fn new(
+        rx: watch::Receiver<AgentStatus>,
+        hints: mpsc::UnboundedSender<String>,
+        cancel: CancellationToken,
+        shutdown: ShutdownSignal,
+        observability: ObservabilityControl,
+    ) -> Self {
+        Self { rx, hints, cancel, shutdown, observability }
+    }
+
+    /// The latest snapshot.
+    pub fn status(&self) -> AgentStatus {
+        self.rx.borrow().clone()
+    }
+
+    pub fn state(&self) -> State {
+        self.rx.borrow().state.clone()
+    }
+
+    pub fn step(&self) -> usize {
+        self.rx.borrow().step
+    }
+
+    pub fn usage(&self) -> TokenUsage {
+        self.rx.borrow().usage
+    }
+
+    pub fn recent_trace(&self) -> Vec<TraceEntry> {
+        self.rx.borrow().recent_trace.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.rx.borrow().finished
+    }
+
+    /// Wait for the next snapshot. Returns false once the engine is dropped.
+    pub async fn changed(&mut self) -> bool {
+        self.rx.changed().await.is_ok()
+    }
+
+    /// Guidance for the agent's next Planning call only (see
+    /// `AgentEngine::hint`). Returns false once the engine is dropped.
+    pub fn hint(&self, text: impl Into<String>) -> bool {
+        self.hints.send(text.into()).is_ok()
+    }
+
+    /// The agent's trace and token event sampling (see `AgentEngine::observability`).
+    pub fn observability(&self) -> &ObservabilityControl {
+        &self.observability
+    }
+
+    /// Cancel the agent (see `AgentEngine::cancel`).
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Stop the agent after its current step (see `AgentEngine::shutdown_gracefully`).
+    pub fn shutdown_gracefully(&self, timeout: std::time::Duration) {
+        self.shutdown.request(&self.cancel, timeout);
+    }
+
+    /// The underlying watch receiver, for `select!` loops.
+    pub fn receiver(&self) -> watch::Receiver<AgentStatus> {
+        self.rx.clone()
+    }
+}
@@ -163,7 +163,7 @@ impl HealingPolicy {
             HealingTrigger::ToolError(substr) => {
                 // Check if the most recent tool call failed with an error containing substr
                 if let Some(last) = memory.history.last() {
-                    if !last.success && last.observation.contains(substr.as_str()) {
+                    if !last.is_success() && last.observation.content.contains(substr.as_str()) {
                         return true;
                     }
                 }
@@ -189,7 +189,7 @@ impl HealingPolicy {
                 if len < *n {
                     return false;
                 }
-                memory.history.iter().rev().take(*n).all(|h| !h.success)
+                memory.history.iter().rev().take(*n).all(|h| !h.is_success())
             }
             HealingTrigger::ConfidenceBelow(threshold) => memory.confidence_score < *threshold,
             HealingTrigger::BudgetPctAbove(pct) => {
@@ -286,7 +286,7 @@ pub fn apply_healing(action: &HealingAction, memory: &mut AgentMemory) -> Healin
                 let summary = if let Some(last) = memory.history.last() {
                     format!(
                         "Based on {} steps of analysis: {}",
-                        memory.step, last.observation
+                        memory.step, last.observation.content
                     )
                 } else {
                     format!("Task completed after {} steps.", memory.step)
@@ -320,7 +320,7 @@ pub fn apply_healing(action: &HealingAction, memory: &mut AgentMemory) -> Healin
 mod tests {
     use super::*;
     use crate::memory::AgentMemory;
-    use crate::types::{HistoryEntry, ToolCall};
+    use crate::types::{HistoryEntry, Observation, ToolCall};
 
     fn make_memory() -> AgentMemory {
         AgentMemory::new("test task")
@@ -334,8 +334,11 @@ mod tests {
                 name: name.to_string(),
                 args: Default::default(),
             },
-            observation: obs.to_string(),
-            success,
+            observation: Observation::from_outcome(success, obs),
+            assistant_text: None,
+            latency_ms: None,
+            model_used: None,
+            usage: None,
         });
     }
 
@@ -6,7 +6,9 @@
 use crate::error::AgentError;
 use crate::events::Event;
 use crate::memory::AgentMemory;
-use crate::types::LlmResponse;
+use crate::types::{LlmResponse, RunResult};
+use futures::future::BoxFuture;
+use futures::FutureExt;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::panic::{catch_unwind, AssertUnwindSafe};
@@ -87,6 +89,7 @@ impl CompositeHooks {
         Self { hooks: Vec::new() }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn add(mut self, hook: Arc<dyn AgentHooks>) -> Self {
         self.hooks.push(hook);
         self
@@ -178,6 +181,7 @@ impl AgentHooks for PrintHooks {
             LlmResponse::ParallelToolCalls { .. } => "parallel_tool_calls",
             LlmResponse::FinalAnswer { .. } => "final_answer",
             LlmResponse::Structured { .. } => "structured",
+            LlmResponse::Truncated { .. } => "truncated",
         };
         println!("\x1b[33m✓ LLM ({}) → {}\x1b[0m", model, kind);
     }
@@ -216,6 +220,57 @@ impl AgentHooks for PrintHooks {
     }
 }
 
+// ─────────────────────────────────────────────────────────────────────────────
+// RunHooks  (async on_complete / on_error callbacks)
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Async callback given the result of a successful run.
+pub type CompletionHook = Arc<dyn Fn(RunResult) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Async callback given the error of a failed run.
+pub type ErrorHook = Arc<dyn Fn(AgentError) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Callbacks added with `AgentBuilder::on_complete` / `on_error`.
+///
+/// They run in registration order once the run has ended, and `run()`
+/// returns after the last one finishes — the place to upload artifacts,
+/// notify other systems or write run records. A hook that panics is logged
+/// (`RUN_HOOK_PANICKED`) and the remaining hooks still run; the run's
+/// result is never changed by a hook.
+#[derive(Clone, Default)]
+pub struct RunHooks {
+    pub on_complete: Vec<CompletionHook>,
+    pub on_error: Vec<ErrorHook>,
+}
+
+impl RunHooks {
+    pub fn is_empty(&self) -> bool {
+        self.on_complete.is_empty() && self.on_error.is_empty()
+    }
+}
+
+/// Run the `on_complete` or `on_error` hooks for `result`.
+pub(crate) async fn run_completion_hooks(memory: &mut AgentMemory, result: &Result<String, AgentError>) {
+    let hooks = memory.run_hooks.clone();
+    let futures: Vec<BoxFuture<'static, ()>> = match result {
+        Ok(answer) => {
+            let run_result = RunResult {
+                answer: answer.clone(),
+                citations: memory.citations.clone(),
+            };
+            hooks.on_complete.iter().map(|hook| hook(run_result.clone())).collect()
+        }
+        Err(e) => hooks.on_error.iter().map(|hook| hook(e.clone())).collect(),
+    };
+    for (index, future) in futures.into_iter().enumerate() {
+        if let Err(panic) = AssertUnwindSafe(future).catch_unwind().await {
+            let msg = panic_message(panic.as_ref());
+            tracing::error!(error = %msg, "Run hook panicked — continuing");
+            memory.log("Engine", "RUN_HOOK_PANICKED", &format!("hook={} error={}", index, msg));
+        }
+    }
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Panic-safe helpers used by engine/states to call hooks
 // ─────────────────────────────────────────────────────────────────────────────
@@ -224,13 +279,17 @@ impl AgentHooks for PrintHooks {
 pub fn safe_hook<F: FnOnce()>(f: F) {
     let result = catch_unwind(AssertUnwindSafe(f));
     if let Err(panic) = result {
-        let msg = if let Some(s) = panic.downcast_ref::<&str>() {
-            s.to_string()
-        } else if let Some(s) = panic.downcast_ref::<String>() {
-            s.clone()
-        } else {
-            "unknown panic".to_string()
-        };
+        let msg = panic_message(panic.as_ref());
         tracing::error!(error = %msg, "AgentHooks callback panicked — continuing");
     }
 }
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
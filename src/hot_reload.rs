@@ -0,0 +1,193 @@
+//! Hot-reloadable configuration — tune long-running agents without restarts.
+//!
+//! The engine polls a JSON file between steps. When its modification time
+//! changes, the system prompt, model map and tool blacklist are reloaded
+//! and a `CONFIG_RELOADED` trace event is recorded. Fields missing from the
+//! file are left untouched.
+//!
+//! ```json
+//! {
+//!   "system_prompt": "You are a careful research assistant.",
+//!   "models": { "default": "gpt-4o-mini" },
+//!   "blacklisted_tools": ["delete_file"]
+//! }
+//! ```
+
+use crate::memory::AgentMemory;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// ReloadableConfig
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The subset of agent configuration that can change while running.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReloadableConfig {
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub models: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub blacklisted_tools: Option<Vec<String>>,
+}
+
+impl ReloadableConfig {
+    /// Apply to memory. Returns the names of the fields that were set.
+    pub fn apply(&self, memory: &mut AgentMemory) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if let Some(ref prompt) = self.system_prompt {
+            memory.system_prompt = prompt.clone();
+            changed.push("system_prompt");
+        }
+        if let Some(ref models) = self.models {
+            memory.config.models = models.clone();
+            changed.push("models");
+        }
+        if let Some(ref tools) = self.blacklisted_tools {
+            memory.blacklisted_tools = tools.iter().cloned().collect::<HashSet<_>>();
+            changed.push("blacklisted_tools");
+        }
+        changed
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// ConfigWatcher
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Watches a config file by modification time.
+#[derive(Debug, Clone)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            last_modified: None,
+        }
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Returns the new config if the file changed since the last poll.
+    ///
+    /// A missing file is not an error — nothing is reloaded until it appears.
+    /// A file that fails to parse is reported once per modification.
+    pub fn poll(&mut self) -> Option<Result<ReloadableConfig, String>> {
+        let modified = std::fs::metadata(&self.path).and_then(|m| m.modified()).ok()?;
+        if self.last_modified == Some(modified) {
+            return None;
+        }
+        self.last_modified = Some(modified);
+
+        let result = std::fs::read_to_string(&self.path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_json::from_str(&data).map_err(|e| e.to_string()));
+        Some(result)
+    }
+
+    /// Poll and apply any change to memory, logging the outcome to the trace.
+    pub fn reload_into(&mut self, memory: &mut AgentMemory) -> bool {
+        match self.poll() {
+            Some(Ok(config)) => {
+                let changed = config.apply(memory);
+                memory.log(
+                    "Engine",
+                    "CONFIG_RELOADED",
+                    &format!("path={} fields={}", self.path.display(), changed.join(",")),
+                );
+                true
+            }
+            Some(Err(e)) => {
+                tracing::warn!(path = %self.path.display(), error = %e, "Config reload failed");
+                memory.log(
+                    "Engine",
+                    "CONFIG_RELOAD_FAILED",
+                    &format!("path={} error={}", self.path.display(), e),
+                );
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_memory() -> AgentMemory {
+        let mut m = AgentMemory::new("test");
+        m.system_prompt = "old".into();
+        m.blacklist_tool("rm");
+        m
+    }
+
+    #[test]
+    fn test_apply_only_present_fields() {
+        let mut memory = make_memory();
+        let cfg = ReloadableConfig {
+            system_prompt: Some("new".into()),
+            ..Default::default()
+        };
+        assert_eq!(cfg.apply(&mut memory), vec!["system_prompt"]);
+        assert_eq!(memory.system_prompt, "new");
+        assert!(memory.blacklisted_tools.contains("rm"));
+    }
+
+    #[test]
+    fn test_missing_file_is_ignored() {
+        let mut watcher = ConfigWatcher::new("/nonexistent/agent-config.json");
+        assert!(watcher.poll().is_none());
+    }
+
+    #[test]
+    fn test_reload_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.json");
+        std::fs::write(
+            &path,
+            r#"{"system_prompt": "v1", "models": {"default": "m1"}, "blacklisted_tools": []}"#,
+        )
+        .unwrap();
+
+        let mut watcher = ConfigWatcher::new(&path);
+        let mut memory = make_memory();
+        assert!(watcher.reload_into(&mut memory));
+        assert_eq!(memory.system_prompt, "v1");
+        assert_eq!(memory.config.models["default"], "m1");
+        assert!(memory.blacklisted_tools.is_empty());
+        assert_eq!(memory.trace.entries().last().unwrap().event, "CONFIG_RELOADED");
+
+        // Unchanged file → nothing to do
+        assert!(!watcher.reload_into(&mut memory));
+    }
+
+    #[test]
+    fn test_invalid_file_reports_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.json");
+        std::fs::write(&path, "not json").unwrap();
+
+        let mut watcher = ConfigWatcher::new(&path);
+        let mut memory = make_memory();
+        assert!(!watcher.reload_into(&mut memory));
+        assert_eq!(memory.system_prompt, "old");
+        assert_eq!(
+            memory.trace.entries().last().unwrap().event,
+            "CONFIG_RELOAD_FAILED"
+        );
+    }
+}
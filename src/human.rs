@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 
@@ -15,6 +16,10 @@ pub struct HumanApprovalRequest {
     pub tool_args: HashMap<String, serde_json::Value>,
     pub risk_level: RiskLevel,
     pub reason: String,
+    /// Every call of the parallel batch this decision covers, the named
+    /// call included. Empty for a single call.
+    #[serde(default)]
+    pub batch: Vec<crate::types::ToolCall>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +30,69 @@ pub enum HumanDecision {
         tool_name: String,
         tool_args: HashMap<String, serde_json::Value>,
     },
+    /// A decision with who made it. Built with `by` and `with_metadata`.
+    Attributed {
+        decision: Box<HumanDecision>,
+        attribution: DecisionAttribution,
+    },
+}
+
+/// Who made a decision, plus anything else the operator wants on record
+/// (ticket numbers, comments, ...).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DecisionAttribution {
+    pub decided_by: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+impl HumanDecision {
+    /// Record the principal who made this decision.
+    pub fn by(self, principal: impl Into<String>) -> Self {
+        let (decision, mut attribution) = self.into_parts();
+        attribution.decided_by = Some(principal.into());
+        decision.attributed(attribution)
+    }
+
+    /// Attach a metadata entry to this decision.
+    pub fn with_metadata(self, key: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        let (decision, mut attribution) = self.into_parts();
+        attribution.metadata.insert(key.into(), value.into());
+        decision.attributed(attribution)
+    }
+
+    /// The plain decision and its attribution (empty if none was given).
+    pub fn into_parts(self) -> (HumanDecision, DecisionAttribution) {
+        match self {
+            Self::Attributed { decision, attribution } => (*decision, attribution),
+            other => (other, DecisionAttribution::default()),
+        }
+    }
+
+    fn attributed(self, attribution: DecisionAttribution) -> Self {
+        Self::Attributed { decision: Box::new(self), attribution }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecisionOutcome {
+    Approved,
+    Rejected,
+    Modified,
+}
+
+/// One human decision, kept in `AgentMemory::human_decisions` and saved
+/// with checkpoints.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecisionRecord {
+    pub step: usize,
+    pub tool_name: String,
+    pub outcome: DecisionOutcome,
+    pub decided_by: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,27 +102,143 @@ pub enum ApprovalPolicy {
     #[default]
     NeverAsk,
     AskAbove(RiskLevel),
-    ToolBased(HashMap<String, RiskLevel>),
+    /// Per-tool risks: ask when a tool's risk meets or exceeds `threshold`.
+    /// Tools missing from `risks` fall back to their declared risk, then `Low`.
+    ToolBased {
+        risks:     HashMap<String, RiskLevel>,
+        threshold: RiskLevel,
+    },
 }
 
-
 impl ApprovalPolicy {
-    pub fn needs_approval(&self, tool_name: &str, _args: &HashMap<String, serde_json::Value>) -> bool {
+    /// A `ToolBased` policy asking for approval at `threshold` and above.
+    pub fn tool_based(risks: HashMap<String, RiskLevel>, threshold: RiskLevel) -> Self {
+        Self::ToolBased { risks, threshold }
+    }
+
+    pub fn needs_approval(&self, tool_name: &str, args: &HashMap<String, serde_json::Value>) -> bool {
+        self.needs_approval_for(tool_name, args, None)
+    }
+
+    /// Like `needs_approval`, taking into account the risk the tool itself
+    /// declares (`Tool::risk`).
+    pub fn needs_approval_for(
+        &self,
+        tool_name: &str,
+        _args: &HashMap<String, serde_json::Value>,
+        declared: Option<RiskLevel>,
+    ) -> bool {
         match self {
             Self::AlwaysAsk => true,
             Self::NeverAsk => false,
-            Self::AskAbove(threshold) => {
-                // Default risk for unknown tools is Medium.
-                // Ask for approval if the default risk meets or exceeds the threshold.
-                let default_risk = RiskLevel::Medium;
-                default_risk >= *threshold
-            }
-            Self::ToolBased(map) => {
-                // Look up the tool's risk level; default to Low for unregistered tools.
-                // Approval is required if the tool's risk is High or above.
-                let risk = map.get(tool_name).copied().unwrap_or(RiskLevel::Low);
-                risk >= RiskLevel::High
+            // Ask for approval if the tool's risk meets or exceeds the threshold.
+            Self::AskAbove(threshold) | Self::ToolBased { threshold, .. } => {
+                self.risk_of(tool_name, declared) >= *threshold
             }
         }
     }
+
+    /// The risk of calling `tool_name`: its entry in a `ToolBased` map, else
+    /// the risk the tool declares, else `Low` under `ToolBased` and `Medium`
+    /// under the other policies.
+    pub fn risk_of(&self, tool_name: &str, declared: Option<RiskLevel>) -> RiskLevel {
+        match self {
+            Self::ToolBased { risks, .. } => risks
+                .get(tool_name)
+                .copied()
+                .or(declared)
+                .unwrap_or(RiskLevel::Low),
+            _ => declared.unwrap_or(RiskLevel::Medium),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_args() -> HashMap<String, serde_json::Value> {
+        HashMap::new()
+    }
+
+    fn risks(entries: &[(&str, RiskLevel)]) -> HashMap<String, RiskLevel> {
+        entries.iter().map(|(n, r)| (n.to_string(), *r)).collect()
+    }
+
+    #[test]
+    fn always_ask_asks_for_every_tool() {
+        let policy = ApprovalPolicy::AlwaysAsk;
+        assert!(policy.needs_approval("read_file", &no_args()));
+        assert!(policy.needs_approval_for("read_file", &no_args(), Some(RiskLevel::Low)));
+    }
+
+    #[test]
+    fn never_ask_never_asks() {
+        let policy = ApprovalPolicy::NeverAsk;
+        assert!(!policy.needs_approval("drop_table", &no_args()));
+        assert!(!policy.needs_approval_for("drop_table", &no_args(), Some(RiskLevel::Critical)));
+    }
+
+    #[test]
+    fn ask_above_compares_declared_risk_to_threshold() {
+        let policy = ApprovalPolicy::AskAbove(RiskLevel::High);
+        assert!(!policy.needs_approval_for("t", &no_args(), Some(RiskLevel::Medium)));
+        assert!(policy.needs_approval_for("t", &no_args(), Some(RiskLevel::High)));
+        assert!(policy.needs_approval_for("t", &no_args(), Some(RiskLevel::Critical)));
+    }
+
+    #[test]
+    fn ask_above_treats_undeclared_risk_as_medium() {
+        assert!(ApprovalPolicy::AskAbove(RiskLevel::Medium).needs_approval("t", &no_args()));
+        assert!(!ApprovalPolicy::AskAbove(RiskLevel::High).needs_approval("t", &no_args()));
+    }
+
+    #[test]
+    fn tool_based_honors_its_threshold() {
+        let policy = ApprovalPolicy::tool_based(
+            risks(&[("write_file", RiskLevel::Medium), ("read_file", RiskLevel::Low)]),
+            RiskLevel::Medium,
+        );
+        assert!(policy.needs_approval("write_file", &no_args()));
+        assert!(!policy.needs_approval("read_file", &no_args()));
+
+        let strict = ApprovalPolicy::tool_based(risks(&[("write_file", RiskLevel::Medium)]), RiskLevel::High);
+        assert!(!strict.needs_approval("write_file", &no_args()));
+    }
+
+    #[test]
+    fn tool_based_map_overrides_declared_risk() {
+        let policy = ApprovalPolicy::tool_based(risks(&[("t", RiskLevel::Low)]), RiskLevel::High);
+        assert!(!policy.needs_approval_for("t", &no_args(), Some(RiskLevel::Critical)));
+        assert_eq!(policy.risk_of("t", Some(RiskLevel::Critical)), RiskLevel::Low);
+    }
+
+    #[test]
+    fn tool_based_falls_back_to_declared_then_low() {
+        let policy = ApprovalPolicy::tool_based(HashMap::new(), RiskLevel::High);
+        assert!(policy.needs_approval_for("t", &no_args(), Some(RiskLevel::High)));
+        assert!(!policy.needs_approval("t", &no_args()));
+        assert_eq!(policy.risk_of("t", None), RiskLevel::Low);
+    }
+
+    #[test]
+    fn attribution_wraps_once_and_unwraps() {
+        let decision = HumanDecision::Approved
+            .by("alice")
+            .with_metadata("ticket", "OPS-1")
+            .by("bob");
+        let (plain, attribution) = decision.into_parts();
+        assert!(matches!(plain, HumanDecision::Approved));
+        assert_eq!(attribution.decided_by.as_deref(), Some("bob"));
+        assert_eq!(attribution.metadata["ticket"], "OPS-1");
+
+        let (plain, attribution) = HumanDecision::Rejected("no".into()).into_parts();
+        assert!(matches!(plain, HumanDecision::Rejected(_)));
+        assert_eq!(attribution, DecisionAttribution::default());
+    }
+
+    #[test]
+    fn default_policy_is_never_ask() {
+        assert!(matches!(ApprovalPolicy::default(), ApprovalPolicy::NeverAsk));
+    }
 }
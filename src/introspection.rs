@@ -207,7 +207,7 @@ impl IntrospectionEngine {
             // Only record new entries
             for entry in memory.history.iter().skip(tracked) {
                 let args_val = serde_json::to_value(&entry.tool.args).unwrap_or_default();
-                self.record_tool_call(&entry.tool.name, &args_val, entry.success);
+                self.record_tool_call(&entry.tool.name, &args_val, entry.is_success());
             }
         }
 
@@ -396,7 +396,7 @@ impl IntrospectionEngine {
 mod tests {
     use super::*;
     use crate::memory::AgentMemory;
-    use crate::types::{HistoryEntry, ToolCall};
+    use crate::types::{HistoryEntry, Observation, ToolCall};
 
     fn make_memory() -> AgentMemory {
         AgentMemory::new("test task")
@@ -410,8 +410,11 @@ mod tests {
                 name: name.to_string(),
                 args: Default::default(),
             },
-            observation: if success { "ok" } else { "ERROR" }.into(),
-            success,
+            observation: Observation::from_outcome(success, if success { "ok" } else { "failed" }),
+            assistant_text: None,
+            latency_ms: None,
+            model_used: None,
+            usage: None,
         }
     }
 
@@ -1,53 +1,135 @@
+pub mod arg_repair;
+pub mod audit;
+pub mod batch;
 pub mod budget;
+pub mod bundle;
 pub mod builder;
+pub mod builders;
 pub mod cache;
+pub mod cancel;
 pub mod checkpoint;
+pub mod checkpoint_writer;
+pub mod citations;
 pub mod contracts;
+pub mod debate;
+pub mod debugger;
+pub mod dedup;
+pub mod dry_run;
+pub mod embedding;
 pub mod engine;
 pub mod error;
+pub mod event_sourcing;
 pub mod events;
 pub mod fork;
+pub mod grammar;
+pub mod handle;
 pub mod healing;
 pub mod hooks;
+pub mod hot_reload;
 pub mod human;
 pub mod introspection;
 pub mod llm;
+pub mod locale;
 pub mod mcp;
 pub mod memory;
 pub mod memory_strategy;
+pub mod moderation;
+#[cfg(feature = "tui")]
+pub mod monitor;
+pub mod notify;
+pub mod observability;
+pub mod orchestra;
 pub mod plan;
+pub mod policy;
+pub mod postprocess;
+pub mod preflight;
 pub mod prompt;
+pub mod prompter;
+pub mod queue;
 pub mod replay;
 pub mod routing;
+pub mod sanitizer;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod sessions;
+pub mod speculation;
 pub mod states;
+pub mod suspend;
 pub mod tool_synthesis;
 pub mod tools;
 pub mod trace;
 pub mod transitions;
 pub mod types;
+pub mod workspace;
 
 // Convenience re-exports at crate root
+pub use audit::{AuditRecord, AuditSink, HostFingerprint, JsonlAuditSink, MemoryAuditSink};
+pub use batch::{
+    AnthropicBatchProvider, BatchProvider, BatchRequest, BatchRunner, BatchStatus,
+    OpenAiBatchProvider,
+};
 pub use builder::AgentBuilder;
-pub use cache::{CacheStats, InMemoryCache, LlmCache, NoopCache};
+pub use bundle::{Artifact, ArtifactContent, ConfigFingerprint, SessionBundle};
+pub use cache::{CacheStats, DiskCache, InMemoryCache, LlmCache, NoopCache};
+pub use cancel::{CancelOnDrop, CancellationToken, ShutdownSignal};
+pub use citations::Citation;
 pub use contracts::{
     ContractSet, ContractViolationAction, GuardFailAction, Invariant, InvariantFailAction,
     PostCondition, PostConditionFailAction, TransitionGuard,
 };
-pub use engine::AgentEngine;
+pub use debate::{DebateArgument, DebateConfig, DebateState, Debater};
+pub use debugger::{diff_checkpoints, Debugger, StepDiff, TimelinePoint};
+pub use dedup::{ObservationDedup, SemanticDedup};
+pub use dry_run::{ChangePlan, ExecutionMode};
+pub use embedding::{cosine_similarity, EmbeddingProvider, OpenAiEmbeddings};
+pub use engine::{AgentEngine, OutputReceiver};
 pub use error::AgentError;
+pub use event_sourcing::MemoryDelta;
 pub use events::Event;
 pub use fork::{
     fork_memory, select_best, ConfidenceScorer, ForkConfig, ForkResult, ForkScorer, MergeStrategy,
     StepEfficiencyScorer, ToolSuccessRateScorer,
 };
+pub use grammar::{tool_call_gbnf, tool_call_schema, GrammarConstraint};
+pub use handle::{AgentHandle, AgentStatus};
 pub use healing::{apply_healing, HealingAction, HealingOutcome, HealingPolicy, HealingTrigger};
 pub use hooks::{AgentHooks, CompositeHooks, NoopHooks, PrintHooks};
+pub use hot_reload::{ConfigWatcher, ReloadableConfig};
 pub use introspection::{Anomaly, IntrospectionConfig, IntrospectionEngine};
-pub use llm::{AsyncLlmCaller, LlmCaller, LlmCallerExt, RetryingLlmCaller};
+pub use llm::{
+    AsyncLlmCaller, CachingLlmCaller, ChatTemplate, CoalesceStats, CoalescingLlmCaller, CompletionCaller,
+    FallbackLlmCaller, JsonlRawLogSink, LlmCaller, LlmCallerExt, LlmError, MemoryRawLogSink, ModelCapabilities,
+    RateLimitedLlmCaller, RateLimiter, RawExchange, RawLogSink, RetryingLlmCaller,
+};
+#[cfg(feature = "candle")]
+pub use llm::{CandleArch, CandleCaller, CandleParams};
+#[cfg(feature = "llama-cpp")]
+pub use llm::{LlamaCppCaller, LlamaCppParams};
+pub use locale::ObservationMarkers;
 pub use memory::AgentMemory;
 pub use memory_strategy::{FullMemory, MemoryStrategy, SlidingWindowMemory, SummaryMemory};
+pub use moderation::{
+    Moderation, ModerationHook, ModerationVerdict, OnAnswerBlocked, OpenAiModerator, RuleModerator,
+};
+// `orchestra::PipelineResult` stays in its module: the root name belongs to tool_synthesis
+pub use orchestra::{
+    Branch, Pipeline, PipelineError, Stage, StageFailure, StageOutcome, StageReport,
+};
+pub use notify::{HttpNotifier, Notification, NotificationKind, Notifications, Notifier, SlackNotifier};
+pub use observability::{ObservabilityControl, TokenEvents, TraceLevel};
 pub use plan::{AgentPlan, PlanRevisionTrigger, PlanStep, PlanningMode, StepStatus};
+pub use policy::{
+    CedarPolicyEngine, OpaPolicyEngine, PolicyAction, PolicyDecision, PolicyEngine, PolicyRequest,
+};
+pub use postprocess::{
+    CitationFootnotes, FinalAnswerTransform, LanguageEnforcer, MarkdownCleanup, TemplateWrap,
+};
 pub use prompt::{PromptError, PromptTemplate};
+pub use prompter::{
+    JsonPrompter, NativePrompter, PlanningPrompter, ReActPrompter, TextReply, TextToolFormat,
+    XmlPrompter,
+};
+pub use queue::{InMemoryQueue, QueueWorker, QueuedTask, TaskQueue, WorkerStats};
 pub use replay::{
     DiffKind, Patch, ReplayDiffEntry, ReplayEngine, ReplayEntry, ReplayEntryKind, ReplayRecorder,
     ReplayRecording,
@@ -56,13 +138,22 @@ pub use routing::{
     BudgetPctAbove, ConfidenceBelow, RoutingCondition, RoutingPolicy, RoutingRule, StepAbove,
     ToolFailureRateAbove,
 };
+pub use sanitizer::{ObservationSanitizer, SanitizeAction};
+pub use sessions::{SessionInfo, SessionManager};
+pub use speculation::SpeculativePlanning;
+pub use suspend::{wait_tool, Suspension};
 pub use tool_synthesis::{
     CompositeToolRegistry, CompositeToolSpec, CompositionConfig, PipelineResult, ToolPipelineStep,
     ToolSource,
 };
-pub use tools::{Tool, ToolFn, ToolRegistry};
+pub use tools::{
+    parse_tool_args, upgrade_tool_fn, SharedToolRegistry, Tool, ToolContext, ToolExtensions, ToolFn,
+    ToolFnV2, ToolRegistry, RAW_ARGS_KEY,
+};
 pub use trace::{Trace, TraceEntry};
 pub use types::{
-    AgentConfig, AgentOutput, HistoryEntry, LlmResponse, LlmStreamChunk, OutputSchema, State,
-    ToolCall,
+    AgentConfig, AgentOutput, HistoryEntry, LlmResponse, LlmStreamChunk, ModelParams, Observation,
+    ObservationStatus, OutputSchema, ReasoningEffort, ReflectionTrigger, RepeatedCallAction, RunResult,
+    SamplingParams, State, ToolCall,
 };
+pub use workspace::{Workspace, WorkspaceCleanup};
@@ -1,30 +1,72 @@
 use async_trait::async_trait;
-use crate::llm::AsyncLlmCaller;
+use crate::llm::{AsyncLlmCaller, LlmError};
 use crate::memory::AgentMemory;
-use crate::tools::ToolRegistry;
-use crate::types::{LlmResponse, ToolCall};
+use crate::tools::{parse_tool_args, ToolRegistry};
+use crate::types::{LlmResponse, LlmStreamChunk, ModelParams, ReasoningEffort, ToolCall};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Output token limit when `SamplingParams::max_tokens` is unset; the API requires one.
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+/// Most models `GET /v1/models` returns per page.
+const MODEL_PAGE_LIMIT: usize = 1000;
+/// Smallest thinking budget the API accepts.
+const MIN_THINKING_BUDGET: u32 = 1024;
+/// Tool-use replies whose thinking blocks are kept to send back.
+const THINKING_REPLIES_KEPT: usize = 64;
+/// The synthetic tool structured output is requested through.
+pub(crate) const STRUCTURED_OUTPUT_TOOL: &str = "__structured_output";
 
 // ── Anthropic request types ──────────────────────────────
 
 #[derive(serde::Serialize)]
 struct AnthropicRequest {
     model:      String,
-    max_tokens: u32,
+    #[serde(flatten)]
+    sampling:   AnthropicSampling,
     system:     Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     tools:      Vec<AnthropicToolDef>,
     messages:   Vec<AnthropicMessage>,
     stream:     bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking:    Option<serde_json::Value>,
 }
 
+/// Sampling fields of a request, from the model's `ModelParams`. The API
+/// requires an output token limit and has no seed parameter.
 #[derive(serde::Serialize)]
-struct AnthropicToolDef {
+pub(crate) struct AnthropicSampling {
+    max_tokens:  u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p:       Option<f32>,
+}
+
+impl AnthropicSampling {
+    pub(crate) fn new(params: &ModelParams, default_max_tokens: u32) -> Self {
+        Self {
+            max_tokens:  params.max_tokens.unwrap_or(default_max_tokens),
+            temperature: params.temperature,
+            top_p:       params.top_p,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct AnthropicToolDef {
     name:         String,
     description:  String,
     input_schema: serde_json::Value,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
-struct AnthropicMessage {
+pub(crate) struct AnthropicMessage {
     role:    String,
     content: serde_json::Value,   // string or array of content blocks
 }
@@ -32,7 +74,6 @@ struct AnthropicMessage {
 // ── Anthropic response types ─────────────────────────────
 
 #[derive(serde::Deserialize, Debug)]
-#[allow(dead_code)]
 struct AnthropicResponse {
     content:     Vec<AnthropicContentBlock>,
     stop_reason: Option<String>,
@@ -41,6 +82,8 @@ struct AnthropicResponse {
 
 #[derive(serde::Deserialize, Debug)]
 struct AnthropicUsage {
+    /// Absent from `message_delta` events, which only count output
+    #[serde(default)]
     input_tokens:  u32,
     output_tokens: u32,
 }
@@ -57,6 +100,27 @@ enum AnthropicContentBlock {
         name:  String,
         input: serde_json::Value,
     },
+
+    #[serde(rename = "thinking")]
+    Thinking {
+        thinking:  String,
+        /// Empty in `content_block_start`; streamed as a `signature_delta`
+        #[serde(default)]
+        signature: String,
+    },
+
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct AnthropicModelList {
+    data: Vec<AnthropicModel>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct AnthropicModel {
+    id: String,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -81,11 +145,16 @@ enum AnthropicStreamEvent {
 
 #[derive(serde::Deserialize, Debug)]
 #[serde(tag = "type")]
+#[allow(clippy::enum_variant_names)]
 enum AnthropicDelta {
     #[serde(rename = "text_delta")]
     TextDelta { text: String },
     #[serde(rename = "input_json_delta")]
     InputJsonDelta { partial_json: String },
+    #[serde(rename = "thinking_delta")]
+    ThinkingDelta { thinking: String },
+    #[serde(rename = "signature_delta")]
+    SignatureDelta { signature: String },
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -101,6 +170,10 @@ pub struct AnthropicCaller {
     client:  reqwest::Client,
     api_key: String,
     api_base: String,
+    thinking_budget: Option<u32>,
+    /// Signed thinking blocks of recent tool-use replies, by first tool call
+    /// id. The API needs them back with the calls while thinking is on.
+    thinking_blocks: Mutex<VecDeque<(String, Vec<serde_json::Value>)>>,
 }
 
 impl AnthropicCaller {
@@ -109,9 +182,20 @@ impl AnthropicCaller {
             client:   reqwest::Client::new(),
             api_key:  api_key.into(),
             api_base: "https://api.anthropic.com".to_string(),
+            thinking_budget: None,
+            thinking_blocks: Mutex::new(VecDeque::new()),
         }
     }
 
+    /// Turn on extended thinking with up to `budget_tokens` (at least 1024)
+    /// of reasoning per call. The budget is added to the output token limit,
+    /// and no temperature is sent, as the API requires. Thinking streams as
+    /// `LlmStreamChunk::Reasoning`.
+    pub fn with_thinking(mut self, budget_tokens: u32) -> Self {
+        self.thinking_budget = Some(budget_tokens.max(MIN_THINKING_BUDGET));
+        self
+    }
+
     pub fn from_env() -> Result<Self, String> {
         let key = std::env::var("ANTHROPIC_API_KEY")
             .map_err(|_| "ANTHROPIC_API_KEY not set".to_string())?;
@@ -126,19 +210,196 @@ impl AnthropicCaller {
         }).collect()
     }
 
-    fn build_messages(memory: &AgentMemory) -> Vec<AnthropicMessage> {
-        // Convert memory.build_messages() (serde_json::Value array)
-        // into Vec<AnthropicMessage>
-        // Filter out "system" role (sent separately in AnthropicRequest.system)
-        memory.build_messages()
-            .into_iter()
-            .filter(|m| m["role"] != "system")
-            .map(|m| AnthropicMessage {
-                role:    m["role"].as_str().unwrap_or("user").to_string(),
+    /// `tool_choice` that disables parallel tool use when the config asks for it.
+    fn build_tool_choice(memory: &AgentMemory, has_tools: bool) -> Option<serde_json::Value> {
+        if has_tools && !memory.config.parallel_tools {
+            Some(serde_json::json!({ "type": "auto", "disable_parallel_tool_use": true }))
+        } else {
+            None
+        }
+    }
+
+    /// First page of `GET /v1/models`.
+    async fn get_models(&self, limit: usize) -> Result<AnthropicModelList, LlmError> {
+        let response = self.client
+            .get(format!("{}/v1/models?limit={}", self.api_base, limit))
+            .header("x-api-key",         &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .send()
+            .await
+            .map_err(|e| LlmError::Network(format!("Network error: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(super::error::from_response(response, "Anthropic API error").await);
+        }
+        response.json()
+            .await
+            .map_err(|e| LlmError::Parse(format!("Failed to parse Anthropic model list: {}", e)))
+    }
+
+    /// Convert memory.build_messages() into Anthropic messages. Tool calls
+    /// become `tool_use` blocks, after the thinking that produced them, and
+    /// tool results become `tool_result` blocks in one user message. The
+    /// system message is sent separately in AnthropicRequest.system.
+    fn build_messages(&self, memory: &AgentMemory) -> Vec<AnthropicMessage> {
+        anthropic_messages(memory.build_messages(), |id| self.thinking_for(id))
+    }
+
+    /// The thinking budget for a call: the one set with `with_thinking`,
+    /// or one derived from the model's `reasoning_effort`.
+    fn thinking_budget(&self, params: &ModelParams) -> Option<u32> {
+        self.thinking_budget.or_else(|| match params.reasoning_effort? {
+            ReasoningEffort::Low => Some(2048),
+            ReasoningEffort::Medium => Some(8192),
+            ReasoningEffort::High => Some(24576),
+        })
+    }
+
+    /// Turn on thinking for `body` if a budget applies. While the model is
+    /// in a tool-use loop, its last reply must come back with its signed
+    /// thinking; if those blocks are gone (the caller was rebuilt, e.g. on
+    /// resume), the call is made without thinking.
+    fn apply_thinking(&self, body: &mut AnthropicRequest, params: &ModelParams) {
+        let Some(budget) = self.thinking_budget(params) else {
+            return;
+        };
+        let last = body.messages.iter().rev().find(|m| m.role == "assistant");
+        if last.is_some_and(|m| has_block(m, "tool_use") && !starts_with_thinking(m)) {
+            tracing::warn!("No thinking blocks for the last tool call; calling Anthropic without thinking");
+            return;
+        }
+        body.thinking = Some(serde_json::json!({ "type": "enabled", "budget_tokens": budget }));
+        // Thinking tokens count toward max_tokens
+        body.sampling.max_tokens += budget;
+        // With thinking the API takes no temperature, and top_p only from 0.95 to 1
+        body.sampling.temperature = None;
+        body.sampling.top_p = body.sampling.top_p.map(|p| p.clamp(0.95, 1.0));
+    }
+
+    fn remember_thinking(&self, blocks: Vec<serde_json::Value>, calls: &[ToolCall]) {
+        let Some(id) = calls.first().and_then(|c| c.id.clone()) else {
+            return;
+        };
+        if blocks.is_empty() {
+            return;
+        }
+        let mut kept = self.thinking_blocks.lock().unwrap();
+        if kept.len() >= THINKING_REPLIES_KEPT {
+            kept.pop_front();
+        }
+        kept.push_back((id, blocks));
+    }
+
+    fn thinking_for(&self, tool_call_id: &str) -> Vec<serde_json::Value> {
+        self.thinking_blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(id, _)| id == tool_call_id)
+            .map(|(_, blocks)| blocks.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Appended to the system prompt when structured output is requested.
+pub(crate) fn structured_output_instruction() -> String {
+    format!(
+        "\n\nYou MUST use the '{}' tool to provide your response. \
+         Format your answer as the tool's input arguments conforming to the schema. \
+         Do not respond with plain text.",
+        STRUCTURED_OUTPUT_TOOL
+    )
+}
+
+/// The synthetic tool that takes the answer as input matching `schema`.
+pub(crate) fn structured_output_tool(schema: &crate::types::OutputSchema) -> AnthropicToolDef {
+    AnthropicToolDef {
+        name:         STRUCTURED_OUTPUT_TOOL.to_string(),
+        description:  schema.description.clone()
+            .unwrap_or_else(|| format!("Provide structured output for: {}", schema.name)),
+        input_schema: schema.schema.clone(),
+    }
+}
+
+/// Anthropic messages for OpenAI-style `messages`. System messages are
+/// dropped (they go in `system`), tool calls become `tool_use` blocks and
+/// tool results `tool_result` blocks in one user turn. `thinking` returns
+/// the signed thinking blocks to send before the tool call with that id.
+pub(crate) fn anthropic_messages(
+    source: Vec<serde_json::Value>,
+    thinking: impl Fn(&str) -> Vec<serde_json::Value>,
+) -> Vec<AnthropicMessage> {
+    let mut messages: Vec<AnthropicMessage> = Vec::new();
+    for m in source {
+        match m["role"].as_str().unwrap_or("user") {
+            "system" => {}
+            "tool" => {
+                let block = serde_json::json!({
+                    "type":        "tool_result",
+                    "tool_use_id": m["tool_call_id"],
+                    "content":     m["content"],
+                });
+                match messages.last_mut() {
+                    Some(last) if last.role == "user" && is_tool_results(&last.content) => {
+                        if let Some(blocks) = last.content.as_array_mut() {
+                            blocks.push(block);
+                        }
+                    }
+                    _ => messages.push(AnthropicMessage {
+                        role:    "user".to_string(),
+                        content: serde_json::Value::Array(vec![block]),
+                    }),
+                }
+            }
+            "assistant" if m["tool_calls"].is_array() => {
+                let calls = m["tool_calls"].as_array().cloned().unwrap_or_default();
+                let mut content = calls
+                    .first()
+                    .and_then(|c| c["id"].as_str())
+                    .map(&thinking)
+                    .unwrap_or_default();
+                if let Some(text) = m["content"].as_str().filter(|t| !t.trim().is_empty()) {
+                    content.push(serde_json::json!({ "type": "text", "text": text }));
+                }
+                for call in &calls {
+                    let input = call["function"]["arguments"]
+                        .as_str()
+                        .and_then(|a| serde_json::from_str::<serde_json::Value>(a).ok())
+                        .filter(|v| v.is_object())
+                        .unwrap_or_else(|| serde_json::json!({}));
+                    content.push(serde_json::json!({
+                        "type":  "tool_use",
+                        "id":    call["id"],
+                        "name":  call["function"]["name"],
+                        "input": input,
+                    }));
+                }
+                messages.push(AnthropicMessage {
+                    role:    "assistant".to_string(),
+                    content: serde_json::Value::Array(content),
+                });
+            }
+            role => messages.push(AnthropicMessage {
+                role:    role.to_string(),
                 content: m["content"].clone(),
-            })
-            .collect()
+            }),
+        }
     }
+    messages
+}
+
+fn is_tool_results(content: &serde_json::Value) -> bool {
+    content.as_array().is_some_and(|blocks| blocks.iter().all(|b| b["type"] == "tool_result"))
+}
+
+fn has_block(message: &AnthropicMessage, kind: &str) -> bool {
+    message.content.as_array().is_some_and(|blocks| blocks.iter().any(|b| b["type"] == kind))
+}
+
+fn starts_with_thinking(message: &AnthropicMessage) -> bool {
+    message.content.as_array().and_then(|blocks| blocks.first()).is_some_and(|b| {
+        b["type"] == "thinking" || b["type"] == "redacted_thinking"
+    })
 }
 
 #[async_trait]
@@ -148,10 +409,10 @@ impl AsyncLlmCaller for AnthropicCaller {
         memory: &AgentMemory,
         tools:  &ToolRegistry,
         model:  &str,
-        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
-    ) -> Result<LlmResponse, String> {
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
         let has_output_schema = memory.config.output_schema.is_some();
-        let structured_tool_name = "__structured_output";
+        let structured_tool_name = STRUCTURED_OUTPUT_TOOL;
 
         let system = {
             let base = if memory.system_prompt.is_empty() {
@@ -162,13 +423,7 @@ impl AsyncLlmCaller for AnthropicCaller {
 
             // For structured output, append instructions to use the synthetic tool
             if let Some(ref _schema) = memory.config.output_schema {
-                let extra = format!(
-                    "\n\nYou MUST use the '{}' tool to provide your response. \
-                     Format your answer as the tool's input arguments conforming to the schema. \
-                     Do not respond with plain text.",
-                    structured_tool_name
-                );
-                Some(format!("{}{}", base.unwrap_or_default(), extra))
+                Some(format!("{}{}", base.unwrap_or_default(), structured_output_instruction()))
             } else {
                 base
             }
@@ -179,65 +434,80 @@ impl AsyncLlmCaller for AnthropicCaller {
 
         // For structured output, add a synthetic tool with the schema
         if let Some(ref schema) = memory.config.output_schema {
-            tool_defs.push(AnthropicToolDef {
-                name:         structured_tool_name.to_string(),
-                description:  schema.description.clone()
-                    .unwrap_or_else(|| format!("Provide structured output for: {}", schema.name)),
-                input_schema: schema.schema.clone(),
-            });
+            tool_defs.push(structured_output_tool(schema));
         }
 
-        let body = AnthropicRequest {
+        let params = memory.model_params(model);
+        let mut body = AnthropicRequest {
             model:      model.to_string(),
-            max_tokens: 4096,
+            sampling:   AnthropicSampling::new(&params, DEFAULT_MAX_TOKENS),
             system,
+            tool_choice: Self::build_tool_choice(memory, !tool_defs.is_empty()),
             tools:      tool_defs,
-            messages:   Self::build_messages(memory),
+            messages:   self.build_messages(memory),
             stream:     false,
+            stop_sequences: memory.stop.clone(),
+            thinking:    None,
         };
+        self.apply_thinking(&mut body, &params);
 
-        let response = self.client
+        let request = self.client
             .post(format!("{}/v1/messages", self.api_base))
             .header("x-api-key",         &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("content-type",      "application/json")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("Network error: {}", e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body   = response.text().await.unwrap_or_default();
-            return Err(format!("Anthropic API error {}: {}", status, body));
-        }
+            .json(&body);
+        let text = super::raw_log::send(memory, "anthropic", model, request, "Anthropic API error").await?;
 
-        let parsed: AnthropicResponse = response.json()
-            .await
-            .map_err(|e| format!("Failed to parse Anthropic response: {}", e))?;
+        let parsed: AnthropicResponse = serde_json::from_str(&text)
+            .map_err(|e| LlmError::Parse(format!("Failed to parse Anthropic response: {}", e)))?;
 
         let usage = Some(crate::budget::TokenUsage::new(parsed.usage.input_tokens, parsed.usage.output_tokens));
+        let truncated = parsed.stop_reason.as_deref() == Some("max_tokens");
 
-        // Collect all tool_use and text blocks
+        // Collect all tool_use, text and thinking blocks
         let mut tool_calls = Vec::new();
-        let mut text_content = None;
+        let mut text_blocks: Vec<String> = Vec::new();
+        let mut reasoning: Vec<String> = Vec::new();
+        let mut thinking_blocks = Vec::new();
 
         for block in parsed.content {
             match block {
                 AnthropicContentBlock::ToolUse { id, name, input, .. } => {
                     // Check if this is our synthetic structured output tool
                     if has_output_schema && name == structured_tool_name {
-                        return Ok(LlmResponse::Structured { data: input, usage });
+                        return Ok(LlmResponse::Structured { data: input, usage, model: None });
                     }
-                    let args = serde_json::from_value(input)
-                        .map_err(|e| format!("Invalid tool args: {}", e))?;
+                    let args = match input {
+                        serde_json::Value::Object(map) => map.into_iter().collect(),
+                        serde_json::Value::String(raw) => parse_tool_args(&raw),
+                        other => parse_tool_args(&other.to_string()),
+                    };
                     tool_calls.push(ToolCall { name, args, id: Some(id) });
                 }
                 AnthropicContentBlock::Text { text } => {
-                    text_content = Some(text);
+                    text_blocks.push(text);
+                }
+                AnthropicContentBlock::Thinking { thinking, signature } => {
+                    thinking_blocks.push(serde_json::json!({
+                        "type": "thinking", "thinking": thinking, "signature": signature,
+                    }));
+                    reasoning.push(thinking);
+                }
+                AnthropicContentBlock::RedactedThinking { data } => {
+                    thinking_blocks.push(serde_json::json!({ "type": "redacted_thinking", "data": data }));
                 }
             }
         }
+        if let (Some(tx), false) = (output_tx, reasoning.is_empty()) {
+            let _ = tx.send(crate::types::AgentOutput::Reasoning(reasoning.join("\n")));
+        }
+        self.remember_thinking(thinking_blocks, &tool_calls);
+        let text_content = if text_blocks.is_empty() {
+            None
+        } else {
+            Some(text_blocks.join("\n"))
+        };
 
         // Tool calls take priority over text content
         if tool_calls.len() > 1 {
@@ -245,20 +515,26 @@ impl AsyncLlmCaller for AnthropicCaller {
                 tools: tool_calls,
                 confidence: 1.0,
                 usage,
+                model: None,
             });
         } else if tool_calls.len() == 1 {
             return Ok(LlmResponse::ToolCall {
                 tool: tool_calls.into_iter().next().unwrap(),
                 confidence: 1.0,
+                assistant_text: text_content.filter(|t| !t.trim().is_empty()),
                 usage,
+                model: None,
             });
         }
 
         if let Some(text) = text_content {
-            return Ok(LlmResponse::FinalAnswer { content: text, usage });
+            if truncated {
+                return Ok(LlmResponse::Truncated { content: text, usage, model: None });
+            }
+            return Ok(LlmResponse::FinalAnswer { content: text, confidence: 1.0, usage, model: None });
         }
 
-        Err("Anthropic returned empty content".to_string())
+        Err(LlmError::Parse("Anthropic returned empty content".to_string()))
     }
 
     fn call_stream_async<'a>(
@@ -267,7 +543,7 @@ impl AsyncLlmCaller for AnthropicCaller {
         tools:  &'a ToolRegistry,
         model:  &'a str,
         _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
-    ) -> futures::stream::BoxStream<'a, Result<crate::types::LlmStreamChunk, String>> {
+    ) -> futures::stream::BoxStream<'a, Result<crate::types::LlmStreamChunk, LlmError>> {
         use futures::{StreamExt, stream};
         
         let system = if memory.system_prompt.is_empty() {
@@ -276,118 +552,315 @@ impl AsyncLlmCaller for AnthropicCaller {
             Some(memory.system_prompt.clone())
         };
 
-        let body = AnthropicRequest {
+        let params = memory.model_params(model);
+        let mut body = AnthropicRequest {
             model:      model.to_string(),
-            max_tokens: 4096,
+            sampling:   AnthropicSampling::new(&params, DEFAULT_MAX_TOKENS),
             system,
             tools:      Self::build_tool_defs(tools),
-            messages:   Self::build_messages(memory),
+            messages:   self.build_messages(memory),
             stream:     true,
+            tool_choice: Self::build_tool_choice(memory, !tools.is_empty()),
+            stop_sequences: memory.stop.clone(),
+            thinking:    None,
         };
+        self.apply_thinking(&mut body, &params);
 
         let client = self.client.clone();
         let api_key = self.api_key.clone();
         let api_base = self.api_base.clone();
 
         let s = stream::once(async move {
-            client
+            let request = client
                 .post(format!("{}/v1/messages", api_base))
                 .header("x-api-key",         &api_key)
                 .header("anthropic-version", "2023-06-01")
                 .header("content-type",      "application/json")
-                .json(&body)
-                .send()
-                .await
-                .map_err(|e| format!("Network error: {}", e))
+                .json(&body);
+            super::raw_log::send_streaming(memory, "anthropic", model, request, "Anthropic API error").await
         })
-        .flat_map(|res| {
+        .flat_map(move |res| {
             match res {
-                Ok(resp) if resp.status().is_success() => {
-                    let mut accumulated_content = String::new();
-                    let mut accumulated_tool_id = String::new();
-                    let mut accumulated_tool_name = String::new();
-                    let mut accumulated_tool_args = String::new();
-                    
-                    let accumulated_usage: Option<crate::budget::TokenUsage> = None;
-                    
+                Ok(resp) => {
+                    let mut parser = StreamParser::default();
                     resp.bytes_stream()
-                        .map(|b| b.map_err(|e| format!("Stream error: {}", e)))
-                        .map(move |res| {
-                            let bytes = res?;
-                            let s = String::from_utf8_lossy(&bytes);
-                            let mut chunks = Vec::new();
-                            
-                            for line in s.lines() {
-                                if let Some(data) = line.strip_prefix("data: ") {
-                                    if let Ok(event) = serde_json::from_str::<AnthropicStreamEvent>(data) {
-                                        match event {
-                                            AnthropicStreamEvent::ContentBlockStart { content_block: AnthropicContentBlock::ToolUse { id, name, .. }, .. } => {
-                                                accumulated_tool_id = id;
-                                                accumulated_tool_name = name;
-                                            }
-                                            AnthropicStreamEvent::ContentBlockStart { .. } => {}
-                                            AnthropicStreamEvent::ContentBlockDelta { delta, .. } => {
-                                                match delta {
-                                                    AnthropicDelta::TextDelta { text } => {
-                                                        accumulated_content.push_str(&text);
-                                                        chunks.push(Ok(crate::types::LlmStreamChunk::Content(text)));
-                                                    }
-                                                    AnthropicDelta::InputJsonDelta { partial_json } => {
-                                                        accumulated_tool_args.push_str(&partial_json);
-                                                        chunks.push(Ok(crate::types::LlmStreamChunk::ToolCallDelta {
-                                                            name: Some(accumulated_tool_name.clone()),
-                                                            args_json: accumulated_tool_args.clone(),
-                                                        }));
-                                                    }
-                                                }
-                                            }
-                                            AnthropicStreamEvent::MessageDelta { delta, usage, .. } => {
-                                                // _accumulated_usage is captured but not fully used yet
-                                                let _accumulated_usage = crate::budget::TokenUsage::new(usage.input_tokens, usage.output_tokens);
-                                                if delta.stop_reason.is_some() {
-                                                    if !accumulated_tool_args.is_empty() {
-                                                        let args: std::collections::HashMap<String, serde_json::Value> = 
-                                                            serde_json::from_str(&accumulated_tool_args)
-                                                                .map_err(|e| format!("Failed to parse Anthropic tool args: {}", e))?;
-                                                        chunks.push(Ok(crate::types::LlmStreamChunk::Done(LlmResponse::ToolCall {
-                                                            tool: ToolCall { name: accumulated_tool_name.clone(), args, id: Some(accumulated_tool_id.clone()) },
-                                                            confidence: 1.0,
-                                                            usage: accumulated_usage,
-                                                        })));
-                                                    } else if !accumulated_content.is_empty() {
-                                                        chunks.push(Ok(crate::types::LlmStreamChunk::Done(LlmResponse::FinalAnswer {
-                                                            content: accumulated_content.clone(),
-                                                            usage: accumulated_usage,
-                                                        })));
-                                                    }
-                                                }
-                                            }
-                                            _ => {}
-                                        }
-                                    }
-                                }
-                            }
-                            Ok(chunks)
+                        .map(move |res| -> Result<Vec<LlmStreamChunk>, LlmError> {
+                            let bytes = res.map_err(|e| LlmError::Network(format!("Stream error: {}", e)))?;
+                            Ok(parser.feed(self, &bytes))
                         })
                         .flat_map(|res| {
                             match res {
-                                Ok(chunks) => stream::iter(chunks),
+                                Ok(chunks) => stream::iter(chunks.into_iter().map(Ok).collect::<Vec<_>>()),
                                 Err(e) => stream::iter(vec![Err(e)]),
                             }
                         })
                         .boxed()
                 }
-                Ok(resp) => {
-                    stream::once(async move {
-                        let status = resp.status();
-                        let body = resp.text().await.unwrap_or_default();
-                        Err(format!("Anthropic API error {}: {}", status, body))
-                    }).boxed()
-                }
                 Err(e) => stream::once(async move { Err(e) }).boxed(),
             }
         });
 
         s.boxed()
     }
+
+    async fn health_check(&self) -> Result<(), LlmError> {
+        self.get_models(1).await.map(|_| ())
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let page = self.get_models(MODEL_PAGE_LIMIT).await?;
+        Ok(page.data.into_iter().map(|m| m.id).collect())
+    }
+}
+
+// ── Stream parsing ───────────────────────────────────────
+
+/// Turns the server-sent events of one streaming call into chunks, building
+/// up the reply's text, tool call and thinking blocks as they arrive.
+#[derive(Default)]
+struct StreamParser {
+    /// Bytes after the last complete line
+    partial: Vec<u8>,
+    content: String,
+    thinking: Vec<serde_json::Value>,
+    tool_id: String,
+    tool_name: String,
+    tool_args: String,
+    input_tokens: u32,
+}
+
+impl StreamParser {
+    /// Parse every complete line received so far; a line split across
+    /// network chunks waits for its end. The thinking blocks of a tool-use
+    /// reply are handed to `caller` for the next request.
+    fn feed(&mut self, caller: &AnthropicCaller, bytes: &[u8]) -> Vec<LlmStreamChunk> {
+        self.partial.extend_from_slice(bytes);
+        let mut chunks = Vec::new();
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim_end().strip_prefix("data: ") else {
+                continue;
+            };
+            if let Ok(event) = serde_json::from_str::<AnthropicStreamEvent>(data) {
+                self.event(caller, event, &mut chunks);
+            }
+        }
+        chunks
+    }
+
+    fn event(&mut self, caller: &AnthropicCaller, event: AnthropicStreamEvent, chunks: &mut Vec<LlmStreamChunk>) {
+        match event {
+            AnthropicStreamEvent::MessageStart { message } => {
+                self.input_tokens = message.usage.input_tokens;
+            }
+            AnthropicStreamEvent::ContentBlockStart { content_block: AnthropicContentBlock::ToolUse { id, name, .. }, .. } => {
+                self.tool_id = id;
+                self.tool_name = name;
+            }
+            AnthropicStreamEvent::ContentBlockStart { content_block: AnthropicContentBlock::Thinking { thinking, signature }, .. } => {
+                self.thinking.push(serde_json::json!({
+                    "type": "thinking", "thinking": thinking, "signature": signature,
+                }));
+            }
+            AnthropicStreamEvent::ContentBlockStart { content_block: AnthropicContentBlock::RedactedThinking { data }, .. } => {
+                self.thinking.push(serde_json::json!({ "type": "redacted_thinking", "data": data }));
+            }
+            AnthropicStreamEvent::ContentBlockStart { .. } => {}
+            AnthropicStreamEvent::ContentBlockDelta { delta, .. } => match delta {
+                AnthropicDelta::TextDelta { text } => {
+                    self.content.push_str(&text);
+                    chunks.push(LlmStreamChunk::Content(text));
+                }
+                AnthropicDelta::InputJsonDelta { partial_json } => {
+                    self.tool_args.push_str(&partial_json);
+                    chunks.push(LlmStreamChunk::ToolCallDelta {
+                        name: Some(self.tool_name.clone()),
+                        args_json: self.tool_args.clone(),
+                    });
+                }
+                AnthropicDelta::ThinkingDelta { thinking } => {
+                    if let Some(block) = self.thinking.last_mut() {
+                        let text = format!("{}{}", block["thinking"].as_str().unwrap_or_default(), thinking);
+                        block["thinking"] = text.into();
+                    }
+                    chunks.push(LlmStreamChunk::Reasoning(thinking));
+                }
+                AnthropicDelta::SignatureDelta { signature } => {
+                    if let Some(block) = self.thinking.last_mut() {
+                        let full = format!("{}{}", block["signature"].as_str().unwrap_or_default(), signature);
+                        block["signature"] = full.into();
+                    }
+                }
+            },
+            AnthropicStreamEvent::MessageDelta { delta, usage } => {
+                let Some(stop_reason) = delta.stop_reason else {
+                    return;
+                };
+                let usage = Some(crate::budget::TokenUsage::new(self.input_tokens, usage.output_tokens));
+                if !self.tool_args.is_empty() {
+                    let args = parse_tool_args(&self.tool_args);
+                    let tool = ToolCall { name: self.tool_name.clone(), args, id: Some(self.tool_id.clone()) };
+                    caller.remember_thinking(std::mem::take(&mut self.thinking), std::slice::from_ref(&tool));
+                    chunks.push(LlmStreamChunk::Done(LlmResponse::ToolCall {
+                        tool,
+                        confidence: 1.0,
+                        assistant_text: Some(self.content.clone()).filter(|t| !t.trim().is_empty()),
+                        usage,
+                        model: None,
+                    }));
+                } else if !self.content.is_empty() {
+                    let content = self.content.clone();
+                    chunks.push(LlmStreamChunk::Done(if stop_reason == "max_tokens" {
+                        LlmResponse::Truncated { content, usage, model: None }
+                    } else {
+                        LlmResponse::FinalAnswer { content, confidence: 1.0, usage, model: None }
+                    }));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{HistoryEntry, Observation};
+    use serde_json::json;
+
+    fn memory_with_call() -> AgentMemory {
+        let mut memory = AgentMemory::new("Weather in Oslo?");
+        memory.history.push(HistoryEntry {
+            step: 1,
+            tool: ToolCall {
+                name: "weather".to_string(),
+                args: [("city".to_string(), json!("Oslo"))].into_iter().collect(),
+                id: Some("toolu_1".to_string()),
+            },
+            observation: Observation::success("4°C, rain"),
+            assistant_text: None,
+            latency_ms: None,
+            model_used: None,
+            usage: None,
+        });
+        memory
+    }
+
+    fn request(caller: &AnthropicCaller, memory: &AgentMemory, params: &ModelParams) -> AnthropicRequest {
+        let mut body = AnthropicRequest {
+            model:      "claude-sonnet-4-6".to_string(),
+            sampling:   AnthropicSampling { max_tokens: 1000, temperature: Some(0.2), top_p: params.top_p },
+            system:     None,
+            tools:      Vec::new(),
+            messages:   caller.build_messages(memory),
+            stream:     false,
+            tool_choice: None,
+            stop_sequences: Vec::new(),
+            thinking:    None,
+        };
+        caller.apply_thinking(&mut body, params);
+        body
+    }
+
+    #[test]
+    fn test_thinking_blocks_parsed() {
+        let parsed: AnthropicResponse = serde_json::from_value(json!({
+            "content": [
+                { "type": "thinking", "thinking": "Check the forecast.", "signature": "sig" },
+                { "type": "redacted_thinking", "data": "abc" },
+                { "type": "tool_use", "id": "toolu_1", "name": "weather", "input": { "city": "Oslo" } }
+            ],
+            "stop_reason": "tool_use",
+            "usage": { "input_tokens": 10, "output_tokens": 5 }
+        }))
+        .unwrap();
+        assert!(matches!(&parsed.content[0], AnthropicContentBlock::Thinking { signature, .. } if signature == "sig"));
+        assert!(matches!(parsed.content[1], AnthropicContentBlock::RedactedThinking { .. }));
+    }
+
+    #[test]
+    fn test_tool_history_sent_with_thinking() {
+        let caller = AnthropicCaller::new("key").with_thinking(2000);
+        let memory = memory_with_call();
+
+        // The reply's thinking is gone: the call is made without thinking
+        let body = request(&caller, &memory, &ModelParams::default());
+        assert!(body.thinking.is_none());
+        assert_eq!(body.messages[1].content[0]["type"], "tool_use");
+        assert_eq!(body.messages[1].content[0]["input"], json!({ "city": "Oslo" }));
+        assert_eq!(body.messages[2].role, "user");
+        assert_eq!(body.messages[2].content[0]["tool_use_id"], "toolu_1");
+
+        let block = json!({ "type": "thinking", "thinking": "Check the forecast.", "signature": "sig" });
+        let call = memory.history[0].tool.clone();
+        caller.remember_thinking(vec![block.clone()], &[call]);
+        let body = request(&caller, &memory, &ModelParams::default());
+        assert_eq!(body.messages[1].content[0], block);
+        assert_eq!(body.thinking, Some(json!({ "type": "enabled", "budget_tokens": 2000 })));
+        assert_eq!((body.sampling.max_tokens, body.sampling.temperature), (3000, None));
+
+        // top_p is raised into the range the API takes with thinking
+        let body = request(&caller, &memory, &ModelParams::new().top_p(0.5));
+        assert_eq!(body.sampling.top_p, Some(0.95));
+    }
+
+    #[test]
+    fn test_stream_parser_keeps_thinking() {
+        let caller = AnthropicCaller::new("key").with_thinking(2000);
+        let events = [
+            r#"{"type":"message_start","message":{"content":[],"stop_reason":null,"usage":{"input_tokens":12,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Check the "}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"forecast."}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"si"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"g"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"weather","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"city\": \"Oslo\"}"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"output_tokens":30}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+        let sse: String = events.iter().map(|data| format!("event: x\ndata: {}\n\n", data)).collect();
+
+        // Network chunks end mid-line
+        let mut parser = StreamParser::default();
+        let chunks: Vec<LlmStreamChunk> = sse.as_bytes().chunks(7).flat_map(|piece| parser.feed(&caller, piece)).collect();
+
+        let reasoning: String = chunks
+            .iter()
+            .filter_map(|c| match c {
+                LlmStreamChunk::Reasoning(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reasoning, "Check the forecast.");
+        let Some(LlmStreamChunk::Done(LlmResponse::ToolCall { tool, usage, .. })) = chunks.last() else {
+            panic!("stream did not end with a tool call");
+        };
+        assert_eq!(tool.args["city"], "Oslo");
+        assert_eq!(usage.as_ref().map(|u| (u.input_tokens, u.output_tokens)), Some((12, 30)));
+
+        // The signed block goes back with the call on the next request
+        assert_eq!(
+            caller.thinking_for("toolu_1"),
+            vec![json!({ "type": "thinking", "thinking": "Check the forecast.", "signature": "sig" })]
+        );
+    }
+
+    #[test]
+    fn test_reasoning_effort_sets_thinking_budget() {
+        let caller = AnthropicCaller::new("key");
+        let memory = AgentMemory::new("Prove it");
+        assert!(request(&caller, &memory, &ModelParams::default()).thinking.is_none());
+
+        let params = ModelParams::new().reasoning_effort(ReasoningEffort::Low);
+        let body = request(&caller, &memory, &params);
+        assert_eq!(body.thinking.unwrap()["budget_tokens"], 2048);
+    }
 }
@@ -0,0 +1,142 @@
+use crate::cache::{CacheStats, LlmCache};
+use crate::memory::AgentMemory;
+use crate::tools::ToolRegistry;
+use crate::types::{LlmResponse, LlmStreamChunk};
+use super::LlmError;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use std::sync::Arc;
+
+/// A wrapper around any `AsyncLlmCaller` that answers repeated requests
+/// from an [`LlmCache`] without calling the provider.
+///
+/// Requests are keyed by `cache::request_key`: model, messages, tool
+/// schemas, output schema and `parallel_tools`. Successful responses are
+/// stored; errors are not. A cached stream is a single `Done` chunk.
+/// With a `DiskCache`, re-running the same task in development or CI makes
+/// no API calls after the first run.
+pub struct CachingLlmCaller {
+    inner: Arc<dyn super::AsyncLlmCaller>,
+    cache: Arc<dyn LlmCache>,
+}
+
+impl CachingLlmCaller {
+    pub fn new(inner: Arc<dyn super::AsyncLlmCaller>, cache: Arc<dyn LlmCache>) -> Self {
+        Self { inner, cache }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
+
+#[async_trait]
+impl super::AsyncLlmCaller for CachingLlmCaller {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        tools:  &ToolRegistry,
+        model:  &str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        let key = crate::cache::request_key(memory, tools, model);
+        if let Some(resp) = self.cache.get(&key) {
+            tracing::debug!(key = %&key[..12], "LLM response served from cache");
+            return Ok(resp);
+        }
+        let resp = self.inner.call_async(memory, tools, model, output_tx).await?;
+        self.cache.put(key, resp.clone());
+        Ok(resp)
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools:  &'a ToolRegistry,
+        model:  &'a str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        use futures::{stream, StreamExt};
+
+        let key = crate::cache::request_key(memory, tools, model);
+        if let Some(resp) = self.cache.get(&key) {
+            tracing::debug!(key = %&key[..12], "LLM response served from cache");
+            return stream::once(async move { Ok(LlmStreamChunk::Done(resp)) }).boxed();
+        }
+        let cache = Arc::clone(&self.cache);
+        let mut key = Some(key);
+        self.inner
+            .call_stream_async(memory, tools, model, output_tx)
+            .map(move |chunk| {
+                if let (Ok(LlmStreamChunk::Done(resp)), Some(key)) = (&chunk, key.take()) {
+                    cache.put(key, resp.clone());
+                }
+                chunk
+            })
+            .boxed()
+    }
+
+    async fn health_check(&self) -> Result<(), LlmError> {
+        self.inner.health_check().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        self.inner.list_models().await
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{DiskCache, InMemoryCache};
+    use crate::llm::{AsyncLlmCaller, MockLlmCaller};
+    use std::time::Duration;
+
+    fn answer(text: &str) -> LlmResponse {
+        LlmResponse::FinalAnswer { content: text.to_string(), confidence: 1.0, usage: None, model: None }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_request_served_from_cache() {
+        let mock = Arc::new(MockLlmCaller::new(vec![answer("first"), answer("second")]));
+        let cache = Arc::new(InMemoryCache::new(10, Duration::from_secs(60)));
+        let caller = CachingLlmCaller::new(mock.clone(), cache);
+        let tools = ToolRegistry::new();
+        let memory = AgentMemory::new("task");
+
+        let a = caller.call_async(&memory, &tools, "m", None).await.unwrap();
+        let b = caller.call_async(&memory, &tools, "m", None).await.unwrap();
+        assert!(matches!((a, b), (LlmResponse::FinalAnswer { content: x, .. }, LlmResponse::FinalAnswer { content: y, .. }) if x == "first" && y == "first"));
+        assert_eq!(mock.call_count(), 1);
+
+        // A different model is a different request
+        let c = caller.call_async(&memory, &tools, "other", None).await.unwrap();
+        assert!(matches!(c, LlmResponse::FinalAnswer { content, .. } if content == "second"));
+        assert_eq!(caller.stats().hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_response_cached_on_disk() {
+        use futures::StreamExt;
+        let dir = tempfile::tempdir().unwrap();
+        let tools = ToolRegistry::new();
+        let memory = AgentMemory::new("task");
+
+        let mock = Arc::new(MockLlmCaller::new(vec![answer("streamed")]));
+        let caller = CachingLlmCaller::new(mock, Arc::new(DiskCache::new(dir.path())));
+        let chunks: Vec<_> = caller.call_stream_async(&memory, &tools, "m", None).collect().await;
+        assert!(matches!(chunks.last(), Some(Ok(LlmStreamChunk::Done(_)))));
+
+        // A later run with an exhausted provider still gets the answer
+        let empty = Arc::new(MockLlmCaller::new(vec![]));
+        let caller = CachingLlmCaller::new(empty.clone(), Arc::new(DiskCache::new(dir.path())));
+        let resp = caller.call_async(&memory, &tools, "m", None).await.unwrap();
+        assert!(matches!(resp, LlmResponse::FinalAnswer { content, .. } if content == "streamed"));
+        assert_eq!(empty.call_count(), 0);
+    }
+}
@@ -0,0 +1,430 @@
+use async_trait::async_trait;
+use crate::budget::TokenUsage;
+use crate::llm::{chat_turns, stop_position, AsyncLlmCaller, ChatTemplate, LlmError};
+use crate::memory::AgentMemory;
+use crate::prompter::{PlanningPrompter, ReActPrompter, XmlPrompter};
+use crate::tools::ToolRegistry;
+use crate::types::{LlmResponse, LlmStreamChunk};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::models::{phi3, qwen2};
+use futures::stream::{self, BoxStream, StreamExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokenizers::Tokenizer;
+
+// ── Params ───────────────────────────────────────────────
+
+/// Model family of a [`CandleCaller`] checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandleArch {
+    /// Qwen2 / Qwen2.5 instruct models
+    Qwen2,
+    /// Phi-3 / Phi-3.5 instruct models
+    Phi3,
+}
+
+impl CandleArch {
+    /// The planning prompter this family follows best without function calling.
+    ///
+    /// Qwen2.5 was trained on `<tool_call>` blocks, so it gets `XmlPrompter`.
+    /// Phi-3 has no tool format of its own and follows ReAct text reliably.
+    pub fn default_prompter(&self) -> Arc<dyn PlanningPrompter> {
+        match self {
+            CandleArch::Qwen2 => Arc::new(XmlPrompter),
+            CandleArch::Phi3 => Arc::new(ReActPrompter),
+        }
+    }
+
+    fn end_tokens(&self) -> &'static [&'static str] {
+        match self {
+            CandleArch::Qwen2 => &["<|im_end|>", "<|endoftext|>"],
+            CandleArch::Phi3 => &["<|end|>", "<|endoftext|>"],
+        }
+    }
+
+    /// The family's chat format.
+    fn template(&self) -> ChatTemplate {
+        match self {
+            CandleArch::Qwen2 => ChatTemplate::chatml(),
+            CandleArch::Phi3 => ChatTemplate::new("<|{{role}}|>\n{{content}}<|end|>\n", "<|assistant|>\n").stop("<|end|>"),
+        }
+    }
+
+    /// Render `(role, content)` turns in the family's chat format.
+    fn render(&self, turns: &[(String, String)]) -> String {
+        self.template().render(turns)
+    }
+}
+
+/// Loading and sampling options for [`CandleCaller`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandleParams {
+    pub arch: CandleArch,
+    /// Maximum tokens generated per call
+    pub max_tokens: usize,
+    /// Sampling temperature (0 = greedy)
+    pub temperature: f64,
+    pub top_p: Option<f64>,
+    pub seed: u64,
+    /// Run on the first CUDA or Metal device when available
+    pub gpu: bool,
+}
+
+impl CandleParams {
+    pub fn new(arch: CandleArch) -> Self {
+        Self {
+            arch,
+            max_tokens: 1024,
+            temperature: 0.2,
+            top_p: None,
+            seed: 42,
+            gpu: false,
+        }
+    }
+
+    pub fn max_tokens(mut self, tokens: usize) -> Self {
+        self.max_tokens = tokens;
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn gpu(mut self, enabled: bool) -> Self {
+        self.gpu = enabled;
+        self
+    }
+}
+
+// ── Caller ───────────────────────────────────────────────
+
+enum Weights {
+    Qwen2(qwen2::ModelForCausalLM),
+    Phi3(phi3::Model),
+}
+
+impl Weights {
+    fn forward(&mut self, input: &Tensor, offset: usize) -> candle_core::Result<Tensor> {
+        match self {
+            Weights::Qwen2(m) => m.forward(input, offset),
+            Weights::Phi3(m) => m.forward(input, offset),
+        }
+    }
+
+    fn clear_kv_cache(&mut self) {
+        match self {
+            Weights::Qwen2(m) => m.clear_kv_cache(),
+            Weights::Phi3(m) => m.clear_kv_cache(),
+        }
+    }
+}
+
+struct Loaded {
+    weights:   Weights,
+    tokenizer: Tokenizer,
+    device:    Device,
+}
+
+type ModelCell = OnceLock<Result<Arc<Mutex<Loaded>>, String>>;
+
+/// Runs a small local model with the candle crate (feature `candle`).
+///
+/// Reads a Hugging Face model directory from disk (`config.json`,
+/// `tokenizer.json`, `*.safetensors`) and never touches the network, which
+/// suits air-gapped deployments. The model is loaded on the first call;
+/// calls are serialized because the KV cache is shared.
+///
+/// These models have no function-calling API. Use the prompter from
+/// [`CandleArch::default_prompter`], which `.candle()` on the builder sets.
+pub struct CandleCaller {
+    dir:    PathBuf,
+    params: CandleParams,
+    model:  Arc<ModelCell>,
+}
+
+impl CandleCaller {
+    /// Caller for the model in `dir`. Loading is deferred to the first call.
+    pub fn new(dir: impl Into<PathBuf>, params: CandleParams) -> Self {
+        Self {
+            dir: dir.into(),
+            params,
+            model: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Load the model now, so a bad directory fails here instead of on the first call.
+    pub fn load(dir: impl Into<PathBuf>, params: CandleParams) -> Result<Self, String> {
+        let caller = Self::new(dir, params);
+        load_model(&caller.model, &caller.dir, &caller.params)?;
+        Ok(caller)
+    }
+}
+
+fn load_model(cell: &ModelCell, dir: &Path, params: &CandleParams) -> Result<Arc<Mutex<Loaded>>, String> {
+    cell.get_or_init(|| {
+        let err = |e: &dyn std::fmt::Display| format!("Failed to load model {}: {}", dir.display(), e);
+
+        let device = if !params.gpu {
+            Device::Cpu
+        } else if candle_core::utils::cuda_is_available() {
+            Device::new_cuda(0).map_err(|e| err(&e))?
+        } else if candle_core::utils::metal_is_available() {
+            Device::new_metal(0).map_err(|e| err(&e))?
+        } else {
+            Device::Cpu
+        };
+        let dtype = if device.is_cpu() { DType::F32 } else { DType::BF16 };
+
+        let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).map_err(|e| err(&e))?;
+        let config = std::fs::read(dir.join("config.json")).map_err(|e| err(&e))?;
+
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| err(&e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "safetensors"))
+            .collect();
+        files.sort();
+        if files.is_empty() {
+            return Err(err(&"no .safetensors files"));
+        }
+        // SAFETY: the weight files are not modified while mapped
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&files, dtype, &device) }
+            .map_err(|e| err(&e))?;
+
+        let weights = match params.arch {
+            CandleArch::Qwen2 => {
+                let config: qwen2::Config = serde_json::from_slice(&config).map_err(|e| err(&e))?;
+                Weights::Qwen2(qwen2::ModelForCausalLM::new(&config, vb).map_err(|e| err(&e))?)
+            }
+            CandleArch::Phi3 => {
+                let config: phi3::Config = serde_json::from_slice(&config).map_err(|e| err(&e))?;
+                Weights::Phi3(phi3::Model::new(&config, vb).map_err(|e| err(&e))?)
+            }
+        };
+        Ok(Arc::new(Mutex::new(Loaded { weights, tokenizer, device })))
+    })
+    .clone()
+}
+
+// ── Generation ───────────────────────────────────────────
+
+/// Move `text` on to `decoded`, the whole reply decoded again after one
+/// more token. Returns the new text to emit (maybe empty) and whether a
+/// stop sequence ended the reply; `text` then ends before it.
+///
+/// A token that ends inside a multi-byte character decodes to a trailing
+/// replacement character, so nothing is emitted until the character is
+/// whole.
+fn advance(text: &mut String, decoded: String, stop_at: &[String]) -> (String, bool) {
+    if let Some(pos) = stop_position(&decoded, stop_at) {
+        let emitted = decoded.get(text.len()..pos).unwrap_or_default().to_string();
+        *text = decoded[..pos].to_string();
+        return (emitted, true);
+    }
+    let extends = decoded.len() > text.len() && decoded.is_char_boundary(text.len());
+    if !extends || decoded.ends_with('\u{FFFD}') {
+        return (String::new(), false);
+    }
+    let emitted = decoded[text.len()..].to_string();
+    *text = decoded;
+    (emitted, false)
+}
+
+/// Generate a reply to `prompt`, calling `on_text` with each decoded piece.
+/// The reply ends before the first of `stop_at`.
+fn generate(
+    loaded: &mut Loaded,
+    prompt: &str,
+    params: &CandleParams,
+    stop_at: &[String],
+    mut on_text: impl FnMut(&str),
+) -> Result<(String, TokenUsage), String> {
+    let err = |e: &dyn std::fmt::Display| format!("candle error: {}", e);
+
+    let prompt_ids = loaded
+        .tokenizer
+        .encode(prompt, true)
+        .map_err(|e| err(&e))?
+        .get_ids()
+        .to_vec();
+    let stop: Vec<u32> = params
+        .arch
+        .end_tokens()
+        .iter()
+        .filter_map(|t| loaded.tokenizer.token_to_id(t))
+        .collect();
+    let temperature = (params.temperature > 0.0).then_some(params.temperature);
+    let mut sampler = LogitsProcessor::new(params.seed, temperature, params.top_p);
+
+    loaded.weights.clear_kv_cache();
+    let mut generated: Vec<u32> = Vec::new();
+    let mut text = String::new();
+    let mut input = prompt_ids.clone();
+    let mut offset = 0;
+
+    while generated.len() < params.max_tokens {
+        let tensor = Tensor::new(input.as_slice(), &loaded.device)
+            .and_then(|t| t.unsqueeze(0))
+            .map_err(|e| err(&e))?;
+        let logits = loaded
+            .weights
+            .forward(&tensor, offset)
+            .and_then(|l| l.squeeze(0))
+            .and_then(|l| if l.rank() == 2 { l.get(l.dim(0)? - 1) } else { Ok(l) })
+            .and_then(|l| l.to_dtype(DType::F32))
+            .map_err(|e| err(&e))?;
+        offset += input.len();
+
+        let token = sampler.sample(&logits).map_err(|e| err(&e))?;
+        if stop.contains(&token) {
+            break;
+        }
+        generated.push(token);
+        input = vec![token];
+
+        // Decode the whole reply so multi-token characters come out whole
+        let decoded = loaded.tokenizer.decode(&generated, true).map_err(|e| err(&e))?;
+        let (piece, stopped) = advance(&mut text, decoded, stop_at);
+        if !piece.is_empty() {
+            on_text(&piece);
+        }
+        if stopped {
+            break;
+        }
+    }
+
+    Ok((text, TokenUsage::new(prompt_ids.len() as u32, generated.len() as u32)))
+}
+
+#[async_trait]
+impl AsyncLlmCaller for CandleCaller {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        tools:  &ToolRegistry,
+        model:  &str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        let mut stream = self.call_stream_async(memory, tools, model, output_tx);
+        while let Some(chunk) = stream.next().await {
+            if let LlmStreamChunk::Done(resp) = chunk? {
+                return Ok(resp);
+            }
+        }
+        Err(LlmError::Parse("Stream ended without a response".to_string()))
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools:  &'a ToolRegistry,
+        _model: &'a str,
+        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        if !tools.is_empty() {
+            return stream::once(async {
+                Err(LlmError::InvalidRequest(
+                    "CandleCaller has no native tool calling; use a text PlanningPrompter".to_string(),
+                ))
+            })
+            .boxed();
+        }
+
+        let prompt = self.params.arch.render(&chat_turns(memory));
+        let cell = self.model.clone();
+        let dir = self.dir.clone();
+        let mut params = self.params.clone();
+        if let Some(max_tokens) = memory.config.sampling.max_tokens {
+            params.max_tokens = max_tokens as usize;
+        }
+        if let Some(temperature) = memory.config.sampling.temperature {
+            params.temperature = temperature as f64;
+        }
+        if let Some(seed) = memory.config.sampling.seed {
+            params.seed = seed;
+        }
+        let stop_at = memory.stop.clone();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::task::spawn_blocking(move || {
+            let result = load_model(&cell, &dir, &params).and_then(|loaded| {
+                let mut loaded = loaded.lock().map_err(|_| "candle model lock poisoned".to_string())?;
+                generate(&mut loaded, &prompt, &params, &stop_at, |piece| {
+                    let _ = tx.send(Ok(LlmStreamChunk::Content(piece.to_string())));
+                })
+            });
+            let _ = tx.send(result.map_err(LlmError::from).map(|(content, usage)| {
+                // Stopped by the token limit rather than an end-of-sequence token
+                let resp = if usage.output_tokens as usize >= params.max_tokens {
+                    LlmResponse::Truncated { content, usage: Some(usage) }
+                } else {
+                    LlmResponse::FinalAnswer { content, confidence: 1.0, usage: Some(usage) }
+                };
+                LlmStreamChunk::Done(resp)
+            }));
+        });
+
+        stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) }).boxed()
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turns(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(r, c)| (r.to_string(), c.to_string())).collect()
+    }
+
+    #[test]
+    fn test_render_each_family() {
+        let t = turns(&[("system", "Be brief."), ("user", "Hi")]);
+        assert_eq!(
+            CandleArch::Qwen2.render(&t),
+            "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(
+            CandleArch::Phi3.render(&t),
+            "<|system|>\nBe brief.<|end|>\n<|user|>\nHi<|end|>\n<|assistant|>\n"
+        );
+    }
+
+    #[test]
+    fn test_advance_emits_new_text() {
+        let mut text = String::new();
+        assert_eq!(advance(&mut text, "Sun".to_string(), &[]), ("Sun".to_string(), false));
+        assert_eq!(advance(&mut text, "Sunny".to_string(), &[]), ("ny".to_string(), false));
+        // Half a character decodes to a replacement character: wait for the rest
+        assert_eq!(advance(&mut text, "Sunny \u{FFFD}".to_string(), &[]), (String::new(), false));
+        assert_eq!(text, "Sunny");
+        assert_eq!(advance(&mut text, "Sunny é".to_string(), &[]), (" é".to_string(), false));
+        assert_eq!(text, "Sunny é");
+    }
+
+    #[test]
+    fn test_advance_stops_before_stop_sequence() {
+        let stop = vec!["Observation:".to_string()];
+        let mut text = "Action: search".to_string();
+        let (piece, stopped) = advance(&mut text, "Action: search\nObservation:".to_string(), &stop);
+        assert_eq!((piece.as_str(), stopped), ("\n", true));
+        assert_eq!(text, "Action: search\n");
+    }
+}
@@ -0,0 +1,178 @@
+//! Model capabilities — what a model's chat API accepts.
+//!
+//! OpenAI's reasoning models do not take every request a chat model does:
+//! `o1-mini` and `o1-preview` reject system messages, tool schemas, JSON
+//! mode and streaming, and the whole o-series rejects `parallel_tool_calls`,
+//! `stop`, `logprobs` and a non-default `temperature`.
+//! [`ModelCapabilities::detect`] recognizes these families by name, and
+//! `OpenAiCaller` builds each request from the capabilities of its model:
+//! system prompts are folded into the first user message, unsupported
+//! parameters are left out, and streams fall back to a single call.
+//!
+//! Models the detection does not know, such as a fine-tune or a proxy
+//! alias, can be described explicitly:
+//!
+//! ```rust,ignore
+//! AgentBuilder::new("task")
+//!     .openai("")
+//!     .model("my-o1-proxy")
+//!     .model_capabilities("my-o1-proxy", ModelCapabilities::detect("o1-mini"))
+//! ```
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What a model's chat API accepts. Fields missing when deserializing are supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelCapabilities {
+    /// Accepts `system` messages; otherwise they are folded into the first user message
+    pub system_messages: bool,
+    /// Accepts tool schemas and answers with tool calls
+    pub tools: bool,
+    /// Accepts the `parallel_tool_calls` request parameter
+    pub parallel_tool_calls: bool,
+    /// Accepts `response_format` JSON mode
+    pub json_mode: bool,
+    /// Can stream its response
+    pub streaming: bool,
+    /// Accepts `stop` sequences
+    pub stop_sequences: bool,
+    /// Accepts a `temperature` other than the default
+    pub temperature: bool,
+    /// Returns token log probabilities (`logprobs`), used for confidence
+    pub logprobs: bool,
+}
+
+impl Default for ModelCapabilities {
+    fn default() -> Self {
+        Self::chat()
+    }
+}
+
+impl ModelCapabilities {
+    /// A regular chat model: everything is supported.
+    pub fn chat() -> Self {
+        Self {
+            system_messages: true,
+            tools: true,
+            parallel_tool_calls: true,
+            json_mode: true,
+            streaming: true,
+            stop_sequences: true,
+            temperature: true,
+            logprobs: true,
+        }
+    }
+
+    /// Capabilities of `model`, recognized by family name. A provider
+    /// prefix (`openai/o1`) is ignored. Unknown models are chat models.
+    pub fn detect(model: &str) -> Self {
+        let name = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+        if name.starts_with("o1-mini") || name.starts_with("o1-preview") {
+            Self {
+                system_messages: false,
+                tools: false,
+                parallel_tool_calls: false,
+                json_mode: false,
+                streaming: false,
+                stop_sequences: false,
+                temperature: false,
+                logprobs: false,
+            }
+        } else if name == "o1" || name.starts_with("o1-") {
+            Self {
+                parallel_tool_calls: false,
+                streaming: false,
+                stop_sequences: false,
+                temperature: false,
+                logprobs: false,
+                ..Self::chat()
+            }
+        } else if is_o_series(&name) {
+            Self {
+                parallel_tool_calls: false,
+                stop_sequences: false,
+                temperature: false,
+                logprobs: false,
+                ..Self::chat()
+            }
+        } else {
+            Self::chat()
+        }
+    }
+
+    /// Rewrite chat messages for a model without system messages: their
+    /// text is prepended to the first user message (or becomes one).
+    pub fn adapt_messages(&self, messages: Vec<Value>) -> Vec<Value> {
+        if self.system_messages {
+            return messages;
+        }
+        let (system, mut rest): (Vec<Value>, Vec<Value>) =
+            messages.into_iter().partition(|m| m["role"] == "system");
+        let instructions: Vec<&str> = system.iter().filter_map(|m| m["content"].as_str()).collect();
+        if instructions.is_empty() {
+            return rest;
+        }
+        let instructions = instructions.join("\n\n");
+        match rest.iter_mut().find(|m| m["role"] == "user") {
+            Some(first) => {
+                let content = first["content"].as_str().unwrap_or_default();
+                first["content"] = Value::String(format!("{}\n\n{}", instructions, content));
+            }
+            None => rest.insert(0, serde_json::json!({ "role": "user", "content": instructions })),
+        }
+        rest
+    }
+}
+
+/// `o3`, `o3-mini`, `o4-mini-2025-04-16`, …
+fn is_o_series(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_families() {
+        assert_eq!(ModelCapabilities::detect("gpt-4o"), ModelCapabilities::chat());
+        assert_eq!(ModelCapabilities::detect("llama-3.3-70b-versatile"), ModelCapabilities::chat());
+
+        let mini = ModelCapabilities::detect("o1-mini-2024-09-12");
+        assert!(!mini.system_messages && !mini.tools && !mini.streaming);
+
+        let o1 = ModelCapabilities::detect("openai/o1");
+        assert!(o1.system_messages && o1.tools && !o1.parallel_tool_calls && !o1.streaming);
+
+        let o3 = ModelCapabilities::detect("o3-mini");
+        assert!(o3.tools && o3.streaming && !o3.parallel_tool_calls && !o3.stop_sequences && !o3.logprobs);
+        assert!(!ModelCapabilities::detect("o4-mini").parallel_tool_calls);
+        assert!(ModelCapabilities::detect("omni-chat").parallel_tool_calls);
+    }
+
+    #[test]
+    fn test_fold_system_messages() {
+        let messages = vec![
+            json!({ "role": "system", "content": "Be brief." }),
+            json!({ "role": "user", "content": "Hi" }),
+            json!({ "role": "assistant", "content": "Hello" }),
+        ];
+        let caps = ModelCapabilities::detect("o1-preview");
+        let adapted = caps.adapt_messages(messages.clone());
+        assert_eq!(adapted.len(), 2);
+        assert_eq!(adapted[0], json!({ "role": "user", "content": "Be brief.\n\nHi" }));
+
+        assert_eq!(ModelCapabilities::chat().adapt_messages(messages.clone()), messages);
+
+        let only_system = caps.adapt_messages(vec![json!({ "role": "system", "content": "Rules" })]);
+        assert_eq!(only_system, vec![json!({ "role": "user", "content": "Rules" })]);
+    }
+}
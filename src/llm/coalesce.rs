@@ -0,0 +1,316 @@
+use crate::memory::AgentMemory;
+use crate::tools::ToolRegistry;
+use crate::types::{LlmResponse, LlmStreamChunk};
+use super::LlmError;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+type Outcome = Option<Result<LlmResponse, LlmError>>;
+type InFlight = Arc<Mutex<HashMap<String, watch::Receiver<Outcome>>>>;
+
+/// Observable coalescing stats.
+#[derive(Debug, Clone, Default)]
+pub struct CoalesceStats {
+    /// Calls that reached the provider
+    pub requests: usize,
+    /// Calls answered by another caller's in-flight request
+    pub dedup_hits: usize,
+}
+
+impl CoalesceStats {
+    pub fn dedup_rate(&self) -> f64 {
+        let total = self.requests + self.dedup_hits;
+        if total == 0 {
+            0.0
+        } else {
+            self.dedup_hits as f64 / total as f64
+        }
+    }
+}
+
+/// A wrapper around any `AsyncLlmCaller`, shared by many agents, that
+/// collapses identical concurrent requests into one provider call.
+///
+/// The first caller for a prompt makes the request (and still streams its
+/// tokens); identical calls that arrive while it is in flight wait for its
+/// response instead of issuing their own. Followers receive only the final
+/// `Done` chunk, without usage: the leader's session records the tokens
+/// once. Nothing is cached once the request completes.
+pub struct CoalescingLlmCaller {
+    inner:      Arc<dyn super::AsyncLlmCaller>,
+    in_flight:  InFlight,
+    requests:   AtomicUsize,
+    dedup_hits: AtomicUsize,
+}
+
+enum Role {
+    Leader(Leader),
+    Follower(watch::Receiver<Outcome>),
+}
+
+/// Publishes the outcome and unregisters the request, even if dropped early.
+struct Leader {
+    key:       String,
+    tx:        watch::Sender<Outcome>,
+    in_flight: InFlight,
+}
+
+impl Leader {
+    fn publish(&self, outcome: Result<LlmResponse, LlmError>) {
+        let _ = self.tx.send(Some(outcome));
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl CoalescingLlmCaller {
+    pub fn new(inner: Arc<dyn super::AsyncLlmCaller>) -> Self {
+        Self {
+            inner,
+            in_flight:  Arc::new(Mutex::new(HashMap::new())),
+            requests:   AtomicUsize::new(0),
+            dedup_hits: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CoalesceStats {
+        CoalesceStats {
+            requests:   self.requests.load(Ordering::Relaxed),
+            dedup_hits: self.dedup_hits.load(Ordering::Relaxed),
+        }
+    }
+
+    fn join(&self, key: String) -> Role {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(rx) = in_flight.get(&key) {
+            self.dedup_hits.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!(key = %&key[..12], "LLM request coalesced");
+            return Role::Follower(rx.clone());
+        }
+        let (tx, rx) = watch::channel(None);
+        in_flight.insert(key.clone(), rx);
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        Role::Leader(Leader { key, tx, in_flight: self.in_flight.clone() })
+    }
+
+    /// Wait for the leader's outcome. `None` if the leader went away without one.
+    /// The response carries no usage, so shared tokens are counted once.
+    async fn follow(mut rx: watch::Receiver<Outcome>) -> Option<Result<LlmResponse, LlmError>> {
+        let outcome = rx.wait_for(|o| o.is_some()).await.ok().and_then(|o| o.clone())?;
+        Some(outcome.map(|mut response| {
+            let (LlmResponse::ToolCall { usage, .. }
+            | LlmResponse::ParallelToolCalls { usage, .. }
+            | LlmResponse::FinalAnswer { usage, .. }
+            | LlmResponse::Structured { usage, .. }
+            | LlmResponse::Truncated { usage, .. }) = &mut response;
+            *usage = None;
+            response
+        }))
+    }
+}
+
+#[async_trait]
+impl super::AsyncLlmCaller for CoalescingLlmCaller {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        tools:  &ToolRegistry,
+        model:  &str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        match self.join(crate::cache::request_key(memory, tools, model)) {
+            Role::Leader(leader) => {
+                let result = self.inner.call_async(memory, tools, model, output_tx).await;
+                leader.publish(result.clone());
+                result
+            }
+            Role::Follower(rx) => match Self::follow(rx).await {
+                Some(result) => result,
+                // Leader was cancelled — make the call ourselves
+                None => self.inner.call_async(memory, tools, model, output_tx).await,
+            },
+        }
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools:  &'a ToolRegistry,
+        model:  &'a str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        use futures::{stream, StreamExt};
+
+        match self.join(crate::cache::request_key(memory, tools, model)) {
+            Role::Leader(leader) => self
+                .inner
+                .call_stream_async(memory, tools, model, output_tx)
+                .map(move |chunk| {
+                    match &chunk {
+                        Ok(LlmStreamChunk::Done(resp)) => leader.publish(Ok(resp.clone())),
+                        Err(e) => leader.publish(Err(e.clone())),
+                        _ => {}
+                    }
+                    chunk
+                })
+                .boxed(),
+            Role::Follower(rx) => {
+                let inner = self.inner.clone();
+                let output_tx = output_tx.cloned();
+                stream::once(async move {
+                    match Self::follow(rx).await {
+                        Some(result) => result.map(LlmStreamChunk::Done),
+                        None => inner
+                            .call_async(memory, tools, model, output_tx.as_ref())
+                            .await
+                            .map(LlmStreamChunk::Done),
+                    }
+                })
+                .boxed()
+            }
+        }
+    }
+
+    async fn health_check(&self) -> Result<(), LlmError> {
+        self.inner.health_check().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        self.inner.list_models().await
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::TokenUsage;
+    use crate::llm::AsyncLlmCaller;
+    use std::time::Duration;
+
+    /// Counts calls and answers slowly so concurrent callers overlap.
+    struct SlowCaller {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AsyncLlmCaller for SlowCaller {
+        async fn call_async(
+            &self,
+            memory: &AgentMemory,
+            _tools: &ToolRegistry,
+            _model: &str,
+            _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+        ) -> Result<LlmResponse, LlmError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(LlmResponse::final_answer(format!("answer to {}", memory.task)).with_usage(TokenUsage::new(100, 20)))
+        }
+
+        fn call_stream_async<'a>(
+            &'a self,
+            memory: &'a AgentMemory,
+            tools:  &'a ToolRegistry,
+            model:  &'a str,
+            output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+        ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+            use futures::{stream, StreamExt};
+            let output_tx = output_tx.cloned();
+            stream::once(async move {
+                self.call_async(memory, tools, model, output_tx.as_ref()).await.map(LlmStreamChunk::Done)
+            })
+            .boxed()
+        }
+    }
+
+    fn make_caller() -> (Arc<SlowCaller>, CoalescingLlmCaller) {
+        let inner = Arc::new(SlowCaller { calls: AtomicUsize::new(0) });
+        (inner.clone(), CoalescingLlmCaller::new(inner))
+    }
+
+    #[tokio::test]
+    async fn test_identical_concurrent_calls_coalesce() {
+        let (inner, caller) = make_caller();
+        let tools = ToolRegistry::new();
+        let memories: Vec<AgentMemory> = (0..5).map(|_| AgentMemory::new("same task")).collect();
+
+        let results = futures::future::join_all(
+            memories.iter().map(|m| caller.call_async(m, &tools, "m", None)),
+        )
+        .await;
+
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+        let stats = caller.stats();
+        assert_eq!(stats.requests, 1);
+        assert_eq!(stats.dedup_hits, 4);
+        assert!(caller.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shared_usage_recorded_once() {
+        let (_, caller) = make_caller();
+        let tools = ToolRegistry::new();
+        let mut memories: Vec<AgentMemory> = (0..3).map(|_| AgentMemory::new("same task")).collect();
+
+        let results = futures::future::join_all(
+            memories.iter().map(|m| caller.call_async(m, &tools, "gpt-4o", None)),
+        )
+        .await;
+        for (memory, result) in memories.iter_mut().zip(results) {
+            if let Ok(LlmResponse::FinalAnswer { usage: Some(usage), .. }) = result {
+                memory.record_usage("gpt-4o", usage);
+            }
+        }
+
+        // One provider call, so its tokens are recorded by one session only
+        let total: u32 = memories.iter().map(|m| m.total_usage.total_tokens).sum();
+        assert_eq!(total, 120);
+    }
+
+    #[tokio::test]
+    async fn test_different_prompts_not_coalesced() {
+        let (inner, caller) = make_caller();
+        let tools = ToolRegistry::new();
+        let a = AgentMemory::new("task a");
+        let b = AgentMemory::new("task b");
+
+        let (ra, rb) = tokio::join!(
+            caller.call_async(&a, &tools, "m", None),
+            caller.call_async(&b, &tools, "m", None)
+        );
+
+        assert!(matches!(ra, Ok(LlmResponse::FinalAnswer { ref content, .. }) if content == "answer to task a"));
+        assert!(matches!(rb, Ok(LlmResponse::FinalAnswer { ref content, .. }) if content == "answer to task b"));
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_followers_get_done() {
+        use futures::StreamExt;
+        let (inner, caller) = make_caller();
+        let tools = ToolRegistry::new();
+        let m1 = AgentMemory::new("same");
+        let m2 = AgentMemory::new("same");
+
+        let (c1, c2) = tokio::join!(
+            caller.call_stream_async(&m1, &tools, "m", None).collect::<Vec<_>>(),
+            caller.call_stream_async(&m2, &tools, "m", None).collect::<Vec<_>>()
+        );
+
+        assert!(matches!(c1.last(), Some(Ok(LlmStreamChunk::Done(_)))));
+        assert!(matches!(c2.last(), Some(Ok(LlmStreamChunk::Done(_)))));
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,419 @@
+use async_trait::async_trait;
+use crate::budget::TokenUsage;
+use crate::llm::{AsyncLlmCaller, LlmError};
+use crate::memory::AgentMemory;
+use crate::prompter::{TextReply, TextToolFormat};
+use crate::tools::ToolRegistry;
+use crate::types::{LlmResponse, LlmStreamChunk};
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// ── Template ─────────────────────────────────────────────
+
+/// How chat messages are written into one prompt string for a model that
+/// only exposes a completions endpoint.
+///
+/// `message` is written once per message, with `{{ role }}` and
+/// `{{ content }}` replaced (spaces inside the braces are optional).
+/// `generation_prompt` follows the last message and opens the assistant
+/// turn the model completes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatTemplate {
+    /// Written once at the start of the prompt
+    #[serde(default)]
+    pub bos: String,
+    pub message: String,
+    pub generation_prompt: String,
+    /// Role that tool results are written with
+    pub tool_role: String,
+    /// Sent as stop sequences, so the model ends at the end of its turn
+    #[serde(default)]
+    pub stop: Vec<String>,
+}
+
+impl ChatTemplate {
+    /// ChatML, used by Qwen, Hermes and many fine-tunes.
+    pub fn chatml() -> Self {
+        Self {
+            bos: String::new(),
+            message: "<|im_start|>{{role}}\n{{content}}<|im_end|>\n".to_string(),
+            generation_prompt: "<|im_start|>assistant\n".to_string(),
+            tool_role: "tool".to_string(),
+            stop: vec!["<|im_end|>".to_string()],
+        }
+    }
+
+    /// Llama 3 and 3.x instruct models.
+    pub fn llama3() -> Self {
+        Self {
+            bos: "<|begin_of_text|>".to_string(),
+            message: "<|start_header_id|>{{role}}<|end_header_id|>\n\n{{content}}<|eot_id|>".to_string(),
+            generation_prompt: "<|start_header_id|>assistant<|end_header_id|>\n\n".to_string(),
+            tool_role: "ipython".to_string(),
+            stop: vec!["<|eot_id|>".to_string(), "<|eom_id|>".to_string()],
+        }
+    }
+
+    /// A custom template. Add stop sequences with [`ChatTemplate::stop`].
+    pub fn new(message: impl Into<String>, generation_prompt: impl Into<String>) -> Self {
+        Self {
+            bos: String::new(),
+            message: message.into(),
+            generation_prompt: generation_prompt.into(),
+            tool_role: "tool".to_string(),
+            stop: Vec::new(),
+        }
+    }
+
+    pub fn bos(mut self, bos: impl Into<String>) -> Self {
+        self.bos = bos.into();
+        self
+    }
+
+    pub fn tool_role(mut self, role: impl Into<String>) -> Self {
+        self.tool_role = role.into();
+        self
+    }
+
+    pub fn stop(mut self, stop: impl Into<String>) -> Self {
+        self.stop.push(stop.into());
+        self
+    }
+
+    /// The prompt for `(role, content)` turns, ending with the generation prompt.
+    pub fn render(&self, turns: &[(String, String)]) -> String {
+        let mut prompt = self.bos.clone();
+        for (role, content) in turns {
+            // Role first, so placeholders inside the content stay as written
+            let message = fill(&self.message, "role", role);
+            prompt.push_str(&fill(&message, "content", content));
+        }
+        prompt.push_str(&self.generation_prompt);
+        prompt
+    }
+}
+
+/// Replace `{{name}}` and `{{ name }}` in `template`.
+fn fill(template: &str, name: &str, value: &str) -> String {
+    template
+        .replace(&format!("{{{{{}}}}}", name), value)
+        .replace(&format!("{{{{ {} }}}}", name), value)
+}
+
+// ── Caller ───────────────────────────────────────────────
+
+/// Caller for self-hosted servers that only expose an OpenAI-style
+/// `/completions` endpoint (no chat endpoint): vLLM, TGI, text-generation-webui
+/// and older llama.cpp builds.
+///
+/// The messages from `AgentMemory::build_messages` are rendered with a
+/// [`ChatTemplate`]. With the default `NativePrompter`, tools are described
+/// in the system turn and calls are read back from the reply text in a
+/// [`TextToolFormat`] (`<tool_call>` blocks unless `text_tool_calls` picks
+/// another). With a text prompter the reply is returned as text for the
+/// prompter to parse. Streaming makes a single call.
+pub struct CompletionCaller {
+    client:   reqwest::Client,
+    api_base: String,
+    api_key:  Option<String>,
+    template: ChatTemplate,
+    format:   TextToolFormat,
+}
+
+impl CompletionCaller {
+    /// `api_base` is the URL before `/completions`, e.g. `http://localhost:8000/v1`.
+    pub fn new(api_base: impl Into<String>, template: ChatTemplate) -> Self {
+        Self {
+            client:   reqwest::Client::new(),
+            api_base: api_base.into().trim_end_matches('/').to_string(),
+            api_key:  None,
+            template,
+            format:   TextToolFormat::Xml,
+        }
+    }
+
+    /// Send `Authorization: Bearer <key>`.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into()).filter(|k| !k.is_empty());
+        self
+    }
+
+    /// Format tool calls are requested in when the planning prompter uses
+    /// native tools. Default: `TextToolFormat::Xml`.
+    pub fn tool_format(mut self, format: TextToolFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// The format this call reads tool calls in, or None when the planning
+    /// prompter writes its own text format.
+    fn text_format(&self, memory: &AgentMemory, tools: &ToolRegistry) -> Option<TextToolFormat> {
+        if !memory.planning_prompter.native_tools() || tools.is_empty() {
+            return None;
+        }
+        Some(memory.config.text_tool_calls.unwrap_or(self.format))
+    }
+
+    /// `(role, content)` turns. Native tool calls in history are written in
+    /// `format`, and tool results use the template's tool role.
+    fn turns(&self, memory: &AgentMemory, tools: &ToolRegistry, format: Option<TextToolFormat>) -> Vec<(String, String)> {
+        let mut turns: Vec<(String, String)> = memory
+            .build_messages()
+            .iter()
+            .map(|m| {
+                let mut content = m["content"].as_str().unwrap_or_default().to_string();
+                let role = match m["role"].as_str().unwrap_or("user") {
+                    "tool" => self.template.tool_role.clone(),
+                    other => other.to_string(),
+                };
+                if let Some(calls) = m["tool_calls"].as_array() {
+                    for call in calls {
+                        if !content.is_empty() {
+                            content.push('\n');
+                        }
+                        content.push_str(&write_call(format.unwrap_or(self.format), &call["function"]));
+                    }
+                }
+                (role, content)
+            })
+            .collect();
+
+        // `text_tool_calls` already put its instructions in the system prompt
+        let instructions = match format {
+            Some(f) if memory.config.text_tool_calls.is_none() => f.instructions(&tools.schemas()),
+            _ => None,
+        };
+        if let Some(instructions) = instructions {
+            match turns.first_mut() {
+                Some((role, content)) if role == "system" => {
+                    content.push_str("\n\n");
+                    content.push_str(&instructions);
+                }
+                _ => turns.insert(0, ("system".to_string(), instructions)),
+            }
+        }
+        turns
+    }
+
+    fn build_body(&self, memory: &AgentMemory, tools: &ToolRegistry, model: &str, format: Option<TextToolFormat>) -> Value {
+        let mut stop = self.template.stop.clone();
+        for s in &memory.stop {
+            if !stop.contains(s) {
+                stop.push(s.clone());
+            }
+        }
+        let mut body = json!({
+            "model":  model,
+            "prompt": self.template.render(&self.turns(memory, tools, format)),
+        });
+        if !stop.is_empty() {
+            body["stop"] = json!(stop);
+        }
+        if let Some(max_tokens) = memory.config.sampling.max_tokens {
+            body["max_tokens"] = max_tokens.into();
+        }
+        if let Some(temperature) = memory.config.sampling.temperature {
+            body["temperature"] = temperature.into();
+        }
+        if let Some(seed) = memory.config.sampling.seed {
+            body["seed"] = seed.into();
+        }
+        if let Some(grammar) = &memory.grammar {
+            grammar.apply(&mut body);
+        }
+        body
+    }
+
+    async fn get_models(&self) -> Result<Value, LlmError> {
+        let response = self.authorize(self.client.get(format!("{}/models", self.api_base)))
+            .send()
+            .await
+            .map_err(|e| LlmError::Network(format!("Network error: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(super::error::from_response(response, "Completion API error").await);
+        }
+        response.json()
+            .await
+            .map_err(|e| LlmError::Parse(format!("Failed to parse model list: {}", e)))
+    }
+}
+
+/// One native `function` object written as text in `format`.
+fn write_call(format: TextToolFormat, function: &Value) -> String {
+    let name = function["name"].as_str().unwrap_or_default();
+    let args = function["arguments"]
+        .as_str()
+        .and_then(|s| serde_json::from_str::<Value>(s).ok())
+        .unwrap_or_else(|| json!({}));
+    match format {
+        TextToolFormat::Xml => format!("<tool_call>{}</tool_call>", json!({ "name": name, "arguments": args })),
+        TextToolFormat::FencedJson => format!("```json\n{}\n```", json!({ "tool": name, "arguments": args })),
+    }
+}
+
+/// Read a `/completions` response body.
+fn parse_response(body: &str, format: Option<TextToolFormat>, step: usize) -> Result<LlmResponse, LlmError> {
+    let value: Value = serde_json::from_str(body)
+        .map_err(|e| LlmError::Parse(format!("Failed to parse completion response: {}", e)))?;
+    let choice = value["choices"]
+        .get(0)
+        .ok_or_else(|| LlmError::Parse("Completion response has no choices".to_string()))?;
+    let content = choice["text"].as_str().unwrap_or_default().trim().to_string();
+    let usage = value.get("usage").filter(|u| u.is_object()).map(|u| {
+        TokenUsage::new(
+            u["prompt_tokens"].as_u64().unwrap_or(0) as u32,
+            u["completion_tokens"].as_u64().unwrap_or(0) as u32,
+        )
+    });
+
+    if choice["finish_reason"] == "length" {
+        return Ok(LlmResponse::Truncated { content, usage, model: None });
+    }
+    match format.and_then(|f| f.parse(&content)) {
+        Some(TextReply::ToolCalls { mut calls, thought }) => {
+            for (i, call) in calls.iter_mut().enumerate() {
+                call.id.get_or_insert_with(|| format!("completion_{}_{}", step, i));
+            }
+            if calls.len() == 1 {
+                Ok(LlmResponse::ToolCall { tool: calls.remove(0), confidence: 1.0, assistant_text: thought, usage, model: None })
+            } else {
+                Ok(LlmResponse::ParallelToolCalls { tools: calls, confidence: 1.0, usage, model: None })
+            }
+        }
+        _ => Ok(LlmResponse::FinalAnswer { content, confidence: 1.0, usage, model: None }),
+    }
+}
+
+#[async_trait]
+impl AsyncLlmCaller for CompletionCaller {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        tools:  &ToolRegistry,
+        model:  &str,
+        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        let format = self.text_format(memory, tools);
+        let request = self
+            .authorize(self.client.post(format!("{}/completions", self.api_base)))
+            .json(&self.build_body(memory, tools, model, format));
+        let body = super::raw_log::send(memory, "completion", model, request, "Completion API error").await?;
+        parse_response(&body, format, memory.step)
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools:  &'a ToolRegistry,
+        model:  &'a str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        use futures::{stream, StreamExt};
+
+        let output_tx = output_tx.cloned();
+        stream::once(async move {
+            self.call_async(memory, tools, model, output_tx.as_ref())
+                .await
+                .map(LlmStreamChunk::Done)
+        })
+        .boxed()
+    }
+
+    async fn health_check(&self) -> Result<(), LlmError> {
+        self.get_models().await.map(|_| ())
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let models = self.get_models().await?;
+        Ok(models["data"]
+            .as_array()
+            .map(|data| data.iter().filter_map(|m| m["id"].as_str().map(str::to_string)).collect())
+            .unwrap_or_default())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::Tool;
+
+    fn turns(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(r, c)| (r.to_string(), c.to_string())).collect()
+    }
+
+    #[test]
+    fn test_render_presets() {
+        let t = turns(&[("system", "Be brief."), ("user", "Hi")]);
+        assert_eq!(
+            ChatTemplate::chatml().render(&t),
+            "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(
+            ChatTemplate::llama3().render(&t[1..]),
+            "<|begin_of_text|><|start_header_id|>user<|end_header_id|>\n\nHi<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>\n\n"
+        );
+
+        let custom = ChatTemplate::new("[{{ role }}] {{ content }}\n", "[assistant] ");
+        assert_eq!(custom.render(&turns(&[("user", "say {{role}}")])), "[user] say {{role}}\n[assistant] ");
+    }
+
+    #[test]
+    fn test_tool_calls_written_and_read_as_text() {
+        let caller = CompletionCaller::new("http://localhost:8000/v1/", ChatTemplate::chatml());
+        let mut tools = ToolRegistry::new();
+        tools.register_tool(Tool::new("search", "Search the web").param("query", "string", "Query").call(|_| Ok(String::new())));
+        let mut memory = AgentMemory::new("Population of Lyon?");
+        memory.history.push(crate::types::HistoryEntry {
+            step: 1,
+            tool: crate::types::ToolCall {
+                name: "search".to_string(),
+                args: [("query".to_string(), json!("Lyon"))].into_iter().collect(),
+                id: Some("c1".to_string()),
+            },
+            observation: crate::types::Observation::success("About 520,000"),
+            assistant_text: None,
+            latency_ms: None,
+            model_used: None,
+            usage: None,
+        });
+
+        let format = caller.text_format(&memory, &tools);
+        assert_eq!(format, Some(TextToolFormat::Xml));
+        let body = caller.build_body(&memory, &tools, "m", format);
+        let prompt = body["prompt"].as_str().unwrap();
+        assert!(prompt.starts_with("<|im_start|>system\n") && prompt.contains("<tool_call>"));
+        assert!(prompt.contains("<|im_start|>assistant\n<tool_call>{") && prompt.contains("{\"query\":\"Lyon\"}"));
+        assert!(prompt.contains("<|im_start|>tool\n"));
+        assert_eq!(body["stop"], json!(["<|im_end|>"]));
+
+        let reply = json!({
+            "choices": [{ "text": "Let me check.\n<tool_call>{\"name\": \"search\", \"arguments\": {\"query\": \"Lyon area\"}}</tool_call>", "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 50, "completion_tokens": 20 }
+        });
+        match parse_response(&reply.to_string(), format, 2).unwrap() {
+            LlmResponse::ToolCall { tool, assistant_text, usage, .. } => {
+                assert_eq!(tool.name, "search");
+                assert_eq!(tool.id.as_deref(), Some("completion_2_0"));
+                assert_eq!(assistant_text.as_deref(), Some("Let me check."));
+                assert_eq!(usage.unwrap().total_tokens, 70);
+            }
+            other => panic!("expected a tool call, got {:?}", other),
+        }
+
+        let cut = json!({ "choices": [{ "text": "It is", "finish_reason": "length" }] });
+        assert!(matches!(parse_response(&cut.to_string(), format, 2).unwrap(), LlmResponse::Truncated { .. }));
+    }
+}
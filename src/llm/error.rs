@@ -0,0 +1,258 @@
+//! LLM failures, classified.
+//!
+//! Callers return [`LlmError`] so that the planner, `RetryingLlmCaller` and
+//! applications can tell an expired key from a rate limit or a prompt that
+//! no longer fits, without matching on message text. Providers map HTTP
+//! statuses with [`LlmError::from_status`].
+//!
+//! # Migrating a caller
+//!
+//! `AsyncLlmCaller` and `LlmCaller` used to return `Result<_, String>`.
+//! Change the signatures to `Result<_, LlmError>`; string errors convert
+//! with `.into()` or `?`, classified by [`LlmError::classify`] from their
+//! text as before. Construct variants directly where the cause is known.
+//! `LlmError` converts back into `String` for code that still propagates
+//! strings.
+
+use std::time::Duration;
+use thiserror::Error;
+
+/// Why an LLM call failed. `Display` is the provider's message unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LlmError {
+    /// Missing or invalid API key, or no access to the model (401, 403)
+    #[error("{0}")]
+    Auth(String),
+
+    /// Too many requests or tokens (429). Retrying later may succeed.
+    #[error("{message}")]
+    RateLimited {
+        message: String,
+        /// How long the provider asked to wait, if it said
+        retry_after: Option<Duration>,
+    },
+
+    /// The prompt exceeded the model's context window. Retrying the same
+    /// prompt fails again; compress memory instead.
+    #[error("{0}")]
+    ContextOverflow(String),
+
+    /// The provider answered, but the response could not be understood
+    #[error("{0}")]
+    Parse(String),
+
+    /// The request did not reach the provider, or the connection broke
+    #[error("{0}")]
+    Network(String),
+
+    /// The provider failed to serve a valid request (5xx, overloaded)
+    #[error("{message}")]
+    Provider { status: Option<u16>, message: String },
+
+    /// The provider rejected the request itself (other 4xx): bad
+    /// parameters, unknown model. Retrying does not help.
+    #[error("{0}")]
+    InvalidRequest(String),
+
+    /// Anything not classified above
+    #[error("{0}")]
+    Other(String),
+}
+
+impl LlmError {
+    /// Classify an error response by its HTTP status. The body is kept as
+    /// the message; a 400 that reports an oversized prompt is a context overflow.
+    pub fn from_status(status: u16, body: impl Into<String>) -> Self {
+        let message = body.into();
+        match status {
+            401 | 403 => Self::Auth(message),
+            429 => Self::RateLimited { message, retry_after: None },
+            413 => Self::ContextOverflow(message),
+            _ if super::is_context_overflow(&message) => Self::ContextOverflow(message),
+            400..=499 => Self::InvalidRequest(message),
+            _ => Self::Provider { status: Some(status), message },
+        }
+    }
+
+    /// Classify an error from its text alone, for callers that only have a
+    /// string. Unrecognized messages become `Other`.
+    pub fn classify(message: impl Into<String>) -> Self {
+        let message = message.into();
+        let lower = message.to_lowercase();
+        if super::is_context_overflow(&message) {
+            Self::ContextOverflow(message)
+        } else if ["401", "403", "authentication", "unauthorized", "forbidden", "invalid api key"]
+            .iter()
+            .any(|p| lower.contains(p))
+        {
+            Self::Auth(message)
+        } else if [
+            "429",
+            "rate limit",
+            "too many requests",
+            "too_many_tokens_error",
+            "token_quota_exceeded",
+            "too_many_requests_error",
+            "queue_exceeded",
+            "limit exceeded",
+        ]
+        .iter()
+        .any(|p| lower.contains(p))
+        {
+            Self::RateLimited { message, retry_after: None }
+        } else if ["network error", "connection", "timed out"]
+            .iter()
+            .any(|p| lower.contains(p))
+        {
+            Self::Network(message)
+        } else {
+            Self::Other(message)
+        }
+    }
+
+    /// The same error with the provider's requested wait attached.
+    /// No effect on variants other than `RateLimited`.
+    pub fn with_retry_after(self, wait: Option<Duration>) -> Self {
+        match self {
+            Self::RateLimited { message, .. } => Self::RateLimited { message, retry_after: wait },
+            other => other,
+        }
+    }
+
+    /// The same classification with a different message.
+    pub fn with_message(self, message: impl Into<String>) -> Self {
+        let message = message.into();
+        match self {
+            Self::Auth(_) => Self::Auth(message),
+            Self::RateLimited { retry_after, .. } => Self::RateLimited { message, retry_after },
+            Self::ContextOverflow(_) => Self::ContextOverflow(message),
+            Self::Parse(_) => Self::Parse(message),
+            Self::Network(_) => Self::Network(message),
+            Self::Provider { status, .. } => Self::Provider { status, message },
+            Self::InvalidRequest(_) => Self::InvalidRequest(message),
+            Self::Other(_) => Self::Other(message),
+        }
+    }
+
+    /// The error message, without the classification.
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Auth(m)
+            | Self::ContextOverflow(m)
+            | Self::Parse(m)
+            | Self::Network(m)
+            | Self::InvalidRequest(m)
+            | Self::Other(m) => m,
+            Self::RateLimited { message, .. } | Self::Provider { message, .. } => message,
+        }
+    }
+
+    /// Short name of the variant, for logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Auth(_) => "auth",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::ContextOverflow(_) => "context_overflow",
+            Self::Parse(_) => "parse",
+            Self::Network(_) => "network",
+            Self::Provider { .. } => "provider",
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::Other(_) => "other",
+        }
+    }
+
+    /// Whether the same request may succeed if sent again.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, Self::Auth(_) | Self::ContextOverflow(_) | Self::InvalidRequest(_))
+    }
+
+    pub fn is_context_overflow(&self) -> bool {
+        matches!(self, Self::ContextOverflow(_))
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self, Self::RateLimited { .. })
+    }
+
+    /// How long the provider asked to wait before retrying, if it said.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Classify a failed HTTP response, honouring a `Retry-After` header in
+/// seconds. The message is `"{prefix} {status}: {body}"`.
+pub(crate) async fn from_response(resp: reqwest::Response, prefix: &str) -> LlmError {
+    let status = resp.status();
+    let retry_after = resp
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let body = resp.text().await.unwrap_or_default();
+    LlmError::from_status(status.as_u16(), format!("{} {}: {}", prefix, status, body))
+        .with_retry_after(retry_after)
+}
+
+impl From<String> for LlmError {
+    fn from(message: String) -> Self {
+        Self::classify(message)
+    }
+}
+
+impl From<&str> for LlmError {
+    fn from(message: &str) -> Self {
+        Self::classify(message)
+    }
+}
+
+impl From<LlmError> for String {
+    fn from(err: LlmError) -> Self {
+        err.to_string()
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        assert!(matches!(LlmError::from_status(401, "bad key"), LlmError::Auth(_)));
+        assert!(LlmError::from_status(429, "slow down").is_rate_limited());
+        assert!(LlmError::from_status(400, "prompt is too long: 210000 tokens").is_context_overflow());
+        assert!(matches!(LlmError::from_status(404, "no such model"), LlmError::InvalidRequest(_)));
+        assert_eq!(
+            LlmError::from_status(529, "overloaded"),
+            LlmError::Provider { status: Some(529), message: "overloaded".into() }
+        );
+    }
+
+    #[test]
+    fn test_classify_legacy_strings() {
+        let err: LlmError = "OpenAI API error: invalid api key".to_string().into();
+        assert_eq!(err.kind(), "auth");
+        assert!(!err.is_retryable());
+        assert_eq!(err.to_string(), "OpenAI API error: invalid api key");
+
+        assert!(LlmError::from("Rate limit reached for gpt-4o").is_rate_limited());
+        assert!(LlmError::from("This model's maximum context length is 8192").is_context_overflow());
+        assert_eq!(LlmError::from("Network error: connection reset").kind(), "network");
+        assert!(LlmError::from("something odd").is_retryable());
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let wait = Some(Duration::from_secs(7));
+        assert_eq!(LlmError::from_status(429, "x").with_retry_after(wait).retry_after(), wait);
+        assert_eq!(LlmError::from_status(500, "x").with_retry_after(wait).retry_after(), None);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::memory::AgentMemory;
+use crate::tools::ToolRegistry;
+use crate::types::{LlmResponse, LlmStreamChunk};
+use super::LlmError;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use std::sync::Arc;
+
+struct Provider {
+    caller: Arc<dyn super::AsyncLlmCaller>,
+    /// Model sent to this provider instead of the one the agent asks for
+    model:  Option<String>,
+}
+
+impl Provider {
+    /// Name the override model on a response, if there is one.
+    fn report_model(&self, response: LlmResponse) -> LlmResponse {
+        match &self.model {
+            Some(model) => response.with_model(model.clone()),
+            None => response,
+        }
+    }
+}
+
+/// A wrapper around an ordered list of `AsyncLlmCaller`s that moves on to
+/// the next provider when one is unavailable: rate limited (429), failing
+/// on its side (5xx, overloaded) or unreachable.
+///
+/// Other errors (auth, context overflow, invalid request) would fail the
+/// same way elsewhere, or point at a configuration mistake, so they are
+/// returned at once. When every provider is unavailable, the last error is
+/// returned.
+///
+/// A stream falls through only if it fails before its first chunk. Once
+/// tokens have been sent, an error is returned as it is.
+///
+/// A response from a provider with a model override names that model
+/// (`LlmResponse::answered_by`), so its usage is costed against it.
+pub struct FallbackLlmCaller {
+    providers: Vec<Provider>,
+}
+
+impl FallbackLlmCaller {
+    /// Providers in order of preference; the first is tried first.
+    pub fn new(callers: Vec<Arc<dyn super::AsyncLlmCaller>>) -> Self {
+        Self {
+            providers: callers.into_iter().map(|caller| Provider { caller, model: None }).collect(),
+        }
+    }
+
+    /// Send `model` to the provider at `index` instead of the model the
+    /// agent asks for, e.g. when a backup does not know the primary's model
+    /// names. Out-of-range indexes are ignored.
+    pub fn with_model(mut self, index: usize, model: impl Into<String>) -> Self {
+        if let Some(provider) = self.providers.get_mut(index) {
+            provider.model = Some(model.into());
+        }
+        self
+    }
+
+    /// Number of providers in the chain.
+    pub fn len(&self) -> usize {
+        self.providers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    /// Whether an error means this provider is unavailable right now.
+    fn falls_through(err: &LlmError) -> bool {
+        matches!(err, LlmError::RateLimited { .. } | LlmError::Provider { .. } | LlmError::Network(_))
+    }
+
+    fn no_providers() -> LlmError {
+        LlmError::Other("FallbackLlmCaller has no providers".to_string())
+    }
+
+    fn announce(
+        index:     usize,
+        err:       &LlmError,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) {
+        tracing::warn!(provider = index, error = %err, kind = err.kind(), "LLM provider unavailable — falling back");
+        if let Some(tx) = output_tx {
+            let _ = tx.send(crate::types::AgentOutput::Action(format!(
+                "LLM provider {} unavailable ({}). Falling back to provider {}...",
+                index + 1,
+                err.kind(),
+                index + 2
+            )));
+        }
+    }
+}
+
+#[async_trait]
+impl super::AsyncLlmCaller for FallbackLlmCaller {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        tools:  &ToolRegistry,
+        model:  &str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        let mut last_err = Self::no_providers();
+
+        for (i, provider) in self.providers.iter().enumerate() {
+            let model = provider.model.as_deref().unwrap_or(model);
+            match provider.caller.call_async(memory, tools, model, output_tx).await {
+                Ok(resp) => return Ok(provider.report_model(resp)),
+                Err(e) if !Self::falls_through(&e) => return Err(e),
+                Err(e) => {
+                    if i + 1 < self.providers.len() {
+                        Self::announce(i, &e, output_tx);
+                    }
+                    last_err = e;
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools:  &'a ToolRegistry,
+        model:  &'a str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        use futures::{stream, StreamExt};
+
+        let output_tx = output_tx.cloned();
+        stream::once(async move {
+            let last = self.providers.len().saturating_sub(1);
+            for (i, provider) in self.providers.iter().enumerate() {
+                let model = provider.model.as_deref().unwrap_or(model);
+                let mut chunks = provider.caller.call_stream_async(memory, tools, model, output_tx.as_ref());
+                match chunks.next().await {
+                    Some(Err(e)) if i < last && Self::falls_through(&e) => {
+                        Self::announce(i, &e, output_tx.as_ref());
+                    }
+                    first => {
+                        return stream::iter(first)
+                            .chain(chunks)
+                            .map(move |chunk| match chunk {
+                                Ok(LlmStreamChunk::Done(resp)) => Ok(LlmStreamChunk::Done(provider.report_model(resp))),
+                                other => other,
+                            })
+                            .boxed()
+                    }
+                }
+            }
+            stream::iter([Err(Self::no_providers())]).boxed()
+        })
+        .flatten()
+        .boxed()
+    }
+
+    /// Healthy if any provider is; otherwise the first provider's error.
+    async fn health_check(&self) -> Result<(), LlmError> {
+        let mut first_err = None;
+        for provider in &self.providers {
+            match provider.caller.health_check().await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_err.unwrap_or_else(Self::no_providers))
+    }
+
+    /// Models of every provider that answered, in chain order, without
+    /// duplicates. Fails only if every provider fails.
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let mut models: Vec<String> = Vec::new();
+        let mut first_err = None;
+        let mut any_ok = false;
+        for provider in &self.providers {
+            match provider.caller.list_models().await {
+                Ok(list) => {
+                    any_ok = true;
+                    for m in list {
+                        if !models.contains(&m) {
+                            models.push(m);
+                        }
+                    }
+                }
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+        if any_ok {
+            Ok(models)
+        } else {
+            Err(first_err.unwrap_or_else(Self::no_providers))
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::AsyncLlmCaller;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// Fails with `error` if set, otherwise answers with its name. Records
+    /// the models it was asked for.
+    struct Scripted {
+        name:   &'static str,
+        error:  Option<LlmError>,
+        calls:  AtomicUsize,
+        models: Mutex<Vec<String>>,
+    }
+
+    fn scripted(name: &'static str, error: Option<LlmError>) -> Arc<Scripted> {
+        Arc::new(Scripted { name, error, calls: AtomicUsize::new(0), models: Mutex::new(Vec::new()) })
+    }
+
+    #[async_trait]
+    impl AsyncLlmCaller for Scripted {
+        async fn call_async(
+            &self,
+            _memory: &AgentMemory,
+            _tools:  &ToolRegistry,
+            model:   &str,
+            _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+        ) -> Result<LlmResponse, LlmError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.models.lock().unwrap().push(model.to_string());
+            match &self.error {
+                Some(e) => Err(e.clone()),
+                None => Ok(LlmResponse::FinalAnswer { content: self.name.to_string(), confidence: 1.0, usage: None, model: None }),
+            }
+        }
+
+        fn call_stream_async<'a>(
+            &'a self,
+            memory: &'a AgentMemory,
+            tools:  &'a ToolRegistry,
+            model:  &'a str,
+            output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+        ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+            use futures::{stream, StreamExt};
+            let output_tx = output_tx.cloned();
+            stream::once(async move {
+                self.call_async(memory, tools, model, output_tx.as_ref()).await
+            })
+            .flat_map(|result| {
+                let chunks = match result {
+                    Ok(resp) => vec![Ok(LlmStreamChunk::Content("tok".to_string())), Ok(LlmStreamChunk::Done(resp))],
+                    Err(e) => vec![Err(e)],
+                };
+                stream::iter(chunks)
+            })
+            .boxed()
+        }
+    }
+
+    fn answer(result: &Result<LlmResponse, LlmError>) -> Option<&str> {
+        match result {
+            Ok(LlmResponse::FinalAnswer { content, .. }) => Some(content),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_through_on_rate_limit_and_provider_errors() {
+        let limited = scripted("a", Some(LlmError::from_status(429, "slow down")));
+        let down = scripted("b", Some(LlmError::from_status(503, "overloaded")));
+        let backup = scripted("c", None);
+        let caller = FallbackLlmCaller::new(vec![limited.clone() as Arc<dyn AsyncLlmCaller>, down, backup.clone()])
+            .with_model(2, "backup-model");
+        let memory = AgentMemory::new("task");
+
+        let result = caller.call_async(&memory, &ToolRegistry::new(), "primary-model", None).await;
+
+        assert_eq!(answer(&result), Some("c"));
+        assert_eq!(*limited.models.lock().unwrap(), vec!["primary-model"]);
+        assert_eq!(*backup.models.lock().unwrap(), vec!["backup-model"]);
+        // The backup's tokens are costed against the model it ran
+        assert_eq!(result.unwrap().answered_by(), Some("backup-model"));
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_and_exhaustion_are_returned() {
+        let auth = scripted("a", Some(LlmError::Auth("bad key".into())));
+        let backup = scripted("b", None);
+        let caller = FallbackLlmCaller::new(vec![auth as Arc<dyn AsyncLlmCaller>, backup.clone()]);
+        let memory = AgentMemory::new("task");
+        let tools = ToolRegistry::new();
+
+        let result = caller.call_async(&memory, &tools, "m", None).await;
+        assert!(matches!(result, Err(LlmError::Auth(_))));
+        assert_eq!(backup.calls.load(Ordering::SeqCst), 0);
+
+        let caller = FallbackLlmCaller::new(vec![
+            scripted("a", Some(LlmError::Network("refused".into()))) as Arc<dyn AsyncLlmCaller>,
+            scripted("b", Some(LlmError::from_status(500, "boom"))),
+        ]);
+        let result = caller.call_async(&memory, &tools, "m", None).await;
+        assert!(matches!(result, Err(LlmError::Provider { status: Some(500), .. })));
+    }
+
+    #[tokio::test]
+    async fn test_stream_falls_through_before_first_chunk() {
+        use futures::StreamExt;
+        let down = scripted("a", Some(LlmError::from_status(502, "bad gateway")));
+        let backup = scripted("b", None);
+        let caller = FallbackLlmCaller::new(vec![down as Arc<dyn AsyncLlmCaller>, backup]);
+        let memory = AgentMemory::new("task");
+        let tools = ToolRegistry::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let chunks: Vec<_> = caller.call_stream_async(&memory, &tools, "m", Some(&tx)).collect().await;
+
+        assert_eq!(chunks.len(), 2);
+        assert!(matches!(&chunks[1], Ok(LlmStreamChunk::Done(LlmResponse::FinalAnswer { content, .. })) if content == "b"));
+        assert!(matches!(&chunks[1], Ok(LlmStreamChunk::Done(resp)) if resp.answered_by().is_none()));
+        assert!(matches!(rx.try_recv(), Ok(crate::types::AgentOutput::Action(msg)) if msg.contains("Falling back")));
+    }
+}
@@ -0,0 +1,640 @@
+use async_trait::async_trait;
+use crate::llm::{AsyncLlmCaller, LlmError};
+use crate::memory::AgentMemory;
+use crate::tools::{parse_tool_args, ToolRegistry, ToolSchema};
+use crate::types::{LlmResponse, LlmStreamChunk, ToolCall};
+use serde_json::{json, Map, Value};
+
+const API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+/// Model used when none is configured; the API has no default.
+const DEFAULT_MODEL: &str = "gemini-2.0-flash";
+/// Most stop sequences the API accepts.
+const MAX_STOP_SEQUENCES: usize = 5;
+/// Most models `GET /models` returns per page.
+const MODEL_PAGE_SIZE: usize = 1000;
+/// Schema keywords of the OpenAPI subset Gemini accepts. Others, such as
+/// `additionalProperties`, `$schema` or `default`, fail the request.
+const SCHEMA_KEYWORDS: &[&str] = &[
+    "type", "format", "description", "nullable", "enum", "required", "minItems", "maxItems",
+    "minimum", "maximum", "minLength", "maxLength", "pattern", "propertyOrdering",
+];
+
+// ── Caller ───────────────────────────────────────────────
+
+/// Google Gemini through its native `generateContent` REST API.
+///
+/// Chat history is converted to Gemini `contents`: assistant turns become
+/// `model` turns with `functionCall` parts, tool results become
+/// `functionResponse` parts, and consecutive turns of one role are merged,
+/// since Gemini expects them to alternate. Tool schemas are reduced to the
+/// OpenAPI subset Gemini accepts. Structured output uses `responseSchema`.
+pub struct GeminiCaller {
+    client:   reqwest::Client,
+    api_key:  String,
+    api_base: String,
+}
+
+impl GeminiCaller {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self::with_base_url(API_BASE, api_key)
+    }
+
+    /// A Gemini-compatible endpoint other than Google's, such as a proxy.
+    pub fn with_base_url(base_url: impl Into<String>, api_key: impl Into<String>) -> Self {
+        Self {
+            client:   reqwest::Client::new(),
+            api_key:  api_key.into(),
+            api_base: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Key from `GEMINI_API_KEY`, or `GOOGLE_API_KEY`.
+    pub fn from_env() -> Result<Self, String> {
+        let key = std::env::var("GEMINI_API_KEY")
+            .or_else(|_| std::env::var("GOOGLE_API_KEY"))
+            .map_err(|_| "GEMINI_API_KEY not set".to_string())?;
+        Ok(Self::new(key))
+    }
+
+    fn url(&self, model: &str, method: &str) -> String {
+        let model = if model.is_empty() { DEFAULT_MODEL } else { model };
+        format!("{}/models/{}:{}", self.api_base, model.trim_start_matches("models/"), method)
+    }
+
+    fn build_body(memory: &AgentMemory, tools: &ToolRegistry) -> Value {
+        let (system, contents) = convert_messages(memory.build_messages());
+        let mut body = json!({ "contents": contents });
+        if let Some(system) = system {
+            body["systemInstruction"] = json!({ "parts": [{ "text": system }] });
+        }
+
+        let sampling = &memory.config.sampling;
+        let mut generation = Map::new();
+        if let Some(max_tokens) = sampling.max_tokens {
+            generation.insert("maxOutputTokens".into(), max_tokens.into());
+        }
+        if let Some(temperature) = sampling.temperature {
+            generation.insert("temperature".into(), temperature.into());
+        }
+        if let Some(seed) = sampling.seed {
+            generation.insert("seed".into(), seed.into());
+        }
+        if !memory.stop.is_empty() {
+            if memory.stop.len() > MAX_STOP_SEQUENCES {
+                tracing::warn!(count = memory.stop.len(), "Gemini accepts 5 stop sequences; sending the first 5");
+            }
+            let stop: Vec<&String> = memory.stop.iter().take(MAX_STOP_SEQUENCES).collect();
+            generation.insert("stopSequences".into(), json!(stop));
+        }
+
+        if let Some(schema) = &memory.config.output_schema {
+            // Tools and a response schema conflict; the schema wins
+            generation.insert("responseMimeType".into(), "application/json".into());
+            generation.insert("responseSchema".into(), gemini_schema(&schema.schema));
+        } else if !tools.is_empty() {
+            let declarations: Vec<Value> = tools.schemas().into_iter().map(function_declaration).collect();
+            body["tools"] = json!([{ "functionDeclarations": declarations }]);
+        }
+        if !generation.is_empty() {
+            body["generationConfig"] = Value::Object(generation);
+        }
+        body
+    }
+
+    /// First page of `GET /models`.
+    async fn get_models(&self, page_size: usize) -> Result<Value, LlmError> {
+        let response = self.client
+            .get(format!("{}/models?pageSize={}", self.api_base, page_size))
+            .header("x-goog-api-key", &self.api_key)
+            .send()
+            .await
+            .map_err(|e| LlmError::Network(format!("Network error: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(super::error::from_response(response, "Gemini API error").await);
+        }
+        response.json()
+            .await
+            .map_err(|e| LlmError::Parse(format!("Failed to parse Gemini model list: {}", e)))
+    }
+}
+
+// ── Request conversion ───────────────────────────────────
+
+/// Split OpenAI-style chat messages into Gemini's system instruction and
+/// `contents`.
+fn convert_messages(messages: Vec<Value>) -> (Option<String>, Vec<Value>) {
+    let mut system = Vec::new();
+    let mut contents: Vec<Value> = Vec::new();
+
+    for message in messages {
+        let (role, parts) = match message["role"].as_str().unwrap_or("user") {
+            "system" => {
+                system.extend(message["content"].as_str().map(str::to_string));
+                continue;
+            }
+            "assistant" => {
+                let mut parts = text_parts(&message["content"]);
+                for call in message["tool_calls"].as_array().into_iter().flatten() {
+                    let args = call["function"]["arguments"]
+                        .as_str()
+                        .and_then(|raw| serde_json::from_str::<Value>(raw).ok())
+                        .filter(Value::is_object)
+                        .unwrap_or_else(|| json!({}));
+                    parts.push(json!({
+                        "functionCall": { "name": call["function"]["name"], "args": args }
+                    }));
+                }
+                ("model", parts)
+            }
+            // The response must be an object; the observation text is wrapped
+            "tool" => ("user", vec![json!({
+                "functionResponse": {
+                    "name": message["name"],
+                    "response": { "result": message["content"] },
+                }
+            })]),
+            _ => ("user", text_parts(&message["content"])),
+        };
+        if parts.is_empty() {
+            continue;
+        }
+
+        // Turns must alternate. Merging also keeps every result of a
+        // parallel call in the one turn that answers it.
+        match contents.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(existing) = last["parts"].as_array_mut() {
+                    existing.extend(parts);
+                }
+            }
+            _ => contents.push(json!({ "role": role, "parts": parts })),
+        }
+    }
+
+    let system = (!system.is_empty()).then(|| system.join("\n\n"));
+    (system, contents)
+}
+
+/// Text parts of a message whose content is a string or an array of blocks.
+fn text_parts(content: &Value) -> Vec<Value> {
+    let texts: Vec<&str> = match content {
+        Value::String(text) => vec![text.as_str()],
+        Value::Array(blocks) => blocks.iter().filter_map(|b| b["text"].as_str()).collect(),
+        _ => Vec::new(),
+    };
+    texts.into_iter().filter(|t| !t.is_empty()).map(|t| json!({ "text": t })).collect()
+}
+
+fn function_declaration(schema: ToolSchema) -> Value {
+    let mut declaration = json!({ "name": schema.name, "description": schema.description });
+    let parameters = gemini_schema(&schema.input_schema);
+    // An object schema without properties is rejected; such tools take no parameters
+    if parameters["properties"].as_object().is_some_and(|p| !p.is_empty()) {
+        declaration["parameters"] = parameters;
+    }
+    declaration
+}
+
+/// Reduce a JSON Schema to the OpenAPI subset Gemini accepts. Unsupported
+/// keywords are dropped, `"type": ["string", "null"]` becomes a nullable
+/// string and `const` becomes a one-value `enum`.
+fn gemini_schema(schema: &Value) -> Value {
+    let Some(object) = schema.as_object() else {
+        return schema.clone();
+    };
+    let mut out = Map::new();
+    for (key, value) in object {
+        match key.as_str() {
+            "type" => match value {
+                Value::Array(types) => {
+                    let concrete: Vec<&Value> = types.iter().filter(|t| t.as_str() != Some("null")).collect();
+                    if let Some(first) = concrete.first() {
+                        out.insert("type".into(), (*first).clone());
+                    }
+                    if concrete.len() < types.len() {
+                        out.insert("nullable".into(), Value::Bool(true));
+                    }
+                }
+                other => {
+                    out.insert("type".into(), other.clone());
+                }
+            },
+            "const" => {
+                out.insert("enum".into(), json!([value]));
+            }
+            "properties" => {
+                let properties: Map<String, Value> = value
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, property)| (name.clone(), gemini_schema(property)))
+                    .collect();
+                out.insert(key.clone(), Value::Object(properties));
+            }
+            "items" => {
+                out.insert(key.clone(), gemini_schema(value));
+            }
+            "anyOf" => {
+                let options = value.as_array().into_iter().flatten().map(gemini_schema).collect();
+                out.insert(key.clone(), Value::Array(options));
+            }
+            k if SCHEMA_KEYWORDS.contains(&k) => {
+                out.insert(key.clone(), value.clone());
+            }
+            _ => {}
+        }
+    }
+    Value::Object(out)
+}
+
+// ── Response parsing ─────────────────────────────────────
+
+fn usage_of(response: &Value) -> Option<crate::budget::TokenUsage> {
+    let meta = response.get("usageMetadata")?;
+    let count = |key: &str| meta[key].as_u64().unwrap_or(0) as u32;
+    // Thinking models report their thoughts separately; they are billed as output
+    Some(crate::budget::TokenUsage::new(
+        count("promptTokenCount"),
+        count("candidatesTokenCount") + count("thoughtsTokenCount"),
+    ))
+}
+
+fn block_reason(response: &Value) -> Option<LlmError> {
+    let reason = response["promptFeedback"]["blockReason"].as_str()?;
+    Some(LlmError::InvalidRequest(format!("Gemini blocked the prompt: {}", reason)))
+}
+
+fn tool_call(call: &Value) -> ToolCall {
+    let args = match &call["args"] {
+        Value::Object(map) => map.clone().into_iter().collect(),
+        Value::String(raw) => parse_tool_args(raw),
+        Value::Null => Default::default(),
+        other => parse_tool_args(&other.to_string()),
+    };
+    ToolCall {
+        name: call["name"].as_str().unwrap_or_default().to_string(),
+        args,
+        id:   call["id"].as_str().map(str::to_string),
+    }
+}
+
+/// Build the response once the text, calls and finish reason are known.
+fn to_response(
+    text:       String,
+    mut calls:  Vec<ToolCall>,
+    finish:     &str,
+    usage:      Option<crate::budget::TokenUsage>,
+    structured: bool,
+) -> Result<LlmResponse, LlmError> {
+    match calls.len() {
+        0 => {}
+        1 => {
+            return Ok(LlmResponse::ToolCall {
+                tool: calls.remove(0),
+                confidence: 1.0,
+                assistant_text: Some(text).filter(|t| !t.trim().is_empty()),
+                usage,
+                model: None,
+            })
+        }
+        _ => return Ok(LlmResponse::ParallelToolCalls { tools: calls, confidence: 1.0, usage, model: None }),
+    }
+
+    if text.is_empty() {
+        return Err(match finish {
+            "" | "STOP" | "MAX_TOKENS" => LlmError::Parse("Gemini returned empty content".to_string()),
+            // SAFETY, RECITATION, BLOCKLIST, ...: the same prompt is refused again
+            reason => LlmError::InvalidRequest(format!("Gemini returned no content (finishReason {})", reason)),
+        });
+    }
+    if finish == "MAX_TOKENS" {
+        return Ok(LlmResponse::Truncated { content: text, usage, model: None });
+    }
+    if structured {
+        let data = serde_json::from_str(&text)
+            .map_err(|e| LlmError::Parse(format!("Gemini structured output is not JSON: {}", e)))?;
+        return Ok(LlmResponse::Structured { data, usage, model: None });
+    }
+    Ok(LlmResponse::FinalAnswer { content: text, confidence: 1.0, usage, model: None })
+}
+
+/// Parse a `generateContent` response.
+fn parse_response(response: &Value, structured: bool) -> Result<LlmResponse, LlmError> {
+    if let Some(e) = block_reason(response) {
+        return Err(e);
+    }
+    let candidate = &response["candidates"][0];
+    let mut text = String::new();
+    let mut calls = Vec::new();
+    for part in candidate["content"]["parts"].as_array().into_iter().flatten() {
+        if part["thought"] == true {
+            continue;
+        }
+        if let Some(t) = part["text"].as_str() {
+            text.push_str(t);
+        }
+        if let Some(call) = part.get("functionCall") {
+            calls.push(tool_call(call));
+        }
+    }
+    let finish = candidate["finishReason"].as_str().unwrap_or_default();
+    to_response(text, calls, finish, usage_of(response), structured)
+}
+
+// ── Stream parsing ───────────────────────────────────────
+
+/// Accumulates `streamGenerateContent?alt=sse` events into stream chunks.
+/// Text arrives in pieces; function calls arrive whole.
+#[derive(Default)]
+struct GeminiStream {
+    buffer:     String,
+    text:       String,
+    calls:      Vec<ToolCall>,
+    usage:      Option<crate::budget::TokenUsage>,
+    structured: bool,
+    done:       bool,
+}
+
+impl GeminiStream {
+    fn new(structured: bool) -> Self {
+        Self { structured, ..Default::default() }
+    }
+
+    /// Feed raw bytes; lines split across network chunks are buffered.
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Result<LlmStreamChunk, LlmError>> {
+        self.buffer.push_str(&String::from_utf8_lossy(bytes));
+        let mut chunks = Vec::new();
+        while let Some(pos) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=pos).collect();
+            if let Some(data) = line.trim().strip_prefix("data:") {
+                chunks.extend(self.handle_data(data.trim()));
+            }
+        }
+        chunks
+    }
+
+    fn handle_data(&mut self, data: &str) -> Vec<Result<LlmStreamChunk, LlmError>> {
+        if self.done {
+            return Vec::new();
+        }
+        let event: Value = match serde_json::from_str(data) {
+            Ok(v) => v,
+            Err(e) => return vec![Err(LlmError::Parse(format!("Invalid stream event: {}", e)))],
+        };
+        if let Some(e) = block_reason(&event) {
+            self.done = true;
+            return vec![Err(e)];
+        }
+        if let Some(usage) = usage_of(&event) {
+            self.usage = Some(usage);
+        }
+
+        let candidate = &event["candidates"][0];
+        let mut chunks = Vec::new();
+        for part in candidate["content"]["parts"].as_array().into_iter().flatten() {
+            if let Some(t) = part["text"].as_str().filter(|t| !t.is_empty()) {
+                if part["thought"] == true {
+                    chunks.push(Ok(LlmStreamChunk::Reasoning(t.to_string())));
+                } else {
+                    self.text.push_str(t);
+                    chunks.push(Ok(LlmStreamChunk::Content(t.to_string())));
+                }
+            }
+            if let Some(call) = part.get("functionCall") {
+                let call = tool_call(call);
+                chunks.push(Ok(LlmStreamChunk::ToolCallDelta {
+                    name:      Some(call.name.clone()),
+                    args_json: serde_json::to_string(&call.args).unwrap_or_default(),
+                }));
+                self.calls.push(call);
+            }
+        }
+
+        if let Some(finish) = candidate["finishReason"].as_str() {
+            self.done = true;
+            let text = std::mem::take(&mut self.text);
+            let calls = std::mem::take(&mut self.calls);
+            chunks.push(to_response(text, calls, finish, self.usage, self.structured).map(LlmStreamChunk::Done));
+        }
+        chunks
+    }
+}
+
+#[async_trait]
+impl AsyncLlmCaller for GeminiCaller {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        tools:  &ToolRegistry,
+        model:  &str,
+        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        let body = Self::build_body(memory, tools);
+        let request = self.client
+            .post(self.url(model, "generateContent"))
+            .header("x-goog-api-key", &self.api_key)
+            .json(&body);
+        let text = super::raw_log::send(memory, "gemini", model, request, "Gemini API error").await?;
+        let parsed: Value = serde_json::from_str(&text)
+            .map_err(|e| LlmError::Parse(format!("Failed to parse Gemini response: {}", e)))?;
+        parse_response(&parsed, memory.config.output_schema.is_some())
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools:  &'a ToolRegistry,
+        model:  &'a str,
+        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> futures::stream::BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        use futures::{StreamExt, stream};
+
+        let body = Self::build_body(memory, tools);
+        let structured = memory.config.output_schema.is_some();
+        let client = self.client.clone();
+        let api_key = self.api_key.clone();
+        let url = format!("{}?alt=sse", self.url(model, "streamGenerateContent"));
+
+        let s = stream::once(async move {
+            let request = client
+                .post(url)
+                .header("x-goog-api-key", &api_key)
+                .json(&body);
+            super::raw_log::send_streaming(memory, "gemini", model, request, "Gemini API error").await
+        })
+        .flat_map(move |res| {
+            match res {
+                Ok(resp) => {
+                    let mut parser = GeminiStream::new(structured);
+                    resp.bytes_stream()
+                        .map(move |res| {
+                            let bytes = res.map_err(|e| LlmError::Network(format!("Stream error: {}", e)))?;
+                            Ok(parser.feed(&bytes))
+                        })
+                        .flat_map(|res| {
+                            match res {
+                                Ok(chunks) => stream::iter(chunks),
+                                Err(e) => stream::iter(vec![Err(e)]),
+                            }
+                        })
+                        .boxed()
+                }
+                Err(e) => stream::once(async move { Err(e) }).boxed(),
+            }
+        });
+
+        s.boxed()
+    }
+
+    async fn health_check(&self) -> Result<(), LlmError> {
+        self.get_models(1).await.map(|_| ())
+    }
+
+    /// Models that support `generateContent`, without the `models/` prefix.
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let page = self.get_models(MODEL_PAGE_SIZE).await?;
+        Ok(page["models"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|m| {
+                m["supportedGenerationMethods"]
+                    .as_array()
+                    .is_none_or(|methods| methods.iter().any(|x| x == "generateContent"))
+            })
+            .filter_map(|m| m["name"].as_str())
+            .map(|name| name.trim_start_matches("models/").to_string())
+            .collect())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_becomes_alternating_contents() {
+        let (system, contents) = convert_messages(vec![
+            json!({ "role": "system", "content": "Be brief." }),
+            json!({ "role": "user", "content": "Weather in Oslo and Rome?" }),
+            json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [
+                    { "id": "a", "type": "function", "function": { "name": "weather", "arguments": "{\"city\":\"Oslo\"}" } },
+                    { "id": "b", "type": "function", "function": { "name": "weather", "arguments": "{\"city\":\"Rome\"}" } },
+                ],
+            }),
+            json!({ "role": "tool", "tool_call_id": "a", "name": "weather", "content": "SUCCESS: 4C" }),
+            json!({ "role": "tool", "tool_call_id": "b", "name": "weather", "content": "SUCCESS: 19C" }),
+            json!({ "role": "user", "content": "Answer in Celsius." }),
+        ]);
+
+        assert_eq!(system.as_deref(), Some("Be brief."));
+        let roles: Vec<&str> = contents.iter().map(|c| c["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, vec!["user", "model", "user"]);
+        assert_eq!(contents[1]["parts"][1]["functionCall"], json!({ "name": "weather", "args": { "city": "Rome" } }));
+        let answer = &contents[2]["parts"];
+        assert_eq!(answer[0]["functionResponse"]["response"]["result"], "SUCCESS: 4C");
+        assert_eq!(answer[1]["functionResponse"]["name"], "weather");
+        assert_eq!(answer[2]["text"], "Answer in Celsius.");
+    }
+
+    #[test]
+    fn test_schema_reduced_to_supported_subset() {
+        let schema = gemini_schema(&json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "unit": { "const": "celsius", "default": "celsius" },
+                "note": { "type": ["string", "null"] },
+                "days": { "type": "array", "items": { "type": "integer", "examples": [1] } },
+            },
+            "required": ["unit"],
+        }));
+        assert_eq!(schema, json!({
+            "type": "object",
+            "properties": {
+                "unit": { "enum": ["celsius"] },
+                "note": { "type": "string", "nullable": true },
+                "days": { "type": "array", "items": { "type": "integer" } },
+            },
+            "required": ["unit"],
+        }));
+
+        let no_params = function_declaration(ToolSchema {
+            name: "now".into(),
+            description: "Current time".into(),
+            input_schema: json!({ "type": "object", "properties": {} }),
+        });
+        assert!(no_params.get("parameters").is_none());
+    }
+
+    #[test]
+    fn test_body_uses_response_schema_instead_of_tools() {
+        let mut memory = AgentMemory::new("Extract the person");
+        memory.config.sampling.max_tokens = Some(256);
+        memory.config.output_schema = Some(crate::types::OutputSchema {
+            name: "person".into(),
+            description: None,
+            schema: json!({ "type": "object", "properties": { "name": { "type": "string" } } }),
+            strict: false,
+        });
+        let mut tools = ToolRegistry::new();
+        tools.register("noop", "desc", json!({}), std::sync::Arc::new(|_| Ok(String::new())));
+
+        let body = GeminiCaller::build_body(&memory, &tools);
+        assert!(body.get("tools").is_none());
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 256);
+        assert_eq!(body["generationConfig"]["responseMimeType"], "application/json");
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "Extract the person");
+    }
+
+    #[test]
+    fn test_response_with_function_calls() {
+        let response = json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [
+                    { "text": "Checking both." },
+                    { "functionCall": { "name": "weather", "args": { "city": "Oslo" } } },
+                ] },
+                "finishReason": "STOP",
+            }],
+            "usageMetadata": { "promptTokenCount": 20, "candidatesTokenCount": 7 },
+        });
+        match parse_response(&response, false).unwrap() {
+            LlmResponse::ToolCall { tool, assistant_text, usage, .. } => {
+                assert_eq!(tool.name, "weather");
+                assert_eq!(tool.args["city"], "Oslo");
+                assert_eq!(assistant_text.as_deref(), Some("Checking both."));
+                assert_eq!(usage.unwrap().total_tokens, 27);
+            }
+            other => panic!("expected tool call, got {:?}", other),
+        }
+
+        let blocked = json!({ "promptFeedback": { "blockReason": "SAFETY" } });
+        assert!(matches!(parse_response(&blocked, false), Err(LlmError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn test_stream_collects_text_until_finish() {
+        let mut parser = GeminiStream::new(false);
+        let mut chunks = parser.feed(b"data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"The answer\"}]}}]}\n\ndata: {\"cand");
+        chunks.extend(parser.feed(
+            b"idates\":[{\"content\":{\"parts\":[{\"text\":\" is\"}]},\"finishReason\":\"MAX_TOKENS\"}]}\n\n",
+        ));
+        let chunks: Vec<LlmStreamChunk> = chunks.into_iter().map(|c| c.unwrap()).collect();
+
+        assert!(matches!(&chunks[0], LlmStreamChunk::Content(t) if t == "The answer"));
+        match chunks.last() {
+            Some(LlmStreamChunk::Done(LlmResponse::Truncated { content, .. })) => assert_eq!(content, "The answer is"),
+            other => panic!("expected truncated answer, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,438 @@
+use async_trait::async_trait;
+use crate::budget::TokenUsage;
+use crate::grammar::GrammarConstraint;
+use crate::llm::{chat_turns, stop_position, AsyncLlmCaller, ChatTemplate, LlmError};
+use crate::memory::AgentMemory;
+use crate::tools::ToolRegistry;
+use crate::types::{LlmResponse, LlmStreamChunk};
+use futures::stream::{self, BoxStream, StreamExt};
+use llama_cpp_2::context::params::LlamaContextParams;
+use llama_cpp_2::llama_backend::LlamaBackend;
+use llama_cpp_2::llama_batch::LlamaBatch;
+use llama_cpp_2::model::params::LlamaModelParams;
+use llama_cpp_2::model::{AddBos, LlamaChatMessage, LlamaModel, Special};
+use llama_cpp_2::sampling::LlamaSampler;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+// ── Params ───────────────────────────────────────────────
+
+/// Model loading and sampling options for [`LlamaCppCaller`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlamaCppParams {
+    /// Context window in tokens
+    pub n_ctx: u32,
+    /// Maximum tokens generated per call
+    pub max_tokens: u32,
+    /// Layers offloaded to the GPU (0 = CPU only)
+    pub gpu_layers: u32,
+    /// Sampling temperature (0 = greedy)
+    pub temperature: f32,
+    /// Seed for the sampler
+    pub seed: u32,
+}
+
+impl Default for LlamaCppParams {
+    fn default() -> Self {
+        Self {
+            n_ctx: 4096,
+            max_tokens: 1024,
+            gpu_layers: 0,
+            temperature: 0.2,
+            seed: 42,
+        }
+    }
+}
+
+impl LlamaCppParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn n_ctx(mut self, tokens: u32) -> Self {
+        self.n_ctx = tokens;
+        self
+    }
+
+    pub fn max_tokens(mut self, tokens: u32) -> Self {
+        self.max_tokens = tokens;
+        self
+    }
+
+    pub fn gpu_layers(mut self, layers: u32) -> Self {
+        self.gpu_layers = layers;
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+// ── Caller ───────────────────────────────────────────────
+
+/// Runs a GGUF model in-process through llama.cpp (feature `llama-cpp`).
+///
+/// No HTTP is involved, so agents work fully offline. The model is loaded
+/// on the first call and shared by clones of the builder. Generation runs
+/// on a blocking thread and streams tokens as `LlmStreamChunk::Content`.
+///
+/// There is no native function calling: pair it with a text
+/// `PlanningPrompter` (`.llama_cpp()` on the builder selects `JsonPrompter`
+/// unless another text prompter is set). A `GrammarConstraint::Gbnf` in the
+/// memory is applied as a grammar sampler; JSON schema constraints are not
+/// supported in-process, so use `GrammarConstraint::ToolCallsGbnf`.
+pub struct LlamaCppCaller {
+    path:   PathBuf,
+    params: LlamaCppParams,
+    model:  Arc<OnceLock<Result<Arc<LlamaModel>, String>>>,
+}
+
+impl LlamaCppCaller {
+    /// Caller for the GGUF file at `path`. Loading is deferred to the first call.
+    pub fn new(path: impl Into<PathBuf>, params: LlamaCppParams) -> Self {
+        Self {
+            path: path.into(),
+            params,
+            model: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Load the model now, so a bad path fails here instead of on the first call.
+    pub fn load(path: impl Into<PathBuf>, params: LlamaCppParams) -> Result<Self, String> {
+        let caller = Self::new(path, params);
+        load_model(&caller.model, &caller.path, &caller.params)?;
+        Ok(caller)
+    }
+}
+
+static BACKEND: OnceLock<Result<LlamaBackend, String>> = OnceLock::new();
+
+/// llama.cpp may only be initialized once per process.
+fn backend() -> Result<&'static LlamaBackend, String> {
+    BACKEND
+        .get_or_init(|| LlamaBackend::init().map_err(|e| format!("llama.cpp init failed: {}", e)))
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+fn load_model(
+    cell:   &OnceLock<Result<Arc<LlamaModel>, String>>,
+    path:   &Path,
+    params: &LlamaCppParams,
+) -> Result<Arc<LlamaModel>, String> {
+    cell.get_or_init(|| {
+        let model_params = LlamaModelParams::default().with_n_gpu_layers(params.gpu_layers);
+        LlamaModel::load_from_file(backend()?, path, &model_params)
+            .map(Arc::new)
+            .map_err(|e| format!("Failed to load model {}: {}", path.display(), e))
+    })
+    .clone()
+}
+
+// ── Prompt ───────────────────────────────────────────────
+
+/// Render turns with the model's chat template, or ChatML if it has none.
+fn render_prompt(model: &LlamaModel, turns: &[(String, String)]) -> String {
+    let templated = model.chat_template(None).ok().and_then(|template| {
+        let messages = turns
+            .iter()
+            .map(|(role, content)| LlamaChatMessage::new(role.clone(), content.clone()))
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        model.apply_chat_template(&template, &messages, true).ok()
+    });
+    prompt_or_chatml(templated, turns)
+}
+
+/// The prompt rendered by the model's own template, or `turns` in ChatML
+/// when the model has no template or it could not be applied.
+fn prompt_or_chatml(templated: Option<String>, turns: &[(String, String)]) -> String {
+    templated.unwrap_or_else(|| ChatTemplate::chatml().render(turns))
+}
+
+// ── Sampling ─────────────────────────────────────────────
+
+/// How the next token is picked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sampling {
+    /// Always the most likely token (temperature 0)
+    Greedy,
+    Random { temperature: f32, seed: u32 },
+}
+
+impl Sampling {
+    fn for_params(params: &LlamaCppParams) -> Self {
+        if params.temperature <= 0.0 {
+            Sampling::Greedy
+        } else {
+            Sampling::Random { temperature: params.temperature, seed: params.seed }
+        }
+    }
+}
+
+/// The GBNF grammar to sample under, if any. The memory's constraint is
+/// already resolved, so `ToolCallsGbnf` arrives as `Gbnf`; JSON schema
+/// constraints have no in-process sampler and are left to the prompter.
+fn gbnf(constraint: Option<&GrammarConstraint>) -> Option<String> {
+    match constraint {
+        Some(GrammarConstraint::Gbnf(grammar)) => Some(grammar.clone()),
+        _ => None,
+    }
+}
+
+fn sampler(model: &LlamaModel, grammar: Option<&str>, params: &LlamaCppParams) -> Result<LlamaSampler, String> {
+    let mut chain = Vec::new();
+    if let Some(grammar) = grammar {
+        chain.push(
+            LlamaSampler::grammar(model, grammar, "root")
+                .map_err(|e| format!("Invalid grammar: {}", e))?,
+        );
+    }
+    match Sampling::for_params(params) {
+        Sampling::Greedy => chain.push(LlamaSampler::greedy()),
+        Sampling::Random { temperature, seed } => {
+            chain.push(LlamaSampler::temp(temperature));
+            chain.push(LlamaSampler::dist(seed));
+        }
+    }
+    Ok(LlamaSampler::chain_simple(chain))
+}
+
+// ── Generation ───────────────────────────────────────────
+
+/// The reply as its tokens decode. Multi-byte characters can span tokens,
+/// so bytes are held until they decode; the reply ends before the first
+/// stop sequence, even one split across tokens.
+#[derive(Default)]
+struct Reply {
+    text:    String,
+    pending: Vec<u8>,
+}
+
+impl Reply {
+    /// Add one token's bytes. Returns the text that became complete (maybe
+    /// empty) and whether a stop sequence ended the reply.
+    fn push(&mut self, bytes: &[u8], stop_at: &[String]) -> (String, bool) {
+        self.pending.extend_from_slice(bytes);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        if valid == 0 {
+            return (String::new(), false);
+        }
+        let piece = String::from_utf8_lossy(&self.pending[..valid]).into_owned();
+        self.pending.drain(..valid);
+        let start = self.text.len();
+        self.text.push_str(&piece);
+        match stop_position(&self.text, stop_at) {
+            Some(pos) => {
+                let emitted = self.text.get(start..pos).unwrap_or_default().to_string();
+                self.text.truncate(pos);
+                (emitted, true)
+            }
+            None => (piece, false),
+        }
+    }
+}
+
+/// Generate a reply to `prompt`, calling `on_text` with each decoded piece.
+/// The reply ends before the first of `stop_at`.
+fn generate(
+    model:   &LlamaModel,
+    prompt:  &str,
+    grammar: Option<&str>,
+    params:  &LlamaCppParams,
+    stop_at: &[String],
+    mut on_text: impl FnMut(&str),
+) -> Result<(String, TokenUsage), String> {
+    let err = |e: &dyn std::fmt::Display| format!("llama.cpp error: {}", e);
+
+    let ctx_params = LlamaContextParams::default().with_n_ctx(NonZeroU32::new(params.n_ctx));
+    let mut ctx = model.new_context(backend()?, ctx_params).map_err(|e| err(&e))?;
+
+    let tokens = model.str_to_token(prompt, AddBos::Always).map_err(|e| err(&e))?;
+    if tokens.len() as u32 >= params.n_ctx {
+        return Err(format!(
+            "Prompt is {} tokens but the context window is {}",
+            tokens.len(),
+            params.n_ctx
+        ));
+    }
+
+    let mut batch = LlamaBatch::new(tokens.len().max(1), 1);
+    let last = tokens.len() as i32 - 1;
+    for (i, token) in (0_i32..).zip(tokens.iter().copied()) {
+        batch.add(token, i, &[0], i == last).map_err(|e| err(&e))?;
+    }
+    ctx.decode(&mut batch).map_err(|e| err(&e))?;
+
+    let mut sampler = sampler(model, grammar, params)?;
+    let mut reply = Reply::default();
+    let mut position = tokens.len() as i32;
+    let mut generated = 0u32;
+
+    while generated < params.max_tokens && (position as u32) < params.n_ctx {
+        let token = sampler.sample(&ctx, batch.n_tokens() - 1);
+        if model.is_eog_token(token) {
+            break;
+        }
+        generated += 1;
+
+        let bytes = model.token_to_bytes(token, Special::Tokenize).map_err(|e| err(&e))?;
+        let (piece, stopped) = reply.push(&bytes, stop_at);
+        if !piece.is_empty() {
+            on_text(&piece);
+        }
+        if stopped {
+            break;
+        }
+
+        batch.clear();
+        batch.add(token, position, &[0], true).map_err(|e| err(&e))?;
+        position += 1;
+        ctx.decode(&mut batch).map_err(|e| err(&e))?;
+    }
+
+    Ok((reply.text, TokenUsage::new(tokens.len() as u32, generated)))
+}
+
+#[async_trait]
+impl AsyncLlmCaller for LlamaCppCaller {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        tools:  &ToolRegistry,
+        model:  &str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        let mut stream = self.call_stream_async(memory, tools, model, output_tx);
+        while let Some(chunk) = stream.next().await {
+            if let LlmStreamChunk::Done(resp) = chunk? {
+                return Ok(resp);
+            }
+        }
+        Err(LlmError::Parse("Stream ended without a response".to_string()))
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools:  &'a ToolRegistry,
+        _model: &'a str,
+        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        if !tools.is_empty() {
+            return stream::once(async {
+                Err(LlmError::InvalidRequest(
+                    "LlamaCppCaller has no native tool calling; use a text PlanningPrompter".to_string(),
+                ))
+            })
+            .boxed();
+        }
+
+        let turns = chat_turns(memory);
+        let grammar = gbnf(memory.grammar.as_ref());
+        let cell = self.model.clone();
+        let path = self.path.clone();
+        let mut params = self.params.clone();
+        if let Some(max_tokens) = memory.config.sampling.max_tokens {
+            params.max_tokens = max_tokens;
+        }
+        if let Some(temperature) = memory.config.sampling.temperature {
+            params.temperature = temperature;
+        }
+        if let Some(seed) = memory.config.sampling.seed {
+            params.seed = seed as u32;
+        }
+        let stop_at = memory.stop.clone();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::task::spawn_blocking(move || {
+            let result = load_model(&cell, &path, &params).and_then(|model| {
+                let prompt = render_prompt(&model, &turns);
+                generate(&model, &prompt, grammar.as_deref(), &params, &stop_at, |piece| {
+                    let _ = tx.send(Ok(LlmStreamChunk::Content(piece.to_string())));
+                })
+            });
+            let _ = tx.send(result.map_err(LlmError::from).map(|(content, usage)| {
+                // Stopped by the token limit rather than an end-of-sequence token
+                let resp = if usage.output_tokens >= params.max_tokens {
+                    LlmResponse::Truncated { content, usage: Some(usage) }
+                } else {
+                    LlmResponse::FinalAnswer { content, confidence: 1.0, usage: Some(usage) }
+                };
+                LlmStreamChunk::Done(resp)
+            }));
+        });
+
+        stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) }).boxed()
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turns(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(r, c)| (r.to_string(), c.to_string())).collect()
+    }
+
+    #[test]
+    fn test_prompt_falls_back_to_chatml() {
+        let t = turns(&[("system", "Be brief."), ("user", "Hi")]);
+        assert_eq!(
+            prompt_or_chatml(None, &t),
+            "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(prompt_or_chatml(Some("[INST] Hi [/INST]".to_string()), &t), "[INST] Hi [/INST]");
+    }
+
+    #[test]
+    fn test_reply_holds_split_characters() {
+        let mut reply = Reply::default();
+        let bytes = "né".as_bytes();
+        assert_eq!(reply.push(&bytes[..2], &[]), ("n".to_string(), false));
+        assert_eq!(reply.push(&bytes[2..], &[]), ("é".to_string(), false));
+        assert_eq!(reply.text, "né");
+    }
+
+    #[test]
+    fn test_reply_ends_before_stop_sequence() {
+        let stop = vec!["<|im_end|>".to_string()];
+        let mut reply = Reply::default();
+        assert_eq!(reply.push(b"Sunny<|im", &stop), ("Sunny<|im".to_string(), false));
+        // The stop sequence completes in a later token: nothing more is emitted
+        assert_eq!(reply.push(b"_end|>\n", &stop), (String::new(), true));
+        assert_eq!(reply.text, "Sunny");
+
+        let mut reply = Reply::default();
+        assert_eq!(reply.push(b"Done.<|im_end|>", &stop), ("Done.".to_string(), true));
+        assert_eq!(reply.text, "Done.");
+    }
+
+    #[test]
+    fn test_sampler_selection() {
+        let params = LlamaCppParams::new().temperature(0.0);
+        assert_eq!(Sampling::for_params(&params), Sampling::Greedy);
+        let params = LlamaCppParams::new().temperature(0.7).seed(7);
+        assert_eq!(Sampling::for_params(&params), Sampling::Random { temperature: 0.7, seed: 7 });
+
+        let grammar = GrammarConstraint::Gbnf("root ::= \"yes\"".to_string());
+        assert_eq!(gbnf(Some(&grammar)).as_deref(), Some("root ::= \"yes\""));
+        assert_eq!(gbnf(Some(&GrammarConstraint::JsonSchema(serde_json::json!({})))), None);
+        assert_eq!(gbnf(None), None);
+    }
+}
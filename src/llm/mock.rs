@@ -2,6 +2,7 @@ use std::sync::Mutex;
 use crate::memory::AgentMemory;
 use crate::tools::ToolRegistry;
 use crate::types::LlmResponse;
+use crate::llm::LlmError;
 use async_trait::async_trait;
 
 pub struct MockLlmCaller {
@@ -38,13 +39,13 @@ impl crate::llm::AsyncLlmCaller for MockLlmCaller {
         _tools:  &ToolRegistry,
         model:  &str,
         _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
-    ) -> Result<LlmResponse, String> {
+    ) -> Result<LlmResponse, LlmError> {
         self.call_log.lock().unwrap()
             .push((model.to_string(), memory.task.clone()));
 
         let mut responses = self.responses.lock().unwrap();
         if responses.is_empty() {
-            return Err("MockLlmCaller: no more programmed responses".to_string());
+            return Err(LlmError::Other("MockLlmCaller: no more programmed responses".to_string()));
         }
         let resp = responses.remove(0);
         Ok(resp)
@@ -56,7 +57,7 @@ impl crate::llm::AsyncLlmCaller for MockLlmCaller {
         _tools:  &'a ToolRegistry,
         model:  &'a str,
         _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
-    ) -> futures::stream::BoxStream<'a, Result<crate::types::LlmStreamChunk, String>> {
+    ) -> futures::stream::BoxStream<'a, Result<crate::types::LlmStreamChunk, LlmError>> {
         use futures::stream::{self, StreamExt};
         let (task, model_s) = (memory.task.clone(), model.to_string());
         
@@ -66,7 +67,7 @@ impl crate::llm::AsyncLlmCaller for MockLlmCaller {
         self.call_log.lock().unwrap().push((model_s, task));
         
         if responses.is_empty() {
-            return stream::once(async move { Err("MockLlmCaller: no more programmed responses".to_string()) }).boxed();
+            return stream::once(async move { Err(LlmError::Other("MockLlmCaller: no more programmed responses".to_string())) }).boxed();
         }
         let resp = responses.remove(0);
         stream::once(async move { Ok(crate::types::LlmStreamChunk::Done(resp)) }).boxed()
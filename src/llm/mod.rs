@@ -6,12 +6,43 @@ use futures::stream::BoxStream;
 
 mod openai;
 mod anthropic;
+mod caching;
+mod capabilities;
+#[cfg(feature = "candle")]
+mod candle;
+mod coalesce;
+mod completion;
+mod error;
+mod fallback;
+mod gemini;
+#[cfg(feature = "llama-cpp")]
+mod llama_cpp;
 mod mock;
+mod rate_limit;
+mod raw_log;
+mod reasoning;
 mod retry;
 
 pub use openai::OpenAiCaller;
 pub use anthropic::AnthropicCaller;
+pub(crate) use anthropic::{
+    anthropic_messages, structured_output_instruction, structured_output_tool, AnthropicSampling, STRUCTURED_OUTPUT_TOOL,
+};
+pub use caching::CachingLlmCaller;
+pub use capabilities::ModelCapabilities;
+#[cfg(feature = "candle")]
+pub use candle::{CandleArch, CandleCaller, CandleParams};
+pub use coalesce::{CoalesceStats, CoalescingLlmCaller};
+pub use completion::{ChatTemplate, CompletionCaller};
+pub use error::LlmError;
+pub use fallback::FallbackLlmCaller;
+pub use gemini::GeminiCaller;
+#[cfg(feature = "llama-cpp")]
+pub use llama_cpp::{LlamaCppCaller, LlamaCppParams};
 pub use mock::MockLlmCaller;
+pub use rate_limit::{RateLimitedLlmCaller, RateLimiter};
+pub use raw_log::{JsonlRawLogSink, MemoryRawLogSink, RawExchange, RawLogSink};
+pub use reasoning::ReasoningCaller;
 pub use retry::RetryingLlmCaller;
 
 /// The single interface between the state machine and any LLM provider.
@@ -19,10 +50,11 @@ pub use retry::RetryingLlmCaller;
 /// # Contract
 /// - Must be Send + Sync (used behind Box<dyn LlmCaller>)
 /// - Returns Ok(LlmResponse) on any valid LLM interaction
-/// - Returns Err(String) ONLY for unrecoverable failures:
-///   - Network failure after retries exhausted
-///   - Authentication failure
-///   - Response unparseable as LlmResponse
+/// - Returns Err(LlmError) ONLY for unrecoverable failures, classified:
+///   - Network failure after retries exhausted (`Network`, `Provider`)
+///   - Authentication failure (`Auth`)
+///   - Rate limits and oversized prompts (`RateLimited`, `ContextOverflow`)
+///   - Response unparseable as LlmResponse (`Parse`)
 /// - MUST build the tool schemas from `tools.schemas()` and include
 ///   them in every API call
 /// - MUST build messages from `memory.build_messages()`
@@ -33,7 +65,21 @@ pub trait LlmCaller: Send + Sync {
         memory: &AgentMemory,
         tools:  &ToolRegistry,
         model:  &str,
-    ) -> Result<LlmResponse, String>;
+    ) -> Result<LlmResponse, LlmError>;
+
+    /// Like `call`, but hands each stream chunk to `on_chunk` as it arrives.
+    /// The default does not stream: it reports only the final `Done` chunk.
+    fn call_with(
+        &self,
+        memory:   &AgentMemory,
+        tools:    &ToolRegistry,
+        model:    &str,
+        on_chunk: &mut dyn FnMut(&LlmStreamChunk),
+    ) -> Result<LlmResponse, LlmError> {
+        let resp = self.call(memory, tools, model)?;
+        on_chunk(&LlmStreamChunk::Done(resp.clone()));
+        Ok(resp)
+    }
 }
 
 /// Async version of LlmCaller for async runtimes.
@@ -45,7 +91,7 @@ pub trait AsyncLlmCaller: Send + Sync {
         tools:  &ToolRegistry,
         model:  &str,
         output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
-    ) -> Result<LlmResponse, String>;
+    ) -> Result<LlmResponse, LlmError>;
 
     /// Asynchronously streams chunks from the LLM.
     fn call_stream_async<'a>(
@@ -54,23 +100,139 @@ pub trait AsyncLlmCaller: Send + Sync {
         tools:  &'a ToolRegistry,
         model:  &'a str,
         output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
-    ) -> BoxStream<'a, Result<LlmStreamChunk, String>>;
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>>;
+
+    /// Check, without generating any tokens, that the provider is reachable
+    /// and the credentials work. Used by the Idle pre-flight and `agentsm
+    /// models`. The default lists models, so providers that implement
+    /// `list_models` get it for free.
+    async fn health_check(&self) -> Result<(), LlmError> {
+        self.list_models().await.map(|_| ())
+    }
+
+    /// Names of the models the provider serves. Empty means the caller
+    /// cannot list them (the default).
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        Ok(Vec::new())
+    }
+}
+
+/// Whether a provider error means the prompt exceeded the model's context window.
+///
+/// These errors are not transient: retrying the same prompt fails again, so
+/// the planner compresses memory instead (`Event::context_overflow()`).
+pub fn is_context_overflow(err: &str) -> bool {
+    let lower = err.to_lowercase();
+    lower.contains("context_length_exceeded")
+        || lower.contains("maximum context length")
+        || lower.contains("context window")
+        || lower.contains("prompt is too long")
+        || lower.contains("input is too long")
+        || lower.contains("reduce the length of the messages")
+}
+
+/// `(role, content)` pairs for local backends that render their own chat
+/// template. Tool results become user turns; missing content is empty.
+#[cfg(any(feature = "llama-cpp", feature = "candle"))]
+pub(crate) fn chat_turns(memory: &AgentMemory) -> Vec<(String, String)> {
+    memory
+        .build_messages()
+        .iter()
+        .map(|m| {
+            let role = match m["role"].as_str().unwrap_or("user") {
+                "tool" => "user",
+                other => other,
+            };
+            (role.to_string(), m["content"].as_str().unwrap_or_default().to_string())
+        })
+        .collect()
+}
+
+/// Where the earliest stop sequence starts in `text`, for local backends
+/// that apply stop sequences themselves.
+#[cfg(any(feature = "llama-cpp", feature = "candle"))]
+pub(crate) fn stop_position(text: &str, stop: &[String]) -> Option<usize> {
+    stop.iter()
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| text.find(s.as_str()))
+        .min()
 }
 
-/// Extension trait: wraps an AsyncLlmCaller into a sync LlmCaller
-/// using tokio::runtime::Handle::block_on.
+/// Extension trait: wraps an AsyncLlmCaller into a sync LlmCaller.
+///
+/// Inside a multi-threaded tokio runtime the call runs on the current
+/// runtime via `block_in_place`. Outside any runtime (a plain CLI `main`),
+/// a single-threaded runtime is started for the call.
 pub struct SyncWrapper<T: AsyncLlmCaller>(pub T);
 
 impl<T: AsyncLlmCaller> LlmCaller for SyncWrapper<T> {
-    fn call(&self, memory: &AgentMemory, tools: &ToolRegistry, model: &str) -> Result<LlmResponse, String> {
+    fn call(&self, memory: &AgentMemory, tools: &ToolRegistry, model: &str) -> Result<LlmResponse, LlmError> {
+        block_on(self.0.call_async(memory, tools, model, None))
+    }
+
+    fn call_with(
+        &self,
+        memory:   &AgentMemory,
+        tools:    &ToolRegistry,
+        model:    &str,
+        on_chunk: &mut dyn FnMut(&LlmStreamChunk),
+    ) -> Result<LlmResponse, LlmError> {
+        use futures::StreamExt;
+
+        block_on(async {
+            let mut stream = self.0.call_stream_async(memory, tools, model, None);
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk?;
+                on_chunk(&chunk);
+                if let LlmStreamChunk::Done(resp) = chunk {
+                    return Ok(resp);
+                }
+            }
+            Err(LlmError::Parse("Stream ended without a response".to_string()))
+        })
+    }
+}
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    match tokio::runtime::Handle::try_current() {
         // block_in_place moves the current thread out of the async executor
         // context before blocking, preventing the "Cannot start a runtime
         // from within a runtime" panic when called from #[tokio::main].
-        tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current()
-                .block_on(self.0.call_async(memory, tools, model, None))
-        })
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(future)),
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start a tokio runtime")
+            .block_on(future),
     }
 }
 
 pub use SyncWrapper as LlmCallerExt;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_call_with_streams_without_a_runtime() {
+        let caller = SyncWrapper(MockLlmCaller::new(vec![LlmResponse::FinalAnswer {
+            content: "hello".into(),
+            confidence: 1.0,
+            usage: None,
+            model: None,
+        }]));
+        let mut seen = Vec::new();
+        let resp = caller
+            .call_with(&AgentMemory::new("t"), &ToolRegistry::new(), "m", &mut |chunk| {
+                seen.push(chunk.clone())
+            })
+            .unwrap();
+
+        assert!(matches!(resp, LlmResponse::FinalAnswer { ref content, .. } if content == "hello"));
+        assert!(matches!(seen.last(), Some(LlmStreamChunk::Done(_))));
+    }
+}
@@ -1,42 +1,204 @@
 use async_openai::{
-    config::OpenAIConfig,
+    config::{Config, OpenAIConfig},
     types::{
-        ChatCompletionMessageToolCall, ChatCompletionRequestMessage, ChatCompletionResponseFormat,
-        ChatCompletionResponseFormatType, ChatCompletionTool, ChatCompletionToolType,
-        CreateChatCompletionRequestArgs, FunctionObject,
+        ChatChoiceLogprobs, ChatCompletionMessageToolCall, ChatCompletionRequestMessage, ChatCompletionResponseFormat,
+        ChatCompletionResponseFormatType, ChatCompletionTool, ChatCompletionToolType, ChatCompletionStreamOptions,
+        CreateChatCompletionRequest, CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
+        CreateChatCompletionStreamResponse, FinishReason, FunctionObject, Stop,
     },
     Client,
 };
 use async_trait::async_trait;
 // use futures::StreamExt;
-use crate::llm::AsyncLlmCaller;
+use crate::llm::{AsyncLlmCaller, LlmError, ModelCapabilities};
 use crate::memory::AgentMemory;
-use crate::tools::ToolRegistry;
-use crate::types::{LlmResponse, ToolCall};
+use crate::tools::{parse_tool_args, ToolRegistry};
+use crate::types::{LlmResponse, ModelParams, ToolCall};
 use futures::stream::BoxStream;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Classify an `async-openai` error. HTTP failures keep their status;
+/// API errors are classified from their text.
+fn api_error(e: async_openai::error::OpenAIError, prefix: &str) -> LlmError {
+    use async_openai::error::OpenAIError;
+    let message = format!("{}: {}", prefix, e);
+    match &e {
+        OpenAIError::Reqwest(err) => match err.status() {
+            Some(status) => LlmError::from_status(status.as_u16(), message),
+            None => LlmError::Network(message),
+        },
+        OpenAIError::JSONDeserialize(_) => LlmError::Parse(message),
+        _ => LlmError::classify(message),
+    }
+}
+
+/// `exp(mean logprob)`: the geometric mean of the token probabilities,
+/// 1.0 for no tokens.
+fn logprob_confidence(logprobs: impl IntoIterator<Item = f32>) -> f64 {
+    let (sum, count) = logprobs
+        .into_iter()
+        .filter(|lp| !lp.is_nan())
+        .fold((0.0, 0usize), |(sum, count), lp| (sum + lp as f64, count + 1));
+    if count == 0 {
+        return 1.0;
+    }
+    (sum / count as f64).exp().clamp(0.0, 1.0)
+}
+
+/// Log probabilities of the tokens in a choice's content.
+fn token_logprobs(logprobs: Option<&ChatChoiceLogprobs>) -> impl Iterator<Item = f32> + '_ {
+    logprobs
+        .and_then(|l| l.content.as_deref())
+        .unwrap_or_default()
+        .iter()
+        .map(|t| t.logprob)
+}
+
+#[derive(Default)]
+struct ToolCallAcc {
+    id: Option<String>,
+    name: Option<String>,
+    args: String,
+}
+
+/// What a streamed response has said so far.
+#[derive(Default)]
+struct StreamAcc {
+    content: String,
+    logprobs: Vec<f32>,
+    // Keyed by the call's index, so parallel calls keep request order
+    tools: BTreeMap<i32, ToolCallAcc>,
+    finish_reason: Option<FinishReason>,
+    usage: Option<crate::budget::TokenUsage>,
+}
+
+impl StreamAcc {
+    /// Take in one chunk, returning the delta to forward, if any.
+    fn accept(
+        &mut self,
+        chunk: CreateChatCompletionStreamResponse,
+    ) -> Result<Option<crate::types::LlmStreamChunk>, LlmError> {
+        // Sent last, with no choices, when `include_usage` is set
+        if let Some(u) = chunk.usage {
+            self.usage = Some(crate::budget::TokenUsage::new(u.prompt_tokens, u.completion_tokens));
+        }
+        let Some(choice) = chunk.choices.into_iter().next() else {
+            return Ok(None);
+        };
+        self.logprobs.extend(token_logprobs(choice.logprobs.as_ref()));
+        if choice.finish_reason.is_some() {
+            self.finish_reason = choice.finish_reason;
+        }
+        let delta = choice.delta;
+
+        if let Some(tool_calls) = delta.tool_calls {
+            for tc in tool_calls {
+                let acc = self.tools.entry(tc.index).or_default();
+                if let Some(id) = tc.id {
+                    acc.id = Some(id);
+                }
+                if let Some(func) = tc.function {
+                    if let Some(name) = func.name {
+                        acc.name = Some(name);
+                    }
+                    if let Some(args) = func.arguments {
+                        acc.args.push_str(&args);
+                    }
+                }
+            }
+
+            // The engine doesn't tell parallel calls apart in its deltas,
+            // so report the first call's progress
+            let (name, args_json) = self
+                .tools
+                .values()
+                .next()
+                .map(|a| (a.name.clone(), a.args.clone()))
+                .unwrap_or((None, String::new()));
+            return Ok(Some(crate::types::LlmStreamChunk::ToolCallDelta { name, args_json }));
+        }
+
+        if let Some(content) = delta.content {
+            self.content.push_str(&content);
+            return Ok(Some(crate::types::LlmStreamChunk::Content(content)));
+        }
+        Ok(None)
+    }
+
+    /// The finished response, once a finish reason has been seen.
+    fn finish(self) -> Option<LlmResponse> {
+        let reason = self.finish_reason?;
+        let (confidence, usage) = (logprob_confidence(self.logprobs), self.usage);
+        let mut tools: Vec<ToolCall> = self
+            .tools
+            .into_values()
+            .map(|acc| ToolCall {
+                name: acc.name.unwrap_or_default(),
+                args: parse_tool_args(&acc.args),
+                id: acc.id,
+            })
+            .collect();
+        Some(if tools.len() > 1 {
+            LlmResponse::ParallelToolCalls { tools, confidence, usage, model: None }
+        } else if let Some(tool) = tools.pop() {
+            LlmResponse::ToolCall {
+                tool,
+                confidence,
+                assistant_text: Some(self.content).filter(|c| !c.trim().is_empty()),
+                usage,
+                model: None,
+            }
+        } else if self.content.is_empty() {
+            return None;
+        } else if reason == FinishReason::Length {
+            LlmResponse::Truncated { content: self.content, usage, model: None }
+        } else {
+            LlmResponse::FinalAnswer { content: self.content, confidence, usage, model: None }
+        })
+    }
+}
 
 pub struct OpenAiCaller {
     client: Client<OpenAIConfig>,
+    /// For requests async-openai has no type for (strict `json_schema` output)
+    config: OpenAIConfig,
+    http:   reqwest::Client,
+    /// Ask for logprobs and estimate confidence from them
+    logprobs: bool,
 }
 
 impl OpenAiCaller {
     /// Standard OpenAI client using OPENAI_API_KEY env var
     pub fn new() -> Self {
+        Self::with_config(OpenAIConfig::default()).with_logprobs(true)
+    }
+
+    fn with_config(config: OpenAIConfig) -> Self {
         Self {
-            client: Client::new(),
+            client: Client::with_config(config.clone()),
+            config,
+            http:   reqwest::Client::new(),
+            logprobs: false,
         }
     }
 
+    /// Request token logprobs and report confidence from them instead of
+    /// 1.0. On for OpenAI itself; off for `with_base_url`, since some
+    /// compatible APIs (Groq) reject the parameter.
+    pub fn with_logprobs(mut self, enabled: bool) -> Self {
+        self.logprobs = enabled;
+        self
+    }
+
     /// Custom base URL — for Groq, Together, Ollama, Fireworks, etc.
     /// api_base example: "https://api.groq.com/openai/v1"
     pub fn with_base_url(api_base: impl Into<String>, api_key: impl Into<String>) -> Self {
         let config = OpenAIConfig::new()
             .with_api_base(api_base)
             .with_api_key(api_key);
-        Self {
-            client: Client::with_config(config),
-        }
+        Self::with_config(config)
     }
 
     /// Convert our ToolSchema into async-openai's ChatCompletionTool type
@@ -55,15 +217,183 @@ impl OpenAiCaller {
             .collect()
     }
 
-    /// Parse the first tool call from an OpenAI response into our ToolCall type
-    fn parse_tool_call(tc: &ChatCompletionMessageToolCall) -> Result<ToolCall, String> {
-        let args: HashMap<String, serde_json::Value> = serde_json::from_str(&tc.function.arguments)
-            .map_err(|e| format!("Failed to parse tool args: {}", e))?;
-        Ok(ToolCall {
+    /// Tool schemas to send, or none for models that reject them.
+    fn tools_for(caps: &ModelCapabilities, tools: &ToolRegistry, model: &str) -> Vec<ChatCompletionTool> {
+        if caps.tools {
+            return Self::build_tools(tools);
+        }
+        if !tools.is_empty() {
+            tracing::warn!(model, "Model does not accept tool schemas; sending none. Use a text PlanningPrompter");
+        }
+        Vec::new()
+    }
+
+    /// Stop sequences to send. The API takes at most four.
+    pub(crate) fn stop_for(caps: &ModelCapabilities, stop: &[String]) -> Option<Stop> {
+        if stop.is_empty() || !caps.stop_sequences {
+            return None;
+        }
+        if stop.len() > MAX_STOP_SEQUENCES {
+            tracing::warn!(count = stop.len(), "OpenAI accepts 4 stop sequences; sending the first 4");
+        }
+        Some(Stop::StringArray(stop.iter().take(MAX_STOP_SEQUENCES).cloned().collect()))
+    }
+
+    /// Set the model's max tokens, temperature and top_p, and the seed.
+    /// Models that only take the default temperature (the o-series) get
+    /// neither temperature nor top_p, and their token limit is left to
+    /// [`Self::add_raw_fields`] as `max_completion_tokens`.
+    fn apply_sampling(
+        caps:    &ModelCapabilities,
+        seed:    Option<u64>,
+        params:  &ModelParams,
+        request: &mut CreateChatCompletionRequestArgs,
+    ) {
+        if let Some(max_tokens) = params.max_tokens.filter(|_| caps.temperature) {
+            request.max_tokens(max_tokens);
+        }
+        if let Some(temperature) = params.temperature.filter(|_| caps.temperature) {
+            request.temperature(temperature);
+        }
+        if let Some(top_p) = params.top_p.filter(|_| caps.temperature) {
+            request.top_p(top_p);
+        }
+        if let Some(seed) = seed {
+            request.seed(seed as i64);
+        }
+    }
+
+    /// The fields [`Self::apply_sampling`] and [`Self::add_raw_fields`]
+    /// set, for request bodies built as JSON (batch requests).
+    pub(crate) fn sampling_fields(
+        caps:   &ModelCapabilities,
+        seed:   Option<u64>,
+        params: &ModelParams,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        let mut request = CreateChatCompletionRequestArgs::default();
+        request.model("").messages(Vec::<ChatCompletionRequestMessage>::new());
+        Self::apply_sampling(caps, seed, params, &mut request);
+        let mut body = request
+            .build()
+            .ok()
+            .and_then(|r| serde_json::to_value(r).ok())
+            .unwrap_or_else(|| serde_json::json!({}));
+        Self::add_raw_fields(caps, params, &mut body);
+        let mut fields = match body {
+            serde_json::Value::Object(fields) => fields,
+            _ => serde_json::Map::new(),
+        };
+        fields.remove("model");
+        fields.remove("messages");
+        fields
+    }
+
+    /// Request fields async-openai 0.23 lacks: `reasoning_effort`, and
+    /// `max_completion_tokens`, which reasoning models take instead of
+    /// `max_tokens`.
+    fn add_raw_fields(caps: &ModelCapabilities, params: &ModelParams, body: &mut serde_json::Value) {
+        if let Some(effort) = params.reasoning_effort {
+            body["reasoning_effort"] = effort.as_str().into();
+        }
+        if let Some(max_tokens) = params.max_tokens.filter(|_| !caps.temperature) {
+            body["max_completion_tokens"] = max_tokens.into();
+        }
+    }
+
+    /// The request needs fields only [`Self::create_raw`] can send.
+    fn needs_raw_fields(caps: &ModelCapabilities, params: &ModelParams) -> bool {
+        params.reasoning_effort.is_some() || (params.max_tokens.is_some() && !caps.temperature)
+    }
+
+    /// Ask for JSON conforming to `schema` in the first system message,
+    /// or in a new one.
+    pub(crate) fn add_schema_instruction(messages: &mut Vec<serde_json::Value>, schema: &crate::types::OutputSchema) {
+        let schema_instruction = format!(
+            "\n\nYou MUST respond with valid JSON conforming to this schema:\nSchema name: {}\n{}\n```json\n{}\n```\nRespond ONLY with the JSON object, no other text.",
+            schema.name,
+            schema.description.as_deref().map(|d| format!("Description: {}\n", d)).unwrap_or_default(),
+            serde_json::to_string_pretty(&schema.schema).unwrap_or_default()
+        );
+
+        // Append to existing system message or insert one
+        let mut found_system = false;
+        for msg in messages.iter_mut() {
+            if msg["role"] == "system" {
+                if let Some(content) = msg["content"].as_str() {
+                    msg["content"] = serde_json::Value::String(format!("{}{}", content, schema_instruction));
+                }
+                found_system = true;
+                break;
+            }
+        }
+        if !found_system {
+            messages.insert(
+                0,
+                serde_json::json!({
+                    "role": "system",
+                    "content": schema_instruction.trim_start()
+                }),
+            );
+        }
+    }
+
+    /// `response_format` for strict structured output against `schema`.
+    pub(crate) fn json_schema_format(schema: &crate::types::OutputSchema) -> serde_json::Value {
+        let mut json_schema = serde_json::json!({
+            "name":   schema.name,
+            "strict": true,
+            "schema": schema.schema,
+        });
+        if let Some(description) = &schema.description {
+            json_schema["description"] = serde_json::Value::String(description.clone());
+        }
+        serde_json::json!({ "type": "json_schema", "json_schema": json_schema })
+    }
+
+    /// Post `request` as JSON with the client's own URL and headers, for
+    /// what async-openai 0.23 cannot do: `response_format: json_schema`
+    /// (when `schema` is given), the fields of [`Self::add_raw_fields`] and
+    /// recording the raw exchange.
+    async fn create_raw(
+        &self,
+        memory:  &AgentMemory,
+        model:   &str,
+        request: &CreateChatCompletionRequest,
+        schema:  Option<&crate::types::OutputSchema>,
+        caps:    &ModelCapabilities,
+        params:  &ModelParams,
+    ) -> Result<CreateChatCompletionResponse, LlmError> {
+        let mut body = serde_json::to_value(request)
+            .map_err(|e| LlmError::InvalidRequest(format!("Failed to build request: {}", e)))?;
+        if let Some(schema) = schema {
+            body["response_format"] = Self::json_schema_format(schema);
+        }
+        Self::add_raw_fields(caps, params, &mut body);
+
+        let request = self
+            .http
+            .post(self.config.url("/chat/completions"))
+            .query(&self.config.query())
+            .headers(self.config.headers())
+            .json(&body);
+        let text = super::raw_log::send(memory, "openai", model, request, "OpenAI API error").await?;
+        serde_json::from_str(&text).map_err(|e| LlmError::Parse(format!("Failed to parse OpenAI response: {}", e)))
+    }
+
+    /// Parse the first tool call from an OpenAI response into our ToolCall type.
+    /// Malformed arguments are kept raw so they can be repaired downstream.
+    fn parse_tool_call(tc: &ChatCompletionMessageToolCall) -> ToolCall {
+        ToolCall {
             name: tc.function.name.clone(),
-            args,
+            args: parse_tool_args(&tc.function.arguments),
             id: Some(tc.id.clone()),
-        })
+        }
+    }
+}
+
+impl Default for OpenAiCaller {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -75,73 +405,66 @@ impl AsyncLlmCaller for OpenAiCaller {
         tools: &ToolRegistry,
         model: &str,
         _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
-    ) -> Result<LlmResponse, String> {
+    ) -> Result<LlmResponse, LlmError> {
+        let caps = memory.model_capabilities(model);
+        let params = memory.model_params(model);
         let has_output_schema = memory.config.output_schema.is_some();
         let mut messages_json = memory.build_messages();
-
         // When structured output is requested, inject the schema into the system prompt
         if let Some(ref schema) = memory.config.output_schema {
-            let schema_instruction = format!(
-                "\n\nYou MUST respond with valid JSON conforming to this schema:\nSchema name: {}\n{}\n```json\n{}\n```\nRespond ONLY with the JSON object, no other text.",
-                schema.name,
-                schema.description.as_deref().map(|d| format!("Description: {}\n", d)).unwrap_or_default(),
-                serde_json::to_string_pretty(&schema.schema).unwrap_or_default()
-            );
-
-            // Append to existing system message or insert one
-            let mut found_system = false;
-            for msg in messages_json.iter_mut() {
-                if msg["role"] == "system" {
-                    if let Some(content) = msg["content"].as_str() {
-                        msg["content"] =
-                            serde_json::Value::String(format!("{}{}", content, schema_instruction));
-                    }
-                    found_system = true;
-                    break;
-                }
-            }
-            if !found_system {
-                messages_json.insert(
-                    0,
-                    serde_json::json!({
-                        "role": "system",
-                        "content": schema_instruction.trim_start()
-                    }),
-                );
-            }
+            Self::add_schema_instruction(&mut messages_json, schema);
         }
 
+        let messages_json = caps.adapt_messages(messages_json);
+
         // Convert serde_json::Value messages to async-openai types
         // Use serde round-trip: serialize to string, deserialize as typed
         let messages: Vec<ChatCompletionRequestMessage> =
             serde_json::from_value(serde_json::Value::Array(messages_json))
-                .map_err(|e| format!("Failed to build messages: {}", e))?;
+                .map_err(|e| LlmError::InvalidRequest(format!("Failed to build messages: {}", e)))?;
 
-        let oai_tools = Self::build_tools(tools);
+        let oai_tools = Self::tools_for(&caps, tools, model);
 
         let mut request_builder = CreateChatCompletionRequestArgs::default();
         request_builder.model(model).messages(messages);
+        Self::apply_sampling(&caps, memory.config.sampling.seed, &params, &mut request_builder);
+        if self.logprobs && caps.logprobs {
+            request_builder.logprobs(true);
+        }
+        if let Some(stop) = Self::stop_for(&caps, &memory.stop) {
+            request_builder.stop(stop);
+        }
 
         if has_output_schema {
-            // Enable JSON mode for structured output
-            request_builder.response_format(ChatCompletionResponseFormat {
-                r#type: ChatCompletionResponseFormatType::JsonObject,
-            });
+            // Enable JSON mode for structured output; the prompt asks for JSON either way
+            if caps.json_mode {
+                request_builder.response_format(ChatCompletionResponseFormat {
+                    r#type: ChatCompletionResponseFormatType::JsonObject,
+                });
+            }
             // Don't send tools when we want structured output — they conflict
         } else if !oai_tools.is_empty() {
             request_builder.tools(oai_tools);
+            if !memory.config.parallel_tools && caps.parallel_tool_calls {
+                request_builder.parallel_tool_calls(false);
+            }
         }
 
         let request = request_builder
             .build()
-            .map_err(|e| format!("Failed to build request: {}", e))?;
+            .map_err(|e| LlmError::InvalidRequest(format!("Failed to build request: {}", e)))?;
 
-        let response = self
-            .client
-            .chat()
-            .create(request)
-            .await
-            .map_err(|e| format!("OpenAI API error: {}", e))?;
+        // Strict structured output replaces JSON mode
+        let strict_schema = memory.config.output_schema.as_ref().filter(|s| s.strict && caps.json_mode);
+        let response = match strict_schema {
+            None if memory.raw_log.is_none() && !Self::needs_raw_fields(&caps, &params) => self
+                .client
+                .chat()
+                .create(request)
+                .await
+                .map_err(|e| api_error(e, "OpenAI API error"))?,
+            _ => self.create_raw(memory, model, &request, strict_schema, &caps, &params).await?,
+        };
 
         let usage = response
             .usage
@@ -151,22 +474,25 @@ impl AsyncLlmCaller for OpenAiCaller {
             .choices
             .into_iter()
             .next()
-            .ok_or("Empty response from OpenAI")?;
+            .ok_or_else(|| LlmError::Parse("Empty response from OpenAI".to_string()))?;
 
+        let truncated = choice.finish_reason == Some(FinishReason::Length);
+        // OpenAI sends logprobs for text only; its tool calls stay at 1.0
+        let confidence = logprob_confidence(token_logprobs(choice.logprobs.as_ref()));
         let message = choice.message;
 
         // If structured output was requested, parse the response as JSON
         if has_output_schema {
             let content = message
                 .content
-                .ok_or("No content in OpenAI structured response")?;
+                .ok_or_else(|| LlmError::Parse("No content in OpenAI structured response".to_string()))?;
             let data: serde_json::Value = serde_json::from_str(&content).map_err(|e| {
-                format!(
+                LlmError::Parse(format!(
                     "Failed to parse structured output as JSON: {} — raw: {}",
                     e, content
-                )
+                ))
             })?;
-            return Ok(LlmResponse::Structured { data, usage });
+            return Ok(LlmResponse::Structured { data, usage, model: None });
         }
 
         // Tool call takes priority over text content
@@ -174,26 +500,32 @@ impl AsyncLlmCaller for OpenAiCaller {
             if tool_calls.len() > 1 {
                 let mut parsed_tools = Vec::new();
                 for tc in tool_calls {
-                    parsed_tools.push(Self::parse_tool_call(&tc)?);
+                    parsed_tools.push(Self::parse_tool_call(&tc));
                 }
                 return Ok(LlmResponse::ParallelToolCalls {
                     tools: parsed_tools,
-                    confidence: 1.0,
+                    confidence,
                     usage,
+                    model: None,
                 });
             } else if let Some(tc) = tool_calls.into_iter().next() {
-                let tool = Self::parse_tool_call(&tc)?;
+                let tool = Self::parse_tool_call(&tc);
                 return Ok(LlmResponse::ToolCall {
                     tool,
-                    confidence: 1.0,
+                    confidence,
+                    assistant_text: message.content.filter(|c| !c.trim().is_empty()),
                     usage,
+                    model: None,
                 });
             }
         }
 
-        let content = message.content.ok_or("No content in OpenAI response")?;
+        let content = message.content.ok_or_else(|| LlmError::Parse("No content in OpenAI response".to_string()))?;
 
-        Ok(LlmResponse::FinalAnswer { content, usage })
+        if truncated {
+            return Ok(LlmResponse::Truncated { content, usage, model: None });
+        }
+        Ok(LlmResponse::FinalAnswer { content, confidence, usage, model: None })
     }
 
     fn call_stream_async<'a>(
@@ -202,32 +534,63 @@ impl AsyncLlmCaller for OpenAiCaller {
         tools: &'a ToolRegistry,
         model: &'a str,
         _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
-    ) -> BoxStream<'a, Result<crate::types::LlmStreamChunk, String>> {
+    ) -> BoxStream<'a, Result<crate::types::LlmStreamChunk, LlmError>> {
         use futures::{stream, StreamExt};
-        let messages_json = memory.build_messages();
+        let caps = memory.model_capabilities(model);
+        let params = memory.model_params(model);
+        if !caps.streaming
+            || memory.config.output_schema.is_some()
+            || memory.raw_log.is_some()
+            || Self::needs_raw_fields(&caps, &params)
+        {
+            // Answer in one chunk; structured output is parsed as a whole,
+            // and async-openai's streams hide the HTTP exchange and take
+            // neither reasoning effort nor max_completion_tokens
+            return stream::once(async move {
+                self.call_async(memory, tools, model, None)
+                    .await
+                    .map(crate::types::LlmStreamChunk::Done)
+            })
+            .boxed();
+        }
+        let messages_json = caps.adapt_messages(memory.build_messages());
         let messages: Vec<ChatCompletionRequestMessage> =
             match serde_json::from_value(serde_json::Value::Array(messages_json)) {
                 Ok(m) => m,
                 Err(e) => {
                     return stream::once(
-                        async move { Err(format!("Failed to build messages: {}", e)) },
+                        async move { Err(LlmError::InvalidRequest(format!("Failed to build messages: {}", e))) },
                     )
                     .boxed()
                 }
             };
 
-        let oai_tools = Self::build_tools(tools);
+        let oai_tools = Self::tools_for(&caps, tools, model);
         let mut request_builder = CreateChatCompletionRequestArgs::default();
-        request_builder.model(model).messages(messages).stream(true);
+        request_builder
+            .model(model)
+            .messages(messages)
+            .stream(true)
+            .stream_options(ChatCompletionStreamOptions { include_usage: true });
+        Self::apply_sampling(&caps, memory.config.sampling.seed, &params, &mut request_builder);
+        if self.logprobs && caps.logprobs {
+            request_builder.logprobs(true);
+        }
+        if let Some(stop) = Self::stop_for(&caps, &memory.stop) {
+            request_builder.stop(stop);
+        }
 
         if !oai_tools.is_empty() {
             request_builder.tools(oai_tools);
+            if !memory.config.parallel_tools && caps.parallel_tool_calls {
+                request_builder.parallel_tool_calls(false);
+            }
         }
 
         let request = match request_builder.build() {
             Ok(r) => r,
             Err(e) => {
-                return stream::once(async move { Err(format!("Failed to build request: {}", e)) })
+                return stream::once(async move { Err(LlmError::InvalidRequest(format!("Failed to build request: {}", e))) })
                     .boxed()
             }
         };
@@ -239,139 +602,137 @@ impl AsyncLlmCaller for OpenAiCaller {
                 .chat()
                 .create_stream(request)
                 .await
-                .map_err(|e| format!("OpenAI API error: {}", e))
+                .map_err(|e| api_error(e, "OpenAI API error"))
         })
         .flat_map(|res| {
             match res {
-                Ok(stream) => {
-                    let mut accumulated_content = String::new();
-
-                    #[derive(Default)]
-                    struct ToolCallAcc {
-                        id: Option<String>,
-                        name: Option<String>,
-                        args: String,
+                Ok(stream) => stream::unfold(Some((stream, StreamAcc::default())), |state| async move {
+                    let (mut stream, mut acc) = state?;
+                    while let Some(res) = stream.next().await {
+                        match res.map_err(|e| api_error(e, "OpenAI stream error")).and_then(|c| acc.accept(c)) {
+                            Ok(None) => continue,
+                            Ok(Some(chunk)) => return Some((Ok(chunk), Some((stream, acc)))),
+                            Err(e) => return Some((Err(e), Some((stream, acc)))),
+                        }
                     }
-                    let mut tool_accumulators: HashMap<i32, ToolCallAcc> = HashMap::new();
-
-                    stream
-                        .map(move |res| {
-                            let res = res.map_err(|e| format!("OpenAI stream error: {}", e))?;
-                            let choice = res
-                                .choices
-                                .into_iter()
-                                .next()
-                                .ok_or("Empty choice in stream")?;
-                            let delta = choice.delta;
-
-                            if let Some(tool_calls) = delta.tool_calls {
-                                for tc in tool_calls {
-                                    let acc = tool_accumulators.entry(tc.index).or_default();
-                                    if let Some(id) = tc.id {
-                                        acc.id = Some(id);
-                                    }
-                                    if let Some(func) = tc.function {
-                                        if let Some(name) = func.name {
-                                            acc.name = Some(name);
-                                        }
-                                        if let Some(args) = func.arguments {
-                                            acc.args.push_str(&args);
-                                        }
-                                    }
-                                }
-
-                                // Emit a delta for the most recently updated tool call (or all of them?)
-                                // For simplicity, we just send a generic delta indicating tool progress.
-                                // The engine currently doesn't use the index to differentiate in UI,
-                                // it just accumulates name/args from LLM_TOOL_CALL_DELTA events.
-                                // BUT wait! If they are parallel, we MUST specify WHICH ONE.
-                                // For now, let's at least emit the LATEST one.
-                                let (name, args_json) = tool_accumulators
-                                    .values()
-                                    .next() // arbitrary
-                                    .map(|a| (a.name.clone(), a.args.clone()))
-                                    .unwrap_or((None, String::new()));
-
-                                return Ok(crate::types::LlmStreamChunk::ToolCallDelta {
-                                    name,
-                                    args_json,
-                                });
-                            }
-
-                            if let Some(content) = delta.content {
-                                accumulated_content.push_str(&content);
-                                return Ok(crate::types::LlmStreamChunk::Content(content));
-                            }
-
-                            if let Some(_reason) = choice.finish_reason {
-                                if !tool_accumulators.is_empty() {
-                                    if tool_accumulators.len() > 1 {
-                                        let mut tools = Vec::new();
-                                        for acc in tool_accumulators.values() {
-                                            let name = acc.name.clone().unwrap_or_default();
-                                            let args: HashMap<String, serde_json::Value> =
-                                                serde_json::from_str(&acc.args).map_err(|e| {
-                                                    format!(
-                                                        "Failed to parse tool args (parallel): {}",
-                                                        e
-                                                    )
-                                                })?;
-                                            tools.push(crate::types::ToolCall {
-                                                name,
-                                                args,
-                                                id: acc.id.clone(),
-                                            });
-                                        }
-                                        return Ok(crate::types::LlmStreamChunk::Done(
-                                            LlmResponse::ParallelToolCalls {
-                                                tools,
-                                                confidence: 1.0,
-                                                usage: None,
-                                            },
-                                        ));
-                                    } else {
-                                        let acc = tool_accumulators.values().next().unwrap();
-                                        let name = acc.name.clone().unwrap_or_default();
-                                        let args: HashMap<String, serde_json::Value> =
-                                            serde_json::from_str(&acc.args).map_err(|e| {
-                                                format!("Failed to parse tool args: {}", e)
-                                            })?;
-                                        return Ok(crate::types::LlmStreamChunk::Done(
-                                            LlmResponse::ToolCall {
-                                                tool: crate::types::ToolCall {
-                                                    name,
-                                                    args,
-                                                    id: acc.id.clone(),
-                                                },
-                                                confidence: 1.0,
-                                                usage: None,
-                                            },
-                                        ));
-                                    }
-                                } else if !accumulated_content.is_empty() {
-                                    return Ok(crate::types::LlmStreamChunk::Done(
-                                        LlmResponse::FinalAnswer {
-                                            content: accumulated_content.clone(),
-                                            usage: None,
-                                        },
-                                    ));
-                                }
-                            }
-
-                            Err("SKIP".to_string())
-                        })
-                        .filter(|res| {
-                            futures::future::ready(match res {
-                                Ok(_) => true,
-                                Err(e) => e != "SKIP",
-                            })
-                        })
-                        .boxed()
-                }
+                    // The usage chunk comes after the finish reason, so the
+                    // response is sent once the stream is over
+                    acc.finish().map(|resp| (Ok(crate::types::LlmStreamChunk::Done(resp)), None))
+                })
+                .boxed(),
                 Err(e) => stream::once(async move { Err(e) }).boxed(),
             }
         });
 
         s.boxed()
     }
+
+    /// `GET /models`, which Ollama and most OpenAI-compatible servers also serve.
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let models = self.client
+            .models()
+            .list()
+            .await
+            .map_err(|e| api_error(e, "OpenAI model listing failed"))?;
+        Ok(models.data.into_iter().map(|m| m.id).collect())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_schema_format() {
+        let schema = crate::types::OutputSchema {
+            name: "person".into(),
+            description: Some("A person".into()),
+            schema: json!({ "type": "object", "properties": { "name": { "type": "string" } } }),
+            strict: true,
+        };
+        let format = OpenAiCaller::json_schema_format(&schema);
+        assert_eq!(format["type"], "json_schema");
+        assert_eq!(format["json_schema"]["name"], "person");
+        assert_eq!(format["json_schema"]["strict"], true);
+        assert_eq!(format["json_schema"]["description"], "A person");
+        assert_eq!(format["json_schema"]["schema"], schema.schema);
+    }
+
+    #[test]
+    fn test_reasoning_models_get_max_completion_tokens() {
+        let memory = AgentMemory::new("task");
+        let params = ModelParams::new().max_tokens(512).temperature(0.2);
+        let build = |caps: &ModelCapabilities| {
+            let mut request = CreateChatCompletionRequestArgs::default();
+            request.model("m").messages(Vec::<ChatCompletionRequestMessage>::new());
+            OpenAiCaller::apply_sampling(caps, memory.config.sampling.seed, &params, &mut request);
+            let mut body = serde_json::to_value(request.build().unwrap()).unwrap();
+            OpenAiCaller::add_raw_fields(caps, &params, &mut body);
+            body
+        };
+
+        let chat = ModelCapabilities::detect("gpt-4o");
+        assert!(!OpenAiCaller::needs_raw_fields(&chat, &params));
+        let body = build(&chat);
+        assert_eq!(body["max_tokens"], 512);
+        assert!(body.get("max_completion_tokens").is_none());
+
+        let reasoning = ModelCapabilities::detect("o3-mini");
+        assert!(OpenAiCaller::needs_raw_fields(&reasoning, &params));
+        let body = build(&reasoning);
+        assert_eq!(body["max_completion_tokens"], 512);
+        assert!(body.get("max_tokens").is_none());
+        assert!(body.get("temperature").is_none());
+    }
+
+    #[test]
+    fn test_stream_keeps_call_order_and_usage() {
+        let chunk = |choices: serde_json::Value, usage: serde_json::Value| {
+            serde_json::from_value::<CreateChatCompletionStreamResponse>(json!({
+                "id": "c", "object": "chat.completion.chunk", "created": 0, "model": "m",
+                "choices": choices, "usage": usage,
+            }))
+            .unwrap()
+        };
+        let call = |index: i32, name: &str| {
+            json!([{ "index": 0, "delta": { "tool_calls": [
+                { "index": index, "id": name, "function": { "name": name, "arguments": "{}" } }
+            ] } }])
+        };
+
+        let mut acc = StreamAcc::default();
+        // Index 1 arrives first
+        for c in [chunk(call(1, "second"), json!(null)), chunk(call(0, "first"), json!(null))] {
+            assert!(matches!(acc.accept(c), Ok(Some(crate::types::LlmStreamChunk::ToolCallDelta { .. }))));
+        }
+        let finish = json!([{ "index": 0, "delta": {}, "finish_reason": "tool_calls" }]);
+        assert!(matches!(acc.accept(chunk(finish, json!(null))), Ok(None)));
+        // The usage chunk has no choices
+        let usage = json!({ "prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15 });
+        assert!(matches!(acc.accept(chunk(json!([]), usage)), Ok(None)));
+
+        match acc.finish() {
+            Some(LlmResponse::ParallelToolCalls { tools, usage, .. }) => {
+                let names: Vec<&str> = tools.iter().map(|t| t.name.as_str()).collect();
+                assert_eq!(names, ["first", "second"]);
+                assert_eq!(usage.map(|u| u.total_tokens), Some(15));
+            }
+            other => panic!("expected parallel calls, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_logprob_confidence() {
+        assert_eq!(logprob_confidence([]), 1.0);
+        assert!((logprob_confidence([0.0, 0.0]) - 1.0).abs() < 1e-9);
+        // Geometric mean of 0.9 and 0.4 is 0.6
+        let confidence = logprob_confidence([0.9f32.ln(), 0.4f32.ln()]);
+        assert!((confidence - 0.6).abs() < 1e-6, "{}", confidence);
+        assert_eq!(logprob_confidence([f32::NEG_INFINITY]), 0.0);
+    }
 }
@@ -0,0 +1,245 @@
+use crate::memory::AgentMemory;
+use crate::tools::ToolRegistry;
+use crate::types::{LlmResponse, LlmStreamChunk};
+use super::LlmError;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use tokio::time::{Duration, Instant};
+
+use std::sync::Arc;
+
+/// A token bucket: holds up to `capacity`, refilled at `capacity` per minute.
+struct Bucket {
+    capacity: f64,
+    /// (available, last refill); waiters queue on the lock in arrival order
+    state:    tokio::sync::Mutex<(f64, Instant)>,
+}
+
+impl Bucket {
+    fn per_minute(capacity: u32) -> Option<Self> {
+        (capacity > 0).then(|| Self {
+            capacity: capacity as f64,
+            state:    tokio::sync::Mutex::new((capacity as f64, Instant::now())),
+        })
+    }
+
+    fn refill(&self, state: &mut (f64, Instant)) {
+        let now = Instant::now();
+        let per_sec = self.capacity / 60.0;
+        state.0 = (state.0 + now.duration_since(state.1).as_secs_f64() * per_sec).min(self.capacity);
+        state.1 = now;
+    }
+
+    /// Take `amount` (at most the capacity), waiting until it is available.
+    /// Returns how long the call waited.
+    async fn take(&self, amount: f64) -> Duration {
+        let amount = amount.min(self.capacity);
+        let started = Instant::now();
+        let mut state = self.state.lock().await;
+        loop {
+            self.refill(&mut state);
+            if state.0 >= amount {
+                state.0 -= amount;
+                return started.elapsed();
+            }
+            let wait = (amount - state.0) / (self.capacity / 60.0);
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+
+    /// Correct an earlier `take` once the real amount is known. The balance
+    /// may go negative, which delays later calls.
+    async fn adjust(&self, extra: f64) {
+        let mut state = self.state.lock().await;
+        self.refill(&mut state);
+        state.0 -= extra;
+    }
+}
+
+/// Requests-per-minute and tokens-per-minute budgets, shared by every
+/// [`RateLimitedLlmCaller`] built on it.
+///
+/// Both limits are token buckets that start full, so a burst up to the
+/// limit goes out at once. A call takes the prompt's estimated tokens
+/// (about four characters per token) up front; the difference to the
+/// reported usage is settled when the response arrives.
+pub struct RateLimiter {
+    requests: Option<Bucket>,
+    tokens:   Option<Bucket>,
+}
+
+impl RateLimiter {
+    /// Limits per minute; 0 means unlimited.
+    pub fn new(requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        Self {
+            requests: Bucket::per_minute(requests_per_minute),
+            tokens:   Bucket::per_minute(tokens_per_minute),
+        }
+    }
+
+    /// Prompt tokens, estimated from the built messages and tool schemas.
+    fn estimate_tokens(memory: &AgentMemory, tools: &ToolRegistry) -> u32 {
+        let chars: usize = memory.build_messages().iter().map(|m| m.to_string().len()).sum::<usize>()
+            + serde_json::to_string(&tools.schemas()).map(|s| s.len()).unwrap_or(0);
+        (chars / 4) as u32
+    }
+
+    /// Wait for both limits. Returns the estimate taken from the token bucket.
+    async fn acquire(
+        &self,
+        memory:    &AgentMemory,
+        tools:     &ToolRegistry,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> u32 {
+        let mut waited = Duration::ZERO;
+        if let Some(requests) = &self.requests {
+            waited += requests.take(1.0).await;
+        }
+        let estimate = Self::estimate_tokens(memory, tools);
+        if let Some(tokens) = &self.tokens {
+            waited += tokens.take(estimate as f64).await;
+        }
+        if waited >= Duration::from_secs(1) {
+            tracing::info!(waited_ms = waited.as_millis() as u64, "LLM call delayed by client rate limit");
+            if let Some(tx) = output_tx {
+                let _ = tx.send(crate::types::AgentOutput::Action(format!(
+                    "Client rate limit: waited {:.1}s before calling the LLM",
+                    waited.as_secs_f64()
+                )));
+            }
+        }
+        estimate
+    }
+
+    async fn settle(&self, estimate: u32, resp: &LlmResponse) {
+        let (LlmResponse::ToolCall { usage, .. }
+        | LlmResponse::ParallelToolCalls { usage, .. }
+        | LlmResponse::FinalAnswer { usage, .. }
+        | LlmResponse::Structured { usage, .. }
+        | LlmResponse::Truncated { usage, .. }) = resp;
+        if let (Some(tokens), Some(usage)) = (&self.tokens, usage) {
+            tokens.adjust(usage.total_tokens as f64 - estimate as f64).await;
+        }
+    }
+}
+
+/// A wrapper around any `AsyncLlmCaller` that keeps calls under a
+/// [`RateLimiter`], waiting before a call instead of letting the provider
+/// answer 429. Agents that use the same API key should share one limiter.
+pub struct RateLimitedLlmCaller {
+    inner:   Arc<dyn super::AsyncLlmCaller>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitedLlmCaller {
+    /// Limits per minute; 0 means unlimited.
+    pub fn new(inner: Arc<dyn super::AsyncLlmCaller>, requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        Self::with_limiter(inner, Arc::new(RateLimiter::new(requests_per_minute, tokens_per_minute)))
+    }
+
+    /// Draw on `limiter`, which other callers may share.
+    pub fn with_limiter(inner: Arc<dyn super::AsyncLlmCaller>, limiter: Arc<RateLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait]
+impl super::AsyncLlmCaller for RateLimitedLlmCaller {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        tools:  &ToolRegistry,
+        model:  &str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        let estimate = self.limiter.acquire(memory, tools, output_tx).await;
+        let resp = self.inner.call_async(memory, tools, model, output_tx).await?;
+        self.limiter.settle(estimate, &resp).await;
+        Ok(resp)
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools:  &'a ToolRegistry,
+        model:  &'a str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        use futures::{stream, StreamExt};
+
+        let output_tx = output_tx.cloned();
+        stream::once(async move {
+            let estimate = self.limiter.acquire(memory, tools, output_tx.as_ref()).await;
+            self.inner
+                .call_stream_async(memory, tools, model, output_tx.as_ref())
+                .then(move |chunk| async move {
+                    if let Ok(LlmStreamChunk::Done(resp)) = &chunk {
+                        self.limiter.settle(estimate, resp).await;
+                    }
+                    chunk
+                })
+                .boxed()
+        })
+        .flatten()
+        .boxed()
+    }
+
+    async fn health_check(&self) -> Result<(), LlmError> {
+        self.inner.health_check().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        self.inner.list_models().await
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::TokenUsage;
+    use crate::llm::{AsyncLlmCaller, MockLlmCaller};
+
+    fn answer(total_tokens: u32) -> LlmResponse {
+        LlmResponse::FinalAnswer {
+            content: "done".to_string(),
+            confidence: 1.0,
+            usage: Some(TokenUsage::new(total_tokens, 0)),
+            model: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_per_minute() {
+        let mock = Arc::new(MockLlmCaller::new(vec![answer(1), answer(1), answer(1)]));
+        let caller = RateLimitedLlmCaller::new(mock, 2, 0);
+        let memory = AgentMemory::new("task");
+        let tools = ToolRegistry::new();
+
+        let started = Instant::now();
+        caller.call_async(&memory, &tools, "m", None).await.unwrap();
+        caller.call_async(&memory, &tools, "m", None).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1), "burst up to the limit");
+
+        caller.call_async(&memory, &tools, "m", None).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(30), "one request refills every 30s");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tokens_per_minute_settles_reported_usage() {
+        let mock = Arc::new(MockLlmCaller::new(vec![answer(6_000), answer(10)]));
+        let caller = RateLimitedLlmCaller::new(mock, 0, 6_000);
+        let memory = AgentMemory::new("task");
+        let tools = ToolRegistry::new();
+
+        // The first call used the whole minute's tokens; the second waits for a refill
+        let started = Instant::now();
+        caller.call_async(&memory, &tools, "m", None).await.unwrap();
+        caller.call_async(&memory, &tools, "m", None).await.unwrap();
+        let estimate = RateLimiter::estimate_tokens(&memory, &tools) as u64;
+        assert!(started.elapsed() >= Duration::from_millis(estimate * 10));
+    }
+}
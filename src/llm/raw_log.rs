@@ -0,0 +1,402 @@
+//! Raw HTTP logging — the exact bodies sent to and received from a provider.
+//!
+//! A provider answering 400 usually says why in a body the caller turns
+//! into a one-line error, and the request that caused it is never shown.
+//! With a [`RawLogSink`] set (`AgentBuilder::raw_llm_log`), every HTTP call
+//! the built-in callers make is recorded as a [`RawExchange`]: URL,
+//! headers, request body, status and response body. Credentials are
+//! redacted: auth headers keep only their last four characters and `key`
+//! query parameters are masked.
+//!
+//! Streamed responses are recorded with their status; the body is only
+//! kept when the call fails. `OpenAiCaller` sends requests over its own
+//! HTTP path while a sink is set, so its streams become single calls.
+//!
+//! This is a debugging aid: the bodies hold the full prompt and answer.
+
+use super::LlmError;
+use crate::memory::AgentMemory;
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Headers whose values are credentials.
+const SECRET_HEADERS: &[&str] = &[
+    "authorization",
+    "x-api-key",
+    "api-key",
+    "x-goog-api-key",
+    "openai-organization",
+];
+
+/// One HTTP call to a provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawExchange {
+    pub timestamp: DateTime<Utc>,
+    pub session_id: String,
+    pub step: usize,
+    /// `"openai"`, `"anthropic"`, `"gemini"`, `"completion"`
+    pub provider: String,
+    pub model: String,
+    pub method: String,
+    /// With `key` query parameters masked
+    pub url: String,
+    /// With credentials redacted
+    pub headers: BTreeMap<String, String>,
+    /// The request body, as JSON if it parses
+    pub request: Value,
+    /// None if no response arrived
+    pub status: Option<u16>,
+    /// The response body; None for a successful stream
+    pub response: Option<String>,
+    /// Network error, if the call failed before a response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Destination for raw exchanges. Called synchronously after each HTTP call.
+pub trait RawLogSink: Send + Sync {
+    fn record(&self, exchange: &RawExchange);
+}
+
+/// Appends one JSON object per line to a file.
+pub struct JsonlRawLogSink {
+    path: PathBuf,
+    file: Mutex<std::fs::File>,
+}
+
+impl JsonlRawLogSink {
+    pub fn new(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl RawLogSink for JsonlRawLogSink {
+    fn record(&self, exchange: &RawExchange) {
+        let line = match serde_json::to_string(exchange) {
+            Ok(l) => l,
+            Err(e) => {
+                tracing::error!(error = %e, "Raw exchange serialization failed");
+                return;
+            }
+        };
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+            tracing::error!(path = %self.path.display(), error = %e, "Raw LLM log write failed");
+        }
+    }
+}
+
+/// Keeps exchanges in memory. Useful for tests.
+#[derive(Default)]
+pub struct MemoryRawLogSink {
+    exchanges: Mutex<Vec<RawExchange>>,
+}
+
+impl MemoryRawLogSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn exchanges(&self) -> Vec<RawExchange> {
+        self.exchanges.lock().unwrap().clone()
+    }
+}
+
+impl RawLogSink for MemoryRawLogSink {
+    fn record(&self, exchange: &RawExchange) {
+        self.exchanges.lock().unwrap().push(exchange.clone());
+    }
+}
+
+/// Sends each exchange to a channel, e.g. to show it in a UI.
+impl RawLogSink for tokio::sync::mpsc::UnboundedSender<RawExchange> {
+    fn record(&self, exchange: &RawExchange) {
+        let _ = self.send(exchange.clone());
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Redaction
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// `sk-...wxyz` style: the last four characters, or nothing for short values.
+fn mask(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() <= 8 {
+        return "[REDACTED]".to_string();
+    }
+    format!(
+        "[REDACTED]...{}",
+        chars[chars.len() - 4..].iter().collect::<String>()
+    )
+}
+
+fn redact_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = value.to_str().unwrap_or("<binary>");
+            let value = if SECRET_HEADERS.contains(&name.as_str()) {
+                mask(value)
+            } else {
+                value.to_string()
+            };
+            (name.as_str().to_string(), value)
+        })
+        .collect()
+}
+
+fn redact_url(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    if url.query_pairs().any(|(k, _)| k == "key") {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(k, v)| {
+                let v = if k == "key" { mask(&v) } else { v.into_owned() };
+                (k.into_owned(), v)
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Sending
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The request half of an exchange, captured before it is sent.
+struct Pending {
+    method: String,
+    url: String,
+    headers: BTreeMap<String, String>,
+    request: Value,
+    started: Instant,
+}
+
+impl Pending {
+    fn capture(request: &reqwest::Request) -> Self {
+        let body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .unwrap_or_default();
+        Self {
+            method: request.method().to_string(),
+            url: redact_url(request.url()),
+            headers: redact_headers(request.headers()),
+            request: serde_json::from_slice(body)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned())),
+            started: Instant::now(),
+        }
+    }
+
+    fn finish(
+        self,
+        memory: &AgentMemory,
+        provider: &str,
+        model: &str,
+        status: Option<u16>,
+        response: Option<String>,
+        error: Option<String>,
+    ) {
+        let Some(sink) = &memory.raw_log else { return };
+        sink.record(&RawExchange {
+            timestamp: Utc::now(),
+            session_id: memory.session_id.clone(),
+            step: memory.step,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            method: self.method,
+            url: self.url,
+            headers: self.headers,
+            request: self.request,
+            status,
+            response,
+            error,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+        });
+    }
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Send `request` and return the response body, recording the exchange if
+/// `memory` has a raw log. A failed status becomes the error
+/// `"{prefix} {status}: {body}"`, like `error::from_response`.
+pub(crate) async fn send(
+    memory: &AgentMemory,
+    provider: &str,
+    model: &str,
+    request: reqwest::RequestBuilder,
+    prefix: &str,
+) -> Result<String, LlmError> {
+    let (client, request) = request.build_split();
+    let request =
+        request.map_err(|e| LlmError::InvalidRequest(format!("Failed to build request: {}", e)))?;
+    let pending = memory.raw_log.is_some().then(|| Pending::capture(&request));
+
+    let response = match client.execute(request).await {
+        Ok(r) => r,
+        Err(e) => {
+            let message = format!("Network error: {}", e);
+            if let Some(p) = pending {
+                p.finish(memory, provider, model, None, None, Some(message.clone()));
+            }
+            return Err(LlmError::Network(message));
+        }
+    };
+    let status = response.status();
+    let wait = retry_after(response.headers());
+    let body = response
+        .text()
+        .await
+        .map_err(|e| LlmError::Network(format!("Network error: {}", e)))?;
+    if let Some(p) = pending {
+        p.finish(
+            memory,
+            provider,
+            model,
+            Some(status.as_u16()),
+            Some(body.clone()),
+            None,
+        );
+    }
+
+    if !status.is_success() {
+        return Err(LlmError::from_status(
+            status.as_u16(),
+            format!("{} {}: {}", prefix, status, body),
+        )
+        .with_retry_after(wait));
+    }
+    Ok(body)
+}
+
+/// Send a streaming `request` and return the response to read chunks from,
+/// recording the request and status if `memory` has a raw log. A failed
+/// status is turned into an error, with its body recorded.
+pub(crate) async fn send_streaming(
+    memory: &AgentMemory,
+    provider: &str,
+    model: &str,
+    request: reqwest::RequestBuilder,
+    prefix: &str,
+) -> Result<reqwest::Response, LlmError> {
+    let (client, request) = request.build_split();
+    let request =
+        request.map_err(|e| LlmError::InvalidRequest(format!("Failed to build request: {}", e)))?;
+    let pending = memory.raw_log.is_some().then(|| Pending::capture(&request));
+
+    let response = match client.execute(request).await {
+        Ok(r) => r,
+        Err(e) => {
+            let message = format!("Network error: {}", e);
+            if let Some(p) = pending {
+                p.finish(memory, provider, model, None, None, Some(message.clone()));
+            }
+            return Err(LlmError::Network(message));
+        }
+    };
+    let status = response.status();
+    if status.is_success() {
+        if let Some(p) = pending {
+            p.finish(memory, provider, model, Some(status.as_u16()), None, None);
+        }
+        return Ok(response);
+    }
+
+    let wait = retry_after(response.headers());
+    let body = response.text().await.unwrap_or_default();
+    if let Some(p) = pending {
+        p.finish(
+            memory,
+            provider,
+            model,
+            Some(status.as_u16()),
+            Some(body.clone()),
+            None,
+        );
+    }
+    Err(
+        LlmError::from_status(status.as_u16(), format!("{} {}: {}", prefix, status, body))
+            .with_retry_after(wait),
+    )
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_credentials_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer sk-proj-abcdefghijkl1234"),
+        );
+        headers.insert("x-api-key", HeaderValue::from_static("short"));
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        let redacted = redact_headers(&headers);
+        assert_eq!(redacted["authorization"], "[REDACTED]...1234");
+        assert_eq!(redacted["x-api-key"], "[REDACTED]");
+        assert_eq!(redacted["content-type"], "application/json");
+
+        let url = reqwest::Url::parse(
+            "https://example.com/v1beta/models/m:generateContent?key=AIzaSecretValue9876&alt=sse",
+        )
+        .unwrap();
+        let redacted = redact_url(&url);
+        assert!(!redacted.contains("AIzaSecret"));
+        assert!(redacted.contains("9876") && redacted.contains("alt=sse"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_call_recorded() {
+        let sink = std::sync::Arc::new(MemoryRawLogSink::new());
+        let mut memory = AgentMemory::new("task");
+        memory.raw_log = Some(sink.clone());
+
+        // Nothing listens on port 9 (discard) locally
+        let request = reqwest::Client::new()
+            .post("http://127.0.0.1:9/v1/messages")
+            .header("x-api-key", "sk-ant-0123456789abcdef")
+            .json(&serde_json::json!({ "model": "m", "max_tokens": 10 }));
+        let err = send(&memory, "anthropic", "m", request, "Anthropic API error")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LlmError::Network(_)));
+
+        let exchanges = sink.exchanges();
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].request["max_tokens"], 10);
+        assert_eq!(exchanges[0].headers["x-api-key"], "[REDACTED]...cdef");
+        assert!(exchanges[0].status.is_none() && exchanges[0].error.is_some());
+    }
+}
@@ -0,0 +1,402 @@
+use async_trait::async_trait;
+use crate::llm::{AsyncLlmCaller, LlmError, OpenAiCaller};
+use crate::memory::AgentMemory;
+use crate::tools::{parse_tool_args, ToolRegistry};
+use crate::types::{LlmResponse, LlmStreamChunk, ToolCall};
+use std::collections::BTreeMap;
+
+// ── Caller ───────────────────────────────────────────────
+
+/// OpenAI-compatible caller for providers that stream a separate
+/// `reasoning_content` field (xAI Grok, DeepSeek R1).
+///
+/// Streaming is parsed directly so reasoning arrives as
+/// `LlmStreamChunk::Reasoning` and never mixes into the answer.
+/// Non-streaming calls go through `OpenAiCaller`, which drops reasoning,
+/// except when a grammar constraint is set: then the stream is collected so
+/// the constraint still applies.
+pub struct ReasoningCaller {
+    client:   reqwest::Client,
+    api_key:  String,
+    api_base: String,
+    inner:    OpenAiCaller,
+}
+
+impl ReasoningCaller {
+    pub fn new(api_base: impl Into<String>, api_key: impl Into<String>) -> Self {
+        let api_base = api_base.into();
+        let api_key = api_key.into();
+        Self {
+            client:   reqwest::Client::new(),
+            inner:    OpenAiCaller::with_base_url(api_base.clone(), api_key.clone()),
+            api_key,
+            api_base,
+        }
+    }
+
+    /// xAI Grok API. An empty key falls back to `XAI_API_KEY`.
+    pub fn xai(api_key: impl Into<String>) -> Self {
+        Self::new("https://api.x.ai/v1", key_or_env(api_key.into(), "XAI_API_KEY"))
+    }
+
+    /// llama.cpp's `llama-server`. An empty URL means `http://localhost:8080/v1`.
+    pub fn llama_server(base_url: impl Into<String>) -> Self {
+        let url = base_url.into();
+        if url.is_empty() {
+            Self::new("http://localhost:8080/v1", "")
+        } else {
+            Self::new(url, "")
+        }
+    }
+
+    /// DeepSeek API. An empty key falls back to `DEEPSEEK_API_KEY`.
+    pub fn deepseek(api_key: impl Into<String>) -> Self {
+        Self::new("https://api.deepseek.com/v1", key_or_env(api_key.into(), "DEEPSEEK_API_KEY"))
+    }
+
+    fn build_body(memory: &AgentMemory, tools: &ToolRegistry, model: &str) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "model":    model,
+            "messages": memory.build_messages(),
+            "stream":   true,
+        });
+        if let Some(max_tokens) = memory.config.sampling.max_tokens {
+            body["max_tokens"] = max_tokens.into();
+        }
+        if let Some(temperature) = memory.config.sampling.temperature {
+            body["temperature"] = temperature.into();
+        }
+        if let Some(seed) = memory.config.sampling.seed {
+            body["seed"] = seed.into();
+        }
+        if !memory.stop.is_empty() {
+            body["stop"] = serde_json::json!(memory.stop);
+        }
+        if !tools.is_empty() {
+            let defs: Vec<serde_json::Value> = tools
+                .schemas()
+                .into_iter()
+                .map(|s| serde_json::json!({
+                    "type": "function",
+                    "function": {
+                        "name":        s.name,
+                        "description": s.description,
+                        "parameters":  s.input_schema,
+                    }
+                }))
+                .collect();
+            body["tools"] = serde_json::Value::Array(defs);
+            if !memory.config.parallel_tools {
+                body["parallel_tool_calls"] = serde_json::Value::Bool(false);
+            }
+        }
+        if let Some(grammar) = &memory.grammar {
+            grammar.apply(&mut body);
+        }
+        body
+    }
+}
+
+fn key_or_env(key: String, var: &str) -> String {
+    if key.is_empty() {
+        std::env::var(var).unwrap_or_default()
+    } else {
+        key
+    }
+}
+
+// ── Stream parsing ───────────────────────────────────────
+
+#[derive(Default)]
+struct ToolCallAcc {
+    id:   Option<String>,
+    name: Option<String>,
+    args: String,
+}
+
+/// Accumulates SSE `data:` payloads into stream chunks.
+#[derive(Default)]
+struct ReasoningStream {
+    buffer:  String,
+    content: String,
+    tools:   BTreeMap<u64, ToolCallAcc>,
+    /// Stopped at the output token limit
+    truncated: bool,
+    done:    bool,
+}
+
+impl ReasoningStream {
+    /// Feed raw bytes; lines split across network chunks are buffered.
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Result<LlmStreamChunk, LlmError>> {
+        self.buffer.push_str(&String::from_utf8_lossy(bytes));
+        let mut chunks = Vec::new();
+        while let Some(pos) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=pos).collect();
+            if let Some(data) = line.trim().strip_prefix("data:") {
+                chunks.extend(self.handle_data(data.trim()));
+            }
+        }
+        chunks
+    }
+
+    fn handle_data(&mut self, data: &str) -> Vec<Result<LlmStreamChunk, LlmError>> {
+        if data == "[DONE]" {
+            return self.finish().into_iter().collect();
+        }
+        let event: serde_json::Value = match serde_json::from_str(data) {
+            Ok(v) => v,
+            Err(e) => return vec![Err(LlmError::Parse(format!("Invalid stream event: {}", e)))],
+        };
+        let choice = &event["choices"][0];
+        let delta = &choice["delta"];
+        let mut chunks = Vec::new();
+
+        if let Some(reasoning) = delta["reasoning_content"].as_str() {
+            if !reasoning.is_empty() {
+                chunks.push(Ok(LlmStreamChunk::Reasoning(reasoning.to_string())));
+            }
+        }
+        if let Some(content) = delta["content"].as_str() {
+            if !content.is_empty() {
+                self.content.push_str(content);
+                chunks.push(Ok(LlmStreamChunk::Content(content.to_string())));
+            }
+        }
+        if let Some(tool_calls) = delta["tool_calls"].as_array() {
+            for tc in tool_calls {
+                let index = tc["index"].as_u64().unwrap_or(0);
+                let acc = self.tools.entry(index).or_default();
+                if let Some(id) = tc["id"].as_str() {
+                    acc.id = Some(id.to_string());
+                }
+                if let Some(name) = tc["function"]["name"].as_str() {
+                    acc.name = Some(name.to_string());
+                }
+                if let Some(args) = tc["function"]["arguments"].as_str() {
+                    acc.args.push_str(args);
+                }
+                chunks.push(Ok(LlmStreamChunk::ToolCallDelta {
+                    name:      acc.name.clone(),
+                    args_json: acc.args.clone(),
+                }));
+            }
+        }
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            self.truncated = reason == "length";
+            chunks.extend(self.finish());
+        }
+        chunks
+    }
+
+    /// Emit the final response once, on `finish_reason` or `[DONE]`.
+    fn finish(&mut self) -> Option<Result<LlmStreamChunk, LlmError>> {
+        if self.done {
+            return None;
+        }
+        self.done = true;
+
+        let assistant_text = Some(self.content.clone()).filter(|t| !t.trim().is_empty());
+        let mut calls: Vec<ToolCall> = std::mem::take(&mut self.tools)
+            .into_values()
+            .map(|acc| ToolCall {
+                name: acc.name.unwrap_or_default(),
+                args: parse_tool_args(&acc.args),
+                id:   acc.id,
+            })
+            .collect();
+
+        let resp = match calls.len() {
+            0 if self.content.is_empty() => return None,
+            0 if self.truncated => LlmResponse::Truncated { content: self.content.clone(), usage: None, model: None },
+            0 => LlmResponse::FinalAnswer { content: self.content.clone(), confidence: 1.0, usage: None, model: None },
+            1 => LlmResponse::ToolCall {
+                tool: calls.remove(0),
+                confidence: 1.0,
+                assistant_text,
+                usage: None,
+                model: None,
+            },
+            _ => LlmResponse::ParallelToolCalls { tools: calls, confidence: 1.0, usage: None, model: None },
+        };
+        Some(Ok(LlmStreamChunk::Done(resp)))
+    }
+}
+
+#[async_trait]
+impl AsyncLlmCaller for ReasoningCaller {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        tools:  &ToolRegistry,
+        model:  &str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        use futures::StreamExt;
+
+        if memory.grammar.is_none() {
+            return self.inner.call_async(memory, tools, model, output_tx).await;
+        }
+        let mut stream = self.call_stream_async(memory, tools, model, output_tx);
+        while let Some(chunk) = stream.next().await {
+            if let LlmStreamChunk::Done(resp) = chunk? {
+                return Ok(resp);
+            }
+        }
+        Err(LlmError::Parse("Stream ended without a response".to_string()))
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools:  &'a ToolRegistry,
+        model:  &'a str,
+        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+    ) -> futures::stream::BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        use futures::{StreamExt, stream};
+
+        let body = Self::build_body(memory, tools, model);
+        let client = self.client.clone();
+        let api_key = self.api_key.clone();
+        let api_base = self.api_base.clone();
+
+        let s = stream::once(async move {
+            client
+                .post(format!("{}/chat/completions", api_base))
+                .bearer_auth(&api_key)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| LlmError::Network(format!("Network error: {}", e)))
+        })
+        .flat_map(|res| {
+            match res {
+                Ok(resp) if resp.status().is_success() => {
+                    let mut parser = ReasoningStream::default();
+                    resp.bytes_stream()
+                        .map(move |res| {
+                            let bytes = res.map_err(|e| LlmError::Network(format!("Stream error: {}", e)))?;
+                            Ok(parser.feed(&bytes))
+                        })
+                        .flat_map(|res| {
+                            match res {
+                                Ok(chunks) => stream::iter(chunks),
+                                Err(e) => stream::iter(vec![Err(e)]),
+                            }
+                        })
+                        .boxed()
+                }
+                Ok(resp) => {
+                    stream::once(async move {
+                        Err(super::error::from_response(resp, "API error").await)
+                    }).boxed()
+                }
+                Err(e) => stream::once(async move { Err(e) }).boxed(),
+            }
+        });
+
+        s.boxed()
+    }
+
+    async fn health_check(&self) -> Result<(), LlmError> {
+        self.inner.health_check().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        self.inner.list_models().await
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(parser: &mut ReasoningStream, lines: &[&str]) -> Vec<LlmStreamChunk> {
+        let mut out = Vec::new();
+        for line in lines {
+            out.extend(parser.feed(format!("data: {}\n\n", line).as_bytes()).into_iter().map(|c| c.unwrap()));
+        }
+        out
+    }
+
+    #[test]
+    fn test_reasoning_is_separate_from_answer() {
+        let mut parser = ReasoningStream::default();
+        let chunks = collect(&mut parser, &[
+            r#"{"choices":[{"delta":{"reasoning_content":"Think "}}]}"#,
+            r#"{"choices":[{"delta":{"reasoning_content":"hard."}}]}"#,
+            r#"{"choices":[{"delta":{"content":"42"}}]}"#,
+            r#"{"choices":[{"delta":{},"finish_reason":"stop"}]}"#,
+            "[DONE]",
+        ]);
+
+        let reasoning: String = chunks.iter().filter_map(|c| match c {
+            LlmStreamChunk::Reasoning(r) => Some(r.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(reasoning, "Think hard.");
+
+        match chunks.last() {
+            Some(LlmStreamChunk::Done(LlmResponse::FinalAnswer { content, .. })) => assert_eq!(content, "42"),
+            other => panic!("expected final answer, got {:?}", other),
+        }
+        assert_eq!(chunks.iter().filter(|c| matches!(c, LlmStreamChunk::Done(_))).count(), 1);
+    }
+
+    #[test]
+    fn test_tool_call_split_across_chunks() {
+        let mut parser = ReasoningStream::default();
+        let mut chunks = parser.feed(br#"data: {"choices":[{"delta":{"tool_calls":[{"index":0,"id":"c1","function":{"name":"search","argu"#);
+        assert!(chunks.is_empty());
+        chunks.extend(parser.feed(b"ments\":\"{\\\"q\\\":\\\"rust\\\"}\"}}]}}]}\n"));
+        chunks.extend(parser.feed(b"data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n"));
+
+        match chunks.last().unwrap().as_ref().unwrap() {
+            LlmStreamChunk::Done(LlmResponse::ToolCall { tool, .. }) => {
+                assert_eq!(tool.name, "search");
+                assert_eq!(tool.args["q"], "rust");
+                assert_eq!(tool.id.as_deref(), Some("c1"));
+            }
+            other => panic!("expected tool call, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_body_carries_grammar() {
+        let mut memory = AgentMemory::new("t");
+        let body = ReasoningCaller::build_body(&memory, &ToolRegistry::new(), "m");
+        assert!(body.get("grammar").is_none());
+
+        memory.grammar = Some(crate::grammar::GrammarConstraint::Gbnf("root ::= \"x\"".into()));
+        let body = ReasoningCaller::build_body(&memory, &ToolRegistry::new(), "m");
+        assert_eq!(body["grammar"], "root ::= \"x\"");
+    }
+
+    #[test]
+    fn test_body_carries_stop_sequences() {
+        let mut memory = AgentMemory::new("t");
+        assert!(ReasoningCaller::build_body(&memory, &ToolRegistry::new(), "m").get("stop").is_none());
+
+        memory.config.sampling.stop = vec!["END".into()];
+        memory.planning_prompter = std::sync::Arc::new(crate::prompter::ReActPrompter);
+        memory.prepare_prompt(&ToolRegistry::new());
+        let body = ReasoningCaller::build_body(&memory, &ToolRegistry::new(), "m");
+        assert_eq!(body["stop"], serde_json::json!(["END", "\nObservation:"]));
+    }
+
+    #[test]
+    fn test_length_finish_is_truncated() {
+        let mut parser = ReasoningStream::default();
+        let chunks = collect(&mut parser, &[
+            r#"{"choices":[{"delta":{"content":"The answer is"}}]}"#,
+            r#"{"choices":[{"delta":{},"finish_reason":"length"}]}"#,
+        ]);
+        match chunks.last() {
+            Some(LlmStreamChunk::Done(LlmResponse::Truncated { content, .. })) => assert_eq!(content, "The answer is"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
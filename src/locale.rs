@@ -0,0 +1,186 @@
+//! Locale support — localized prompts and observation markers.
+//!
+//! Tool results reach the model with a status marker in front: `SUCCESS:`,
+//! `ERROR:` or `REJECTED:`. The status itself is kept on the
+//! [`Observation`]; [`ObservationMarkers`] only decides how it is written, so
+//! an agent that talks to its model in another language can use markers in
+//! that language.
+//!
+//! `AgentConfig::locale` (a BCP 47 tag such as `"de"` or `"pt-BR"`, default
+//! `"en"`) picks both:
+//! - the markers, from a small built-in table, unless
+//!   `AgentConfig::observation_markers` sets them explicitly
+//! - the system prompt, when templates were added for several locales with
+//!   `AgentBuilder::localized_prompt`
+//!
+//! ```rust,ignore
+//! let agent = AgentBuilder::new("Fasse die Datei zusammen")
+//!     .openai("")
+//!     .locale("de-AT")
+//!     .localized_prompt("de", PromptTemplate::new("Du bist ein sorgfältiger Assistent."))
+//!     .localized_prompt("en", PromptTemplate::new("You are a careful assistant."))
+//!     .build()?;
+//! // Observations read "ERFOLG: ..." / "FEHLER: ...", the German prompt is used
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::prompt::PromptTemplate;
+use crate::types::{Observation, ObservationStatus};
+
+/// Locale used when none is configured.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Languages with built-in markers besides English.
+const BUILTIN_LANGUAGES: [&str; 5] = ["de", "es", "fr", "it", "pt"];
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Observation markers
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Status markers put in front of tool observations, colon included.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObservationMarkers {
+    pub success:  String,
+    pub error:    String,
+    pub rejected: String,
+}
+
+impl Default for ObservationMarkers {
+    fn default() -> Self {
+        Self::english()
+    }
+}
+
+impl ObservationMarkers {
+    pub fn new(success: impl Into<String>, error: impl Into<String>, rejected: impl Into<String>) -> Self {
+        Self { success: success.into(), error: error.into(), rejected: rejected.into() }
+    }
+
+    /// `SUCCESS:`, `ERROR:`, `REJECTED:`
+    pub fn english() -> Self {
+        Self::new("SUCCESS:", "ERROR:", "REJECTED:")
+    }
+
+    /// Built-in markers for `locale`'s language (`de`, `es`, `fr`, `it`,
+    /// `pt`). Other languages get the English markers.
+    pub fn for_locale(locale: &str) -> Self {
+        match language(locale).as_str() {
+            "de" => Self::new("ERFOLG:", "FEHLER:", "ABGELEHNT:"),
+            "es" => Self::new("ÉXITO:", "ERROR:", "RECHAZADO:"),
+            "fr" => Self::new("SUCCÈS:", "ERREUR:", "REJETÉ:"),
+            "it" => Self::new("SUCCESSO:", "ERRORE:", "RIFIUTATO:"),
+            "pt" => Self::new("SUCESSO:", "ERRO:", "REJEITADO:"),
+            _ => Self::english(),
+        }
+    }
+
+    pub fn success(&self, text: impl std::fmt::Display) -> String {
+        format!("{} {}", self.success, text)
+    }
+
+    pub fn error(&self, text: impl std::fmt::Display) -> String {
+        format!("{} {}", self.error, text)
+    }
+
+    pub fn rejected(&self, text: impl std::fmt::Display) -> String {
+        format!("{} {}", self.rejected, text)
+    }
+
+    /// Split `text` into the status its marker stands for and the rest.
+    pub fn parse<'a>(&self, text: &'a str) -> Option<(ObservationStatus, &'a str)> {
+        [
+            (&self.success, ObservationStatus::Success),
+            (&self.error, ObservationStatus::Error),
+            (&self.rejected, ObservationStatus::Rejected),
+        ]
+        .into_iter()
+        .find_map(|(marker, status)| text.strip_prefix(marker.as_str()).map(|rest| (status, rest.trim_start())))
+    }
+
+    /// [`parse`](Self::parse) with the English markers, then each built-in set.
+    pub fn parse_any(text: &str) -> Option<(ObservationStatus, &str)> {
+        std::iter::once(Self::english())
+            .chain(BUILTIN_LANGUAGES.iter().map(|lang| Self::for_locale(lang)))
+            .find_map(|markers| markers.parse(text))
+    }
+
+    /// `observation` as the model sees it with these markers.
+    pub fn render(&self, observation: &Observation) -> String {
+        observation.render(self)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Prompt selection
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Lower-cased primary language subtag: `"pt-BR"` → `"pt"`, `"de_AT"` → `"de"`.
+pub fn language(locale: &str) -> String {
+    locale.split(['-', '_']).next().unwrap_or("").to_ascii_lowercase()
+}
+
+/// The template for `locale`: an exact (case-insensitive) match first, then
+/// one for its language.
+pub fn select_template<'a>(
+    templates: &'a HashMap<String, PromptTemplate>,
+    locale: &str,
+) -> Option<&'a PromptTemplate> {
+    let normalized = |tag: &str| tag.replace('_', "-").to_ascii_lowercase();
+    let wanted = normalized(locale);
+    let lang = language(locale);
+    templates
+        .iter()
+        .find(|(tag, _)| normalized(tag) == wanted)
+        .or_else(|| templates.iter().find(|(tag, _)| normalized(tag) == lang))
+        .map(|(_, tpl)| tpl)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markers_for_locale() {
+        assert_eq!(ObservationMarkers::for_locale("en-US"), ObservationMarkers::english());
+        assert_eq!(ObservationMarkers::for_locale("ja"), ObservationMarkers::english());
+
+        let de = ObservationMarkers::for_locale("de_AT");
+        assert_eq!(de.success("ok"), "ERFOLG: ok");
+        assert_eq!(de.render(&Observation::rejected("nein")), "ABGELEHNT: nein");
+    }
+
+    #[test]
+    fn test_parse_markers() {
+        let en = ObservationMarkers::english();
+        assert_eq!(en.parse("ERROR: boom"), Some((ObservationStatus::Error, "boom")));
+        assert_eq!(en.parse("plain"), None);
+        assert_eq!(
+            ObservationMarkers::parse_any("SUCCÈS: 42"),
+            Some((ObservationStatus::Success, "42"))
+        );
+        assert_eq!(
+            ObservationMarkers::parse_any("REJECTED: denied"),
+            Some((ObservationStatus::Rejected, "denied"))
+        );
+    }
+
+    #[test]
+    fn test_select_template() {
+        let mut templates = HashMap::new();
+        templates.insert("pt".to_string(), PromptTemplate::new("pt"));
+        templates.insert("pt-BR".to_string(), PromptTemplate::new("pt-BR"));
+        templates.insert("en".to_string(), PromptTemplate::new("en"));
+
+        let render = |locale: &str| select_template(&templates, locale).map(|t| t.render().unwrap());
+        assert_eq!(render("pt_br").as_deref(), Some("pt-BR"));
+        assert_eq!(render("pt-PT").as_deref(), Some("pt"));
+        assert_eq!(render("en-GB").as_deref(), Some("en"));
+        assert_eq!(render("de"), None);
+    }
+}
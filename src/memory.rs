@@ -1,16 +1,24 @@
 use crate::budget::{TokenBudget, TokenUsage};
 use crate::cache::{LlmCache, NoopCache};
 use crate::hooks::{AgentHooks, NoopHooks};
-use crate::human::{ApprovalPolicy, HumanApprovalRequest, HumanDecision};
+use crate::human::{ApprovalPolicy, DecisionRecord, HumanApprovalRequest, HumanDecision};
 use crate::memory_strategy::{FullMemory, MemoryStrategy};
 use crate::prompt::PromptTemplate;
+use crate::prompter::{NativePrompter, PlanningPrompter};
 use crate::trace::{Trace, TraceEntry};
-use crate::types::{AgentConfig, HistoryEntry, ToolCall, ToolResult};
+use crate::types::{
+    AgentConfig, HistoryEntry, Observation, ObservationStatus, ReflectionTrigger, ToolCall, ToolResult,
+};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+/// Tool name under which `prune_failed_branch` used to leave its note in
+/// history. Such entries in older checkpoints are sent as notes, never as
+/// tool calls.
+pub const PRUNED_BRANCH: &str = "[PRUNED]";
+
 pub struct ApprovalCallback(pub Arc<dyn Fn(HumanApprovalRequest) -> HumanDecision + Send + Sync>);
 
 impl std::fmt::Debug for ApprovalCallback {
@@ -25,11 +33,18 @@ impl Clone for ApprovalCallback {
     }
 }
 
+/// Checks that a structured answer converts to the type `run_typed` asks
+/// for; `Err` holds the reason shown to the model.
+pub type AnswerCheck = Arc<dyn Fn(&serde_json::Value) -> Result<(), String> + Send + Sync>;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct AgentMemory {
     // ── Task definition ──────────────────────────────────
     /// The original task description
     pub task: String,
+    /// Constraints sent with the task in every request, however history is compressed
+    #[serde(default)]
+    pub pinned: Vec<String>,
     /// Classifies task for model selection ("research", "calculation", "default")
     pub task_type: String,
     /// The system prompt to prepend to every LLM call
@@ -42,17 +57,62 @@ pub struct AgentMemory {
     pub retry_count: usize,
     /// Last recorded confidence score from LLM
     pub confidence_score: f64,
+    /// Number of too-short final answers sent back for revision
+    #[serde(default)]
+    pub answer_revisions: usize,
+    /// Feedback for the next LLM call explaining why the last answer was rejected
+    #[serde(default)]
+    pub answer_feedback: Option<String>,
+    /// One-shot guidance for the next Planning call, dropped once it is sent
+    #[serde(default)]
+    pub hints: Vec<String>,
+    /// Approaches given up on by `prune_failed_branch`, sent after history
+    /// on every call
+    #[serde(default)]
+    pub pruned_notes: Vec<String>,
+    /// Set when a `wait_until` call is committed; the engine suspends the
+    /// run and clears it when the run continues
+    #[serde(default)]
+    pub suspension: Option<crate::suspend::Suspension>,
+    /// Answer text so far while a truncated answer is being continued
+    #[serde(default)]
+    pub partial_answer: Option<String>,
+    /// Set when the last LLM call overflowed the context window; cleared on the next success
+    #[serde(default)]
+    pub context_overflow: bool,
+    /// Failed LLM calls this run, as (step, error)
+    #[serde(default)]
+    pub llm_failures: Vec<(usize, String)>,
 
     // ── Tool call lifecycle ──────────────────────────────
     /// Set by PlanningState when LLM requests a tool, consumed by ActingState
     pub current_tool_call: Option<ToolCall>,
+    /// Text the LLM wrote alongside `current_tool_call`, committed with it to history
+    #[serde(default)]
+    pub current_assistant_text: Option<String>,
     /// Set by ActingState after tool execution, consumed by ObservingState
-    pub last_observation: Option<String>,
+    pub last_observation: Option<Observation>,
+    /// Model that produced this step's tool calls, set by PlanningState
+    #[serde(default)]
+    pub current_model: Option<String>,
+    /// Tokens spent on this step's planning calls, set by PlanningState
+    #[serde(default)]
+    pub current_usage: Option<TokenUsage>,
+    /// How long `current_tool_call` ran, set by ActingState
+    #[serde(default)]
+    pub current_latency_ms: Option<u64>,
+    /// Planning response prepared while `current_tool_call` ran, checked by
+    /// the next PlanningState (not serialized)
+    #[serde(skip)]
+    pub speculation: Option<crate::speculation::Speculation>,
 
     /// Multiple tool calls queued for parallel execution.
     pub pending_tool_calls: Vec<ToolCall>,
     /// Results from parallel tool execution.
     pub parallel_results: Vec<ToolResult>,
+    /// Tool calls beyond the per-step limit, executed on the following steps.
+    #[serde(default)]
+    pub deferred_tool_calls: Vec<ToolCall>,
 
     // ── History and results ──────────────────────────────
     /// Ordered list of completed tool calls and their observations
@@ -61,6 +121,9 @@ pub struct AgentMemory {
     pub final_answer: Option<String>,
     /// Set when agent encounters an unrecoverable error
     pub error: Option<String>,
+    /// Sources attached to tool observations, numbered from 1
+    #[serde(default)]
+    pub citations: Vec<crate::citations::Citation>,
 
     // ── Configuration ────────────────────────────────────
     pub config: AgentConfig,
@@ -75,6 +138,9 @@ pub struct AgentMemory {
     /// Callback invoked when approval is needed
     #[serde(skip)]
     pub approval_callback: Option<ApprovalCallback>,
+    /// Every human decision and who made it. Kept across `apply()`, like the trace.
+    #[serde(default)]
+    pub human_decisions: Vec<DecisionRecord>,
 
     // ── Observability ────────────────────────────────────
     /// Full event-sourcing log — every state transition recorded here
@@ -84,6 +150,10 @@ pub struct AgentMemory {
     /// Total tokens consumed in this session
     pub total_usage: TokenUsage,
 
+    /// Cost in USD of calls to models with known pricing
+    #[serde(default)]
+    pub total_cost: f64,
+
     /// Optional budget limits
     pub budget: Option<TokenBudget>,
 
@@ -91,6 +161,9 @@ pub struct AgentMemory {
     /// Optional template for the system prompt
     #[serde(skip)]
     pub prompt_template: Option<PromptTemplate>,
+    /// System prompt templates by locale, chosen with `config.locale`
+    #[serde(skip)]
+    pub localized_prompts: HashMap<String, PromptTemplate>,
 
     // ── LLM Cache ───────────────────────────────────────
     /// LLM response cache (not serialized)
@@ -102,16 +175,85 @@ pub struct AgentMemory {
     #[serde(skip, default = "default_memory_strategy")]
     pub memory_strategy: Arc<dyn MemoryStrategy>,
 
+    // ── Planning Prompter ───────────────────────────────
+    /// How planning requests are framed (not serialized)
+    #[serde(skip, default = "default_planning_prompter")]
+    pub planning_prompter: Arc<dyn PlanningPrompter>,
+
+    /// Prompter instructions for the current tools, appended to the system
+    /// prompt. Refreshed by `prepare_prompt` each planning step.
+    #[serde(skip)]
+    pub tool_instructions: Option<String>,
+
+    /// `config.sampling.grammar` resolved against the current tools.
+    /// Refreshed by `prepare_prompt` each planning step.
+    #[serde(skip)]
+    pub grammar: Option<crate::grammar::GrammarConstraint>,
+
+    /// The stop sequences of `model_params` for the current model plus the
+    /// prompter's stop sequences.
+    /// Refreshed by `prepare_prompt` each planning step.
+    #[serde(skip)]
+    pub stop: Vec<String>,
+
+    /// Progress report sent at the end of the request when `config.status_line`
+    /// is on. Refreshed by `prepare_prompt` each planning step.
+    #[serde(skip)]
+    pub status_line: Option<String>,
+
     // ── Hooks ─────────────────────────────────────────────
     /// Callback hooks for real-time observability (not serialized)
     #[serde(skip, default = "default_hooks")]
     pub hooks: Arc<dyn AgentHooks>,
 
+    // ── Audit ─────────────────────────────────────────────
+    /// Tool execution audit log, bound to this session (not serialized)
+    #[serde(skip)]
+    pub audit: Option<crate::audit::AuditLog>,
+
+    // ── Moderation ────────────────────────────────────────
+    /// Hook that checks final answers before they are accepted (not serialized)
+    #[serde(skip)]
+    pub moderation: Option<crate::moderation::Moderation>,
+
+    // ── Notifications ─────────────────────────────────────
+    /// Notifiers for approval requests, completions and failures (not serialized)
+    #[serde(skip)]
+    pub notifications: Option<crate::notify::Notifications>,
+
+    // ── Raw LLM Log ───────────────────────────────────────
+    /// Records the HTTP bodies of every provider call (not serialized)
+    #[serde(skip)]
+    pub raw_log: Option<Arc<dyn crate::llm::RawLogSink>>,
+
+    // ── Policy Engine ─────────────────────────────────────
+    /// Org-wide rules checked before tool calls and final answers (not serialized)
+    #[serde(skip)]
+    pub policy_engine: Option<Arc<dyn crate::policy::PolicyEngine>>,
+
+    // ── Answer Post-Processing ───────────────────────────
+    /// Transforms applied in order to every accepted final answer (not serialized)
+    #[serde(skip)]
+    pub answer_transforms: Vec<Arc<dyn crate::postprocess::FinalAnswerTransform>>,
+    /// Set by `AgentEngine::run_typed` for the length of the run (not serialized)
+    #[serde(skip)]
+    pub answer_check: Option<AnswerCheck>,
+
+    // ── Run Hooks ────────────────────────────────────────
+    /// Async callbacks run when `run()` ends (not serialized)
+    #[serde(skip)]
+    pub run_hooks: crate::hooks::RunHooks,
+
     // ── Adaptive Model Routing ──────────────────────────
     /// Optional routing policy for dynamic model selection
     #[serde(skip)]
     pub routing_policy: Option<crate::routing::RoutingPolicy>,
 
+    // ── Semantic Dedup ──────────────────────────────────
+    /// Catches tool calls that repeat an earlier one in meaning (not serialized)
+    #[serde(skip)]
+    pub semantic_dedup: Option<crate::dedup::SemanticDedup>,
+
     // ── Introspection ────────────────────────────────────
     /// Notes from anomaly detection, injected into LLM context
     pub anomaly_notes: Vec<String>,
@@ -123,6 +265,14 @@ pub struct AgentMemory {
     #[serde(skip, default)]
     pub planning_mode: crate::plan::PlanningMode,
 
+    // ── Plan Mode ────────────────────────────────────────
+    /// Whether mutating tools run or are only simulated
+    #[serde(default)]
+    pub execution_mode: crate::dry_run::ExecutionMode,
+    /// Mutating tool calls simulated in plan mode, awaiting approval
+    #[serde(default)]
+    pub planned_actions: Vec<ToolCall>,
+
     // ── Deterministic Replay ───────────────────────────────
     /// Replay recorder for time-travel debugging
     #[serde(skip, default = "default_replay_recorder")]
@@ -132,6 +282,23 @@ pub struct AgentMemory {
     /// Registry for composite tools created during the session
     #[serde(skip, default)]
     pub composite_tools: crate::tool_synthesis::CompositeToolRegistry,
+
+    // ── Tool Context ─────────────────────────────────────
+    /// Session the agent runs in, set by the builder (not serialized)
+    #[serde(skip)]
+    pub session_id: String,
+    /// Values handed to every tool through `ToolContext` (not serialized)
+    #[serde(skip)]
+    pub tool_extensions: crate::tools::ToolExtensions,
+    /// The engine's workspace, handed to tools (not serialized)
+    #[serde(skip)]
+    pub workspace: Option<Arc<crate::workspace::Workspace>>,
+    /// Cancelled when the run is cancelled, handed to tools (not serialized)
+    #[serde(skip)]
+    pub cancel: crate::cancel::CancellationToken,
+    /// Trace and token event sampling, shared with handles (not serialized)
+    #[serde(skip)]
+    pub observability: crate::observability::ObservabilityControl,
 }
 
 fn default_hooks() -> Arc<dyn AgentHooks> {
@@ -146,6 +313,23 @@ fn default_memory_strategy() -> Arc<dyn MemoryStrategy> {
     Arc::new(FullMemory)
 }
 
+fn default_planning_prompter() -> Arc<dyn PlanningPrompter> {
+    Arc::new(NativePrompter)
+}
+
+/// `12400` as `"12,400"`.
+fn group_digits(n: u32) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
 fn default_replay_recorder() -> crate::replay::ReplayRecorder {
     crate::replay::ReplayRecorder::disabled()
 }
@@ -165,37 +349,153 @@ impl AgentMemory {
     pub fn new(task: impl Into<String>) -> Self {
         Self {
             task: task.into(),
+            pinned: Vec::new(),
             task_type: "default".to_string(),
             system_prompt: String::new(),
             step: 0,
             retry_count: 0,
             confidence_score: 1.0,
+            answer_revisions: 0,
+            answer_feedback: None,
+            hints: Vec::new(),
+            pruned_notes: Vec::new(),
+            suspension: None,
+            partial_answer: None,
+            context_overflow: false,
+            llm_failures: Vec::new(),
             current_tool_call: None,
+            current_assistant_text: None,
             last_observation: None,
+            current_model: None,
+            current_usage: None,
+            current_latency_ms: None,
+            speculation: None,
             pending_tool_calls: Vec::new(),
             parallel_results: Vec::new(),
+            deferred_tool_calls: Vec::new(),
             history: Vec::new(),
             final_answer: None,
             error: None,
+            citations: Vec::new(),
             config: AgentConfig::default(),
             blacklisted_tools: HashSet::new(),
             pending_approval: None,
             approval_policy: ApprovalPolicy::default(),
             approval_callback: None,
+            human_decisions: Vec::new(),
             trace: Trace::new(),
             total_usage: TokenUsage::default(),
+            total_cost: 0.0,
             budget: None,
             prompt_template: None,
+            localized_prompts: HashMap::new(),
             cache: Arc::new(NoopCache),
             memory_strategy: Arc::new(FullMemory),
+            planning_prompter: Arc::new(NativePrompter),
+            tool_instructions: None,
+            grammar: None,
+            stop: Vec::new(),
+            status_line: None,
             hooks: Arc::new(NoopHooks),
+            audit: None,
+            moderation: None,
+            notifications: None,
+            raw_log: None,
+            policy_engine: None,
+            answer_transforms: Vec::new(),
+            answer_check: None,
+            run_hooks: Default::default(),
             routing_policy: None,
+            semantic_dedup: None,
             anomaly_notes: Vec::new(),
             current_plan: None,
             planning_mode: crate::plan::PlanningMode::Implicit,
+            execution_mode: crate::dry_run::ExecutionMode::Apply,
+            planned_actions: Vec::new(),
             replay_recorder: crate::replay::ReplayRecorder::disabled(),
             composite_tools: Default::default(),
+            session_id: String::new(),
+            tool_extensions: Default::default(),
+            workspace: None,
+            cancel: Default::default(),
+            observability: Default::default(),
+        }
+    }
+
+    /// Clear everything a run produced so the task can run again from Idle.
+    /// Task, configuration, trace and token usage are kept.
+    pub fn reset_progress(&mut self) {
+        self.step = 0;
+        self.retry_count = 0;
+        self.confidence_score = 1.0;
+        self.answer_revisions = 0;
+        self.answer_feedback = None;
+        self.hints.clear();
+        self.pruned_notes.clear();
+        self.partial_answer = None;
+        self.context_overflow = false;
+        self.llm_failures.clear();
+        self.current_tool_call = None;
+        self.current_assistant_text = None;
+        self.last_observation = None;
+        self.current_model = None;
+        self.current_usage = None;
+        self.current_latency_ms = None;
+        self.speculation = None;
+        self.pending_tool_calls.clear();
+        self.parallel_results.clear();
+        self.deferred_tool_calls.clear();
+        self.history.clear();
+        self.final_answer = None;
+        self.error = None;
+        self.citations.clear();
+        self.pending_approval = None;
+        self.anomaly_notes.clear();
+        self.current_plan = None;
+        self.planned_actions.clear();
+    }
+
+    /// Rebuild memory from a trace recorded with `event_sourced_memory` on.
+    /// See [`crate::event_sourcing`].
+    pub fn from_trace(entries: &[crate::trace::TraceEntry]) -> Result<Self, String> {
+        crate::event_sourcing::reconstruct(entries)
+    }
+
+    /// Serialize this memory through `codec`, e.g. to hand it to another
+    /// process. Runtime-only fields (hooks, cache, strategy) are not included.
+    pub fn export(&self, codec: &dyn crate::checkpoint::MemoryCodec) -> Result<Vec<u8>, String> {
+        crate::checkpoint::encode_with(codec, self)
+    }
+
+    /// Inverse of [`AgentMemory::export`].
+    pub fn import(data: Vec<u8>, codec: &dyn crate::checkpoint::MemoryCodec) -> Result<Self, String> {
+        crate::checkpoint::decode_with(codec, data)
+    }
+
+    /// Move sources attached by a tool into `citations` and return the
+    /// observation with a numbered `Sources:` list in their place.
+    /// A URL seen before keeps its original number.
+    pub fn record_citations(&mut self, tool: &str, observation: String) -> String {
+        let (text, sources) = crate::citations::extract_citations(&observation);
+        if sources.is_empty() {
+            return observation;
+        }
+        let mut listed = Vec::new();
+        for mut source in sources {
+            if let Some(existing) = self.citations.iter().find(|c| c.url == source.url) {
+                listed.push(existing.clone());
+                continue;
+            }
+            source.id = self.citations.len() + 1;
+            source.tool = tool.to_string();
+            source.step = self.step;
+            self.citations.push(source.clone());
+            listed.push(source);
         }
+        self.log("Observing", "CITATIONS_RECORDED", &format!(
+            "tool={} sources={} total={}", tool, listed.len(), self.citations.len()
+        ));
+        format!("{}\n{}", text, crate::citations::format_sources(&listed))
     }
 
     pub fn with_task_type(mut self, task_type: impl Into<String>) -> Self {
@@ -217,8 +517,21 @@ impl AgentMemory {
         self.blacklisted_tools.insert(tool_name.into());
     }
 
+    /// Markers for tool observations: the configured ones, or the built-ins
+    /// for `config.locale`.
+    pub fn markers(&self) -> crate::locale::ObservationMarkers {
+        self.config
+            .observation_markers
+            .clone()
+            .unwrap_or_else(|| crate::locale::ObservationMarkers::for_locale(&self.config.locale))
+    }
+
     /// Records an event into the trace log. Called by all state handlers.
+    /// Events the observability trace level leaves out are dropped.
     pub fn log(&mut self, state: &str, event: &str, data: &str) {
+        if !self.observability.trace_level().keeps(event) {
+            return;
+        }
         tracing::debug!(state, event, data, step = self.step, "agent trace");
         self.trace.record(TraceEntry {
             step: self.step,
@@ -229,13 +542,172 @@ impl AgentMemory {
         });
     }
 
+    /// The context a tool sees while serving `call`.
+    pub fn tool_context(&self, call: &ToolCall) -> crate::tools::ToolContext {
+        crate::tools::ToolContext {
+            session_id:   self.session_id.clone(),
+            step:         self.step,
+            task:         self.task.clone(),
+            tool_call_id: call.id.clone(),
+            workspace:    self.workspace.clone(),
+            cancel:       self.cancel.clone(),
+            extensions:   self.tool_extensions.clone(),
+        }
+    }
+
+    /// Drop the failed tool calls at the end of history (a rejected action,
+    /// or a run of failures) and add a note to `pruned_notes` saying the
+    /// approach did not work, so the model stops building on it. The note
+    /// is not a history entry: a tool call the model never made would be
+    /// rejected by native tool-calling APIs. The same note is not added
+    /// twice. Returns the number of entries removed.
+    pub fn prune_failed_branch(&mut self) -> usize {
+        let keep = self.history.iter().rposition(|h| h.is_success()).map_or(0, |i| i + 1);
+        let failed: Vec<HistoryEntry> = self.history.drain(keep..).collect();
+        let Some(last) = failed.last() else {
+            return 0;
+        };
+
+        let mut tools: Vec<&str> = Vec::new();
+        for entry in &failed {
+            if !tools.contains(&entry.tool.name.as_str()) {
+                tools.push(&entry.tool.name);
+            }
+        }
+        let outcome = match last.observation.status {
+            ObservationStatus::Rejected => "was rejected",
+            _ => "failed",
+        };
+        let result: String = last.observation.render(&self.markers()).chars().take(200).collect();
+        let note = format!(
+            "Approach `{}` {} ({} attempt(s)). Last result: {}. Do not retry it; take a different approach.",
+            tools.join(", "),
+            outcome,
+            failed.len(),
+            result
+        );
+        let noted = self.pruned_notes.contains(&note)
+            || self.history.iter().any(|h| h.tool.name == PRUNED_BRANCH && *h.observation.content == *note);
+        if !noted {
+            self.pruned_notes.push(note);
+        }
+        self.log(
+            "Memory",
+            "BRANCH_PRUNED",
+            &format!("removed={} tools={} note_added={}", failed.len(), tools.join(","), !noted),
+        );
+        failed.len()
+    }
+
+    /// Add guidance for the next Planning call only. It is sent as a user
+    /// message after history and never stored there.
+    pub fn add_hint(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        self.log("Engine", "HINT_ADDED", &text.chars().take(100).collect::<String>());
+        self.hints.push(text);
+    }
+
+    /// Add one call's usage to the session totals. Returns the session cost
+    /// so far, or `None` if `model` has no pricing configured.
+    pub fn record_usage(&mut self, model: &str, usage: TokenUsage) -> Option<f64> {
+        self.total_usage.add(usage);
+        let pricing = self.config.pricing.get(model)?;
+        self.total_cost += pricing.cost(usage);
+        Some(self.total_cost)
+    }
+
+    /// What `model` accepts: the configured capabilities, or those detected
+    /// from its name.
+    pub fn model_capabilities(&self, model: &str) -> crate::llm::ModelCapabilities {
+        self.config
+            .model_capabilities
+            .get(model)
+            .copied()
+            .unwrap_or_else(|| crate::llm::ModelCapabilities::detect(model))
+    }
+
+    /// Sampling options for `model`: `config.sampling`, then the
+    /// `config.model_params` entries for the task type and for `model`.
+    pub fn model_params(&self, model: &str) -> crate::types::ModelParams {
+        let sampling = &self.config.sampling;
+        let mut params = crate::types::ModelParams {
+            temperature: sampling.temperature,
+            top_p: None,
+            max_tokens: sampling.max_tokens,
+            stop: sampling.stop.clone(),
+            reasoning_effort: None,
+        };
+        for key in [self.task_type.as_str(), model] {
+            if let Some(overrides) = self.config.model_params.get(key) {
+                params.merge(overrides);
+            }
+        }
+        params
+    }
+
+    /// Refresh `tool_instructions`, `grammar`, `stop` and `status_line` from
+    /// the prompter, the sampling config and the usable tools.
+    pub fn prepare_prompt(&mut self, tools: &crate::tools::ToolRegistry) {
+        let schemas: Vec<_> = tools
+            .schemas()
+            .into_iter()
+            .filter(|s| !self.blacklisted_tools.contains(&s.name))
+            .collect();
+        let prompter = self.planning_prompter.instructions(&schemas);
+        let fallback = self.config.text_tool_calls.and_then(|f| f.instructions(&schemas));
+        self.tool_instructions = match (prompter, fallback) {
+            (Some(a), Some(b)) => Some(format!("{}\n\n{}", a, b)),
+            (a, b) => a.or(b),
+        };
+        if let (Some(_), Some(schema)) = (&self.answer_check, &self.config.output_schema) {
+            // Providers without structured output only see the schema here
+            let typed = format!(
+                "Give your final answer as only a JSON value matching the '{}' schema:\n{}",
+                schema.name, schema.schema
+            );
+            self.tool_instructions = Some(match self.tool_instructions.take() {
+                Some(extra) => format!("{}\n\n{}", extra, typed),
+                None => typed,
+            });
+        }
+        self.grammar = self.config.sampling.grammar.as_ref().map(|g| g.resolve(&schemas));
+        let mut stop = self.model_params(self.current_model.as_deref().unwrap_or_default()).stop;
+        for s in self.planning_prompter.stop_sequences() {
+            if !stop.contains(&s) {
+                stop.push(s);
+            }
+        }
+        self.stop = stop;
+        self.status_line = self.config.status_line.then(|| self.status_text(schemas.len()));
+    }
+
+    /// "Status: step 7/15, 12,400/50,000 tokens used, 3 tools available."
+    fn status_text(&self, tool_count: usize) -> String {
+        let used = group_digits(self.total_usage.total_tokens);
+        let tokens = match self.budget.and_then(|b| b.max_total_tokens) {
+            Some(max) => format!("{}/{} tokens used", used, group_digits(max)),
+            None => format!("{} tokens used", used),
+        };
+        format!(
+            "Status: step {}/{}, {}, {} tool{} available.",
+            self.step,
+            self.config.max_steps,
+            tokens,
+            tool_count,
+            if tool_count == 1 { "" } else { "s" },
+        )
+    }
+
     /// Builds the messages array to send to the LLM.
     /// Groups parallel tool calls into single assistant messages to comply with LLM protocols.
     pub fn build_messages(&self) -> Vec<serde_json::Value> {
         let mut messages = Vec::new();
 
-        // System message — render template if present, otherwise use system_prompt
-        let system_text = if let Some(ref tpl) = self.prompt_template {
+        // System message — render the template for the locale or the default
+        // template if present, otherwise use system_prompt
+        let template = crate::locale::select_template(&self.localized_prompts, &self.config.locale)
+            .or(self.prompt_template.as_ref());
+        let system_text = if let Some(tpl) = template {
             match tpl.render() {
                 Ok(rendered) => rendered,
                 Err(e) => {
@@ -247,6 +719,12 @@ impl AgentMemory {
             self.system_prompt.clone()
         };
 
+        let system_text = match &self.tool_instructions {
+            Some(extra) if system_text.is_empty() => extra.clone(),
+            Some(extra) => format!("{}\n\n{}", system_text, extra),
+            None => system_text,
+        };
+
         if !system_text.is_empty() {
             messages.push(serde_json::json!({
                 "role": "system",
@@ -254,15 +732,14 @@ impl AgentMemory {
             }));
         }
 
-        // Current task (the initial user request)
-        messages.push(serde_json::json!({
-            "role": "user",
-            "content": &self.task
-        }));
+        // Current task (the initial user request) and the pinned constraints
+        let task_message = self.task_message();
+        messages.push(task_message.clone());
 
         // History grouped by step
+        let markers = self.markers();
         let mut steps: Vec<Vec<&HistoryEntry>> = Vec::new();
-        for entry in &self.history {
+        for entry in self.history.iter().filter(|h| h.tool.name != PRUNED_BRANCH) {
             if let Some(last_step) = steps.last_mut() {
                 if last_step[0].step == entry.step {
                     last_step.push(entry);
@@ -273,47 +750,166 @@ impl AgentMemory {
         }
 
         for step_entries in steps {
-            let mut oai_tool_calls = Vec::new();
-            let mut tool_results = Vec::new();
-
-            for entry in step_entries {
-                let tool_id = entry
-                    .tool
-                    .id
-                    .clone()
-                    .unwrap_or_else(|| "legacy".to_string());
-
-                oai_tool_calls.push(serde_json::json!({
-                    "id": tool_id,
-                    "type": "function",
-                    "function": {
-                        "name": entry.tool.name,
-                        "arguments": serde_json::to_string(&entry.tool.args).unwrap_or_default()
-                    }
-                }));
-
-                tool_results.push(serde_json::json!({
-                    "role": "tool",
-                    "tool_call_id": tool_id,
-                    "name": entry.tool.name,
-                    "content": entry.observation
-                }));
-            }
+            messages.extend(self.planning_prompter.render_step(&step_entries, &markers));
+        }
+
+        // Approaches given up on, including notes older checkpoints kept in history
+        let pruned: Vec<&str> = self
+            .history
+            .iter()
+            .filter(|h| h.tool.name == PRUNED_BRANCH)
+            .map(|h| &*h.observation.content)
+            .chain(self.pruned_notes.iter().map(String::as_str))
+            .collect();
+        if !pruned.is_empty() {
+            messages.push(serde_json::json!({
+                "role": "user",
+                "content": pruned.join("\n")
+            }));
+        }
+
+        // Why the previous final answer was rejected
+        if let Some(ref feedback) = self.answer_feedback {
+            messages.push(serde_json::json!({
+                "role": "user",
+                "content": feedback
+            }));
+        }
 
-            // 1. Assistant message with ALL tool calls from this step
+        // One-shot guidance from an operator or supervising agent
+        if !self.hints.is_empty() {
+            messages.push(serde_json::json!({
+                "role": "user",
+                "content": self.hints.join("\n")
+            }));
+        }
+
+        // Progress against the limits; before a continuation, which must come last
+        if let Some(ref status) = self.status_line {
+            messages.push(serde_json::json!({
+                "role": "user",
+                "content": status
+            }));
+        }
+
+        // An answer cut off at the output token limit, to be continued
+        if let Some(ref partial) = self.partial_answer {
             messages.push(serde_json::json!({
                 "role": "assistant",
-                "content": null,
-                "tool_calls": oai_tool_calls
+                "content": partial
+            }));
+            messages.push(serde_json::json!({
+                "role": "user",
+                "content": "Your answer was cut off at the output token limit. Continue it exactly \
+                            where it stopped, without repeating any of it."
             }));
+        }
 
-            // 2. Individual tool messages for each result
-            messages.extend(tool_results);
+        // Apply memory strategy to trim/transform messages. The task survives
+        // any trimming: if it was dropped, it goes back after the system messages.
+        let mut messages = self.memory_strategy.apply(messages);
+        if !messages.contains(&task_message) {
+            let at = messages.iter().take_while(|m| m["role"] == "system").count();
+            messages.insert(at, task_message);
         }
+        messages
+    }
+
+    /// The task verbatim, followed by the pinned constraints.
+    fn task_message(&self) -> serde_json::Value {
+        let content = if self.pinned.is_empty() {
+            self.task.clone()
+        } else {
+            let constraints: Vec<String> = self.pinned.iter().map(|c| format!("- {}", c)).collect();
+            format!("{}\n\nConstraints (these always apply):\n{}", self.task, constraints.join("\n"))
+        };
+        serde_json::json!({
+            "role": "user",
+            "content": content
+        })
+    }
 
-        // Apply memory strategy to trim/transform messages
-        
+    /// Rough size of the next prompt in tokens (about 4 characters per token).
+    pub fn estimated_prompt_tokens(&self) -> usize {
+        let chars: usize = self
+            .build_messages()
+            .iter()
+            .map(|m| m.to_string().chars().count())
+            .sum();
+        chars.div_ceil(4)
+    }
 
-        self.memory_strategy.apply(messages)
+    /// Renders the task, history and outcome as Markdown for PRs and
+    /// incident tickets, one collapsible `<details>` section per step.
+    /// Arguments are printed with sorted keys, so the same history always
+    /// produces the same text.
+    pub fn history_markdown(&self) -> String {
+        use crate::trace::code_block;
+
+        let markers = self.markers();
+        let mut out = format!("# Agent history\n\n## Task\n\n{}\n", self.task.trim_end());
+        for group in self.history.chunk_by(|a, b| a.step == b.step) {
+            let tools: Vec<&str> = group.iter().map(|h| h.tool.name.as_str()).collect();
+            let failed = group.iter().filter(|h| !h.is_success()).count();
+            let status = if failed == 0 { "ok".to_string() } else { format!("{} failed", failed) };
+            out.push_str(&format!(
+                "\n<details>\n<summary>Step {} · {} · {}</summary>\n",
+                group[0].step,
+                tools.join(", "),
+                status
+            ));
+            for h in group {
+                let outcome = if h.is_success() { "success" } else { "failed" };
+                out.push_str(&format!("\n#### `{}` — {}\n\n", h.tool.name, outcome));
+                if let Some(text) = h.assistant_text.as_deref().filter(|t| !t.trim().is_empty()) {
+                    for line in text.trim_end().lines() {
+                        out.push_str(&format!("> {}\n", line));
+                    }
+                    out.push('\n');
+                }
+                if !h.tool.args.is_empty() {
+                    let args: std::collections::BTreeMap<_, _> = h.tool.args.iter().collect();
+                    out.push_str(&code_block("json", &serde_json::to_string_pretty(&args).unwrap_or_default()));
+                    out.push('\n');
+                }
+                out.push_str(&code_block("text", &h.observation.render(&markers)));
+            }
+            out.push_str("\n</details>\n");
+        }
+        if let Some(ref answer) = self.final_answer {
+            out.push_str(&format!("\n## Final answer\n\n{}\n", answer.trim_end()));
+        }
+        if let Some(ref error) = self.error {
+            out.push_str(&format!("\n## Error\n\n{}", code_block("text", error)));
+        }
+        out
+    }
+
+    /// Why history should be compressed now, if any configured trigger fires.
+    pub fn reflection_reason(&self) -> Option<String> {
+        let interval = self.config.reflect_every_n_steps;
+        if interval > 0 && self.step.is_multiple_of(interval) {
+            return Some(format!("step={} interval={}", self.step, interval));
+        }
+        self.config.reflection_triggers.iter().find_map(|trigger| match *trigger {
+            ReflectionTrigger::EveryNSteps(n) if n > 0 && self.step.is_multiple_of(n) => {
+                Some(format!("step={} interval={}", self.step, n))
+            }
+            ReflectionTrigger::PromptTokensAbove(limit) => {
+                let tokens = self.estimated_prompt_tokens();
+                (tokens > limit).then(|| format!("prompt_tokens~{} limit={}", tokens, limit))
+            }
+            ReflectionTrigger::HistoryEntriesAbove(limit) if self.history.len() > limit => {
+                Some(format!("history_entries={} limit={}", self.history.len(), limit))
+            }
+            ReflectionTrigger::ConsecutiveFailures(n)
+                if n > 0
+                    && self.history.len() >= n
+                    && self.history.iter().rev().take(n).all(|h| !h.is_success()) =>
+            {
+                Some(format!("consecutive_failures={}", n))
+            }
+            _ => None,
+        })
     }
 }
@@ -0,0 +1,280 @@
+//! Output Moderation — check final answers before they reach users.
+//!
+//! A [`ModerationHook`] sees every final answer (text and structured) after
+//! the length and citation checks. It can allow the answer, redact or
+//! rewrite it, or block it. A blocked answer produces the `AnswerBlocked`
+//! event, and what happens next depends on [`OnAnswerBlocked`].
+//!
+//! With `stream(true)` the hook also sees the text streamed so far. Once it
+//! objects, no further `LlmToken` output is sent for that call.
+//!
+//! ```rust,ignore
+//! let moderation = Moderation::new(Arc::new(
+//!     RuleModerator::new().block_term("internal only").redact_term("ACME-SECRET"),
+//! ))
+//! .on_blocked(OnAnswerBlocked::Revise);
+//!
+//! let agent = AgentBuilder::new("task").openai("").moderation(moderation).build()?;
+//! ```
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Hook interface
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Decision about one answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModerationVerdict {
+    Allow,
+    /// Accept this text instead, with sensitive parts removed
+    Redact(String),
+    /// Accept this text instead
+    Rewrite(String),
+    /// Reject the answer
+    Block { reason: String },
+}
+
+#[async_trait]
+pub trait ModerationHook: Send + Sync {
+    /// Judge a final answer. An `Err` is treated as a block (fail closed).
+    async fn moderate(&self, answer: &str) -> Result<ModerationVerdict, String>;
+
+    /// Judge streamed text so far. Return `false` to stop forwarding tokens.
+    fn check_stream(&self, _partial: &str) -> bool {
+        true
+    }
+}
+
+/// What to do when an answer is blocked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum OnAnswerBlocked {
+    /// Fail the run (`Planning --AnswerBlocked--> Error`)
+    #[default]
+    Fail,
+    /// Send the answer back with the reason. Uses the `answer_revisions`
+    /// limit (`Planning --AnswerBlocked--> Planning`).
+    Revise,
+    /// Accept this fixed message as the answer instead
+    Replace(String),
+}
+
+/// A moderation hook and its settings. Set with `AgentBuilder::moderation`.
+#[derive(Clone)]
+pub struct Moderation {
+    pub hook: Arc<dyn ModerationHook>,
+    pub on_blocked: OnAnswerBlocked,
+    /// Also check streamed tokens
+    pub stream: bool,
+}
+
+impl std::fmt::Debug for Moderation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Moderation")
+            .field("on_blocked", &self.on_blocked)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl Moderation {
+    pub fn new(hook: Arc<dyn ModerationHook>) -> Self {
+        Self {
+            hook,
+            on_blocked: OnAnswerBlocked::Fail,
+            stream: false,
+        }
+    }
+
+    pub fn on_blocked(mut self, action: OnAnswerBlocked) -> Self {
+        self.on_blocked = action;
+        self
+    }
+
+    /// Check streamed tokens with `ModerationHook::check_stream`.
+    pub fn stream(mut self, enabled: bool) -> Self {
+        self.stream = enabled;
+        self
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Built-in hooks
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Replaces each redacted term.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Case-insensitive term lists: blocked terms reject the answer, redacted
+/// terms are replaced with [`REDACTED`].
+#[derive(Debug, Clone, Default)]
+pub struct RuleModerator {
+    block_terms: Vec<String>,
+    redact_terms: Vec<String>,
+}
+
+impl RuleModerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn block_term(mut self, term: impl Into<String>) -> Self {
+        self.block_terms.push(term.into().to_lowercase());
+        self
+    }
+
+    pub fn redact_term(mut self, term: impl Into<String>) -> Self {
+        self.redact_terms.push(term.into().to_lowercase());
+        self
+    }
+
+    fn blocked_term(&self, text: &str) -> Option<&str> {
+        let lower = text.to_lowercase();
+        self.block_terms
+            .iter()
+            .find(|t| lower.contains(t.as_str()))
+            .map(|t| t.as_str())
+    }
+}
+
+/// Replace every case-insensitive occurrence of `term` in `text`.
+fn redact(text: &str, term: &str) -> String {
+    if term.is_empty() {
+        return text.to_string();
+    }
+    let lower = text.to_lowercase();
+    // Lowercasing can change byte lengths outside ASCII; only redact when offsets line up
+    if lower.len() != text.len() {
+        return text.replace(term, REDACTED);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, _) in lower.match_indices(term) {
+        out.push_str(&text[last..start]);
+        out.push_str(REDACTED);
+        last = start + term.len();
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+#[async_trait]
+impl ModerationHook for RuleModerator {
+    async fn moderate(&self, answer: &str) -> Result<ModerationVerdict, String> {
+        if let Some(term) = self.blocked_term(answer) {
+            return Ok(ModerationVerdict::Block {
+                reason: format!("contains blocked term '{}'", term),
+            });
+        }
+        let redacted = self
+            .redact_terms
+            .iter()
+            .fold(answer.to_string(), |text, term| redact(&text, term));
+        if redacted != answer {
+            Ok(ModerationVerdict::Redact(redacted))
+        } else {
+            Ok(ModerationVerdict::Allow)
+        }
+    }
+
+    fn check_stream(&self, partial: &str) -> bool {
+        self.blocked_term(partial).is_none()
+    }
+}
+
+/// OpenAI's moderation endpoint. Blocks answers it flags.
+pub struct OpenAiModerator {
+    client: reqwest::Client,
+    api_key: String,
+    api_base: String,
+    model: String,
+}
+
+impl OpenAiModerator {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key: api_key.into(),
+            api_base: "https://api.openai.com/v1".to_string(),
+            model: "omni-moderation-latest".to_string(),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        let key =
+            std::env::var("OPENAI_API_KEY").map_err(|_| "OPENAI_API_KEY not set".to_string())?;
+        Ok(Self::new(key))
+    }
+
+    pub fn with_base_url(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = api_base.into();
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+}
+
+#[async_trait]
+impl ModerationHook for OpenAiModerator {
+    async fn moderate(&self, answer: &str) -> Result<ModerationVerdict, String> {
+        let resp = self
+            .client
+            .post(format!("{}/moderations", self.api_base))
+            .bearer_auth(&self.api_key)
+            .json(&json!({ "model": self.model, "input": answer }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("moderation API returned {}", resp.status()));
+        }
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        let result = &body["results"][0];
+        if !result["flagged"].as_bool().unwrap_or(false) {
+            return Ok(ModerationVerdict::Allow);
+        }
+        let categories: Vec<&str> = result["categories"]
+            .as_object()
+            .map(|c| {
+                c.iter()
+                    .filter(|(_, v)| v.as_bool() == Some(true))
+                    .map(|(k, _)| k.as_str())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(ModerationVerdict::Block {
+            reason: format!("flagged by {}: {}", self.model, categories.join(", ")),
+        })
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rule_moderator_block_and_redact() {
+        let m = RuleModerator::new().block_term("Launch Codes").redact_term("acme-secret");
+
+        assert_eq!(m.moderate("All clear.").await.unwrap(), ModerationVerdict::Allow);
+        assert_eq!(
+            m.moderate("Key: ACME-SECRET-1 and acme-secret-2").await.unwrap(),
+            ModerationVerdict::Redact("Key: [REDACTED]-1 and [REDACTED]-2".into())
+        );
+        assert!(matches!(
+            m.moderate("here are the launch codes").await.unwrap(),
+            ModerationVerdict::Block { .. }
+        ));
+        assert!(m.check_stream("here are the"));
+        assert!(!m.check_stream("here are the LAUNCH CODES"));
+    }
+}
@@ -0,0 +1,344 @@
+//! Live terminal monitor for agent runs (feature `tui`).
+//!
+//! Consumes a stream of [`AgentOutput`] — typically `engine.run_streaming()`,
+//! or NDJSON piped into the `agentsm monitor` binary — and renders the
+//! current state, streaming tokens, tool calls, token budget and a trace
+//! tail. Press `q` or `Esc` to quit.
+
+use crate::budget::TokenUsage;
+use crate::types::{AgentOutput, State};
+use futures::{Stream, StreamExt};
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph, Wrap};
+use ratatui::Frame;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// MonitorState
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// One tool call as shown in the monitor.
+#[derive(Debug, Clone)]
+pub struct ToolCallLine {
+    pub name: String,
+    pub args: String,
+    pub result: Option<String>,
+    pub success: Option<bool>,
+}
+
+/// Everything the monitor renders, built up from the output stream.
+#[derive(Debug, Clone)]
+pub struct MonitorState {
+    pub current_state: Option<State>,
+    /// Tokens of the LLM call currently in flight.
+    pub tokens: String,
+    pub tool_calls: Vec<ToolCallLine>,
+    pub trace_tail: VecDeque<String>,
+    pub usage: TokenUsage,
+    pub budget: Option<u32>,
+    pub final_answer: Option<String>,
+    pub error: Option<String>,
+    trace_capacity: usize,
+}
+
+impl MonitorState {
+    pub fn new(budget: Option<u32>) -> Self {
+        Self {
+            current_state: None,
+            tokens: String::new(),
+            tool_calls: Vec::new(),
+            trace_tail: VecDeque::new(),
+            usage: TokenUsage::default(),
+            budget,
+            final_answer: None,
+            error: None,
+            trace_capacity: 200,
+        }
+    }
+
+    /// Fold one output event into the state.
+    pub fn apply(&mut self, output: &AgentOutput) {
+        match output {
+            AgentOutput::StateStarted(state) => {
+                if *state == State::planning() {
+                    self.tokens.clear();
+                }
+                self.push_trace(format!("→ {}", state));
+                self.current_state = Some(state.clone());
+            }
+            AgentOutput::LlmToken(token) => self.tokens.push_str(token),
+            AgentOutput::ToolCallDelta { .. } => {}
+            AgentOutput::ToolCallStarted { name, args } => {
+                self.push_trace(format!("tool {} started", name));
+                self.tool_calls.push(ToolCallLine {
+                    name: name.clone(),
+                    args: serde_json::to_string(args).unwrap_or_default(),
+                    result: None,
+                    success: None,
+                });
+            }
+            AgentOutput::ToolCallFinished { name, result, success } => {
+                self.push_trace(format!(
+                    "tool {} {}",
+                    name,
+                    if *success { "succeeded" } else { "failed" }
+                ));
+                if let Some(call) = self
+                    .tool_calls
+                    .iter_mut()
+                    .rev()
+                    .find(|c| c.name == *name && c.result.is_none())
+                {
+                    call.result = Some(result.clone());
+                    call.success = Some(*success);
+                }
+            }
+            AgentOutput::Reasoning(_) => {}
+            AgentOutput::Usage { cumulative, .. } => self.usage = *cumulative,
+            AgentOutput::Commentary(text) => self.push_trace(format!("commentary: {}", text)),
+            AgentOutput::Action(msg) => self.push_trace(msg.clone()),
+            AgentOutput::AnswerTruncated(_) => self.push_trace("answer truncated".to_string()),
+            AgentOutput::FinalAnswer(answer) => {
+                self.push_trace("final answer".to_string());
+                self.final_answer = Some(answer.clone());
+            }
+            AgentOutput::Error(err) => {
+                self.push_trace(format!("error: {}", err));
+                self.error = Some(err.clone());
+            }
+        }
+    }
+
+    /// Fraction of the token budget consumed, if a budget is known.
+    pub fn budget_ratio(&self) -> Option<f64> {
+        self.budget
+            .filter(|b| *b > 0)
+            .map(|b| (self.usage.total_tokens as f64 / b as f64).min(1.0))
+    }
+
+    fn push_trace(&mut self, line: String) {
+        if self.trace_tail.len() == self.trace_capacity {
+            self.trace_tail.pop_front();
+        }
+        self.trace_tail.push_back(line);
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Rendering
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Draw the monitor into a frame.
+pub fn render(frame: &mut Frame, state: &MonitorState) {
+    let [header, gauge, body, trace] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Min(6),
+        Constraint::Length(10),
+    ])
+    .areas(frame.area());
+
+    let status = match (&state.final_answer, &state.error) {
+        (Some(_), _) => ("finished", Color::Green),
+        (_, Some(_)) => ("failed", Color::Red),
+        _ => ("running", Color::Yellow),
+    };
+    let current = state
+        .current_state
+        .as_ref()
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    frame.render_widget(
+        Paragraph::new(Line::from(format!(
+            "state: {}   status: {}   (q to quit)",
+            current, status.0
+        )))
+        .style(Style::default().fg(status.1))
+        .block(Block::bordered().title(" Agent-B monitor ")),
+        header,
+    );
+
+    let (ratio, label) = match state.budget_ratio() {
+        Some(r) => (
+            r,
+            format!("{} / {} tokens", state.usage.total_tokens, state.budget.unwrap_or(0)),
+        ),
+        None => (0.0, format!("{} tokens (no budget)", state.usage.total_tokens)),
+    };
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::bordered().title(" Budget "))
+            .gauge_style(Style::default().fg(if ratio > 0.8 { Color::Red } else { Color::Cyan }))
+            .ratio(ratio)
+            .label(label),
+        gauge,
+    );
+
+    let [tokens_area, tools_area] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body);
+
+    let text = state
+        .final_answer
+        .as_deref()
+        .or(state.error.as_deref())
+        .unwrap_or(&state.tokens);
+    // Keep the tail of long outputs visible.
+    let visible_lines = tokens_area.height.saturating_sub(2) as usize;
+    let lines: Vec<&str> = text.lines().collect();
+    let start = lines.len().saturating_sub(visible_lines);
+    frame.render_widget(
+        Paragraph::new(lines[start..].join("\n"))
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(" LLM output ")),
+        tokens_area,
+    );
+
+    let tools: Vec<ListItem> = state
+        .tool_calls
+        .iter()
+        .rev()
+        .map(|c| {
+            let (marker, color) = match c.success {
+                Some(true) => ("✓", Color::Green),
+                Some(false) => ("✗", Color::Red),
+                None => ("…", Color::Yellow),
+            };
+            ListItem::new(format!("{} {}({})", marker, c.name, c.args))
+                .style(Style::default().fg(color))
+        })
+        .collect();
+    frame.render_widget(
+        List::new(tools).block(Block::bordered().title(" Tool calls ")),
+        tools_area,
+    );
+
+    let trace_lines = trace.height.saturating_sub(2) as usize;
+    let skip = state.trace_tail.len().saturating_sub(trace_lines);
+    let items: Vec<ListItem> = state
+        .trace_tail
+        .iter()
+        .skip(skip)
+        .map(|l| ListItem::new(l.as_str()))
+        .collect();
+    frame.render_widget(
+        List::new(items).block(Block::bordered().title(" Trace ")),
+        trace,
+    );
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Event loop
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Run the monitor until the user quits.
+///
+/// The stream is drained as it produces events; once it ends the last frame
+/// stays on screen until `q`/`Esc` is pressed. Returns the final state.
+pub async fn run_monitor<S>(mut outputs: S, budget: Option<u32>) -> std::io::Result<MonitorState>
+where
+    S: Stream<Item = AgentOutput> + Unpin,
+{
+    let mut terminal = ratatui::init();
+    let mut state = MonitorState::new(budget);
+    let mut finished = false;
+    let mut tick = tokio::time::interval(Duration::from_millis(50));
+
+    let result = loop {
+        if let Err(e) = terminal.draw(|f| render(f, &state)) {
+            break Err(e);
+        }
+
+        tokio::select! {
+            item = outputs.next(), if !finished => match item {
+                Some(output) => state.apply(&output),
+                None => finished = true,
+            },
+            _ = tick.tick() => {}
+        }
+
+        match poll_quit() {
+            Ok(true) => break Ok(()),
+            Ok(false) => {}
+            Err(e) => break Err(e),
+        }
+    };
+
+    ratatui::restore();
+    result.map(|_| state)
+}
+
+fn poll_quit() -> std::io::Result<bool> {
+    while event::poll(Duration::ZERO)? {
+        if let TermEvent::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press
+                && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+            {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_apply_tracks_state_and_tokens() {
+        let mut state = MonitorState::new(None);
+        state.apply(&AgentOutput::StateStarted(State::planning()));
+        state.apply(&AgentOutput::LlmToken("Hel".into()));
+        state.apply(&AgentOutput::LlmToken("lo".into()));
+        assert_eq!(state.current_state, Some(State::planning()));
+        assert_eq!(state.tokens, "Hello");
+
+        // A new planning cycle clears the token buffer
+        state.apply(&AgentOutput::StateStarted(State::planning()));
+        assert!(state.tokens.is_empty());
+    }
+
+    #[test]
+    fn test_apply_matches_tool_results() {
+        let mut state = MonitorState::new(None);
+        state.apply(&AgentOutput::ToolCallStarted {
+            name: "search".into(),
+            args: HashMap::new(),
+        });
+        state.apply(&AgentOutput::ToolCallFinished {
+            name: "search".into(),
+            result: "found".into(),
+            success: true,
+        });
+        assert_eq!(state.tool_calls.len(), 1);
+        assert_eq!(state.tool_calls[0].success, Some(true));
+        assert_eq!(state.tool_calls[0].result.as_deref(), Some("found"));
+    }
+
+    #[test]
+    fn test_trace_tail_is_bounded() {
+        let mut state = MonitorState::new(None);
+        for i in 0..500 {
+            state.apply(&AgentOutput::Action(format!("a{}", i)));
+        }
+        assert_eq!(state.trace_tail.len(), 200);
+        assert_eq!(state.trace_tail.back().map(String::as_str), Some("a499"));
+    }
+
+    #[test]
+    fn test_budget_ratio() {
+        let mut state = MonitorState::new(Some(1000));
+        state.usage = TokenUsage::new(200, 50);
+        assert_eq!(state.budget_ratio(), Some(0.25));
+        assert_eq!(MonitorState::new(None).budget_ratio(), None);
+    }
+}
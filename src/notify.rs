@@ -0,0 +1,276 @@
+//! Notifications — tell people when an agent needs them or has finished.
+//!
+//! A [`Notifier`] receives approval requests (when a run enters
+//! `WaitingForHuman`), run completions and run failures. Each notification
+//! carries a deep link back to the session when a link template is set.
+//!
+//! ```rust,ignore
+//! let agent = AgentBuilder::new("task")
+//!     .openai("")
+//!     .notifier(Arc::new(SlackNotifier::new(webhook_url)))
+//!     .session_link("https://console.example.com/sessions/{session_id}")
+//!     .build()?;
+//! ```
+//!
+//! A failed delivery is logged as `NOTIFY_FAILED` and never fails the run.
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::human::HumanApprovalRequest;
+use crate::memory::AgentMemory;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Notifier interface
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// What happened.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// A tool call is waiting for a human decision
+    ApprovalRequested { request: HumanApprovalRequest },
+    RunCompleted { answer: String },
+    RunFailed { error: String },
+}
+
+/// One notification: the event plus the session it belongs to.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    #[serde(flatten)]
+    pub kind: NotificationKind,
+    pub session_id: String,
+    pub task: String,
+    pub step: usize,
+    /// Deep link to the session, from `Notifications::session_link`
+    pub link: Option<String>,
+}
+
+impl Notification {
+    /// One line of plain text describing the notification.
+    pub fn summary(&self) -> String {
+        match &self.kind {
+            NotificationKind::ApprovalRequested { request } => format!(
+                "Approval needed: `{}` ({:?} risk) — {}",
+                request.tool_name, request.risk_level, request.reason
+            ),
+            NotificationKind::RunCompleted { answer } => {
+                format!("Run completed after {} steps: {}", self.step, preview(answer))
+            }
+            NotificationKind::RunFailed { error } => {
+                format!("Run failed after {} steps: {}", self.step, preview(error))
+            }
+        }
+    }
+}
+
+/// First 200 characters of `text`, on one line.
+fn preview(text: &str) -> String {
+    let line = text.replace('\n', " ");
+    match line.char_indices().nth(200) {
+        Some((i, _)) => format!("{}…", &line[..i]),
+        None => line,
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), String>;
+}
+
+/// Notifiers and settings. Set with `AgentBuilder::notifier`.
+#[derive(Clone, Default)]
+pub struct Notifications {
+    pub notifiers: Vec<Arc<dyn Notifier>>,
+    /// Deep link template; `{session_id}` is replaced with the session ID
+    pub session_link: Option<String>,
+}
+
+impl std::fmt::Debug for Notifications {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Notifications")
+            .field("notifiers", &self.notifiers.len())
+            .field("session_link", &self.session_link)
+            .finish()
+    }
+}
+
+impl Notifications {
+    fn link(&self, session_id: &str) -> Option<String> {
+        self.session_link
+            .as_ref()
+            .map(|t| t.replace("{session_id}", session_id))
+    }
+}
+
+/// Send `kind` to every notifier configured on `memory`. Failures are logged.
+pub(crate) async fn send(memory: &mut AgentMemory, state: &str, kind: NotificationKind) {
+    let notifications = match &memory.notifications {
+        Some(n) if !n.notifiers.is_empty() => n.clone(),
+        _ => return,
+    };
+    let notification = Notification {
+        kind,
+        session_id: memory.session_id.clone(),
+        task: memory.task.clone(),
+        step: memory.step,
+        link: notifications.link(&memory.session_id),
+    };
+    for notifier in &notifications.notifiers {
+        if let Err(e) = notifier.notify(&notification).await {
+            tracing::warn!(error = %e, "Notification failed");
+            memory.log(state, "NOTIFY_FAILED", &e);
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Built-in notifiers
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Posts to a Slack incoming webhook.
+pub struct SlackNotifier {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl SlackNotifier {
+    pub fn new(webhook_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            webhook_url: webhook_url.into(),
+        }
+    }
+
+    fn payload(notification: &Notification) -> serde_json::Value {
+        let mut text = format!("*{}*\n{}", notification.task, notification.summary());
+        if let Some(link) = &notification.link {
+            text.push_str(&format!("\n<{}|Open session {}>", link, notification.session_id));
+        }
+        json!({ "text": text })
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), String> {
+        let resp = self
+            .client
+            .post(&self.webhook_url)
+            .json(&Self::payload(notification))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("Slack webhook returned {}", resp.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Posts the [`Notification`] as JSON to any HTTP endpoint.
+pub struct HttpNotifier {
+    client: reqwest::Client,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpNotifier {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Send an extra header with every request (e.g. an auth token).
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+#[async_trait]
+impl Notifier for HttpNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), String> {
+        let mut req = self.client.post(&self.url).json(notification);
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("notification endpoint returned {}", resp.status()));
+        }
+        Ok(())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::human::RiskLevel;
+    use std::collections::HashMap;
+
+    fn approval() -> Notification {
+        Notification {
+            kind: NotificationKind::ApprovalRequested {
+                request: HumanApprovalRequest {
+                    tool_name: "drop_table".to_string(),
+                    tool_args: HashMap::new(),
+                    risk_level: RiskLevel::Critical,
+                    reason: "Critical risk under the approval policy".to_string(),
+                    batch: Vec::new(),
+                },
+            },
+            session_id: "s1".to_string(),
+            task: "Clean up".to_string(),
+            step: 3,
+            link: Notifications {
+                notifiers: Vec::new(),
+                session_link: Some("https://console.example.com/sessions/{session_id}".to_string()),
+            }
+            .link("s1"),
+        }
+    }
+
+    #[test]
+    fn test_slack_payload_has_summary_and_link() {
+        let text = SlackNotifier::payload(&approval())["text"].as_str().unwrap().to_string();
+        assert_eq!(
+            text,
+            "*Clean up*\nApproval needed: `drop_table` (Critical risk) — Critical risk under the approval policy\n\
+             <https://console.example.com/sessions/s1|Open session s1>"
+        );
+    }
+
+    #[test]
+    fn test_notification_json_shape() {
+        let value = serde_json::to_value(approval()).unwrap();
+        assert_eq!(value["kind"], "approval_requested");
+        assert_eq!(value["request"]["tool_name"], "drop_table");
+        assert_eq!(value["session_id"], "s1");
+        assert_eq!(value["link"], "https://console.example.com/sessions/s1");
+    }
+
+    #[test]
+    fn test_summary_preview_is_one_short_line() {
+        let n = Notification {
+            kind: NotificationKind::RunCompleted { answer: format!("line one\n{}", "x".repeat(300)) },
+            session_id: "s1".to_string(),
+            task: "t".to_string(),
+            step: 2,
+            link: None,
+        };
+        let summary = n.summary();
+        assert!(summary.starts_with("Run completed after 2 steps: line one x"));
+        assert!(summary.ends_with('…'));
+        assert!(!summary.contains('\n'));
+    }
+}
@@ -0,0 +1,157 @@
+//! Observability sampling — record less in high-volume deployments.
+//!
+//! Every run records a full trace and streams every token by default. With
+//! thousands of sessions that is a lot of memory, storage and channel
+//! traffic for runs nobody looks at. [`ObservabilityControl`] lowers both:
+//!
+//! - [`TraceLevel`] picks which trace entries `AgentMemory::log` keeps.
+//!   Errors are kept at every level, so a failed run can still be diagnosed.
+//! - [`TokenEvents`] picks how `LlmToken`, `Reasoning` and `ToolCallDelta`
+//!   outputs are sent while the LLM streams.
+//!
+//! Each engine owns its control, so each session is set on its own. Set the
+//! starting levels on the builder, and change them while the agent runs
+//! through `AgentEngine::observability()` or `AgentHandle::observability()`;
+//! a change applies from the next trace entry or streamed chunk.
+//!
+//! ```rust,ignore
+//! let mut engine = AgentBuilder::new("Triage the ticket")
+//!     .trace_level(TraceLevel::ErrorsOnly)
+//!     .token_events(TokenEvents::Off)
+//!     .build()?;
+//! let handle = engine.handle();
+//!
+//! // An operator opens the session in a dashboard
+//! handle.observability().set_trace_level(TraceLevel::Full);
+//! handle.observability().set_token_events(TokenEvents::All);
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+/// Trace event that records each state transition, as
+/// `"<from> --<event>--> <to>"`.
+pub const TRANSITION: &str = "TRANSITION";
+
+/// Which trace entries a run records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceLevel {
+    /// Every entry
+    #[default]
+    Full,
+    /// `TRANSITION` entries and errors
+    Transitions,
+    /// Errors only
+    ErrorsOnly,
+}
+
+impl TraceLevel {
+    /// Whether an entry for `event` is recorded at this level. Event-sourced
+    /// memory entries are always recorded, since memory is rebuilt from them.
+    pub fn keeps(&self, event: &str) -> bool {
+        if event == crate::event_sourcing::MEMORY_INIT || event == crate::event_sourcing::MEMORY_DELTA {
+            return true;
+        }
+        match self {
+            TraceLevel::Full => true,
+            TraceLevel::Transitions => event == TRANSITION || is_error_event(event),
+            TraceLevel::ErrorsOnly => is_error_event(event),
+        }
+    }
+}
+
+/// Events that report a failure: any name containing `FAIL`, `ERROR` or
+/// `PANIC`, and the ones that end a run early.
+pub fn is_error_event(event: &str) -> bool {
+    ["FAIL", "ERROR", "PANIC"].iter().any(|word| event.contains(word))
+        || matches!(event, "MAX_STEPS" | "BUDGET_EXCEEDED" | "CANCELLED" | "SHUTDOWN" | "SUSPENDED")
+}
+
+/// How token-level stream outputs are sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenEvents {
+    /// One output per streamed chunk
+    #[default]
+    All,
+    /// Up to `n` content chunks joined into one `LlmToken` output, with the
+    /// rest sent when the stream ends. The text is unchanged; there are
+    /// fewer, larger events. `Reasoning` and `ToolCallDelta` are sent as usual.
+    Batched(usize),
+    /// None; the final answer and every other output are still sent
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Settings {
+    trace_level: TraceLevel,
+    token_events: TokenEvents,
+}
+
+/// Shared observability settings of one engine. Clones change the same
+/// settings.
+#[derive(Debug, Clone, Default)]
+pub struct ObservabilityControl {
+    settings: Arc<RwLock<Settings>>,
+}
+
+impl ObservabilityControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trace_level(&self) -> TraceLevel {
+        self.read().trace_level
+    }
+
+    pub fn token_events(&self) -> TokenEvents {
+        self.read().token_events
+    }
+
+    pub fn set_trace_level(&self, level: TraceLevel) {
+        self.write(|s| s.trace_level = level);
+    }
+
+    pub fn set_token_events(&self, events: TokenEvents) {
+        self.write(|s| s.token_events = events);
+    }
+
+    fn read(&self) -> Settings {
+        // Settings are plain values, so a poisoned lock still holds valid ones
+        *self.settings.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self, f: impl FnOnce(&mut Settings)) {
+        f(&mut self.settings.write().unwrap_or_else(|e| e.into_inner()));
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::AgentMemory;
+
+    #[test]
+    fn test_trace_level_filters_log_and_keeps_errors() {
+        let mut memory = AgentMemory::new("task");
+        let control = memory.observability.clone();
+
+        control.set_trace_level(TraceLevel::ErrorsOnly);
+        memory.log("Planning", "STEP_START", "step=1/15");
+        memory.log("Engine", TRANSITION, "Planning --LlmToolCall--> Acting");
+        memory.log("Acting", "TOOL_FAILURE", "search: timeout");
+        memory.log("Memory", crate::event_sourcing::MEMORY_DELTA, "{}");
+
+        control.set_trace_level(TraceLevel::Transitions);
+        memory.log("Planning", "STEP_START", "step=2/15");
+        memory.log("Engine", TRANSITION, "Acting --ToolSuccess--> Observing");
+
+        let events: Vec<_> = memory.trace.entries().iter().map(|e| e.event.as_str()).collect();
+        assert_eq!(events, vec!["TOOL_FAILURE", "MEMORY_DELTA", "TRANSITION"]);
+        assert!(is_error_event("LLM_STREAM_ERROR") && is_error_event("MAX_STEPS"));
+        assert!(!is_error_event("TOOL_SUCCESS"));
+    }
+}
@@ -0,0 +1,461 @@
+//! Orchestra — compose agents into sequential pipelines.
+//!
+//! A [`Pipeline`] runs its stages in order. Each stage is an agent (an
+//! `AgentBuilder` built fresh for every run) whose task is made from the
+//! previous stage's answer — as is, through a closure, or through a
+//! [`PromptTemplate`] with an `{input}` variable. The first stage gets the
+//! pipeline's input.
+//!
+//! The stages can share one token budget: each stage is built with what the
+//! earlier stages left over. Every stage's trace is copied into one combined
+//! trace, with the stage name in front of each state (`extract/Planning`).
+//! What happens when a stage fails is set per stage with [`StageFailure`].
+//!
+//! A [`Branch`] step picks which stage runs next from the previous stage's
+//! output — usually structured JSON from an agent with an `output_schema` —
+//! the way transitions pick the next state inside one agent.
+//!
+//! ```rust,ignore
+//! let result = Pipeline::new("report")
+//!     .stage(Stage::new("extract", AgentBuilder::new("").openai("").tool(/* ... */)))
+//!     .stage(
+//!         Stage::new("summarize", AgentBuilder::new("").openai(""))
+//!             .task_template(PromptTemplate::new("Summarize for an executive:\n\n{input}"))
+//!             .on_failure(StageFailure::Retry(1)),
+//!     )
+//!     .max_tokens(50_000)
+//!     .run("Q3 sales figures in ./data")
+//!     .await?;
+//! println!("{} ({} tokens)", result.output, result.usage.total_tokens);
+//! ```
+
+use crate::budget::{TokenBudget, TokenUsage};
+use crate::builder::AgentBuilder;
+use crate::error::AgentError;
+use crate::prompt::{PromptError, PromptTemplate};
+use crate::trace::{Trace, TraceEntry};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::sync::Arc;
+use thiserror::Error;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Stages
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Builds a stage's task from the previous stage's output.
+pub type TaskMapper = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// What a pipeline does when a stage's agent fails.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum StageFailure {
+    /// Stop the pipeline with `PipelineError::StageFailed` (the default)
+    #[default]
+    Abort,
+    /// Pass the stage's input on to the next stage unchanged
+    Skip,
+    /// Use this text as the stage's output
+    Fallback(String),
+    /// Run the stage up to this many more times, then abort
+    Retry(usize),
+}
+
+#[derive(Clone)]
+enum StageTask {
+    Input,
+    Map(TaskMapper),
+    Template(PromptTemplate),
+}
+
+/// One agent in a pipeline.
+#[derive(Clone)]
+pub struct Stage {
+    name: String,
+    builder: AgentBuilder,
+    task: StageTask,
+    on_failure: StageFailure,
+}
+
+impl Stage {
+    /// A stage running the agent `builder` describes. Its task is the
+    /// previous stage's output, unless `task` or `task_template` say otherwise;
+    /// the task set on the builder is replaced.
+    pub fn new(name: impl Into<String>, builder: AgentBuilder) -> Self {
+        Self {
+            name: name.into(),
+            builder,
+            task: StageTask::Input,
+            on_failure: StageFailure::Abort,
+        }
+    }
+
+    /// Make the task from the previous output with a closure.
+    pub fn task<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.task = StageTask::Map(Arc::new(f));
+        self
+    }
+
+    /// Make the task by rendering `template` with the previous output bound to `{input}`.
+    pub fn task_template(mut self, template: PromptTemplate) -> Self {
+        self.task = StageTask::Template(template);
+        self
+    }
+
+    /// What to do when this stage fails.
+    pub fn on_failure(mut self, policy: StageFailure) -> Self {
+        self.on_failure = policy;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn task_for(&self, input: &str) -> Result<String, PromptError> {
+        match &self.task {
+            StageTask::Input => Ok(input.to_string()),
+            StageTask::Map(f) => Ok(f(input)),
+            StageTask::Template(template) => template.clone().var("input", input).render(),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Branches
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Decides whether a branch arm is taken, from the previous stage's output.
+pub type BranchPredicate = Arc<dyn Fn(&Value) -> bool + Send + Sync>;
+
+/// A pipeline step that runs one of several stages, picked by looking at
+/// the previous stage's output.
+///
+/// The output is parsed as JSON (give the previous agent an `output_schema`
+/// to make it structured); output that is not JSON is seen as a JSON
+/// string. Arms are tried in the order they were added and the first whose
+/// predicate holds runs. With no match, the `otherwise` stage runs, or the
+/// pipeline fails with `PipelineError::NoBranchMatched`.
+#[derive(Clone)]
+pub struct Branch {
+    name: String,
+    arms: Vec<(BranchPredicate, Stage)>,
+    otherwise: Option<Stage>,
+}
+
+impl Branch {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            arms: Vec::new(),
+            otherwise: None,
+        }
+    }
+
+    /// Run `stage` when `predicate` holds for the previous output.
+    pub fn when<F>(mut self, predicate: F, stage: Stage) -> Self
+    where
+        F: Fn(&Value) -> bool + Send + Sync + 'static,
+    {
+        self.arms.push((Arc::new(predicate), stage));
+        self
+    }
+
+    /// Like `when`, with the previous output deserialized into `T`. The arm
+    /// is not taken when the output does not deserialize.
+    pub fn when_as<T, F>(self, predicate: F, stage: Stage) -> Self
+    where
+        T: DeserializeOwned,
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        self.when(
+            move |value| T::deserialize(value).is_ok_and(|typed| predicate(&typed)),
+            stage,
+        )
+    }
+
+    /// Run `stage` when no arm matches.
+    pub fn otherwise(mut self, stage: Stage) -> Self {
+        self.otherwise = Some(stage);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn choose(&self, output: &str) -> Option<&Stage> {
+        let value = serde_json::from_str(output).unwrap_or_else(|_| Value::String(output.to_string()));
+        self.arms
+            .iter()
+            .find(|(predicate, _)| predicate(&value))
+            .map(|(_, stage)| stage)
+            .or(self.otherwise.as_ref())
+    }
+}
+
+#[derive(Clone)]
+enum Step {
+    Stage(Stage),
+    Branch(Branch),
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Results
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// How a stage ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StageOutcome {
+    /// The agent answered
+    Completed,
+    /// The agent failed and `StageFailure::Skip` passed the input on
+    Skipped { error: String },
+    /// The agent failed and `StageFailure::Fallback` supplied the output
+    FellBack { error: String },
+}
+
+/// What one stage did.
+#[derive(Debug, Clone)]
+pub struct StageReport {
+    pub name: String,
+    /// The branch that picked this stage, if it ran as part of one
+    pub branch: Option<String>,
+    /// The task the stage's agent was given
+    pub task: String,
+    /// The output handed to the next stage
+    pub output: String,
+    pub outcome: StageOutcome,
+    /// Runs of the agent, including retries
+    pub attempts: usize,
+    /// Tokens spent over all attempts
+    pub usage: TokenUsage,
+}
+
+/// Outcome of `Pipeline::run`.
+#[derive(Debug, Clone)]
+pub struct PipelineResult {
+    /// The last stage's output
+    pub output: String,
+    pub stages: Vec<StageReport>,
+    /// Tokens spent by all stages
+    pub usage: TokenUsage,
+    /// Every stage's trace, states prefixed with the stage name, plus the
+    /// pipeline's own `Pipeline` entries
+    pub trace: Trace,
+}
+
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    #[error("Stage '{stage}' failed after {attempts} attempt(s): {source}")]
+    StageFailed {
+        stage: String,
+        attempts: usize,
+        source: AgentError,
+    },
+
+    #[error("Token budget exhausted before stage '{stage}'")]
+    BudgetExhausted { stage: String },
+
+    #[error("Stage '{stage}' task template: {source}")]
+    Template { stage: String, source: PromptError },
+
+    #[error("No arm of branch '{branch}' matched and it has no fallback stage")]
+    NoBranchMatched { branch: String },
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Pipeline
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Agents run one after another, each working on the previous one's answer.
+/// A step is a [`Stage`] or a [`Branch`] of stages.
+#[derive(Clone)]
+pub struct Pipeline {
+    name: String,
+    steps: Vec<Step>,
+    budget: Option<TokenBudget>,
+}
+
+impl Pipeline {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            steps: Vec::new(),
+            budget: None,
+        }
+    }
+
+    /// Append a stage.
+    pub fn stage(mut self, stage: Stage) -> Self {
+        self.steps.push(Step::Stage(stage));
+        self
+    }
+
+    /// Append a branch: one of its stages runs, picked from the previous output.
+    pub fn branch(mut self, branch: Branch) -> Self {
+        self.steps.push(Step::Branch(branch));
+        self
+    }
+
+    /// Share a total token limit between all stages.
+    pub fn max_tokens(self, max: u32) -> Self {
+        self.token_budget(TokenBudget::new(max))
+    }
+
+    /// Share a detailed token budget between all stages. It replaces any
+    /// budget set on the stages' builders.
+    pub fn token_budget(mut self, budget: TokenBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Run every step in order, starting from `input`.
+    pub async fn run(&self, input: impl Into<String>) -> Result<PipelineResult, PipelineError> {
+        let mut output = input.into();
+        let mut usage = TokenUsage::default();
+        let mut trace = Trace::new();
+        let mut stages = Vec::with_capacity(self.steps.len());
+
+        for (index, step) in self.steps.iter().enumerate() {
+            let report = match step {
+                Step::Stage(stage) => {
+                    self.run_stage(index, stage, None, &output, &mut usage, &mut trace).await?
+                }
+                Step::Branch(branch) => {
+                    let Some(stage) = branch.choose(&output) else {
+                        record(&mut trace, index, "BRANCH_UNMATCHED", &format!("branch='{}'", branch.name));
+                        return Err(PipelineError::NoBranchMatched { branch: branch.name.clone() });
+                    };
+                    record(
+                        &mut trace,
+                        index,
+                        "BRANCH_TAKEN",
+                        &format!("branch='{}' stage='{}'", branch.name, stage.name),
+                    );
+                    self.run_stage(index, stage, Some(&branch.name), &output, &mut usage, &mut trace)
+                        .await?
+                }
+            };
+            output = report.output.clone();
+            stages.push(report);
+        }
+
+        Ok(PipelineResult { output, stages, usage, trace })
+    }
+
+    async fn run_stage(
+        &self,
+        index:  usize,
+        stage:  &Stage,
+        branch: Option<&str>,
+        input:  &str,
+        usage:  &mut TokenUsage,
+        trace:  &mut Trace,
+    ) -> Result<StageReport, PipelineError> {
+        let task = stage.task_for(input).map_err(|source| PipelineError::Template {
+            stage: stage.name.clone(),
+            source,
+        })?;
+        let remaining = self.budget.map(|b| b.remaining(*usage));
+        if remaining.is_some_and(|b| exhausted(&b)) {
+            record(trace, index, "BUDGET_EXHAUSTED", &stage.name);
+            return Err(PipelineError::BudgetExhausted { stage: stage.name.clone() });
+        }
+        record(trace, index, "STAGE_START", &format!("stage='{}' pipeline='{}'", stage.name, self.name));
+
+        let max_attempts = match stage.on_failure {
+            StageFailure::Retry(n) => n + 1,
+            _ => 1,
+        };
+        let mut stage_usage = TokenUsage::default();
+        let mut attempts = 0;
+        let result = loop {
+            attempts += 1;
+            let mut builder = stage.builder.clone()
+                .task(task.clone())
+                // Stages and attempts must not share checkpoints
+                .session_id(uuid::Uuid::new_v4().to_string());
+            if let Some(remaining) = self.budget.map(|b| b.remaining(*usage)) {
+                builder = builder.token_budget(remaining);
+            }
+            let result = match builder.build() {
+                Ok(mut engine) => {
+                    let result = engine.run().await;
+                    usage.add(engine.memory.total_usage);
+                    stage_usage.add(engine.memory.total_usage);
+                    for entry in engine.trace().entries() {
+                        trace.record(TraceEntry {
+                            state: format!("{}/{}", stage.name, entry.state),
+                            ..entry.clone()
+                        });
+                    }
+                    result
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Err(e) if attempts < max_attempts => {
+                    record(trace, index, "STAGE_RETRY", &format!("stage='{}' error={}", stage.name, e));
+                }
+                result => break result,
+            }
+        };
+
+        let (output, outcome) = match (result, &stage.on_failure) {
+            (Ok(answer), _) => (answer, StageOutcome::Completed),
+            (Err(e), StageFailure::Skip) => {
+                (input.to_string(), StageOutcome::Skipped { error: e.to_string() })
+            }
+            (Err(e), StageFailure::Fallback(text)) => {
+                (text.clone(), StageOutcome::FellBack { error: e.to_string() })
+            }
+            (Err(source), _) => {
+                record(trace, index, "STAGE_FAILED", &format!("stage='{}' error={}", stage.name, source));
+                return Err(PipelineError::StageFailed {
+                    stage: stage.name.clone(),
+                    attempts,
+                    source,
+                });
+            }
+        };
+        record(
+            trace,
+            index,
+            "STAGE_DONE",
+            &format!("stage='{}' outcome={:?} tokens={}", stage.name, outcome, stage_usage.total_tokens),
+        );
+
+        Ok(StageReport {
+            name: stage.name.clone(),
+            branch: branch.map(str::to_string),
+            task,
+            output,
+            outcome,
+            attempts,
+            usage: stage_usage,
+        })
+    }
+}
+
+/// Whether some limit of the budget has nothing left.
+fn exhausted(budget: &TokenBudget) -> bool {
+    [budget.max_total_tokens, budget.max_input_tokens, budget.max_output_tokens].contains(&Some(0))
+}
+
+/// Add a pipeline-level entry to the combined trace.
+fn record(trace: &mut Trace, stage_index: usize, event: &str, data: &str) {
+    trace.record(TraceEntry {
+        step: stage_index,
+        state: "Pipeline".to_string(),
+        event: event.to_string(),
+        data: data.to_string(),
+        timestamp: chrono::Utc::now(),
+    });
+}
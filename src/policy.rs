@@ -0,0 +1,362 @@
+//! Policy Engines — org-wide rules checked before agents act.
+//!
+//! A [`PolicyEngine`] is asked before every tool execution and before every
+//! final answer is accepted. Rules such as "no writes to prod tables" or "no
+//! emails outside the company domain" then live in one central policy
+//! service instead of in each agent.
+//!
+//! - A denied tool call is not run. Its observation is
+//!   `REJECTED: denied by policy: <reason>`, so the model can take another
+//!   approach.
+//! - A denied final answer fails the run
+//!   (`Planning --PolicyDenied--> Error`).
+//! - An engine error counts as a denial (fail closed).
+//!
+//! Two HTTP adapters are built in: [`OpaPolicyEngine`] for Open Policy Agent
+//! and [`CedarPolicyEngine`] for a Cedar authorization service.
+//!
+//! ```rust,ignore
+//! let opa = OpaPolicyEngine::new("http://localhost:8181", "agents/decision");
+//! let agent = AgentBuilder::new("task").openai("").policy_engine(Arc::new(opa)).build()?;
+//! ```
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::memory::AgentMemory;
+use crate::types::ToolCall;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Engine interface
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// What the agent is about to do.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PolicyAction {
+    ToolCall {
+        tool: String,
+        args: HashMap<String, Value>,
+        /// Declared with `Tool::mutating(true)`
+        mutating: bool,
+    },
+    FinalAnswer { content: String },
+}
+
+/// One question to the policy engine: an action plus who is asking.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PolicyRequest {
+    pub action: PolicyAction,
+    pub session_id: String,
+    pub task: String,
+    pub step: usize,
+}
+
+impl PolicyRequest {
+    pub fn tool_call(memory: &AgentMemory, call: &ToolCall, mutating: bool) -> Self {
+        Self::new(
+            memory,
+            PolicyAction::ToolCall {
+                tool: call.name.clone(),
+                args: call.args.clone(),
+                mutating,
+            },
+        )
+    }
+
+    pub fn final_answer(memory: &AgentMemory, content: &str) -> Self {
+        Self::new(memory, PolicyAction::FinalAnswer { content: content.to_string() })
+    }
+
+    fn new(memory: &AgentMemory, action: PolicyAction) -> Self {
+        Self {
+            action,
+            session_id: memory.session_id.clone(),
+            task: memory.task.clone(),
+            step: memory.step,
+        }
+    }
+}
+
+/// The engine's answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyDecision {
+    Allow,
+    Deny { reason: String },
+}
+
+#[async_trait]
+pub trait PolicyEngine: Send + Sync {
+    /// Decide whether the action may go ahead. An `Err` is treated as a
+    /// denial (fail closed).
+    async fn evaluate(&self, request: &PolicyRequest) -> Result<PolicyDecision, String>;
+}
+
+/// Ask `engine` about `request`. Returns the denial reason, if any.
+pub(crate) async fn check(engine: &dyn PolicyEngine, request: &PolicyRequest) -> Option<String> {
+    match engine.evaluate(request).await {
+        Ok(PolicyDecision::Allow) => None,
+        Ok(PolicyDecision::Deny { reason }) => Some(reason),
+        Err(e) => Some(format!("policy engine failed: {}", e)),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Open Policy Agent
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Queries an OPA server's Data API: `POST {base}/v1/data/{path}` with the
+/// [`PolicyRequest`] as `input`.
+///
+/// The rule's result may be a boolean, or an object with `allow` and either
+/// `reason` (a string) or `deny` (a list of messages). An undefined result
+/// is a denial.
+pub struct OpaPolicyEngine {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl OpaPolicyEngine {
+    /// `path` is the rule's package path, e.g. `agents/decision` for
+    /// `data.agents.decision`.
+    pub fn new(base_url: impl Into<String>, path: impl Into<String>) -> Self {
+        let base = base_url.into();
+        let path = path.into();
+        Self {
+            client: reqwest::Client::new(),
+            url: format!("{}/v1/data/{}", base.trim_end_matches('/'), path.trim_matches('/')),
+            token: None,
+        }
+    }
+
+    /// Send `Authorization: Bearer <token>` (OPA's `--authentication=token`).
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+}
+
+fn opa_decision(body: &Value) -> Result<PolicyDecision, String> {
+    let deny = |reason: String| Ok(PolicyDecision::Deny { reason });
+    match body.get("result") {
+        None => deny("policy result is undefined".to_string()),
+        Some(Value::Bool(true)) => Ok(PolicyDecision::Allow),
+        Some(Value::Bool(false)) => deny("denied by OPA policy".to_string()),
+        Some(Value::Object(result)) => {
+            let messages: Vec<&str> = result
+                .get("deny")
+                .and_then(Value::as_array)
+                .map(|d| d.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            let allow = result.get("allow").and_then(Value::as_bool).unwrap_or(false);
+            if allow && messages.is_empty() {
+                return Ok(PolicyDecision::Allow);
+            }
+            let reason = match result.get("reason").and_then(Value::as_str) {
+                Some(reason) => reason.to_string(),
+                None if !messages.is_empty() => messages.join("; "),
+                None => "denied by OPA policy".to_string(),
+            };
+            deny(reason)
+        }
+        Some(other) => Err(format!("unexpected OPA result: {}", other)),
+    }
+}
+
+#[async_trait]
+impl PolicyEngine for OpaPolicyEngine {
+    async fn evaluate(&self, request: &PolicyRequest) -> Result<PolicyDecision, String> {
+        let mut req = self.client.post(&self.url).json(&json!({ "input": request }));
+        if let Some(token) = &self.token {
+            req = req.bearer_auth(token);
+        }
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("OPA returned {}", resp.status()));
+        }
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        opa_decision(&body)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Cedar
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Queries a Cedar authorization service (such as `cedar-agent`):
+/// `POST {base}/v1/is_authorized` with a principal, action, resource and
+/// context, answered with `{"decision": "Allow" | "Deny"}`.
+///
+/// Requests are mapped as:
+///
+/// | Agent action | Cedar action            | Cedar resource      |
+/// |--------------|-------------------------|---------------------|
+/// | tool call    | `Action::"call_tool"`   | `Tool::"<name>"`    |
+/// | final answer | `Action::"final_answer"`| `Answer::"<session>"` |
+///
+/// The principal is `Agent::"<session>"` unless set with `principal`. The
+/// context holds the task, step, tool arguments and answer text.
+pub struct CedarPolicyEngine {
+    client: reqwest::Client,
+    url: String,
+    principal: Option<String>,
+}
+
+impl CedarPolicyEngine {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base = base_url.into();
+        Self {
+            client: reqwest::Client::new(),
+            url: format!("{}/v1/is_authorized", base.trim_end_matches('/')),
+            principal: None,
+        }
+    }
+
+    /// Use a fixed principal entity, e.g. `User::"alice"`.
+    pub fn principal(mut self, principal: impl Into<String>) -> Self {
+        self.principal = Some(principal.into());
+        self
+    }
+
+    fn body(&self, request: &PolicyRequest) -> Value {
+        let principal = self
+            .principal
+            .clone()
+            .unwrap_or_else(|| cedar_uid("Agent", &request.session_id));
+        let mut context = json!({ "task": request.task, "step": request.step });
+        let (action, resource) = match &request.action {
+            PolicyAction::ToolCall { tool, args, mutating } => {
+                context["args"] = json!(args);
+                context["mutating"] = json!(mutating);
+                ("call_tool", cedar_uid("Tool", tool))
+            }
+            PolicyAction::FinalAnswer { content } => {
+                context["answer"] = json!(content);
+                ("final_answer", cedar_uid("Answer", &request.session_id))
+            }
+        };
+        json!({
+            "principal": principal,
+            "action": cedar_uid("Action", action),
+            "resource": resource,
+            "context": context,
+        })
+    }
+}
+
+/// A Cedar entity UID, `Type::"id"`, with the id escaped.
+fn cedar_uid(entity_type: &str, id: &str) -> String {
+    format!("{}::{}", entity_type, Value::String(id.to_string()))
+}
+
+fn cedar_decision(body: &Value) -> Result<PolicyDecision, String> {
+    match body["decision"].as_str() {
+        Some("Allow") => Ok(PolicyDecision::Allow),
+        Some("Deny") => {
+            let policies: Vec<&str> = body["diagnostics"]["reason"]
+                .as_array()
+                .map(|r| r.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            let reason = if policies.is_empty() {
+                "denied by Cedar policy".to_string()
+            } else {
+                format!("denied by Cedar policy {}", policies.join(", "))
+            };
+            Ok(PolicyDecision::Deny { reason })
+        }
+        _ => Err(format!("unexpected Cedar response: {}", body)),
+    }
+}
+
+#[async_trait]
+impl PolicyEngine for CedarPolicyEngine {
+    async fn evaluate(&self, request: &PolicyRequest) -> Result<PolicyDecision, String> {
+        let resp = self
+            .client
+            .post(&self.url)
+            .json(&self.body(request))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("Cedar service returned {}", resp.status()));
+        }
+        let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+        cedar_decision(&body)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deny(reason: &str) -> PolicyDecision {
+        PolicyDecision::Deny { reason: reason.to_string() }
+    }
+
+    #[test]
+    fn test_opa_result_shapes() {
+        assert_eq!(opa_decision(&json!({ "result": true })), Ok(PolicyDecision::Allow));
+        assert_eq!(opa_decision(&json!({ "result": false })), Ok(deny("denied by OPA policy")));
+        assert_eq!(opa_decision(&json!({})), Ok(deny("policy result is undefined")));
+        assert_eq!(
+            opa_decision(&json!({ "result": { "allow": true, "deny": [] } })),
+            Ok(PolicyDecision::Allow)
+        );
+        assert_eq!(
+            opa_decision(&json!({ "result": { "allow": false, "reason": "prod is read-only" } })),
+            Ok(deny("prod is read-only"))
+        );
+        assert_eq!(
+            opa_decision(&json!({ "result": { "allow": true, "deny": ["a", "b"] } })),
+            Ok(deny("a; b"))
+        );
+        assert!(opa_decision(&json!({ "result": 3 })).is_err());
+    }
+
+    #[test]
+    fn test_cedar_request_and_decision() {
+        let mut memory = AgentMemory::new("clean up");
+        memory.session_id = "s1".to_string();
+        let call = ToolCall {
+            name: "drop_table".to_string(),
+            args: HashMap::from([("table".to_string(), json!("users"))]),
+            id: None,
+        };
+        let engine = CedarPolicyEngine::new("http://cedar:8180/");
+        let body = engine.body(&PolicyRequest::tool_call(&memory, &call, true));
+        assert_eq!(engine.url, "http://cedar:8180/v1/is_authorized");
+        assert_eq!(body["principal"], r#"Agent::"s1""#);
+        assert_eq!(body["action"], r#"Action::"call_tool""#);
+        assert_eq!(body["resource"], r#"Tool::"drop_table""#);
+        assert_eq!(body["context"]["args"]["table"], "users");
+        assert_eq!(body["context"]["mutating"], true);
+
+        let body = engine
+            .principal(r#"User::"alice""#)
+            .body(&PolicyRequest::final_answer(&memory, "done"));
+        assert_eq!(body["principal"], r#"User::"alice""#);
+        assert_eq!(body["action"], r#"Action::"final_answer""#);
+        assert_eq!(body["context"]["answer"], "done");
+
+        assert_eq!(cedar_decision(&json!({ "decision": "Allow" })), Ok(PolicyDecision::Allow));
+        assert_eq!(
+            cedar_decision(&json!({ "decision": "Deny", "diagnostics": { "reason": ["policy0"] } })),
+            Ok(deny("denied by Cedar policy policy0"))
+        );
+        assert!(cedar_decision(&json!({})).is_err());
+    }
+
+    #[test]
+    fn test_opa_url() {
+        let engine = OpaPolicyEngine::new("http://opa:8181/", "/agents/decision");
+        assert_eq!(engine.url, "http://opa:8181/v1/data/agents/decision");
+    }
+}
@@ -0,0 +1,319 @@
+//! Answer Post-Processing — a chain of transforms applied to final answers.
+//!
+//! Apps rarely show the model's answer as is: they tidy the markdown, turn
+//! citations into footnotes, make sure the answer is in the user's
+//! language, or wrap it in a template. A [`FinalAnswerTransform`] does one
+//! of these. Transforms added with `AgentBuilder::answer_transform` run in
+//! order on every accepted answer. They run after moderation and before the
+//! answer is stored, streamed as `AgentOutput::FinalAnswer` and returned
+//! from `run`.
+//!
+//! ```rust,ignore
+//! let agent = AgentBuilder::new("task")
+//!     .openai("")
+//!     .answer_transform(Arc::new(MarkdownCleanup))
+//!     .answer_transform(Arc::new(CitationFootnotes))
+//!     .answer_transform(Arc::new(TemplateWrap::new("{answer}\n\n_Session {session_id}_")))
+//!     .build()?;
+//! ```
+//!
+//! A transform that fails is logged as `ANSWER_TRANSFORM_FAILED`. It is then
+//! skipped, and the answer it was given goes on to the next transform.
+//! Structured (JSON) answers are not transformed.
+
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::citations::Citation;
+use crate::llm::AsyncLlmCaller;
+use crate::memory::AgentMemory;
+use crate::tools::ToolRegistry;
+use crate::types::LlmResponse;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Transform interface
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[async_trait]
+pub trait FinalAnswerTransform: Send + Sync {
+    /// Short name used in the trace
+    fn name(&self) -> &str;
+
+    /// Rewrite `answer`. `memory` is the run's memory as the answer is accepted.
+    async fn transform(&self, answer: String, memory: &AgentMemory) -> Result<String, String>;
+}
+
+/// Run the configured transforms over `answer`, logging each one.
+pub(crate) async fn apply(memory: &mut AgentMemory, mut answer: String) -> String {
+    let transforms = memory.answer_transforms.clone();
+    for transform in transforms {
+        match transform.transform(answer.clone(), memory).await {
+            Ok(next) => {
+                let data = format!("transform={} len={}->{}", transform.name(), answer.len(), next.len());
+                memory.log("Planning", "ANSWER_TRANSFORMED", &data);
+                answer = next;
+            }
+            Err(e) => {
+                let data = format!("transform={} error={}", transform.name(), e);
+                memory.log("Planning", "ANSWER_TRANSFORM_FAILED", &data);
+            }
+        }
+    }
+    answer
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Built-in transforms
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Tidies markdown. It unwraps an answer that is entirely one code fence,
+/// removes trailing spaces, collapses runs of blank lines, and trims the
+/// whole answer. Lines inside code fences are left alone.
+pub struct MarkdownCleanup;
+
+#[async_trait]
+impl FinalAnswerTransform for MarkdownCleanup {
+    fn name(&self) -> &str {
+        "markdown_cleanup"
+    }
+
+    async fn transform(&self, answer: String, _memory: &AgentMemory) -> Result<String, String> {
+        Ok(clean_markdown(&answer))
+    }
+}
+
+fn clean_markdown(answer: &str) -> String {
+    let mut text = answer.trim();
+    // The whole answer fenced as ```markdown ... ```
+    if let Some(inner) = text.strip_prefix("```").and_then(|t| t.strip_suffix("```")) {
+        if let Some((lang, body)) = inner.split_once('\n') {
+            if !body.contains("```") && matches!(lang.trim(), "" | "md" | "markdown" | "text") {
+                text = body.trim();
+            }
+        }
+    }
+
+    let mut out: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        let line = if in_fence { line } else { line.trim_end() };
+        if !in_fence && line.is_empty() && out.last().is_some_and(|l| l.is_empty()) {
+            continue;
+        }
+        out.push(line);
+    }
+    out.join("\n")
+}
+
+/// Turns citations (`[1]`, `[1, 2]`) into markdown footnotes (`[^1]`,
+/// `[^1][^2]`). A footnote with each source's title and link is added at the
+/// end. Only numbers that match a source in `AgentMemory::citations` are
+/// rewritten.
+pub struct CitationFootnotes;
+
+#[async_trait]
+impl FinalAnswerTransform for CitationFootnotes {
+    fn name(&self) -> &str {
+        "citation_footnotes"
+    }
+
+    async fn transform(&self, answer: String, memory: &AgentMemory) -> Result<String, String> {
+        Ok(render_footnotes(&answer, &memory.citations))
+    }
+}
+
+fn render_footnotes(answer: &str, citations: &[Citation]) -> String {
+    let mut out = String::new();
+    let mut used: Vec<usize> = Vec::new();
+    let mut rest = answer;
+    while let Some(open) = rest.find('[') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let close = after.find(']');
+        // Every number in the brackets must be a known source
+        let ids: Option<Vec<usize>> = close.and_then(|close| {
+            after[..close]
+                .split(',')
+                .map(|part| {
+                    part.trim()
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|id| citations.iter().any(|c| c.id == *id))
+                })
+                .collect()
+        });
+        match (ids, close) {
+            (Some(ids), Some(close)) => {
+                for id in ids {
+                    out.push_str(&format!("[^{}]", id));
+                    if !used.contains(&id) {
+                        used.push(id);
+                    }
+                }
+                rest = &after[close + 1..];
+            }
+            _ => {
+                out.push('[');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    if !used.is_empty() {
+        out.push('\n');
+        for id in used {
+            if let Some(c) = citations.iter().find(|c| c.id == id) {
+                if c.title.is_empty() {
+                    out.push_str(&format!("\n[^{}]: <{}>", id, c.url));
+                } else {
+                    out.push_str(&format!("\n[^{}]: [{}]({})", id, c.title, c.url));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Asks a model to make sure the answer is in `language`, translating it if
+/// it is not. Formatting, code and citation markers are kept.
+pub struct LanguageEnforcer {
+    language: String,
+    llm:      Arc<dyn AsyncLlmCaller>,
+    model:    String,
+}
+
+impl LanguageEnforcer {
+    pub fn new(language: impl Into<String>, llm: Arc<dyn AsyncLlmCaller>, model: impl Into<String>) -> Self {
+        Self { language: language.into(), llm, model: model.into() }
+    }
+}
+
+#[async_trait]
+impl FinalAnswerTransform for LanguageEnforcer {
+    fn name(&self) -> &str {
+        "language"
+    }
+
+    async fn transform(&self, answer: String, _memory: &AgentMemory) -> Result<String, String> {
+        let request = AgentMemory::new(answer).with_system_prompt(format!(
+            "You make sure text is written in {lang}. If the user's text is already in {lang}, \
+             repeat it exactly. Otherwise translate it into {lang}. Keep the markdown formatting, \
+             code blocks, links and citation markers such as [1] unchanged. Respond with the text only.",
+            lang = self.language
+        ));
+        match self.llm.call_async(&request, &ToolRegistry::new(), &self.model, None).await? {
+            LlmResponse::FinalAnswer { content, .. } if !content.trim().is_empty() => Ok(content),
+            LlmResponse::FinalAnswer { .. } => Err("language model returned an empty answer".to_string()),
+            LlmResponse::Truncated { .. } => Err("language model hit its output token limit".to_string()),
+            _ => Err("language model did not return text".to_string()),
+        }
+    }
+}
+
+/// Wraps the answer in a template. The template can use these placeholders:
+/// - `{answer}`: the answer
+/// - `{task}`: the task
+/// - `{session_id}`: the session ID
+/// - `{steps}`: the number of steps taken
+pub struct TemplateWrap {
+    template: String,
+}
+
+impl TemplateWrap {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self { template: template.into() }
+    }
+}
+
+#[async_trait]
+impl FinalAnswerTransform for TemplateWrap {
+    fn name(&self) -> &str {
+        "template"
+    }
+
+    async fn transform(&self, answer: String, memory: &AgentMemory) -> Result<String, String> {
+        if !self.template.contains("{answer}") {
+            return Err("template has no {answer} placeholder".to_string());
+        }
+        // {answer} last, so placeholders inside the answer are left alone
+        Ok(self
+            .template
+            .replace("{task}", &memory.task)
+            .replace("{session_id}", &memory.session_id)
+            .replace("{steps}", &memory.step.to_string())
+            .replace("{answer}", &answer))
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(id: usize, title: &str) -> Citation {
+        let mut c = Citation::new(format!("https://example.com/{}", id), title);
+        c.id = id;
+        c
+    }
+
+    #[test]
+    fn test_markdown_cleanup() {
+        let answer = "```markdown\n# Title  \n\n\n\nBody text   \n```";
+        assert_eq!(clean_markdown(answer), "# Title\n\nBody text");
+
+        // Code blocks keep their blank lines and trailing spaces
+        let code = "Run:\n\n```sh\necho hi  \n\n\nls\n```\n\n\nDone.";
+        assert_eq!(clean_markdown(code), "Run:\n\n```sh\necho hi  \n\n\nls\n```\n\nDone.");
+    }
+
+    #[test]
+    fn test_footnotes_for_known_sources_only() {
+        let citations = vec![source(1, "One"), source(2, "")];
+        let out = render_footnotes("A [1], B [1, 2], list [x] and [7].", &citations);
+        assert_eq!(
+            out,
+            "A [^1], B [^1][^2], list [x] and [7].\n\n\
+             [^1]: [One](https://example.com/1)\n\
+             [^2]: <https://example.com/2>"
+        );
+        assert_eq!(render_footnotes("No sources [1].", &[]), "No sources [1].");
+    }
+
+    #[tokio::test]
+    async fn test_template_wrap() {
+        let mut memory = AgentMemory::new("Summarize");
+        memory.session_id = "s1".to_string();
+        memory.step = 3;
+        let wrap = TemplateWrap::new("## {task}\n\n{answer}\n\n_{session_id}, {steps} steps_");
+        let out = wrap.transform("Keep {task} literal".to_string(), &memory).await.unwrap();
+        assert_eq!(out, "## Summarize\n\nKeep {task} literal\n\n_s1, 3 steps_");
+
+        assert!(TemplateWrap::new("no slot").transform("x".into(), &memory).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_transform_is_skipped() {
+        let mut memory = AgentMemory::new("t");
+        memory.answer_transforms = vec![
+            Arc::new(TemplateWrap::new("no slot")),
+            Arc::new(MarkdownCleanup),
+        ];
+        let out = apply(&mut memory, "  answer  ".to_string()).await;
+        assert_eq!(out, "answer");
+        let events: Vec<&str> = memory
+            .trace
+            .entries()
+            .iter()
+            .map(|e| e.event.as_str())
+            .filter(|e| e.starts_with("ANSWER_"))
+            .collect();
+        assert_eq!(events, vec!["ANSWER_TRANSFORM_FAILED", "ANSWER_TRANSFORMED"]);
+    }
+}
@@ -0,0 +1,205 @@
+//! Pre-flight checks and the kickoff message, run by IdleState.
+//!
+//! With `AgentBuilder::preflight(true)` the Idle state checks, before the
+//! first LLM call, that every tool schema is one the providers accept and
+//! that the provider answers `AsyncLlmCaller::health_check` within
+//! [`HEALTH_CHECK_TIMEOUT`]. A problem fails the run with `PreflightFailed`
+//! → Error, so a typo in a tool name, a wrong base URL or a dead Ollama
+//! instance is reported before any tokens are spent. A model the provider
+//! does not list is only logged (`PREFLIGHT_MODEL_UNLISTED`), since aliases
+//! are often missing from model lists.
+//!
+//! With `AgentBuilder::kickoff(true)` Idle also sends an
+//! `AgentOutput::Action` naming the task, the model and the tools.
+
+use crate::llm::AsyncLlmCaller;
+use crate::memory::AgentMemory;
+use crate::tools::ToolRegistry;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Longest tool name OpenAI and Anthropic accept.
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+/// How long the provider gets to answer the health check.
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Problems with the registered tool schemas, one line per problem.
+///
+/// Names must be 1–64 characters of `[A-Za-z0-9_-]`; input schemas must be
+/// JSON objects of type `object` whose `required` names are all declared
+/// in `properties`.
+pub fn check_tools(tools: &ToolRegistry) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut entries: Vec<_> = tools.iter().collect();
+    entries.sort_unstable_by_key(|(name, _)| *name);
+
+    for (name, schema) in entries {
+        let schema = &schema.input_schema;
+        let valid_name = !name.is_empty()
+            && name.len() <= MAX_TOOL_NAME_LEN
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_name {
+            problems.push(format!(
+                "tool '{}': name must be 1-{} characters of letters, digits, '_' or '-'",
+                name, MAX_TOOL_NAME_LEN
+            ));
+        }
+
+        let Some(object) = schema.as_object() else {
+            problems.push(format!("tool '{}': input schema is not a JSON object", name));
+            continue;
+        };
+        if let Some(kind) = object.get("type") {
+            if kind != "object" {
+                problems.push(format!("tool '{}': input schema type is {}, not \"object\"", name, kind));
+            }
+        }
+        let properties = object.get("properties").and_then(Value::as_object);
+        for required in object.get("required").and_then(Value::as_array).into_iter().flatten() {
+            let declared = required
+                .as_str()
+                .is_some_and(|r| properties.is_some_and(|p| p.contains_key(r)));
+            if !declared {
+                problems.push(format!(
+                    "tool '{}': required parameter {} is not in properties",
+                    name, required
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Check the tools, then the provider. Returns the first failure found.
+pub(crate) async fn run(
+    memory: &mut AgentMemory,
+    tools:  &ToolRegistry,
+    llm:    &dyn AsyncLlmCaller,
+    model:  &str,
+) -> Result<(), String> {
+    let problems = check_tools(tools);
+    if !problems.is_empty() {
+        return Err(problems.join("; "));
+    }
+    match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, llm.health_check()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(format!("provider health check failed ({}): {}", e.kind(), e)),
+        Err(_) => {
+            return Err(format!(
+                "provider health check timed out after {}s",
+                HEALTH_CHECK_TIMEOUT.as_secs()
+            ))
+        }
+    }
+
+    if !model.is_empty() {
+        // Listing can fail where the health check passed (or is unsupported); not fatal
+        if let Ok(models) = llm.list_models().await {
+            if !models.is_empty() && !models.iter().any(|m| m == model) {
+                memory.log("Idle", "PREFLIGHT_MODEL_UNLISTED", &format!("model='{}'", model));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The user-visible summary sent at the start of a run.
+pub(crate) fn kickoff_message(task: &str, model: &str, tools: &ToolRegistry) -> String {
+    let model = if model.is_empty() { "provider default" } else { model };
+    let mut names: Vec<&str> = tools.iter().map(|(name, _)| name).collect();
+    names.sort_unstable();
+    let tools = if names.is_empty() { "none".to_string() } else { names.join(", ") };
+    format!("Starting task '{}' with model {} (tools: {})", task, model, tools)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::LlmError;
+    use std::sync::Arc;
+
+    fn registry(name: &str, schema: Value) -> ToolRegistry {
+        let mut tools = ToolRegistry::new();
+        tools.register(name, "desc", schema, Arc::new(|_| Ok(String::new())));
+        tools
+    }
+
+    #[test]
+    fn test_valid_tools_pass() {
+        let tools = registry(
+            "web_search",
+            serde_json::json!({
+                "type": "object",
+                "properties": { "q": { "type": "string" } },
+                "required": ["q"],
+            }),
+        );
+        assert!(check_tools(&tools).is_empty());
+        assert!(check_tools(&registry("noop", serde_json::json!({}))).is_empty());
+    }
+
+    #[test]
+    fn test_bad_name_and_schema_reported() {
+        let problems = check_tools(&registry("web search", serde_json::json!({ "type": "string" })));
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("name must be"));
+        assert!(problems[1].contains("not \"object\""));
+
+        let problems = check_tools(&registry(
+            "search",
+            serde_json::json!({ "type": "object", "properties": {}, "required": ["q"] }),
+        ));
+        assert_eq!(problems, vec![r#"tool 'search': required parameter "q" is not in properties"#]);
+    }
+
+    /// A provider that is down: every request fails to connect.
+    struct DownCaller;
+
+    #[async_trait::async_trait]
+    impl AsyncLlmCaller for DownCaller {
+        async fn call_async(
+            &self,
+            _memory: &AgentMemory,
+            _tools:  &ToolRegistry,
+            _model:  &str,
+            _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+        ) -> Result<crate::types::LlmResponse, LlmError> {
+            panic!("pre-flight must not call the model")
+        }
+
+        fn call_stream_async<'a>(
+            &'a self,
+            _memory: &'a AgentMemory,
+            _tools:  &'a ToolRegistry,
+            _model:  &'a str,
+            _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>,
+        ) -> futures::stream::BoxStream<'a, Result<crate::types::LlmStreamChunk, LlmError>> {
+            panic!("pre-flight must not call the model")
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+            Err(LlmError::Network("connection refused".into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_provider_fails_health_check() {
+        let mut memory = AgentMemory::new("task");
+        let err = run(&mut memory, &ToolRegistry::new(), &DownCaller, "llama3").await.unwrap_err();
+        assert_eq!(err, "provider health check failed (network): connection refused");
+
+        let mock = crate::llm::MockLlmCaller::new(vec![]);
+        assert!(run(&mut memory, &ToolRegistry::new(), &mock, "llama3").await.is_ok());
+    }
+
+    #[test]
+    fn test_kickoff_message_lists_tools_in_order() {
+        let mut tools = registry("zeta", serde_json::json!({}));
+        tools.merge(registry("alpha", serde_json::json!({})));
+        assert_eq!(
+            kickoff_message("Sum it", "", &tools),
+            "Starting task 'Sum it' with model provider default (tools: alpha, zeta)"
+        );
+    }
+}
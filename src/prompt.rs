@@ -161,8 +161,14 @@ impl PromptTemplate {
                     }
                 }
                 _ => {
-                    out.push(src[i] as char);
-                    i += 1;
+                    // Copy up to the next brace; braces are ASCII, so this
+                    // never splits a multi-byte character
+                    let next = src[i..]
+                        .iter()
+                        .position(|&b| b == b'{' || b == b'}')
+                        .map_or(len, |n| i + n);
+                    out.push_str(&self.template[i..next]);
+                    i = next;
                 }
             }
         }
@@ -185,6 +191,12 @@ mod tests {
         assert_eq!(t.render().unwrap(), "Hello World");
     }
 
+    #[test]
+    fn test_non_ascii_text_kept() {
+        let t = PromptTemplate::new("Grüße, {name} — 日本").var("name", "Jürgen");
+        assert_eq!(t.render().unwrap(), "Grüße, Jürgen — 日本");
+    }
+
     #[test]
     fn test_multiple_vars() {
         let t = PromptTemplate::new("You are a {role}. Focus on {topic}.")
@@ -0,0 +1,612 @@
+//! Planning prompters — how the "decide the next action" request is framed.
+//!
+//! A [`PlanningPrompter`] controls three things:
+//! - whether tool schemas go through the provider's function-calling API
+//! - how past steps are rendered into messages
+//! - how a text reply is read back as tool calls or an answer
+//!
+//! Three prompters are built in:
+//! - `NativePrompter` — function calling with `tool_calls` / `tool` messages (default)
+//! - `ReActPrompter` — `Thought:` / `Action:` / `Action Input:` scratchpad text
+//! - `XmlPrompter` — `<tool_call>` blocks, a format many local models follow well
+//! - `JsonPrompter` — one bare JSON object per reply, for grammar-constrained decoding
+//!
+//! The text prompters let the crate drive models without native function
+//! calling. Their instructions, including a description of every tool, are
+//! appended to the system prompt.
+//!
+//! ```rust,ignore
+//! let agent = AgentBuilder::new("task")
+//!     .llm(local_model)
+//!     .planning_prompter(Arc::new(ReActPrompter))
+//!     .build()?;
+//! ```
+
+use crate::locale::ObservationMarkers;
+use crate::tools::{parse_tool_args, ToolSchema};
+use crate::types::{HistoryEntry, ToolCall};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Trait
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A model reply read as text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextReply {
+    /// The model asked for tools. `thought` is any reasoning before the calls.
+    ToolCalls {
+        calls: Vec<ToolCall>,
+        thought: Option<String>,
+    },
+    /// The model answered. The text has the format's markers removed.
+    Answer(String),
+}
+
+/// Frames the planning request. Set with `AgentBuilder::planning_prompter`.
+pub trait PlanningPrompter: Send + Sync {
+    /// Human-readable name for logging.
+    fn name(&self) -> &'static str;
+
+    /// Send tool schemas through the provider's function-calling API.
+    fn native_tools(&self) -> bool {
+        true
+    }
+
+    /// Text appended to the system prompt: the tools and the reply format.
+    fn instructions(&self, _tools: &[ToolSchema]) -> Option<String> {
+        None
+    }
+
+    /// Messages for one step of history (one or more calls from one reply).
+    /// Observations are written with `markers`.
+    fn render_step(&self, entries: &[&HistoryEntry], markers: &ObservationMarkers) -> Vec<Value> {
+        native_step_messages(entries, markers)
+    }
+
+    /// Read a text reply. `None` keeps it as a final answer, unchanged.
+    fn parse(&self, _text: &str) -> Option<TextReply> {
+        None
+    }
+
+    /// Strings that end a reply in this format, sent as stop sequences
+    /// so the model does not write past its turn.
+    fn stop_sequences(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// OpenAI-style messages for one step: an assistant message with all the
+/// `tool_calls`, then one `tool` message per result.
+pub fn native_step_messages(entries: &[&HistoryEntry], markers: &ObservationMarkers) -> Vec<Value> {
+    let mut oai_tool_calls = Vec::new();
+    let mut tool_results = Vec::new();
+    let assistant_text = entries.iter().find_map(|e| e.assistant_text.clone());
+
+    for entry in entries {
+        let tool_id = entry
+            .tool
+            .id
+            .clone()
+            .unwrap_or_else(|| "legacy".to_string());
+
+        oai_tool_calls.push(json!({
+            "id": tool_id,
+            "type": "function",
+            "function": {
+                "name": entry.tool.name,
+                "arguments": serde_json::to_string(&entry.tool.args).unwrap_or_default()
+            }
+        }));
+
+        tool_results.push(json!({
+            "role": "tool",
+            "tool_call_id": tool_id,
+            "name": entry.tool.name,
+            "content": entry.observation.render(markers)
+        }));
+    }
+
+    let mut messages = vec![json!({
+        "role": "assistant",
+        "content": assistant_text,
+        "tool_calls": oai_tool_calls
+    })];
+    messages.extend(tool_results);
+    messages
+}
+
+/// One line per tool: name, description and parameter schema.
+fn describe_tools(tools: &[ToolSchema]) -> String {
+    let mut tools: Vec<&ToolSchema> = tools.iter().collect();
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    tools
+        .iter()
+        .map(|t| format!("- {}: {} Parameters: {}", t.name, t.description, t.input_schema))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn args_json(call: &ToolCall) -> String {
+    serde_json::to_string(&call.args).unwrap_or_else(|_| "{}".to_string())
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// NativePrompter
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Provider function calling. This is the default.
+pub struct NativePrompter;
+
+impl PlanningPrompter for NativePrompter {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// ReActPrompter
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// ReAct scratchpad text. One tool call per reply.
+///
+/// ```text
+/// Thought: I need the population first.
+/// Action: search
+/// Action Input: {"query": "population of Lyon"}
+/// ```
+///
+/// Results come back as `Observation: ...` user messages. The model ends
+/// with `Final Answer: ...`.
+pub struct ReActPrompter;
+
+impl PlanningPrompter for ReActPrompter {
+    fn name(&self) -> &'static str {
+        "react"
+    }
+
+    fn native_tools(&self) -> bool {
+        false
+    }
+
+    fn instructions(&self, tools: &[ToolSchema]) -> Option<String> {
+        if tools.is_empty() {
+            return Some("Reply with \"Final Answer: <your answer>\".".to_string());
+        }
+        Some(format!(
+            "You can use these tools:\n{}\n\n\
+             To use a tool, reply in exactly this format and stop:\n\
+             Thought: <your reasoning>\n\
+             Action: <tool name>\n\
+             Action Input: <arguments as a JSON object>\n\n\
+             You will get the result as \"Observation: ...\". \
+             When you can answer, reply with \"Final Answer: <your answer>\".",
+            describe_tools(tools)
+        ))
+    }
+
+    fn render_step(&self, entries: &[&HistoryEntry], markers: &ObservationMarkers) -> Vec<Value> {
+        let mut messages = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let thought = if i == 0 { entry.assistant_text.as_deref() } else { None };
+            let mut text = String::new();
+            if let Some(t) = thought {
+                text.push_str(&format!("Thought: {}\n", t));
+            }
+            text.push_str(&format!(
+                "Action: {}\nAction Input: {}",
+                entry.tool.name,
+                args_json(&entry.tool)
+            ));
+            messages.push(json!({ "role": "assistant", "content": text }));
+            messages.push(json!({
+                "role": "user",
+                "content": format!("Observation: {}", entry.observation.render(markers))
+            }));
+        }
+        messages
+    }
+
+    fn parse(&self, text: &str) -> Option<TextReply> {
+        if let Some(pos) = text.find("Final Answer:") {
+            return Some(TextReply::Answer(
+                text[pos + "Final Answer:".len()..].trim().to_string(),
+            ));
+        }
+        let action_pos = text.find("Action:")?;
+        let after = &text[action_pos + "Action:".len()..];
+        let (name, rest) = match after.find("Action Input:") {
+            Some(p) => (&after[..p], &after[p + "Action Input:".len()..]),
+            None => (after, ""),
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        // The model may keep writing after the input; stop at a hallucinated result
+        let input = rest.split("\nObservation:").next().unwrap_or("").trim();
+        let input = input.trim_start_matches("```json").trim_matches('`').trim();
+
+        let thought = text[..action_pos].trim();
+        let thought = thought.strip_prefix("Thought:").unwrap_or(thought);
+        Some(TextReply::ToolCalls {
+            calls: vec![ToolCall {
+                name: name.to_string(),
+                args: parse_tool_args(input),
+                id: None,
+            }],
+            thought: non_empty(thought),
+        })
+    }
+
+    /// Stop before the model invents the tool result.
+    fn stop_sequences(&self) -> Vec<String> {
+        vec!["\nObservation:".to_string()]
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// XmlPrompter
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// `<tool_call>` blocks. Several blocks in one reply run as parallel calls.
+///
+/// ```text
+/// <tool_call>{"name": "search", "arguments": {"query": "population of Lyon"}}</tool_call>
+/// ```
+///
+/// Results come back as `<tool_result name="...">...</tool_result>` user
+/// messages. A reply without blocks is the final answer.
+pub struct XmlPrompter;
+
+impl PlanningPrompter for XmlPrompter {
+    fn name(&self) -> &'static str {
+        "xml"
+    }
+
+    fn native_tools(&self) -> bool {
+        false
+    }
+
+    fn instructions(&self, tools: &[ToolSchema]) -> Option<String> {
+        if tools.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "You can use these tools:\n{}\n\n\
+             To use a tool, reply with one block per call and stop:\n\
+             <tool_call>{{\"name\": \"<tool name>\", \"arguments\": {{...}}}}</tool_call>\n\n\
+             Results come back in <tool_result> blocks. \
+             When you can answer, reply with the answer only, without any <tool_call> block.",
+            describe_tools(tools)
+        ))
+    }
+
+    fn render_step(&self, entries: &[&HistoryEntry], markers: &ObservationMarkers) -> Vec<Value> {
+        let mut call_text = entries
+            .iter()
+            .find_map(|e| e.assistant_text.clone())
+            .map(|t| format!("{}\n", t))
+            .unwrap_or_default();
+        let mut results = Vec::new();
+        for entry in entries {
+            call_text.push_str(&format!(
+                "<tool_call>{}</tool_call>\n",
+                json!({ "name": entry.tool.name, "arguments": entry.tool.args })
+            ));
+            results.push(format!(
+                "<tool_result name=\"{}\">{}</tool_result>",
+                entry.tool.name, entry.observation.render(markers)
+            ));
+        }
+        vec![
+            json!({ "role": "assistant", "content": call_text.trim_end() }),
+            json!({ "role": "user", "content": results.join("\n") }),
+        ]
+    }
+
+    fn parse(&self, text: &str) -> Option<TextReply> {
+        let first = text.find("<tool_call>")?;
+        let mut calls = Vec::new();
+        let mut rest = &text[first..];
+        while let Some(start) = rest.find("<tool_call>") {
+            let body = &rest[start + "<tool_call>".len()..];
+            let (inner, next) = match body.find("</tool_call>") {
+                Some(end) => (&body[..end], &body[end + "</tool_call>".len()..]),
+                None => (body, ""),
+            };
+            if let Some(call) = serde_json::from_str::<Value>(inner.trim())
+                .ok()
+                .as_ref()
+                .and_then(json_tool_call)
+            {
+                calls.push(call);
+            }
+            rest = next;
+        }
+        if calls.is_empty() {
+            return None;
+        }
+        Some(TextReply::ToolCalls {
+            calls,
+            thought: non_empty(&text[..first]),
+        })
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// JsonPrompter
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// One JSON object per reply, with nothing around it.
+///
+/// ```text
+/// {"tool": "search", "arguments": {"query": "population of Lyon"}}
+/// {"answer": "About 520,000 people live in Lyon."}
+/// ```
+///
+/// This is the shape `GrammarConstraint::ToolCalls` enforces, so a local
+/// model under that grammar can only produce replies this prompter reads.
+pub struct JsonPrompter;
+
+impl PlanningPrompter for JsonPrompter {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn native_tools(&self) -> bool {
+        false
+    }
+
+    fn instructions(&self, tools: &[ToolSchema]) -> Option<String> {
+        if tools.is_empty() {
+            return Some("Reply with only a JSON object: {\"answer\": \"<your answer>\"}".to_string());
+        }
+        Some(format!(
+            "You can use these tools:\n{}\n\n\
+             Reply with exactly one JSON object and nothing else.\n\
+             To use a tool: {{\"tool\": \"<tool name>\", \"arguments\": {{...}}}}\n\
+             When you can answer: {{\"answer\": \"<your answer>\"}}",
+            describe_tools(tools)
+        ))
+    }
+
+    fn render_step(&self, entries: &[&HistoryEntry], markers: &ObservationMarkers) -> Vec<Value> {
+        let mut messages = Vec::new();
+        for entry in entries {
+            messages.push(json!({
+                "role": "assistant",
+                "content": json!({ "tool": entry.tool.name, "arguments": entry.tool.args }).to_string()
+            }));
+            messages.push(json!({
+                "role": "user",
+                "content": format!("Result of {}: {}", entry.tool.name, entry.observation.render(markers))
+            }));
+        }
+        messages
+    }
+
+    fn parse(&self, text: &str) -> Option<TextReply> {
+        let text = text.trim();
+        let text = text.strip_prefix("```json").or_else(|| text.strip_prefix("```")).unwrap_or(text);
+        let value: Value = serde_json::from_str(text.trim_end_matches('`').trim()).ok()?;
+        if let Some(answer) = value.get("answer").and_then(Value::as_str) {
+            return Some(TextReply::Answer(answer.to_string()));
+        }
+        json_tool_call(&value).map(|call| TextReply::ToolCalls { calls: vec![call], thought: None })
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Text tool-call fallback
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Format for tool calls written as text by models that ignore the native
+/// tool schemas. Set with `AgentBuilder::text_tool_calls`.
+///
+/// Unlike a text prompter, this keeps native function calling and only
+/// reads text answers that contain a tool call in this format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextToolFormat {
+    /// A fenced ```json block holding `{"tool": .., "arguments": {..}}`, or an array of them
+    FencedJson,
+    /// `<tool_call>` blocks, as read by [`XmlPrompter`]
+    Xml,
+}
+
+impl TextToolFormat {
+    /// System prompt section describing the format and the tools.
+    pub fn instructions(&self, tools: &[ToolSchema]) -> Option<String> {
+        if tools.is_empty() {
+            return None;
+        }
+        let format = match self {
+            TextToolFormat::FencedJson => {
+                "```json\n{\"tool\": \"<tool name>\", \"arguments\": {...}}\n```"
+            }
+            TextToolFormat::Xml => {
+                "<tool_call>{\"name\": \"<tool name>\", \"arguments\": {...}}</tool_call>"
+            }
+        };
+        Some(format!(
+            "Available tools:\n{}\n\n\
+             If you cannot call tools natively, request a tool by replying with only this, then stop:\n{}",
+            describe_tools(tools),
+            format
+        ))
+    }
+
+    /// Tool calls written in this format, if any.
+    pub fn parse(&self, text: &str) -> Option<TextReply> {
+        match self {
+            TextToolFormat::Xml => XmlPrompter.parse(text),
+            TextToolFormat::FencedJson => parse_fenced_json(text),
+        }
+    }
+}
+
+fn parse_fenced_json(text: &str) -> Option<TextReply> {
+    let first = text.find("```")?;
+    let mut calls = Vec::new();
+    let mut rest = &text[first..];
+    while let Some(start) = rest.find("```") {
+        let body = &rest[start + 3..];
+        // Skip a `json` language tag
+        let body = body.strip_prefix("json").unwrap_or(body);
+        let Some(end) = body.find("```") else {
+            break;
+        };
+        if let Ok(value) = serde_json::from_str::<Value>(body[..end].trim()) {
+            let items = match value {
+                Value::Array(items) => items,
+                other => vec![other],
+            };
+            calls.extend(items.iter().filter_map(json_tool_call));
+        }
+        rest = &body[end + 3..];
+    }
+    if calls.is_empty() {
+        return None;
+    }
+    Some(TextReply::ToolCalls {
+        calls,
+        thought: non_empty(&text[..first]),
+    })
+}
+
+/// `{"tool"|"name": .., "arguments"|"args": {..}}` as a tool call.
+fn json_tool_call(value: &Value) -> Option<ToolCall> {
+    let name = value.get("tool").or_else(|| value.get("name"))?.as_str()?;
+    let args = match value.get("arguments").or_else(|| value.get("args")) {
+        Some(Value::Object(m)) => m.clone().into_iter().collect(),
+        Some(Value::String(s)) => parse_tool_args(s),
+        _ => Default::default(),
+    };
+    Some(ToolCall { name: name.to_string(), args, id: None })
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_react_parse_action_and_answer() {
+        let reply = ReActPrompter.parse(
+            "Thought: look it up\nAction: search\nAction Input: {\"query\": \"lyon\"}\nObservation: made up",
+        );
+        match reply {
+            Some(TextReply::ToolCalls { calls, thought }) => {
+                assert_eq!(calls[0].name, "search");
+                assert_eq!(calls[0].args["query"], "lyon");
+                assert_eq!(thought.as_deref(), Some("look it up"));
+            }
+            other => panic!("expected a tool call, got {:?}", other),
+        }
+        assert_eq!(
+            ReActPrompter.parse("Thought: done\nFinal Answer: 42"),
+            Some(TextReply::Answer("42".into()))
+        );
+        assert_eq!(ReActPrompter.parse("just text"), None);
+    }
+
+    #[test]
+    fn test_xml_parse_multiple_blocks() {
+        let reply = XmlPrompter.parse(
+            "Checking both.\n<tool_call>{\"name\": \"a\", \"arguments\": {\"x\": 1}}</tool_call>\n\
+             <tool_call>{\"name\": \"b\", \"arguments\": \"{\\\"y\\\": 2}\"}</tool_call>",
+        );
+        match reply {
+            Some(TextReply::ToolCalls { calls, thought }) => {
+                assert_eq!(calls.len(), 2);
+                assert_eq!(calls[0].args["x"], 1);
+                assert_eq!(calls[1].args["y"], 2);
+                assert_eq!(thought.as_deref(), Some("Checking both."));
+            }
+            other => panic!("expected tool calls, got {:?}", other),
+        }
+        assert_eq!(XmlPrompter.parse("The answer is 42."), None);
+    }
+
+    #[test]
+    fn test_fenced_json_fallback() {
+        let reply = TextToolFormat::FencedJson.parse(
+            "Let me check.\n```json\n{\"tool\": \"search\", \"arguments\": {\"query\": \"rust\"}}\n```",
+        );
+        match reply {
+            Some(TextReply::ToolCalls { calls, thought }) => {
+                assert_eq!(calls.len(), 1);
+                assert_eq!(calls[0].name, "search");
+                assert_eq!(calls[0].args["query"], "rust");
+                assert_eq!(thought.as_deref(), Some("Let me check."));
+            }
+            other => panic!("expected a tool call, got {:?}", other),
+        }
+        // Code that is not a tool call stays an answer
+        assert_eq!(TextToolFormat::FencedJson.parse("```json\n{\"a\": 1}\n```"), None);
+    }
+
+    #[test]
+    fn test_json_parse_call_and_answer() {
+        assert_eq!(
+            JsonPrompter.parse("{\"tool\": \"search\", \"arguments\": {\"query\": \"rust\"}}"),
+            Some(TextReply::ToolCalls {
+                calls: vec![ToolCall {
+                    name: "search".into(),
+                    args: [("query".to_string(), json!("rust"))].into_iter().collect(),
+                    id: None,
+                }],
+                thought: None,
+            })
+        );
+        assert_eq!(
+            JsonPrompter.parse(" {\"answer\": \"42\"}\n"),
+            Some(TextReply::Answer("42".into()))
+        );
+        assert_eq!(JsonPrompter.parse("42"), None);
+    }
+
+    #[test]
+    fn test_native_messages_after_prune() {
+        use crate::memory::{AgentMemory, PRUNED_BRANCH};
+        use crate::types::Observation;
+
+        let mut memory = AgentMemory::new("task");
+        for (step, name) in [(1, "read"), (2, "flaky"), (3, "flaky")] {
+            memory.history.push(HistoryEntry {
+                step,
+                tool: ToolCall { name: name.into(), args: Default::default(), id: Some(format!("call_{}", step)) },
+                observation: if name == "read" { Observation::success("ok") } else { Observation::error("down") },
+                assistant_text: None,
+                latency_ms: None,
+                model_used: None,
+                usage: None,
+            });
+        }
+        assert_eq!(memory.prune_failed_branch(), 2);
+
+        // Only real calls are rendered, with their own ids
+        let entries: Vec<&HistoryEntry> = memory.history.iter().collect();
+        let step = native_step_messages(&entries, &ObservationMarkers::default());
+        assert_eq!(step.len(), 2);
+        assert_eq!(step[0]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(step[1]["tool_call_id"], "call_1");
+
+        // The note follows history as a user message, never as a tool call
+        let messages = memory.build_messages();
+        let calls: Vec<&Value> = messages.iter().filter_map(|m| m["tool_calls"].as_array()).flatten().collect();
+        assert_eq!(calls.len(), 1);
+        assert!(calls.iter().all(|c| c["function"]["name"] != PRUNED_BRANCH && c["id"] != "legacy"));
+        assert!(messages.iter().any(|m| m["role"] == "user"
+            && m["content"].as_str().is_some_and(|c| c.contains("`flaky` failed (2 attempt(s))"))));
+    }
+}
@@ -0,0 +1,95 @@
+use super::{QueuedTask, TaskQueue};
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Inner {
+    ready: VecDeque<QueuedTask>,
+    in_flight: HashMap<String, QueuedTask>,
+    dead: Vec<QueuedTask>,
+    next_id: usize,
+}
+
+/// Process-local queue. Useful for tests and single-binary deployments.
+#[derive(Default)]
+pub struct InMemoryQueue {
+    inner: Mutex<Inner>,
+}
+
+impl InMemoryQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enqueue a task and return its id.
+    pub fn push(&self, task: impl Into<String>) -> String {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = format!("task-{}", inner.next_id);
+        inner.ready.push_back(QueuedTask {
+            id: id.clone(),
+            task: task.into(),
+            attempts: 0,
+            receipt: String::new(),
+        });
+        id
+    }
+
+    /// Tasks waiting to be delivered.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().ready.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Tasks delivered but not yet acked or nacked.
+    pub fn in_flight(&self) -> usize {
+        self.inner.lock().unwrap().in_flight.len()
+    }
+
+    /// Tasks that were nacked without requeue.
+    pub fn dead_letters(&self) -> Vec<QueuedTask> {
+        self.inner.lock().unwrap().dead.clone()
+    }
+}
+
+#[async_trait]
+impl TaskQueue for InMemoryQueue {
+    async fn receive(&self) -> Result<Option<QueuedTask>, String> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut task = match inner.ready.pop_front() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        task.attempts += 1;
+        task.receipt = format!("{}#{}", task.id, task.attempts);
+        inner.in_flight.insert(task.receipt.clone(), task.clone());
+        Ok(Some(task))
+    }
+
+    async fn ack(&self, task: &QueuedTask) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .in_flight
+            .remove(&task.receipt)
+            .map(|_| ())
+            .ok_or_else(|| format!("Unknown receipt '{}'", task.receipt))
+    }
+
+    async fn nack(&self, task: &QueuedTask, requeue: bool) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        let task = inner
+            .in_flight
+            .remove(&task.receipt)
+            .ok_or_else(|| format!("Unknown receipt '{}'", task.receipt))?;
+        if requeue {
+            inner.ready.push_back(task);
+        } else {
+            inner.dead.push(task);
+        }
+        Ok(())
+    }
+}
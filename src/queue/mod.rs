@@ -0,0 +1,273 @@
+//! Queue-backed task ingestion — one agent per queued task.
+//!
+//! `QueueWorker` pulls tasks from a [`TaskQueue`], builds an agent for each
+//! one with a user-supplied factory, and runs up to `concurrency` agents at
+//! a time. A task is acknowledged when its run succeeds. When a run fails,
+//! the task is handed back for another delivery. After `max_attempts`
+//! deliveries it is dead-lettered.
+//!
+//! ```rust,ignore
+//! let queue = Arc::new(InMemoryQueue::new());
+//! queue.push("Summarize ticket #1");
+//!
+//! let stats = QueueWorker::new(queue, |task: &QueuedTask| {
+//!     Ok(AgentBuilder::new(&task.task).openai("").session_id(&task.id))
+//! })
+//! .concurrency(8)
+//! .max_attempts(3)
+//! .drain()
+//! .await;
+//! ```
+//!
+//! Backends: [`InMemoryQueue`], `RedisListQueue` (feature `redis`), and
+//! `SqsQueue` (feature `sqs`).
+
+mod memory;
+#[cfg(feature = "redis")]
+mod redis_list;
+#[cfg(feature = "sqs")]
+mod sqs;
+
+pub use memory::InMemoryQueue;
+#[cfg(feature = "redis")]
+pub use redis_list::RedisListQueue;
+#[cfg(feature = "sqs")]
+pub use sqs::SqsQueue;
+
+use crate::builder::AgentBuilder;
+use crate::error::AgentError;
+use async_trait::async_trait;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Queue interface
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A task delivered by a queue.
+#[derive(Debug, Clone)]
+pub struct QueuedTask {
+    /// Stable id of the message across redeliveries
+    pub id: String,
+    /// The task text (or a payload the agent factory knows how to read)
+    pub task: String,
+    /// Number of times this task has been delivered, including this one
+    pub attempts: u32,
+    /// Backend handle used to ack/nack this delivery
+    pub receipt: String,
+}
+
+/// A source of tasks with at-least-once delivery.
+#[async_trait]
+pub trait TaskQueue: Send + Sync {
+    /// Take the next task, or `None` if the queue is empty right now.
+    async fn receive(&self) -> Result<Option<QueuedTask>, String>;
+
+    /// The task was handled; remove it for good.
+    async fn ack(&self, task: &QueuedTask) -> Result<(), String>;
+
+    /// The task failed. With `requeue` it is delivered again later;
+    /// otherwise it is moved to the backend's dead-letter store.
+    async fn nack(&self, task: &QueuedTask, requeue: bool) -> Result<(), String>;
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Worker
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Builds the agent for one queued task.
+pub type AgentFactory = Arc<dyn Fn(&QueuedTask) -> Result<AgentBuilder, AgentError> + Send + Sync>;
+
+/// Counts of task outcomes over a worker's lifetime.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerStats {
+    /// Runs that succeeded and were acknowledged
+    pub succeeded: usize,
+    /// Failed runs handed back for another attempt
+    pub retried: usize,
+    /// Tasks dead-lettered: out of attempts, or the agent could not be built
+    pub dead_lettered: usize,
+}
+
+enum Outcome {
+    Succeeded,
+    Retried,
+    DeadLettered,
+}
+
+/// Runs one agent per queued task with bounded concurrency.
+pub struct QueueWorker {
+    queue: Arc<dyn TaskQueue>,
+    factory: AgentFactory,
+    concurrency: usize,
+    max_attempts: u32,
+    poll_interval: Duration,
+}
+
+impl QueueWorker {
+    pub fn new<F>(queue: Arc<dyn TaskQueue>, factory: F) -> Self
+    where
+        F: Fn(&QueuedTask) -> Result<AgentBuilder, AgentError> + Send + Sync + 'static,
+    {
+        Self {
+            queue,
+            factory: Arc::new(factory),
+            concurrency: 4,
+            max_attempts: 3,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Maximum number of agents running at once (default 4).
+    pub fn concurrency(mut self, n: usize) -> Self {
+        self.concurrency = n.max(1);
+        self
+    }
+
+    /// Deliveries before a failing task is dead-lettered (default 3).
+    pub fn max_attempts(mut self, n: u32) -> Self {
+        self.max_attempts = n.max(1);
+        self
+    }
+
+    /// How long to wait before polling an empty queue again (default 1s).
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Process tasks until `shutdown` completes, then wait for in-flight runs.
+    pub async fn run_until(&self, shutdown: impl Future<Output = ()>) -> WorkerStats {
+        self.work(Some(shutdown)).await
+    }
+
+    /// Process tasks until the queue is empty and no run is in flight.
+    pub async fn drain(&self) -> WorkerStats {
+        self.work(None::<std::future::Pending<()>>).await
+    }
+
+    async fn work<S: Future<Output = ()>>(&self, shutdown: Option<S>) -> WorkerStats {
+        let mut shutdown = std::pin::pin!(shutdown);
+        let mut stats = WorkerStats::default();
+        let slots = Arc::new(Semaphore::new(self.concurrency));
+        let mut running: JoinSet<Outcome> = JoinSet::new();
+
+        loop {
+            while let Some(done) = running.try_join_next() {
+                record(&mut stats, done);
+            }
+
+            let permit = tokio::select! {
+                permit = slots.clone().acquire_owned() => permit.expect("semaphore is never closed"),
+                _ = wait_for(&mut shutdown) => break,
+            };
+
+            let received = tokio::select! {
+                received = self.queue.receive() => received,
+                _ = wait_for(&mut shutdown) => break,
+            };
+
+            match received {
+                Ok(Some(task)) => {
+                    let queue = self.queue.clone();
+                    let factory = self.factory.clone();
+                    let max_attempts = self.max_attempts;
+                    running.spawn(async move {
+                        let outcome = process(queue, factory, task, max_attempts).await;
+                        drop(permit);
+                        outcome
+                    });
+                }
+                Ok(None) => {
+                    drop(permit);
+                    if shutdown.is_none() && running.is_empty() {
+                        break;
+                    }
+                    // Give in-flight runs a chance to finish (and possibly requeue)
+                    tokio::select! {
+                        _ = tokio::time::sleep(self.poll_interval) => {}
+                        Some(done) = running.join_next() => record(&mut stats, done),
+                        _ = wait_for(&mut shutdown) => break,
+                    }
+                }
+                Err(e) => {
+                    drop(permit);
+                    tracing::warn!(error = %e, "Task queue receive failed");
+                    tokio::select! {
+                        _ = tokio::time::sleep(self.poll_interval) => {}
+                        _ = wait_for(&mut shutdown) => break,
+                    }
+                }
+            }
+        }
+
+        while let Some(done) = running.join_next().await {
+            record(&mut stats, done);
+        }
+        stats
+    }
+}
+
+/// Resolves when the shutdown future does; never in drain mode.
+async fn wait_for<S: Future<Output = ()>>(shutdown: &mut std::pin::Pin<&mut Option<S>>) {
+    match shutdown.as_mut().as_pin_mut() {
+        Some(f) => f.await,
+        None => std::future::pending().await,
+    }
+}
+
+fn record(stats: &mut WorkerStats, done: Result<Outcome, tokio::task::JoinError>) {
+    match done {
+        Ok(Outcome::Succeeded) => stats.succeeded += 1,
+        Ok(Outcome::Retried) => stats.retried += 1,
+        Ok(Outcome::DeadLettered) => stats.dead_lettered += 1,
+        // A panicking agent never acked its task, so the queue redelivers it
+        Err(e) => tracing::error!(error = %e, "Queue task panicked"),
+    }
+}
+
+async fn process(
+    queue: Arc<dyn TaskQueue>,
+    factory: AgentFactory,
+    task: QueuedTask,
+    max_attempts: u32,
+) -> Outcome {
+    let result = match factory(&task).and_then(|b| b.build()) {
+        Ok(mut engine) => engine.run().await,
+        Err(e) => {
+            // A task we cannot build an agent for will never succeed
+            tracing::error!(task = %task.id, error = %e, "Could not build agent for queued task");
+            if let Err(e) = queue.nack(&task, false).await {
+                tracing::warn!(task = %task.id, error = %e, "Task nack failed");
+            }
+            return Outcome::DeadLettered;
+        }
+    };
+
+    match result {
+        Ok(_) => {
+            if let Err(e) = queue.ack(&task).await {
+                tracing::warn!(task = %task.id, error = %e, "Task ack failed");
+            }
+            Outcome::Succeeded
+        }
+        Err(e) => {
+            let requeue = task.attempts < max_attempts;
+            tracing::warn!(
+                task = %task.id, attempt = task.attempts, requeue, error = %e,
+                "Queued task failed"
+            );
+            if let Err(e) = queue.nack(&task, requeue).await {
+                tracing::warn!(task = %task.id, error = %e, "Task nack failed");
+            }
+            if requeue {
+                Outcome::Retried
+            } else {
+                Outcome::DeadLettered
+            }
+        }
+    }
+}
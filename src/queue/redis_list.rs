@@ -0,0 +1,132 @@
+use super::{QueuedTask, TaskQueue};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+/// Stored form of a task in the Redis lists.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    id: String,
+    task: String,
+    /// Deliveries before this one
+    #[serde(default)]
+    attempts: u32,
+}
+
+/// Reliable queue on a Redis list.
+///
+/// Producers `LPUSH` onto `key`. Each delivery atomically moves the message
+/// to `key:processing` with `LMOVE`. If a worker dies mid-run, the message
+/// stays there and can be recovered. Dead letters go to `key:dead`.
+///
+/// Messages are JSON `{"id": "...", "task": "..."}`. A message that is not
+/// valid JSON is taken as the task text.
+pub struct RedisListQueue {
+    conn: ::redis::aio::MultiplexedConnection,
+    key: String,
+}
+
+impl RedisListQueue {
+    pub async fn connect(url: &str, key: impl Into<String>) -> Result<Self, String> {
+        let client = ::redis::Client::open(url).map_err(|e| e.to_string())?;
+        let conn = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(Self { conn, key: key.into() })
+    }
+
+    fn processing_key(&self) -> String {
+        format!("{}:processing", self.key)
+    }
+
+    fn dead_key(&self) -> String {
+        format!("{}:dead", self.key)
+    }
+
+    /// Enqueue a task and return its id.
+    pub async fn push(&self, task: impl Into<String>) -> Result<String, String> {
+        let envelope = Envelope {
+            id: uuid::Uuid::new_v4().to_string(),
+            task: task.into(),
+            attempts: 0,
+        };
+        let payload = serde_json::to_string(&envelope).map_err(|e| e.to_string())?;
+        let mut conn = self.conn.clone();
+        let _: i64 = ::redis::cmd("LPUSH")
+            .arg(&self.key)
+            .arg(payload)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(envelope.id)
+    }
+
+    /// Remove a delivery from the processing list.
+    async fn settle(&self, task: &QueuedTask) -> Result<(), String> {
+        let mut conn = self.conn.clone();
+        let removed: i64 = ::redis::cmd("LREM")
+            .arg(self.processing_key())
+            .arg(1)
+            .arg(&task.receipt)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| e.to_string())?;
+        if removed == 0 {
+            return Err(format!("Task '{}' is not in {}", task.id, self.processing_key()));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TaskQueue for RedisListQueue {
+    async fn receive(&self) -> Result<Option<QueuedTask>, String> {
+        let mut conn = self.conn.clone();
+        let payload: Option<String> = ::redis::cmd("LMOVE")
+            .arg(&self.key)
+            .arg(self.processing_key())
+            .arg("RIGHT")
+            .arg("LEFT")
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(payload.map(|raw| {
+            let envelope = serde_json::from_str::<Envelope>(&raw).unwrap_or_else(|_| Envelope {
+                id: format!("{:x}", sha2::Sha256::digest(raw.as_bytes())),
+                task: raw.clone(),
+                attempts: 0,
+            });
+            QueuedTask {
+                id: envelope.id,
+                task: envelope.task,
+                attempts: envelope.attempts + 1,
+                receipt: raw,
+            }
+        }))
+    }
+
+    async fn ack(&self, task: &QueuedTask) -> Result<(), String> {
+        self.settle(task).await
+    }
+
+    async fn nack(&self, task: &QueuedTask, requeue: bool) -> Result<(), String> {
+        self.settle(task).await?;
+        let envelope = Envelope {
+            id: task.id.clone(),
+            task: task.task.clone(),
+            attempts: task.attempts,
+        };
+        let payload = serde_json::to_string(&envelope).map_err(|e| e.to_string())?;
+        let target = if requeue { self.key.clone() } else { self.dead_key() };
+        let mut conn = self.conn.clone();
+        let _: i64 = ::redis::cmd("LPUSH")
+            .arg(target)
+            .arg(payload)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use super::{QueuedTask, TaskQueue};
+use async_trait::async_trait;
+use aws_sdk_sqs::types::MessageSystemAttributeName;
+
+/// Amazon SQS queue. The message body is the task text.
+///
+/// Failed tasks are made visible again immediately to retry. Dead letters
+/// are sent to `dead_letter_url` if set. Otherwise they are deleted, and
+/// the queue's own redrive policy (if any) never sees them.
+pub struct SqsQueue {
+    client: aws_sdk_sqs::Client,
+    queue_url: String,
+    dead_letter_url: Option<String>,
+    wait_time_seconds: i32,
+}
+
+impl SqsQueue {
+    pub fn new(client: aws_sdk_sqs::Client, queue_url: impl Into<String>) -> Self {
+        Self {
+            client,
+            queue_url: queue_url.into(),
+            dead_letter_url: None,
+            wait_time_seconds: 10,
+        }
+    }
+
+    /// Build a client from the default AWS environment (region, credentials).
+    pub async fn from_env(queue_url: impl Into<String>) -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Self::new(aws_sdk_sqs::Client::new(&config), queue_url)
+    }
+
+    /// Queue that receives tasks that ran out of attempts.
+    pub fn dead_letter_url(mut self, url: impl Into<String>) -> Self {
+        self.dead_letter_url = Some(url.into());
+        self
+    }
+
+    /// Long-poll duration for `receive` (0–20 seconds, default 10).
+    pub fn wait_time_seconds(mut self, seconds: i32) -> Self {
+        self.wait_time_seconds = seconds.clamp(0, 20);
+        self
+    }
+
+    async fn delete(&self, task: &QueuedTask) -> Result<(), String> {
+        self.client
+            .delete_message()
+            .queue_url(&self.queue_url)
+            .receipt_handle(&task.receipt)
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl TaskQueue for SqsQueue {
+    async fn receive(&self) -> Result<Option<QueuedTask>, String> {
+        let output = self
+            .client
+            .receive_message()
+            .queue_url(&self.queue_url)
+            .max_number_of_messages(1)
+            .wait_time_seconds(self.wait_time_seconds)
+            .message_system_attribute_names(MessageSystemAttributeName::ApproximateReceiveCount)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let message = match output.messages().first() {
+            Some(m) => m,
+            None => return Ok(None),
+        };
+        let attempts = message
+            .attributes()
+            .and_then(|a| a.get(&MessageSystemAttributeName::ApproximateReceiveCount))
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+
+        Ok(Some(QueuedTask {
+            id: message.message_id().unwrap_or_default().to_string(),
+            task: message.body().unwrap_or_default().to_string(),
+            attempts,
+            receipt: message
+                .receipt_handle()
+                .ok_or_else(|| "SQS message without receipt handle".to_string())?
+                .to_string(),
+        }))
+    }
+
+    async fn ack(&self, task: &QueuedTask) -> Result<(), String> {
+        self.delete(task).await
+    }
+
+    async fn nack(&self, task: &QueuedTask, requeue: bool) -> Result<(), String> {
+        if requeue {
+            return self
+                .client
+                .change_message_visibility()
+                .queue_url(&self.queue_url)
+                .receipt_handle(&task.receipt)
+                .visibility_timeout(0)
+                .send()
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string());
+        }
+
+        match &self.dead_letter_url {
+            Some(url) => {
+                self.client
+                    .send_message()
+                    .queue_url(url)
+                    .message_body(&task.task)
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            None => tracing::warn!(task = %task.id, "Dropping dead-lettered SQS task (no dead_letter_url)"),
+        }
+        self.delete(task).await
+    }
+}
@@ -405,7 +405,9 @@ mod tests {
             "gpt-4o",
             &LlmResponse::FinalAnswer {
                 content: "test".into(),
+                confidence: 1.0,
                 usage: None,
+                model: None,
             },
         );
         assert!(rec.is_empty()); // Nothing recorded
@@ -416,7 +418,9 @@ mod tests {
         let mut rec = ReplayRecorder::new("s1");
         let resp = LlmResponse::FinalAnswer {
             content: "hello".into(),
+            confidence: 1.0,
             usage: None,
+            model: None,
         };
         rec.record_llm_call(1, "Planning", "gpt-4o", &resp);
         assert_eq!(rec.len(), 1);
@@ -474,7 +478,9 @@ mod tests {
             "gpt-4o",
             &LlmResponse::FinalAnswer {
                 content: "done".into(),
+                confidence: 1.0,
                 usage: None,
+                model: None,
             },
         );
 
@@ -493,7 +499,9 @@ mod tests {
             "gpt-4o",
             &LlmResponse::FinalAnswer {
                 content: "original".into(),
+                confidence: 1.0,
                 usage: None,
+                model: None,
             },
         );
 
@@ -501,7 +509,9 @@ mod tests {
             1,
             Patch::LlmResponse(LlmResponse::FinalAnswer {
                 content: "patched".into(),
+                confidence: 1.0,
                 usage: None,
+                model: None,
             }),
         );
 
@@ -548,7 +558,9 @@ mod tests {
             "gpt-4o",
             &LlmResponse::FinalAnswer {
                 content: "original".into(),
+                confidence: 1.0,
                 usage: None,
+                model: None,
             },
         );
 
@@ -86,7 +86,7 @@ impl RoutingCondition for ToolFailureRateAbove {
         if recent.is_empty() {
             return false;
         }
-        let failures = recent.iter().filter(|h| !h.success).count() as f64;
+        let failures = recent.iter().filter(|h| !h.is_success()).count() as f64;
         let rate = failures / recent.len() as f64;
         rate > self.threshold
     }
@@ -296,7 +296,7 @@ mod tests {
 
     #[test]
     fn test_tool_failure_rate_triggers() {
-        use crate::types::{HistoryEntry, ToolCall};
+        use crate::types::{HistoryEntry, Observation, ToolCall};
         let policy =
             RoutingPolicy::new("gpt-4o-mini").when_tool_failure_rate_above(0.5, 4, "gpt-4o");
         let mut m = make_memory();
@@ -309,8 +309,12 @@ mod tests {
                     name: "search".into(),
                     args: Default::default(),
                 },
-                observation: "ERROR: timeout".into(),
-                success: i == 0, // only first one succeeds
+                // only first one succeeds
+                observation: Observation::from_outcome(i == 0, "timeout"),
+                assistant_text: None,
+                latency_ms: None,
+                model_used: None,
+                usage: None,
             });
         }
         assert_eq!(policy.resolve(&m), "gpt-4o");
@@ -0,0 +1,566 @@
+//! Visualizer Server — a web page that draws the agent graph and highlights
+//! the state a run is in (feature `server`).
+//!
+//! A [`Visualizer`] serves one engine's [`GraphShape`] and follows its
+//! [`AgentHandle`]. The page renders the Mermaid export of the graph and
+//! redraws it with the current state highlighted each time the status
+//! stream reports a step.
+//!
+//! ```rust,ignore
+//! let visualizer = Visualizer::new(&engine);
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:7878").await?;
+//! tokio::spawn(visualizer.serve(listener));
+//! engine.run().await?;  // open http://127.0.0.1:7878 while it runs
+//! ```
+//!
+//! | Route | Response |
+//! |---|---|
+//! | `GET /` | The page |
+//! | `GET /graph.mmd?current=<state>` | [`GraphShape::to_mermaid`] |
+//! | `GET /graph.dot?current=<state>` | [`GraphShape::to_dot`] |
+//! | `GET /status` | The latest status as JSON |
+//! | `GET /events` | The status as server-sent events, one per step, until the run finishes |
+//! | `GET /outputs` | Each [`AgentOutput`] published to the [`OutputFeed`] as a server-sent event, until a final answer or error (404 without a feed) |
+//!
+//! `agentsm monitor http://127.0.0.1:7878/outputs` renders the `/outputs`
+//! stream in the terminal monitor.
+//!
+//! An [`EventWebhook`] resumes suspended sessions of a [`SessionManager`]
+//! when the events they wait for arrive:
+//!
+//! | Route | Response |
+//! |---|---|
+//! | `POST /sessions/<id>/events` | `202 Accepted` once the session runs again with the JSON body as its event payload; `409 Conflict` if it is running, not suspended or unknown |
+//!
+//! These are plain HTTP/1.1 listeners for demos and debugging: no TLS, no
+//! authentication. Bind them to localhost. The page loads Mermaid from a CDN.
+
+use crate::builder::AgentBuilder;
+use crate::checkpoint::GraphShape;
+use crate::engine::AgentEngine;
+use crate::handle::{AgentHandle, AgentStatus};
+use crate::sessions::SessionManager;
+use crate::types::AgentOutput;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+const PAGE: &str = include_str!("server/visualizer.html");
+
+/// Longest request head read before the connection is dropped
+const MAX_HEAD: usize = 8 * 1024;
+/// Longest request body read before the connection is dropped
+const MAX_BODY: usize = 1024 * 1024;
+/// Outputs an `/outputs` client may fall behind by before it skips some
+const FEED_CAPACITY: usize = 1024;
+
+/// Broadcasts a run's [`AgentOutput`] to `/outputs` clients. Cheap to clone.
+///
+/// ```rust,ignore
+/// let feed = OutputFeed::new();
+/// tokio::spawn(Visualizer::new(&engine).with_outputs(feed.clone()).serve(listener));
+/// let mut outputs = engine.run_streaming();
+/// while let Some(output) = outputs.next().await {
+///     feed.publish(&output);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct OutputFeed {
+    tx: broadcast::Sender<AgentOutput>,
+}
+
+impl OutputFeed {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(FEED_CAPACITY).0,
+        }
+    }
+
+    /// Send `output` to every connected client. Without clients it is dropped.
+    pub fn publish(&self, output: &AgentOutput) {
+        let _ = self.tx.send(output.clone());
+    }
+}
+
+impl Default for OutputFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serves the graph page for one engine. Cheap to clone.
+#[derive(Clone)]
+pub struct Visualizer {
+    graph: Arc<GraphShape>,
+    session_id: Arc<str>,
+    handle: AgentHandle,
+    outputs: Option<OutputFeed>,
+}
+
+impl Visualizer {
+    /// Follow `engine`. Take it before the run starts, like `engine.handle()`.
+    pub fn new(engine: &AgentEngine) -> Self {
+        Self::from_parts(engine.graph_shape(), engine.session_id.clone(), engine.handle())
+    }
+
+    pub fn from_parts(graph: GraphShape, session_id: impl Into<String>, handle: AgentHandle) -> Self {
+        Self {
+            graph: Arc::new(graph),
+            session_id: session_id.into().into(),
+            handle,
+            outputs: None,
+        }
+    }
+
+    /// Serve what is published to `feed` at `/outputs`.
+    pub fn with_outputs(mut self, feed: OutputFeed) -> Self {
+        self.outputs = Some(feed);
+        self
+    }
+
+    /// Answer requests on `listener` until the task is dropped. Each
+    /// connection is handled on its own task.
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let visualizer = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = visualizer.respond(stream).await {
+                    tracing::debug!(error = %e, "Visualizer request failed");
+                }
+            });
+        }
+    }
+
+    async fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        let request = read_request(&mut stream).await?;
+        if request.method != "GET" {
+            return write_response(&mut stream, "405 Method Not Allowed", "text/plain", "GET only\n").await;
+        }
+        let (path, current) = split_target(&request.target);
+        match path {
+            "/" => write_response(&mut stream, "200 OK", "text/html; charset=utf-8", PAGE).await,
+            "/graph.mmd" => {
+                let body = self.graph.to_mermaid(current.as_deref());
+                write_response(&mut stream, "200 OK", "text/plain; charset=utf-8", &body).await
+            }
+            "/graph.dot" => {
+                let body = self.graph.to_dot(current.as_deref());
+                write_response(&mut stream, "200 OK", "text/vnd.graphviz; charset=utf-8", &body).await
+            }
+            "/status" => {
+                let body = self.status_json(&self.handle.status()).to_string();
+                write_response(&mut stream, "200 OK", "application/json", &body).await
+            }
+            "/events" => self.stream_events(stream).await,
+            "/outputs" => match &self.outputs {
+                Some(feed) => stream_outputs(feed.tx.subscribe(), stream).await,
+                None => write_response(&mut stream, "404 Not Found", "text/plain", "No output feed\n").await,
+            },
+            _ => write_response(&mut stream, "404 Not Found", "text/plain", "Not found\n").await,
+        }
+    }
+
+    /// One event now and one per step after it; the stream ends when the
+    /// run finishes or the engine is dropped.
+    async fn stream_events(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            )
+            .await?;
+        let mut rx = self.handle.receiver();
+        loop {
+            let status = rx.borrow_and_update().clone();
+            let event = format!("data: {}\n\n", self.status_json(&status));
+            stream.write_all(event.as_bytes()).await?;
+            if status.finished || rx.changed().await.is_err() {
+                return stream.shutdown().await;
+            }
+        }
+    }
+
+    fn status_json(&self, status: &AgentStatus) -> serde_json::Value {
+        serde_json::json!({
+            "session_id": &*self.session_id,
+            "state": status.state.as_str(),
+            "step": status.step,
+            "total_tokens": status.usage.total_tokens,
+            "cost": status.cost,
+            "finished": status.finished,
+        })
+    }
+}
+
+/// Outputs published from now on; the stream ends after a final answer or
+/// error. A client too slow to keep up skips what it missed.
+async fn stream_outputs(mut rx: broadcast::Receiver<AgentOutput>, mut stream: TcpStream) -> io::Result<()> {
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        )
+        .await?;
+    loop {
+        let output = match rx.recv().await {
+            Ok(output) => output,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return stream.shutdown().await,
+        };
+        let json = serde_json::to_string(&output).map_err(io::Error::other)?;
+        stream.write_all(format!("data: {}\n\n", json).as_bytes()).await?;
+        if matches!(output, AgentOutput::FinalAnswer(_) | AgentOutput::Error(_)) {
+            return stream.shutdown().await;
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// EventWebhook
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Resumes suspended sessions when the events they wait for arrive, with
+/// [`SessionManager::resume_with_event`]. Cheap to clone.
+///
+/// ```rust,ignore
+/// let webhook = EventWebhook::new(sessions.clone(), |_id| AgentBuilder::new("").openai("").wait_tool());
+/// tokio::spawn(webhook.serve(TcpListener::bind("127.0.0.1:7879").await?));
+/// // curl -X POST localhost:7879/sessions/ci/events -d '{"status":"green"}'
+/// ```
+#[derive(Clone)]
+pub struct EventWebhook {
+    sessions: Arc<SessionManager>,
+    builder: Arc<dyn Fn(&str) -> AgentBuilder + Send + Sync>,
+}
+
+impl EventWebhook {
+    /// `builder` supplies the LLM, tools and hooks for a session id, as for
+    /// `SessionManager::resume`.
+    pub fn new(
+        sessions: Arc<SessionManager>,
+        builder: impl Fn(&str) -> AgentBuilder + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            sessions,
+            builder: Arc::new(builder),
+        }
+    }
+
+    /// Answer requests on `listener` until the task is dropped. Each
+    /// connection is handled on its own task.
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let webhook = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = webhook.respond(stream).await {
+                    tracing::debug!(error = %e, "Webhook request failed");
+                }
+            });
+        }
+    }
+
+    async fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        let request = read_request(&mut stream).await?;
+        let (path, _) = split_target(&request.target);
+        let Some(session_id) = path
+            .strip_prefix("/sessions/")
+            .and_then(|rest| rest.strip_suffix("/events"))
+            .filter(|id| !id.is_empty() && !id.contains('/'))
+            .map(percent_decode)
+        else {
+            return write_response(&mut stream, "404 Not Found", "text/plain", "Not found\n").await;
+        };
+        if request.method != "POST" {
+            return write_response(&mut stream, "405 Method Not Allowed", "text/plain", "POST only\n").await;
+        }
+        let payload: serde_json::Value = match serde_json::from_slice(&request.body) {
+            Ok(payload) => payload,
+            Err(e) => {
+                let body = format!("Invalid JSON payload: {}\n", e);
+                return write_response(&mut stream, "400 Bad Request", "text/plain", &body).await;
+            }
+        };
+
+        // The resumed run's outputs are not kept; watch it with `SessionManager::handle`
+        let builder = (self.builder)(&session_id);
+        match self.sessions.resume_with_event(&session_id, payload, builder).await {
+            Ok(_outputs) => {
+                let body = serde_json::json!({ "session_id": session_id }).to_string();
+                write_response(&mut stream, "202 Accepted", "application/json", &body).await
+            }
+            Err(e) => {
+                let body = serde_json::json!({ "session_id": session_id, "error": e.to_string() }).to_string();
+                write_response(&mut stream, "409 Conflict", "application/json", &body).await
+            }
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// HTTP
+// ─────────────────────────────────────────────────────────────────────────────
+
+struct Request {
+    method: String,
+    target: String,
+    body: Vec<u8>,
+}
+
+/// Read a request's head and, if it has a `Content-Length`, its body.
+async fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let head_len = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if data.len() > MAX_HEAD {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request head too large"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        data.extend_from_slice(&chunk[..n]);
+    };
+    let head = String::from_utf8_lossy(&data[..head_len]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default().to_string();
+    let length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request body too large"));
+    }
+
+    let mut body = data.split_off(head_len);
+    while body.len() < length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(length);
+    Ok(Request { method, target, body })
+}
+
+async fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Path and decoded `current` query parameter of a request target.
+fn split_target(target: &str) -> (&str, Option<String>) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let current = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("current="))
+        .map(percent_decode)
+        .filter(|s| !s.is_empty());
+    (path, current)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = |b: u8| (b as char).to_digit(16);
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        out.push((high * 16 + low) as u8);
+                        i += 2;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::AgentBuilder;
+    use crate::llm::MockLlmCaller;
+
+    async fn get(addr: std::net::SocketAddr, target: &str, until: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        let mut chunk = [0u8; 4096];
+        while !String::from_utf8_lossy(&response).contains(until) {
+            let n = stream.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            response.extend_from_slice(&chunk[..n]);
+        }
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    #[test]
+    fn test_split_target() {
+        assert_eq!(split_target("/graph.mmd"), ("/graph.mmd", None));
+        assert_eq!(
+            split_target("/graph.dot?x=1&current=Wait%3A%20CI"),
+            ("/graph.dot", Some("Wait: CI".to_string()))
+        );
+        assert_eq!(percent_decode("a+b%2"), "a b%2");
+    }
+
+    #[tokio::test]
+    async fn test_serves_graph_and_status() {
+        let engine = AgentBuilder::new("task")
+            .llm(Arc::new(MockLlmCaller::new(vec![])))
+            .session_id("demo")
+            .build()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Visualizer::new(&engine).serve(listener));
+
+        let page = get(addr, "/", "</html>").await;
+        assert!(page.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(page.contains("new EventSource(\"/events\")"));
+
+        let mermaid = get(addr, "/graph.mmd?current=Planning", "class Planning current").await;
+        assert!(mermaid.contains("    [*] --> Idle\n"));
+        assert!(mermaid.contains("    class Planning current\n"));
+
+        let events = get(addr, "/events", "\n\n").await;
+        assert!(events.contains("Content-Type: text/event-stream"));
+        assert!(events.contains("\"session_id\":\"demo\""));
+        assert!(events.contains("\"state\":\"Idle\""));
+
+        assert!(get(addr, "/nope", "\r\n\r\n").await.starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn test_streams_published_outputs() {
+        let engine = AgentBuilder::new("task")
+            .llm(Arc::new(MockLlmCaller::new(vec![])))
+            .build()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Visualizer::new(&engine).serve(listener));
+        assert!(get(addr, "/outputs", "\r\n\r\n").await.starts_with("HTTP/1.1 404"));
+
+        let feed = OutputFeed::new();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Visualizer::new(&engine).with_outputs(feed.clone()).serve(listener));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /outputs HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut head = [0u8; 1];
+        stream.read_exact(&mut head).await.unwrap();
+
+        feed.publish(&AgentOutput::LlmToken("Hel".into()));
+        feed.publish(&AgentOutput::FinalAnswer("Hello".into()));
+        feed.publish(&AgentOutput::LlmToken("after".into()));
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.contains("Content-Type: text/event-stream"));
+        assert!(response.ends_with("data: {\"LlmToken\":\"Hel\"}\n\ndata: {\"FinalAnswer\":\"Hello\"}\n\n"));
+    }
+
+    async fn post(addr: std::net::SocketAddr, target: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            target,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_webhook_resumes_session_with_event() {
+        use crate::checkpoint::{CheckpointStore, MemoryCheckpointStore};
+        use crate::types::{LlmResponse, ToolCall};
+
+        let store = Arc::new(MemoryCheckpointStore::new());
+        let sessions = Arc::new(SessionManager::with_store(store.clone()));
+        let builder = |responses: Vec<LlmResponse>| {
+            AgentBuilder::new("task")
+                .llm(Arc::new(MockLlmCaller::new(responses)))
+                .session_id("ci")
+                .wait_tool()
+        };
+
+        let wait = LlmResponse::ToolCall {
+            tool: ToolCall {
+                name: "wait_until".to_string(),
+                args: serde_json::from_value(serde_json::json!({ "event": "ci_finished" })).unwrap(),
+                id: None,
+            },
+            confidence: 1.0,
+            assistant_text: None,
+            usage: None,
+            model: None,
+        };
+        let (id, _outputs) = sessions.start(builder(vec![wait])).unwrap();
+        let mut handle = sessions.handle(&id).unwrap();
+        while handle.changed().await {}
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let webhook = EventWebhook::new(sessions.clone(), move |_id| {
+            builder(vec![LlmResponse::final_answer("CI is green, merged.")])
+        });
+        tokio::spawn(webhook.serve(listener));
+
+        assert!(post(addr, "/sessions/ci/events", "not json").await.starts_with("HTTP/1.1 400"));
+        assert!(get(addr, "/sessions/ci/events", "\r\n\r\n").await.starts_with("HTTP/1.1 405"));
+
+        let response = post(addr, "/sessions/ci/events", r#"{"status":"green"}"#).await;
+        assert!(response.starts_with("HTTP/1.1 202 Accepted\r\n"));
+        assert!(response.ends_with(r#"{"session_id":"ci"}"#));
+
+        let mut handle = sessions.handle(&id).unwrap();
+        while handle.changed().await {}
+        let reaped = sessions.reap().await;
+        assert_eq!(reaped[0].1.as_ref().unwrap().answer, "CI is green, merged.");
+        let memory = store.load_latest(&id).await.unwrap().unwrap().memory;
+        assert_eq!(
+            &*memory.history.last().unwrap().observation.content,
+            r#"Event 'ci_finished' arrived: {"status":"green"}"#
+        );
+
+        // Only suspended sessions take events
+        let response = post(addr, "/sessions/ci/events", "{}").await;
+        assert!(response.starts_with("HTTP/1.1 409 Conflict\r\n"));
+        assert!(post(addr, "/status", "{}").await.starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,310 @@
+//! Session Manager — many live agents in one process.
+//!
+//! A [`SessionManager`] starts agents on background tasks and tracks them by
+//! session id. Callers look up an [`AgentHandle`] to watch a session, cancel
+//! it, list what is running, and reap sessions that finished.
+//!
+//! With a checkpoint store, the manager sets it on every agent it starts,
+//! so each step is checkpointed. `evict` stops a session to free its task
+//! and memory; `resume` rehydrates it from its latest checkpoint later. This
+//! is the layer an HTTP or gRPC front end sits on.
+//!
+//! Agents with the `wait_until` tool suspend themselves (see
+//! [`crate::suspend`]); `suspended` lists them, `resume_due` resumes
+//! those whose wake-up time has passed and `resume_with_event` those whose
+//! event arrived.
+//!
+//! ```rust,ignore
+//! let sessions = SessionManager::with_store(Arc::new(SqliteCheckpointStore::new("agents.db")?));
+//!
+//! let (id, mut outputs) = sessions.start(AgentBuilder::new("Summarize the report").openai(""))?;
+//! let handle = sessions.handle(&id).unwrap();
+//!
+//! sessions.evict(&id)?;                                        // free it, keep the checkpoint
+//! let outputs = sessions.resume(&id, AgentBuilder::new("").openai("")).await?;
+//! ```
+
+use crate::builder::AgentBuilder;
+use crate::checkpoint::CheckpointStore;
+use crate::engine::{AgentEngine, OutputReceiver};
+use crate::error::AgentError;
+use crate::handle::AgentHandle;
+use crate::suspend::Suspension;
+use crate::types::{RunResult, State};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+/// A running or finished session, as listed by `SessionManager::list`.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub state: State,
+    pub step: usize,
+    pub finished: bool,
+    pub started_at: DateTime<Utc>,
+}
+
+struct LiveSession {
+    handle: AgentHandle,
+    join: JoinHandle<Result<RunResult, AgentError>>,
+    started_at: DateTime<Utc>,
+}
+
+/// Tracks agents running on background tasks by session id.
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: Mutex<HashMap<String, LiveSession>>,
+    store: Option<Arc<dyn CheckpointStore>>,
+}
+
+impl SessionManager {
+    /// A manager without a checkpoint store. `evict` and `resume` are unavailable.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A manager that checkpoints every agent it starts into `store`.
+    pub fn with_store(store: Arc<dyn CheckpointStore>) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            store: Some(store),
+        }
+    }
+
+    /// Build the agent and run it on a background task.
+    /// Returns its session id and the receiver of its outputs.
+    pub fn start(&self, builder: AgentBuilder) -> Result<(String, OutputReceiver), AgentError> {
+        let builder = match &self.store {
+            Some(store) => builder.checkpoint_store(Arc::clone(store)),
+            None => builder,
+        };
+        let engine = builder.build()?;
+        let id = engine.session_id.clone();
+        let outputs = self.launch(engine)?;
+        Ok((id, outputs))
+    }
+
+    /// Rehydrate a session from its latest checkpoint and run it again.
+    /// `builder` supplies what checkpoints do not hold: the LLM, tools and hooks.
+    pub async fn resume(&self, session_id: &str, builder: AgentBuilder) -> Result<OutputReceiver, AgentError> {
+        let store = self.require_store()?;
+        if self.sessions.lock().unwrap().contains_key(session_id) {
+            return Err(AgentError::BuildError(format!(
+                "Session {} is already running",
+                session_id
+            )));
+        }
+        let engine = builder
+            .checkpoint_store(store)
+            .resume(session_id)
+            .await?
+            .build()?;
+        self.launch(engine)
+    }
+
+    fn launch(&self, engine: AgentEngine) -> Result<OutputReceiver, AgentError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.contains_key(&engine.session_id) {
+            return Err(AgentError::BuildError(format!(
+                "Session {} is already running",
+                engine.session_id
+            )));
+        }
+        let id = engine.session_id.clone();
+        let (handle, outputs, join) = engine.spawn();
+        sessions.insert(
+            id,
+            LiveSession {
+                handle,
+                join,
+                started_at: Utc::now(),
+            },
+        );
+        Ok(outputs)
+    }
+
+    fn require_store(&self) -> Result<Arc<dyn CheckpointStore>, AgentError> {
+        self.store.clone().ok_or_else(|| {
+            AgentError::BuildError("SessionManager has no checkpoint store".to_string())
+        })
+    }
+
+    /// Live status of a tracked session.
+    pub fn handle(&self, session_id: &str) -> Option<AgentHandle> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|s| s.handle.clone())
+    }
+
+    /// Abort a session and stop tracking it. Returns false if it was not tracked.
+    pub fn cancel(&self, session_id: &str) -> bool {
+        match self.sessions.lock().unwrap().remove(session_id) {
+            Some(session) => {
+                session.join.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop a session to free its task and memory, keeping its checkpoint
+    /// for `resume`. Work since the last completed step is redone on resume.
+    pub fn evict(&self, session_id: &str) -> Result<(), AgentError> {
+        self.require_store()?;
+        if self.cancel(session_id) {
+            Ok(())
+        } else {
+            Err(AgentError::BuildError(format!("Session {} is not running", session_id)))
+        }
+    }
+
+    /// All tracked sessions, oldest first.
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let mut list: Vec<SessionInfo> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, s)| {
+                let status = s.handle.status();
+                SessionInfo {
+                    session_id: id.clone(),
+                    state: status.state,
+                    step: status.step,
+                    finished: s.join.is_finished(),
+                    started_at: s.started_at,
+                }
+            })
+            .collect();
+        list.sort_by_key(|s| s.started_at);
+        list
+    }
+
+    /// Sessions in the checkpoint store, running or not.
+    pub async fn stored_sessions(&self) -> Result<Vec<String>, AgentError> {
+        self.require_store()?
+            .list_sessions()
+            .await
+            .map_err(AgentError::MemoryError)
+    }
+
+    /// Stored sessions that are suspended and not running, with what each
+    /// waits for.
+    pub async fn suspended(&self) -> Result<Vec<(String, Suspension)>, AgentError> {
+        let store = self.require_store()?;
+        let mut suspended = Vec::new();
+        for id in store.list_sessions().await.map_err(AgentError::MemoryError)? {
+            if self.is_running(&id) {
+                continue;
+            }
+            let checkpoint = store.load_latest(&id).await.map_err(AgentError::MemoryError)?;
+            if let Some(suspension) = checkpoint.and_then(|c| c.memory.suspension) {
+                suspended.push((id, suspension));
+            }
+        }
+        Ok(suspended)
+    }
+
+    /// Resume every suspended session whose wake-up time has passed.
+    /// `builder` supplies the LLM, tools and hooks for a session id, as for
+    /// `resume`. Call it periodically; returns the resumed sessions' outputs.
+    pub async fn resume_due(
+        &self,
+        builder: impl Fn(&str) -> AgentBuilder,
+    ) -> Result<Vec<(String, OutputReceiver)>, AgentError> {
+        let now = Utc::now();
+        let mut resumed = Vec::new();
+        for (id, suspension) in self.suspended().await? {
+            if !suspension.is_due(now) {
+                continue;
+            }
+            self.forget_finished(&id);
+            let outputs = self.resume(&id, builder(&id)).await?;
+            resumed.push((id, outputs));
+        }
+        Ok(resumed)
+    }
+
+    /// Resume a suspended session because the event it waits for happened,
+    /// e.g. from a webhook. `payload` becomes the observation of its
+    /// `wait_until` call. Fails if the session is running or not suspended.
+    ///
+    /// With the `server` feature, `server::EventWebhook` serves this as
+    /// `POST /sessions/<id>/events`.
+    pub async fn resume_with_event(
+        &self,
+        session_id: &str,
+        payload: serde_json::Value,
+        builder: AgentBuilder,
+    ) -> Result<OutputReceiver, AgentError> {
+        let store = self.require_store()?;
+        if self.is_running(session_id) {
+            return Err(AgentError::BuildError(format!(
+                "Session {} is already running",
+                session_id
+            )));
+        }
+        let mut engine = builder
+            .checkpoint_store(store)
+            .resume(session_id)
+            .await?
+            .build()?;
+        engine.deliver_event(payload)?;
+        self.forget_finished(session_id);
+        self.launch(engine)
+    }
+
+    /// Stop tracking a session whose task ended without being reaped. For a
+    /// suspended session, its result is the `Suspended` error.
+    fn forget_finished(&self, session_id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(session_id).is_some_and(|s| s.join.is_finished()) {
+            sessions.remove(session_id);
+        }
+    }
+
+    fn is_running(&self, session_id: &str) -> bool {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .is_some_and(|s| !s.join.is_finished())
+    }
+
+    /// Stop tracking sessions whose task has ended and return their results.
+    pub async fn reap(&self) -> Vec<(String, Result<RunResult, AgentError>)> {
+        let finished: Vec<(String, LiveSession)> = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let ids: Vec<String> = sessions
+                .iter()
+                .filter(|(_, s)| s.join.is_finished())
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.into_iter()
+                .filter_map(|id| sessions.remove(&id).map(|s| (id, s)))
+                .collect()
+        };
+        let mut results = Vec::with_capacity(finished.len());
+        for (id, session) in finished {
+            let result = session
+                .join
+                .await
+                .unwrap_or_else(|e| Err(AgentError::AgentFailed(format!("Session task failed: {}", e))));
+            results.push((id, result));
+        }
+        results
+    }
+
+    /// Number of tracked sessions.
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
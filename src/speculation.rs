@@ -0,0 +1,443 @@
+//! Speculative Planning — ask for the next step while a slow tool runs.
+//!
+//! In a chain of slow tools, each tool run and each planning call wait for
+//! the one before. With [`SpeculativePlanning`] configured, Acting makes the
+//! next planning call while a slow tool runs, with a [`Guess`] standing in
+//! for its observation:
+//!
+//! - a call that repeats an earlier successful one (same tool, same
+//!   arguments) is shown the earlier result. Planning keeps the prepared
+//!   response if the real observation is at least `threshold` similar to it
+//!   (see [`similarity`]).
+//! - any other call is shown a note that its result is still pending.
+//!   Planning keeps the prepared response if the tool succeeded and the
+//!   model moved on to a different tool call — a step that did not need the
+//!   result. An answer, or the same call again, is dropped.
+//!
+//! A kept response logs `SPECULATION_HIT`. A dropped one logs
+//! `SPECULATION_DISCARDED` and Planning calls the LLM as usual; its tokens
+//! still count toward the budget and are reported with `AgentOutput::Usage`.
+//! When the tool finishes first with a result no response could hold for,
+//! the prepared call is cancelled before it returns. Its tokens are then
+//! unknown and not counted.
+//!
+//! A tool is expected to be slow when it is listed with
+//! [`SpeculativePlanning::slow_tool`], or when its latest run took at least
+//! `min_latency_ms`; for fast tools the extra call costs more than it saves.
+//! The prepared call goes through the same checks and hooks as a planning
+//! call: nothing is speculated on over budget, in plan mode, while an
+//! explicit plan is active, while deferred calls are queued, or when the
+//! step would trigger reflection or exceed `max_steps`, and every
+//! `on_llm_start` is followed by `on_llm_end` or `on_llm_error`. The
+//! prepared call is not streamed, so a kept response arrives without
+//! `LlmToken` events.
+
+use crate::llm::AsyncLlmCaller;
+use crate::memory::AgentMemory;
+use crate::states::PlanningState;
+use crate::tools::ToolRegistry;
+use crate::types::{AgentOutput, HistoryEntry, LlmResponse, Observation, ToolCall};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::{JoinError, JoinHandle};
+
+/// Configuration of speculative planning. Set with `AgentBuilder::speculative_planning`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeculativePlanning {
+    /// Similarity between a repeated call's earlier and real observation
+    /// needed to keep the prepared response (0.0–1.0)
+    pub threshold: f64,
+    /// A tool whose latest run took at least this long is expected to be slow
+    #[serde(default)]
+    pub min_latency_ms: u64,
+    /// Tools expected to be slow from their first call
+    #[serde(default)]
+    pub slow_tools: Vec<String>,
+}
+
+impl Default for SpeculativePlanning {
+    fn default() -> Self {
+        Self {
+            threshold: 0.9,
+            min_latency_ms: 1000,
+            slow_tools: Vec::new(),
+        }
+    }
+}
+
+impl SpeculativePlanning {
+    /// Threshold 0.9, for tools that took a second or more.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn min_latency_ms(mut self, ms: u64) -> Self {
+        self.min_latency_ms = ms;
+        self
+    }
+
+    /// Speculate on `name` before any of its runs has been timed.
+    pub fn slow_tool(mut self, name: impl Into<String>) -> Self {
+        self.slow_tools.push(name.into());
+        self
+    }
+}
+
+/// What the prepared planning call saw in place of the observation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Guess {
+    /// The result of an earlier run of the same call
+    Repeat(String),
+    /// A note that the result is still pending
+    Pending,
+}
+
+impl Guess {
+    fn observation(&self, call: &ToolCall) -> String {
+        match self {
+            Guess::Repeat(text) => text.clone(),
+            Guess::Pending => format!(
+                "'{}' is still running; its result will be shown on the next step. \
+                 Continue with a step that does not depend on it, if there is one.",
+                call.name
+            ),
+        }
+    }
+}
+
+/// A planning response prepared while `call` ran, kept in `memory.speculation`
+/// until the next Planning state checks it.
+#[derive(Debug, Clone)]
+pub struct Speculation {
+    /// The call whose observation was guessed
+    pub call: ToolCall,
+    pub guess: Guess,
+    /// Model the response came from
+    pub model: String,
+    /// Length of history with the guessed entry committed
+    pub history_len: usize,
+    pub response: LlmResponse,
+}
+
+/// Share of distinct words the two texts have in common, ignoring case
+/// (1.0 for texts that only differ in case and whitespace).
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a = a.to_lowercase();
+    let b = b.to_lowercase();
+    let a: HashSet<&str> = a.split_whitespace().collect();
+    let b: HashSet<&str> = b.split_whitespace().collect();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+/// The guess to plan against while `call` runs, or None if its tool is not
+/// expected to be slow.
+fn guess(memory: &AgentMemory, config: &SpeculativePlanning, call: &ToolCall) -> Option<Guess> {
+    let slow = config.slow_tools.contains(&call.name)
+        || memory
+            .history
+            .iter()
+            .rev()
+            .find(|h| h.tool.name == call.name)
+            .is_some_and(|h| h.latency_ms.unwrap_or(0) >= config.min_latency_ms);
+    if !slow {
+        return None;
+    }
+    let earlier = memory
+        .history
+        .iter()
+        .rev()
+        .find(|h| h.tool.name == call.name && h.tool.args == call.args)
+        .filter(|h| h.is_success());
+    Some(match earlier {
+        Some(h) => Guess::Repeat(crate::dedup::split_marker(&h.observation.content).0.to_string()),
+        None => Guess::Pending,
+    })
+}
+
+/// Memory as the next Planning state will see it, with `observation`
+/// committed as the result of `call`. None if that step would not call the
+/// LLM.
+fn predict(memory: &AgentMemory, call: &ToolCall, observation: &str) -> Option<AgentMemory> {
+    if !memory.deferred_tool_calls.is_empty()
+        || memory.current_plan.as_ref().is_some_and(|p| !p.is_complete())
+        || memory.budget.is_some_and(|b| b.is_exceeded(memory.total_usage))
+    {
+        return None;
+    }
+    let mut predicted = memory.clone();
+    predicted.current_tool_call = None;
+    predicted.last_observation = None;
+    let entry = HistoryEntry {
+        step: predicted.step,
+        tool: call.clone(),
+        observation: Observation::success(observation),
+        assistant_text: predicted.current_assistant_text.take(),
+        latency_ms: None,
+        model_used: predicted.current_model.take(),
+        usage: predicted.current_usage.take(),
+    };
+    crate::dedup::push_history(&mut predicted, entry);
+    if predicted.reflection_reason().is_some() || predicted.step >= predicted.config.max_steps {
+        return None;
+    }
+    predicted.step += 1;
+    Some(predicted)
+}
+
+/// Whether a response prepared against `guess` still holds for the real
+/// result of `call`: the similarity score for a repeat, or `None` if it does
+/// not hold.
+fn holds(guess: &Guess, call: &ToolCall, result: &str, response: &LlmResponse, threshold: f64) -> Option<f64> {
+    match guess {
+        Guess::Repeat(text) => Some(similarity(result, text)).filter(|score| *score >= threshold),
+        // An action that does not wait for the pending result. Providers give
+        // every call a new id, so a repeat is the same name and arguments.
+        Guess::Pending => {
+            let same = |tool: &ToolCall| tool.name == call.name && tool.args == call.args;
+            match response {
+                LlmResponse::ToolCall { tool, .. } if !same(tool) => Some(1.0),
+                LlmResponse::ParallelToolCalls { tools, .. } if !tools.iter().any(same) => Some(1.0),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Wait for `run`, the tool task of `call`, and return its result with how
+/// long the tool took. If the tool is expected to be slow, the next planning
+/// call is made meanwhile against a guess at its result, and its response
+/// left in `memory.speculation`. A planning call that outlasts the tool does
+/// not count toward the tool's time.
+pub(crate) async fn run_alongside(
+    memory: &mut AgentMemory,
+    tools: &Arc<ToolRegistry>,
+    llm: &dyn AsyncLlmCaller,
+    call: &ToolCall,
+    run: JoinHandle<Result<String, String>>,
+) -> (Result<Result<String, String>, JoinError>, Duration) {
+    let started = Instant::now();
+    let run = async move {
+        let joined = run.await;
+        (joined, started.elapsed())
+    };
+    tokio::pin!(run);
+    let Some(config) = memory.config.speculative_planning.clone() else {
+        return run.await;
+    };
+    let Some(guess) = guess(memory, &config, call) else {
+        return run.await;
+    };
+    let Some(mut predicted) = predict(memory, call, &guess.observation(call)) else {
+        return run.await;
+    };
+    let model = PlanningState.resolve_model(&predicted);
+    predicted.current_model = Some(model.clone());
+    predicted.prepare_prompt(tools);
+    let history_len = predicted.history.len();
+
+    // Text prompters describe the tools themselves
+    let no_tools = ToolRegistry::new();
+    let offered = if predicted.planning_prompter.native_tools() {
+        tools.as_ref()
+    } else {
+        &no_tools
+    };
+
+    memory.log(
+        "Acting",
+        "SPECULATION_START",
+        &format!(
+            "tool='{}' model={} guess={}",
+            call.name,
+            model,
+            if guess == Guess::Pending { "pending" } else { "repeat" }
+        ),
+    );
+    // Hook: on_llm_start
+    memory.hooks.on_llm_start(&model, &predicted);
+    let (joined, response) = {
+        let planning = llm.call_async(&predicted, offered, &model, None);
+        tokio::pin!(planning);
+        tokio::select! {
+            // Start the planning call before looking at the tool
+            biased;
+            response = &mut planning => (run.await, Some(response)),
+            joined = &mut run => {
+                // The tool finished first: a failed call or a differing
+                // repeat cannot keep the response, so stop waiting for it
+                let wait = match (&joined.0, &guess) {
+                    (Ok(Ok(output)), Guess::Repeat(text)) => similarity(output, text) >= config.threshold,
+                    (Ok(Ok(_)), Guess::Pending) => true,
+                    _ => false,
+                };
+                let response = if wait { Some(planning.await) } else { None };
+                (joined, response)
+            }
+        }
+    };
+
+    match response {
+        Some(Ok(response)) => {
+            memory.speculation = Some(Speculation {
+                call: call.clone(),
+                guess,
+                model,
+                history_len,
+                response,
+            });
+        }
+        Some(Err(err)) => {
+            memory.log("Acting", "SPECULATION_FAILED", &err.to_string());
+            // Hook: on_llm_error
+            memory.hooks.on_llm_error(&model, &err.to_string(), memory);
+        }
+        None => {
+            memory.log(
+                "Acting",
+                "SPECULATION_DISCARDED",
+                &format!("tool='{}' result does not match the guess", call.name),
+            );
+            // Hook: on_llm_error (the call was dropped before it returned)
+            memory.hooks.on_llm_error(&model, "speculative call cancelled", memory);
+        }
+    }
+    joined
+}
+
+/// Take the prepared response for this step, if there is one, it was made
+/// for the same prompt and model, and it holds for the committed
+/// observation. A response that is dropped still has its tokens counted
+/// and reported.
+pub(crate) fn take_response(
+    memory: &mut AgentMemory,
+    model: &str,
+    output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+) -> Option<LlmResponse> {
+    let speculation = memory.speculation.take()?;
+    let threshold = memory.config.speculative_planning.as_ref().map_or(1.0, |c| c.threshold);
+    let score = memory
+        .history
+        .last()
+        .filter(|h| {
+            memory.history.len() == speculation.history_len
+                && h.tool == speculation.call
+                && h.is_success()
+                && speculation.model == model
+        })
+        .and_then(|h| {
+            let result = crate::dedup::split_marker(&h.observation.content).0;
+            holds(&speculation.guess, &speculation.call, result, &speculation.response, threshold)
+        });
+
+    match score {
+        Some(score) => {
+            let data = match speculation.guess {
+                Guess::Repeat(_) => format!("similarity={:.2}", score),
+                Guess::Pending => "guess=pending".to_string(),
+            };
+            memory.log("Planning", "SPECULATION_HIT", &data);
+            Some(speculation.response)
+        }
+        None => {
+            memory.log("Planning", "SPECULATION_DISCARDED", &format!("tool='{}'", speculation.call.name));
+            // Hook: on_llm_end (paired with the on_llm_start of the prepared call)
+            memory.hooks.on_llm_end(&speculation.model, &speculation.response, memory);
+            let (LlmResponse::ToolCall { usage, .. }
+            | LlmResponse::ParallelToolCalls { usage, .. }
+            | LlmResponse::FinalAnswer { usage, .. }
+            | LlmResponse::Structured { usage, .. }
+            | LlmResponse::Truncated { usage, .. }) = speculation.response;
+            if let Some(usage) = usage {
+                let model = speculation.response.answered_by().unwrap_or(&speculation.model);
+                crate::states::report_usage(memory, model, usage, output_tx);
+            }
+            None
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn entry(step: usize, query: &str, result: &str, latency_ms: u64) -> HistoryEntry {
+        HistoryEntry {
+            step,
+            tool: ToolCall {
+                name: "search".to_string(),
+                args: HashMap::from([("q".to_string(), serde_json::json!(query))]),
+                id: None,
+            },
+            observation: Observation::success(result),
+            assistant_text: None,
+            latency_ms: Some(latency_ms),
+            model_used: None,
+            usage: None,
+        }
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("Sunny, 21 degrees", "sunny,  21 DEGREES"), 1.0);
+        assert_eq!(similarity("", ""), 1.0);
+        assert!((similarity("a b c d", "a b c e") - 0.6).abs() < 1e-9);
+        assert_eq!(similarity("sunny", "station offline"), 0.0);
+    }
+
+    #[test]
+    fn test_guess_for_slow_tools() {
+        let config = SpeculativePlanning::new().min_latency_ms(500).slow_tool("fetch");
+        let mut memory = AgentMemory::new("task");
+        memory.history.push(entry(1, "rust", "old result", 800));
+        memory.history.push(entry(2, "rust", "Rust 1.80 released", 900));
+
+        let call = |q: &str| entry(0, q, "", 0).tool;
+        assert_eq!(guess(&memory, &config, &call("rust")), Some(Guess::Repeat("Rust 1.80 released".to_string())));
+        // Same tool, new arguments: nothing to repeat, but still slow
+        assert_eq!(guess(&memory, &config, &call("zig")), Some(Guess::Pending));
+
+        // The latest run decides whether the tool is slow
+        memory.history.push(entry(3, "go", "fast result", 10));
+        assert_eq!(guess(&memory, &config, &call("rust")), None);
+
+        // Declared slow before it ever ran
+        let fetch = ToolCall { name: "fetch".to_string(), args: HashMap::new(), id: None };
+        assert_eq!(guess(&memory, &config, &fetch), Some(Guess::Pending));
+    }
+
+    #[test]
+    fn test_pending_guess_holds_for_independent_steps() {
+        let call = entry(0, "rust", "", 0).tool;
+        let next = |tool: ToolCall| LlmResponse::ToolCall { tool, confidence: 1.0, assistant_text: None, usage: None, model: None };
+        let other = ToolCall { name: "summarize".to_string(), args: HashMap::new(), id: None };
+        assert_eq!(holds(&Guess::Pending, &call, "anything", &next(other), 0.9), Some(1.0));
+        assert_eq!(holds(&Guess::Pending, &call, "anything", &next(call.clone()), 0.9), None);
+        // A repeat of the pending call comes back with an id of its own
+        let pending = ToolCall { id: Some("call_1".to_string()), ..call.clone() };
+        let repeat = ToolCall { id: Some("call_2".to_string()), ..call.clone() };
+        assert_eq!(holds(&Guess::Pending, &pending, "anything", &next(repeat.clone()), 0.9), None);
+        let parallel = LlmResponse::ParallelToolCalls { tools: vec![repeat], confidence: 1.0, usage: None, model: None };
+        assert_eq!(holds(&Guess::Pending, &pending, "anything", &parallel, 0.9), None);
+        let answer = LlmResponse::FinalAnswer { content: "done".to_string(), confidence: 1.0, usage: None, model: None };
+        assert_eq!(holds(&Guess::Pending, &call, "anything", &answer, 0.9), None);
+        let repeat = Guess::Repeat("sunny, 21 degrees".to_string());
+        assert_eq!(holds(&repeat, &call, "Sunny, 21 degrees", &answer, 0.9), Some(1.0));
+        assert_eq!(holds(&repeat, &call, "station offline", &answer, 0.9), None);
+    }
+}
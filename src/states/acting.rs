@@ -1,9 +1,10 @@
 use crate::events::Event;
+use crate::human::DecisionOutcome;
 use crate::llm::AsyncLlmCaller;
 use crate::memory::AgentMemory;
 use crate::states::AgentState;
 use crate::tools::ToolRegistry;
-use crate::types::{AgentOutput, State};
+use crate::types::{AgentOutput, Observation, State};
 use async_trait::async_trait;
 
 pub struct ActingState;
@@ -18,7 +19,7 @@ impl AgentState for ActingState {
         &self,
         memory: &mut AgentMemory,
         tools: &std::sync::Arc<ToolRegistry>,
-        _llm: &dyn AsyncLlmCaller,
+        llm: &dyn AsyncLlmCaller,
         output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
     ) -> Event {
         if let Some(tx) = output_tx {
@@ -26,7 +27,7 @@ impl AgentState for ActingState {
         }
 
         // Extract tool call from memory
-        let tool_call = match memory.current_tool_call.as_ref() {
+        let mut tool_call = match memory.current_tool_call.as_ref() {
             Some(tc) => tc.clone(),
             None => {
                 memory.error = Some("ActingState called with no current_tool_call".to_string());
@@ -35,6 +36,51 @@ impl AgentState for ActingState {
             }
         };
 
+        // Validate arguments, repairing them with the cheap model if configured
+        if let Err(err) = crate::arg_repair::repair_if_invalid(
+            memory, tools, llm, &mut tool_call, "Acting", output_tx,
+        )
+        .await
+        {
+            memory.last_observation = Some(Observation::error(err.as_str()));
+            memory.log("Acting", "TOOL_FAILURE", &err);
+            if let Some(tx) = output_tx {
+                let _ = tx.send(AgentOutput::ToolCallFinished {
+                    name: tool_call.name.clone(),
+                    result: err,
+                    success: false,
+                });
+            }
+            return Event::tool_failure();
+        }
+        memory.current_tool_call = Some(tool_call.clone());
+
+        // Org-wide policy: a denied call is not run
+        if let Some(engine) = memory.policy_engine.clone() {
+            let request = crate::policy::PolicyRequest::tool_call(
+                memory,
+                &tool_call,
+                tools.is_mutating(&tool_call.name),
+            );
+            if let Some(reason) = crate::policy::check(engine.as_ref(), &request).await {
+                memory.last_observation =
+                    Some(Observation::rejected(format!("denied by policy: {}", reason)));
+                memory.log(
+                    "Acting",
+                    "POLICY_DENIED",
+                    &format!("tool='{}' reason={}", tool_call.name, reason),
+                );
+                if let Some(tx) = output_tx {
+                    let _ = tx.send(AgentOutput::ToolCallFinished {
+                        name: tool_call.name.clone(),
+                        result: reason,
+                        success: false,
+                    });
+                }
+                return Event::tool_failure();
+            }
+        }
+
         memory.log(
             "Acting",
             "TOOL_EXECUTE",
@@ -53,11 +99,43 @@ impl AgentState for ActingState {
             .hooks
             .on_tool_start(&tool_call.name, &tool_call.args, memory);
 
-        // Execute tool
-        match tools.execute(&tool_call.name, &tool_call.args) {
+        // Execute tool on a blocking thread, like ParallelActing (mutating
+        // tools are only described in plan mode)
+        memory.current_latency_ms = None;
+        let result = if crate::dry_run::should_simulate(memory, tools, &tool_call.name) {
+            memory.planned_actions.push(tool_call.clone());
+            memory.log("Acting", "TOOL_SIMULATED", &format!("tool='{}'", tool_call.name));
+            Ok(crate::dry_run::simulate(&tool_call))
+        } else {
+            let registry = std::sync::Arc::clone(tools);
+            let ctx = memory.tool_context(&tool_call);
+            let (name, args) = (tool_call.name.clone(), tool_call.args.clone());
+            let run = tokio::task::spawn_blocking(move || registry.execute_with(&name, &args, &ctx));
+            // While a slow tool runs, the next step is planned against a guess at its result
+            let (joined, elapsed) = crate::speculation::run_alongside(memory, tools, llm, &tool_call, run).await;
+            let result = joined.unwrap_or_else(|e| Err(format!("Tool panicked: {}", e)));
+            if let Some(audit) = &memory.audit {
+                // A human decision this step let the call run
+                let approved_by = memory
+                    .human_decisions
+                    .last()
+                    .filter(|d| d.step == memory.step && d.outcome != DecisionOutcome::Rejected)
+                    .and_then(|d| d.decided_by.as_deref());
+                audit.tool_executed(
+                    memory.step,
+                    &tool_call.name,
+                    &tool_call.args,
+                    &result,
+                    elapsed,
+                    approved_by,
+                );
+            }
+            memory.current_latency_ms = Some(elapsed.as_millis() as u64);
+            result
+        };
+        match result {
             Ok(result) => {
-                let observation = format!("SUCCESS: {}", result);
-                memory.last_observation = Some(observation.clone());
+                memory.last_observation = Some(Observation::success(result.as_str()));
                 memory.log(
                     "Acting",
                     "TOOL_SUCCESS",
@@ -80,8 +158,7 @@ impl AgentState for ActingState {
                 Event::tool_success()
             }
             Err(err) => {
-                let observation = format!("ERROR: {}", err);
-                memory.last_observation = Some(observation.clone());
+                memory.last_observation = Some(Observation::error(err.as_str()));
                 memory.log("Acting", "TOOL_FAILURE", &err);
 
                 if let Some(tx) = output_tx {
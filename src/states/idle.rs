@@ -1,4 +1,4 @@
-use crate::states::AgentState;
+use crate::states::{AgentState, PlanningState};
 use crate::events::Event;
 use crate::memory::AgentMemory;
 use crate::tools::ToolRegistry;
@@ -15,8 +15,8 @@ impl AgentState for IdleState {
     async fn handle(
         &self,
         memory:    &mut AgentMemory,
-        _tools:    &std::sync::Arc<ToolRegistry>,
-        _llm:      &dyn AsyncLlmCaller,
+        tools:     &std::sync::Arc<ToolRegistry>,
+        llm:       &dyn AsyncLlmCaller,
         output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
     ) -> Event {
         if let Some(tx) = output_tx {
@@ -27,6 +27,27 @@ impl AgentState for IdleState {
             "task='{}' task_type='{}' max_steps={}",
             memory.task, memory.task_type, memory.config.max_steps
         ));
+
+        if !memory.config.preflight && !memory.config.kickoff {
+            return Event::start();
+        }
+        let model = PlanningState.resolve_model(memory);
+
+        if memory.config.preflight {
+            if let Err(problem) = crate::preflight::run(memory, tools, llm, &model).await {
+                memory.log("Idle", "PREFLIGHT_FAILED", &problem);
+                memory.error = Some(format!("Pre-flight check failed: {}", problem));
+                return Event::preflight_failed();
+            }
+            memory.log("Idle", "PREFLIGHT_OK", &format!("tools={} model='{}'", tools.len(), model));
+        }
+
+        if memory.config.kickoff {
+            if let Some(tx) = output_tx {
+                let message = crate::preflight::kickoff_message(&memory.task, &model, tools);
+                let _ = tx.send(AgentOutput::Action(message));
+            }
+        }
         Event::start()
     }
 }
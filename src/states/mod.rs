@@ -18,6 +18,7 @@ mod waiting_for_human;
 
 pub use idle::IdleState;
 pub use planning::PlanningState;
+pub(crate) use planning::report_usage;
 pub use acting::ActingState;
 pub use parallel_acting::ParallelActingState;
 pub use observing::ObservingState;
@@ -3,7 +3,7 @@ use crate::events::Event;
 use crate::memory::AgentMemory;
 use crate::tools::ToolRegistry;
 use crate::llm::AsyncLlmCaller;
-use crate::types::{AgentOutput, HistoryEntry, State};
+use crate::types::{AgentOutput, HistoryEntry, ObservationStatus, State};
 use async_trait::async_trait;
 
 pub struct ObservingState;
@@ -16,7 +16,7 @@ impl AgentState for ObservingState {
         &self,
         memory:    &mut AgentMemory,
         _tools:    &std::sync::Arc<ToolRegistry>,
-        _llm:      &dyn AsyncLlmCaller,
+        llm:       &dyn AsyncLlmCaller,
         output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
     ) -> Event {
         if let Some(tx) = output_tx {
@@ -25,26 +25,37 @@ impl AgentState for ObservingState {
         // Commit tool call and observation to history (single call legacy)
         let tool_call = memory.current_tool_call.take();
         let observation = memory.last_observation.take();
+        let rejected = observation.as_ref().is_some_and(|o| o.status == ObservationStatus::Rejected)
+            || memory.parallel_results.iter().any(|r| r.observation.status == ObservationStatus::Rejected);
 
-        if let (Some(tool), Some(obs)) = (tool_call, observation) {
-            let success = obs.starts_with("SUCCESS:");
+        if let (Some(tool), Some(mut obs)) = (tool_call, observation) {
+            let content = memory.record_citations(&tool.name, obs.content.to_string());
+            obs.content = crate::sanitizer::sanitize_observation(memory, llm, &tool.name, content).await.into();
+            let success = obs.is_success();
             let entry = HistoryEntry {
                 step: memory.step,
                 tool,
-                observation: obs.clone(),
-                success,
+                observation: obs,
+                assistant_text: memory.current_assistant_text.take(),
+                latency_ms: memory.current_latency_ms.take(),
+                model_used: memory.current_model.clone(),
+                usage: memory.current_usage.take(),
             };
-            memory.history.push(entry);
+            crate::suspend::record(memory, &entry.tool, success);
+            crate::dedup::push_history(memory, entry);
             memory.log("Observing", "HISTORY_COMMIT", &format!(
                 "step={} success={} len={}", memory.step, success, memory.history.len()
             ));
         }
 
-        // Commit parallel results if any
+        // Commit parallel results if any, in the order the calls were requested
         let parallel = memory.parallel_results.drain(..).collect::<Vec<_>>();
-        for res in parallel {
+        for mut res in parallel {
+            let content = memory.record_citations(&res.tool_name, res.observation.content.to_string());
+            res.observation.content =
+                crate::sanitizer::sanitize_observation(memory, llm, &res.tool_name, content).await.into();
             memory.log("Observing", "HISTORY_COMMIT_PARALLEL", &format!(
-                "step={} tool={} success={}", memory.step, res.tool_name, res.success
+                "step={} tool={} success={}", memory.step, res.tool_name, res.is_success()
             ));
             let entry = HistoryEntry {
                 step: memory.step,
@@ -53,18 +64,26 @@ impl AgentState for ObservingState {
                     args: res.tool_args,
                     id:   res.id,
                 },
-                observation: res.output,
-                success: res.success,
+                observation: res.observation,
+                assistant_text: None,
+                latency_ms: Some(res.latency_ms),
+                model_used: memory.current_model.clone(),
+                usage: memory.current_usage.take(),
             };
-            memory.history.push(entry);
+            crate::suspend::record(memory, &entry.tool, entry.is_success());
+            crate::dedup::push_history(memory, entry);
+        }
+
+        // Drop failed attempts the model should not build on
+        let failures = memory.history.iter().rev().take_while(|h| !h.is_success()).count();
+        let limit = memory.config.prune_after_failures;
+        if (rejected && memory.config.prune_on_rejection) || (limit > 0 && failures >= limit) {
+            memory.prune_failed_branch();
         }
 
         // Check if reflection is needed
-        let reflect_interval = memory.config.reflect_every_n_steps;
-        if reflect_interval > 0 && memory.step.is_multiple_of(reflect_interval) {
-            memory.log("Observing", "NEEDS_REFLECTION", &format!(
-                "step={} interval={}", memory.step, reflect_interval
-            ));
+        if let Some(reason) = memory.reflection_reason() {
+            memory.log("Observing", "NEEDS_REFLECTION", &reason);
             Event::needs_reflection()
         } else {
             Event::r#continue()
@@ -3,12 +3,17 @@ use crate::events::Event;
 use crate::memory::AgentMemory;
 use crate::tools::ToolRegistry;
 use crate::llm::AsyncLlmCaller;
-use crate::types::{AgentOutput, State, ToolResult};
+use crate::types::{AgentOutput, Observation, State, ToolResult};
 use async_trait::async_trait;
 use std::sync::Arc;
 use tokio::time::Instant;
-use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
 
+/// Runs `memory.pending_tool_calls` concurrently as one batch.
+///
+/// The batch is validated, checked against policy and run against a single
+/// snapshot of the registry, so every call sees the same tools even if the
+/// engine's `SharedToolRegistry` changes while the batch runs.
 pub struct ParallelActingState;
 
 #[async_trait]
@@ -19,21 +24,78 @@ impl AgentState for ParallelActingState {
         &self,
         memory:    &mut AgentMemory,
         tools:     &Arc<ToolRegistry>,
-        _llm:      &dyn AsyncLlmCaller,
+        llm:       &dyn AsyncLlmCaller,
         output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
     ) -> Event {
         if let Some(tx) = output_tx {
             let _ = tx.send(AgentOutput::StateStarted(State::parallel_acting()));
         }
 
-        let pending = memory.pending_tool_calls.clone();
+        // One registry version for the whole batch
+        let tools = Arc::clone(tools);
+        let tools = &tools;
+
+        // The batch is consumed here, so it is moved out rather than copied
+        let mut pending = std::mem::take(&mut memory.pending_tool_calls);
         let count = pending.len();
         memory.log("ParallelActing", "PARALLEL_ACTING_START", &format!("count={}", count));
 
+        // Validate (and repair) arguments up front; invalid calls fail without running
+        let mut invalid = Vec::with_capacity(count);
+        for call in pending.iter_mut() {
+            invalid.push(
+                crate::arg_repair::repair_if_invalid(memory, tools, llm, call, "ParallelActing", output_tx)
+                    .await
+                    .err()
+                    .map(Observation::error),
+            );
+        }
+
+        // Org-wide policy: denied calls fail without running
+        if let Some(engine) = memory.policy_engine.clone() {
+            for (call, invalid_err) in pending.iter().zip(invalid.iter_mut()) {
+                if invalid_err.is_some() {
+                    continue;
+                }
+                let request = crate::policy::PolicyRequest::tool_call(
+                    memory,
+                    call,
+                    tools.is_mutating(&call.name),
+                );
+                if let Some(reason) = crate::policy::check(engine.as_ref(), &request).await {
+                    memory.log(
+                        "ParallelActing",
+                        "POLICY_DENIED",
+                        &format!("tool='{}' reason={}", call.name, reason),
+                    );
+                    *invalid_err = Some(Observation::rejected(format!("denied by policy: {}", reason)));
+                }
+            }
+        }
+
+        // Mutating tools are only described in plan mode
+        let mut simulated = Vec::with_capacity(count);
+        for (call, invalid_err) in pending.iter().zip(&invalid) {
+            let simulate = invalid_err.is_none()
+                && crate::dry_run::should_simulate(memory, tools, &call.name);
+            if simulate {
+                memory.planned_actions.push(call.clone());
+                memory.log("ParallelActing", "TOOL_SIMULATED", &format!("tool='{}'", call.name));
+            }
+            simulated.push(simulate);
+        }
+
+        let calls = pending.clone();
+        let started = Instant::now();
         let mut tasks = Vec::new();
-        for tool_call in pending {
+        for ((tool_call, invalid_err), simulate) in pending.into_iter().zip(invalid).zip(simulated) {
             let tools_clone = Arc::clone(tools);
             let tx_clone = output_tx.cloned();
+            let audit = memory.audit.clone();
+            let step = memory.step;
+            let ctx = memory.tool_context(&tool_call);
+            let max_retries = memory.config.parallel_tool_retries;
+            let cancel = memory.cancel.clone();
             
             tasks.push(tokio::task::spawn_blocking(move || {
                 let start = Instant::now();
@@ -45,11 +107,38 @@ impl AgentState for ParallelActingState {
                     });
                 }
 
-                let result = tools_clone.execute(&tool_call.name, &tool_call.args);
+                if let Some(observation) = invalid_err {
+                    if let Some(ref tx) = tx_clone {
+                        let _ = tx.send(AgentOutput::ToolCallFinished {
+                            name: tool_call.name.clone(),
+                            result: observation.content.to_string(),
+                            success: false,
+                        });
+                    }
+                    let latency = start.elapsed().as_millis() as u64;
+                    return ToolResult::new(tool_call.name, tool_call.args, tool_call.id, observation, latency);
+                }
+
+                // Failed calls run again, up to `max_retries` times, unless the run was cancelled
+                let mut retries = 0;
+                let result = if simulate {
+                    Ok(crate::dry_run::simulate(&tool_call))
+                } else {
+                    loop {
+                        let attempt = Instant::now();
+                        let result = tools_clone.execute_with(&tool_call.name, &tool_call.args, &ctx);
+                        if let Some(audit) = &audit {
+                            audit.tool_executed(step, &tool_call.name, &tool_call.args, &result, attempt.elapsed(), None);
+                        }
+                        if result.is_ok() || retries >= max_retries || cancel.is_cancelled() {
+                            break result;
+                        }
+                        retries += 1;
+                    }
+                };
                 let latency = start.elapsed().as_millis() as u64;
 
-                
-                match result {
+                let tool_result = match result {
                     Ok(res) => {
                         if let Some(ref tx) = tx_clone {
                             let _ = tx.send(AgentOutput::ToolCallFinished {
@@ -58,7 +147,7 @@ impl AgentState for ParallelActingState {
                                 success: true,
                             });
                         }
-                        ToolResult::success(tool_call.name.clone(), tool_call.args.clone(), tool_call.id.clone(), res, latency)
+                        ToolResult::success(tool_call.name, tool_call.args, tool_call.id, res, latency)
                     }
                     Err(err) => {
                         if let Some(ref tx) = tx_clone {
@@ -68,25 +157,99 @@ impl AgentState for ParallelActingState {
                                 success: false,
                             });
                         }
-                        ToolResult::failure(tool_call.name.clone(), tool_call.args.clone(), tool_call.id.clone(), err, latency)
+                        ToolResult::failure(tool_call.name, tool_call.args, tool_call.id, err, latency)
                     }
-                }
+                };
+                tool_result.with_retries(retries)
             }));
         }
 
-        let results = join_all(tasks).await;
+        // Wait for every tool. After a cancel, wait only the grace period:
+        // tools still running then are recorded as failed and left to finish
+        // on their threads.
+        let mut running: FuturesUnordered<_> = tasks
+            .into_iter()
+            .enumerate()
+            .map(|(i, task)| async move { (i, task.await) })
+            .collect();
+        // Results go in the slot of their request, not in completion order, so
+        // history and replays are the same however the tools were scheduled
+        let mut results: Vec<Option<ToolResult>> = vec![None; count];
+        let mut finished = vec![false; count];
+        let cancel = memory.cancel.clone();
+        let grace = std::time::Duration::from_millis(memory.config.cancel_grace_ms);
+        let deadline = tokio::time::sleep(grace);
+        tokio::pin!(deadline);
+        let mut cancelled = false;
+        loop {
+            tokio::select! {
+                next = running.next() => match next {
+                    Some((i, result)) => {
+                        // A panicking tool still gets its result, so no slot is skipped
+                        let call = &calls[i];
+                        results[i] = Some(result.unwrap_or_else(|e| ToolResult::failure(
+                            call.name.clone(),
+                            call.args.clone(),
+                            call.id.clone(),
+                            format!("Tool panicked: {}", e),
+                            started.elapsed().as_millis() as u64,
+                        )));
+                        finished[i] = true;
+                    }
+                    None => break,
+                },
+                _ = cancel.cancelled(), if !cancelled => {
+                    cancelled = true;
+                    deadline.as_mut().reset(Instant::now() + grace);
+                }
+                _ = &mut deadline, if cancelled => break,
+            }
+        }
+
+        let mut abandoned = Vec::new();
+        for ((slot, call), done) in results.iter_mut().zip(calls).zip(finished) {
+            if !done {
+                abandoned.push(call.name.clone());
+                *slot = Some(ToolResult::failure(
+                    call.name,
+                    call.args,
+                    call.id,
+                    "Cancelled: the run was cancelled while the tool was running".to_string(),
+                    started.elapsed().as_millis() as u64,
+                ));
+            }
+        }
+        if !abandoned.is_empty() {
+            memory.log(
+                "ParallelActing",
+                "TOOLS_ABANDONED",
+                &format!("grace_ms={} tools={:?}", grace.as_millis(), abandoned),
+            );
+        }
+
         let mut tool_results = Vec::new();
         let mut success_count = 0;
 
         for tool_res in results.into_iter().flatten() {
-            if tool_res.success {
+            if tool_res.is_success() {
                 success_count += 1;
             }
+            if tool_res.retries > 0 {
+                memory.log(
+                    "ParallelActing",
+                    "TOOL_RETRIED",
+                    &format!(
+                        "tool='{}' retries={} success={}",
+                        tool_res.tool_name,
+                        tool_res.retries,
+                        tool_res.is_success()
+                    ),
+                );
+            }
             tool_results.push(tool_res);
         }
 
         memory.parallel_results = tool_results;
-        memory.pending_tool_calls.clear();
         memory.log("ParallelActing", "PARALLEL_ACTING_DONE", &format!("success={}/{}", success_count, count));
 
         if success_count > 0 || count == 0 {
@@ -107,10 +270,10 @@ mod tests {
     struct MockLlm;
     #[async_trait]
     impl AsyncLlmCaller for MockLlm {
-        async fn call_async(&self, _: &AgentMemory, _: &ToolRegistry, _: &str, _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>) -> Result<crate::types::LlmResponse, String> {
-            Err("Not used".to_string())
+        async fn call_async(&self, _: &AgentMemory, _: &ToolRegistry, _: &str, _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>) -> Result<crate::types::LlmResponse, crate::llm::LlmError> {
+            Err("Not used".into())
         }
-        fn call_stream_async<'a>(&'a self, _: &'a AgentMemory, _: &'a ToolRegistry, _: &'a str, _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>) -> futures::stream::BoxStream<'a, Result<crate::types::LlmStreamChunk, String>> {
+        fn call_stream_async<'a>(&'a self, _: &'a AgentMemory, _: &'a ToolRegistry, _: &'a str, _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<crate::types::AgentOutput>>) -> futures::stream::BoxStream<'a, Result<crate::types::LlmStreamChunk, crate::llm::LlmError>> {
             unimplemented!()
         }
     }
@@ -135,7 +298,7 @@ mod tests {
         
         assert_eq!(event, Event::tool_success());
         assert_eq!(memory.parallel_results.len(), 2);
-        assert!(memory.parallel_results.iter().all(|r| r.success));
+        assert!(memory.parallel_results.iter().all(|r| r.is_success()));
         assert!(memory.pending_tool_calls.is_empty());
     }
 
@@ -159,8 +322,77 @@ mod tests {
         
         assert_eq!(event, Event::tool_success()); // Success if at least one succeeded
         assert_eq!(memory.parallel_results.len(), 2);
-        assert!(memory.parallel_results.iter().any(|r| r.success));
-        assert!(memory.parallel_results.iter().any(|r| !r.success));
+        assert!(memory.parallel_results.iter().any(|r| r.is_success()));
+        assert!(memory.parallel_results.iter().any(|r| !r.is_success()));
+    }
+
+    #[tokio::test]
+    async fn test_failed_calls_retried_in_batch() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut memory = AgentMemory::new("test");
+        memory.config.parallel_tool_retries = 2;
+        let mut registry = ToolRegistry::new();
+
+        // Fails once, then succeeds
+        let calls = Arc::new(AtomicUsize::new(0));
+        let flaky_calls = Arc::clone(&calls);
+        registry.register_tool(Tool::new("flaky", "flaky").call(move |_| {
+            match flaky_calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Err("timeout".to_string()),
+                _ => Ok("ok".to_string()),
+            }
+        }));
+        registry.register_tool(Tool::new("broken", "broken").call(|_| Err("down".to_string())));
+
+        let tools = Arc::new(registry);
+
+        memory.pending_tool_calls = vec![
+            ToolCall { name: "flaky".to_string(), args: HashMap::new(), id: Some("id1".to_string()) },
+            ToolCall { name: "broken".to_string(), args: HashMap::new(), id: Some("id2".to_string()) },
+        ];
+
+        let state = ParallelActingState;
+        let event = state.handle(&mut memory, &tools, &MockLlm, None).await;
+
+        assert_eq!(event, Event::tool_success());
+        let flaky = &memory.parallel_results[0];
+        assert!(flaky.is_success());
+        assert_eq!(flaky.retries, 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let broken = &memory.parallel_results[1];
+        assert!(!broken.is_success());
+        assert_eq!(broken.retries, 2);
+        assert_eq!(memory.trace.for_state("ParallelActing").iter().filter(|e| e.event == "TOOL_RETRIED").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_results_keep_request_order() {
+        let mut memory = AgentMemory::new("test");
+        let mut registry = ToolRegistry::new();
+
+        // The first call finishes last; the second panics
+        registry.register_tool(Tool::new("slow", "slow").call(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            Ok("slow".to_string())
+        }));
+        registry.register_tool(Tool::new("boom", "boom").call(|_| panic!("bad tool")));
+        registry.register_tool(Tool::new("fast", "fast").call(|_| Ok("fast".to_string())));
+
+        let tools = Arc::new(registry);
+
+        memory.pending_tool_calls = ["slow", "boom", "fast"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ToolCall { name: name.to_string(), args: HashMap::new(), id: Some(format!("id{}", i)) })
+            .collect();
+
+        let state = ParallelActingState;
+        state.handle(&mut memory, &tools, &MockLlm, None).await;
+
+        let ids: Vec<_> = memory.parallel_results.iter().map(|r| r.id.as_deref().unwrap()).collect();
+        assert_eq!(ids, vec!["id0", "id1", "id2"]);
+        assert!(memory.parallel_results[1].observation.content.starts_with("Tool panicked"));
     }
 
     #[tokio::test]
@@ -183,6 +415,6 @@ mod tests {
         
         assert_eq!(event, Event::tool_failure());
         assert_eq!(memory.parallel_results.len(), 2);
-        assert!(memory.parallel_results.iter().all(|r| !r.success));
+        assert!(memory.parallel_results.iter().all(|r| !r.is_success()));
     }
 }
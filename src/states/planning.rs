@@ -1,9 +1,16 @@
+use crate::budget::TokenUsage;
 use crate::events::Event;
-use crate::llm::AsyncLlmCaller;
+use crate::llm::{AsyncLlmCaller, LlmError};
 use crate::memory::AgentMemory;
+use crate::moderation::{ModerationVerdict, OnAnswerBlocked};
+use crate::observability::TokenEvents;
+use crate::prompter::TextReply;
 use crate::states::AgentState;
 use crate::tools::ToolRegistry;
-use crate::types::{AgentOutput, LlmResponse, LlmStreamChunk, State, ToolCall};
+use crate::types::{
+    AgentOutput, HistoryEntry, LlmResponse, LlmStreamChunk, Observation, RepeatedCallAction, State,
+    ToolCall,
+};
 use async_trait::async_trait;
 use futures::StreamExt;
 
@@ -17,7 +24,7 @@ impl PlanningState {
     ///   1. `memory.config.models[task_type]`     — exact task-type match
     ///   2. `memory.config.models["default"]`     — generic fallback
     ///   3. `""`                                  — let the LlmCaller use its own default
-    fn resolve_model(&self, memory: &AgentMemory) -> String {
+    pub(crate) fn resolve_model(&self, memory: &AgentMemory) -> String {
         // Adaptive routing takes priority
         if let Some(ref policy) = memory.routing_policy {
             let routed = policy.resolve(memory);
@@ -34,7 +41,114 @@ impl PlanningState {
             .unwrap_or_default()
     }
 
-    fn handle_tool_call(&self, memory: &mut AgentMemory, tool: ToolCall, confidence: f64) -> Event {
+    /// Fail the run on an LLM error, except for a first context overflow,
+    /// which is sent to Reflecting for emergency compression, and retryable
+    /// failures under `max_llm_failures_per_run`, which wait and plan again.
+    async fn handle_llm_error(
+        &self,
+        memory: &mut AgentMemory,
+        model: &str,
+        error: String,
+        raw: &LlmError,
+    ) -> Event {
+        if raw.is_context_overflow() && !memory.context_overflow {
+            memory.context_overflow = true;
+            memory.log(
+                "Planning",
+                "CONTEXT_OVERFLOW",
+                &format!("history_entries={} error={}", memory.history.len(), raw),
+            );
+            return Event::context_overflow();
+        }
+        let message = raw.to_string();
+        memory.llm_failures.push((memory.step, message.clone()));
+        memory.log("Planning", "LLM_ERROR", &format!("kind={} error={}", raw.kind(), message));
+        // Hook: on_llm_error
+        memory.hooks.on_llm_error(model, &message, memory);
+
+        // Auth and invalid-request errors fail the same way every time
+        let failures = memory.llm_failures.len();
+        if failures < memory.config.max_llm_failures_per_run && raw.is_retryable() {
+            let backoff = memory
+                .config
+                .llm_retry_delay_ms
+                .saturating_mul(1 << (failures - 1).min(5));
+            let delay = raw
+                .retry_after()
+                .map_or(backoff, |wait| wait.as_millis() as u64)
+                .min(30_000);
+            memory.log(
+                "Planning",
+                "LLM_RETRY",
+                &format!(
+                    "failure={}/{} wait_ms={}",
+                    failures, memory.config.max_llm_failures_per_run, delay
+                ),
+            );
+            // A cancel ends the wait; the engine then stops before planning again
+            tokio::select! {
+                _ = tokio::time::sleep(std::time::Duration::from_millis(delay)) => {}
+                _ = memory.cancel.cancelled() => {
+                    memory.log("Planning", "LLM_RETRY_CANCELLED", &format!("failure={}", failures));
+                }
+            }
+            return Event::llm_retry();
+        }
+        memory.error = Some(error);
+        Event::fatal_error()
+    }
+
+    /// Keep text that accompanied a tool call and stream it as commentary.
+    fn record_commentary(
+        &self,
+        memory: &mut AgentMemory,
+        assistant_text: Option<String>,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) {
+        let text = match assistant_text {
+            Some(t) if !t.trim().is_empty() => t,
+            _ => return,
+        };
+        memory.log(
+            "Planning",
+            "LLM_COMMENTARY",
+            &text.chars().take(100).collect::<String>(),
+        );
+        if let Some(tx) = output_tx {
+            let _ = tx.send(AgentOutput::Commentary(text.clone()));
+        }
+        memory.current_assistant_text = Some(text);
+    }
+
+    /// Send a response the model was unsure of to Reflecting, while low
+    /// confidence retries remain.
+    fn check_confidence(&self, memory: &mut AgentMemory, confidence: f64) -> Option<Event> {
+        if confidence >= memory.config.confidence_threshold || memory.retry_count >= memory.config.max_retries {
+            return None;
+        }
+        memory.retry_count += 1;
+        memory.confidence_score = confidence;
+        memory.log(
+            "Planning",
+            "LOW_CONFIDENCE",
+            &format!(
+                "confidence={:.2} threshold={:.2} retry={}/{}",
+                confidence,
+                memory.config.confidence_threshold,
+                memory.retry_count,
+                memory.config.max_retries
+            ),
+        );
+        Some(Event::low_confidence())
+    }
+
+    fn handle_tool_call(
+        &self,
+        memory: &mut AgentMemory,
+        registry: &ToolRegistry,
+        tool: ToolCall,
+        confidence: f64,
+    ) -> Event {
         // Check blacklist
         if memory.blacklisted_tools.contains(&tool.name) {
             memory.log(
@@ -45,36 +159,24 @@ impl PlanningState {
             return Event::tool_blacklisted();
         }
 
+        // The exact call the previous step made: likely a loop
+        if let Some(event) = self.handle_repeated_call(memory, &tool) {
+            return event;
+        }
+
         // Check confidence
-        if confidence < memory.config.confidence_threshold
-            && memory.retry_count < memory.config.max_retries
-        {
-            memory.retry_count += 1;
-            memory.confidence_score = confidence;
-            memory.log(
-                "Planning",
-                "LOW_CONFIDENCE",
-                &format!(
-                    "confidence={:.2} threshold={:.2} retry={}/{}",
-                    confidence,
-                    memory.config.confidence_threshold,
-                    memory.retry_count,
-                    memory.config.max_retries
-                ),
-            );
-            return Event::low_confidence();
+        if let Some(event) = self.check_confidence(memory, confidence) {
+            return event;
         }
 
         // Check human approval
-        if memory
-            .approval_policy
-            .needs_approval(&tool.name, &tool.args)
-        {
+        if let Some((risk_level, reason)) = self.approval_needed(memory, registry, &tool) {
             memory.pending_approval = Some(crate::human::HumanApprovalRequest {
                 tool_name: tool.name.clone(),
                 tool_args: tool.args.clone(),
-                risk_level: crate::human::RiskLevel::High, // Default to High for now if policy says yes
-                reason: "Policy-mandated approval".to_string(),
+                risk_level,
+                reason,
+                batch: Vec::new(),
             });
             memory.current_tool_call = Some(tool);
             memory.confidence_score = confidence;
@@ -87,56 +189,712 @@ impl PlanningState {
         }
 
         // Accept tool call
-        memory.current_tool_call = Some(tool.clone());
-        memory.pending_tool_calls.clear(); // Clear parallel queue if single call
-        memory.confidence_score = confidence;
         memory.log(
             "Planning",
             "LLM_TOOL_CALL",
             &format!("tool='{}' confidence={:.2}", tool.name, confidence),
         );
+        memory.current_tool_call = Some(tool);
+        memory.pending_tool_calls.clear(); // Clear parallel queue if single call
+        memory.confidence_score = confidence;
         Event::llm_tool_call()
     }
 
+    /// The risk and reason if `tool` needs human approval: the tool may
+    /// demand it, or its risk may cross the policy's bar.
+    fn approval_needed(
+        &self,
+        memory: &AgentMemory,
+        registry: &ToolRegistry,
+        tool: &ToolCall,
+    ) -> Option<(crate::human::RiskLevel, String)> {
+        let declared = registry.risk(&tool.name);
+        let flagged = registry.requires_approval(&tool.name);
+        if !flagged
+            && !memory
+                .approval_policy
+                .needs_approval_for(&tool.name, &tool.args, declared)
+        {
+            return None;
+        }
+        let risk_level = memory.approval_policy.risk_of(&tool.name, declared);
+        let reason = if flagged {
+            format!("Tool '{}' always requires approval", tool.name)
+        } else {
+            format!("{:?} risk under the approval policy", risk_level)
+        };
+        Some((risk_level, reason))
+    }
+
+    /// Apply `repeated_call_action` if `tool` repeats the last history entry's call.
+    fn handle_repeated_call(&self, memory: &mut AgentMemory, tool: &ToolCall) -> Option<Event> {
+        let action = memory.config.repeated_call_action;
+        if action == RepeatedCallAction::Allow {
+            return None;
+        }
+        let previous = memory
+            .history
+            .last()
+            .filter(|e| e.tool.name == tool.name && e.tool.args == tool.args)?
+            .clone();
+        let data = format!(
+            "tool='{}' earlier_step={} action={:?}",
+            tool.name, previous.step, action
+        );
+        memory.log("Planning", "REPEATED_TOOL_CALL", &data);
+        match action {
+            RepeatedCallAction::Allow => None,
+            RepeatedCallAction::ReuseObservation => {
+                let (content, _) = crate::dedup::split_marker(&previous.observation.content);
+                let content = format!(
+                    "{}\n[Reused from step {}: the same call was not run again.]",
+                    content, previous.step
+                );
+                let observation = Observation::new(previous.observation.status, content)
+                    .with_metadata("reused_from_step", previous.step);
+                memory.last_observation = Some(observation.clone());
+                memory.history.push(HistoryEntry {
+                    step: memory.step,
+                    tool: tool.clone(),
+                    observation,
+                    assistant_text: None,
+                    latency_ms: None,
+                    model_used: memory.current_model.clone(),
+                    usage: memory.current_usage.take(),
+                });
+                Some(Event::tool_call_reused())
+            }
+            RepeatedCallAction::Reflect => {
+                // Survives the history compression and is dropped after the next response
+                memory.hints.push(crate::dedup::repeat_note(&previous, &memory.markers()));
+                Some(Event::repeated_tool_call())
+            }
+        }
+    }
+
     fn handle_parallel_tool_calls(
         &self,
         memory: &mut AgentMemory,
-        tools: Vec<ToolCall>,
+        registry: &ToolRegistry,
+        mut tools: Vec<ToolCall>,
         confidence: f64,
     ) -> Event {
-        memory.current_tool_call = None;
-        memory.pending_tool_calls = tools.clone();
-        memory.parallel_results.clear();
-        memory.confidence_score = confidence;
+        // Enforce the per-step limit; excess calls run on the next steps
+        let limit = if memory.config.parallel_tools {
+            memory.config.max_tool_calls_per_step.unwrap_or(usize::MAX).max(1)
+        } else {
+            1
+        };
+        if tools.len() > limit {
+            let excess = tools.split_off(limit);
+            memory.log(
+                "Planning",
+                "TOOL_CALLS_DEFERRED",
+                &format!("accepted={} deferred={}", tools.len(), excess.len()),
+            );
+            memory.deferred_tool_calls.splice(0..0, excess);
+        }
+        if tools.len() == 1 {
+            let tool = tools.remove(0);
+            return self.handle_tool_call(memory, registry, tool, confidence);
+        }
+
         memory.log(
             "Planning",
             "LLM_PARALLEL_TOOLS",
             &format!("count={} confidence={:.2}", tools.len(), confidence),
         );
+
+        // One call needing approval holds the whole batch; the request names
+        // the first of the riskiest such calls and carries the rest
+        let flagged = tools
+            .iter()
+            .filter_map(|tool| {
+                self.approval_needed(memory, registry, tool)
+                    .map(|(risk, reason)| (tool, risk, reason))
+            })
+            .min_by_key(|(_, risk, _)| std::cmp::Reverse(*risk));
+        let approval = flagged.map(|(tool, risk_level, reason)| crate::human::HumanApprovalRequest {
+            tool_name: tool.name.clone(),
+            tool_args: tool.args.clone(),
+            risk_level,
+            reason: format!("{} (one of {} parallel calls)", reason, tools.len()),
+            batch: tools.clone(),
+        });
+
+        memory.current_tool_call = None;
+        memory.pending_tool_calls = tools;
+        memory.parallel_results.clear();
+        memory.confidence_score = confidence;
+        if let Some(request) = approval {
+            memory.pending_approval = Some(request);
+            memory.log(
+                "Planning",
+                "APPROVAL_REQUIRED",
+                "Parallel batch needs human approval",
+            );
+            return Event::human_approval_required();
+        }
         Event::llm_parallel_tool_calls()
     }
 
-    fn handle_final_answer(
+    /// Call the LLM once, streaming tokens to `output_tx`, with a
+    /// non-streaming fallback. Errors are already turned into the event to return.
+    async fn call_llm(
+        &self,
+        memory: &mut AgentMemory,
+        tools: &ToolRegistry,
+        llm: &dyn AsyncLlmCaller,
+        model: &str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) -> Result<LlmResponse, Event> {
+        // Text prompters describe the tools themselves
+        let no_tools = ToolRegistry::new();
+        let registry = tools;
+        let tools = if memory.planning_prompter.native_tools() {
+            tools
+        } else {
+            &no_tools
+        };
+
+        // Hook: on_llm_start
+        memory.hooks.on_llm_start(model, memory);
+
+        let stream_moderation = memory.moderation.clone().filter(|m| m.stream);
+        let (final_resp, stream_err, stream_muted, reasoning) = {
+            let mut stream = llm.call_stream_async(memory, tools, model, output_tx);
+            let mut final_resp = None;
+            let mut stream_err = None;
+            let mut streamed = String::new();
+            let mut reasoning = String::new();
+            let mut stream_muted = false;
+            let mut batch = TokenBatch::default();
+
+            while let Some(chunk_res) = stream.next().await {
+                // Read per chunk, so a change made while streaming applies at once
+                let token_events = memory.observability.token_events();
+                match chunk_res {
+                    Ok(LlmStreamChunk::Content(token)) => {
+                        if let Some(ref m) = stream_moderation {
+                            streamed.push_str(&token);
+                            stream_muted = stream_muted || !m.hook.check_stream(&streamed);
+                        }
+                        if let (Some(tx), false) = (output_tx, stream_muted) {
+                            batch.push(token, token_events, tx);
+                        }
+                    }
+                    Ok(LlmStreamChunk::Reasoning(text)) => {
+                        reasoning.push_str(&text);
+                        if let (Some(tx), false) = (output_tx, token_events == TokenEvents::Off) {
+                            batch.flush(tx);
+                            let _ = tx.send(AgentOutput::Reasoning(text));
+                        }
+                    }
+                    Ok(LlmStreamChunk::ToolCallDelta { name, args_json }) => {
+                        if let (Some(tx), false) = (output_tx, token_events == TokenEvents::Off) {
+                            batch.flush(tx);
+                            let _ = tx.send(AgentOutput::ToolCallDelta { name, args_json });
+                        }
+                    }
+                    Ok(LlmStreamChunk::Done(resp)) => {
+                        final_resp = Some(resp);
+                    }
+                    Err(err) => {
+                        stream_err = Some(err);
+                        break;
+                    }
+                }
+            }
+            if let Some(tx) = output_tx {
+                batch.flush(tx);
+            }
+
+            (final_resp, stream_err, stream_muted, reasoning)
+        };
+        if stream_muted {
+            memory.log("Planning", "STREAM_MODERATED", "token output stopped by moderation hook");
+        }
+        if !reasoning.is_empty() {
+            // The full chain of thought stays out of the trace (and checkpoints)
+            let preview: String = reasoning.chars().take(100).collect();
+            memory.log("Planning", "REASONING", &format!("chars={} preview={}", reasoning.chars().count(), preview));
+        }
+
+        let resp = if let Some(err) = stream_err {
+            memory.log("Planning", "LLM_STREAM_ERROR", &err.to_string());
+            // Same prompt, same overflow — skip the non-stream fallback
+            if err.is_context_overflow() {
+                return Err(self.handle_llm_error(memory, model, format!("LLM stream error: {}", err), &err).await);
+            }
+            match llm.call_async(memory, tools, model, output_tx).await {
+                Ok(resp) => {
+                    memory.log(
+                        "Planning",
+                        "LLM_FALLBACK_SYNC",
+                        "Recovered via non-stream call",
+                    );
+                    resp
+                }
+                Err(sync_err) => {
+                    return Err(self.handle_llm_error(
+                        memory,
+                        model,
+                        format!(
+                            "LLM stream error: {} | fallback call_async error: {}",
+                            err, sync_err
+                        ),
+                        &sync_err,
+                    ).await);
+                }
+            }
+        } else {
+            match final_resp {
+                Some(r) => r,
+                None => {
+                    let stream_end_err = "LLM stream ended without Done chunk".to_string();
+                    memory.log("Planning", "STREAM_ERROR", &stream_end_err);
+                    match llm.call_async(memory, tools, model, output_tx).await {
+                        Ok(resp) => {
+                            memory.log(
+                                "Planning",
+                                "LLM_FALLBACK_SYNC",
+                                "Recovered from incomplete stream",
+                            );
+                            resp
+                        }
+                        Err(sync_err) => {
+                            return Err(self.handle_llm_error(
+                                memory,
+                                model,
+                                format!(
+                                    "{} | fallback call_async error: {}",
+                                    stream_end_err, sync_err
+                                ),
+                                &sync_err,
+                            ).await);
+                        }
+                    }
+                }
+            }
+        };
+        Ok(self.accept_response(memory, registry, model, resp, output_tx))
+    }
+
+    /// Count a response's tokens, read tool calls written as text and run
+    /// `on_llm_end`.
+    fn accept_response(
+        &self,
+        memory: &mut AgentMemory,
+        registry: &ToolRegistry,
+        model: &str,
+        resp: LlmResponse,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) -> LlmResponse {
+        let (LlmResponse::ToolCall { usage, .. }
+        | LlmResponse::ParallelToolCalls { usage, .. }
+        | LlmResponse::FinalAnswer { usage, .. }
+        | LlmResponse::Structured { usage, .. }
+        | LlmResponse::Truncated { usage, .. }) = &resp;
+
+        if let Some(u) = *usage {
+            // Costed against the model that answered, e.g. a fallback
+            report_usage(memory, resp.answered_by().unwrap_or(model), u, output_tx);
+            memory.current_usage.get_or_insert_with(TokenUsage::default).add(u);
+        }
+        memory.context_overflow = false;
+        let resp = self.read_text_reply(memory, registry, resp);
+
+        // Hook: on_llm_end
+        memory.hooks.on_llm_end(model, &resp, memory);
+        resp
+    }
+
+    /// Follow a truncated answer with up to `max_continuations` calls that
+    /// continue it, and stitch the pieces together. The result is still
+    /// `Truncated` if the last piece was cut off too.
+    async fn continue_truncated(
+        &self,
+        memory: &mut AgentMemory,
+        tools: &ToolRegistry,
+        llm: &dyn AsyncLlmCaller,
+        model: &str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+        mut resp: LlmResponse,
+    ) -> Result<LlmResponse, Event> {
+        let mut stitched = String::new();
+        let mut continuations = 0;
+        loop {
+            let content = match resp {
+                LlmResponse::Truncated { content, .. } if continuations < memory.config.max_continuations => content,
+                LlmResponse::Truncated { content, usage, model } => {
+                    return Ok(LlmResponse::Truncated { content: stitched + &content, usage, model });
+                }
+                LlmResponse::FinalAnswer { content, confidence, usage, model } => {
+                    return Ok(LlmResponse::FinalAnswer { content: stitched + &content, confidence, usage, model });
+                }
+                other => {
+                    if !stitched.is_empty() {
+                        memory.log("Planning", "CONTINUATION_ABANDONED", &format!("len={}", stitched.len()));
+                    }
+                    return Ok(other);
+                }
+            };
+            continuations += 1;
+            stitched.push_str(&content);
+            memory.log(
+                "Planning",
+                "ANSWER_CONTINUED",
+                &format!(
+                    "len={} continuation={}/{}",
+                    stitched.len(),
+                    continuations,
+                    memory.config.max_continuations
+                ),
+            );
+            memory.partial_answer = Some(stitched.clone());
+            let next = self.call_llm(memory, tools, llm, model, output_tx).await;
+            memory.partial_answer = None;
+            resp = next?;
+        }
+    }
+
+    /// Let the planning prompter, then the text tool-call fallback, read
+    /// tool calls out of a text answer.
+    fn read_text_reply(
+        &self,
+        memory: &mut AgentMemory,
+        tools: &ToolRegistry,
+        resp: LlmResponse,
+    ) -> LlmResponse {
+        let (content, confidence, usage, model) = match resp {
+            LlmResponse::FinalAnswer { content, confidence, usage, model } => (content, confidence, usage, model),
+            other => return other,
+        };
+        let (reply, source) = match memory.planning_prompter.parse(&content) {
+            Some(reply) => (reply, memory.planning_prompter.name()),
+            None => {
+                // Fallback: only accept calls to registered tools, so JSON in an answer stays an answer
+                let fallback = memory.config.text_tool_calls.and_then(|f| f.parse(&content));
+                let known = matches!(&fallback, Some(TextReply::ToolCalls { calls, .. })
+                    if calls.iter().all(|c| tools.has(&c.name)));
+                match fallback {
+                    Some(reply) if known => (reply, "fallback"),
+                    _ => return LlmResponse::FinalAnswer { content, confidence, usage, model },
+                }
+            }
+        };
+        match reply {
+            TextReply::Answer(content) => LlmResponse::FinalAnswer { content, confidence, usage, model },
+            TextReply::ToolCalls { mut calls, thought } => {
+                for (i, call) in calls.iter_mut().enumerate() {
+                    call.id.get_or_insert_with(|| format!("text_{}_{}", memory.step, i));
+                }
+                memory.log(
+                    "Planning",
+                    "TEXT_TOOL_CALL",
+                    &format!(
+                        "source={} tools={}",
+                        source,
+                        calls.iter().map(|c| c.name.as_str()).collect::<Vec<_>>().join(",")
+                    ),
+                );
+                if calls.len() == 1 {
+                    LlmResponse::ToolCall {
+                        tool: calls.remove(0),
+                        confidence,
+                        assistant_text: thought,
+                        usage,
+                        model,
+                    }
+                } else {
+                    LlmResponse::ParallelToolCalls {
+                        tools: calls,
+                        confidence,
+                        usage,
+                        model,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the policy engine and the moderation hook on an answer about to be accepted.
+    ///
+    /// Returns the text to accept, or the event to return instead.
+    async fn moderate_answer(&self, memory: &mut AgentMemory, content: String) -> Result<String, Event> {
+        if let Some(engine) = memory.policy_engine.clone() {
+            let request = crate::policy::PolicyRequest::final_answer(memory, &content);
+            if let Some(reason) = crate::policy::check(engine.as_ref(), &request).await {
+                memory.log("Planning", "POLICY_DENIED", &format!("answer reason={}", reason));
+                memory.error = Some(format!("Answer denied by policy: {}", reason));
+                return Err(Event::policy_denied());
+            }
+        }
+        let moderation = match memory.moderation.clone() {
+            Some(m) => m,
+            None => return Ok(content),
+        };
+        let reason = match moderation.hook.moderate(&content).await {
+            Ok(ModerationVerdict::Allow) => return Ok(content),
+            Ok(ModerationVerdict::Redact(text)) => {
+                memory.log("Planning", "ANSWER_REDACTED", &format!("len={}->{}", content.len(), text.len()));
+                return Ok(text);
+            }
+            Ok(ModerationVerdict::Rewrite(text)) => {
+                memory.log("Planning", "ANSWER_REWRITTEN", &format!("len={}->{}", content.len(), text.len()));
+                return Ok(text);
+            }
+            Ok(ModerationVerdict::Block { reason }) => reason,
+            Err(e) => {
+                memory.log("Planning", "MODERATION_FAILED", &e);
+                format!("moderation failed: {}", e)
+            }
+        };
+
+        memory.log(
+            "Planning",
+            "ANSWER_BLOCKED",
+            &format!("reason={} action={:?}", reason, moderation.on_blocked),
+        );
+        match moderation.on_blocked {
+            OnAnswerBlocked::Replace(text) => Ok(text),
+            OnAnswerBlocked::Revise if memory.answer_revisions < memory.config.max_answer_revisions => {
+                memory.answer_revisions += 1;
+                memory.answer_feedback = Some(format!(
+                    "Your previous answer was blocked by content moderation ({}). \
+                     Provide an answer that complies with the content policy.",
+                    reason
+                ));
+                Err(Event::answer_blocked())
+            }
+            OnAnswerBlocked::Revise => {
+                memory.error = Some(format!(
+                    "Answer still blocked after {} revisions: {}",
+                    memory.answer_revisions, reason
+                ));
+                memory.log(
+                    "Planning",
+                    "ANSWER_REVISIONS_EXHAUSTED",
+                    &format!("revisions={} reason=moderation", memory.answer_revisions),
+                );
+                Err(Event::answer_revisions_exhausted())
+            }
+            OnAnswerBlocked::Fail => {
+                memory.error = Some(format!("Answer blocked by moderation: {}", reason));
+                Err(Event::answer_blocked())
+            }
+        }
+    }
+
+    /// Send an answer that does not match the output schema back to the
+    /// model, until the revisions run out.
+    fn reject_structured_answer(&self, memory: &mut AgentMemory, answer: &str, problem: String) -> Event {
+        let name = memory.config.output_schema.as_ref().map(|s| s.name.clone()).unwrap_or_default();
+        if memory.answer_revisions >= memory.config.max_answer_revisions {
+            memory.error = Some(format!(
+                "Answer still does not match the '{}' output schema after {} revisions: {}",
+                name, memory.answer_revisions, problem
+            ));
+            memory.log(
+                "Planning",
+                "ANSWER_REVISIONS_EXHAUSTED",
+                &format!("revisions={} reason=output_schema", memory.answer_revisions),
+            );
+            return Event::answer_revisions_exhausted();
+        }
+        memory.answer_revisions += 1;
+        memory.answer_feedback = Some(format!(
+            "Your previous answer was rejected because {}:\n\n{}\n\n\
+             Respond with only a JSON value matching the '{}' schema.",
+            problem, answer, name
+        ));
+        memory.log(
+            "Planning",
+            "INVALID_STRUCTURED_OUTPUT",
+            &format!(
+                "{} revision={}/{}",
+                problem, memory.answer_revisions, memory.config.max_answer_revisions
+            ),
+        );
+        Event::invalid_structured_output()
+    }
+
+    async fn handle_structured_answer(
+        &self,
+        memory: &mut AgentMemory,
+        data: serde_json::Value,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) -> Event {
+        let json_str = serde_json::to_string_pretty(&data).unwrap_or_else(|_| data.to_string());
+        if let Err(problem) = check_structured(memory, &data) {
+            return self.reject_structured_answer(memory, &json_str, problem);
+        }
+        let moderated = match self.moderate_answer(memory, json_str.clone()).await {
+            Ok(text) => text,
+            Err(event) => return event,
+        };
+        // A redaction, rewrite or replacement edits the text, which then has
+        // to pass the same checks as the model's answer
+        if moderated != json_str {
+            let checked = parse_json_answer(&moderated)
+                .map_err(|e| format!("it is not valid JSON ({})", e))
+                .and_then(|data| check_structured(memory, &data));
+            if let Err(problem) = checked {
+                return self.reject_structured_answer(
+                    memory,
+                    &moderated,
+                    format!("content moderation changed it and {}", problem),
+                );
+            }
+        }
+        let json_str = moderated;
+        memory.answer_feedback = None;
+        memory.final_answer = Some(json_str.clone());
+        memory.log(
+            "Planning",
+            "LLM_STRUCTURED_OUTPUT",
+            &json_str.chars().take(100).collect::<String>(),
+        );
+
+        if let Some(tx) = output_tx {
+            let _ = tx.send(AgentOutput::FinalAnswer(json_str));
+        }
+
+        Event::llm_final_answer()
+    }
+
+    /// Accept an answer that stayed cut off after all continuations, telling
+    /// the caller it is incomplete.
+    async fn handle_truncated_answer(
         &self,
         memory: &mut AgentMemory,
         content: String,
         output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
     ) -> Event {
+        memory.log(
+            "Planning",
+            "ANSWER_TRUNCATED",
+            &format!("len={} continuations={}", content.len(), memory.config.max_continuations),
+        );
+        if let Some(tx) = output_tx {
+            let _ = tx.send(AgentOutput::AnswerTruncated(content.clone()));
+        }
+        self.handle_final_answer(memory, content, 1.0, output_tx).await
+    }
+
+    async fn handle_final_answer(
+        &self,
+        memory: &mut AgentMemory,
+        content: String,
+        confidence: f64,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) -> Event {
+        if let Some(event) = self.check_confidence(memory, confidence) {
+            return event;
+        }
+        memory.confidence_score = confidence;
+
+        // With an output schema, a text answer must be the JSON itself
+        if memory.config.output_schema.is_some() {
+            return match parse_json_answer(&content) {
+                Ok(data) => self.handle_structured_answer(memory, data, output_tx).await,
+                Err(e) => self.reject_structured_answer(memory, &content, format!("it is not valid JSON ({})", e)),
+            };
+        }
+
         // Check minimum length
         if content.len() < memory.config.min_answer_length {
-            memory.log(
-                "Planning",
-                "ANSWER_TOO_SHORT",
-                &format!(
-                    "len={} min={}",
+            if memory.answer_revisions < memory.config.max_answer_revisions {
+                memory.answer_revisions += 1;
+                memory.answer_feedback = Some(format!(
+                    "Your previous answer was rejected because it was too short ({} characters, \
+                     minimum is {}):\n\n{}\n\nPlease provide a complete answer to the task.",
+                    content.len(),
+                    memory.config.min_answer_length,
+                    content
+                ));
+                memory.log(
+                    "Planning",
+                    "ANSWER_TOO_SHORT",
+                    &format!(
+                        "len={} min={} revision={}/{}",
+                        content.len(),
+                        memory.config.min_answer_length,
+                        memory.answer_revisions,
+                        memory.config.max_answer_revisions
+                    ),
+                );
+                return Event::answer_too_short();
+            }
+
+            if !memory.config.accept_short_answer {
+                memory.error = Some(format!(
+                    "Answer still too short after {} revisions (len={} min={})",
+                    memory.answer_revisions,
                     content.len(),
                     memory.config.min_answer_length
-                ),
+                ));
+                memory.log(
+                    "Planning",
+                    "ANSWER_REVISIONS_EXHAUSTED",
+                    &format!("revisions={}", memory.answer_revisions),
+                );
+                return Event::answer_revisions_exhausted();
+            }
+
+            memory.log(
+                "Planning",
+                "SHORT_ANSWER_ACCEPTED",
+                &format!("revisions={}", memory.answer_revisions),
             );
-            return Event::answer_too_short();
         }
 
+        // Check citations
+        if memory.config.require_citations && !memory.citations.is_empty() {
+            if let Err(problem) = crate::citations::validate_answer(&content, &memory.citations) {
+                if memory.answer_revisions >= memory.config.max_answer_revisions {
+                    memory.error = Some(format!(
+                        "Answer still not grounded in sources after {} revisions: {}",
+                        memory.answer_revisions, problem
+                    ));
+                    memory.log(
+                        "Planning",
+                        "ANSWER_REVISIONS_EXHAUSTED",
+                        &format!("revisions={} reason=citations", memory.answer_revisions),
+                    );
+                    return Event::answer_revisions_exhausted();
+                }
+                memory.answer_revisions += 1;
+                memory.answer_feedback = Some(format!(
+                    "Your previous answer was rejected because {}:\n\n{}\n\n\
+                     Cite the sources that support each claim as [n]. Available sources:\n{}",
+                    problem,
+                    content,
+                    crate::citations::format_sources(&memory.citations)
+                ));
+                memory.log(
+                    "Planning",
+                    "ANSWER_UNCITED",
+                    &format!(
+                        "{} revision={}/{}",
+                        problem, memory.answer_revisions, memory.config.max_answer_revisions
+                    ),
+                );
+                return Event::answer_uncited();
+            }
+        }
+
+        // Moderate
+        let content = match self.moderate_answer(memory, content).await {
+            Ok(text) => text,
+            Err(event) => return event,
+        };
+
+        // Post-process
+        let content = crate::postprocess::apply(memory, content).await;
+
         // Accept answer
+        memory.answer_feedback = None;
         memory.final_answer = Some(content.clone());
         memory.log(
             "Planning",
@@ -168,6 +926,10 @@ impl AgentState for PlanningState {
         if let Some(tx) = output_tx {
             let _ = tx.send(AgentOutput::StateStarted(State::planning()));
         }
+        // Commentary from a call that never reached Observing is stale
+        memory.current_assistant_text = None;
+        memory.current_model = None;
+        memory.current_usage = None;
 
         // 1. Guard: max steps
         if memory.step >= memory.config.max_steps {
@@ -197,6 +959,18 @@ impl AgentState for PlanningState {
             &format!("step={}/{}", memory.step, memory.config.max_steps),
         );
 
+        // 2a. Deferred tool calls from an earlier response run before asking the LLM again
+        if !memory.deferred_tool_calls.is_empty() {
+            let deferred = std::mem::take(&mut memory.deferred_tool_calls);
+            memory.log(
+                "Planning",
+                "DEFERRED_TOOL_CALLS",
+                &format!("count={}", deferred.len()),
+            );
+            let confidence = memory.confidence_score;
+            return self.handle_parallel_tool_calls(memory, tools, deferred, confidence);
+        }
+
         // 2b. Plan-and-Execute: inject plan context
         if let Some(ref mut plan) = memory.current_plan {
             if !plan.is_complete() {
@@ -210,11 +984,18 @@ impl AgentState for PlanningState {
 
         // 3. Resolve model
         let model = self.resolve_model(memory).to_string();
+        memory.current_model = Some(model.clone());
+
+        // 3a. Frame the request: tool instructions for text prompters
+        memory.prepare_prompt(tools);
 
-        // 3b. Check LLM cache
+        // 3b. Check LLM cache. A cut-off answer is never served from it:
+        // continuing one takes fresh calls (entries from older versions may
+        // still hold one)
         let messages_for_key = memory.build_messages();
         let cache_key = crate::cache::cache_key(&messages_for_key, &model);
-        if let Some(cached_resp) = memory.cache.get(&cache_key) {
+        let cached = memory.cache.get(&cache_key).filter(|r| !matches!(r, LlmResponse::Truncated { .. }));
+        if let Some(cached_resp) = cached {
             memory.log(
                 "Planning",
                 "CACHE_HIT",
@@ -227,33 +1008,36 @@ impl AgentState for PlanningState {
             let (LlmResponse::ToolCall { usage, .. }
             | LlmResponse::ParallelToolCalls { usage, .. }
             | LlmResponse::FinalAnswer { usage, .. }
-            | LlmResponse::Structured { usage, .. }) = &cached_resp;
+            | LlmResponse::Structured { usage, .. }
+            | LlmResponse::Truncated { usage, .. }) = &cached_resp;
             if let Some(u) = usage {
                 memory.total_usage.add(*u);
+                memory.current_usage.get_or_insert_with(TokenUsage::default).add(*u);
             }
+            consume_hints(memory);
             return match cached_resp {
                 LlmResponse::ToolCall {
-                    tool, confidence, ..
-                } => self.handle_tool_call(memory, tool, confidence),
+                    tool,
+                    confidence,
+                    assistant_text,
+                    ..
+                } => {
+                    self.record_commentary(memory, assistant_text, output_tx);
+                    self.handle_tool_call(memory, tools, tool, confidence)
+                }
                 LlmResponse::ParallelToolCalls {
-                    tools, confidence, ..
-                } => self.handle_parallel_tool_calls(memory, tools, confidence),
-                LlmResponse::FinalAnswer { content, .. } => {
-                    self.handle_final_answer(memory, content, output_tx)
+                    tools: calls,
+                    confidence,
+                    ..
+                } => self.handle_parallel_tool_calls(memory, tools, calls, confidence),
+                LlmResponse::FinalAnswer { content, confidence, .. } => {
+                    self.handle_final_answer(memory, content, confidence, output_tx).await
                 }
                 LlmResponse::Structured { data, .. } => {
-                    let json_str =
-                        serde_json::to_string_pretty(&data).unwrap_or_else(|_| data.to_string());
-                    memory.final_answer = Some(json_str.clone());
-                    memory.log(
-                        "Planning",
-                        "LLM_STRUCTURED_OUTPUT",
-                        &json_str.chars().take(100).collect::<String>(),
-                    );
-                    if let Some(tx) = output_tx {
-                        let _ = tx.send(AgentOutput::FinalAnswer(json_str));
-                    }
-                    Event::llm_final_answer()
+                    self.handle_structured_answer(memory, data, output_tx).await
+                }
+                LlmResponse::Truncated { content, .. } => {
+                    self.handle_truncated_answer(memory, content, output_tx).await
                 }
             };
         }
@@ -263,132 +1047,257 @@ impl AgentState for PlanningState {
             &format!("key={}", &cache_key[..12]),
         );
 
-        // 4. Call LLM (streaming)
-        // Hook: on_llm_start
-        memory.hooks.on_llm_start(&model, memory);
-
-        let (final_resp, stream_err) = {
-            let mut stream = llm.call_stream_async(memory, tools, &model, output_tx);
-            let mut final_resp = None;
-            let mut stream_err = None;
-
-            while let Some(chunk_res) = stream.next().await {
-                match chunk_res {
-                    Ok(LlmStreamChunk::Content(token)) => {
-                        if let Some(tx) = output_tx {
-                            let _ = tx.send(AgentOutput::LlmToken(token));
-                        }
-                    }
-                    Ok(LlmStreamChunk::ToolCallDelta { name, args_json }) => {
-                        if let Some(tx) = output_tx {
-                            let _ = tx.send(AgentOutput::ToolCallDelta { name, args_json });
-                        }
-                    }
-                    Ok(LlmStreamChunk::Done(resp)) => {
-                        final_resp = Some(resp);
-                    }
-                    Err(err) => {
-                        stream_err = Some(err);
-                        break;
-                    }
-                }
-            }
-
-            (final_resp, stream_err)
+        // 4. Call LLM (streaming), unless a response was prepared while the last tool ran
+        let resp = match crate::speculation::take_response(memory, &model, output_tx) {
+            Some(resp) => self.accept_response(memory, tools, &model, resp, output_tx),
+            None => match self.call_llm(memory, tools, llm, &model, output_tx).await {
+                Ok(resp) => resp,
+                Err(event) => return event,
+            },
         };
 
-        let resp = if let Some(err) = stream_err {
-            memory.log("Planning", "LLM_STREAM_ERROR", &err);
-            match llm.call_async(memory, tools, &model, output_tx).await {
-                Ok(resp) => {
-                    memory.log(
-                        "Planning",
-                        "LLM_FALLBACK_SYNC",
-                        "Recovered via non-stream call",
-                    );
-                    resp
-                }
-                Err(sync_err) => {
-                    memory.error = Some(format!(
-                        "LLM stream error: {} | fallback call_async error: {}",
-                        err, sync_err
-                    ));
-                    memory.log("Planning", "LLM_ERROR", &sync_err);
-                    // Hook: on_llm_error
-                    memory.hooks.on_llm_error(&model, &sync_err, memory);
-                    return Event::fatal_error();
-                }
-            }
-        } else {
-            match final_resp {
-                Some(r) => r,
-                None => {
-                    let stream_end_err = "LLM stream ended without Done chunk".to_string();
-                    memory.log("Planning", "STREAM_ERROR", &stream_end_err);
-                    match llm.call_async(memory, tools, &model, output_tx).await {
-                        Ok(resp) => {
-                            memory.log(
-                                "Planning",
-                                "LLM_FALLBACK_SYNC",
-                                "Recovered from incomplete stream",
-                            );
-                            resp
-                        }
-                        Err(sync_err) => {
-                            memory.error = Some(format!(
-                                "{} | fallback call_async error: {}",
-                                stream_end_err, sync_err
-                            ));
-                            memory.log("Planning", "LLM_ERROR", &sync_err);
-                            // Hook: on_llm_error
-                            memory.hooks.on_llm_error(&model, &sync_err, memory);
-                            return Event::fatal_error();
-                        }
-                    }
-                }
-            }
+        // 4a. Answer cut off at the output token limit: ask the model to go on
+        let mut resp = match self.continue_truncated(memory, tools, llm, &model, output_tx, resp).await {
+            Ok(resp) => resp,
+            Err(event) => return event,
         };
-        let (LlmResponse::ToolCall { usage, .. }
-        | LlmResponse::ParallelToolCalls { usage, .. }
-        | LlmResponse::FinalAnswer { usage, .. }
-        | LlmResponse::Structured { usage, .. }) = &resp;
 
-        if let Some(u) = usage {
-            memory.total_usage.add(*u);
+        // 4b. Unknown tool names: re-prompt within the same step
+        let mut retries = 0;
+        while let Some(unknown) = unknown_tools(&resp, tools) {
+            if retries >= memory.config.unknown_tool_retries {
+                break;
+            }
+            retries += 1;
+            memory.log(
+                "Planning",
+                "UNKNOWN_TOOL",
+                &format!(
+                    "tools={} retry={}/{}",
+                    unknown.join(","),
+                    retries,
+                    memory.config.unknown_tool_retries
+                ),
+            );
+            let note = unknown_tool_note(&unknown, tools, memory);
+            let saved = memory.answer_feedback.replace(note);
+            let next = self.call_llm(memory, tools, llm, &model, output_tx).await;
+            memory.answer_feedback = saved;
+            resp = match next {
+                Ok(resp) => resp,
+                Err(event) => return event,
+            };
         }
 
-        // Hook: on_llm_end
-        memory.hooks.on_llm_end(&model, &resp, memory);
+        // 4c. Same intent as an earlier call: remind the model instead of spending a step
+        if let Some(dedup) = memory.semantic_dedup.clone() {
+            let mut nudges = 0;
+            while nudges < dedup.max_nudges {
+                let Some(note) = semantic_repeat(memory, &dedup, &resp).await else {
+                    break;
+                };
+                nudges += 1;
+                let saved = memory.answer_feedback.replace(note);
+                let next = self.call_llm(memory, tools, llm, &model, output_tx).await;
+                memory.answer_feedback = saved;
+                resp = match next {
+                    Ok(resp) => resp,
+                    Err(event) => return event,
+                };
+            }
+        }
 
-        // Store in cache
-        memory.cache.put(cache_key, resp.clone());
+        // Store in cache, unless still cut off after all continuations
+        if !matches!(resp, LlmResponse::Truncated { .. }) {
+            memory.cache.put(cache_key, resp.clone());
+        }
+        consume_hints(memory);
 
         match resp {
             LlmResponse::ToolCall {
-                tool, confidence, ..
-            } => self.handle_tool_call(memory, tool, confidence),
+                tool,
+                confidence,
+                assistant_text,
+                ..
+            } => {
+                self.record_commentary(memory, assistant_text, output_tx);
+                self.handle_tool_call(memory, tools, tool, confidence)
+            }
             LlmResponse::ParallelToolCalls {
-                tools, confidence, ..
-            } => self.handle_parallel_tool_calls(memory, tools, confidence),
-            LlmResponse::FinalAnswer { content, .. } => {
-                self.handle_final_answer(memory, content, output_tx)
+                tools: calls,
+                confidence,
+                ..
+            } => self.handle_parallel_tool_calls(memory, tools, calls, confidence),
+            LlmResponse::FinalAnswer { content, confidence, .. } => {
+                self.handle_final_answer(memory, content, confidence, output_tx).await
             }
             LlmResponse::Structured { data, .. } => {
-                let json_str =
-                    serde_json::to_string_pretty(&data).unwrap_or_else(|_| data.to_string());
-                memory.final_answer = Some(json_str.clone());
-                memory.log(
-                    "Planning",
-                    "LLM_STRUCTURED_OUTPUT",
-                    &json_str.chars().take(100).collect::<String>(),
+                self.handle_structured_answer(memory, data, output_tx).await
+            }
+            LlmResponse::Truncated { content, .. } => {
+                self.handle_truncated_answer(memory, content, output_tx).await
+            }
+        }
+    }
+}
+
+/// Add one LLM call's usage to the session totals and send the
+/// `AgentOutput::Usage` event for it.
+pub(crate) fn report_usage(
+    memory: &mut AgentMemory,
+    model: &str,
+    usage: TokenUsage,
+    output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+) {
+    let cost = memory.record_usage(model, usage);
+    if let Some(tx) = output_tx {
+        let _ = tx.send(AgentOutput::Usage {
+            step: memory.step,
+            usage,
+            cumulative: memory.total_usage,
+            cost,
+        });
+    }
+}
+
+/// Check a structured answer against the output schema and the answer
+/// check, describing the first problem as feedback for the model.
+fn check_structured(memory: &AgentMemory, data: &serde_json::Value) -> Result<(), String> {
+    if let Some(schema) = &memory.config.output_schema {
+        crate::tools::check_value(data, &schema.schema, "$")
+            .map_err(|problem| format!("it does not match the schema: {}", problem))?;
+    }
+    match &memory.answer_check {
+        Some(check) => check(data).map_err(|problem| format!("it failed the answer check: {}", problem)),
+        None => Ok(()),
+    }
+}
+
+/// Parse a text answer as JSON, allowing a surrounding Markdown code fence.
+fn parse_json_answer(content: &str) -> Result<serde_json::Value, serde_json::Error> {
+    let text = content.trim();
+    let text = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(text);
+    serde_json::from_str(text.trim())
+}
+
+/// Names in a tool-call response that are not in the registry.
+fn unknown_tools(resp: &LlmResponse, tools: &ToolRegistry) -> Option<Vec<String>> {
+    let calls = match resp {
+        LlmResponse::ToolCall { tool, .. } => std::slice::from_ref(tool),
+        LlmResponse::ParallelToolCalls { tools: calls, .. } => calls.as_slice(),
+        _ => return None,
+    };
+    let unknown: Vec<String> = calls
+        .iter()
+        .filter(|c| !tools.has(&c.name))
+        .map(|c| c.name.clone())
+        .collect();
+    (!unknown.is_empty()).then_some(unknown)
+}
+
+/// A reminder for the first call in `resp` that repeats an earlier call in meaning.
+async fn semantic_repeat(
+    memory: &mut AgentMemory,
+    dedup: &crate::dedup::SemanticDedup,
+    resp: &LlmResponse,
+) -> Option<String> {
+    let calls = match resp {
+        LlmResponse::ToolCall { tool, .. } => std::slice::from_ref(tool),
+        LlmResponse::ParallelToolCalls { tools: calls, .. } => calls.as_slice(),
+        _ => return None,
+    };
+    for call in calls {
+        match dedup.find_repeat(&memory.history, call).await {
+            Ok(Some((entry, similarity))) => {
+                let note = crate::dedup::repeat_note(entry, &memory.markers());
+                let data = format!(
+                    "tool='{}' earlier_step={} similarity={:.2}",
+                    call.name, entry.step, similarity
                 );
+                memory.log("Planning", "SEMANTIC_REPEAT", &data);
+                return Some(note);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                memory.log("Planning", "SEMANTIC_DEDUP_ERROR", &e);
+                return None;
+            }
+        }
+    }
+    None
+}
 
-                if let Some(tx) = output_tx {
-                    let _ = tx.send(AgentOutput::FinalAnswer(json_str));
-                }
+/// Content chunks held back under `TokenEvents::Batched`.
+#[derive(Default)]
+struct TokenBatch {
+    text: String,
+    chunks: usize,
+}
 
-                Event::llm_final_answer()
+impl TokenBatch {
+    /// Send `token` as `mode` asks, after anything still held back.
+    fn push(&mut self, token: String, mode: TokenEvents, tx: &tokio::sync::mpsc::UnboundedSender<AgentOutput>) {
+        match mode {
+            TokenEvents::All => {
+                self.flush(tx);
+                let _ = tx.send(AgentOutput::LlmToken(token));
+            }
+            TokenEvents::Batched(n) => {
+                self.text.push_str(&token);
+                self.chunks += 1;
+                if self.chunks >= n {
+                    self.flush(tx);
+                }
+            }
+            TokenEvents::Off => {
+                self.text.clear();
+                self.chunks = 0;
             }
         }
     }
+
+    fn flush(&mut self, tx: &tokio::sync::mpsc::UnboundedSender<AgentOutput>) {
+        if !self.text.is_empty() {
+            let _ = tx.send(AgentOutput::LlmToken(std::mem::take(&mut self.text)));
+        }
+        self.chunks = 0;
+    }
+}
+
+/// Drop hints once a response to a prompt that carried them is in hand.
+fn consume_hints(memory: &mut AgentMemory) {
+    if !memory.hints.is_empty() {
+        let data = format!("hints={}", memory.hints.len());
+        memory.hints.clear();
+        memory.log("Planning", "HINTS_CONSUMED", &data);
+    }
+}
+
+/// Tells the model which tools it may call instead.
+fn unknown_tool_note(unknown: &[String], tools: &ToolRegistry, memory: &AgentMemory) -> String {
+    let mut available: Vec<String> = tools
+        .schemas()
+        .into_iter()
+        .map(|s| s.name)
+        .filter(|n| !memory.blacklisted_tools.contains(n))
+        .collect();
+    available.sort();
+    let requested = unknown.iter().map(|n| format!("'{}'", n)).collect::<Vec<_>>().join(", ");
+    if available.is_empty() {
+        format!(
+            "You requested {}, but no tools are available. Answer the task directly.",
+            requested
+        )
+    } else {
+        format!(
+            "You requested {}, which does not exist. Available tools are: {}. \
+             Call one of these or give your final answer.",
+            requested,
+            available.join(", ")
+        )
+    }
 }
@@ -3,7 +3,7 @@ use crate::events::Event;
 use crate::memory::AgentMemory;
 use crate::tools::ToolRegistry;
 use crate::llm::AsyncLlmCaller;
-use crate::types::{AgentOutput, HistoryEntry, ToolCall, State};
+use crate::types::{AgentOutput, HistoryEntry, Observation, ToolCall, State};
 use async_trait::async_trait;
 use std::collections::HashMap;
 
@@ -46,13 +46,23 @@ impl AgentState for ReflectingState {
                 args: HashMap::new(),
                 id:   None,
             },
-            observation: summary,
-            success: true,
+            observation: Observation::success(summary),
+            assistant_text: None,
+            latency_ms: None,
+            model_used: None,
+            usage: None,
         };
 
         memory.history = vec![summary_entry];
         memory.retry_count = 0;  // Reset retry budget
 
+        // Context overflow: also drop everything else injected into the prompt
+        if memory.context_overflow {
+            memory.anomaly_notes.clear();
+            memory.answer_feedback = None;
+            memory.log("Reflecting", "EMERGENCY_TRUNCATION", "cleared anomaly notes and feedback");
+        }
+
         memory.log("Reflecting", "COMPRESS_DONE", &format!(
             "compressed to {} entries", memory.history.len()
         ));
@@ -3,8 +3,8 @@ use crate::events::Event;
 use crate::memory::AgentMemory;
 use crate::tools::ToolRegistry;
 use crate::llm::AsyncLlmCaller;
-use crate::types::{AgentOutput, State};
-use crate::human::HumanDecision;
+use crate::types::{AgentOutput, Observation, State, ToolResult};
+use crate::human::{DecisionAttribution, DecisionOutcome, DecisionRecord, HumanDecision};
 use async_trait::async_trait;
 use std::sync::Arc;
 
@@ -36,6 +36,12 @@ impl AgentState for WaitingForHumanState {
         };
 
         memory.log("WaitingForHuman", "APPROVAL_REQUEST", &request.tool_name);
+        crate::notify::send(
+            memory,
+            "WaitingForHuman",
+            crate::notify::NotificationKind::ApprovalRequested { request: request.clone() },
+        )
+        .await;
 
         let callback = match memory.approval_callback.as_ref() {
             Some(cb) => Arc::clone(&cb.0),
@@ -52,20 +58,56 @@ impl AgentState for WaitingForHumanState {
         // Invoke callback
         // NOTE: In a more complex system, this might be a long-running wait.
         // For simplicity, we assume the callback handles the interaction.
-        let decision = callback(request);
+        let tool_name = request.tool_name.clone();
+        let tool_args = request.tool_args.clone();
+        let batch = !request.batch.is_empty();
+        let (decision, attribution) = callback(request).into_parts();
+        let outcome = match decision {
+            HumanDecision::Approved | HumanDecision::Attributed { .. } => DecisionOutcome::Approved,
+            HumanDecision::Rejected(_) => DecisionOutcome::Rejected,
+            HumanDecision::Modified { .. } => DecisionOutcome::Modified,
+        };
+        let by = describe(&attribution);
+        memory.human_decisions.push(DecisionRecord {
+            step: memory.step,
+            tool_name: tool_name.clone(),
+            outcome,
+            decided_by: attribution.decided_by,
+            metadata: attribution.metadata,
+            timestamp: chrono::Utc::now(),
+        });
 
         match decision {
-            HumanDecision::Approved => {
-                memory.log("WaitingForHuman", "APPROVED", "Human approved action");
-                Event::human_approved()
+            HumanDecision::Rejected(reason) if batch => {
+                memory.log("WaitingForHuman", "REJECTED", &format!("{}{}", reason, by));
+                // No call of the batch runs; each gets the rejection as its result
+                let observation = Observation::rejected(reason.as_str());
+                memory.parallel_results = std::mem::take(&mut memory.pending_tool_calls)
+                    .into_iter()
+                    .map(|call| ToolResult::new(call.name, call.args, call.id, observation.clone(), 0))
+                    .collect();
+                Event::human_rejected()
             }
             HumanDecision::Rejected(reason) => {
-                memory.log("WaitingForHuman", "REJECTED", &reason);
-                memory.last_observation = Some(format!("REJECTED: {}", reason));
+                memory.log("WaitingForHuman", "REJECTED", &format!("{}{}", reason, by));
+                memory.last_observation = Some(Observation::rejected(reason.as_str()));
                 Event::human_rejected()
             }
+            HumanDecision::Modified { tool_name: new_name, tool_args: new_args } if batch => {
+                memory.log("WaitingForHuman", "MODIFIED", &format!("{}{}", new_name, by));
+                // Only the call the request named is changed
+                if let Some(tc) = memory
+                    .pending_tool_calls
+                    .iter_mut()
+                    .find(|tc| tc.name == tool_name && tc.args == tool_args)
+                {
+                    tc.name = new_name;
+                    tc.args = new_args;
+                }
+                Event::human_approved_batch()
+            }
             HumanDecision::Modified { tool_name, tool_args } => {
-                memory.log("WaitingForHuman", "MODIFIED", &tool_name);
+                memory.log("WaitingForHuman", "MODIFIED", &format!("{}{}", tool_name, by));
                 // Update the tool call in memory
                 if let Some(ref mut tc) = memory.current_tool_call {
                     tc.name = tool_name;
@@ -73,6 +115,29 @@ impl AgentState for WaitingForHumanState {
                 }
                 Event::human_modified()
             }
+            // `into_parts` has already unwrapped attribution
+            HumanDecision::Approved | HumanDecision::Attributed { .. } => {
+                memory.log("WaitingForHuman", "APPROVED", &format!("Human approved action{}", by));
+                if batch {
+                    Event::human_approved_batch()
+                } else {
+                    Event::human_approved()
+                }
+            }
         }
     }
 }
+
+/// ` by=<principal> <key>=<value>...` for trace entries, or empty.
+fn describe(attribution: &DecisionAttribution) -> String {
+    let mut out = String::new();
+    if let Some(by) = &attribution.decided_by {
+        out.push_str(&format!(" by={}", by));
+    }
+    let mut keys: Vec<&String> = attribution.metadata.keys().collect();
+    keys.sort();
+    for key in keys {
+        out.push_str(&format!(" {}={}", key, attribution.metadata[key]));
+    }
+    out
+}
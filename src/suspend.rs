@@ -0,0 +1,211 @@
+//! Suspension — let an agent wait without burning steps.
+//!
+//! An agent that polls a slow external process (a CI run, a batch job, a
+//! human reply by email) spends a step and an LLM call on every poll. With
+//! the [`wait_tool`] registered, it can call `wait_until` instead: once the
+//! call's observation is in history, the engine saves a checkpoint, logs
+//! `SUSPENDED` and `run` returns `AgentError::Suspended` with the
+//! [`Suspension`]. The session's task and memory are freed.
+//!
+//! Running the engine again — the same engine, or one rebuilt with
+//! `AgentBuilder::resume` — continues from where it waited. The engine logs
+//! `RESUMED` and tells the model when it woke up. Resuming early is
+//! allowed; the model sees the time and can wait again.
+//!
+//! `SessionManager::resume_due` resumes stored sessions whose wake-up time
+//! has passed; call it periodically. Sessions waiting for an event are
+//! resumed by whoever observes it, with `SessionManager::resume_with_event`
+//! (or `AgentEngine::deliver_event`), which hands the model the event's
+//! payload as an observation.
+//!
+//! ```rust,ignore
+//! let sessions = SessionManager::with_store(store);
+//! sessions.start(AgentBuilder::new("Merge the PR once CI is green").openai("").wait_tool())?;
+//!
+//! loop {
+//!     tokio::time::sleep(Duration::from_secs(30)).await;
+//!     sessions.reap().await;
+//!     sessions.resume_due(|_id| AgentBuilder::new("").openai("").wait_tool()).await?;
+//! }
+//! ```
+
+use crate::memory::AgentMemory;
+use crate::tools::Tool;
+use crate::types::{HistoryEntry, Observation, ToolCall};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Name of the built-in wait tool.
+pub const WAIT_TOOL: &str = "wait_until";
+
+/// What a suspended agent is waiting for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Suspension {
+    /// When to resume, if the agent waits for a time
+    pub resume_at: Option<DateTime<Utc>>,
+    /// External event to resume on, if the agent waits for one
+    pub event: Option<String>,
+    /// Why the agent is waiting, in its own words
+    pub reason: Option<String>,
+    pub suspended_at: DateTime<Utc>,
+}
+
+impl Suspension {
+    /// Read the arguments of a `wait_until` call made at `now`.
+    pub fn from_args(args: &HashMap<String, Value>, now: DateTime<Utc>) -> Result<Self, String> {
+        let text = |key: &str| {
+            args.get(key)
+                .and_then(|v| v.as_str())
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        };
+
+        let after = match args.get("seconds") {
+            None | Some(Value::Null) => None,
+            Some(v) => {
+                let secs = v
+                    .as_f64()
+                    .or_else(|| v.as_str().and_then(|s| s.trim().parse().ok()))
+                    .ok_or_else(|| format!("'seconds' must be a number, got {}", v))?;
+                if !(secs > 0.0 && secs.is_finite()) {
+                    return Err(format!("'seconds' must be positive, got {}", secs));
+                }
+                Some(now + chrono::Duration::milliseconds((secs * 1000.0) as i64))
+            }
+        };
+        let until = match text("until") {
+            None => None,
+            Some(s) => Some(
+                DateTime::parse_from_rfc3339(&s)
+                    .map_err(|e| format!("'until' must be an RFC 3339 time such as 2025-01-31T09:00:00Z: {}", e))?
+                    .with_timezone(&Utc),
+            ),
+        };
+        let event = text("event");
+
+        // With both a delay and a time, wake at the earlier one
+        let resume_at = match (after, until) {
+            (Some(a), Some(u)) => Some(a.min(u)),
+            (a, u) => a.or(u),
+        };
+        if resume_at.is_none() && event.is_none() {
+            return Err("give 'seconds', 'until' or 'event'".to_string());
+        }
+
+        Ok(Self { resume_at, event, reason: text("reason"), suspended_at: now })
+    }
+
+    /// Whether the wake-up time has passed. Never for event-only waits.
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.resume_at.is_some_and(|t| t <= now)
+    }
+
+    /// `"until 2025-01-31T09:00:00Z or event 'ci_finished'"`
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(t) = self.resume_at {
+            parts.push(format!("until {}", t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)));
+        }
+        if let Some(event) = &self.event {
+            parts.push(format!("for event '{}'", event));
+        }
+        parts.join(" or ")
+    }
+}
+
+/// The `wait_until` tool. Register it with `AgentBuilder::wait_tool`.
+pub fn wait_tool() -> Tool {
+    Tool::new(
+        WAIT_TOOL,
+        "Pause this run until a time or an external event instead of polling. \
+         The run is saved and continues from here when it is resumed.",
+    )
+    .param_opt("seconds", "number", "Wait this many seconds")
+    .param_opt("until", "string", "Wait until this RFC 3339 time, e.g. 2025-01-31T09:00:00Z")
+    .param_opt("event", "string", "Wait for this external event, e.g. 'ci_finished'")
+    .param_opt("reason", "string", "What you are waiting for")
+    .call(|args| {
+        let suspension = Suspension::from_args(args, Utc::now())?;
+        Ok(format!(
+            "Waiting {}. The run is suspended and continues here when resumed.",
+            suspension.describe()
+        ))
+    })
+}
+
+/// Note a successful `wait_until` call as it is committed to history; the
+/// engine suspends before the next state.
+pub(crate) fn record(memory: &mut AgentMemory, call: &ToolCall, success: bool) {
+    if !success || call.name != WAIT_TOOL {
+        return;
+    }
+    match Suspension::from_args(&call.args, Utc::now()) {
+        Ok(suspension) => memory.suspension = Some(suspension),
+        Err(e) => tracing::warn!(error = %e, "wait_until arguments no longer valid"),
+    }
+}
+
+/// Give a suspended agent the external event it waits for. The payload is
+/// added to history as the observation of a `wait_until` call for the
+/// event, so the model reads it on the next Planning call.
+pub(crate) fn deliver_event(memory: &mut AgentMemory, payload: &Value) -> Result<(), String> {
+    let suspension = memory.suspension.as_ref().ok_or("the agent is not suspended")?;
+    let name = suspension.event.clone().unwrap_or_else(|| "resume".to_string());
+    let content = match payload {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    memory.log("Engine", "RESUME_EVENT", &format!("event='{}' payload={}", name, content.chars().take(100).collect::<String>()));
+    memory.history.push(HistoryEntry {
+        step: memory.step,
+        tool: ToolCall {
+            name: WAIT_TOOL.to_string(),
+            args: HashMap::from([("event".to_string(), Value::String(name.clone()))]),
+            id: None,
+        },
+        observation: Observation::success(format!("Event '{}' arrived: {}", name, content)),
+        assistant_text: None,
+        latency_ms: None,
+        model_used: None,
+        usage: None,
+    });
+    Ok(())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn args(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_suspension_from_args() {
+        let now = Utc::now();
+
+        let s = Suspension::from_args(&args(json!({"seconds": 90, "reason": "CI"})), now).unwrap();
+        assert_eq!(s.resume_at, Some(now + chrono::Duration::seconds(90)));
+        assert_eq!(s.reason.as_deref(), Some("CI"));
+        assert!(!s.is_due(now) && s.is_due(now + chrono::Duration::seconds(90)));
+
+        let s = Suspension::from_args(&args(json!({"event": "ci_finished"})), now).unwrap();
+        assert!(s.resume_at.is_none() && !s.is_due(now + chrono::Duration::days(365)));
+        assert_eq!(s.describe(), "for event 'ci_finished'");
+
+        let s = Suspension::from_args(&args(json!({"until": "2030-01-01T00:00:00Z", "seconds": "60"})), now).unwrap();
+        assert_eq!(s.resume_at, Some(now + chrono::Duration::seconds(60)));
+
+        assert!(Suspension::from_args(&args(json!({})), now).is_err());
+        assert!(Suspension::from_args(&args(json!({"seconds": -5})), now).is_err());
+        assert!(Suspension::from_args(&args(json!({"until": "tomorrow"})), now).is_err());
+    }
+}
@@ -1,12 +1,97 @@
+use std::any::{Any, TypeId};
 use std::collections::HashMap;
 use serde_json::Value;
 
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use crate::human::RiskLevel;
 
 /// A tool function: takes JSON args, returns string result or error string.
 /// Arc<dyn Fn> — shareable, Send + Sync for thread safety.
 pub type ToolFn = Arc<dyn Fn(&HashMap<String, Value>) -> Result<String, String> + Send + Sync>;
 
+/// A tool function that also sees the [`ToolContext`] of the call.
+pub type ToolFnV2 = Arc<dyn Fn(&HashMap<String, Value>, &ToolContext) -> Result<String, String> + Send + Sync>;
+
+/// Adapt an args-only [`ToolFn`] to the [`ToolFnV2`] signature; the context is ignored.
+pub fn upgrade_tool_fn(func: ToolFn) -> ToolFnV2 {
+    Arc::new(move |args, _ctx| func(args))
+}
+
+/// Argument key holding the raw payload when an LLM's tool arguments were not valid JSON.
+pub const RAW_ARGS_KEY: &str = "__raw_arguments";
+
+/// Parse a tool-call argument string from an LLM.
+///
+/// Never fails: an unparseable payload is kept under [`RAW_ARGS_KEY`] so it
+/// can be repaired or reported as a tool failure instead of aborting the call.
+pub fn parse_tool_args(raw: &str) -> HashMap<String, Value> {
+    if raw.trim().is_empty() {
+        return HashMap::new();
+    }
+    serde_json::from_str(raw).unwrap_or_else(|_| {
+        let mut args = HashMap::new();
+        args.insert(RAW_ARGS_KEY.to_string(), Value::String(raw.to_string()));
+        args
+    })
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tool Context
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Values shared with every tool of a session, looked up by type.
+///
+/// Set on the builder with `AgentBuilder::tool_extension` — a database pool,
+/// an HTTP client, tenant credentials. Cloning is cheap.
+#[derive(Clone, Default)]
+pub struct ToolExtensions {
+    map: Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl ToolExtensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `value`, replacing any earlier value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        Arc::make_mut(&mut self.map).insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>()).and_then(|v| v.downcast_ref::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl std::fmt::Debug for ToolExtensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolExtensions").field("len", &self.len()).finish()
+    }
+}
+
+/// What a tool can know about the call it is serving, besides its arguments.
+#[derive(Debug, Clone, Default)]
+pub struct ToolContext {
+    pub session_id:   String,
+    pub step:         usize,
+    pub task:         String,
+    /// Id the LLM gave this call, if any
+    pub tool_call_id: Option<String>,
+    /// Directory shared by the session's tools, if the agent has one
+    pub workspace:    Option<Arc<crate::workspace::Workspace>>,
+    /// Cancelled when the run is; long-running tools should check it
+    pub cancel:       crate::cancel::CancellationToken,
+    pub extensions:   ToolExtensions,
+}
+
 /// Tool schema for sending to LLM (OpenAI / Anthropic tool format)
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct ToolSchema {
@@ -18,8 +103,16 @@ pub struct ToolSchema {
 /// Registered tool entry
 #[derive(Clone)]
 struct ToolEntry {
-    schema: ToolSchema,
-    func:   ToolFn,
+    schema:   ToolSchema,
+    func:     ToolFnV2,
+    /// Has side effects — simulated instead of run in plan mode
+    mutating: bool,
+    /// Declared risk, used by the approval policy
+    risk:     Option<RiskLevel>,
+    /// Always ask a human before running, whatever the approval policy
+    requires_approval: bool,
+    /// JSON Schema results must match (see `Tool::returns`)
+    output_schema: Option<Value>,
 }
 
 #[derive(Clone, Default)]
@@ -45,6 +138,17 @@ impl ToolRegistry {
         description: impl Into<String>,
         schema:      Value,
         func:        ToolFn,
+    ) {
+        self.register_with_context(name, description, schema, upgrade_tool_fn(func));
+    }
+
+    /// Register a tool whose implementation receives the [`ToolContext`].
+    pub fn register_with_context(
+        &mut self,
+        name:        impl Into<String>,
+        description: impl Into<String>,
+        schema:      Value,
+        func:        ToolFnV2,
     ) {
         let name = name.into();
         self.tools.insert(name.clone(), ToolEntry {
@@ -54,25 +158,211 @@ impl ToolRegistry {
                 input_schema: schema,
             },
             func,
+            mutating: false,
+            risk:     None,
+            requires_approval: false,
+            output_schema: None,
         });
     }
 
     /// Register a `Tool` built with the `Tool` builder — ergonomic shorthand.
     pub fn register_tool(&mut self, tool: Tool) {
+        let (mutating, risk, requires_approval) = (tool.mutating, tool.risk, tool.requires_approval);
+        let output_schema = tool.output_schema.clone();
         let (schema, func) = tool.into_parts();
-        self.register(schema.name.clone(), schema.description.clone(), schema.input_schema, func);
+        let name = schema.name.clone();
+        self.register_with_context(name.clone(), schema.description, schema.input_schema, func);
+        if let Some(entry) = self.tools.get_mut(&name) {
+            entry.mutating = mutating;
+            entry.risk = risk;
+            entry.requires_approval = requires_approval;
+            entry.output_schema = output_schema;
+        }
     }
 
-    /// Execute a named tool with given arguments.
+    /// Execute a named tool with given arguments and an empty context.
     /// Returns Ok(result_string) or Err(error_string).
     /// Never panics — all errors are captured as Err variants.
     pub fn execute(&self, name: &str, args: &HashMap<String, Value>) -> Result<String, String> {
-        match self.tools.get(name) {
-            Some(entry) => (entry.func)(args),
-            None        => Err(format!("Tool '{}' not found in registry", name)),
+        self.execute_with(name, args, &ToolContext::default())
+    }
+
+    /// Execute a named tool with given arguments and call context.
+    ///
+    /// A result that does not match the tool's output schema is returned as
+    /// an error.
+    pub fn execute_with(
+        &self,
+        name: &str,
+        args: &HashMap<String, Value>,
+        ctx:  &ToolContext,
+    ) -> Result<String, String> {
+        let entry = self.tools.get(name)
+            .ok_or_else(|| format!("Tool '{}' not found in registry", name))?;
+        let output = (entry.func)(args, ctx)?;
+        self.validate_output(name, &output)
+            .map_err(|e| format!("Tool '{}' returned output that does not match its output schema: {}", name, e))?;
+        Ok(output)
+    }
+
+    /// Check arguments against the tool's JSON Schema.
+    ///
+    /// Only required properties and primitive `type`s are checked; anything
+    /// the schema does not constrain is accepted.
+    pub fn validate_args(&self, name: &str, args: &HashMap<String, Value>) -> Result<(), String> {
+        if let Some(raw) = args.get(RAW_ARGS_KEY) {
+            return Err(format!("arguments are not valid JSON: {}", raw));
+        }
+        let entry = self.tools.get(name)
+            .ok_or_else(|| format!("Tool '{}' not found in registry", name))?;
+        let schema = &entry.schema.input_schema;
+
+        if let Some(required) = schema["required"].as_array() {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !args.contains_key(key) {
+                    return Err(format!("missing required argument '{}'", key));
+                }
+            }
+        }
+
+        if let Some(properties) = schema["properties"].as_object() {
+            for (key, value) in args {
+                let expected = match properties.get(key).and_then(|p| p["type"].as_str()) {
+                    Some(t) => t,
+                    None    => continue,
+                };
+                let ok = match expected {
+                    "string"  => value.is_string(),
+                    "integer" => value.is_i64() || value.is_u64(),
+                    "number"  => value.is_number(),
+                    "boolean" => value.is_boolean(),
+                    "array"   => value.is_array(),
+                    "object"  => value.is_object(),
+                    "null"    => value.is_null(),
+                    _         => true,
+                };
+                if !ok {
+                    return Err(format!("argument '{}' should be of type {}, got {}", key, expected, value));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check a tool's result against its output schema (see `Tool::returns`).
+    ///
+    /// The result must be JSON; a citation block added with
+    /// `citations::with_citations` is ignored. Tools without an output
+    /// schema accept anything.
+    pub fn validate_output(&self, name: &str, output: &str) -> Result<(), String> {
+        let schema = match self.output_schema(name) {
+            Some(schema) => schema,
+            None         => return Ok(()),
+        };
+        let (text, _) = crate::citations::extract_citations(output);
+        let value: Value = serde_json::from_str(&text)
+            .map_err(|e| format!("output is not valid JSON: {}", e))?;
+        check_value(&value, schema, "$")
+    }
+
+    /// The JSON Schema a tool's results match, if it declares one.
+    pub fn output_schema(&self, name: &str) -> Option<&Value> {
+        self.tools.get(name).and_then(|e| e.output_schema.as_ref())
+    }
+
+    /// Returns the schema of a registered tool.
+    pub fn get_schema(&self, name: &str) -> Option<&ToolSchema> {
+        self.tools.get(name).map(|e| &e.schema)
+    }
+
+    /// Unregister a tool. Returns its schema if it was registered.
+    pub fn remove(&mut self, name: &str) -> Option<ToolSchema> {
+        self.tools.remove(name).map(|e| e.schema)
+    }
+
+    /// Register a tool, returning the schema of the tool it replaced (if any).
+    pub fn replace(
+        &mut self,
+        name:        impl Into<String>,
+        description: impl Into<String>,
+        schema:      Value,
+        func:        ToolFn,
+    ) -> Option<ToolSchema> {
+        let name = name.into();
+        let previous = self.remove(&name);
+        self.register(name, description, schema, func);
+        previous
+    }
+
+    /// Iterate over registered tools as `(name, schema)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ToolSchema)> {
+        self.tools.iter().map(|(name, e)| (name.as_str(), &e.schema))
+    }
+
+    /// Move every tool from `other` into this registry.
+    ///
+    /// Tools in `other` win on name clashes; the replaced names are returned.
+    pub fn merge(&mut self, other: ToolRegistry) -> Vec<String> {
+        let mut replaced = Vec::new();
+        for (name, entry) in other.tools {
+            if self.tools.insert(name.clone(), entry).is_some() {
+                replaced.push(name);
+            }
+        }
+        replaced
+    }
+
+    /// Flag a tool as side-effecting. Returns false if no such tool is registered.
+    pub fn set_mutating(&mut self, name: &str, mutating: bool) -> bool {
+        match self.tools.get_mut(name) {
+            Some(entry) => {
+                entry.mutating = mutating;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether a tool is flagged as side-effecting (see `Tool::mutating`).
+    pub fn is_mutating(&self, name: &str) -> bool {
+        self.tools.get(name).is_some_and(|e| e.mutating)
+    }
+
+    /// Declare a tool's risk for the approval policy. Returns false if no
+    /// such tool is registered.
+    pub fn set_risk(&mut self, name: &str, risk: Option<RiskLevel>) -> bool {
+        match self.tools.get_mut(name) {
+            Some(entry) => {
+                entry.risk = risk;
+                true
+            }
+            None => false,
         }
     }
 
+    /// The risk a tool declares (see `Tool::risk`).
+    pub fn risk(&self, name: &str) -> Option<RiskLevel> {
+        self.tools.get(name).and_then(|e| e.risk)
+    }
+
+    /// Require human approval for every call to a tool. Returns false if no
+    /// such tool is registered.
+    pub fn set_requires_approval(&mut self, name: &str, required: bool) -> bool {
+        match self.tools.get_mut(name) {
+            Some(entry) => {
+                entry.requires_approval = required;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether every call to a tool needs approval (see `Tool::requires_approval`).
+    pub fn requires_approval(&self, name: &str) -> bool {
+        self.tools.get(name).is_some_and(|e| e.requires_approval)
+    }
+
     /// Returns true if a tool with this name is registered.
     pub fn has(&self, name: &str) -> bool {
         self.tools.contains_key(name)
@@ -93,12 +383,162 @@ impl ToolRegistry {
     }
 }
 
+/// Check `value` against a JSON Schema: `type` (one or a list), `enum`,
+/// `required`, `properties` and `items`, recursively. Other keywords are
+/// accepted without checking. Errors name the offending path, e.g.
+/// `$.results[0].url`.
+pub(crate) fn check_value(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    let type_matches = |t: &str| match t {
+        "string"  => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number"  => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array"   => value.is_array(),
+        "object"  => value.is_object(),
+        "null"    => value.is_null(),
+        _         => true,
+    };
+    let ok = match &schema["type"] {
+        Value::String(t) => type_matches(t),
+        Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).any(type_matches),
+        _                => true,
+    };
+    if !ok {
+        let expected = match &schema["type"] {
+            Value::String(t) => t.clone(),
+            other            => other.to_string(),
+        };
+        return Err(format!("{} should be of type {}, got {}", path, expected, value));
+    }
+
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            return Err(format!("{} should be one of {}, got {}", path, schema["enum"], value));
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        if let Some(required) = schema["required"].as_array() {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !object.contains_key(key) {
+                    return Err(format!("{} is missing required property '{}'", path, key));
+                }
+            }
+        }
+        if let Some(properties) = schema["properties"].as_object() {
+            for (key, field) in object {
+                if let Some(field_schema) = properties.get(key) {
+                    check_value(field, field_schema, &format!("{}.{}", path, key))?;
+                }
+            }
+        }
+    }
+
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check_value(item, item_schema, &format!("{}[{}]", path, i))?;
+        }
+    }
+
+    Ok(())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Shared Registry
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A [`ToolRegistry`] that can change while an agent runs, for tools
+/// registered at runtime or an MCP server announcing `list_changed`.
+///
+/// Readers take a [`snapshot`](Self::snapshot): the registry as it was at
+/// that moment, unaffected by later changes. Writers change a copy and swap
+/// it in, so a snapshot never holds a half-made change and taking one never
+/// waits on a running tool. The copy is only made while older snapshots are
+/// still in use.
+///
+/// # Consistency for in-flight steps
+///
+/// The engine takes one snapshot per state it handles. A change becomes
+/// visible at the next state:
+/// - Planning offers the model the tools of its snapshot. If one of them is
+///   removed before Acting, the call fails like any unknown tool.
+/// - Acting and ParallelActing validate, check policy for and run every call
+///   of a batch against one snapshot, so a batch never mixes two versions.
+/// - A tool already running finishes even if it is removed meanwhile.
+#[derive(Clone, Default)]
+pub struct SharedToolRegistry {
+    current: Arc<RwLock<Arc<ToolRegistry>>>,
+}
+
+impl SharedToolRegistry {
+    pub fn new(registry: ToolRegistry) -> Self {
+        Self { current: Arc::new(RwLock::new(Arc::new(registry))) }
+    }
+
+    /// The registry as it is now.
+    pub fn snapshot(&self) -> Arc<ToolRegistry> {
+        // A panic in `update` leaves the previous registry in place
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Change the registry. Snapshots already taken keep the old tools.
+    pub fn update<R>(&self, f: impl FnOnce(&mut ToolRegistry) -> R) -> R {
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        f(Arc::make_mut(&mut current))
+    }
+
+    /// Register a tool built with [`Tool`], replacing one of the same name.
+    pub fn register_tool(&self, tool: Tool) {
+        self.update(|registry| registry.register_tool(tool));
+    }
+
+    /// Unregister a tool. Returns its schema if it was registered.
+    pub fn remove(&self, name: &str) -> Option<ToolSchema> {
+        self.update(|registry| registry.remove(name))
+    }
+}
+
+impl From<ToolRegistry> for SharedToolRegistry {
+    fn from(registry: ToolRegistry) -> Self {
+        Self::new(registry)
+    }
+}
 
+impl From<Arc<ToolRegistry>> for SharedToolRegistry {
+    fn from(registry: Arc<ToolRegistry>) -> Self {
+        Self { current: Arc::new(RwLock::new(registry)) }
+    }
+}
 
 // ─────────────────────────────────────────────────────────────────────────────
 // Tool Builder
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// Longest example result shown in a tool description.
+const EXAMPLE_RESULT_CHARS: usize = 500;
+
+/// The examples section of a tool description, or `""` without examples:
+///
+/// ```text
+///
+///
+/// Example:
+/// Call: {"query":"rust"}
+/// Result: Results for 'rust'
+/// ```
+fn render_examples(examples: &[(Value, String)]) -> String {
+    let mut out = String::new();
+    for (i, (args, result)) in examples.iter().enumerate() {
+        let heading = if examples.len() == 1 { "Example".to_string() } else { format!("Example {}", i + 1) };
+        let mut shown: String = result.chars().take(EXAMPLE_RESULT_CHARS).collect();
+        if shown.len() < result.len() {
+            shown.push_str("...");
+        }
+        out.push_str(&format!("\n\n{}:\nCall: {}\nResult: {}", heading, args, shown));
+    }
+    out
+}
+
 /// Parameter definition used by [`Tool`].
 #[derive(Clone)]
 struct ToolParam {
@@ -130,7 +570,13 @@ pub struct Tool {
     name:        String,
     description: String,
     params:      Vec<ToolParam>,
-    func:        Option<ToolFn>,
+    func:        Option<ToolFnV2>,
+    mutating:    bool,
+    risk:        Option<RiskLevel>,
+    requires_approval: bool,
+    output_schema: Option<Value>,
+    /// (args, result) pairs shown to the model
+    examples:    Vec<(Value, String)>,
 }
 
 impl Tool {
@@ -141,6 +587,11 @@ impl Tool {
             description: description.into(),
             params:      Vec::new(),
             func:        None,
+            mutating:    false,
+            risk:        None,
+            requires_approval: false,
+            output_schema: None,
+            examples:    Vec::new(),
         }
     }
 
@@ -179,12 +630,63 @@ impl Tool {
         self
     }
 
+    /// Flag this tool as side-effecting (writes files, deploys, sends email…).
+    /// In plan mode it is simulated instead of run.
+    pub fn mutating(mut self, mutating: bool) -> Self {
+        self.mutating = mutating;
+        self
+    }
+
+    /// Declare how risky a call to this tool is. `ApprovalPolicy::AskAbove`
+    /// and `ToolBased` use it, and it is reported in the `HumanApprovalRequest`.
+    pub fn risk(mut self, risk: RiskLevel) -> Self {
+        self.risk = Some(risk);
+        self
+    }
+
+    /// Ask a human before every call to this tool, whatever the approval policy.
+    pub fn requires_approval(mut self) -> Self {
+        self.requires_approval = true;
+        self
+    }
+
+    /// Declare the JSON Schema of this tool's results.
+    ///
+    /// The schema is appended to the description the LLM sees, so the model
+    /// knows which fields the observation will have before it calls the
+    /// tool. Results are checked against it; a result that is not JSON or
+    /// does not match fails the call with an error naming the mismatch.
+    pub fn returns(mut self, schema: Value) -> Self {
+        self.output_schema = Some(schema);
+        self
+    }
+
+    /// Show the model an example call: `args` (a JSON object) and the
+    /// result it produces. Examples are appended to the description, in the
+    /// order added, in the same format for every provider. Weaker models
+    /// misuse a tool far less after seeing one concrete call.
+    ///
+    /// Results longer than 500 characters are shortened in the description.
+    pub fn example(mut self, args: Value, result: impl Into<String>) -> Self {
+        self.examples.push((args, result.into()));
+        self
+    }
+
     /// Attach the implementation function to this tool.
     ///
     /// This is the final step — it consumes the builder.
     pub fn call<F>(mut self, f: F) -> Self
     where
         F: Fn(&HashMap<String, Value>) -> Result<String, String> + Send + Sync + 'static,
+    {
+        self.func = Some(Arc::new(move |args, _ctx| f(args)));
+        self
+    }
+
+    /// Like [`Tool::call`], for implementations that need the [`ToolContext`].
+    pub fn call_with_context<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&HashMap<String, Value>, &ToolContext) -> Result<String, String> + Send + Sync + 'static,
     {
         self.func = Some(Arc::new(f));
         self
@@ -193,7 +695,7 @@ impl Tool {
     /// Build the JSON Schema and extract the (schema, fn) pair for registration.
     ///
     /// Panics if `.call()` was not invoked before this.
-    pub(crate) fn into_parts(self) -> (ToolSchema, ToolFn) {
+    pub(crate) fn into_parts(self) -> (ToolSchema, ToolFnV2) {
         let func = self.func
             .expect("Tool::call() must be called before registering the tool");
 
@@ -216,9 +718,15 @@ impl Tool {
             "required":   required,
         });
 
+        let mut description = self.description;
+        if let Some(output) = &self.output_schema {
+            description.push_str(&format!("\n\nReturns JSON matching this schema: {}", output));
+        }
+        description.push_str(&render_examples(&self.examples));
+
         let schema = ToolSchema {
             name:         self.name,
-            description:  self.description,
+            description,
             input_schema,
         };
 
@@ -18,6 +18,8 @@ pub struct Trace {
 impl Trace {
     pub fn new() -> Self { Self { entries: Vec::new() } }
 
+    pub fn from_entries(entries: Vec<TraceEntry>) -> Self { Self { entries } }
+
     pub fn record(&mut self, entry: TraceEntry) {
         self.entries.push(entry);
     }
@@ -45,6 +47,31 @@ impl Trace {
             .unwrap_or_else(|_| "[]".to_string())
     }
 
+    /// Renders the trace as Markdown for PRs and incident tickets: one
+    /// collapsible `<details>` table per step. Timestamps are left out, so
+    /// two runs that took the same path produce the same text.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# Agent trace\n");
+        for group in self.entries.chunk_by(|a, b| a.step == b.step) {
+            out.push_str(&format!(
+                "\n<details>\n<summary>Step {} · {} event(s)</summary>\n\n",
+                group[0].step,
+                group.len()
+            ));
+            out.push_str("| State | Event | Data |\n|---|---|---|\n");
+            for e in group {
+                out.push_str(&format!(
+                    "| {} | `{}` | {} |\n",
+                    e.state,
+                    e.event,
+                    table_cell(&e.data)
+                ));
+            }
+            out.push_str("\n</details>\n");
+        }
+        out
+    }
+
     /// Prints a human-readable trace table to stdout
     pub fn print(&self) {
         println!("\n{:<6} {:<14} {:<28} data", "step", "state", "event");
@@ -54,3 +81,62 @@ impl Trace {
         }
     }
 }
+
+/// Text safe inside a Markdown table cell.
+fn table_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\r', "").replace('\n', "<br>")
+}
+
+/// A fenced code block holding `text`, with a fence longer than any run of
+/// backticks inside it.
+pub(crate) fn code_block(lang: &str, text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}{lang}\n{}\n{fence}\n", text.trim_end())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(step: usize, event: &str, data: &str) -> TraceEntry {
+        TraceEntry {
+            step,
+            state: "Planning".to_string(),
+            event: event.to_string(),
+            data: data.to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_markdown_groups_steps_and_escapes() {
+        let trace = || {
+            Trace::from_entries(vec![
+                entry(0, "START", ""),
+                entry(1, "STEP_START", "step=1/15"),
+                entry(1, "LLM_FINAL_ANSWER", "a | b\nc"),
+            ])
+        };
+        let md = trace().to_markdown();
+        assert!(md.contains("<summary>Step 0 · 1 event(s)</summary>"));
+        assert!(md.contains("<summary>Step 1 · 2 event(s)</summary>"));
+        assert!(md.contains("| Planning | `LLM_FINAL_ANSWER` | a \\| b<br>c |"));
+        // Timestamps differ between the two traces; the text does not
+        assert_eq!(md, trace().to_markdown());
+    }
+
+    #[test]
+    fn test_code_block_outgrows_backticks() {
+        assert_eq!(code_block("text", "plain"), "```text\nplain\n```\n");
+        assert!(code_block("", "has ``` inside").starts_with("````\n"));
+    }
+}
@@ -16,6 +16,7 @@ pub fn build_transition_table() -> TransitionTable {
 
     // ── IDLE ─────────────────────────────────────────────
     t.insert((State::idle(),       Event::start()),           State::planning());
+    t.insert((State::idle(),       Event::preflight_failed()), State::error());
 
     // ── PLANNING ─────────────────────────────────────────
     t.insert((State::planning(),   Event::llm_tool_call()),     State::acting());
@@ -24,14 +25,24 @@ pub fn build_transition_table() -> TransitionTable {
     t.insert((State::planning(),   Event::max_steps()),        State::error());
     t.insert((State::planning(),   Event::low_confidence()),   State::reflecting());
     t.insert((State::planning(),   Event::answer_too_short()),  State::planning());
+    t.insert((State::planning(),   Event::answer_revisions_exhausted()), State::error());
+    t.insert((State::planning(),   Event::answer_uncited()),   State::planning());
+    t.insert((State::planning(),   Event::invalid_structured_output()), State::planning());
+    t.insert((State::planning(),   Event::answer_blocked()),   State::error());
+    t.insert((State::planning(),   Event::policy_denied()),    State::error());
     t.insert((State::planning(),   Event::tool_blacklisted()), State::planning());
+    t.insert((State::planning(),   Event::tool_call_reused()), State::planning());
+    t.insert((State::planning(),   Event::repeated_tool_call()), State::reflecting());
     t.insert((State::planning(),   Event::human_approval_required()), State::waiting_for_human());
+    t.insert((State::planning(),   Event::context_overflow()), State::reflecting());
+    t.insert((State::planning(),   Event::llm_retry()),        State::planning());
     t.insert((State::planning(),   Event::fatal_error()),      State::error());
 
     // ── WAITING FOR HUMAN ───────────────────────────────
     t.insert((State::waiting_for_human(), Event::human_approved()), State::acting());
     t.insert((State::waiting_for_human(), Event::human_rejected()), State::observing());
     t.insert((State::waiting_for_human(), Event::human_modified()), State::acting());
+    t.insert((State::waiting_for_human(), Event::human_approved_batch()), State::parallel_acting());
     t.insert((State::waiting_for_human(), Event::fatal_error()),   State::error());
 
     // ── ACTING ───────────────────────────────────────────
@@ -57,6 +68,31 @@ pub fn build_transition_table() -> TransitionTable {
     t
 }
 
+/// Transition table for chat-mode agents (`AgentBuilder::chat_mode`).
+///
+/// There are no tools, so Acting, Observing and Reflecting are left out:
+/// Planning either answers (→ Done), revises its answer, or fails.
+pub fn build_chat_transition_table() -> TransitionTable {
+    let mut t = HashMap::new();
+
+    t.insert((State::idle(),       Event::start()),           State::planning());
+    t.insert((State::idle(),       Event::preflight_failed()), State::error());
+
+    t.insert((State::planning(),   Event::llm_final_answer()),  State::done());
+    t.insert((State::planning(),   Event::max_steps()),        State::error());
+    t.insert((State::planning(),   Event::answer_too_short()),  State::planning());
+    t.insert((State::planning(),   Event::answer_revisions_exhausted()), State::error());
+    t.insert((State::planning(),   Event::answer_uncited()),   State::planning());
+    t.insert((State::planning(),   Event::invalid_structured_output()), State::planning());
+    t.insert((State::planning(),   Event::answer_blocked()),   State::error());
+    t.insert((State::planning(),   Event::policy_denied()),    State::error());
+    t.insert((State::planning(),   Event::context_overflow()), State::error());
+    t.insert((State::planning(),   Event::llm_retry()),        State::planning());
+    t.insert((State::planning(),   Event::fatal_error()),      State::error());
+
+    t
+}
+
 /// Validates that a given (state, event) pair is legal.
 pub fn is_valid_transition(table: &TransitionTable, state: &State, event: &Event) -> bool {
     table.contains_key(&(state.clone(), event.clone()))
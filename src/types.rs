@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// A named state in the agent's state machine.
 ///
@@ -65,16 +66,35 @@ impl State {
 
 /// Result of a single tool execution in a parallel batch.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ToolResultRepr")]
 pub struct ToolResult {
     pub tool_name: String,
     pub tool_args: HashMap<String, serde_json::Value>,
     pub id: Option<String>,
-    pub output: String, // "SUCCESS: ..." or "ERROR: ..."
-    pub success: bool,
+    pub observation: Observation,
     pub latency_ms: u64,
+    /// Times the call was run again after failing (see `parallel_tool_retries`)
+    #[serde(default)]
+    pub retries: u32,
 }
 
 impl ToolResult {
+    pub fn new(
+        tool_name: String,
+        tool_args: HashMap<String, serde_json::Value>,
+        id: Option<String>,
+        observation: Observation,
+        latency_ms: u64,
+    ) -> Self {
+        Self { tool_name, tool_args, id, observation, latency_ms, retries: 0 }
+    }
+
+    /// Record how many times the call was retried.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
     pub fn success(
         tool_name: String,
         tool_args: HashMap<String, serde_json::Value>,
@@ -82,14 +102,7 @@ impl ToolResult {
         output: String,
         latency_ms: u64,
     ) -> Self {
-        Self {
-            tool_name,
-            tool_args,
-            id,
-            output: format!("SUCCESS: {}", output),
-            success: true,
-            latency_ms,
-        }
+        Self::new(tool_name, tool_args, id, Observation::success(output), latency_ms)
     }
 
     pub fn failure(
@@ -99,13 +112,39 @@ impl ToolResult {
         error: String,
         latency_ms: u64,
     ) -> Self {
+        Self::new(tool_name, tool_args, id, Observation::error(error), latency_ms)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.observation.is_success()
+    }
+}
+
+/// `ToolResult` as stored now, or with the `output`/`success` pair older
+/// checkpoints have.
+#[derive(Deserialize)]
+struct ToolResultRepr {
+    tool_name: String,
+    tool_args: HashMap<String, serde_json::Value>,
+    id: Option<String>,
+    #[serde(alias = "output")]
+    observation: Observation,
+    #[serde(default)]
+    success: Option<bool>,
+    latency_ms: u64,
+    #[serde(default)]
+    retries: u32,
+}
+
+impl From<ToolResultRepr> for ToolResult {
+    fn from(repr: ToolResultRepr) -> Self {
         Self {
-            tool_name,
-            tool_args,
-            id,
-            output: format!("ERROR: {}", error),
-            success: false,
-            latency_ms,
+            tool_name: repr.tool_name,
+            tool_args: repr.tool_args,
+            id: repr.id,
+            observation: repr.observation.reconcile(repr.success),
+            latency_ms: repr.latency_ms,
+            retries: repr.retries,
         }
     }
 }
@@ -117,54 +156,293 @@ impl std::fmt::Display for State {
 }
 
 /// A tool invocation requested by the LLM.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ToolCall {
     pub name: String,
     pub args: HashMap<String, serde_json::Value>,
     pub id: Option<String>,
 }
 
+/// How a tool call went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObservationStatus {
+    Success,
+    Error,
+    /// Not run: denied by a human or by policy
+    Rejected,
+}
+
+/// The outcome of a tool call as the agent records it. `content` holds the
+/// tool output or error text. The status marker the model sees (`SUCCESS:`
+/// and so on) is added when the observation is rendered with
+/// [`ObservationMarkers`](crate::locale::ObservationMarkers).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ObservationRepr")]
+pub struct Observation {
+    pub status: ObservationStatus,
+    /// Shared, so cloning memory for checkpoints and forks does not copy tool output
+    pub content: Arc<str>,
+    /// Extra facts about the observation, e.g. `reused_from_step`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+impl Observation {
+    pub fn new(status: ObservationStatus, content: impl Into<Arc<str>>) -> Self {
+        Self { status, content: content.into(), metadata: HashMap::new() }
+    }
+
+    pub fn success(content: impl Into<Arc<str>>) -> Self {
+        Self::new(ObservationStatus::Success, content)
+    }
+
+    pub fn error(content: impl Into<Arc<str>>) -> Self {
+        Self::new(ObservationStatus::Error, content)
+    }
+
+    pub fn rejected(content: impl Into<Arc<str>>) -> Self {
+        Self::new(ObservationStatus::Rejected, content)
+    }
+
+    /// A success or an error, by `success`.
+    pub fn from_outcome(success: bool, content: impl Into<Arc<str>>) -> Self {
+        let status = if success { ObservationStatus::Success } else { ObservationStatus::Error };
+        Self::new(status, content)
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == ObservationStatus::Success
+    }
+
+    /// The text sent to the model: the status marker, then the content.
+    pub fn render(&self, markers: &crate::locale::ObservationMarkers) -> String {
+        match self.status {
+            ObservationStatus::Success => markers.success(&self.content),
+            ObservationStatus::Error => markers.error(&self.content),
+            ObservationStatus::Rejected => markers.rejected(&self.content),
+        }
+    }
+
+    /// Read a marker-prefixed string as written before observations had a
+    /// status. Text without a known marker counts as a success.
+    pub fn parse_legacy(text: &str) -> Self {
+        match crate::locale::ObservationMarkers::parse_any(text) {
+            Some((status, content)) => Self::new(status, content),
+            None => Self::success(text),
+        }
+    }
+
+    /// Apply the `success` flag stored next to a legacy observation: a
+    /// failure whose text had no marker is an error.
+    fn reconcile(mut self, success: Option<bool>) -> Self {
+        if success == Some(false) && self.is_success() {
+            self.status = ObservationStatus::Error;
+        }
+        self
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ObservationRepr {
+    Current {
+        status:   ObservationStatus,
+        content:  Arc<str>,
+        #[serde(default)]
+        metadata: HashMap<String, serde_json::Value>,
+    },
+    Legacy(String),
+}
+
+impl From<ObservationRepr> for Observation {
+    fn from(repr: ObservationRepr) -> Self {
+        match repr {
+            ObservationRepr::Current { status, content, metadata } => Self { status, content, metadata },
+            ObservationRepr::Legacy(text) => Self::parse_legacy(&text),
+        }
+    }
+}
+
 /// A completed tool invocation stored in history.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "HistoryEntryRepr")]
 pub struct HistoryEntry {
     pub step: usize,
     pub tool: ToolCall,
-    pub observation: String,
-    pub success: bool,
+    pub observation: Observation,
+    /// Text the LLM emitted alongside the tool call, if any.
+    #[serde(default)]
+    pub assistant_text: Option<String>,
+    /// How long the tool ran (None if it was not run)
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    /// Model whose response requested the call
+    #[serde(default)]
+    pub model_used: Option<String>,
+    /// Tokens spent on the planning call(s) of this step. Only the first
+    /// entry of a step carries it, so summing over history counts each once.
+    #[serde(default)]
+    pub usage: Option<crate::budget::TokenUsage>,
+}
+
+impl HistoryEntry {
+    pub fn is_success(&self) -> bool {
+        self.observation.is_success()
+    }
+}
+
+/// `HistoryEntry` as stored now, or with the string observation and
+/// `success` flag older checkpoints have.
+#[derive(Deserialize)]
+struct HistoryEntryRepr {
+    step: usize,
+    tool: ToolCall,
+    observation: Observation,
+    #[serde(default)]
+    success: Option<bool>,
+    #[serde(default)]
+    assistant_text: Option<String>,
+    #[serde(default)]
+    latency_ms: Option<u64>,
+    #[serde(default)]
+    model_used: Option<String>,
+    #[serde(default)]
+    usage: Option<crate::budget::TokenUsage>,
+}
+
+impl From<HistoryEntryRepr> for HistoryEntry {
+    fn from(repr: HistoryEntryRepr) -> Self {
+        Self {
+            step: repr.step,
+            tool: repr.tool,
+            observation: repr.observation.reconcile(repr.success),
+            assistant_text: repr.assistant_text,
+            latency_ms: repr.latency_ms,
+            model_used: repr.model_used,
+            usage: repr.usage,
+        }
+    }
 }
 
 /// What the LLM can return. Always one of these two variants.
+///
+/// `model` is set when the model that answered is not the one the caller
+/// was asked for, e.g. a `FallbackLlmCaller` provider with a model
+/// override. Usage is costed against that model.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LlmResponse {
     /// LLM wants to invoke a tool
     ToolCall {
         tool: ToolCall,
         confidence: f64, // 0.0 - 1.0, estimated from response metadata
+        /// Commentary the LLM wrote before requesting the tool
+        #[serde(default)]
+        assistant_text: Option<String>,
         usage: Option<crate::budget::TokenUsage>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
     },
     /// LLM wants to invoke multiple tools in parallel
     ParallelToolCalls {
         tools: Vec<ToolCall>,
         confidence: f64,
         usage: Option<crate::budget::TokenUsage>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
     },
     /// LLM produced a final answer — task is complete
     FinalAnswer {
         content: String,
+        /// 0.0 - 1.0, estimated from response metadata; 1.0 when unknown
+        #[serde(default = "full_confidence")]
+        confidence: f64,
         usage: Option<crate::budget::TokenUsage>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
     },
     /// LLM produced structured output conforming to a schema
     Structured {
         data: serde_json::Value,
         usage: Option<crate::budget::TokenUsage>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+    },
+    /// LLM stopped at the output token limit in the middle of an answer
+    /// (`finish_reason: length`, `stop_reason: max_tokens`)
+    Truncated {
+        content: String,
+        usage: Option<crate::budget::TokenUsage>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
     },
 }
 
+impl LlmResponse {
+    /// A final answer with full confidence and no usage. Prefer it to the
+    /// struct literal in mocks and callers, so they keep compiling when the
+    /// variant gains a field.
+    pub fn final_answer(content: impl Into<String>) -> Self {
+        LlmResponse::FinalAnswer { content: content.into(), confidence: full_confidence(), usage: None, model: None }
+    }
+
+    /// Set the confidence of a tool call or final answer; other variants
+    /// have none and are returned unchanged.
+    pub fn with_confidence(mut self, value: f64) -> Self {
+        match &mut self {
+            LlmResponse::ToolCall { confidence, .. }
+            | LlmResponse::ParallelToolCalls { confidence, .. }
+            | LlmResponse::FinalAnswer { confidence, .. } => *confidence = value,
+            LlmResponse::Structured { .. } | LlmResponse::Truncated { .. } => {}
+        }
+        self
+    }
+
+    /// Note that `value` answered instead of the model asked for.
+    pub fn with_model(mut self, value: impl Into<String>) -> Self {
+        let (LlmResponse::ToolCall { model, .. }
+        | LlmResponse::ParallelToolCalls { model, .. }
+        | LlmResponse::FinalAnswer { model, .. }
+        | LlmResponse::Structured { model, .. }
+        | LlmResponse::Truncated { model, .. }) = &mut self;
+        *model = Some(value.into());
+        self
+    }
+
+    /// The model that answered, if it is not the one asked for.
+    pub fn answered_by(&self) -> Option<&str> {
+        let (LlmResponse::ToolCall { model, .. }
+        | LlmResponse::ParallelToolCalls { model, .. }
+        | LlmResponse::FinalAnswer { model, .. }
+        | LlmResponse::Structured { model, .. }
+        | LlmResponse::Truncated { model, .. }) = self;
+        model.as_deref()
+    }
+
+    pub fn with_usage(mut self, value: crate::budget::TokenUsage) -> Self {
+        match &mut self {
+            LlmResponse::ToolCall { usage, .. }
+            | LlmResponse::ParallelToolCalls { usage, .. }
+            | LlmResponse::FinalAnswer { usage, .. }
+            | LlmResponse::Structured { usage, .. }
+            | LlmResponse::Truncated { usage, .. } => *usage = Some(value),
+        }
+        self
+    }
+}
+
 /// A chunk of streaming output from an LLM.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LlmStreamChunk {
     /// A piece of text content
     Content(String),
+    /// A piece of model reasoning, kept out of the answer
+    Reasoning(String),
     /// Partial tool call arguments (accumulated)
     ToolCallDelta {
         name: Option<String>,
@@ -181,6 +459,9 @@ pub enum AgentOutput {
     StateStarted(State),
     /// A token/chunk of text from the LLM
     LlmToken(String),
+    /// A chunk of the model's reasoning (DeepSeek/Grok `reasoning_content`,
+    /// Claude extended thinking, Gemini thoughts)
+    Reasoning(String),
     /// A chunk of tool call arguments
     ToolCallDelta {
         name: Option<String>,
@@ -197,10 +478,24 @@ pub enum AgentOutput {
         result: String,
         success: bool,
     },
+    /// Text the LLM wrote alongside a tool call
+    Commentary(String),
     /// A generic action or progress message
     Action(String),
+    /// The final answer was still cut off at the output token limit after
+    /// all continuations; it goes through the usual answer checks as is
+    AnswerTruncated(String),
     /// The agent has produced a final answer
     FinalAnswer(String),
+    /// Token usage of an LLM call, sent after every call
+    Usage {
+        step: usize,
+        usage: crate::budget::TokenUsage,
+        /// Usage of the whole session so far
+        cumulative: crate::budget::TokenUsage,
+        /// Session cost in USD so far (None if the model has no pricing)
+        cost: Option<f64>,
+    },
     /// An error occurred during execution
     Error(String),
 }
@@ -220,12 +515,146 @@ pub struct AgentConfig {
     /// Compress history every N steps (0 = never)
     pub reflect_every_n_steps: usize,
 
+    /// Further conditions that trigger history compression after a step
+    #[serde(default)]
+    pub reflection_triggers: Vec<ReflectionTrigger>,
+
     /// Minimum answer length in characters
     pub min_answer_length: usize,
 
+    /// How many times a too-short answer is sent back for revision
+    #[serde(default = "default_max_answer_revisions")]
+    pub max_answer_revisions: usize,
+
+    /// Accept the last short answer instead of failing once revisions run out
+    #[serde(default)]
+    pub accept_short_answer: bool,
+
     /// Whether to allow parallel tool execution
     pub parallel_tools: bool,
 
+    /// Re-prompts within one step when the LLM names an unregistered tool
+    #[serde(default = "default_unknown_tool_retries")]
+    pub unknown_tool_retries: usize,
+
+    /// LLM call failures a run survives; the one that reaches this count fails the run
+    #[serde(default = "default_max_llm_failures_per_run")]
+    pub max_llm_failures_per_run: usize,
+
+    /// Wait before retrying a failed LLM call, doubled on each further failure
+    #[serde(default = "default_llm_retry_delay_ms")]
+    pub llm_retry_delay_ms: u64,
+
+    /// Extra attempts ParallelActing gives each failed call in a batch
+    /// before handing the results to the LLM (0 = none)
+    #[serde(default)]
+    pub parallel_tool_retries: u32,
+
+    /// After a cancel, how long ParallelActing waits for running tools before abandoning them
+    #[serde(default = "default_cancel_grace_ms")]
+    pub cancel_grace_ms: u64,
+
+    /// Follow-up calls asking the model to continue an answer cut off at the
+    /// output token limit (0 = accept the truncated answer)
+    #[serde(default = "default_max_continuations")]
+    pub max_continuations: usize,
+
+    /// Max tool calls accepted from one LLM response; the rest run on later steps
+    #[serde(default)]
+    pub max_tool_calls_per_step: Option<usize>,
+
+    /// Collapse the failed attempts in history into one note when a human
+    /// rejects a tool call (see `AgentMemory::prune_failed_branch`)
+    #[serde(default)]
+    pub prune_on_rejection: bool,
+
+    /// Collapse history once this many tool calls in a row have failed (0 = never)
+    #[serde(default)]
+    pub prune_after_failures: usize,
+
+    /// Reject final answers that do not cite the sources tools attached
+    #[serde(default)]
+    pub require_citations: bool,
+
+    /// Cheap model used to repair malformed tool arguments (None = no repair)
+    #[serde(default)]
+    pub arg_repair_model: Option<String>,
+
+    /// Convert tool arguments to the scalar types their schema declares
+    /// (e.g. `"3"` → `3`) before validation
+    #[serde(default)]
+    pub coerce_tool_args: bool,
+
+    /// Scan tool output for prompt injection before it enters history (None = off)
+    #[serde(default)]
+    pub observation_sanitizer: Option<crate::sanitizer::ObservationSanitizer>,
+
+    /// Read tool calls written as text in this format from final answers (None = off)
+    #[serde(default)]
+    pub text_tool_calls: Option<crate::prompter::TextToolFormat>,
+
+    /// Collapse repeated observations into one history entry (None = off)
+    #[serde(default)]
+    pub observation_dedup: Option<crate::dedup::ObservationDedup>,
+
+    /// Plan the next step while a slow tool call runs, keeping the response
+    /// if it holds for the real observation (None = off)
+    #[serde(default)]
+    pub speculative_planning: Option<crate::speculation::SpeculativePlanning>,
+
+    /// What Planning does when the LLM repeats the previous step's tool call exactly
+    #[serde(default)]
+    pub repeated_call_action: RepeatedCallAction,
+
+    /// BCP 47 tag that picks localized prompts and observation markers
+    #[serde(default = "default_locale")]
+    pub locale: String,
+
+    /// Markers in front of tool observations (None = the built-ins for `locale`)
+    #[serde(default)]
+    pub observation_markers: Option<crate::locale::ObservationMarkers>,
+
+    /// Idle sends an `AgentOutput::Action` naming the task, model and tools
+    #[serde(default)]
+    pub kickoff: bool,
+
+    /// Idle checks tool schemas and provider connectivity before the first
+    /// LLM call, failing with `PreflightFailed` if anything is wrong
+    #[serde(default)]
+    pub preflight: bool,
+
+    /// Each planning request ends with a status line giving the step, the
+    /// tokens used against the budget and the number of usable tools
+    #[serde(default)]
+    pub status_line: bool,
+
+    /// Provider-side sampling options sent with planning requests
+    #[serde(default)]
+    pub sampling: SamplingParams,
+
+    /// Record every memory change in the trace so memory can be rebuilt from it
+    #[serde(default)]
+    pub event_sourced_memory: bool,
+
+    /// Determinism mode: `build()` sets this seed, temperature 0 and
+    /// sequential tool calls, and each run logs `DETERMINISTIC` with the
+    /// seed and config hash (None = off)
+    #[serde(default)]
+    pub deterministic_seed: Option<u64>,
+
+    /// Prices by model name, for cost reporting in `AgentOutput::Usage`
+    #[serde(default)]
+    pub pricing: HashMap<String, crate::budget::ModelPricing>,
+
+    /// What each model's API accepts, for models name detection gets wrong
+    #[serde(default)]
+    pub model_capabilities: HashMap<String, crate::llm::ModelCapabilities>,
+
+    /// Sampling overrides by model name or task type, applied over `sampling`
+    /// (task type first, then model)
+    #[serde(default)]
+    pub model_params: HashMap<String, ModelParams>,
+
     /// Model selection map: task_type → model name string.
     ///
     /// The key `"default"` is used as the fallback when the agent's
@@ -252,6 +681,161 @@ pub struct AgentConfig {
     pub output_schema: Option<OutputSchema>,
 }
 
+/// Sampling options sent with planning requests, for providers that accept them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingParams {
+    /// Constrain the reply with a grammar or JSON schema (llama.cpp, Ollama)
+    #[serde(default)]
+    pub grammar: Option<crate::grammar::GrammarConstraint>,
+
+    /// Cap on output tokens per LLM call (provider default if unset)
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+
+    /// Generation stops before any of these strings; the planning
+    /// prompter's own stop sequences are added
+    #[serde(default)]
+    pub stop: Vec<String>,
+
+    /// Sampling temperature (provider default if unset)
+    #[serde(default)]
+    pub temperature: Option<f32>,
+
+    /// Seed for providers that support reproducible sampling (OpenAI,
+    /// DeepSeek, llama.cpp, Candle); ignored by the others
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// Sampling options for one model or task type, set with
+/// `AgentBuilder::model_params`. Unset fields keep the value from
+/// `AgentConfig::sampling`; stop sequences are added to it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelParams {
+    #[serde(default)]
+    pub temperature: Option<f32>,
+
+    /// Nucleus sampling: only tokens within this cumulative probability
+    #[serde(default)]
+    pub top_p: Option<f32>,
+
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+
+    #[serde(default)]
+    pub stop: Vec<String>,
+
+    /// How much OpenAI reasoning models (o1, o3, o4-mini) think before
+    /// answering; a thinking budget for Claude
+    #[serde(default)]
+    pub reasoning_effort: Option<ReasoningEffort>,
+}
+
+impl ModelParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Sent as `max_completion_tokens` to OpenAI reasoning models, which
+    /// are then called without streaming.
+    pub fn max_tokens(mut self, max: u32) -> Self {
+        self.max_tokens = Some(max);
+        self
+    }
+
+    pub fn stop(mut self, sequence: impl Into<String>) -> Self {
+        self.stop.push(sequence.into());
+        self
+    }
+
+    /// OpenAI calls to a model with an effort set are not streamed: the
+    /// answer arrives as one chunk, without `LlmToken` events.
+    pub fn reasoning_effort(mut self, effort: ReasoningEffort) -> Self {
+        self.reasoning_effort = Some(effort);
+        self
+    }
+
+    /// `other`'s set fields replace these; its stop sequences are added.
+    pub fn merge(&mut self, other: &ModelParams) {
+        self.temperature = other.temperature.or(self.temperature);
+        self.top_p = other.top_p.or(self.top_p);
+        self.max_tokens = other.max_tokens.or(self.max_tokens);
+        self.reasoning_effort = other.reasoning_effort.or(self.reasoning_effort);
+        for s in &other.stop {
+            if !self.stop.contains(s) {
+                self.stop.push(s.clone());
+            }
+        }
+    }
+}
+
+/// `reasoning_effort` for OpenAI reasoning models.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+}
+
+/// How Planning handles a tool call identical (same tool, same arguments)
+/// to the one made in the step before, a common sign of a loop.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RepeatedCallAction {
+    /// Run it again
+    #[default]
+    Allow,
+    /// Skip it and record the previous observation again (`ToolCallReused` → Planning)
+    ReuseObservation,
+    /// Skip it, compress history and tell the model not to repeat itself
+    /// (`RepeatedToolCall` → Reflecting)
+    Reflect,
+}
+
+/// A condition, checked after each step, that sends the agent to `Reflecting`.
+///
+/// Any trigger firing is enough. `reflect_every_n_steps` is checked as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReflectionTrigger {
+    /// Every N steps, like `reflect_every_n_steps`
+    EveryNSteps(usize),
+    /// Estimated prompt size (about 4 characters per token) exceeds N tokens
+    PromptTokensAbove(usize),
+    /// History holds more than N entries
+    HistoryEntriesAbove(usize),
+    /// The last N observations all failed
+    ConsecutiveFailures(usize),
+}
+
+/// Outcome of `AgentEngine::run_detailed()`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunResult {
+    /// The final answer
+    pub answer: String,
+    /// Sources attached by tools, numbered as cited in the answer
+    pub citations: Vec<crate::citations::Citation>,
+}
+
 /// Schema definition for structured LLM output.
 /// The LLM will be instructed to return JSON conforming to this schema.
 ///
@@ -269,6 +853,7 @@ pub struct AgentConfig {
 ///         },
 ///         "required": ["name", "age"]
 ///     }),
+///     strict: false,
 /// };
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -279,6 +864,128 @@ pub struct OutputSchema {
     pub description: Option<String>,
     /// The JSON Schema object defining the expected structure
     pub schema: serde_json::Value,
+    /// Ask OpenAI for `response_format: json_schema` with strict decoding
+    /// instead of JSON mode. The schema must meet OpenAI's strict-mode rules
+    /// (every property required, `additionalProperties: false`).
+    #[serde(default)]
+    pub strict: bool,
+}
+
+fn full_confidence() -> f64 {
+    1.0
+}
+
+fn default_max_answer_revisions() -> usize {
+    2
+}
+
+fn default_unknown_tool_retries() -> usize {
+    1
+}
+
+fn default_max_llm_failures_per_run() -> usize {
+    1
+}
+
+fn default_llm_retry_delay_ms() -> u64 {
+    1000
+}
+
+fn default_max_continuations() -> usize {
+    2
+}
+
+fn default_locale() -> String {
+    crate::locale::DEFAULT_LOCALE.to_string()
+}
+
+fn default_cancel_grace_ms() -> u64 {
+    2000
+}
+
+impl AgentConfig {
+    /// With `deterministic_seed` set, fix everything that makes runs differ:
+    /// the seed, a temperature of 0 and sequential tool calls.
+    pub fn apply_determinism(&mut self) {
+        if let Some(seed) = self.deterministic_seed {
+            self.sampling.seed = Some(seed);
+            self.sampling.temperature = Some(0.0);
+            for params in self.model_params.values_mut() {
+                params.temperature = Some(0.0);
+                params.top_p = None;
+            }
+            self.parallel_tools = false;
+        }
+    }
+
+    /// Check for values that are accepted by the type but make no sense, such
+    /// as `max_steps = 0` (every run stops before its first step). Called by
+    /// `AgentBuilder::build`. The error lists every problem found.
+    pub fn validate(&self) -> Result<(), crate::error::AgentError> {
+        let mut problems = Vec::new();
+
+        if self.max_steps == 0 {
+            problems.push("max_steps is 0, so every run stops before its first step; use at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.confidence_threshold) {
+            problems.push(format!(
+                "confidence_threshold is {}, but confidence scores are between 0.0 and 1.0{}",
+                self.confidence_threshold,
+                if self.confidence_threshold > 1.0 { ", so every step would count as low confidence" } else { "" }
+            ));
+        }
+        if self.reflect_every_n_steps == 1 {
+            problems.push(
+                "reflect_every_n_steps is 1, which compresses history after every step; \
+                 use 0 to turn periodic reflection off, or 2 or more"
+                    .to_string(),
+            );
+        }
+        if self.max_tool_calls_per_step == Some(0) {
+            problems.push("max_tool_calls_per_step is 0, so no tool call would ever run; use None for no limit".to_string());
+        }
+        if let Some(temperature) = self.sampling.temperature.filter(|t| !(t.is_finite() && *t >= 0.0)) {
+            problems.push(format!("sampling.temperature is {}; use 0.0 or more", temperature));
+        }
+        if self.sampling.max_tokens == Some(0) {
+            problems.push("sampling.max_tokens is 0, so the model could not answer; use None for the provider default".to_string());
+        }
+        for (key, params) in &self.model_params {
+            if let Some(temperature) = params.temperature.filter(|t| !(t.is_finite() && *t >= 0.0)) {
+                problems.push(format!("model_params['{}'].temperature is {}; use 0.0 or more", key, temperature));
+            }
+            if let Some(top_p) = params.top_p.filter(|p| !(*p > 0.0 && *p <= 1.0)) {
+                problems.push(format!("model_params['{}'].top_p is {}; use a value above 0.0 and at most 1.0", key, top_p));
+            }
+            if params.max_tokens == Some(0) {
+                problems.push(format!("model_params['{}'].max_tokens is 0, so the model could not answer", key));
+            }
+        }
+        if let Some(threshold) = self
+            .speculative_planning
+            .as_ref()
+            .map(|s| s.threshold)
+            .filter(|t| !(0.0..=1.0).contains(t))
+        {
+            problems.push(format!(
+                "speculative_planning.threshold is {}, but similarity is between 0.0 and 1.0",
+                threshold
+            ));
+        }
+        for (model, pricing) in &self.pricing {
+            let valid = |price: f64| price.is_finite() && price >= 0.0;
+            if !valid(pricing.input_per_mtok) || !valid(pricing.output_per_mtok) {
+                problems.push(format!("pricing for '{}' must be finite and not negative", model));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            problems.sort();
+            Err(crate::error::AgentError::Config(problems))
+        }
+    }
 }
 
 impl Default for AgentConfig {
@@ -288,8 +995,39 @@ impl Default for AgentConfig {
             max_retries: 3,
             confidence_threshold: 0.4,
             reflect_every_n_steps: 5,
+            reflection_triggers: Vec::new(),
             min_answer_length: 5,
+            max_answer_revisions: default_max_answer_revisions(),
+            accept_short_answer: false,
             parallel_tools: true,
+            unknown_tool_retries: 1,
+            max_llm_failures_per_run: default_max_llm_failures_per_run(),
+            llm_retry_delay_ms: default_llm_retry_delay_ms(),
+            parallel_tool_retries: 0,
+            cancel_grace_ms: default_cancel_grace_ms(),
+            max_continuations: default_max_continuations(),
+            max_tool_calls_per_step: None,
+            prune_on_rejection: false,
+            prune_after_failures: 0,
+            require_citations: false,
+            arg_repair_model: None,
+            coerce_tool_args: false,
+            observation_sanitizer: None,
+            text_tool_calls: None,
+            observation_dedup: None,
+            speculative_planning: None,
+            repeated_call_action: RepeatedCallAction::default(),
+            locale: default_locale(),
+            observation_markers: None,
+            kickoff: false,
+            preflight: false,
+            status_line: false,
+            sampling: SamplingParams::default(),
+            event_sourced_memory: false,
+            deterministic_seed: None,
+            pricing: HashMap::new(),
+            model_capabilities: HashMap::new(),
+            model_params: HashMap::new(),
             models: HashMap::new(), // no hardcoded defaults
             output_schema: None,
         }
@@ -0,0 +1,163 @@
+//! Workspaces — a per-session directory for filesystem tools.
+//!
+//! Tools that read and write files need somewhere to do it. A [`Workspace`]
+//! is a directory owned by the engine and handed to every tool through
+//! `ToolContext::workspace`, so tools in one session share it while
+//! parallel sessions never collide.
+//!
+//! `AgentBuilder::temp_workspace()` creates a fresh directory under the
+//! system temp dir for every `build()`; `AgentBuilder::workspace(path)` uses
+//! a directory you choose. At the end of each run the directory is removed
+//! or kept according to its [`WorkspaceCleanup`] policy.
+//!
+//! ```rust,ignore
+//! let tool = Tool::new("write_file", "Write a file in the workspace")
+//!     .param("path", "string", "Relative path")
+//!     .param("content", "string", "File content")
+//!     .call_with_context(|args, ctx| {
+//!         let ws = ctx.workspace.as_ref().ok_or("no workspace")?;
+//!         let path = ws.resolve(args["path"].as_str().unwrap_or(""))?;
+//!         std::fs::write(path, args["content"].as_str().unwrap_or("")).map_err(|e| e.to_string())?;
+//!         Ok("written".to_string())
+//!     });
+//! ```
+
+use std::path::{Component, Path, PathBuf};
+
+/// When a workspace directory is deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkspaceCleanup {
+    /// Never delete it
+    Keep,
+    /// Delete it at the end of every run, and when the engine is dropped
+    Always,
+    /// Delete it only after a successful run; keep failures for inspection
+    OnSuccess,
+}
+
+/// A directory shared by the tools of one session.
+#[derive(Debug)]
+pub struct Workspace {
+    path:    PathBuf,
+    cleanup: WorkspaceCleanup,
+}
+
+impl Workspace {
+    /// A new, empty directory under the system temp dir. Cleanup: `Always`.
+    pub fn temp() -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("agent-b-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path, cleanup: WorkspaceCleanup::Always })
+    }
+
+    /// `path`, created if missing. Cleanup: `Keep`.
+    pub fn at(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path, cleanup: WorkspaceCleanup::Keep })
+    }
+
+    pub fn with_cleanup(mut self, cleanup: WorkspaceCleanup) -> Self {
+        self.cleanup = cleanup;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn cleanup(&self) -> WorkspaceCleanup {
+        self.cleanup
+    }
+
+    /// `relative` inside the workspace. Absolute paths and `..` are rejected,
+    /// so a tool cannot be talked into writing elsewhere.
+    pub fn resolve(&self, relative: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let relative = relative.as_ref();
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!(
+                "Path '{}' must stay inside the workspace",
+                relative.display()
+            ));
+        }
+        Ok(self.path.join(relative))
+    }
+
+    /// Recreate the directory if an earlier run removed it.
+    pub(crate) fn prepare(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.path)
+    }
+
+    /// Apply the cleanup policy after a run. Returns true if the directory was removed.
+    pub(crate) fn finish(&self, succeeded: bool) -> bool {
+        let remove = match self.cleanup {
+            WorkspaceCleanup::Keep => false,
+            WorkspaceCleanup::Always => true,
+            WorkspaceCleanup::OnSuccess => succeeded,
+        };
+        remove && std::fs::remove_dir_all(&self.path).is_ok()
+    }
+}
+
+/// How the builder makes a workspace for each `build()`.
+#[derive(Debug, Clone)]
+pub(crate) struct WorkspaceSpec {
+    /// `None` = a fresh temp dir
+    pub path:    Option<PathBuf>,
+    pub cleanup: Option<WorkspaceCleanup>,
+}
+
+impl WorkspaceSpec {
+    pub fn open(&self) -> std::io::Result<Workspace> {
+        let workspace = match &self.path {
+            Some(path) => Workspace::at(path)?,
+            None => Workspace::temp()?,
+        };
+        Ok(match self.cleanup {
+            Some(cleanup) => workspace.with_cleanup(cleanup),
+            None => workspace,
+        })
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if self.cleanup == WorkspaceCleanup::Always {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_stays_inside() {
+        let ws = Workspace::temp().unwrap();
+        assert_eq!(ws.resolve("a/b.txt").unwrap(), ws.path().join("a/b.txt"));
+        assert!(ws.resolve("../escape").is_err());
+        assert!(ws.resolve("/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_cleanup_policies() {
+        let ws = Workspace::temp().unwrap().with_cleanup(WorkspaceCleanup::OnSuccess);
+        assert!(!ws.finish(false));
+        assert!(ws.path().exists());
+        assert!(ws.finish(true));
+        assert!(!ws.path().exists());
+
+        let temp = Workspace::temp().unwrap();
+        let path = temp.path().to_path_buf();
+        drop(temp);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,183 @@
+use agent_b::llm::MockLlmCaller;
+use agent_b::types::{LlmResponse, ToolCall};
+use agent_b::{AgentBuilder, BatchProvider, BatchRequest, BatchRunner, BatchStatus, InMemoryCache};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Answers every request after one `InProgress` poll. Requests that offer
+/// tools get a tool call first, then a final answer.
+#[derive(Default)]
+struct FakeBatchProvider {
+    submitted: Mutex<Vec<Vec<BatchRequest>>>,
+    polls: Mutex<usize>,
+}
+
+#[async_trait]
+impl BatchProvider for FakeBatchProvider {
+    async fn submit(&self, requests: Vec<BatchRequest>) -> Result<String, String> {
+        let mut submitted = self.submitted.lock().unwrap();
+        submitted.push(requests);
+        Ok(format!("batch_{}", submitted.len()))
+    }
+
+    async fn poll(&self, batch_id: &str) -> Result<BatchStatus, String> {
+        let mut polls = self.polls.lock().unwrap();
+        *polls += 1;
+        if *polls % 2 == 1 {
+            return Ok(BatchStatus::InProgress);
+        }
+        let index: usize = batch_id.trim_start_matches("batch_").parse().unwrap();
+        let requests = self.submitted.lock().unwrap()[index - 1].clone();
+        let results = requests
+            .into_iter()
+            .map(|req| {
+                let already_called = req.messages.iter().any(|m| m["role"] == "tool");
+                let resp = if !req.tools.is_empty() && !already_called {
+                    LlmResponse::ToolCall {
+                        tool: ToolCall {
+                            name: req.tools[0].name.clone(),
+                            args: HashMap::new(),
+                            id: Some("call_1".to_string()),
+                        },
+                        confidence: 1.0,
+                        assistant_text: None,
+                        usage: None,
+                        model: None,
+                    }
+                } else {
+                    LlmResponse::final_answer(format!("classified by {}", req.model))
+                };
+                (req.custom_id, Ok(resp))
+            })
+            .collect();
+        Ok(BatchStatus::Completed(results))
+    }
+}
+
+#[tokio::test]
+async fn test_batch_runner_tool_less_sessions() {
+    let sessions = (0..3)
+        .map(|i| {
+            AgentBuilder::new(format!("Classify ticket {}", i))
+                .llm(Arc::new(MockLlmCaller::new(vec![])))
+                .model("cheap-model")
+                .build()
+                .unwrap()
+        })
+        .collect();
+
+    let provider = Arc::new(FakeBatchProvider::default());
+    let results = BatchRunner::new(provider.clone(), sessions)
+        .poll_interval(Duration::from_millis(1))
+        .run()
+        .await;
+
+    assert_eq!(results.len(), 3);
+    for r in &results {
+        assert_eq!(r.as_deref().unwrap(), "classified by cheap-model");
+    }
+    // One batch covered every session
+    assert_eq!(provider.submitted.lock().unwrap().len(), 1);
+    assert_eq!(provider.submitted.lock().unwrap()[0].len(), 3);
+}
+
+#[tokio::test]
+async fn test_batch_runner_executes_tools_between_batches() {
+    let session = AgentBuilder::new("Look something up")
+        .llm(Arc::new(MockLlmCaller::new(vec![])))
+        .tool(
+            "lookup",
+            "Look something up",
+            serde_json::json!({ "type": "object", "properties": {} }),
+            Arc::new(|_| Ok("found it".to_string())),
+        )
+        .build()
+        .unwrap();
+
+    let provider = Arc::new(FakeBatchProvider::default());
+    let runner = BatchRunner::new(provider.clone(), vec![session])
+        .poll_interval(Duration::from_millis(1));
+    let results = runner.run().await;
+
+    assert!(results[0].is_ok(), "{:?}", results[0]);
+    assert_eq!(provider.submitted.lock().unwrap().len(), 2);
+}
+
+/// Answers every request in a batch with the next scripted response.
+struct ScriptedBatchProvider {
+    responses: Mutex<Vec<LlmResponse>>,
+    submitted: Mutex<Vec<Vec<BatchRequest>>>,
+}
+
+impl ScriptedBatchProvider {
+    fn new(responses: Vec<LlmResponse>) -> Self {
+        Self { responses: Mutex::new(responses), submitted: Mutex::default() }
+    }
+}
+
+#[async_trait]
+impl BatchProvider for ScriptedBatchProvider {
+    async fn submit(&self, requests: Vec<BatchRequest>) -> Result<String, String> {
+        let mut submitted = self.submitted.lock().unwrap();
+        submitted.push(requests);
+        Ok(format!("batch_{}", submitted.len()))
+    }
+
+    async fn poll(&self, batch_id: &str) -> Result<BatchStatus, String> {
+        let index: usize = batch_id.trim_start_matches("batch_").parse().unwrap();
+        let response = self.responses.lock().unwrap().remove(0);
+        let results = self.submitted.lock().unwrap()[index - 1]
+            .iter()
+            .map(|req| (req.custom_id.clone(), Ok(response.clone())))
+            .collect();
+        Ok(BatchStatus::Completed(results))
+    }
+}
+
+#[tokio::test]
+async fn test_batch_result_answers_one_call() {
+    let session = AgentBuilder::new("Write a summary")
+        .llm(Arc::new(MockLlmCaller::new(vec![])))
+        .max_continuations(2)
+        .max_llm_failures(3, Duration::from_millis(1))
+        .build()
+        .unwrap();
+
+    // The continuation of a cut-off answer can't reuse the same result
+    let provider = Arc::new(ScriptedBatchProvider::new(vec![
+        LlmResponse::Truncated { content: "The summ".to_string(), usage: None, model: None },
+        LlmResponse::final_answer("The summary."),
+    ]));
+    let results = BatchRunner::new(provider.clone(), vec![session])
+        .poll_interval(Duration::from_millis(1))
+        .run()
+        .await;
+
+    assert_eq!(results[0].as_deref().unwrap(), "The summary.");
+    assert_eq!(provider.submitted.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_cached_sessions_stay_out_of_the_batch() {
+    let cache = Arc::new(InMemoryCache::new(16, Duration::from_secs(60)));
+    let session = || {
+        AgentBuilder::new("Classify ticket 1")
+            .llm(Arc::new(MockLlmCaller::new(vec![])))
+            .cache(cache.clone())
+            .build()
+            .unwrap()
+    };
+
+    let first = Arc::new(ScriptedBatchProvider::new(vec![LlmResponse::final_answer("billing")]));
+    let results = BatchRunner::new(first.clone(), vec![session()]).run().await;
+    assert_eq!(results[0].as_deref().unwrap(), "billing");
+    assert_eq!(first.submitted.lock().unwrap().len(), 1);
+
+    // The same prompt again is answered from the cache, without a batch
+    let second = Arc::new(ScriptedBatchProvider::new(vec![]));
+    let results = BatchRunner::new(second.clone(), vec![session()]).run().await;
+    assert_eq!(results[0].as_deref().unwrap(), "billing");
+    assert!(second.submitted.lock().unwrap().is_empty());
+}
@@ -15,12 +15,11 @@ async fn test_token_usage_accumulation() {
                 id: Some("call_1".to_string()),
             },
             confidence: 1.0,
+            assistant_text: None,
             usage: Some(TokenUsage::new(10, 20)), // Total 30
+            model: None,
         },
-        LlmResponse::FinalAnswer {
-            content: "Answer that is long enough to pass minimum length check.".to_string(),
-            usage: Some(TokenUsage::new(5, 15)), // Total 20
-        },
+        LlmResponse::final_answer("Answer that is long enough to pass minimum length check.").with_usage(TokenUsage::new(5, 15)), // Total 20
     ];
 
     let mut agent = AgentBuilder::new("Test usage")
@@ -37,6 +36,37 @@ async fn test_token_usage_accumulation() {
     assert_eq!(agent.memory.total_usage.total_tokens, 50);
 }
 
+#[tokio::test]
+async fn test_history_entry_records_step_cost() {
+    let mock_responses = vec![
+        LlmResponse::ToolCall {
+            tool: ToolCall {
+                name: "dummy".to_string(),
+                args: HashMap::new(),
+                id: Some("call_1".to_string()),
+            },
+            confidence: 1.0,
+            assistant_text: None,
+            usage: Some(TokenUsage::new(10, 20)),
+            model: None,
+        },
+        LlmResponse::final_answer("Answer that is long enough to pass minimum length check.").with_usage(TokenUsage::new(5, 15)),
+    ];
+
+    let mut agent = AgentBuilder::new("Test step cost")
+        .llm(Arc::new(MockLlmCaller::new(mock_responses)))
+        .tool("dummy", "desc", serde_json::json!({}), Arc::new(|_| Ok("res".to_string())))
+        .build()
+        .unwrap();
+
+    agent.run().await.unwrap();
+
+    let entry = &agent.memory.history[0];
+    assert_eq!(entry.usage, Some(TokenUsage::new(10, 20)));
+    assert!(entry.model_used.is_some());
+    assert!(entry.latency_ms.is_some());
+}
+
 #[tokio::test]
 async fn test_budget_enforcement() {
     let mock_responses = vec![
@@ -47,12 +77,11 @@ async fn test_budget_enforcement() {
                 id: Some("call_1".to_string()),
             },
             confidence: 1.0,
+            assistant_text: None,
             usage: Some(TokenUsage::new(60, 0)), // 60 total
+            model: None,
         },
-        LlmResponse::FinalAnswer {
-            content: "Should not be reached".to_string(),
-            usage: None,
-        },
+        LlmResponse::final_answer("Should not be reached"),
     ];
 
     let mut agent = AgentBuilder::new("Test budget")
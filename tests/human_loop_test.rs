@@ -1,7 +1,7 @@
 use agent_b::AgentBuilder;
 use agent_b::llm::MockLlmCaller;
 use agent_b::human::{ApprovalPolicy, RiskLevel, HumanDecision, HumanApprovalRequest};
-use agent_b::types::{LlmResponse, ToolCall};
+use agent_b::types::{LlmResponse, ObservationStatus, ToolCall};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -20,19 +20,18 @@ async fn test_human_approval_flow() {
                 id: Some("call_1".to_string()),
             },
             confidence: 1.0,
+            assistant_text: None,
             usage:      None,
+            model: None,
         },
-        LlmResponse::FinalAnswer {
-            content: "I have deleted the database as requested.".to_string(),
-            usage:   None,
-        },
+        LlmResponse::final_answer("I have deleted the database as requested."),
     ];
     let mock_llm = MockLlmCaller::new(responses);
 
     // 3. Setup approval policy: Require approval for "delete_database"
     let mut policy_map = HashMap::new();
     policy_map.insert("delete_database".to_string(), RiskLevel::Critical);
-    let policy = ApprovalPolicy::ToolBased(policy_map);
+    let policy = ApprovalPolicy::tool_based(policy_map, RiskLevel::High);
 
     // 4. Build agent with HIP
     let mut agent = AgentBuilder::new("Delete the database")
@@ -53,7 +52,7 @@ async fn test_human_approval_flow() {
     // Check history: should have 1 successful tool call
     assert_eq!(agent.memory.history.len(), 1);
     assert_eq!(agent.memory.history[0].tool.name, "delete_database");
-    assert!(agent.memory.history[0].success);
+    assert!(agent.memory.history[0].is_success());
 }
 
 #[tokio::test]
@@ -69,12 +68,11 @@ async fn test_human_rejection_flow() {
                 id: Some("call_1".to_string()),
             },
             confidence: 1.0,
+            assistant_text: None,
             usage:      None,
+            model: None,
         },
-        LlmResponse::FinalAnswer {
-            content: "I couldn't delete the database because you rejected it.".to_string(),
-            usage:   None,
-        },
+        LlmResponse::final_answer("I couldn't delete the database because you rejected it."),
     ];
     let mock_llm = MockLlmCaller::new(responses);
 
@@ -96,8 +94,8 @@ async fn test_human_rejection_flow() {
     
     // Check history: should have 1 FAILED entry (rejected is failure)
     assert_eq!(agent.memory.history.len(), 1);
-    assert!(!agent.memory.history[0].success);
-    assert!(agent.memory.history[0].observation.contains("REJECTED"));
+    assert!(!agent.memory.history[0].is_success());
+    assert_eq!(agent.memory.history[0].observation.status, ObservationStatus::Rejected);
 }
 
 #[tokio::test]
@@ -113,12 +111,11 @@ async fn test_human_modification_flow() {
                 id: Some("call_1".to_string()),
             },
             confidence: 1.0,
+            assistant_text: None,
             usage:      None,
+            model: None,
         },
-        LlmResponse::FinalAnswer {
-            content: "Here are the files...".to_string(),
-            usage:   None,
-        },
+        LlmResponse::final_answer("Here are the files..."),
     ];
     let mock_llm = MockLlmCaller::new(responses);
 
@@ -149,3 +146,242 @@ async fn test_human_modification_flow() {
     assert_eq!(agent.memory.history.len(), 1);
     assert_eq!(agent.memory.history[0].tool.args.get("dir").unwrap().as_str().unwrap(), "/tmp");
 }
+
+#[tokio::test]
+async fn test_rejection_prunes_failed_branch() {
+    let unsafe_tool = agent_b::Tool::new("delete_database", "Deletes all data")
+        .call(|_| Ok("Database deleted".to_string()));
+    let delete = || LlmResponse::ToolCall {
+        tool: ToolCall {
+            name: "delete_database".to_string(),
+            args: HashMap::new(),
+            id: Some("call_1".to_string()),
+        },
+        confidence: 1.0,
+        assistant_text: None,
+        usage:      None,
+        model: None,
+    };
+    let mock_llm = MockLlmCaller::new(vec![
+        delete(),
+        delete(),
+        LlmResponse::final_answer("I will not delete the database."),
+    ]);
+
+    let mut agent = AgentBuilder::new("Delete the database")
+        .llm(Arc::new(mock_llm))
+        .add_tool(unsafe_tool)
+        .approval_policy(ApprovalPolicy::AlwaysAsk)
+        .on_approval(|_| HumanDecision::Rejected("not today".to_string()))
+        .prune_on_rejection(true)
+        .build()
+        .unwrap();
+
+    agent.run().await.unwrap();
+
+    // Both rejected attempts collapse into a single note outside history
+    assert!(agent.memory.history.is_empty());
+    assert_eq!(agent.memory.pruned_notes.len(), 1);
+    let note = &agent.memory.pruned_notes[0];
+    assert!(note.contains("`delete_database` was rejected"));
+    assert!(note.contains("not today"));
+}
+
+fn delete_then_answer() -> MockLlmCaller {
+    MockLlmCaller::new(vec![
+        LlmResponse::ToolCall {
+            tool: ToolCall {
+                name: "delete_database".to_string(),
+                args: HashMap::new(),
+                id: Some("call_1".to_string()),
+            },
+            confidence: 1.0,
+            assistant_text: None,
+            usage:      None,
+            model: None,
+        },
+        LlmResponse::final_answer("Done."),
+    ])
+}
+
+#[tokio::test]
+async fn test_declared_risk_flows_into_approval_request() {
+    let unsafe_tool = agent_b::Tool::new("delete_database", "Deletes all data")
+        .risk(RiskLevel::Critical)
+        .call(|_| Ok("Database deleted".to_string()));
+
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen_in_handler = seen.clone();
+    let mut agent = AgentBuilder::new("Delete the database")
+        .llm(Arc::new(delete_then_answer()))
+        .add_tool(unsafe_tool)
+        .approval_policy(ApprovalPolicy::AskAbove(RiskLevel::High))
+        .on_approval(move |req: HumanApprovalRequest| {
+            seen_in_handler.lock().unwrap().push(req);
+            HumanDecision::Approved
+        })
+        .build()
+        .unwrap();
+
+    agent.run().await.unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert_eq!(seen[0].risk_level, RiskLevel::Critical);
+    assert!(agent.memory.history[0].is_success());
+}
+
+#[tokio::test]
+async fn test_low_declared_risk_skips_approval() {
+    let safe_tool = agent_b::Tool::new("delete_database", "Deletes a scratch table")
+        .risk(RiskLevel::Low)
+        .call(|_| Ok("Table deleted".to_string()));
+
+    let mut agent = AgentBuilder::new("Delete the scratch table")
+        .llm(Arc::new(delete_then_answer()))
+        .add_tool(safe_tool)
+        .approval_policy(ApprovalPolicy::AskAbove(RiskLevel::Medium))
+        .on_approval(|_| HumanDecision::Rejected("should not be asked".to_string()))
+        .build()
+        .unwrap();
+
+    agent.run().await.unwrap();
+
+    assert_eq!(agent.memory.history.len(), 1);
+    assert!(agent.memory.history[0].is_success());
+}
+
+#[tokio::test]
+async fn test_requires_approval_overrides_never_ask() {
+    let unsafe_tool = agent_b::Tool::new("delete_database", "Deletes all data")
+        .requires_approval()
+        .call(|_| Ok("Database deleted".to_string()));
+
+    let mut agent = AgentBuilder::new("Delete the database")
+        .llm(Arc::new(delete_then_answer()))
+        .add_tool(unsafe_tool)
+        .approval_policy(ApprovalPolicy::NeverAsk)
+        .on_approval(|_| HumanDecision::Rejected("no".to_string()))
+        .build()
+        .unwrap();
+
+    agent.run().await.unwrap();
+
+    assert_eq!(agent.memory.history.len(), 1);
+    assert!(!agent.memory.history[0].is_success());
+    assert_eq!(agent.memory.history[0].observation.status, ObservationStatus::Rejected);
+}
+
+#[tokio::test]
+async fn test_decisions_are_attributed() {
+    use agent_b::human::DecisionOutcome;
+    use agent_b::{AuditSink, MemoryAuditSink};
+
+    let unsafe_tool = agent_b::Tool::new("delete_database", "Deletes all data")
+        .call(|_| Ok("Database deleted".to_string()));
+    let sink = Arc::new(MemoryAuditSink::new());
+
+    let mut agent = AgentBuilder::new("Delete the database")
+        .llm(Arc::new(delete_then_answer()))
+        .add_tool(unsafe_tool)
+        .approval_policy(ApprovalPolicy::AlwaysAsk)
+        .on_approval(|_| HumanDecision::Approved.by("alice@example.com").with_metadata("ticket", "OPS-42"))
+        .audit_sink(sink.clone() as Arc<dyn AuditSink>)
+        .build()
+        .unwrap();
+
+    agent.run().await.unwrap();
+
+    let record = &agent.memory.human_decisions[0];
+    assert_eq!(record.outcome, DecisionOutcome::Approved);
+    assert_eq!(record.decided_by.as_deref(), Some("alice@example.com"));
+    assert_eq!(record.metadata["ticket"], "OPS-42");
+
+    let approved = agent.trace().entries().iter().find(|e| e.event == "APPROVED").unwrap();
+    assert_eq!(approved.data, "Human approved action by=alice@example.com ticket=\"OPS-42\"");
+
+    assert_eq!(sink.records()[0].approved_by.as_deref(), Some("alice@example.com"));
+
+    // Decisions are saved with the rest of memory
+    let json = serde_json::to_string(&agent.memory).unwrap();
+    let restored: agent_b::AgentMemory = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.human_decisions, agent.memory.human_decisions);
+}
+
+fn read_and_delete() -> MockLlmCaller {
+    MockLlmCaller::new(vec![
+        LlmResponse::ParallelToolCalls {
+            tools: vec![
+                ToolCall { name: "read_table".to_string(), args: HashMap::new(), id: Some("call_1".to_string()) },
+                ToolCall { name: "delete_database".to_string(), args: HashMap::new(), id: Some("call_2".to_string()) },
+            ],
+            confidence: 1.0,
+            usage: None,
+            model: None,
+        },
+        LlmResponse::final_answer("Done."),
+    ])
+}
+
+#[tokio::test]
+async fn test_parallel_batch_waits_for_approval() {
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen_in_handler = seen.clone();
+    let mut agent = AgentBuilder::new("Clean up")
+        .llm(Arc::new(read_and_delete()))
+        .add_tool(agent_b::Tool::new("read_table", "Reads a table").call(|_| Ok("rows".to_string())))
+        .add_tool(
+            agent_b::Tool::new("delete_database", "Deletes all data")
+                .requires_approval()
+                .call(|_| Ok("Database deleted".to_string())),
+        )
+        .approval_policy(ApprovalPolicy::NeverAsk)
+        .on_approval(move |req: HumanApprovalRequest| {
+            seen_in_handler.lock().unwrap().push(req);
+            HumanDecision::Approved
+        })
+        .build()
+        .unwrap();
+
+    agent.run().await.unwrap();
+
+    // One request for the whole batch, naming the call that needed it
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert_eq!(seen[0].tool_name, "delete_database");
+    assert_eq!(seen[0].batch.len(), 2);
+    assert_eq!(agent.memory.history.len(), 2);
+    assert!(agent.memory.history.iter().all(|h| h.is_success()));
+}
+
+#[tokio::test]
+async fn test_rejected_parallel_batch_runs_nothing() {
+    let ran = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let ran_in_tool = ran.clone();
+    let mut agent = AgentBuilder::new("Clean up")
+        .llm(Arc::new(read_and_delete()))
+        .add_tool(agent_b::Tool::new("read_table", "Reads a table").call(move |_| {
+            ran_in_tool.store(true, std::sync::atomic::Ordering::SeqCst);
+            Ok("rows".to_string())
+        }))
+        .add_tool(
+            agent_b::Tool::new("delete_database", "Deletes all data")
+                .risk(RiskLevel::Critical)
+                .call(|_| Ok("Database deleted".to_string())),
+        )
+        .approval_policy(ApprovalPolicy::AskAbove(RiskLevel::High))
+        .on_approval(|_| HumanDecision::Rejected("not today".to_string()))
+        .build()
+        .unwrap();
+
+    agent.run().await.unwrap();
+
+    assert!(!ran.load(std::sync::atomic::Ordering::SeqCst));
+    assert_eq!(agent.memory.history.len(), 2);
+    assert!(agent
+        .memory
+        .history
+        .iter()
+        .all(|h| h.observation.status == ObservationStatus::Rejected));
+    assert_eq!(agent.memory.history[1].tool.id.as_deref(), Some("call_2"));
+}
@@ -13,12 +13,12 @@ async fn test_mcp_integration() {
     let engine = builder.openai("sk-fake-key").build().unwrap();
     
     // Verify tool was registered
-    assert!(engine.tools.has("echo"));
+    assert!(engine.tools.snapshot().has("echo"));
     
     // Execute the tool
     let mut args = HashMap::new();
     args.insert("message".to_string(), json!("Hello MCP"));
     
-    let result = engine.tools.execute("echo", &args).unwrap();
+    let result = engine.tools.snapshot().execute("echo", &args).unwrap();
     assert_eq!(result, "Echo: Hello MCP");
 }
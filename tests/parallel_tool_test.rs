@@ -22,11 +22,9 @@ async fn test_parallel_tool_execution() {
             ],
             confidence: 1.0,
             usage: None,
+            model: None,
         },
-        LlmResponse::FinalAnswer {
-            content: "Both tools finished.".to_string(),
-            usage: None,
-        },
+        LlmResponse::final_answer("Both tools finished."),
     ];
 
     let mut agent = AgentBuilder::new("Run two tools")
@@ -51,3 +49,72 @@ async fn test_parallel_tool_execution() {
     // Parallel should take ~100ms, sequential would take ~200ms
     assert!(duration.as_millis() < 180, "Parallel execution seems too slow: {}ms", duration.as_millis());
 }
+
+fn make_call(name: &str) -> ToolCall {
+    ToolCall {
+        name: name.to_string(),
+        args: HashMap::new(),
+        id: Some(format!("id_{}", name)),
+    }
+}
+
+#[tokio::test]
+async fn test_excess_parallel_calls_are_deferred() {
+    let mock = Arc::new(MockLlmCaller::new(vec![
+        LlmResponse::ParallelToolCalls {
+            tools: vec![make_call("tool_a"), make_call("tool_b"), make_call("tool_c")],
+            confidence: 1.0,
+            usage: None,
+            model: None,
+        },
+        LlmResponse::final_answer("All three tools finished."),
+    ]));
+
+    let mut agent = AgentBuilder::new("Run three tools")
+        .llm(mock.clone())
+        .tool("tool_a", "desc", serde_json::json!({}), Arc::new(|_| Ok("A".to_string())))
+        .tool("tool_b", "desc", serde_json::json!({}), Arc::new(|_| Ok("B".to_string())))
+        .tool("tool_c", "desc", serde_json::json!({}), Arc::new(|_| Ok("C".to_string())))
+        .max_tool_calls_per_step(2)
+        .build()
+        .unwrap();
+
+    let answer = agent.run().await.unwrap();
+    assert_eq!(answer, "All three tools finished.");
+
+    // The deferred call ran on its own step without another LLM call
+    assert_eq!(mock.call_count(), 2);
+    let steps: Vec<(usize, &str)> = agent
+        .memory
+        .history
+        .iter()
+        .map(|h| (h.step, h.tool.name.as_str()))
+        .collect();
+    assert_eq!(steps, vec![(1, "tool_a"), (1, "tool_b"), (2, "tool_c")]);
+}
+
+#[tokio::test]
+async fn test_parallel_disabled_runs_calls_one_per_step() {
+    let mock = Arc::new(MockLlmCaller::new(vec![
+        LlmResponse::ParallelToolCalls {
+            tools: vec![make_call("tool_a"), make_call("tool_b")],
+            confidence: 1.0,
+            usage: None,
+            model: None,
+        },
+        LlmResponse::final_answer("Both tools finished."),
+    ]));
+
+    let mut agent = AgentBuilder::new("Run two tools")
+        .llm(mock.clone())
+        .tool("tool_a", "desc", serde_json::json!({}), Arc::new(|_| Ok("A".to_string())))
+        .tool("tool_b", "desc", serde_json::json!({}), Arc::new(|_| Ok("B".to_string())))
+        .parallel_tools(false)
+        .build()
+        .unwrap();
+
+    agent.run().await.unwrap();
+    assert_eq!(mock.call_count(), 2);
+    assert_eq!(agent.memory.history.len(), 2);
+    assert_eq!(agent.memory.history[1].step, 2);
+}
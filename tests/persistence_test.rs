@@ -1,7 +1,7 @@
 use agent_b::AgentBuilder;
 use agent_b::llm::MockLlmCaller;
 use agent_b::types::{LlmResponse, ToolCall};
-use agent_b::checkpoint::{MemoryCheckpointStore, FileCheckpointStore, SqliteCheckpointStore, CheckpointStore, AgentCheckpoint};
+use agent_b::checkpoint::{MemoryCheckpointStore, FileCheckpointStore, SqliteCheckpointStore, CheckpointStore, AgentCheckpoint, MemoryCodec};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tempfile::TempDir;
@@ -21,7 +21,9 @@ async fn test_persistence_memory_store() {
                     id: Some("call_1".to_string()),
                 },
                 confidence: 1.0,
+                assistant_text: None,
                 usage:      None,
+                model: None,
             },
         ];
         
@@ -53,10 +55,7 @@ async fn test_persistence_memory_store() {
     // 2. Resume run: verify history is preserved
     {
         let responses = vec![
-            LlmResponse::FinalAnswer {
-                content: "resumed answer that is long enough".to_string(),
-                usage:   None,
-            },
+            LlmResponse::final_answer("resumed answer that is long enough"),
         ];
         
         let tool = agent_b::Tool::new("test_tool", "desc")
@@ -73,7 +72,7 @@ async fn test_persistence_memory_store() {
         println!("Resumed state: {}", agent.current_state());
         assert_eq!(agent.memory.task, "Task 1");
         assert_eq!(agent.memory.history.len(), 1);
-        assert!(agent.memory.history[0].observation.contains("result 1"));
+        assert!(agent.memory.history[0].observation.content.contains("result 1"));
 
         let answer = agent.run().await.unwrap();
         assert_eq!(answer, "resumed answer that is long enough");
@@ -88,7 +87,7 @@ async fn test_persistence_file_store() {
 
     // 1. First run
     {
-        let mock_llm = vec![LlmResponse::FinalAnswer { content: "ok enough length".to_string(), usage: None }];
+        let mock_llm = vec![LlmResponse::final_answer("ok enough length")];
         let mut agent = AgentBuilder::new("Task File")
             .llm(Arc::new(MockLlmCaller::new(mock_llm)))
             .checkpoint_store(store.clone())
@@ -104,6 +103,85 @@ async fn test_persistence_file_store() {
     assert_eq!(checkpoint.state.as_str(), "Done");
 }
 
+fn file_checkpoint(session_id: &str, step: usize) -> AgentCheckpoint {
+    let mut memory = agent_b::AgentMemory::new("Crash task");
+    memory.step = step;
+    AgentCheckpoint {
+        checkpoint_id: format!("{}-{}", session_id, step),
+        session_id: session_id.to_string(),
+        state: agent_b::State::planning(),
+        memory,
+        timestamp: chrono::Utc::now(),
+        graph: None,
+        trace_cursor: None,
+        memory_from_trace: false,
+        sequence: step as u64,
+        schema_version: agent_b::checkpoint::CHECKPOINT_SCHEMA_VERSION,
+    }
+}
+
+#[tokio::test]
+async fn test_file_store_survives_torn_write() {
+    use std::io::Write;
+
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("crash.checkpoints");
+    {
+        let store = FileCheckpointStore::new(temp_dir.path());
+        store.save(file_checkpoint("crash", 1)).await.unwrap();
+        store.save(file_checkpoint("crash", 2)).await.unwrap();
+    }
+    let intact = std::fs::metadata(&path).unwrap().len();
+
+    // The process dies halfway through writing checkpoint 3
+    let torn = {
+        let mut frame = Vec::new();
+        let payload = serde_json::to_vec(&file_checkpoint("crash", 3)).unwrap();
+        frame.extend((payload.len() as u32).to_le_bytes());
+        frame.extend(&payload[..payload.len() / 2]);
+        frame
+    };
+    std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&torn).unwrap();
+
+    // A fresh process resumes from the last complete checkpoint
+    let store = FileCheckpointStore::new(temp_dir.path());
+    let latest = store.load_latest("crash").await.unwrap().unwrap();
+    assert_eq!(latest.memory.step, 2);
+
+    // The next save truncates the torn frame before appending
+    store.save(file_checkpoint("crash", 3)).await.unwrap();
+    let steps: Vec<usize> = store
+        .list_checkpoints("crash")
+        .await
+        .unwrap()
+        .iter()
+        .map(|c| c.memory.step)
+        .collect();
+    assert_eq!(steps, vec![1, 2, 3]);
+    assert!(std::fs::metadata(&path).unwrap().len() > intact);
+
+    // A tail the filesystem zero-filled is skipped too
+    std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[0u8; 64]).unwrap();
+    let store = FileCheckpointStore::new(temp_dir.path());
+    assert_eq!(store.load_latest("crash").await.unwrap().unwrap().memory.step, 3);
+}
+
+#[tokio::test]
+async fn test_file_store_reads_legacy_json_sessions() {
+    let temp_dir = TempDir::new().unwrap();
+    let legacy = vec![file_checkpoint("old", 1), file_checkpoint("old", 2)];
+    std::fs::write(temp_dir.path().join("old.json"), serde_json::to_vec_pretty(&legacy).unwrap()).unwrap();
+
+    let store = FileCheckpointStore::new(temp_dir.path());
+    assert_eq!(store.load_latest("old").await.unwrap().unwrap().memory.step, 2);
+
+    // New checkpoints are appended after the legacy ones
+    store.save(file_checkpoint("old", 3)).await.unwrap();
+    assert_eq!(store.list_checkpoints("old").await.unwrap().len(), 3);
+    assert_eq!(store.list_sessions().await.unwrap(), vec!["old".to_string()]);
+    assert!(store.load_by_id("old-1").await.unwrap().is_some());
+}
+
 #[tokio::test]
 async fn test_persistence_sqlite_store() {
     let temp_dir = TempDir::new().unwrap();
@@ -113,7 +191,7 @@ async fn test_persistence_sqlite_store() {
 
     // 1. First run
     {
-        let mock_llm = vec![LlmResponse::FinalAnswer { content: "ok enough length".to_string(), usage: None }];
+        let mock_llm = vec![LlmResponse::final_answer("ok enough length")];
         let mut agent = AgentBuilder::new("Task Sqlite")
             .llm(Arc::new(MockLlmCaller::new(mock_llm)))
             .checkpoint_store(store.clone())
@@ -128,3 +206,425 @@ async fn test_persistence_sqlite_store() {
     assert_eq!(checkpoint.memory.task, "Task Sqlite");
     assert_eq!(checkpoint.state.as_str(), "Done");
 }
+
+#[tokio::test]
+async fn test_sqlite_store_uses_wal_and_reuses_connections() {
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("wal.db");
+    let store = Arc::new(SqliteCheckpointStore::new(&db_path).unwrap().pool_size(2));
+
+    // Concurrent saves share the pool; none is lost
+    let saves: Vec<_> = (0..8)
+        .map(|step| {
+            let store = store.clone();
+            tokio::spawn(async move { store.save(file_checkpoint("wal", step)).await })
+        })
+        .collect();
+    for save in saves {
+        save.await.unwrap().unwrap();
+    }
+    assert_eq!(store.list_checkpoints("wal").await.unwrap().len(), 8);
+
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    let mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+    assert_eq!(mode, "wal");
+    let index: String = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'index' AND name = 'idx_checkpoints_session_sequence'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert!(index.contains("session_id, sequence"));
+}
+
+#[tokio::test]
+async fn test_stores_order_checkpoints_by_sequence() {
+    let temp_dir = TempDir::new().unwrap();
+    let stores: Vec<Arc<dyn CheckpointStore>> = vec![
+        Arc::new(MemoryCheckpointStore::new()),
+        Arc::new(FileCheckpointStore::new(temp_dir.path().join("files"))),
+        Arc::new(SqliteCheckpointStore::new(temp_dir.path().join("seq.db")).unwrap()),
+    ];
+    for store in stores {
+        // The clock jumps back after step 2, and steps 3 and 4 share a timestamp
+        let now = chrono::Utc::now();
+        for (step, offset) in [(1, 0), (2, 10), (3, -60), (4, -60)] {
+            let mut checkpoint = file_checkpoint("skew", step);
+            checkpoint.timestamp = now + chrono::Duration::seconds(offset);
+            store.save(checkpoint).await.unwrap();
+        }
+
+        assert_eq!(store.load_latest("skew").await.unwrap().unwrap().memory.step, 4);
+        assert_eq!(store.last_sequence("skew").await.unwrap(), 4);
+        assert_eq!(store.last_sequence("missing").await.unwrap(), 0);
+        let page: Vec<usize> = store
+            .list_checkpoints_page("skew", 1, 2)
+            .await
+            .unwrap()
+            .iter()
+            .map(|c| c.memory.step)
+            .collect();
+        assert_eq!(page, vec![2, 3]);
+        assert!(store.list_checkpoints_page("skew", 4, 10).await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_engine_numbers_checkpoints_across_runs() {
+    let store = Arc::new(MemoryCheckpointStore::new());
+    let answer = || vec![LlmResponse::final_answer("ok enough length")];
+
+    let mut agent = AgentBuilder::new("Numbered")
+        .llm(Arc::new(MockLlmCaller::new(answer())))
+        .checkpoint_store(store.clone())
+        .session_id("numbered")
+        .build()
+        .unwrap();
+    agent.run().await.unwrap();
+    let first_run = store.last_sequence("numbered").await.unwrap();
+    assert!(first_run > 0);
+
+    // A second run of the same session continues the numbering
+    let mut again = AgentBuilder::new("Numbered")
+        .llm(Arc::new(MockLlmCaller::new(answer())))
+        .checkpoint_store(store.clone())
+        .session_id("numbered")
+        .build()
+        .unwrap();
+    again.run().await.unwrap();
+
+    let sequences: Vec<u64> = store
+        .list_checkpoints("numbered")
+        .await
+        .unwrap()
+        .iter()
+        .map(|c| c.sequence)
+        .collect();
+    assert_eq!(sequences, (1..=sequences.len() as u64).collect::<Vec<_>>());
+    assert!(sequences.len() as u64 > first_run);
+}
+
+#[tokio::test]
+async fn test_trace_kept_out_of_checkpoint_payloads() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_store: Arc<dyn CheckpointStore> = Arc::new(FileCheckpointStore::new(temp_dir.path().join("files")));
+    let sqlite_store: Arc<dyn CheckpointStore> = Arc::new(SqliteCheckpointStore::new(temp_dir.path().join("test.db")).unwrap());
+    let memory_store: Arc<dyn CheckpointStore> = Arc::new(MemoryCheckpointStore::new());
+
+    for store in [file_store, sqlite_store, memory_store] {
+        let session_id = "trace_session";
+        let tool = || agent_b::Tool::new("test_tool", "desc").call(|_| Ok("result 1".to_string()));
+
+        // First run stops after one tool call
+        {
+            let mock_llm = vec![LlmResponse::ToolCall {
+                tool: ToolCall { name: "test_tool".to_string(), args: HashMap::new(), id: Some("call_1".to_string()) },
+                confidence: 1.0,
+                assistant_text: None,
+                usage: None,
+                model: None,
+            }];
+            let mut agent = AgentBuilder::new("Trace task")
+                .llm(Arc::new(MockLlmCaller::new(mock_llm)))
+                .add_tool(tool())
+                .checkpoint_store(store.clone())
+                .session_id(session_id)
+                .build()
+                .unwrap();
+            let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+            for _ in 0..4 {
+                agent.step(&tx).await.unwrap();
+            }
+        }
+
+        // Resume and finish
+        let mock_llm = vec![LlmResponse::final_answer("resumed answer that is long enough")];
+        let mut agent = AgentBuilder::new("Dummy")
+            .llm(Arc::new(MockLlmCaller::new(mock_llm)))
+            .add_tool(tool())
+            .checkpoint_store(store.clone())
+            .resume(session_id).await.unwrap()
+            .build()
+            .unwrap();
+        agent.run().await.unwrap();
+
+        // Every checkpoint gets back exactly the trace it was saved with
+        let checkpoints = store.list_checkpoints(session_id).await.unwrap();
+        assert!(checkpoints.len() >= 5);
+        for cp in &checkpoints {
+            assert_eq!(Some(cp.memory.trace.len()), cp.trace_cursor);
+        }
+        let latest = store.load_latest(session_id).await.unwrap().unwrap();
+        assert_eq!(latest.memory.trace.len(), agent.trace().len());
+        let step_starts = latest.memory.trace.entries().iter().filter(|e| e.event == "STEP_START").count();
+        assert_eq!(step_starts, 2);
+    }
+
+    // Stored checkpoints do not embed the trace
+    let file_data = std::fs::read(temp_dir.path().join("files/trace_session.checkpoints")).unwrap();
+    assert!(!String::from_utf8_lossy(&file_data).contains("STEP_START"));
+    assert!(temp_dir.path().join("files/trace_session.trace").exists());
+    let sqlite = SqliteCheckpointStore::new(temp_dir.path().join("test.db")).unwrap();
+    assert_eq!(sqlite.list_sessions().await.unwrap(), vec!["trace_session".to_string()]);
+    let conn = rusqlite::Connection::open(temp_dir.path().join("test.db")).unwrap();
+    let embedded: i64 = conn
+        .query_row("SELECT COUNT(*) FROM checkpoints WHERE memory LIKE '%STEP_START%'", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(embedded, 0);
+}
+
+/// Toy "encryption": XOR every byte. Output is not valid UTF-8.
+struct XorCodec(u8);
+
+impl MemoryCodec for XorCodec {
+    fn encode(&self, plain: Vec<u8>) -> Result<Vec<u8>, String> {
+        Ok(plain.into_iter().map(|b| b ^ self.0).collect())
+    }
+
+    fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, String> {
+        self.encode(stored)
+    }
+}
+
+#[tokio::test]
+async fn test_stores_route_memory_through_codec() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_store = Arc::new(FileCheckpointStore::new(temp_dir.path().join("files")).with_codec(Arc::new(XorCodec(0xA5))));
+    let sqlite_store = Arc::new(SqliteCheckpointStore::new(temp_dir.path().join("test.db")).unwrap().with_codec(Arc::new(XorCodec(0xA5))));
+    let stores: Vec<Arc<dyn CheckpointStore>> = vec![file_store, sqlite_store];
+
+    for (i, store) in stores.into_iter().enumerate() {
+        let session_id = format!("secret_session_{}", i);
+        let mock_llm = vec![LlmResponse::final_answer("ok enough length")];
+        let mut agent = AgentBuilder::new("Top secret task")
+            .llm(Arc::new(MockLlmCaller::new(mock_llm)))
+            .checkpoint_store(store.clone())
+            .session_id(&session_id)
+            .build()
+            .unwrap();
+        agent.run().await.unwrap();
+
+        let checkpoint = store.load_latest(&session_id).await.unwrap().unwrap();
+        assert_eq!(checkpoint.memory.task, "Top secret task");
+        let by_id = store.load_by_id(&checkpoint.checkpoint_id).await.unwrap().unwrap();
+        assert_eq!(by_id.memory.task, "Top secret task");
+    }
+
+    // Nothing on disk holds the task in the clear
+    let file_data = std::fs::read(temp_dir.path().join("files/secret_session_0.checkpoints")).unwrap();
+    assert!(!String::from_utf8_lossy(&file_data).contains("Top secret task"));
+    let db_data = std::fs::read(temp_dir.path().join("test.db")).unwrap();
+    assert!(!String::from_utf8_lossy(&db_data).contains("Top secret task"));
+
+    // Reading with the wrong codec fails instead of returning garbage
+    let wrong = FileCheckpointStore::new(temp_dir.path().join("files"));
+    assert!(wrong.load_latest("secret_session_0").await.is_err());
+}
+
+#[test]
+fn test_memory_export_import_with_codec() {
+    let memory = agent_b::AgentMemory::new("Exported task");
+    let data = memory.export(&XorCodec(0x3C)).unwrap();
+    assert!(!String::from_utf8_lossy(&data).contains("Exported task"));
+
+    let restored = agent_b::AgentMemory::import(data, &XorCodec(0x3C)).unwrap();
+    assert_eq!(restored.task, "Exported task");
+}
+
+#[tokio::test]
+async fn test_resume_validates_graph_shape() {
+    use agent_b::states::ReflectingState;
+    use agent_b::AgentError;
+
+    let temp_dir = TempDir::new().unwrap();
+    let store = Arc::new(SqliteCheckpointStore::new(temp_dir.path().join("graph.db")).unwrap());
+    let session_id = "test_session_graph";
+
+    let with_research = |builder: AgentBuilder| {
+        builder
+            .state("Research", Arc::new(ReflectingState))
+            .transition("Planning", "NeedsResearch", "Research")
+            .transition("Research", "ReflectDone", "Planning")
+    };
+
+    // 1. First run: custom graph, one step
+    {
+        let mut agent = with_research(AgentBuilder::new("Task Graph"))
+            .llm(Arc::new(MockLlmCaller::new(vec![])))
+            .checkpoint_store(store.clone())
+            .session_id(session_id)
+            .build()
+            .unwrap();
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        agent.step(&tx).await.unwrap();
+    }
+
+    let checkpoint = store.load_latest(session_id).await.unwrap().unwrap();
+    let graph = checkpoint.graph.expect("graph shape should be stored");
+    assert!(graph.states.contains(&"Research".to_string()));
+
+    // 2. Resuming without the custom state is reported up front
+    let result = AgentBuilder::new("Task Graph")
+        .llm(Arc::new(MockLlmCaller::new(vec![])))
+        .checkpoint_store(store.clone())
+        .resume(session_id)
+        .await
+        .unwrap()
+        .build();
+    match result {
+        Err(AgentError::GraphMismatch(msg)) => {
+            assert!(msg.contains("'Research' has no handler"), "{}", msg);
+            assert!(msg.contains("Planning --NeedsResearch--> Research"), "{}", msg);
+        }
+        Err(e) => panic!("expected GraphMismatch, got {}", e),
+        Ok(_) => panic!("expected GraphMismatch, got an engine"),
+    }
+
+    // 3. Same graph resumes fine
+    let resumed = with_research(AgentBuilder::new("Task Graph"))
+        .llm(Arc::new(MockLlmCaller::new(vec![])))
+        .checkpoint_store(store.clone())
+        .resume(session_id)
+        .await
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(resumed.current_state().as_str(), "Planning");
+}
+
+#[test]
+fn test_legacy_string_observations_still_load() {
+    use agent_b::memory::AgentMemory;
+    use agent_b::types::ObservationStatus;
+
+    // Memory as checkpoints stored it before observations had a status
+    let mut value = serde_json::to_value(AgentMemory::new("Legacy")).unwrap();
+    let tool = serde_json::json!({ "name": "search", "args": {}, "id": null });
+    value["history"] = serde_json::json!([
+        { "step": 1, "tool": tool, "observation": "SUCCESS: 3 hits", "success": true },
+        { "step": 2, "tool": tool, "observation": "REJECTED: not now", "success": false },
+        { "step": 3, "tool": tool, "observation": "timed out", "success": false },
+    ]);
+    value["last_observation"] = serde_json::json!("ERROR: boom");
+    value["parallel_results"] = serde_json::json!([{
+        "tool_name": "search", "tool_args": {}, "id": null,
+        "output": "SUCCESS: ok", "success": true, "latency_ms": 4
+    }]);
+
+    let memory: AgentMemory = serde_json::from_value(value).unwrap();
+    let statuses: Vec<_> = memory.history.iter().map(|h| h.observation.status).collect();
+    assert_eq!(
+        statuses,
+        vec![ObservationStatus::Success, ObservationStatus::Rejected, ObservationStatus::Error]
+    );
+    assert_eq!(&*memory.history[0].observation.content, "3 hits");
+    assert_eq!(memory.last_observation.as_ref().unwrap().status, ObservationStatus::Error);
+    assert!(memory.parallel_results[0].is_success());
+    assert_eq!(&*memory.parallel_results[0].observation.content, "ok");
+
+    // The new layout round-trips
+    let json = serde_json::to_string(&memory.history).unwrap();
+    assert!(json.contains(r#""status":"rejected""#));
+    let again: Vec<agent_b::HistoryEntry> = serde_json::from_str(&json).unwrap();
+    assert_eq!(again[1].observation, memory.history[1].observation);
+}
+
+#[tokio::test]
+async fn test_old_checkpoints_are_migrated() {
+    use agent_b::checkpoint::{CheckpointMigrations, CHECKPOINT_SCHEMA_VERSION};
+
+    // A checkpoint saved before versioning, whose memory calls `task` `goal`
+    let mut old = serde_json::to_value(file_checkpoint("old", 1)).unwrap();
+    old.as_object_mut().unwrap().remove("schema_version");
+    let task = old["memory"].as_object_mut().unwrap().remove("task").unwrap();
+    old["memory"]["goal"] = task;
+
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("old.json"), serde_json::to_vec(&vec![old]).unwrap()).unwrap();
+    assert!(FileCheckpointStore::new(temp_dir.path()).load_latest("old").await.is_err());
+
+    let migrations = CheckpointMigrations::new().register(0, |memory| {
+        let goal = memory.as_object_mut().and_then(|m| m.remove("goal")).ok_or("no goal")?;
+        memory["task"] = goal;
+        Ok(())
+    });
+    let store = FileCheckpointStore::new(temp_dir.path()).with_migrations(migrations);
+    let checkpoint = store.load_latest("old").await.unwrap().unwrap();
+    assert_eq!(checkpoint.memory.task, "Crash task");
+    assert_eq!(checkpoint.schema_version, CHECKPOINT_SCHEMA_VERSION);
+
+    // Checkpoints from a newer build are refused with a clear error
+    let mut newer = file_checkpoint("newer", 1);
+    newer.schema_version = CHECKPOINT_SCHEMA_VERSION + 1;
+    let db = SqliteCheckpointStore::new(temp_dir.path().join("v.db")).unwrap();
+    db.save(newer).await.unwrap();
+    let err = db.load_latest("newer").await.unwrap_err();
+    assert!(err.contains("newer than this build"), "{}", err);
+}
+
+/// Holds every save until the test opens the gate.
+struct GatedStore {
+    inner: MemoryCheckpointStore,
+    gate: tokio::sync::Semaphore,
+}
+
+#[async_trait::async_trait]
+impl CheckpointStore for GatedStore {
+    async fn save(&self, checkpoint: AgentCheckpoint) -> Result<(), String> {
+        self.gate.acquire().await.unwrap().forget();
+        self.inner.save(checkpoint).await
+    }
+    async fn load_latest(&self, session_id: &str) -> Result<Option<AgentCheckpoint>, String> {
+        self.inner.load_latest(session_id).await
+    }
+    async fn load_by_id(&self, checkpoint_id: &str) -> Result<Option<AgentCheckpoint>, String> {
+        self.inner.load_by_id(checkpoint_id).await
+    }
+    async fn list_sessions(&self) -> Result<Vec<String>, String> {
+        self.inner.list_sessions().await
+    }
+    async fn list_checkpoints(&self, session_id: &str) -> Result<Vec<AgentCheckpoint>, String> {
+        self.inner.list_checkpoints(session_id).await
+    }
+    async fn last_sequence(&self, session_id: &str) -> Result<u64, String> {
+        self.inner.last_sequence(session_id).await
+    }
+}
+
+#[tokio::test]
+async fn test_checkpoints_saved_off_the_step_loop() {
+    let store = Arc::new(GatedStore { inner: MemoryCheckpointStore::new(), gate: tokio::sync::Semaphore::new(0) });
+    let agent = AgentBuilder::new("Gated")
+        .llm(Arc::new(MockLlmCaller::new(vec![LlmResponse::final_answer("answered while the store was blocked")])))
+        .checkpoint_store(store.clone())
+        .session_id("gated")
+        .build()
+        .unwrap();
+    let (_handle, mut rx, join) = agent.spawn();
+
+    // The run reaches its answer although no save has gone through
+    loop {
+        match rx.recv().await {
+            Some(agent_b::AgentOutput::FinalAnswer(_)) => break,
+            Some(_) => {}
+            None => panic!("run ended without an answer"),
+        }
+    }
+    assert!(store.inner.list_checkpoints("gated").await.unwrap().is_empty());
+    assert!(!join.is_finished());
+
+    // The run returns once the queue is written, in order. Checkpoints
+    // queued behind the blocked save were folded into one.
+    store.gate.add_permits(1000);
+    join.await.unwrap().unwrap();
+    let checkpoints = store.list_checkpoints("gated").await.unwrap();
+    let sequences: Vec<u64> = checkpoints.iter().map(|c| c.sequence).collect();
+    assert!(!sequences.is_empty() && sequences.len() <= 2);
+    assert_eq!(sequences, (1..=sequences.len() as u64).collect::<Vec<_>>());
+    let last = checkpoints.last().unwrap();
+    assert_eq!(last.state.as_str(), "Done");
+    // No trace entry was lost with the skipped checkpoints
+    let transitions = last.memory.trace.entries().iter().filter(|e| e.event == "TRANSITION").count();
+    assert!(transitions >= 2);
+}
@@ -0,0 +1,126 @@
+use agent_b::budget::TokenUsage;
+use agent_b::llm::MockLlmCaller;
+use agent_b::prompt::PromptTemplate;
+use agent_b::types::LlmResponse;
+use agent_b::{AgentBuilder, Branch, Pipeline, PipelineError, Stage, StageFailure, StageOutcome};
+use serde::Deserialize;
+use std::sync::Arc;
+
+fn answering(answer: &str, usage: Option<TokenUsage>) -> AgentBuilder {
+    AgentBuilder::new("").llm(Arc::new(MockLlmCaller::new(vec![
+        usage.into_iter().fold(LlmResponse::final_answer(answer), LlmResponse::with_usage),
+    ])))
+}
+
+/// An agent whose LLM has no responses left, so every run fails.
+fn failing() -> AgentBuilder {
+    AgentBuilder::new("").llm(Arc::new(MockLlmCaller::new(vec![])))
+}
+
+#[tokio::test]
+async fn test_output_becomes_next_task() {
+    let result = Pipeline::new("report")
+        .stage(Stage::new("extract", answering("Revenue rose 12%", None)))
+        .stage(
+            Stage::new("summarize", answering("Sales are up", None))
+                .task_template(PromptTemplate::new("Summarize: {input}")),
+        )
+        .stage(Stage::new("shout", answering("SALES ARE UP", None)).task(|input| input.to_uppercase()))
+        .run("Q3 figures")
+        .await
+        .unwrap();
+
+    assert_eq!(result.output, "SALES ARE UP");
+    let tasks: Vec<_> = result.stages.iter().map(|s| s.task.as_str()).collect();
+    assert_eq!(tasks, vec!["Q3 figures", "Summarize: Revenue rose 12%", "SALES ARE UP"]);
+    assert!(result.stages.iter().all(|s| s.outcome == StageOutcome::Completed && s.attempts == 1));
+
+    // The combined trace holds every stage's entries under its name
+    let states: Vec<_> = result.trace.entries().iter().map(|e| e.state.as_str()).collect();
+    assert!(states.contains(&"extract/Planning"));
+    assert!(states.contains(&"summarize/Planning"));
+    assert_eq!(states.iter().filter(|s| **s == "Pipeline").count(), 6);
+}
+
+#[tokio::test]
+async fn test_failure_policies() {
+    let result = Pipeline::new("p")
+        .stage(Stage::new("flaky", failing()).on_failure(StageFailure::Skip))
+        .stage(Stage::new("backup", failing()).on_failure(StageFailure::Fallback("n/a".into())))
+        .run("input")
+        .await
+        .unwrap();
+    assert_eq!(result.stages[0].output, "input");
+    assert!(matches!(result.stages[0].outcome, StageOutcome::Skipped { .. }));
+    assert_eq!(result.output, "n/a");
+    assert!(matches!(result.stages[1].outcome, StageOutcome::FellBack { .. }));
+
+    let err = Pipeline::new("p")
+        .stage(Stage::new("first", answering("first done", None)))
+        .stage(Stage::new("broken", failing()).on_failure(StageFailure::Retry(2)))
+        .run("input")
+        .await
+        .unwrap_err();
+    match err {
+        PipelineError::StageFailed { stage, attempts, .. } => {
+            assert_eq!(stage, "broken");
+            assert_eq!(attempts, 3);
+        }
+        other => panic!("expected StageFailed, got {}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_budget_shared_between_stages() {
+    let pipeline = Pipeline::new("p")
+        .stage(Stage::new("first", answering("first done", Some(TokenUsage::new(20, 10)))))
+        .stage(Stage::new("second", answering("never runs", None)))
+        .max_tokens(30);
+
+    let err = pipeline.run("input").await.unwrap_err();
+    assert!(matches!(err, PipelineError::BudgetExhausted { ref stage } if stage == "second"));
+}
+
+#[derive(Deserialize)]
+struct Triage {
+    severity: u8,
+}
+
+fn triage(answer: &str) -> Pipeline {
+    Pipeline::new("support")
+        .stage(Stage::new("classify", answering(answer, None)))
+        .branch(
+            Branch::new("route")
+                .when_as(|t: &Triage| t.severity >= 3, Stage::new("escalate", answering("Paged on-call", None)))
+                .when(|v| v["kind"] == "refund", Stage::new("refund", answering("Refund issued", None)))
+                .otherwise(Stage::new("reply", answering("Replied", None))),
+        )
+}
+
+#[tokio::test]
+async fn test_branch_picks_stage_from_structured_output() {
+    let result = triage(r#"{"severity": 4, "kind": "refund"}"#).run("ticket").await.unwrap();
+    assert_eq!(result.output, "Paged on-call");
+    assert_eq!(result.stages[1].name, "escalate");
+    assert_eq!(result.stages[1].branch.as_deref(), Some("route"));
+    assert_eq!(result.stages[1].task, r#"{"severity": 4, "kind": "refund"}"#);
+
+    let result = triage(r#"{"severity": 1, "kind": "refund"}"#).run("ticket").await.unwrap();
+    assert_eq!(result.output, "Refund issued");
+
+    // Not JSON: no arm matches, so the fallback stage runs
+    let result = triage("no idea").run("ticket").await.unwrap();
+    assert_eq!(result.output, "Replied");
+    assert!(result
+        .trace
+        .entries()
+        .iter()
+        .any(|e| e.event == "BRANCH_TAKEN" && e.data == "branch='route' stage='reply'"));
+
+    let err = Pipeline::new("p")
+        .branch(Branch::new("strict").when(|v| v.is_object(), Stage::new("never", answering("never runs", None))))
+        .run("plain text")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, PipelineError::NoBranchMatched { ref branch } if branch == "strict"));
+}
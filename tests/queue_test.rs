@@ -0,0 +1,118 @@
+use agent_b::llm::{AsyncLlmCaller, LlmError, MockLlmCaller};
+use agent_b::memory::AgentMemory;
+use agent_b::types::{AgentOutput, LlmResponse, LlmStreamChunk};
+use agent_b::{AgentBuilder, InMemoryQueue, QueueWorker, QueuedTask, TaskQueue, ToolRegistry, WorkerStats};
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Answers after a short delay and records the highest number of concurrent calls.
+#[derive(Default)]
+struct ConcurrencyProbe {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+#[async_trait]
+impl AsyncLlmCaller for ConcurrencyProbe {
+    async fn call_async(
+        &self,
+        memory: &AgentMemory,
+        _tools: &ToolRegistry,
+        _model: &str,
+        _output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) -> Result<LlmResponse, LlmError> {
+        let now = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        self.current.fetch_sub(1, Ordering::SeqCst);
+        Ok(LlmResponse::final_answer(format!("done: {}", memory.task)))
+    }
+
+    fn call_stream_async<'a>(
+        &'a self,
+        memory: &'a AgentMemory,
+        tools: &'a ToolRegistry,
+        model: &'a str,
+        output_tx: Option<&tokio::sync::mpsc::UnboundedSender<AgentOutput>>,
+    ) -> BoxStream<'a, Result<LlmStreamChunk, LlmError>> {
+        use futures::{stream, StreamExt};
+        let output_tx = output_tx.cloned();
+        stream::once(async move {
+            self.call_async(memory, tools, model, output_tx.as_ref()).await.map(LlmStreamChunk::Done)
+        })
+        .boxed()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_worker_bounds_concurrency() {
+    let queue = Arc::new(InMemoryQueue::new());
+    for i in 0..8 {
+        queue.push(format!("task {}", i));
+    }
+    let probe = Arc::new(ConcurrencyProbe::default());
+
+    let llm = probe.clone();
+    let stats = QueueWorker::new(queue.clone(), move |task: &QueuedTask| {
+        Ok(AgentBuilder::new(task.task.clone()).llm(llm.clone()))
+    })
+    .concurrency(3)
+    .poll_interval(Duration::from_millis(5))
+    .drain()
+    .await;
+
+    assert_eq!(stats, WorkerStats { succeeded: 8, retried: 0, dead_lettered: 0 });
+    assert!(probe.peak.load(Ordering::SeqCst) <= 3);
+    assert!(queue.is_empty());
+    assert_eq!(queue.in_flight(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_failed_tasks_retry_then_dead_letter() {
+    let queue = Arc::new(InMemoryQueue::new());
+    queue.push("ok");
+    let bad = queue.push("always fails");
+
+    let stats = QueueWorker::new(queue.clone(), |task: &QueuedTask| {
+        // The mock has no answer for failing tasks, so the run errors
+        let responses = if task.task == "ok" {
+            vec![LlmResponse::final_answer("All fine.")]
+        } else {
+            vec![]
+        };
+        Ok(AgentBuilder::new(task.task.clone()).llm(Arc::new(MockLlmCaller::new(responses))))
+    })
+    .max_attempts(3)
+    .poll_interval(Duration::from_millis(5))
+    .drain()
+    .await;
+
+    assert_eq!(stats, WorkerStats { succeeded: 1, retried: 2, dead_lettered: 1 });
+    let dead = queue.dead_letters();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].id, bad);
+    assert_eq!(dead[0].attempts, 3);
+}
+
+#[tokio::test]
+async fn test_in_memory_queue_ack_and_nack() {
+    let queue = InMemoryQueue::new();
+    queue.push("a");
+
+    let first = queue.receive().await.unwrap().unwrap();
+    assert_eq!(first.attempts, 1);
+    assert!(queue.receive().await.unwrap().is_none());
+
+    queue.nack(&first, true).await.unwrap();
+    let second = queue.receive().await.unwrap().unwrap();
+    assert_eq!(second.id, first.id);
+    assert_eq!(second.attempts, 2);
+
+    // A stale receipt cannot settle the new delivery
+    assert!(queue.ack(&first).await.is_err());
+    queue.ack(&second).await.unwrap();
+    assert_eq!(queue.in_flight(), 0);
+}
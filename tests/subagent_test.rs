@@ -9,10 +9,7 @@ async fn test_subagent_delegation() {
     // 1. Setup a specialized calculator sub-agent
     // This sub-agent knows how to add numbers.
     let calc_responses = vec![
-        LlmResponse::FinalAnswer {
-            content: "The result is 42".to_string(),
-            usage: None,
-        },
+        LlmResponse::final_answer("The result is 42"),
     ];
     let calc_agent = AgentBuilder::new("Calculate sum")
         .llm(Arc::new(MockLlmCaller::new(calc_responses)));
@@ -30,12 +27,11 @@ async fn test_subagent_delegation() {
                 id: Some("call_calc_1".to_string()),
             },
             confidence: 1.0,
+            assistant_text: None,
             usage: None,
+            model: None,
         },
-        LlmResponse::FinalAnswer {
-            content: "The calculator said it's 42".to_string(),
-            usage: None,
-        },
+        LlmResponse::final_answer("The calculator said it's 42"),
     ];
 
     let mut parent = AgentBuilder::new("Ask calculator for a sum")
@@ -52,14 +48,14 @@ async fn test_subagent_delegation() {
     // 4. Verify trace/history
     assert_eq!(parent.memory.history.len(), 1);
     assert_eq!(parent.memory.history[0].tool.name, "calculator");
-    assert!(parent.memory.history[0].observation.contains("42"));
+    assert!(parent.memory.history[0].observation.content.contains("42"));
 }
 
 #[tokio::test(flavor = "multi_thread")]
 async fn test_nested_subagent_delegation() {
     // Grandchild: just answers
     let grandchild_llm = Arc::new(MockLlmCaller::new(vec![
-        LlmResponse::FinalAnswer { content: "I am the grandchild".to_string(), usage: None }
+        LlmResponse::final_answer("I am the grandchild")
     ]));
     let grandchild = AgentBuilder::new("gc").llm(grandchild_llm);
 
@@ -68,9 +64,11 @@ async fn test_nested_subagent_delegation() {
         LlmResponse::ToolCall {
             tool: ToolCall { name: "grandchild".to_string(), args: HashMap::new(), id: Some("c1".to_string()) },
             confidence: 1.0,
+            assistant_text: None,
             usage: None,
+            model: None,
         },
-        LlmResponse::FinalAnswer { content: "Grandchild said: ...".to_string(), usage: None }
+        LlmResponse::final_answer("Grandchild said: ...")
     ]));
     let child = AgentBuilder::new("c")
         .llm(child_llm)
@@ -81,9 +79,11 @@ async fn test_nested_subagent_delegation() {
         LlmResponse::ToolCall {
             tool: ToolCall { name: "child".to_string(), args: HashMap::new(), id: Some("p1".to_string()) },
             confidence: 1.0,
+            assistant_text: None,
             usage: None,
+            model: None,
         },
-        LlmResponse::FinalAnswer { content: "Child finished".to_string(), usage: None }
+        LlmResponse::final_answer("Child finished")
     ]));
     
     let mut parent = AgentBuilder::new("p")
@@ -95,3 +95,50 @@ async fn test_nested_subagent_delegation() {
     let answer = parent.run().await.unwrap();
     assert_eq!(answer, "Child finished");
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_subagent_pool_fans_out_and_merges() {
+    // Three tasks but only two programmed answers: one instance must fail
+    let worker_llm = Arc::new(MockLlmCaller::new(vec![
+        LlmResponse::final_answer("worker answer"),
+        LlmResponse::final_answer("worker answer"),
+    ]));
+    let worker = AgentBuilder::new("unused").llm(worker_llm.clone());
+
+    let parent_llm = Arc::new(MockLlmCaller::new(vec![
+        LlmResponse::ToolCall {
+            tool: ToolCall {
+                name: "researchers".to_string(),
+                args: {
+                    let mut m = HashMap::new();
+                    m.insert("tasks".to_string(), serde_json::json!(["alpha", "beta", "gamma"]));
+                    m
+                },
+                id: Some("pool_1".to_string()),
+            },
+            confidence: 1.0,
+            assistant_text: None,
+            usage: None,
+            model: None,
+        },
+        LlmResponse::final_answer("merged"),
+    ]));
+
+    let mut parent = AgentBuilder::new("Research three topics")
+        .llm(parent_llm)
+        .add_subagent_pool("researchers", "Parallel researchers", worker, 3)
+        .build()
+        .unwrap();
+
+    assert_eq!(parent.run().await.unwrap(), "merged");
+    // The failing instance tries a stream, then one non-stream call
+    assert_eq!(worker_llm.call_count(), 4);
+
+    let obs = &parent.memory.history[0].observation.content;
+    let a = obs.find("## Task 1: alpha").unwrap();
+    let b = obs.find("## Task 2: beta").unwrap();
+    let c = obs.find("## Task 3: gamma").unwrap();
+    assert!(a < b && b < c);
+    assert_eq!(obs.matches("worker answer").count(), 2);
+    assert_eq!(obs.matches("ERROR:").count(), 1);
+}